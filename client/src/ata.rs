@@ -0,0 +1,141 @@
+//! Deposits and withdrawals through associated token accounts.
+//!
+//! The program accepts any initialized token account of the owner for
+//! the market's mint. Wallets hold that balance in the owner's ATA, so the
+//! builders here use it and prepend the ATA program's idempotent create:
+//! a missing ATA is created in the same transaction and an existing one
+//! is left alone, with no separate keypair or rent step.
+//!
+//! ```ignore
+//! let ixs = ata::deposit_from_ata(&program_id, &payer, &owner, &slab, &mint, &vault, idx, amt);
+//! ```
+
+use crate::ix;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_instruction, system_program,
+};
+
+/// SPL Associated Token Account program.
+pub const ATA_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+/// `AssociatedTokenAccountInstruction::CreateIdempotent`.
+const CREATE_IDEMPOTENT: u8 = 1;
+
+/// The owner's ATA for `mint` under the SPL token program. `owner` may be
+/// off curve (a PDA).
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), spl_token::ID.as_ref(), mint.as_ref()],
+        &ATA_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Create `owner`'s ATA for `mint` unless it already exists; `payer`
+/// funds the rent.
+pub fn create_idempotent(payer: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: ATA_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(associated_token_address(owner, mint), false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: vec![CREATE_IDEMPOTENT],
+    }
+}
+
+/// Create-if-missing, then DepositCollateral from the owner's ATA. A
+/// freshly created ATA is empty; for wrapped SOL use `deposit_sol`.
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_from_ata(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    owner: &Pubkey,
+    slab: &Pubkey,
+    mint: &Pubkey,
+    vault: &Pubkey,
+    user_idx: u16,
+    amount: u64,
+) -> Vec<Instruction> {
+    let user_token = associated_token_address(owner, mint);
+    vec![
+        create_idempotent(payer, owner, mint),
+        ix::deposit(
+            program_id,
+            owner,
+            slab,
+            &user_token,
+            vault,
+            user_idx,
+            amount,
+        ),
+    ]
+}
+
+/// Wrapped-SOL markets: create the owner's wSOL ATA if missing, move
+/// `amount` lamports into it, sync, and deposit, all in one transaction.
+/// The owner pays.
+pub fn deposit_sol(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    slab: &Pubkey,
+    vault: &Pubkey,
+    user_idx: u16,
+    amount: u64,
+) -> Result<Vec<Instruction>, String> {
+    let mint = spl_token::native_mint::ID;
+    let user_token = associated_token_address(owner, &mint);
+    Ok(vec![
+        create_idempotent(owner, owner, &mint),
+        system_instruction::transfer(owner, &user_token, amount),
+        spl_token::instruction::sync_native(&spl_token::ID, &user_token)
+            .map_err(|e| e.to_string())?,
+        ix::deposit(
+            program_id,
+            owner,
+            slab,
+            &user_token,
+            vault,
+            user_idx,
+            amount,
+        ),
+    ])
+}
+
+/// Create-if-missing, then WithdrawCollateral into the owner's ATA, so a
+/// wallet that never held the mint can still withdraw.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_to_ata(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    owner: &Pubkey,
+    slab: &Pubkey,
+    mint: &Pubkey,
+    vault: &Pubkey,
+    oracles: &[Pubkey],
+    user_idx: u16,
+    amount: u64,
+    nonce: Option<u64>,
+) -> Vec<Instruction> {
+    let user_token = associated_token_address(owner, mint);
+    vec![
+        create_idempotent(payer, owner, mint),
+        ix::withdraw(
+            program_id,
+            owner,
+            slab,
+            vault,
+            &user_token,
+            oracles,
+            user_idx,
+            amount,
+            nonce,
+        ),
+    ]
+}
@@ -0,0 +1,233 @@
+//! Create and initialize a Percolator market on a live cluster.
+//!
+//! Allocates the slab (owned by the program), then sends InitMarket from a
+//! TOML config. InitMarket creates the collateral vault (the vault PDA's
+//! ATA) and, when configured with the default size, the matcher context
+//! in the same transaction. Prints every address as JSON on stdout so
+//! downstream tooling can pick them up.
+//!
+//!   percolator-bootstrap --url http://127.0.0.1:8899 \
+//!       --keypair ~/.config/solana/id.json \
+//!       --program-id <PROGRAM> --config market.toml
+//!
+//! With `--fee-payer`, that key pays every fee and rent (slab, vault,
+//! matcher context) and the admin key only signs.
+//!
+//! Each transaction is retried with backoff and re-signed only after its
+//! blockhash expires (`tx::TxSender`); `--skip-preflight` sends without
+//! simulating first.
+
+use clap::Parser;
+use percolator_client::{
+    budget,
+    config::BootstrapConfig,
+    ix,
+    tx::{self, SendPolicy, TxSender},
+};
+use percolator_prog::{
+    accounts::derive_vault_authority, constants::MATCHER_CONTEXT_LEN, state::offsets::sbf::SLAB_LEN,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    system_instruction,
+};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    name = "percolator-bootstrap",
+    about = "Create and initialize a Percolator market"
+)]
+struct Args {
+    /// RPC endpoint.
+    #[arg(long, default_value = "http://127.0.0.1:8899")]
+    url: String,
+    /// Market admin; also the payer unless `--fee-payer` is given.
+    #[arg(long)]
+    keypair: PathBuf,
+    /// Pays transaction fees and rent.
+    #[arg(long)]
+    fee_payer: Option<PathBuf>,
+    /// Deployed Percolator program id.
+    #[arg(long)]
+    program_id: Pubkey,
+    /// Market config (TOML).
+    #[arg(long)]
+    config: PathBuf,
+    /// Compute-unit limit for the InitMarket transaction.
+    #[arg(long, default_value_t = 1_400_000)]
+    cu_limit: u32,
+    /// Priority fee in micro-lamports per CU (0 = none).
+    #[arg(long, default_value_t = 0)]
+    priority_fee: u64,
+    /// Send without preflight simulation.
+    #[arg(long)]
+    skip_preflight: bool,
+}
+
+fn send(
+    sender: &TxSender,
+    payer: &Keypair,
+    ixs: &[Instruction],
+    signers: &[&dyn Signer],
+) -> Result<String, String> {
+    sender
+        .send(|blockhash| tx::signed(ixs, payer, signers, blockhash))
+        .map(|sig| sig.to_string())
+}
+
+fn create_account_ix(
+    rpc: &RpcClient,
+    payer: &Pubkey,
+    account: &Pubkey,
+    space: usize,
+    owner: &Pubkey,
+) -> Result<Instruction, String> {
+    let lamports = rpc
+        .get_minimum_balance_for_rent_exemption(space)
+        .map_err(|e| e.to_string())?;
+    Ok(system_instruction::create_account(
+        payer,
+        account,
+        lamports,
+        space as u64,
+        owner,
+    ))
+}
+
+fn run(args: Args) -> Result<serde_json::Value, String> {
+    let read =
+        |path: &PathBuf| read_keypair_file(path).map_err(|e| format!("{}: {e}", path.display()));
+    let admin = read(&args.keypair)?;
+    let sponsor = args.fee_payer.as_ref().map(read).transpose()?;
+    let payer = sponsor.as_ref().unwrap_or(&admin);
+    let toml = std::fs::read_to_string(&args.config)
+        .map_err(|e| format!("{}: {e}", args.config.display()))?;
+    let cfg = BootstrapConfig::from_toml(&toml)?;
+    let params = cfg.init_market_params(admin.pubkey())?;
+    let oracles = cfg.oracle_accounts()?;
+    let rpc = RpcClient::new_with_commitment(args.url.clone(), CommitmentConfig::confirmed());
+    let sender = TxSender::new(
+        &rpc,
+        SendPolicy {
+            skip_preflight: args.skip_preflight,
+            ..SendPolicy::default()
+        },
+    );
+
+    let slab = Keypair::new();
+    let (vault_authority, _) = derive_vault_authority(&args.program_id, &slab.pubkey());
+    let vault = ix::vault_address(&args.program_id, &slab.pubkey(), &params.collateral_mint);
+
+    // Slab: program-owned, exactly the on-chain (sbf) SLAB_LEN for the
+    // size tier this client was built with. InitMarket zeroes it.
+    let slab_sig = send(
+        &sender,
+        payer,
+        &[create_account_ix(
+            &rpc,
+            &payer.pubkey(),
+            &slab.pubkey(),
+            SLAB_LEN,
+            &args.program_id,
+        )?],
+        &[&slab],
+    )?;
+
+    // A matcher context of the default size is created by InitMarket in
+    // the same transaction; other sizes still need their own.
+    let mut init_ixs = budget::compute_budget_ixs(args.cu_limit, args.priority_fee);
+    let mut init_signers: Vec<&dyn Signer> = vec![&admin];
+    let matcher_ctx = Keypair::new();
+    let matcher = match &cfg.matcher {
+        Some(m) if m.context_len as usize == MATCHER_CONTEXT_LEN => {
+            let program = percolator_client::config::parse_pubkey("matcher.program", &m.program)?;
+            init_ixs.push(ix::init_market_with_matcher_ctx(
+                &args.program_id,
+                &slab.pubkey(),
+                &oracles,
+                &params,
+                &program,
+                &matcher_ctx.pubkey(),
+            ));
+            init_signers.push(&matcher_ctx);
+            Some((program, None))
+        }
+        Some(m) => {
+            let program = percolator_client::config::parse_pubkey("matcher.program", &m.program)?;
+            let sig = send(
+                &sender,
+                payer,
+                &[create_account_ix(
+                    &rpc,
+                    &payer.pubkey(),
+                    &matcher_ctx.pubkey(),
+                    m.context_len as usize,
+                    &program,
+                )?],
+                &[&matcher_ctx],
+            )?;
+            init_ixs.push(ix::init_market(
+                &args.program_id,
+                &slab.pubkey(),
+                &oracles,
+                &params,
+            ));
+            Some((program, Some(sig)))
+        }
+        None => {
+            init_ixs.push(ix::init_market(
+                &args.program_id,
+                &slab.pubkey(),
+                &oracles,
+                &params,
+            ));
+            None
+        }
+    };
+    if let Some(sponsor) = &sponsor {
+        let init = init_ixs.pop().expect("InitMarket was pushed");
+        init_ixs.push(ix::with_rent_payer(init, &sponsor.pubkey()));
+    }
+    let init_sig = send(&sender, payer, &init_ixs, &init_signers)?;
+
+    let mut out = serde_json::json!({
+        "rpc_url": args.url,
+        "program_id": args.program_id.to_string(),
+        "admin": admin.pubkey().to_string(),
+        "fee_payer": payer.pubkey().to_string(),
+        "slab": slab.pubkey().to_string(),
+        "slab_len": SLAB_LEN,
+        "vault": vault.to_string(),
+        "vault_authority": vault_authority.to_string(),
+        "collateral_mint": params.collateral_mint.to_string(),
+        "oracles": oracles.iter().map(|k| k.to_string()).collect::<Vec<_>>(),
+        "hyperp": cfg.is_hyperp(),
+        "signatures": {
+            "create_slab": slab_sig,
+            "init_market": init_sig,
+        },
+    });
+    if let Some((program, sig)) = matcher {
+        out["matcher_program"] = program.to_string().into();
+        out["matcher_context"] = matcher_ctx.pubkey().to_string().into();
+        if let Some(sig) = sig {
+            out["signatures"]["create_matcher_context"] = sig.into();
+        }
+    }
+    Ok(out)
+}
+
+fn main() {
+    match run(Args::parse()) {
+        Ok(out) => println!("{}", serde_json::to_string_pretty(&out).unwrap()),
+        Err(e) => {
+            eprintln!("percolator-bootstrap: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Yellowstone gRPC ingestion for one market.
+//!
+//! Subscribes to the slab account and to every successful transaction
+//! that touches it, runs both streams through `feed::FeedDecoder` and
+//! writes the normalized fill / funding / liquidation records to stdout
+//! as JSON lines.
+//!
+//!   percolator-geyser --endpoint https://grpc.example:443 --x-token <TOKEN> \
+//!       --program-id <PROGRAM> --slab <SLAB> > feed.jsonl
+
+use clap::Parser;
+use futures::StreamExt;
+use percolator_client::feed::{AccountUpdate, FeedDecoder, FeedRecord, TxInstruction, TxUpdate};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{collections::HashMap, io::Write};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::prelude::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+    SubscribeRequestFilterAccounts, SubscribeRequestFilterTransactions, SubscribeUpdateAccount,
+    SubscribeUpdateTransaction,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "percolator-geyser",
+    about = "Normalized market feed from Yellowstone gRPC"
+)]
+struct Args {
+    #[arg(long)]
+    endpoint: String,
+    #[arg(long)]
+    x_token: Option<String>,
+    #[arg(long)]
+    program_id: Pubkey,
+    #[arg(long)]
+    slab: Pubkey,
+}
+
+fn pubkey(bytes: &[u8]) -> Result<Pubkey, String> {
+    Pubkey::try_from(bytes).map_err(|_| format!("bad pubkey length {}", bytes.len()))
+}
+
+fn signature(bytes: &[u8]) -> Result<Signature, String> {
+    Signature::try_from(bytes).map_err(|_| format!("bad signature length {}", bytes.len()))
+}
+
+fn tx_update(u: SubscribeUpdateTransaction) -> Result<Option<TxUpdate>, String> {
+    let Some(info) = u.transaction else {
+        return Ok(None);
+    };
+    let (Some(tx), Some(meta)) = (info.transaction, info.meta) else {
+        return Ok(None);
+    };
+    let Some(message) = tx.message else {
+        return Ok(None);
+    };
+    // Static keys, then lookup-table writable, then readonly: the v0
+    // message key order instruction indices refer to.
+    let keys = message
+        .account_keys
+        .iter()
+        .chain(&meta.loaded_writable_addresses)
+        .chain(&meta.loaded_readonly_addresses)
+        .map(|k| pubkey(k))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = |i: usize| {
+        keys.get(i)
+            .copied()
+            .ok_or_else(|| format!("account index {i} out of range"))
+    };
+    let instructions = message
+        .instructions
+        .into_iter()
+        .map(|ix| {
+            Ok(TxInstruction {
+                program_id: key(ix.program_id_index as usize)?,
+                accounts: ix
+                    .accounts
+                    .iter()
+                    .map(|&i| key(i as usize))
+                    .collect::<Result<_, String>>()?,
+                data: ix.data,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    let return_data = match meta.return_data {
+        Some(r) if !meta.return_data_none => Some((pubkey(&r.program_id)?, r.data)),
+        _ => None,
+    };
+    Ok(Some(TxUpdate {
+        slot: u.slot,
+        signature: signature(&info.signature)?,
+        success: meta.err.is_none(),
+        instructions,
+        return_data,
+    }))
+}
+
+fn on_account(
+    decoder: &mut FeedDecoder,
+    u: SubscribeUpdateAccount,
+) -> Result<Vec<FeedRecord>, String> {
+    let Some(info) = u.account else {
+        return Ok(Vec::new());
+    };
+    let txn_signature = info.txn_signature.as_deref().map(signature).transpose()?;
+    decoder.on_account(&AccountUpdate {
+        slot: u.slot,
+        data: &info.data,
+        txn_signature,
+    })
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let mut client = GeyserGrpcClient::build_from_shared(args.endpoint.clone())
+        .and_then(|b| b.x_token(args.x_token.clone()))
+        .map_err(|e| format!("{}: {e}", args.endpoint))?
+        .connect()
+        .await
+        .map_err(|e| format!("{}: {e}", args.endpoint))?;
+    let request = SubscribeRequest {
+        accounts: HashMap::from([(
+            "slab".to_string(),
+            SubscribeRequestFilterAccounts {
+                account: vec![args.slab.to_string()],
+                ..Default::default()
+            },
+        )]),
+        transactions: HashMap::from([(
+            "market".to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(false),
+                failed: Some(false),
+                account_include: vec![args.slab.to_string()],
+                ..Default::default()
+            },
+        )]),
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        ..Default::default()
+    };
+    let (_sink, mut stream) = client
+        .subscribe_with_request(Some(request))
+        .await
+        .map_err(|e| format!("subscribe: {e}"))?;
+
+    let mut decoder = FeedDecoder::new(args.program_id);
+    let mut out = std::io::stdout().lock();
+    while let Some(msg) = stream.next().await {
+        let msg = msg.map_err(|e| format!("stream: {e}"))?;
+        let records = match msg.update_oneof {
+            Some(UpdateOneof::Account(u)) => on_account(&mut decoder, u),
+            Some(UpdateOneof::Transaction(u)) => {
+                tx_update(u).map(|t| t.map(|t| decoder.on_transaction(&t)).unwrap_or_default())
+            }
+            _ => continue,
+        };
+        match records {
+            Ok(records) => {
+                for r in records {
+                    let line = serde_json::to_string(&r).map_err(|e| e.to_string())?;
+                    writeln!(out, "{line}").map_err(|e| e.to_string())?;
+                }
+                out.flush().map_err(|e| e.to_string())?;
+            }
+            Err(e) => eprintln!("percolator-geyser: skipped update: {e}"),
+        }
+    }
+    Err("stream closed".into())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(Args::parse()).await {
+        eprintln!("percolator-geyser: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,522 @@
+//! Reference keeper for Percolator markets.
+//!
+//! Every poll it fetches the configured slabs and, per market:
+//! - pushes a Hyperp mark when the keeper key is the mark authority and a
+//!   `mark_price_cmd` is configured;
+//! - submits KeeperCrank with FullClose candidates for accounts whose
+//!   off-chain health estimate is below maintenance (plus buffer);
+//! - otherwise cranks once the market's slot lag reaches the configured
+//!   interval or approaches the accrual envelope.
+//!
+//! With a `[jito]` section, candidate-bearing cranks go to the block engine
+//! as single-transaction bundles carrying a tip; plain cranks still use RPC.
+//!
+//! The health estimate lags the engine (no mark-to-market since last
+//! touch); the crank re-checks every candidate on-chain, so a false
+//! positive only costs a touch.
+//!
+//!   percolator-keeper --url http://127.0.0.1:8899 \
+//!       --keypair keeper.json --program-id <PROGRAM> --config keeper.toml
+//!
+//! `--fee-payer` moves transaction fees and Jito tips to a separate
+//! funded key; the keeper key then only signs as crank caller and mark
+//! authority.
+//!
+//! RPC submissions go through `tx::TxSender` under the config's `[send]`
+//! policy: backoff between attempts, and a re-sign only once the
+//! blockhash has expired. `--nonce-account` signs every transaction
+//! against that durable nonce instead, so the same signed transaction is
+//! resent until it lands and can never land twice. The nonce authority
+//! must be the keeper key or the fee payer.
+
+use clap::Parser;
+use percolator_client::{
+    budget,
+    config::{parse_pubkey, JitoConfig, KeeperConfig, KeeperSlab},
+    ix, jito,
+    slab::Slab,
+    tx::{self, Nonce, TxSender},
+};
+use percolator_prog::constants::MAX_KEEPER_CANDIDATES;
+use prometheus::{Encoder, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Parser)]
+#[command(
+    name = "percolator-keeper",
+    about = "Crank, mark-push and liquidation keeper"
+)]
+struct Args {
+    #[arg(long, default_value = "http://127.0.0.1:8899")]
+    url: String,
+    /// Crank caller and (optionally) Hyperp mark authority; also the fee
+    /// payer unless `--fee-payer` is given.
+    #[arg(long)]
+    keypair: PathBuf,
+    /// Pays transaction fees and tips.
+    #[arg(long)]
+    fee_payer: Option<PathBuf>,
+    /// Durable nonce account for crank and mark-push transactions.
+    #[arg(long)]
+    nonce_account: Option<Pubkey>,
+    #[arg(long)]
+    program_id: Pubkey,
+    /// Keeper config (TOML).
+    #[arg(long)]
+    config: PathBuf,
+    /// Fixed compute-unit limit. Defaults to the program's published
+    /// per-instruction estimates.
+    #[arg(long)]
+    cu_limit: Option<u32>,
+}
+
+struct Metrics {
+    registry: Registry,
+    cranks: IntCounterVec,
+    candidates: IntCounterVec,
+    mark_pushes: IntCounterVec,
+    bundles: IntCounterVec,
+    slot_lag: IntGaugeVec,
+    insurance: IntGaugeVec,
+    vault: IntGaugeVec,
+    open_accounts: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let c = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(c.clone())).unwrap();
+            c
+        };
+        let cranks = counter(
+            "percolator_keeper_cranks_total",
+            "KeeperCrank transactions sent",
+            &["slab", "result"],
+        );
+        let candidates = counter(
+            "percolator_keeper_liquidation_candidates_total",
+            "FullClose candidates submitted",
+            &["slab"],
+        );
+        let mark_pushes = counter(
+            "percolator_keeper_mark_pushes_total",
+            "PushHyperpMark transactions sent",
+            &["slab", "result"],
+        );
+        let bundles = counter(
+            "percolator_keeper_jito_bundles_total",
+            "Liquidation cranks submitted as Jito bundles",
+            &["slab", "result"],
+        );
+        let gauge = |name: &str, help: &str| {
+            let g = IntGaugeVec::new(Opts::new(name, help), &["slab"]).unwrap();
+            registry.register(Box::new(g.clone())).unwrap();
+            g
+        };
+        let slot_lag = gauge(
+            "percolator_keeper_slot_lag",
+            "Current slot minus engine last_market_slot",
+        );
+        let insurance = gauge(
+            "percolator_keeper_insurance_balance",
+            "Engine insurance fund balance (units)",
+        );
+        let vault = gauge("percolator_keeper_vault", "Engine vault balance (units)");
+        let open_accounts = gauge(
+            "percolator_keeper_open_accounts",
+            "Used accounts with a nonzero effective position",
+        );
+        Self {
+            registry,
+            cranks,
+            candidates,
+            mark_pushes,
+            bundles,
+            slot_lag,
+            insurance,
+            vault,
+            open_accounts,
+        }
+    }
+
+    /// Minimal `/metrics` endpoint: any request gets the text exposition.
+    fn serve(registry: Registry, addr: String) {
+        std::thread::spawn(move || {
+            let listener = match TcpListener::bind(&addr) {
+                Ok(l) => l,
+                Err(e) => {
+                    eprintln!("metrics: bind {addr}: {e}");
+                    return;
+                }
+            };
+            for mut stream in listener.incoming().flatten() {
+                let mut req = [0u8; 1024];
+                let _ = stream.read(&mut req);
+                let mut body = Vec::new();
+                if TextEncoder::new()
+                    .encode(&registry.gather(), &mut body)
+                    .is_err()
+                {
+                    continue;
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+    }
+}
+
+struct Keeper {
+    rpc: RpcClient,
+    authority: Keypair,
+    fee_payer: Option<Keypair>,
+    nonce: Option<Nonce>,
+    program_id: Pubkey,
+    cfg: KeeperConfig,
+    cu_limit: Option<u32>,
+    metrics: Metrics,
+}
+
+/// How long a submitted bundle has to land before it counts as dropped.
+const BUNDLE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn clamp_i64(v: u128) -> i64 {
+    v.min(i64::MAX as u128) as i64
+}
+
+fn run_mark_cmd(cmd: &str) -> Result<u64, String> {
+    let out = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .output()
+        .map_err(|e| format!("mark_price_cmd: {e}"))?;
+    if !out.status.success() {
+        return Err(format!("mark_price_cmd exited with {}", out.status));
+    }
+    let s = String::from_utf8_lossy(&out.stdout);
+    s.trim()
+        .parse::<u64>()
+        .map_err(|e| format!("mark_price_cmd output {:?}: {e}", s.trim()))
+}
+
+impl Keeper {
+    fn fee_payer(&self) -> &Keypair {
+        self.fee_payer.as_ref().unwrap_or(&self.authority)
+    }
+
+    /// Compute-budget instructions sized for `counted`, then `ixs`.
+    fn with_budget(&self, counted: &[Instruction], ixs: &[Instruction]) -> Vec<Instruction> {
+        let cu_limit = self
+            .cu_limit
+            .unwrap_or_else(|| budget::estimate_cu(&self.program_id, counted));
+        let mut all = budget::compute_budget_ixs(cu_limit, self.cfg.priority_fee_micro_lamports);
+        all.extend_from_slice(ixs);
+        all
+    }
+
+    fn signed_tx(&self, ixs: &[Instruction]) -> Result<Transaction, String> {
+        let Some(nonce) = &self.nonce else {
+            let blockhash = self.rpc.get_latest_blockhash().map_err(|e| e.to_string())?;
+            let all = self.with_budget(ixs, ixs);
+            return tx::signed(&all, self.fee_payer(), &[&self.authority], blockhash);
+        };
+        // The advance is budgeted but `durable` puts it first.
+        let all = self.with_budget(&tx::with_advance_nonce(ixs, nonce), ixs);
+        let account = self
+            .rpc
+            .get_account(&nonce.account)
+            .map_err(|e| format!("{}: {e}", nonce.account))?;
+        let nonce_hash = tx::nonce_data(&account)?.blockhash();
+        tx::durable(
+            &all,
+            self.fee_payer(),
+            &[&self.authority],
+            nonce,
+            nonce_hash,
+        )
+    }
+
+    fn send(&self, ixs: &[Instruction]) -> Result<String, String> {
+        let sender = TxSender::new(&self.rpc, self.cfg.send);
+        let sig = if self.nonce.is_some() {
+            sender.send_durable(&self.signed_tx(ixs)?)?
+        } else {
+            let all = self.with_budget(ixs, ixs);
+            sender.send(|blockhash| {
+                tx::signed(&all, self.fee_payer(), &[&self.authority], blockhash)
+            })?
+        };
+        Ok(sig.to_string())
+    }
+
+    /// Send `ixs` plus the tip as a one-transaction bundle and wait for it
+    /// to land. An on-chain failure is reported, not retried.
+    fn send_bundle(&self, cfg: &JitoConfig, ixs: &[Instruction]) -> Result<String, String> {
+        let mut with_tip = ixs.to_vec();
+        with_tip.push(jito::tip_ix(&self.fee_payer().pubkey(), cfg)?);
+        let tx = self.signed_tx(&with_tip)?;
+        let sig = tx.signatures[0];
+        let bundle_id = jito::send_bundle(cfg, &[tx])?;
+        let start = Instant::now();
+        while start.elapsed() < BUNDLE_CONFIRM_TIMEOUT {
+            match self
+                .rpc
+                .get_signature_status_with_commitment(&sig, CommitmentConfig::confirmed())
+                .map_err(|e| e.to_string())?
+            {
+                Some(Ok(())) => return Ok(sig.to_string()),
+                Some(Err(e)) => return Err(format!("bundle {bundle_id}: {e}")),
+                None => std::thread::sleep(Duration::from_millis(500)),
+            }
+        }
+        Err(format!("bundle {bundle_id} did not land"))
+    }
+
+    /// Candidate-bearing cranks go through Jito when configured.
+    fn send_crank(
+        &self,
+        label: &str,
+        crank: Instruction,
+        liquidating: bool,
+    ) -> Result<String, String> {
+        let Some(cfg) = self.cfg.jito.as_ref().filter(|_| liquidating) else {
+            return self.send(&[crank]);
+        };
+        let result = self.send_bundle(cfg, std::slice::from_ref(&crank));
+        let status = if result.is_ok() { "ok" } else { "err" };
+        self.metrics
+            .bundles
+            .with_label_values(&[label, status])
+            .inc();
+        match result {
+            Err(e) if cfg.fallback_to_rpc => {
+                eprintln!("{label}: {e}; resending through RPC");
+                self.send(&[crank])
+            }
+            other => other,
+        }
+    }
+
+    fn tick_slab(
+        &self,
+        entry: &KeeperSlab,
+        slab_key: &Pubkey,
+        data: &[u8],
+        slot: u64,
+    ) -> Result<(), String> {
+        let label = slab_key.to_string();
+        let slab = Slab::from_bytes(data);
+        let config = slab.config();
+        let engine = slab.engine()?;
+        if engine.market_mode == percolator::MarketMode::Resolved {
+            return Ok(());
+        }
+
+        let lag = slot.saturating_sub(engine.last_market_slot);
+        self.metrics
+            .slot_lag
+            .with_label_values(&[&label])
+            .set(clamp_i64(lag as u128));
+        self.metrics
+            .insurance
+            .with_label_values(&[&label])
+            .set(clamp_i64(engine.insurance_fund.balance.get()));
+        self.metrics
+            .vault
+            .with_label_values(&[&label])
+            .set(clamp_i64(engine.vault.get()));
+        let open = slab
+            .used_indices()?
+            .into_iter()
+            .filter(|&i| slab.effective_pos_q(i).unwrap_or(0) != 0)
+            .count();
+        self.metrics
+            .open_accounts
+            .with_label_values(&[&label])
+            .set(open as i64);
+
+        let is_hyperp = config.index_feed_id == [0u8; 32];
+        if is_hyperp && slab.is_hyperp_pusher(&self.authority.pubkey().to_bytes()) {
+            if let Some(cmd) = &entry.mark_price_cmd {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                let result = run_mark_cmd(cmd).and_then(|price_e6| {
+                    self.send(&[ix::push_hyperp_mark(
+                        &self.program_id,
+                        &self.authority.pubkey(),
+                        slab_key,
+                        price_e6,
+                        now,
+                    )])
+                });
+                let status = if result.is_ok() { "ok" } else { "err" };
+                self.metrics
+                    .mark_pushes
+                    .with_label_values(&[&label, status])
+                    .inc();
+                if let Err(e) = result {
+                    eprintln!("{label}: mark push failed: {e}");
+                }
+            }
+        }
+
+        let candidates: Vec<(u16, u8)> = slab
+            .liquidation_candidates(self.cfg.liquidation_buffer_bps)?
+            .into_iter()
+            .take(MAX_KEEPER_CANDIDATES)
+            .map(|h| (h.idx, ix::CANDIDATE_FULL_CLOSE))
+            .collect();
+        if candidates.is_empty() && lag < self.cfg.crank_due_lag() {
+            return Ok(());
+        }
+
+        let oracle = match entry.oracles.first() {
+            Some(k) => parse_pubkey("slab.oracles", k)?,
+            None if is_hyperp => solana_sdk::sysvar::clock::ID,
+            None => return Err("slab.oracles is required for external-oracle markets".into()),
+        };
+        let mut crank = ix::keeper_crank(
+            &self.program_id,
+            &self.authority.pubkey(),
+            slab_key,
+            &oracle,
+            &candidates,
+        );
+        for extra in entry.oracles.iter().skip(1) {
+            crank
+                .accounts
+                .push(solana_sdk::instruction::AccountMeta::new_readonly(
+                    parse_pubkey("slab.oracles", extra)?,
+                    false,
+                ));
+        }
+        let result = self.send_crank(&label, crank, !candidates.is_empty());
+        let status = if result.is_ok() { "ok" } else { "err" };
+        self.metrics
+            .cranks
+            .with_label_values(&[&label, status])
+            .inc();
+        self.metrics
+            .candidates
+            .with_label_values(&[&label])
+            .inc_by(candidates.len() as u64);
+        result.map(|sig| {
+            println!(
+                "{label}: crank lag={lag} candidates={:?} sig={sig}",
+                candidates.iter().map(|c| c.0).collect::<Vec<_>>()
+            )
+        })
+    }
+
+    fn tick(&self, slabs: &[Pubkey]) -> Result<(), String> {
+        let slot = self.rpc.get_slot().map_err(|e| e.to_string())?;
+        let accounts = self
+            .rpc
+            .get_multiple_accounts(slabs)
+            .map_err(|e| e.to_string())?;
+        for ((entry, key), account) in self.cfg.slabs.iter().zip(slabs).zip(accounts) {
+            let Some(account) = account else {
+                eprintln!("{key}: slab account not found");
+                continue;
+            };
+            if let Err(e) = self.tick_slab(entry, key, &account.data, slot) {
+                eprintln!("{key}: {e}");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let setup = || -> Result<Keeper, String> {
+        let read = |path: &PathBuf| {
+            read_keypair_file(path).map_err(|e| format!("{}: {e}", path.display()))
+        };
+        let toml = std::fs::read_to_string(&args.config)
+            .map_err(|e| format!("{}: {e}", args.config.display()))?;
+        let rpc = RpcClient::new_with_commitment(args.url.clone(), CommitmentConfig::confirmed());
+        let authority = read(&args.keypair)?;
+        let fee_payer = args.fee_payer.as_ref().map(read).transpose()?;
+        let nonce = match args.nonce_account {
+            Some(account) => {
+                let data = rpc
+                    .get_account(&account)
+                    .map_err(|e| format!("{account}: {e}"))
+                    .and_then(|a| tx::nonce_data(&a))?;
+                let signers = [Some(&authority), fee_payer.as_ref()];
+                if !signers
+                    .iter()
+                    .flatten()
+                    .any(|k| k.pubkey() == data.authority)
+                {
+                    return Err(format!(
+                        "{account}: nonce authority {} is neither the keeper key nor the fee payer",
+                        data.authority
+                    ));
+                }
+                Some(Nonce {
+                    account,
+                    authority: data.authority,
+                })
+            }
+            None => None,
+        };
+        Ok(Keeper {
+            rpc,
+            authority,
+            fee_payer,
+            nonce,
+            program_id: args.program_id,
+            cfg: KeeperConfig::from_toml(&toml)?,
+            cu_limit: args.cu_limit,
+            metrics: Metrics::new(),
+        })
+    };
+    let keeper = setup().unwrap_or_else(|e| {
+        eprintln!("percolator-keeper: {e}");
+        std::process::exit(1);
+    });
+    let slabs: Vec<Pubkey> = keeper
+        .cfg
+        .slabs
+        .iter()
+        .map(|s| parse_pubkey("slab.address", &s.address))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("percolator-keeper: {e}");
+            std::process::exit(1);
+        });
+    if let Some(addr) = &keeper.cfg.metrics_addr {
+        Metrics::serve(keeper.metrics.registry.clone(), addr.clone());
+    }
+
+    let interval = Duration::from_millis(keeper.cfg.poll_interval_ms);
+    loop {
+        if let Err(e) = keeper.tick(&slabs) {
+            eprintln!("percolator-keeper: {e}");
+        }
+        std::thread::sleep(interval);
+    }
+}
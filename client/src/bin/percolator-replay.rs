@@ -0,0 +1,53 @@
+//! Replay a recorded fixture through the BPF program and check it
+//! reproduces the recorded outcomes and final slab.
+//!
+//!   percolator-replay --program ../target/deploy/percolator_prog.so \
+//!       --fixture incident.json [--dump-final slab.bin]
+//!
+//! `--dump-final` writes the replayed slab for `percolator inspect --file`.
+
+use clap::Parser;
+use percolator_client::replay::ReplayFixture;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "percolator-replay", about = "Deterministic market replay")]
+struct Args {
+    /// Program binary (`cargo build-sbf` output).
+    #[arg(long)]
+    program: PathBuf,
+    /// Replay fixture (JSON).
+    #[arg(long)]
+    fixture: PathBuf,
+    /// Write the replayed slab here.
+    #[arg(long)]
+    dump_final: Option<PathBuf>,
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let program =
+        std::fs::read(&args.program).map_err(|e| format!("{}: {e}", args.program.display()))?;
+    let json = std::fs::read_to_string(&args.fixture)
+        .map_err(|e| format!("{}: {e}", args.fixture.display()))?;
+    let fixture = ReplayFixture::from_json(&json)?;
+    let report = fixture.run(&program).map_err(|e| e.to_string())?;
+    if let Some(path) = &args.dump_final {
+        std::fs::write(path, &report.final_slab).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    println!(
+        "{}",
+        serde_json::json!({
+            "steps": report.steps,
+            "compute_units": report.compute_units,
+            "final_slab_checked": fixture.expected_slab.is_some(),
+        })
+    );
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("percolator-replay: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,221 @@
+//! Export one owner's account statement for a market.
+//!
+//!   percolator-statement --url https://api.mainnet-beta.solana.com \
+//!       --slab <SLAB> --owner <OWNER> [--format csv|json] [--out statement.csv]
+//!
+//! Walks the slab's signature history oldest first, decodes the owner's
+//! deposits, withdrawals, fills, funding and liquidations
+//! (`statement::StatementBuilder`), and ends with one `ledger` row per
+//! account the owner still holds, read from the current slab.
+//! `--until` stops the walk at a signature already exported.
+
+use base64::Engine as _;
+use clap::{Parser, ValueEnum};
+use percolator_client::{
+    feed::{TxInstruction, TxUpdate},
+    slab::Slab,
+    statement::{self, StatementBuilder, StatementRecord, StatementTx},
+};
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+    UiTransactionEncoding,
+};
+use std::{path::PathBuf, str::FromStr};
+
+/// Signatures per `getSignaturesForAddress` page (the RPC maximum).
+const PAGE: usize = 1_000;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Parser)]
+#[command(name = "percolator-statement", about = "Per-owner account statement")]
+struct Args {
+    #[arg(long, default_value = "http://127.0.0.1:8899")]
+    url: String,
+    /// Market slab account.
+    #[arg(long)]
+    slab: Pubkey,
+    /// Account owner (user or LP owner).
+    #[arg(long)]
+    owner: Pubkey,
+    #[arg(long, value_enum, default_value = "csv")]
+    format: Format,
+    /// Stop at this signature (exclusive).
+    #[arg(long)]
+    until: Option<Signature>,
+    /// Write here instead of stdout.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn pubkey(s: &str) -> Result<Pubkey, String> {
+    Pubkey::from_str(s).map_err(|e| format!("{s}: {e}"))
+}
+
+/// Successful signatures touching `slab`, oldest first.
+fn signatures(
+    rpc: &RpcClient,
+    slab: &Pubkey,
+    until: Option<Signature>,
+) -> Result<Vec<Signature>, String> {
+    let mut out = Vec::new();
+    let mut before = None;
+    loop {
+        let page = rpc
+            .get_signatures_for_address_with_config(
+                slab,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until,
+                    limit: Some(PAGE),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .map_err(|e| format!("signatures for {slab}: {e}"))?;
+        let Some(last) = page.last() else { break };
+        before = Some(Signature::from_str(&last.signature).map_err(|e| e.to_string())?);
+        let full = page.len() == PAGE;
+        for s in page.into_iter().filter(|s| s.err.is_none()) {
+            out.push(Signature::from_str(&s.signature).map_err(|e| e.to_string())?);
+        }
+        if !full {
+            break;
+        }
+    }
+    out.reverse();
+    Ok(out)
+}
+
+fn statement_tx(
+    signature: Signature,
+    t: EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<StatementTx, String> {
+    let tx = t
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| format!("{signature}: undecodable transaction"))?;
+    let meta = t
+        .transaction
+        .meta
+        .ok_or_else(|| format!("{signature}: no status meta"))?;
+    // Static keys, then lookup-table writable, then readonly: the v0
+    // message key order instruction indices refer to.
+    let mut keys = tx.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for k in loaded.writable.iter().chain(&loaded.readonly) {
+            keys.push(pubkey(k)?);
+        }
+    }
+    let key = |i: u8| {
+        keys.get(i as usize)
+            .copied()
+            .ok_or_else(|| format!("{signature}: account index {i} out of range"))
+    };
+    let instructions = tx
+        .message
+        .instructions()
+        .iter()
+        .map(|ix| {
+            Ok(TxInstruction {
+                program_id: key(ix.program_id_index)?,
+                accounts: ix
+                    .accounts
+                    .iter()
+                    .map(|&i| key(i))
+                    .collect::<Result<_, _>>()?,
+                data: ix.data.clone(),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let return_data = match meta.return_data {
+        OptionSerializer::Some(r) => Some((
+            pubkey(&r.program_id)?,
+            b64.decode(&r.data.0)
+                .map_err(|e| format!("{signature}: return data: {e}"))?,
+        )),
+        _ => None,
+    };
+    // sol_log_data logs each field as its own base64 word.
+    let log_data = match meta.log_messages {
+        OptionSerializer::Some(logs) => logs
+            .iter()
+            .filter_map(|l| l.strip_prefix("Program data: "))
+            .flat_map(str::split_whitespace)
+            .filter_map(|w| b64.decode(w).ok())
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(StatementTx {
+        tx: TxUpdate {
+            slot: t.slot,
+            signature,
+            success: meta.err.is_none(),
+            instructions,
+            return_data,
+        },
+        block_time: t.block_time,
+        log_data,
+    })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let rpc = RpcClient::new_with_commitment(args.url, CommitmentConfig::confirmed());
+    let sigs = signatures(&rpc, &args.slab, args.until)?;
+    let account = rpc
+        .get_account(&args.slab)
+        .map_err(|e| format!("{}: {e}", args.slab))?;
+
+    let mut builder = StatementBuilder::new(account.owner, args.slab, args.owner);
+    let mut records = Vec::new();
+    for sig in sigs {
+        let t = rpc
+            .get_transaction_with_config(
+                &sig,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .map_err(|e| format!("{sig}: {e}"))?;
+        records.extend(builder.on_transaction(&statement_tx(sig, t)?));
+    }
+
+    let slab = Slab::from_bytes(&account.data);
+    let engine = slab.engine()?;
+    for idx in slab.used_indices()? {
+        if engine.accounts[idx as usize].owner == args.owner.to_bytes() {
+            records.push(StatementRecord::from(slab.pnl_statement(idx)?));
+        }
+    }
+
+    let out = match args.format {
+        Format::Csv => statement::to_csv(&records),
+        Format::Json => serde_json::to_string_pretty(&records).map_err(|e| e.to_string())? + "\n",
+    };
+    match &args.out {
+        Some(path) => std::fs::write(path, out).map_err(|e| format!("{}: {e}", path.display())),
+        None => {
+            print!("{out}");
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("percolator-statement: {e}");
+        std::process::exit(1);
+    }
+}
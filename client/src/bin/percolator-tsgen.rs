@@ -0,0 +1,44 @@
+//! Generate the web frontend's TypeScript layouts from the program source.
+//!
+//!   percolator-tsgen --source ../src/percolator.rs --out web/src/percolator.generated.ts
+//!
+//! Offsets follow the tier this binary was built with (`--features small`
+//! / `medium`). Writes to stdout without `--out`.
+
+use clap::Parser;
+use percolator_client::tsgen::{self, Tier};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    name = "percolator-tsgen",
+    about = "TypeScript layouts from src/percolator.rs"
+)]
+struct Args {
+    /// Program source.
+    #[arg(long, default_value = "../src/percolator.rs")]
+    source: PathBuf,
+    /// Output file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let source = std::fs::read_to_string(&args.source)
+        .map_err(|e| format!("{}: {e}", args.source.display()))?;
+    let ts = tsgen::generate(&source, Tier::current())?.to_typescript();
+    match &args.out {
+        Some(path) => std::fs::write(path, ts).map_err(|e| format!("{}: {e}", path.display())),
+        None => {
+            print!("{ts}");
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("percolator-tsgen: {e}");
+        std::process::exit(1);
+    }
+}
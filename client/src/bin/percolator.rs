@@ -0,0 +1,64 @@
+//! Operator CLI.
+//!
+//!   percolator inspect <SLAB> [--url http://127.0.0.1:8899]
+//!   percolator inspect --file slab.bin
+//!
+//! `inspect` fetches (or loads) a slab account and prints header, config,
+//! risk params, engine aggregates and the per-account table.
+
+use clap::{Parser, Subcommand};
+use percolator_client::{inspect, slab::Slab};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "percolator", about = "Percolator operator CLI")]
+struct Cli {
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8899")]
+    url: String,
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Pretty-print a market slab.
+    Inspect {
+        /// Slab account to fetch.
+        #[arg(required_unless_present = "file")]
+        slab: Option<Pubkey>,
+        /// Read raw account data from a file instead of RPC.
+        #[arg(long, conflicts_with = "slab")]
+        file: Option<PathBuf>,
+    },
+}
+
+fn run(cli: Cli) -> Result<String, String> {
+    match cli.cmd {
+        Cmd::Inspect { slab, file } => {
+            let data = match (slab, file) {
+                (_, Some(path)) => {
+                    std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?
+                }
+                (Some(key), None) => {
+                    RpcClient::new_with_commitment(cli.url, CommitmentConfig::confirmed())
+                        .get_account_data(&key)
+                        .map_err(|e| format!("{key}: {e}"))?
+                }
+                (None, None) => unreachable!("clap requires slab or --file"),
+            };
+            inspect::render(&Slab::from_bytes(&data))
+        }
+    }
+}
+
+fn main() {
+    match run(Cli::parse()) {
+        Ok(out) => print!("{out}"),
+        Err(e) => {
+            eprintln!("percolator: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Compute-budget instructions sized from the program's published
+//! per-instruction estimates (`constants::CU_ESTIMATES`).
+//!
+//! Trades and cranks routinely exceed the runtime's default 200k CU on a
+//! busy slab, and congested clusters drop transactions that bid no
+//! priority fee. `with_compute_budget` prepends both in one call:
+//!
+//! ```ignore
+//! let ixs = budget::with_compute_budget(&program_id, &[trade], 5_000);
+//! ```
+
+use percolator_prog::constants::{CU_ESTIMATES, CU_ESTIMATE_DEFAULT, MAX_TX_CU};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+};
+
+/// Budget for an instruction of another program (token transfers, ATA
+/// creation, ...). Generous; these are small next to the program's own.
+pub const FOREIGN_IX_CU: u32 = 50_000;
+
+/// Published ceiling for one instruction of this program, by tag.
+pub fn cu_estimate_for_tag(tag: u8) -> u32 {
+    CU_ESTIMATES
+        .iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, cu)| *cu)
+        .unwrap_or(CU_ESTIMATE_DEFAULT)
+}
+
+/// Summed estimate for `ixs`, capped at the transaction limit.
+/// Compute-budget instructions already present cost nothing.
+pub fn estimate_cu(program_id: &Pubkey, ixs: &[Instruction]) -> u32 {
+    ixs.iter()
+        .map(|ix| {
+            if ix.program_id == *program_id {
+                ix.data
+                    .first()
+                    .map_or(CU_ESTIMATE_DEFAULT, |&t| cu_estimate_for_tag(t))
+            } else if ix.program_id == solana_sdk::compute_budget::ID {
+                0
+            } else {
+                FOREIGN_IX_CU
+            }
+        })
+        .fold(0u32, u32::saturating_add)
+        .min(MAX_TX_CU)
+}
+
+/// SetComputeUnitLimit, plus SetComputeUnitPrice when
+/// `priority_fee_micro_lamports` (per CU) is nonzero.
+pub fn compute_budget_ixs(cu_limit: u32, priority_fee_micro_lamports: u64) -> Vec<Instruction> {
+    let mut out = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        cu_limit.min(MAX_TX_CU),
+    )];
+    if priority_fee_micro_lamports > 0 {
+        out.push(ComputeBudgetInstruction::set_compute_unit_price(
+            priority_fee_micro_lamports,
+        ));
+    }
+    out
+}
+
+/// `ixs` preceded by a limit covering their estimate and the given
+/// priority fee.
+pub fn with_compute_budget(
+    program_id: &Pubkey,
+    ixs: &[Instruction],
+    priority_fee_micro_lamports: u64,
+) -> Vec<Instruction> {
+    let mut out = compute_budget_ixs(estimate_cu(program_id, ixs), priority_fee_micro_lamports);
+    out.extend_from_slice(ixs);
+    out
+}
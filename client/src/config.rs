@@ -0,0 +1,409 @@
+//! Market bootstrap config (TOML).
+//!
+//! Field names follow `InitMarketArgs`. Everything that has a safe program
+//! default is optional; margins, fees and oracle wiring are not.
+
+use crate::ix::{InitMarketExtended, InitMarketOracleLegs, InitMarketParams, InitMarketRisk};
+use crate::tx::SendPolicy;
+use percolator_prog::state::MarketMetadata;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BootstrapConfig {
+    pub market: MarketSection,
+    pub risk: RiskSection,
+    #[serde(default)]
+    pub extended: ExtendedSection,
+    pub oracle_legs: Option<OracleLegsSection>,
+    pub matcher: Option<MatcherSection>,
+    pub metadata: Option<MetadataSection>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketSection {
+    pub collateral_mint: String,
+    /// Hex feed id (64 chars). Omit for a Hyperp market.
+    #[serde(default)]
+    pub index_feed_id: Option<String>,
+    /// Oracle account passed to InitMarket. Omit for a Hyperp market.
+    #[serde(default)]
+    pub index_oracle: Option<String>,
+    pub max_staleness_secs: u64,
+    pub conf_filter_bps: u16,
+    #[serde(default)]
+    pub invert: u8,
+    #[serde(default)]
+    pub unit_scale: u32,
+    #[serde(default)]
+    pub initial_mark_price_e6: u64,
+    #[serde(default)]
+    pub maintenance_fee_per_slot: u128,
+    #[serde(default)]
+    pub trade_fee_base_bps: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RiskSection {
+    pub h_min: u64,
+    pub h_max: u64,
+    pub maintenance_margin_bps: u64,
+    pub initial_margin_bps: u64,
+    pub max_trading_fee_bps: u64,
+    pub max_accounts: u64,
+    pub new_account_fee: u128,
+    pub liquidation_fee_bps: u64,
+    pub liquidation_fee_cap: u128,
+    pub resolve_price_deviation_bps: u64,
+    #[serde(default)]
+    pub min_liquidation_abs: u128,
+    pub min_nonzero_mm_req: u128,
+    pub min_nonzero_im_req: u128,
+    pub max_price_move_bps_per_slot: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ExtendedSection {
+    pub insurance_withdraw_max_bps: u16,
+    pub insurance_withdraw_cooldown_slots: u64,
+    pub permissionless_resolve_stale_slots: u64,
+    pub funding_horizon_slots: u64,
+    pub funding_k_bps: u64,
+    pub funding_max_premium_bps: i64,
+    pub funding_max_e9_per_slot: i64,
+    pub mark_min_fee: u64,
+    pub force_close_delay_slots: u64,
+}
+
+impl Default for ExtendedSection {
+    /// Mirrors the program's funding defaults; live insurance withdrawal
+    /// and permissionless resolution are off.
+    fn default() -> Self {
+        use percolator_prog::constants::*;
+        Self {
+            insurance_withdraw_max_bps: 0,
+            insurance_withdraw_cooldown_slots: 0,
+            permissionless_resolve_stale_slots: DEFAULT_PERMISSIONLESS_RESOLVE_STALE_SLOTS,
+            funding_horizon_slots: DEFAULT_FUNDING_HORIZON_SLOTS,
+            funding_k_bps: DEFAULT_FUNDING_K_BPS,
+            funding_max_premium_bps: DEFAULT_FUNDING_MAX_PREMIUM_BPS,
+            funding_max_e9_per_slot: DEFAULT_FUNDING_MAX_E9_PER_SLOT,
+            mark_min_fee: 0,
+            force_close_delay_slots: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OracleLegsSection {
+    pub count: u8,
+    #[serde(default)]
+    pub flags: u8,
+    #[serde(default)]
+    pub leg2_feed_id: Option<String>,
+    #[serde(default)]
+    pub leg3_feed_id: Option<String>,
+    #[serde(default)]
+    pub leg2_oracle: Option<String>,
+    #[serde(default)]
+    pub leg3_oracle: Option<String>,
+}
+
+/// Explorer labels stored in the slab. Only the URI's sha256 goes on-chain.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetadataSection {
+    pub symbol: String,
+    pub base_decimals: u8,
+    #[serde(default)]
+    pub oracle_description: String,
+    #[serde(default)]
+    pub uri: Option<String>,
+}
+
+impl MetadataSection {
+    pub fn to_metadata(&self) -> Result<MarketMetadata, String> {
+        let m = MarketMetadata {
+            symbol: label("metadata.symbol", &self.symbol)?,
+            oracle_description: label("metadata.oracle_description", &self.oracle_description)?,
+            uri_hash: match &self.uri {
+                Some(uri) => solana_sdk::hash::hash(uri.as_bytes()).to_bytes(),
+                None => [0u8; 32],
+            },
+            base_decimals: self.base_decimals,
+        };
+        if !percolator_prog::policy::market_metadata_ok(&m) {
+            return Err(format!(
+                "metadata: labels must be printable ASCII and base_decimals at most {}",
+                percolator_prog::constants::MAX_BASE_DECIMALS
+            ));
+        }
+        Ok(m)
+    }
+}
+
+fn label<const N: usize>(field: &str, s: &str) -> Result<[u8; N], String> {
+    if s.len() > N {
+        return Err(format!("{field}: at most {N} bytes, got {}", s.len()));
+    }
+    let mut out = [0u8; N];
+    out[..s.len()].copy_from_slice(s.as_bytes());
+    Ok(out)
+}
+
+/// Matcher context account to allocate alongside the market.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatcherSection {
+    pub program: String,
+    #[serde(default = "default_matcher_context_len")]
+    pub context_len: u64,
+}
+
+fn default_matcher_context_len() -> u64 {
+    percolator_prog::constants::MATCHER_CONTEXT_LEN as u64
+}
+
+pub fn parse_pubkey(field: &str, s: &str) -> Result<Pubkey, String> {
+    Pubkey::from_str(s).map_err(|e| format!("{field}: invalid pubkey {s:?}: {e}"))
+}
+
+pub fn parse_feed_id(field: &str, s: &str) -> Result<[u8; 32], String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.len() != 64 {
+        return Err(format!("{field}: expected 64 hex chars, got {}", s.len()));
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte =
+            u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|e| format!("{field}: {e}"))?;
+    }
+    Ok(out)
+}
+
+impl BootstrapConfig {
+    pub fn from_toml(s: &str) -> Result<Self, String> {
+        toml::from_str(s).map_err(|e| e.to_string())
+    }
+
+    pub fn is_hyperp(&self) -> bool {
+        self.market.index_feed_id.is_none()
+    }
+
+    pub fn collateral_mint(&self) -> Result<Pubkey, String> {
+        parse_pubkey("market.collateral_mint", &self.market.collateral_mint)
+    }
+
+    /// Oracle accounts for InitMarket, one per active leg. Hyperp markets
+    /// get a single placeholder (the clock sysvar) since the slot is unread.
+    pub fn oracle_accounts(&self) -> Result<Vec<Pubkey>, String> {
+        if self.is_hyperp() {
+            return Ok(vec![solana_sdk::sysvar::clock::ID]);
+        }
+        let index = self
+            .market
+            .index_oracle
+            .as_deref()
+            .ok_or("market.index_oracle is required for external-oracle markets")?;
+        let mut out = vec![parse_pubkey("market.index_oracle", index)?];
+        if let Some(l) = &self.oracle_legs {
+            let extra = [
+                ("oracle_legs.leg2_oracle", &l.leg2_oracle),
+                ("oracle_legs.leg3_oracle", &l.leg3_oracle),
+            ];
+            for (field, key) in extra.iter().take(l.count.saturating_sub(1) as usize) {
+                let key = key.as_deref().ok_or(format!("{field} is required"))?;
+                out.push(parse_pubkey(field, key)?);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Build InitMarket params for `admin`.
+    pub fn init_market_params(&self, admin: Pubkey) -> Result<InitMarketParams, String> {
+        let m = &self.market;
+        let index_feed_id = match &m.index_feed_id {
+            Some(hex) => parse_feed_id("market.index_feed_id", hex)?,
+            None => [0u8; 32],
+        };
+        let r = &self.risk;
+        let e = &self.extended;
+        let oracle_legs = match &self.oracle_legs {
+            Some(l) => Some(InitMarketOracleLegs {
+                count: l.count,
+                flags: l.flags,
+                leg2_feed_id: match &l.leg2_feed_id {
+                    Some(hex) => parse_feed_id("oracle_legs.leg2_feed_id", hex)?,
+                    None => [0u8; 32],
+                },
+                leg3_feed_id: match &l.leg3_feed_id {
+                    Some(hex) => parse_feed_id("oracle_legs.leg3_feed_id", hex)?,
+                    None => [0u8; 32],
+                },
+            }),
+            None => None,
+        };
+        let metadata = match &self.metadata {
+            Some(m) => Some(m.to_metadata()?),
+            None => None,
+        };
+        Ok(InitMarketParams {
+            admin,
+            collateral_mint: self.collateral_mint()?,
+            index_feed_id,
+            max_staleness_secs: m.max_staleness_secs,
+            conf_filter_bps: m.conf_filter_bps,
+            invert: m.invert,
+            unit_scale: m.unit_scale,
+            initial_mark_price_e6: m.initial_mark_price_e6,
+            maintenance_fee_per_slot: m.maintenance_fee_per_slot,
+            risk: InitMarketRisk {
+                h_min: r.h_min,
+                maintenance_margin_bps: r.maintenance_margin_bps,
+                initial_margin_bps: r.initial_margin_bps,
+                max_trading_fee_bps: r.max_trading_fee_bps,
+                max_accounts: r.max_accounts,
+                new_account_fee: r.new_account_fee,
+                h_max: r.h_max,
+                max_crank_staleness_slots: u64::MAX,
+                liquidation_fee_bps: r.liquidation_fee_bps,
+                liquidation_fee_cap: r.liquidation_fee_cap,
+                resolve_price_deviation_bps: r.resolve_price_deviation_bps,
+                min_liquidation_abs: r.min_liquidation_abs,
+                min_nonzero_mm_req: r.min_nonzero_mm_req,
+                min_nonzero_im_req: r.min_nonzero_im_req,
+                max_price_move_bps_per_slot: r.max_price_move_bps_per_slot,
+            },
+            extended: InitMarketExtended {
+                insurance_withdraw_max_bps: e.insurance_withdraw_max_bps,
+                insurance_withdraw_cooldown_slots: e.insurance_withdraw_cooldown_slots,
+                permissionless_resolve_stale_slots: e.permissionless_resolve_stale_slots,
+                funding_horizon_slots: e.funding_horizon_slots,
+                funding_k_bps: e.funding_k_bps,
+                funding_max_premium_bps: e.funding_max_premium_bps,
+                funding_max_e9_per_slot: e.funding_max_e9_per_slot,
+                mark_min_fee: e.mark_min_fee,
+                force_close_delay_slots: e.force_close_delay_slots,
+            },
+            oracle_legs,
+            trade_fee_base_bps: m.trade_fee_base_bps,
+            metadata,
+        })
+    }
+}
+
+/// `percolator-keeper` config (TOML).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeeperConfig {
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Crank at least this often even when nothing is liquidatable.
+    #[serde(default = "default_crank_interval_slots")]
+    pub crank_interval_slots: u64,
+    /// Crank early once the market lags within this many slots of
+    /// `MAX_ACCRUAL_DT_SLOTS`, so accrual never needs partial catchup.
+    #[serde(default = "default_staleness_margin_slots")]
+    pub staleness_margin_slots: u64,
+    /// Submit candidates whose estimated health is below this fraction of
+    /// their maintenance requirement (bps). 0 = only estimated-underwater.
+    #[serde(default)]
+    pub liquidation_buffer_bps: u64,
+    /// `host:port` for the Prometheus `/metrics` endpoint. Omit to disable.
+    #[serde(default)]
+    pub metrics_addr: Option<String>,
+    /// Priority fee on every keeper transaction, micro-lamports per CU.
+    #[serde(default)]
+    pub priority_fee_micro_lamports: u64,
+    /// Send liquidation cranks as Jito bundles. Omit to use plain RPC.
+    #[serde(default)]
+    pub jito: Option<JitoConfig>,
+    /// Retry, backoff and preflight for RPC submissions.
+    #[serde(default)]
+    pub send: SendPolicy,
+    #[serde(rename = "slab")]
+    pub slabs: Vec<KeeperSlab>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeeperSlab {
+    pub address: String,
+    /// Oracle accounts passed to KeeperCrank, one per active leg. Hyperp
+    /// markets may leave this empty.
+    #[serde(default)]
+    pub oracles: Vec<String>,
+    /// Hyperp only: shell command printing the next mark (`price_e6`).
+    /// Pushed only when the keeper key is the market's mark authority.
+    #[serde(default)]
+    pub mark_price_cmd: Option<String>,
+}
+
+/// Jito block-engine submission for candidate-bearing cranks.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JitoConfig {
+    /// Block-engine bundles endpoint, e.g.
+    /// `https://mainnet.block-engine.jito.wtf/api/v1/bundles`.
+    pub url: String,
+    /// One of the block engine's tip accounts (`getTipAccounts`).
+    pub tip_account: String,
+    /// Transferred to `tip_account` inside the crank transaction, so the
+    /// tip is only paid when the liquidation lands.
+    pub tip_lamports: u64,
+    /// Resend through plain RPC when the bundle is rejected or does not
+    /// land. Off by default: a public resend gives up the frontrunning
+    /// protection the bundle was for.
+    #[serde(default)]
+    pub fallback_to_rpc: bool,
+}
+
+/// Block engines ignore bundles tipping less than this.
+pub const JITO_MIN_TIP_LAMPORTS: u64 = 1_000;
+
+impl JitoConfig {
+    pub fn tip_account(&self) -> Result<Pubkey, String> {
+        parse_pubkey("jito.tip_account", &self.tip_account)
+    }
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_crank_interval_slots() -> u64 {
+    percolator_prog::constants::MAX_ACCRUAL_DT_SLOTS
+}
+
+fn default_staleness_margin_slots() -> u64 {
+    2
+}
+
+impl KeeperConfig {
+    pub fn from_toml(s: &str) -> Result<Self, String> {
+        let cfg: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+        if let Some(jito) = &cfg.jito {
+            jito.tip_account()?;
+            if jito.tip_lamports < JITO_MIN_TIP_LAMPORTS {
+                return Err(format!(
+                    "jito.tip_lamports must be at least {JITO_MIN_TIP_LAMPORTS}"
+                ));
+            }
+        }
+        Ok(cfg)
+    }
+
+    /// Slot lag at which a crank is due regardless of candidates.
+    pub fn crank_due_lag(&self) -> u64 {
+        let envelope = percolator_prog::constants::MAX_ACCRUAL_DT_SLOTS
+            .saturating_sub(self.staleness_margin_slots)
+            .max(1);
+        self.crank_interval_slots.clamp(1, envelope)
+    }
+}
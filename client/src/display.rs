@@ -0,0 +1,100 @@
+//! Engine-space ↔ displayed prices and sides for inverted markets.
+//!
+//! With `invert = 1` the engine trades the reciprocal of the feed: a
+//! SOL/USD feed at $138 becomes an engine price of `10^12 / 138e6`, and
+//! an engine long gains when that reciprocal rises, i.e. when SOL falls.
+//! Integrators quoting in feed terms must therefore flip both the price
+//! and the side. `unit_scale` divides engine prices as well and is undone
+//! here (`units::unscale_price_e6`). PnL, capital and fees are collateral
+//! amounts and are never flipped.
+//!
+//! ```ignore
+//! let o = Orientation::of(&slab.config());
+//! let px = o.display_price_e6(engine.last_oracle_price);
+//! let side = o.display_side(h.effective_pos_q);
+//! let size = o.engine_size_q(user_wants_q); // for TradeBuilder
+//! ```
+//!
+//! Invariant: for any two engine prices, `display_side(q)` and the move
+//! in displayed price agree in sign with the engine's PnL on `q`. See
+//! `tests/display.rs`.
+
+use percolator_prog::{
+    policy::{self, INVERSION_CONSTANT},
+    state::MarketConfig,
+    units,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Long,
+    Short,
+    Flat,
+}
+
+impl Side {
+    pub fn of(q: i128) -> Self {
+        match q.signum() {
+            1 => Side::Long,
+            -1 => Side::Short,
+            _ => Side::Flat,
+        }
+    }
+}
+
+/// How one market maps engine values to what users see.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub invert: bool,
+    pub unit_scale: u32,
+}
+
+impl Orientation {
+    pub fn of(config: &MarketConfig) -> Self {
+        Self {
+            invert: config.invert != 0,
+            unit_scale: config.unit_scale,
+        }
+    }
+
+    /// Feed-terms price for an engine price. Undoes `unit_scale`, then
+    /// the inversion; `None` for zero or out-of-range prices. Both steps
+    /// floor, so a round trip can be off by the engine's price tick.
+    pub fn display_price_e6(&self, engine_price_e6: u64) -> Option<u64> {
+        let unscaled = units::unscale_price_e6(engine_price_e6, self.unit_scale)?;
+        if !self.invert {
+            return (unscaled != 0).then_some(unscaled);
+        }
+        if unscaled == 0 {
+            return None;
+        }
+        let p = INVERSION_CONSTANT / unscaled as u128;
+        (p != 0 && p <= u64::MAX as u128).then_some(p as u64)
+    }
+
+    /// Engine price for a feed-terms price: the program's own
+    /// normalization (`policy::to_engine_price`).
+    pub fn engine_price_e6(&self, display_price_e6: u64) -> Option<u64> {
+        policy::to_engine_price(display_price_e6, self.invert as u8, self.unit_scale)
+    }
+
+    /// Signed size in feed terms: an engine long is a displayed short on
+    /// an inverted market.
+    pub fn display_size_q(&self, engine_q: i128) -> i128 {
+        if self.invert {
+            engine_q.saturating_neg()
+        } else {
+            engine_q
+        }
+    }
+
+    /// Engine size for a feed-terms size, e.g. the `size` of a trade the
+    /// user asked for as "buy".
+    pub fn engine_size_q(&self, display_q: i128) -> i128 {
+        self.display_size_q(display_q)
+    }
+
+    pub fn display_side(&self, engine_q: i128) -> Side {
+        Side::of(self.display_size_q(engine_q))
+    }
+}
@@ -0,0 +1,259 @@
+//! Normalized fill / funding / liquidation feed.
+//!
+//! Transport-agnostic core of the Geyser ingestion adapter
+//! (`percolator-geyser`). It consumes two streams for one market:
+//!
+//! - transaction updates: successful TradeNoCpi / TradeCpi instructions
+//!   become `Fill`s, read from the `TradeResult` return data;
+//! - slab account updates: new market-history samples become `Funding`
+//!   records, and positions that shrink inside a KeeperCrank transaction
+//!   become `Liquidation`s (dust sweeps included).
+//!
+//! Account and transaction updates for the same signature arrive in
+//! either order. A position change waits in `pending` until its
+//! transaction is classified, and classifications are remembered for
+//! `PAIRING_WINDOW_SLOTS`. The first slab update backfills the history
+//! ring as `Funding` records.
+
+use crate::{
+    ix,
+    watch::{SlabEvent, SlabWatcher},
+};
+use percolator_prog::{constants::CLIENT_TAG_LEN, ix::TradeResult};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+
+/// How long an unpaired account or transaction update is kept.
+pub const PAIRING_WINDOW_SLOTS: u64 = 300;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FeedRecord {
+    Fill {
+        slot: u64,
+        signature: String,
+        user: String,
+        lp_idx: u16,
+        user_idx: u16,
+        /// User side; positive = bought.
+        size_q: i128,
+        exec_price_e6: u64,
+        oracle_price_e6: u64,
+        fee_bps: u64,
+        fee_paid: u128,
+        /// Hex of the instruction's nonzero client tag.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        client_tag: Option<String>,
+    },
+    Funding {
+        slot: u64,
+        sample_slot: u64,
+        funding_rate_e9: i64,
+        price_e6: u64,
+        mark_e6: u64,
+        oi_long_q: u128,
+        oi_short_q: u128,
+    },
+    Liquidation {
+        slot: u64,
+        signature: String,
+        idx: u16,
+        before_q: i128,
+        after_q: i128,
+        price_e6: u64,
+    },
+}
+
+/// Byte range of the client tag in TradeNoCpi / TradeCpi data: tag, two
+/// indices, size, price, flags and the client order id come first.
+const TRADE_CLIENT_TAG: core::ops::Range<usize> = 38..38 + CLIENT_TAG_LEN;
+
+fn client_tag_hex(data: &[u8]) -> Option<String> {
+    let tag = data.get(TRADE_CLIENT_TAG)?;
+    if tag.iter().all(|&b| b == 0) {
+        return None;
+    }
+    Some(tag.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// One top-level instruction, keys already resolved (lookup tables
+/// included).
+pub struct TxInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+}
+
+pub struct TxUpdate {
+    pub slot: u64,
+    pub signature: Signature,
+    pub success: bool,
+    pub instructions: Vec<TxInstruction>,
+    /// `(program_id, data)` from the transaction meta.
+    pub return_data: Option<(Pubkey, Vec<u8>)>,
+}
+
+pub struct AccountUpdate<'a> {
+    pub slot: u64,
+    pub data: &'a [u8],
+    /// Transaction that produced this write, when the source reports it.
+    pub txn_signature: Option<Signature>,
+}
+
+struct Pending {
+    slot: u64,
+    price_e6: u64,
+    shrinks: Vec<(u16, i128, i128)>,
+}
+
+pub struct FeedDecoder {
+    program_id: Pubkey,
+    watcher: SlabWatcher,
+    last_sample_slot: u64,
+    /// signature -> (slot, was a successful KeeperCrank)
+    classified: HashMap<Signature, (u64, bool)>,
+    pending: HashMap<Signature, Pending>,
+}
+
+impl FeedDecoder {
+    pub fn new(program_id: Pubkey) -> Self {
+        Self {
+            program_id,
+            watcher: SlabWatcher::new(),
+            last_sample_slot: 0,
+            classified: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    fn prune(&mut self, slot: u64) {
+        let keep = |s: u64| s.saturating_add(PAIRING_WINDOW_SLOTS) >= slot;
+        self.classified.retain(|_, (s, _)| keep(*s));
+        self.pending.retain(|_, p| keep(p.slot));
+    }
+
+    fn liquidations(sig: &Signature, p: Pending) -> Vec<FeedRecord> {
+        p.shrinks
+            .into_iter()
+            .map(|(idx, before_q, after_q)| FeedRecord::Liquidation {
+                slot: p.slot,
+                signature: sig.to_string(),
+                idx,
+                before_q,
+                after_q,
+                price_e6: p.price_e6,
+            })
+            .collect()
+    }
+
+    pub fn on_transaction(&mut self, tx: &TxUpdate) -> Vec<FeedRecord> {
+        self.prune(tx.slot);
+        let ours: Vec<&TxInstruction> = tx
+            .instructions
+            .iter()
+            .filter(|i| i.program_id == self.program_id)
+            .collect();
+        if ours.is_empty() {
+            return Vec::new();
+        }
+        let is_crank = tx.success
+            && ours
+                .iter()
+                .any(|i| i.data.first() == Some(&ix::TAG_KEEPER_CRANK));
+        self.classified.insert(tx.signature, (tx.slot, is_crank));
+        let mut out = match self.pending.remove(&tx.signature) {
+            Some(p) if is_crank => Self::liquidations(&tx.signature, p),
+            _ => Vec::new(),
+        };
+
+        // Return data holds the last trade's result only.
+        let trade = ours.iter().rev().find(|i| {
+            matches!(
+                i.data.first(),
+                Some(&ix::TAG_TRADE_NOCPI) | Some(&ix::TAG_TRADE_CPI)
+            )
+        });
+        let result = match (&tx.return_data, tx.success) {
+            (Some((pid, data)), true) if *pid == self.program_id => TradeResult::decode(data),
+            _ => None,
+        };
+        if let (Some(trade), Some(r)) = (trade, result) {
+            if r.exec_size != 0 && trade.data.len() >= 5 {
+                out.push(FeedRecord::Fill {
+                    slot: tx.slot,
+                    signature: tx.signature.to_string(),
+                    user: trade
+                        .accounts
+                        .first()
+                        .map(|k| k.to_string())
+                        .unwrap_or_default(),
+                    lp_idx: u16::from_le_bytes([trade.data[1], trade.data[2]]),
+                    user_idx: u16::from_le_bytes([trade.data[3], trade.data[4]]),
+                    size_q: r.exec_size,
+                    exec_price_e6: r.exec_price_e6,
+                    oracle_price_e6: r.oracle_price_e6,
+                    fee_bps: r.fee_bps,
+                    fee_paid: r.fee_paid,
+                    client_tag: client_tag_hex(&trade.data),
+                });
+            }
+        }
+        out
+    }
+
+    pub fn on_account(&mut self, update: &AccountUpdate) -> Result<Vec<FeedRecord>, String> {
+        self.prune(update.slot);
+        let events = self.watcher.update(update.data)?;
+        let slab = self.watcher.snapshot().expect("update stores a snapshot");
+        let mut out: Vec<FeedRecord> = slab
+            .history()?
+            .into_iter()
+            .filter(|s| s.slot > self.last_sample_slot)
+            .map(|s| FeedRecord::Funding {
+                slot: update.slot,
+                sample_slot: s.slot,
+                funding_rate_e9: s.funding_rate_e9,
+                price_e6: s.price_e6,
+                mark_e6: s.mark_e6,
+                oi_long_q: s.oi_long_q,
+                oi_short_q: s.oi_short_q,
+            })
+            .collect();
+        if let Some(FeedRecord::Funding { sample_slot, .. }) = out.last() {
+            self.last_sample_slot = *sample_slot;
+        }
+
+        let shrinks: Vec<(u16, i128, i128)> = events
+            .iter()
+            .filter_map(|e| match *e {
+                SlabEvent::PositionChanged {
+                    idx,
+                    before_q,
+                    after_q,
+                } if after_q.unsigned_abs() < before_q.unsigned_abs()
+                    && (after_q == 0 || after_q.signum() == before_q.signum()) =>
+                {
+                    Some((idx, before_q, after_q))
+                }
+                _ => None,
+            })
+            .collect();
+        let Some(sig) = update.txn_signature.filter(|_| !shrinks.is_empty()) else {
+            return Ok(out);
+        };
+        let pending = Pending {
+            slot: update.slot,
+            price_e6: slab.engine()?.last_oracle_price,
+            shrinks,
+        };
+        match self.classified.get(&sig) {
+            Some((_, true)) => out.extend(Self::liquidations(&sig, pending)),
+            Some((_, false)) => {}
+            None => {
+                self.pending.insert(sig, pending);
+            }
+        }
+        Ok(out)
+    }
+}
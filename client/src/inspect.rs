@@ -0,0 +1,208 @@
+//! Human-readable slab dump for `percolator inspect`.
+//!
+//! Pure rendering over a [`Slab`] so the same report can be produced from
+//! RPC data or a saved account dump.
+
+use crate::{display::Orientation, slab::Slab};
+use percolator::{Account, MarketMode};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt::Write;
+
+fn key(bytes: &[u8; 32]) -> String {
+    if *bytes == [0u8; 32] {
+        "-".to_string()
+    } else {
+        Pubkey::new_from_array(*bytes).to_string()
+    }
+}
+
+fn hex(bytes: &[u8; 32]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Render header, config, risk params, engine aggregates and the account
+/// table. Health columns use [`Slab::health_estimate`] and share its lag.
+pub fn render(slab: &Slab) -> Result<String, String> {
+    let mut out = String::new();
+    let header = slab.header();
+    let config = slab.config();
+    let engine = slab.engine()?;
+    let w = &mut out;
+
+    let _ = writeln!(w, "== header ==");
+    let _ = writeln!(w, "magic                    {:#018x}", header.magic);
+    use percolator_prog::constants::{MAGIC, SLAB_VERSION};
+    let stale = if header.magic == MAGIC && header.version < SLAB_VERSION {
+        " (older layout, needs MigrateSlab)"
+    } else {
+        ""
+    };
+    let _ = writeln!(w, "version                  {}{stale}", header.version);
+    let _ = writeln!(w, "admin                    {}", key(&header.admin));
+    let _ = writeln!(
+        w,
+        "insurance_authority      {}",
+        key(&header.insurance_authority)
+    );
+    let _ = writeln!(
+        w,
+        "insurance_operator       {}",
+        key(&header.insurance_operator)
+    );
+
+    if let Ok(meta) = slab.metadata() {
+        if meta != Default::default() {
+            let _ = writeln!(w, "\n== metadata ==");
+            let _ = writeln!(w, "symbol                   {}", meta.symbol_str());
+            let _ = writeln!(w, "base_decimals            {}", meta.base_decimals);
+            let _ = writeln!(
+                w,
+                "oracle_description       {}",
+                meta.oracle_description_str()
+            );
+            let _ = writeln!(w, "uri_hash                 {}", hex(&meta.uri_hash));
+        }
+    }
+
+    let _ = writeln!(w, "\n== config ==");
+    let hyperp = config.index_feed_id == [0u8; 32];
+    let _ = writeln!(
+        w,
+        "collateral_mint          {}",
+        key(&config.collateral_mint)
+    );
+    let _ = writeln!(w, "vault                    {}", key(&config.vault_pubkey));
+    if hyperp {
+        let _ = writeln!(w, "oracle                   hyperp");
+        let _ = writeln!(
+            w,
+            "hyperp_authority         {}",
+            key(&config.hyperp_authority)
+        );
+        let _ = writeln!(w, "hyperp_mark_e6           {}", config.hyperp_mark_e6);
+    } else {
+        let _ = writeln!(w, "index_feed_id            {}", hex(&config.index_feed_id));
+        let _ = writeln!(w, "oracle_leg_count         {}", config.oracle_leg_count);
+    }
+    let _ = writeln!(w, "max_staleness_secs       {}", config.max_staleness_secs);
+    let _ = writeln!(w, "conf_filter_bps          {}", config.conf_filter_bps);
+    let _ = writeln!(w, "invert                   {}", config.invert);
+    let _ = writeln!(w, "unit_scale               {}", config.unit_scale);
+    let _ = writeln!(
+        w,
+        "last_effective_price_e6  {}",
+        config.last_effective_price_e6
+    );
+    let _ = writeln!(w, "mark_ewma_e6             {}", config.mark_ewma_e6);
+    let _ = writeln!(
+        w,
+        "funding_horizon_slots    {}",
+        config.funding_horizon_slots
+    );
+    let _ = writeln!(w, "funding_k_bps            {}", config.funding_k_bps);
+    let _ = writeln!(
+        w,
+        "maintenance_fee_per_slot {}",
+        config.maintenance_fee_per_slot
+    );
+    let _ = writeln!(w, "trade_fee_base_bps       {}", config.trade_fee_base_bps);
+    let _ = writeln!(w, "new_account_fee          {}", config.new_account_fee);
+    let _ = writeln!(
+        w,
+        "permissionless_resolve   {} slots",
+        config.permissionless_resolve_stale_slots
+    );
+    let _ = writeln!(
+        w,
+        "force_close_delay_slots  {}",
+        config.force_close_delay_slots
+    );
+
+    let p = &engine.params;
+    let _ = writeln!(w, "\n== risk params ==");
+    let _ = writeln!(w, "maintenance_margin_bps   {}", p.maintenance_margin_bps);
+    let _ = writeln!(w, "initial_margin_bps       {}", p.initial_margin_bps);
+    let _ = writeln!(w, "max_trading_fee_bps      {}", p.max_trading_fee_bps);
+    let _ = writeln!(w, "max_accounts             {}", p.max_accounts);
+    let _ = writeln!(w, "liquidation_fee_bps      {}", p.liquidation_fee_bps);
+    let _ = writeln!(w, "liquidation_fee_cap      {}", p.liquidation_fee_cap);
+    let _ = writeln!(w, "min_liquidation_abs      {}", p.min_liquidation_abs);
+    let _ = writeln!(w, "min_nonzero_mm_req       {}", p.min_nonzero_mm_req);
+    let _ = writeln!(w, "min_nonzero_im_req       {}", p.min_nonzero_im_req);
+    let _ = writeln!(w, "h_min / h_max            {} / {}", p.h_min, p.h_max);
+    let _ = writeln!(
+        w,
+        "max_price_move_bps/slot  {}",
+        p.max_price_move_bps_per_slot
+    );
+
+    let _ = writeln!(w, "\n== engine ==");
+    let mode = if engine.market_mode == MarketMode::Resolved {
+        "resolved"
+    } else {
+        "live"
+    };
+    let _ = writeln!(w, "market_mode              {mode}");
+    let _ = writeln!(w, "last_market_slot         {}", engine.last_market_slot);
+    let _ = writeln!(w, "last_oracle_price        {}", engine.last_oracle_price);
+    if config.invert != 0 {
+        // Engine prices and positions are in the reciprocal of the feed.
+        let px = Orientation::of(&config)
+            .display_price_e6(engine.last_oracle_price)
+            .map_or("-".to_string(), |p| p.to_string());
+        let _ = writeln!(
+            w,
+            "display_price_e6         {px} (feed terms; engine longs are shorts)"
+        );
+    }
+    let _ = writeln!(w, "vault                    {}", engine.vault.get());
+    let _ = writeln!(w, "c_tot                    {}", engine.c_tot.get());
+    let _ = writeln!(
+        w,
+        "insurance                {}",
+        engine.insurance_fund.balance.get()
+    );
+    let _ = writeln!(w, "oi_eff_long_q            {}", engine.oi_eff_long_q);
+    let _ = writeln!(w, "oi_eff_short_q           {}", engine.oi_eff_short_q);
+    let _ = writeln!(w, "num_used_accounts        {}", engine.num_used_accounts);
+
+    let _ = writeln!(w, "\n== accounts ==");
+    let _ = writeln!(
+        w,
+        "{:>5} {:>4} {:<44} {:>20} {:>20} {:>24} {:>20} {:>8}",
+        "idx", "kind", "owner", "capital", "pnl", "position_q", "health", "liq"
+    );
+    for idx in slab.used_indices()? {
+        let acc = &engine.accounts[idx as usize];
+        let kind = if acc.kind == Account::KIND_LP {
+            "lp"
+        } else {
+            "user"
+        };
+        let (pos, health, liq) = match slab.health_estimate(idx) {
+            Some(h) => (
+                h.effective_pos_q.to_string(),
+                h.health().to_string(),
+                if h.effective_pos_q != 0 && h.health() < 0 {
+                    "yes"
+                } else {
+                    ""
+                },
+            ),
+            None => ("?".into(), "?".into(), ""),
+        };
+        let _ = writeln!(
+            w,
+            "{:>5} {:>4} {:<44} {:>20} {:>20} {:>24} {:>20} {:>8}",
+            idx,
+            kind,
+            key(&acc.owner),
+            acc.capital.get(),
+            acc.pnl,
+            pos,
+            health,
+            liq
+        );
+    }
+    Ok(out)
+}
@@ -0,0 +1,766 @@
+//! Instruction builders.
+//!
+//! Each encoder mirrors the matching arm of
+//! `percolator_prog::ix::Instruction::decode`, and each builder returns the
+//! account list in the order the processor indexes it. `tests/encode.rs`
+//! round-trips every encoder through the on-chain decoder.
+
+use percolator_prog::{
+    constants::{CLIENT_TAG_LEN, PERMIT_DEPOSIT_PAYLOAD_LEN},
+    state::MarketMetadata,
+};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Signature,
+    sysvar,
+};
+
+pub const TAG_INIT_MARKET: u8 = 0;
+pub const TAG_DEPOSIT_COLLATERAL: u8 = 3;
+pub const TAG_WITHDRAW_COLLATERAL: u8 = 4;
+pub const TAG_KEEPER_CRANK: u8 = 5;
+pub const TAG_TRADE_NOCPI: u8 = 6;
+pub const TAG_TRADE_CPI: u8 = 10;
+pub const TAG_PUSH_HYPERP_MARK: u8 = 17;
+pub const TAG_SET_MARKET_METADATA: u8 = 52;
+pub const TAG_REGISTER_MARKET: u8 = 53;
+pub const TAG_INIT_SLAB: u8 = 54;
+pub const TAG_MIGRATE_SLAB: u8 = 55;
+pub const TAG_ADD_HYPERP_PUSHER: u8 = 59;
+pub const TAG_REMOVE_HYPERP_PUSHER: u8 = 60;
+pub const TAG_DEPOSIT_WITH_PERMIT: u8 = 61;
+
+/// `caller_idx` sentinel for a permissionless crank.
+pub const CRANK_PERMISSIONLESS: u16 = u16::MAX;
+/// KeeperCrank candidate policy: liquidate with FullClose.
+pub const CANDIDATE_FULL_CLOSE: u8 = 0;
+/// KeeperCrank candidate policy: touch only (settle, no liquidation).
+pub const CANDIDATE_TOUCH_ONLY: u8 = 0xFF;
+
+/// Risk parameters in InitMarket wire order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InitMarketRisk {
+    pub h_min: u64,
+    pub maintenance_margin_bps: u64,
+    pub initial_margin_bps: u64,
+    pub max_trading_fee_bps: u64,
+    pub max_accounts: u64,
+    pub new_account_fee: u128,
+    pub h_max: u64,
+    /// Legacy wire slot, ignored by the program.
+    pub max_crank_staleness_slots: u64,
+    pub liquidation_fee_bps: u64,
+    pub liquidation_fee_cap: u128,
+    pub resolve_price_deviation_bps: u64,
+    pub min_liquidation_abs: u128,
+    pub min_nonzero_mm_req: u128,
+    pub min_nonzero_im_req: u128,
+    pub max_price_move_bps_per_slot: u64,
+}
+
+/// The 66-byte extended InitMarket tail. Always encoded; the program's
+/// minimal-payload defaults are not reachable through this builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InitMarketExtended {
+    pub insurance_withdraw_max_bps: u16,
+    pub insurance_withdraw_cooldown_slots: u64,
+    pub permissionless_resolve_stale_slots: u64,
+    pub funding_horizon_slots: u64,
+    pub funding_k_bps: u64,
+    pub funding_max_premium_bps: i64,
+    pub funding_max_e9_per_slot: i64,
+    pub mark_min_fee: u64,
+    pub force_close_delay_slots: u64,
+}
+
+/// Optional oracle-leg tail (synthetic cross rates).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InitMarketOracleLegs {
+    pub count: u8,
+    pub flags: u8,
+    pub leg2_feed_id: [u8; 32],
+    pub leg3_feed_id: [u8; 32],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitMarketParams {
+    pub admin: Pubkey,
+    pub collateral_mint: Pubkey,
+    /// All zeros selects Hyperp mode.
+    pub index_feed_id: [u8; 32],
+    pub max_staleness_secs: u64,
+    pub conf_filter_bps: u16,
+    pub invert: u8,
+    pub unit_scale: u32,
+    pub initial_mark_price_e6: u64,
+    pub maintenance_fee_per_slot: u128,
+    pub risk: InitMarketRisk,
+    pub extended: InitMarketExtended,
+    pub oracle_legs: Option<InitMarketOracleLegs>,
+    pub trade_fee_base_bps: Option<u64>,
+    /// Explorer labels; written as the last InitMarket tail.
+    pub metadata: Option<MarketMetadata>,
+}
+
+pub fn encode_init_market(p: &InitMarketParams) -> Vec<u8> {
+    let mut data = vec![TAG_INIT_MARKET];
+    data.extend_from_slice(p.admin.as_ref());
+    data.extend_from_slice(p.collateral_mint.as_ref());
+    data.extend_from_slice(&p.index_feed_id);
+    data.extend_from_slice(&p.max_staleness_secs.to_le_bytes());
+    data.extend_from_slice(&p.conf_filter_bps.to_le_bytes());
+    data.push(p.invert);
+    data.extend_from_slice(&p.unit_scale.to_le_bytes());
+    data.extend_from_slice(&p.initial_mark_price_e6.to_le_bytes());
+    data.extend_from_slice(&p.maintenance_fee_per_slot.to_le_bytes());
+
+    let r = &p.risk;
+    data.extend_from_slice(&r.h_min.to_le_bytes());
+    data.extend_from_slice(&r.maintenance_margin_bps.to_le_bytes());
+    data.extend_from_slice(&r.initial_margin_bps.to_le_bytes());
+    data.extend_from_slice(&r.max_trading_fee_bps.to_le_bytes());
+    data.extend_from_slice(&r.max_accounts.to_le_bytes());
+    data.extend_from_slice(&r.new_account_fee.to_le_bytes());
+    data.extend_from_slice(&r.h_max.to_le_bytes());
+    data.extend_from_slice(&r.max_crank_staleness_slots.to_le_bytes());
+    data.extend_from_slice(&r.liquidation_fee_bps.to_le_bytes());
+    data.extend_from_slice(&r.liquidation_fee_cap.to_le_bytes());
+    data.extend_from_slice(&r.resolve_price_deviation_bps.to_le_bytes());
+    data.extend_from_slice(&r.min_liquidation_abs.to_le_bytes());
+    data.extend_from_slice(&r.min_nonzero_mm_req.to_le_bytes());
+    data.extend_from_slice(&r.min_nonzero_im_req.to_le_bytes());
+    data.extend_from_slice(&r.max_price_move_bps_per_slot.to_le_bytes());
+
+    let e = &p.extended;
+    data.extend_from_slice(&e.insurance_withdraw_max_bps.to_le_bytes());
+    data.extend_from_slice(&e.insurance_withdraw_cooldown_slots.to_le_bytes());
+    data.extend_from_slice(&e.permissionless_resolve_stale_slots.to_le_bytes());
+    data.extend_from_slice(&e.funding_horizon_slots.to_le_bytes());
+    data.extend_from_slice(&e.funding_k_bps.to_le_bytes());
+    data.extend_from_slice(&e.funding_max_premium_bps.to_le_bytes());
+    data.extend_from_slice(&e.funding_max_e9_per_slot.to_le_bytes());
+    data.extend_from_slice(&e.mark_min_fee.to_le_bytes());
+    data.extend_from_slice(&e.force_close_delay_slots.to_le_bytes());
+
+    if let Some(legs) = &p.oracle_legs {
+        data.push(legs.count);
+        data.push(legs.flags);
+        data.extend_from_slice(&legs.leg2_feed_id);
+        data.extend_from_slice(&legs.leg3_feed_id);
+    }
+    if let Some(fee) = p.trade_fee_base_bps {
+        data.extend_from_slice(&fee.to_le_bytes());
+    }
+    if let Some(m) = &p.metadata {
+        data.extend_from_slice(&m.to_wire());
+    }
+    data
+}
+
+/// InitMarket: admin (signer), slab, mint, vault, clock, then one oracle
+/// account per active leg (Hyperp markets still pass exactly one oracle
+/// slot; its contents are not read), then the accounts InitMarket needs
+/// to create the vault. The vault is always the vault PDA's ATA
+/// ([`vault_address`]); the admin pays its rent unless
+/// [`with_rent_payer`] names someone else.
+pub fn init_market(
+    program_id: &Pubkey,
+    slab: &Pubkey,
+    oracles: &[Pubkey],
+    params: &InitMarketParams,
+) -> Instruction {
+    let (vault_authority, _) = percolator_prog::accounts::derive_vault_authority(program_id, slab);
+    let mut accounts = vec![
+        AccountMeta::new(params.admin, true),
+        AccountMeta::new(*slab, false),
+        AccountMeta::new_readonly(params.collateral_mint, false),
+        AccountMeta::new(
+            percolator_prog::accounts::associated_token_address(
+                &vault_authority,
+                &params.collateral_mint,
+            ),
+            false,
+        ),
+        AccountMeta::new_readonly(sysvar::clock::ID, false),
+    ];
+    accounts.extend(oracles.iter().map(|k| AccountMeta::new_readonly(*k, false)));
+    accounts.extend([
+        AccountMeta::new_readonly(vault_authority, false),
+        AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(
+            percolator_prog::accounts::ASSOCIATED_TOKEN_PROGRAM_ID,
+            false,
+        ),
+    ]);
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: encode_init_market(params),
+    }
+}
+
+/// [`init_market`] that also creates `matcher_ctx` (a fresh keypair that
+/// must sign) as a `MATCHER_CONTEXT_LEN`-byte account owned by
+/// `matcher_program`, so a pre-created slab needs a single transaction.
+pub fn init_market_with_matcher_ctx(
+    program_id: &Pubkey,
+    slab: &Pubkey,
+    oracles: &[Pubkey],
+    params: &InitMarketParams,
+    matcher_program: &Pubkey,
+    matcher_ctx: &Pubkey,
+) -> Instruction {
+    let mut ix = init_market(program_id, slab, oracles, params);
+    ix.accounts.extend([
+        AccountMeta::new_readonly(*matcher_program, false),
+        AccountMeta::new(*matcher_ctx, true),
+    ]);
+    ix
+}
+
+/// The market's collateral vault: the vault PDA's ATA for `mint`.
+pub fn vault_address(program_id: &Pubkey, slab: &Pubkey, mint: &Pubkey) -> Pubkey {
+    percolator_prog::accounts::derive_vault(program_id, slab, mint)
+}
+
+/// DepositCollateral payload, without an allowlist proof.
+pub fn encode_deposit(user_idx: u16, amount: u64) -> Vec<u8> {
+    let mut data = vec![TAG_DEPOSIT_COLLATERAL];
+    data.extend_from_slice(&user_idx.to_le_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
+
+/// DepositCollateral payload carrying a client tag (and an empty
+/// allowlist proof ahead of it).
+pub fn encode_deposit_tagged(
+    user_idx: u16,
+    amount: u64,
+    client_tag: &[u8; CLIENT_TAG_LEN],
+) -> Vec<u8> {
+    let mut data = encode_deposit(user_idx, amount);
+    data.push(0);
+    data.extend_from_slice(client_tag);
+    data
+}
+
+/// DepositCollateral: owner (signer), slab, owner's token account,
+/// vault, token program, clock. Any initialized token account of the
+/// owner for the market's mint is accepted; see `ata` for the usual one.
+pub fn deposit(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    slab: &Pubkey,
+    user_token: &Pubkey,
+    vault: &Pubkey,
+    user_idx: u16,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*slab, false),
+            AccountMeta::new(*user_token, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ],
+        data: encode_deposit(user_idx, amount),
+    }
+}
+
+/// The PDA an owner approves (SPL `approve`) as delegate of the token
+/// account DepositWithPermit pulls from. One per market.
+pub fn permit_authority(program_id: &Pubkey, slab: &Pubkey) -> Pubkey {
+    percolator_prog::accounts::derive_permit_authority(program_id, slab).0
+}
+
+/// Message the account owner signs off-chain to authorize a deposit.
+/// `nonce` is the account's current replay nonce; `expiry` is unix
+/// seconds.
+pub fn permit_deposit_payload(
+    slab: &Pubkey,
+    user_idx: u16,
+    amount: u64,
+    nonce: u64,
+    expiry: i64,
+) -> [u8; PERMIT_DEPOSIT_PAYLOAD_LEN] {
+    percolator_prog::policy::permit_deposit_payload(
+        &slab.to_bytes(),
+        user_idx,
+        amount,
+        nonce,
+        expiry,
+    )
+}
+
+/// Ed25519 precompile verification of one signature, with key,
+/// signature and message inline: the only form the program reads.
+/// Place it immediately before the instruction that consumes it.
+pub fn ed25519_verify(signer: &Pubkey, signature: &Signature, msg: &[u8]) -> Instruction {
+    const PK_OFF: u16 = 16;
+    const SIG_OFF: u16 = PK_OFF + 32;
+    const MSG_OFF: u16 = SIG_OFF + 64;
+    let mut data = vec![1u8, 0];
+    for v in [
+        SIG_OFF,
+        u16::MAX,
+        PK_OFF,
+        u16::MAX,
+        MSG_OFF,
+        msg.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.extend_from_slice(signer.as_ref());
+    data.extend_from_slice(signature.as_ref());
+    data.extend_from_slice(msg);
+    Instruction {
+        program_id: solana_sdk::ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+/// DepositWithPermit payload, without an allowlist proof.
+pub fn encode_deposit_with_permit(user_idx: u16, amount: u64, nonce: u64, expiry: i64) -> Vec<u8> {
+    let mut data = vec![TAG_DEPOSIT_WITH_PERMIT];
+    data.extend_from_slice(&user_idx.to_le_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&nonce.to_le_bytes());
+    data.extend_from_slice(&expiry.to_le_bytes());
+    data
+}
+
+/// DepositWithPermit: relayer, slab, owner's token account, vault, token
+/// program, clock, permit PDA, instructions sysvar. Send it right after
+/// `ed25519_verify` of the owner's `permit_deposit_payload` signature.
+/// The relayer signs nothing the program reads; it only pays fees.
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_with_permit(
+    program_id: &Pubkey,
+    relayer: &Pubkey,
+    slab: &Pubkey,
+    user_token: &Pubkey,
+    vault: &Pubkey,
+    user_idx: u16,
+    amount: u64,
+    nonce: u64,
+    expiry: i64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*relayer, false),
+            AccountMeta::new(*slab, false),
+            AccountMeta::new(*user_token, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(permit_authority(program_id, slab), false),
+            AccountMeta::new_readonly(sysvar::instructions::ID, false),
+        ],
+        data: encode_deposit_with_permit(user_idx, amount, nonce, expiry),
+    }
+}
+
+/// WithdrawCollateral payload. `nonce` is only encoded when set.
+pub fn encode_withdraw(user_idx: u16, amount: u64, nonce: Option<u64>) -> Vec<u8> {
+    let mut data = vec![TAG_WITHDRAW_COLLATERAL];
+    data.extend_from_slice(&user_idx.to_le_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    if let Some(n) = nonce {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data
+}
+
+/// WithdrawCollateral: owner (signer), slab, vault, destination token
+/// account (any account of the mint), vault authority PDA, token program,
+/// clock, then one oracle account per active leg.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    slab: &Pubkey,
+    vault: &Pubkey,
+    destination: &Pubkey,
+    oracles: &[Pubkey],
+    user_idx: u16,
+    amount: u64,
+    nonce: Option<u64>,
+) -> Instruction {
+    let (vault_authority, _) = percolator_prog::accounts::derive_vault_authority(program_id, slab);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*slab, false),
+        AccountMeta::new(*vault, false),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(vault_authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(sysvar::clock::ID, false),
+    ];
+    accounts.extend(oracles.iter().map(|k| AccountMeta::new_readonly(*k, false)));
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: encode_withdraw(user_idx, amount, nonce),
+    }
+}
+
+/// KeeperCrank payload: caller index, format version 1, then
+/// `(idx, policy)` candidate pairs. Only the fixed-width policies
+/// (`CANDIDATE_FULL_CLOSE`, `CANDIDATE_TOUCH_ONLY`) are supported here;
+/// ExactPartial carries a trailing quantity.
+pub fn encode_keeper_crank(caller_idx: u16, candidates: &[(u16, u8)]) -> Vec<u8> {
+    let mut data = vec![TAG_KEEPER_CRANK];
+    data.extend_from_slice(&caller_idx.to_le_bytes());
+    data.push(1u8);
+    for &(idx, policy) in candidates {
+        data.extend_from_slice(&idx.to_le_bytes());
+        data.push(policy);
+    }
+    data
+}
+
+/// Permissionless KeeperCrank: caller (signer), slab, clock, oracle.
+pub fn keeper_crank(
+    program_id: &Pubkey,
+    caller: &Pubkey,
+    slab: &Pubkey,
+    oracle: &Pubkey,
+    candidates: &[(u16, u8)],
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*caller, true),
+            AccountMeta::new(*slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(*oracle, false),
+        ],
+        data: encode_keeper_crank(CRANK_PERMISSIONLESS, candidates),
+    }
+}
+
+/// PushHyperpMark payload. `timestamp` is only checked on relayed pushes.
+pub fn encode_push_hyperp_mark(price_e6: u64, timestamp: i64) -> Vec<u8> {
+    let mut data = vec![TAG_PUSH_HYPERP_MARK];
+    data.extend_from_slice(&price_e6.to_le_bytes());
+    data.extend_from_slice(&timestamp.to_le_bytes());
+    data
+}
+
+/// Direct PushHyperpMark: mark authority or extra pusher (signer), slab.
+pub fn push_hyperp_mark(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    slab: &Pubkey,
+    price_e6: u64,
+    timestamp: i64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*slab, false),
+        ],
+        data: encode_push_hyperp_mark(price_e6, timestamp),
+    }
+}
+
+/// AddHyperpPusher (`add == true`) or RemoveHyperpPusher payload.
+pub fn encode_hyperp_pusher(add: bool, pusher: &Pubkey) -> Vec<u8> {
+    let tag = if add {
+        TAG_ADD_HYPERP_PUSHER
+    } else {
+        TAG_REMOVE_HYPERP_PUSHER
+    };
+    let mut data = vec![tag];
+    data.extend_from_slice(pusher.as_ref());
+    data
+}
+
+/// AddHyperpPusher / RemoveHyperpPusher: mark authority (signer), slab.
+/// Adding a key before retiring the old one rotates pushers with no gap.
+pub fn hyperp_pusher(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    slab: &Pubkey,
+    add: bool,
+    pusher: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*slab, false),
+        ],
+        data: encode_hyperp_pusher(add, pusher),
+    }
+}
+
+/// TradeNoCpi arguments. The `client_order_id` / `client_tag` tail is
+/// only encoded when one of them is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TradeNoCpiArgs {
+    pub lp_idx: u16,
+    pub user_idx: u16,
+    /// Positive buys from the LP, negative sells to it.
+    pub size: i128,
+    /// 0 fills at the wrapper's effective price.
+    pub exec_price_e6: u64,
+    /// `TRADE_FLAG_*` bits.
+    pub flags: u8,
+    /// 0 = none. Repeats within the account's ring are no-ops.
+    pub client_order_id: u64,
+    /// Integrator attribution, echoed in a `ClientTagEvent` log.
+    pub client_tag: [u8; CLIENT_TAG_LEN],
+}
+
+pub fn encode_trade_nocpi(a: &TradeNoCpiArgs) -> Vec<u8> {
+    let mut data = vec![TAG_TRADE_NOCPI];
+    data.extend_from_slice(&a.lp_idx.to_le_bytes());
+    data.extend_from_slice(&a.user_idx.to_le_bytes());
+    data.extend_from_slice(&a.size.to_le_bytes());
+    data.extend_from_slice(&a.exec_price_e6.to_le_bytes());
+    data.push(a.flags);
+    let tagged = a.client_tag != [0; CLIENT_TAG_LEN];
+    if a.client_order_id != 0 || tagged {
+        data.extend_from_slice(&a.client_order_id.to_le_bytes());
+    }
+    if tagged {
+        data.extend_from_slice(&a.client_tag);
+    }
+    data
+}
+
+/// Bilateral TradeNoCpi: user (signer), LP owner (signer), slab, clock,
+/// then one oracle account per active leg (one for Hyperp markets).
+pub fn trade_nocpi(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    lp_owner: &Pubkey,
+    slab: &Pubkey,
+    oracles: &[Pubkey],
+    args: &TradeNoCpiArgs,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new_readonly(*lp_owner, true),
+        AccountMeta::new(*slab, false),
+        AccountMeta::new_readonly(sysvar::clock::ID, false),
+    ];
+    accounts.extend(oracles.iter().map(|k| AccountMeta::new_readonly(*k, false)));
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: encode_trade_nocpi(args),
+    }
+}
+
+pub fn encode_set_market_metadata(metadata: &MarketMetadata) -> Vec<u8> {
+    let mut data = vec![TAG_SET_MARKET_METADATA];
+    data.extend_from_slice(&metadata.to_wire());
+    data
+}
+
+/// SetMarketMetadata: admin (signer), slab.
+pub fn set_market_metadata(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    slab: &Pubkey,
+    metadata: &MarketMetadata,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(*slab, false),
+        ],
+        data: encode_set_market_metadata(metadata),
+    }
+}
+
+/// Market pointer PDA for a collateral mint, index feed and market index.
+/// The account holds a `state::MarketPointer` naming the slab.
+pub fn market_address(
+    program_id: &Pubkey,
+    collateral_mint: &Pubkey,
+    index_feed_id: &[u8; 32],
+    index: u16,
+) -> Pubkey {
+    percolator_prog::accounts::derive_market(
+        program_id,
+        &collateral_mint.to_bytes(),
+        index_feed_id,
+        index,
+    )
+    .0
+}
+
+/// RegisterMarket: admin (signer, pays rent), slab, pointer PDA, system
+/// program. See [`with_rent_payer`] for a sponsored registration.
+pub fn register_market(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    slab: &Pubkey,
+    collateral_mint: &Pubkey,
+    index_feed_id: &[u8; 32],
+    index: u16,
+) -> Instruction {
+    let mut data = vec![TAG_REGISTER_MARKET];
+    data.extend_from_slice(&index.to_le_bytes());
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(*slab, false),
+            AccountMeta::new(
+                market_address(program_id, collateral_mint, index_feed_id, index),
+                false,
+            ),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        ],
+        data,
+    }
+}
+
+/// Hand the rent of an [`init_market`], [`init_market_with_matcher_ctx`]
+/// or [`register_market`] instruction to `payer`, a writable signer that
+/// is usually also the fee payer. The admin stays a signer for
+/// authorization but is no longer writable, so it needs no lamports.
+pub fn with_rent_payer(mut ix: Instruction, payer: &Pubkey) -> Instruction {
+    if let Some(admin) = ix.accounts.first_mut() {
+        admin.is_writable = false;
+    }
+    ix.accounts.push(AccountMeta::new(*payer, true));
+    ix
+}
+
+/// InitSlab: admin (signer), slab. Zeroes the next `max_bytes` of the slab.
+pub fn init_slab(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    slab: &Pubkey,
+    max_bytes: u32,
+) -> Instruction {
+    let mut data = vec![TAG_INIT_SLAB];
+    data.extend_from_slice(&max_bytes.to_le_bytes());
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(*slab, false),
+        ],
+        data,
+    }
+}
+
+/// Enough InitSlab chunks of `max_bytes` to zero a `slab_len`-byte slab,
+/// one per transaction, ahead of InitMarket. Chunks are identical, so send
+/// each with a fresh blockhash.
+pub fn init_slab_chunks(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    slab: &Pubkey,
+    slab_len: usize,
+    max_bytes: u32,
+) -> Vec<Instruction> {
+    let body = slab_len.saturating_sub(percolator_prog::constants::HEADER_LEN);
+    let n = body.div_ceil(max_bytes.max(1) as usize).max(1);
+    (0..n)
+        .map(|_| init_slab(program_id, admin, slab, max_bytes))
+        .collect()
+}
+
+/// MigrateSlab: rent payer (signer), slab, system program. Grows an
+/// older slab by one step.
+pub fn migrate_slab(program_id: &Pubkey, payer: &Pubkey, slab: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*slab, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        ],
+        data: vec![TAG_MIGRATE_SLAB],
+    }
+}
+
+/// Enough MigrateSlab steps to grow a `slab_len`-byte slab to the current
+/// `SLAB_LEN` (both as on-chain lengths). Steps are identical, so send
+/// each with a fresh blockhash; extra ones are no-ops.
+pub fn migrate_slab_steps(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    slab: &Pubkey,
+    slab_len: usize,
+) -> Vec<Instruction> {
+    use percolator_prog::state::offsets::sbf::SLAB_LEN;
+    let n = SLAB_LEN
+        .saturating_sub(slab_len)
+        .div_ceil(solana_sdk::entrypoint::MAX_PERMITTED_DATA_INCREASE);
+    (0..n)
+        .map(|_| migrate_slab(program_id, payer, slab))
+        .collect()
+}
+
+/// Matcher InitVamm: the LP's PDA (`["lp", slab, lp_idx]`), matcher
+/// context. Sent to the LP's matcher program before InitLP binds the
+/// context. `VAMM_MODE_IMPACT` prices fills on the impact curve
+/// (`policy::vamm_exec_price_e6`); `VAMM_MODE_PASSIVE` ignores the impact
+/// parameters.
+pub fn init_vamm(
+    matcher_program: &Pubkey,
+    lp_pda: &Pubkey,
+    matcher_ctx: &Pubkey,
+    mode: u8,
+    params: &percolator_prog::matcher_abi::VammParams,
+) -> Instruction {
+    Instruction {
+        program_id: *matcher_program,
+        accounts: vec![
+            AccountMeta::new_readonly(*lp_pda, false),
+            AccountMeta::new(*matcher_ctx, false),
+        ],
+        data: percolator_prog::matcher_abi::encode_init_vamm(mode, params).to_vec(),
+    }
+}
+
+/// Matcher UpdateVammParams: context authority (signer), matcher context.
+/// Sent to the LP's matcher program, not to Percolator.
+pub fn update_vamm_params(
+    matcher_program: &Pubkey,
+    authority: &Pubkey,
+    matcher_ctx: &Pubkey,
+    params: &percolator_prog::matcher_abi::VammParams,
+) -> Instruction {
+    Instruction {
+        program_id: *matcher_program,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*matcher_ctx, false),
+        ],
+        data: percolator_prog::matcher_abi::encode_update_vamm_params(params).to_vec(),
+    }
+}
+
+/// Matcher quote: matcher context (read-only). The answer is a
+/// `matcher_abi::VammQuote` in the transaction's return data.
+pub fn quote_vamm(
+    matcher_program: &Pubkey,
+    matcher_ctx: &Pubkey,
+    oracle_price_e6: u64,
+    size: i128,
+) -> Instruction {
+    Instruction {
+        program_id: *matcher_program,
+        accounts: vec![AccountMeta::new_readonly(*matcher_ctx, false)],
+        data: percolator_prog::matcher_abi::encode_quote(oracle_price_e6, size).to_vec(),
+    }
+}
@@ -0,0 +1,48 @@
+//! Jito block-engine bundle submission.
+//!
+//! A liquidation crank sent through public RPC can be dropped under
+//! congestion or copied by a faster keeper. As a bundle it reaches the
+//! block engine's auction directly and lands whole or not at all. The tip
+//! is a system transfer inside the crank transaction itself rather than a
+//! separate bundle entry, so a bundle that is dropped or reverts pays
+//! nothing.
+
+use crate::config::JitoConfig;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use solana_client::{rpc_client::RpcClient, rpc_request::RpcRequest};
+use solana_sdk::{
+    instruction::Instruction, pubkey::Pubkey, system_instruction, transaction::Transaction,
+};
+
+/// Tip transfer to append as the last instruction of the bundled
+/// transaction.
+pub fn tip_ix(payer: &Pubkey, cfg: &JitoConfig) -> Result<Instruction, String> {
+    Ok(system_instruction::transfer(
+        payer,
+        &cfg.tip_account()?,
+        cfg.tip_lamports,
+    ))
+}
+
+/// `sendBundle` params: base64 wire transactions, in execution order.
+pub fn send_bundle_params(txs: &[Transaction]) -> Result<serde_json::Value, String> {
+    let encoded = txs
+        .iter()
+        .map(|tx| bincode::serialize(tx).map(|b| B64.encode(b)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("serialize bundle: {e}"))?;
+    Ok(serde_json::json!([encoded, { "encoding": "base64" }]))
+}
+
+/// Submit `txs` as one bundle; returns the block engine's bundle id.
+/// Acceptance is not landing: callers confirm the signatures themselves.
+pub fn send_bundle(cfg: &JitoConfig, txs: &[Transaction]) -> Result<String, String> {
+    RpcClient::new(cfg.url.clone())
+        .send::<String>(
+            RpcRequest::Custom {
+                method: "sendBundle",
+            },
+            send_bundle_params(txs)?,
+        )
+        .map_err(|e| format!("sendBundle: {e}"))
+}
@@ -0,0 +1,47 @@
+//! Off-chain helpers for operating Percolator markets.
+//!
+//! - `ix`: instruction encoders and account lists that mirror the
+//!   program's decoder and processor.
+//! - `ata`: deposits and withdrawals through associated token accounts,
+//!   created idempotently in the same transaction.
+//! - `budget`: compute-budget instructions from published CU estimates.
+//! - `config`: TOML configs for `percolator-bootstrap` and `percolator-keeper`.
+//! - `slab`: aligned off-chain views over fetched slab accounts.
+//! - `inspect`: the `percolator inspect` report.
+//! - `trade`: trade instructions with indices resolved by owner.
+//! - `quote`: vAMM fill prices computed locally from matcher context bytes.
+//! - `route`: best-price-first splits of one order across several LPs.
+//! - `display`: engine ↔ displayed prices and sides for inverted markets.
+//! - `watch`: typed change events between slab snapshots, and a WebSocket
+//!   subscription that emits them (feature `rpc`).
+//! - `feed`: normalized fill / funding / liquidation records from
+//!   transaction and slab updates (`percolator-geyser`).
+//! - `statement`: per-owner deposit / fill / funding / liquidation
+//!   statements from transaction history (`percolator-statement`).
+//! - `tx`: transactions whose fee payer is not the authority (sponsored
+//!   flows).
+//! - `jito` (feature `keeper`): bundle submission for liquidation cranks.
+//! - `replay` (feature `replay`): deterministic replay of recorded history.
+//! - `tsgen` (feature `tsgen`): TypeScript layouts generated from the
+//!   program source.
+
+pub mod ata;
+pub mod budget;
+pub mod config;
+pub mod display;
+pub mod feed;
+pub mod inspect;
+pub mod ix;
+#[cfg(feature = "keeper")]
+pub mod jito;
+pub mod quote;
+#[cfg(feature = "replay")]
+pub mod replay;
+pub mod route;
+pub mod slab;
+pub mod statement;
+pub mod trade;
+#[cfg(feature = "tsgen")]
+pub mod tsgen;
+pub mod tx;
+pub mod watch;
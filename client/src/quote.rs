@@ -0,0 +1,66 @@
+//! Off-chain vAMM quotes from matcher context bytes.
+//!
+//! Aggregators fetch an LP's matcher context once (or stream it) and price
+//! any size locally, with no RPC simulation per quote:
+//!
+//! ```ignore
+//! let ctx = rpc.get_account_data(&matcher_ctx)?;
+//! let q = quote::quote(&ctx, oracle_price_e6, size)?;
+//! ```
+//!
+//! The math is `policy::vamm_quote`, the reference the matcher program
+//! prices with, so a quote equals the fill a TradeCpi would get from the
+//! same context at the same oracle price. Prices are in engine space; use
+//! `display::Orientation` for inverted or scaled markets. The market's own
+//! trading fee (`MarketConfig`) is charged on top by the wrapper and is
+//! not included here.
+
+use percolator_prog::{
+    matcher_abi::{self, VammCtx},
+    policy,
+};
+
+/// A priced fill against one LP's matcher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quote {
+    /// 0 when nothing is fillable in the requested direction.
+    pub exec_price_e6: u64,
+    /// Taker side; `|fill_size| <= |requested|`.
+    pub fill_size: i128,
+    /// Fee + spread + impact after the matcher's cap.
+    pub total_bps: u32,
+    /// The matcher's own fee, part of `total_bps`.
+    pub matcher_fee_bps: u32,
+    /// `|fill_size| * exec_price_e6 / POS_SCALE`.
+    pub fill_notional_e6: u128,
+}
+
+/// Quote `size` (taker side) against a matcher context's current state.
+pub fn quote(ctx: &[u8], oracle_price_e6: u64, size: i128) -> Result<Quote, String> {
+    let vamm = matcher_abi::read_vamm_ctx(ctx).ok_or("not an initialized vAMM matcher context")?;
+    quote_vamm(&vamm, oracle_price_e6, size)
+}
+
+/// As [`quote`], for a context already parsed (e.g. cached by a router).
+pub fn quote_vamm(vamm: &VammCtx, oracle_price_e6: u64, size: i128) -> Result<Quote, String> {
+    let params = vamm.effective_params();
+    let q = policy::vamm_quote(&params, oracle_price_e6, size, vamm.inventory)
+        .ok_or_else(|| format!("size {size} at {oracle_price_e6} is not priceable"))?;
+    let fill_notional_e6 = q
+        .fill_size
+        .unsigned_abs()
+        .checked_mul(q.exec_price_e6 as u128)
+        .ok_or("fill notional overflow")?
+        / percolator::POS_SCALE;
+    Ok(Quote {
+        exec_price_e6: q.exec_price_e6,
+        fill_size: q.fill_size,
+        total_bps: q.total_bps,
+        matcher_fee_bps: if q.fill_size == 0 {
+            0
+        } else {
+            params.trading_fee_bps
+        },
+        fill_notional_e6,
+    })
+}
@@ -0,0 +1,323 @@
+//! Deterministic replay of recorded market history.
+//!
+//! A fixture is a starting snapshot (the slab plus every account the
+//! recorded instructions touch: vault, token accounts, oracles, ...) and an
+//! ordered list of steps. Each step pins the clock, overwrites any accounts
+//! whose state changed outside the program (oracle updates), and re-sends
+//! the recorded instructions. Execution goes through LiteSVM running the
+//! deployed BPF binary, so the slab stays in the on-chain (sbf) layout and
+//! every code path is the one that ran on-chain.
+//!
+//! Signatures are not reproducible, so signature and blockhash checks are
+//! disabled and a throwaway fee payer is used; nothing in the program reads
+//! the fee payer unless it is also listed in an instruction.
+
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use litesvm::LiteSVM;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+
+use crate::config::parse_pubkey;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayFixture {
+    pub program_id: String,
+    pub slab: String,
+    /// Initial state. Must include the slab.
+    pub accounts: Vec<FixtureAccount>,
+    pub steps: Vec<ReplayStep>,
+    /// Base64 slab data expected after the last step, if known.
+    #[serde(default)]
+    pub expected_slab: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureAccount {
+    pub address: String,
+    pub owner: String,
+    pub lamports: u64,
+    #[serde(default)]
+    pub executable: bool,
+    /// Base64.
+    pub data: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayStep {
+    pub slot: u64,
+    pub unix_timestamp: i64,
+    /// Accounts to overwrite before the step (oracle observations, token
+    /// balances changed by other programs).
+    #[serde(default)]
+    pub accounts: Vec<FixtureAccount>,
+    pub instructions: Vec<FixtureInstruction>,
+    /// Whether the recorded transaction succeeded. Failed transactions are
+    /// replayed too and must fail again.
+    #[serde(default = "default_true")]
+    pub success: bool,
+    /// Original signature, for reporting only.
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureInstruction {
+    pub program_id: String,
+    pub accounts: Vec<FixtureMeta>,
+    /// Base64.
+    pub data: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureMeta {
+    pub pubkey: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// First divergence between two slab images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlabDiff {
+    /// Byte offset of the first differing byte, or the shorter length.
+    pub first_offset: usize,
+    pub differing_bytes: usize,
+    pub len_replayed: usize,
+    pub len_expected: usize,
+}
+
+pub fn diff_slab(replayed: &[u8], expected: &[u8]) -> Option<SlabDiff> {
+    let common = replayed.len().min(expected.len());
+    let differing = replayed
+        .iter()
+        .zip(expected)
+        .filter(|(a, b)| a != b)
+        .count();
+    if differing == 0 && replayed.len() == expected.len() {
+        return None;
+    }
+    let first_offset = replayed
+        .iter()
+        .zip(expected)
+        .position(|(a, b)| a != b)
+        .unwrap_or(common);
+    Some(SlabDiff {
+        first_offset,
+        differing_bytes: differing + replayed.len().abs_diff(expected.len()),
+        len_replayed: replayed.len(),
+        len_expected: expected.len(),
+    })
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Fixture(String),
+    /// A step's outcome differs from the recording.
+    Outcome {
+        step: usize,
+        signature: Option<String>,
+        expected_success: bool,
+        error: Option<String>,
+        logs: Vec<String>,
+    },
+    FinalState(SlabDiff),
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Fixture(e) => write!(f, "fixture: {e}"),
+            ReplayError::Outcome {
+                step,
+                signature,
+                expected_success,
+                error,
+                logs,
+            } => {
+                let sig = signature.as_deref().unwrap_or("-");
+                if *expected_success {
+                    write!(f, "step {step} ({sig}) failed on replay: {error:?}")?;
+                } else {
+                    write!(
+                        f,
+                        "step {step} ({sig}) succeeded on replay but failed on-chain"
+                    )?;
+                }
+                for line in logs {
+                    write!(f, "\n  {line}")?;
+                }
+                Ok(())
+            }
+            ReplayError::FinalState(d) => write!(
+                f,
+                "final slab differs: first at byte {}, {} bytes differ (len {} vs {})",
+                d.first_offset, d.differing_bytes, d.len_replayed, d.len_expected
+            ),
+        }
+    }
+}
+
+fn decode_b64(field: &str, s: &str) -> Result<Vec<u8>, ReplayError> {
+    B64.decode(s)
+        .map_err(|e| ReplayError::Fixture(format!("{field}: {e}")))
+}
+
+fn fixture_err(e: String) -> ReplayError {
+    ReplayError::Fixture(e)
+}
+
+impl FixtureAccount {
+    pub fn from_account(address: &Pubkey, account: &Account) -> Self {
+        Self {
+            address: address.to_string(),
+            owner: account.owner.to_string(),
+            lamports: account.lamports,
+            executable: account.executable,
+            data: B64.encode(&account.data),
+        }
+    }
+
+    fn load(&self) -> Result<(Pubkey, Account), ReplayError> {
+        Ok((
+            parse_pubkey("account.address", &self.address).map_err(fixture_err)?,
+            Account {
+                lamports: self.lamports,
+                data: decode_b64("account.data", &self.data)?,
+                owner: parse_pubkey("account.owner", &self.owner).map_err(fixture_err)?,
+                executable: self.executable,
+                rent_epoch: 0,
+            },
+        ))
+    }
+}
+
+impl FixtureInstruction {
+    fn load(&self) -> Result<Instruction, ReplayError> {
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|m| {
+                Ok(AccountMeta {
+                    pubkey: parse_pubkey("instruction.accounts", &m.pubkey).map_err(fixture_err)?,
+                    is_signer: m.is_signer,
+                    is_writable: m.is_writable,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+        Ok(Instruction {
+            program_id: parse_pubkey("instruction.program_id", &self.program_id)
+                .map_err(fixture_err)?,
+            accounts,
+            data: decode_b64("instruction.data", &self.data)?,
+        })
+    }
+}
+
+/// Result of a replay that matched every recorded outcome.
+pub struct ReplayReport {
+    pub steps: usize,
+    pub final_slab: Vec<u8>,
+    pub compute_units: Vec<u64>,
+}
+
+impl ReplayFixture {
+    pub fn from_json(s: &str) -> Result<Self, String> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
+
+    /// Re-execute every step against `program` (the BPF `.so`) and, when
+    /// `expected_slab` is set, compare the final slab byte for byte.
+    pub fn run(&self, program: &[u8]) -> Result<ReplayReport, ReplayError> {
+        let program_id = parse_pubkey("program_id", &self.program_id).map_err(fixture_err)?;
+        let slab = parse_pubkey("slab", &self.slab).map_err(fixture_err)?;
+        if !self.accounts.iter().any(|a| a.address == self.slab) {
+            return Err(ReplayError::Fixture("slab missing from accounts".into()));
+        }
+        let mut svm = LiteSVM::new()
+            .with_sigverify(false)
+            .with_blockhash_check(false);
+        svm.add_program(program_id, program);
+        for acc in &self.accounts {
+            let (key, account) = acc.load()?;
+            svm.set_account(key, account)
+                .map_err(|e| ReplayError::Fixture(format!("{key}: {e:?}")))?;
+        }
+        let payer = Keypair::new();
+        svm.airdrop(&payer.pubkey(), 1_000_000_000_000)
+            .map_err(|e| ReplayError::Fixture(format!("airdrop: {e:?}")))?;
+
+        let mut compute_units = Vec::with_capacity(self.steps.len());
+        for (i, step) in self.steps.iter().enumerate() {
+            svm.set_sysvar(&Clock {
+                slot: step.slot,
+                unix_timestamp: step.unix_timestamp,
+                ..Clock::default()
+            });
+            for acc in &step.accounts {
+                let (key, account) = acc.load()?;
+                svm.set_account(key, account)
+                    .map_err(|e| ReplayError::Fixture(format!("step {i}: {key}: {e:?}")))?;
+            }
+            let ixs = step
+                .instructions
+                .iter()
+                .map(FixtureInstruction::load)
+                .collect::<Result<Vec<_>, _>>()?;
+            let message = Message::new(&ixs, Some(&payer.pubkey()));
+            let signatures =
+                vec![Signature::default(); message.header.num_required_signatures as usize];
+            let tx = Transaction {
+                signatures,
+                message,
+            };
+            let (error, logs, cu) = match svm.send_transaction(tx) {
+                Ok(meta) => (None, meta.logs, meta.compute_units_consumed),
+                Err(failed) => (
+                    Some(format!("{:?}", failed.err)),
+                    failed.meta.logs,
+                    failed.meta.compute_units_consumed,
+                ),
+            };
+            if error.is_none() != step.success {
+                return Err(ReplayError::Outcome {
+                    step: i,
+                    signature: step.signature.clone(),
+                    expected_success: step.success,
+                    error,
+                    logs,
+                });
+            }
+            compute_units.push(cu);
+            svm.expire_blockhash();
+        }
+
+        let final_slab = svm.get_account(&slab).map(|a| a.data).unwrap_or_default();
+        if let Some(expected) = &self.expected_slab {
+            let expected = decode_b64("expected_slab", expected)?;
+            if let Some(d) = diff_slab(&final_slab, &expected) {
+                return Err(ReplayError::FinalState(d));
+            }
+        }
+        Ok(ReplayReport {
+            steps: self.steps.len(),
+            final_slab,
+            compute_units,
+        })
+    }
+}
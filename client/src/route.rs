@@ -0,0 +1,88 @@
+//! Splitting one order across several LPs, best price first.
+//!
+//! A market can host any number of LPs, but a TradeCpi fills against one.
+//! Routers quote each LP (`quote::quote` on its matcher context), plan
+//! the split here, and submit one TradeCpi per leg in a single
+//! transaction so the route is all-or-nothing:
+//!
+//! ```ignore
+//! let quotes = [RouteQuote { lp_idx: 1, price_e6, max_size_q }, ..];
+//! let legs = route::plan(&quotes, size)?;
+//! ```
+//!
+//! The plan is advisory: every leg still runs through its own matcher
+//! CPI, limit price and engine health checks on-chain.
+
+/// Maximum LP legs in one plan. Each leg is its own TradeCpi in the same
+/// transaction, so this also bounds per-transaction CU.
+pub const MAX_ROUTE_LEGS: usize = 4;
+
+/// One LP quote offered to the router.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteQuote {
+    pub lp_idx: u16,
+    /// Engine-space price the LP's matcher quotes for this leg.
+    pub price_e6: u64,
+    /// Largest absolute size the LP will fill at `price_e6`.
+    pub max_size_q: u128,
+}
+
+/// A planned split of `size_q`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoutePlan {
+    /// `legs[i]` is the signed size routed to `quotes[i]` (0 = unused).
+    pub legs: Vec<i128>,
+    /// Absolute size filled; less than `|size_q|` when the quotes are too
+    /// shallow.
+    pub filled: u128,
+}
+
+/// Buys consume the cheapest quotes first, sells the richest; ties keep
+/// input order. Rejects duplicate LP indices, zero-price quotes, more
+/// than `MAX_ROUTE_LEGS` quotes, and `size_q` of 0 or `i128::MIN`.
+pub fn plan(quotes: &[RouteQuote], size_q: i128) -> Result<RoutePlan, String> {
+    if quotes.len() > MAX_ROUTE_LEGS {
+        return Err(format!(
+            "{} quotes, at most {MAX_ROUTE_LEGS} legs per route",
+            quotes.len()
+        ));
+    }
+    if size_q == 0 || size_q == i128::MIN {
+        return Err(format!("invalid route size {size_q}"));
+    }
+    for (i, q) in quotes.iter().enumerate() {
+        if q.price_e6 == 0 {
+            return Err(format!("LP {} quotes a zero price", q.lp_idx));
+        }
+        if quotes[..i].iter().any(|p| p.lp_idx == q.lp_idx) {
+            return Err(format!("LP {} quoted twice", q.lp_idx));
+        }
+    }
+
+    let buy = size_q > 0;
+    let mut order: Vec<usize> = (0..quotes.len()).collect();
+    // Stable, so equal prices keep input order.
+    order.sort_by(|&a, &b| {
+        let (a, b) = (quotes[a].price_e6, quotes[b].price_e6);
+        if buy {
+            a.cmp(&b)
+        } else {
+            b.cmp(&a)
+        }
+    });
+
+    let mut legs = vec![0i128; quotes.len()];
+    let mut remaining = size_q.unsigned_abs();
+    for i in order {
+        if remaining == 0 {
+            break;
+        }
+        let take = remaining.min(quotes[i].max_size_q);
+        legs[i] = if buy { take as i128 } else { -(take as i128) };
+        remaining -= take;
+    }
+    Ok(RoutePlan {
+        legs,
+        filled: size_q.unsigned_abs() - remaining,
+    })
+}
@@ -0,0 +1,320 @@
+//! Read-only views over a slab account fetched off-chain.
+//!
+//! RPC hands back an unaligned `Vec<u8>`; `Slab` copies it into a
+//! u128-backed buffer so the program's own zero-copy readers
+//! (`state::read_*`, `zc::engine_ref`) can be reused unchanged.
+
+use percolator::{Account, RiskEngine, SideMode, MAX_ACCOUNTS, POS_SCALE};
+use percolator_prog::{
+    constants, policy,
+    state::{self, offsets::sbf, HistorySample, MarketConfig, MarketMetadata, SlabHeader},
+    zc,
+};
+
+pub struct Slab {
+    words: Vec<u128>,
+    len: usize,
+}
+
+/// Off-chain maintenance-margin estimate for one account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthEstimate {
+    pub idx: u16,
+    pub effective_pos_q: i128,
+    pub notional: u128,
+    /// capital + realized pnl. Excludes mark-to-market since the account's
+    /// last touch, so it lags the engine; the crank re-checks on-chain.
+    pub equity: i128,
+    pub maintenance_req: u128,
+    /// `policy::liquidation_price_e6` with no fee or funding horizon.
+    pub liq_price_e6: Option<u64>,
+}
+
+impl HealthEstimate {
+    /// equity - maintenance requirement; negative means liquidatable.
+    pub fn health(&self) -> i128 {
+        self.equity
+            .saturating_sub(self.maintenance_req.min(i128::MAX as u128) as i128)
+    }
+
+    /// Adverse move left before `liq_price_e6`, in bps of `price`: zero or
+    /// negative once liquidatable, `i128::MAX` when no price liquidates.
+    pub fn liq_gap_bps(&self, price: u64) -> i128 {
+        let Some(liq) = self.liq_price_e6 else {
+            return i128::MAX;
+        };
+        if price == 0 {
+            return i128::MAX;
+        }
+        let gap = if self.effective_pos_q > 0 {
+            price as i128 - liq as i128
+        } else {
+            liq as i128 - price as i128
+        };
+        gap * 10_000 / price as i128
+    }
+}
+
+/// One account's PnL ledger (`state::PnlLedger`) valued at the engine's
+/// last oracle price.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PnlStatement {
+    pub idx: u16,
+    pub position_q: i128,
+    /// 0 when flat.
+    pub entry_price_e6: u64,
+    pub realized_pnl: i64,
+    pub unrealized_pnl: i128,
+    pub fees_paid: u64,
+    /// Settled funding plus what the ledger position owes on the market
+    /// funding index since; negative when received.
+    pub funding_paid: i128,
+}
+
+impl Slab {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut words = vec![0u128; data.len().div_ceil(16)];
+        bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..data.len()].copy_from_slice(data);
+        Self {
+            words,
+            len: data.len(),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &bytemuck::cast_slice::<u128, u8>(&self.words)[..self.len]
+    }
+
+    pub fn header(&self) -> SlabHeader {
+        state::read_header(self.bytes())
+    }
+
+    pub fn config(&self) -> MarketConfig {
+        state::read_config(self.bytes())
+    }
+
+    pub fn engine(&self) -> Result<&RiskEngine, String> {
+        zc::engine_ref(self.bytes()).map_err(|e| format!("slab engine view: {e:?}"))
+    }
+
+    /// Indices with the engine's `used` bit set, within `max_accounts`.
+    pub fn used_indices(&self) -> Result<Vec<u16>, String> {
+        let engine = self.engine()?;
+        let cap = (engine.params.max_accounts as usize).min(MAX_ACCOUNTS);
+        Ok((0..cap)
+            .filter(|&i| (engine.used[i >> 6] >> (i & 63)) & 1 == 1)
+            .map(|i| i as u16)
+            .collect())
+    }
+
+    /// Lowest used index owned by `owner`, restricted to LP accounts when
+    /// `lp` is set and to user accounts otherwise.
+    pub fn find_account(&self, owner: &[u8; 32], lp: bool) -> Result<Option<u16>, String> {
+        let engine = self.engine()?;
+        Ok(self.used_indices()?.into_iter().find(|&i| {
+            let acc = &engine.accounts[i as usize];
+            acc.owner == *owner && (acc.kind == Account::KIND_LP) == lp
+        }))
+    }
+
+    /// Effective position after ADL scaling, mirroring the processor's
+    /// `effective_pos_q_checked`. `None` for unused slots or corrupt state.
+    pub fn effective_pos_q(&self, idx: u16) -> Option<i128> {
+        let engine = self.engine().ok()?;
+        let i = idx as usize;
+        if i >= MAX_ACCOUNTS || (engine.used[i >> 6] >> (i & 63)) & 1 == 0 {
+            return None;
+        }
+        let acc = &engine.accounts[i];
+        let basis = acc.position_basis_q;
+        if basis == 0 {
+            return Some(0);
+        }
+        let (epoch, mode, a_side) = if basis > 0 {
+            (
+                engine.adl_epoch_long,
+                engine.side_mode_long,
+                engine.adl_mult_long,
+            )
+        } else {
+            (
+                engine.adl_epoch_short,
+                engine.side_mode_short,
+                engine.adl_mult_short,
+            )
+        };
+        if acc.adl_epoch_snap != epoch {
+            return (mode == SideMode::ResetPending
+                && acc.adl_epoch_snap.checked_add(1) == Some(epoch))
+            .then_some(0);
+        }
+        if acc.adl_a_basis == 0 {
+            return None;
+        }
+        let abs = percolator::wide_math::mul_div_floor_u128(
+            basis.unsigned_abs(),
+            a_side,
+            acc.adl_a_basis,
+        );
+        let abs = i128::try_from(abs).ok()?;
+        Some(if basis < 0 { -abs } else { abs })
+    }
+
+    /// Start of the tail region at host offset `off`. Found from the end
+    /// of the account, so it reads either target's layout; any other
+    /// length, such as a version-0 slab that MigrateSlab has not grown, has
+    /// no tail to read.
+    fn tail_start(&self, off: usize, what: &str) -> Result<usize, String> {
+        if self.len != constants::SLAB_LEN && self.len != sbf::SLAB_LEN {
+            return Err(format!(
+                "slab has no {what}: {} bytes is not a version-{} layout",
+                self.len,
+                constants::SLAB_VERSION
+            ));
+        }
+        Ok(self.len - (constants::SLAB_LEN - off))
+    }
+
+    /// Market history ring written by KeeperCrank, oldest first. Sliced
+    /// from the end of the account, so it reads either slab layout.
+    pub fn history(&self) -> Result<Vec<HistorySample>, String> {
+        let start = self.tail_start(constants::HISTORY_OFF, "history")?;
+        let region = &self.bytes()[start..start + constants::HISTORY_LEN];
+        let (_, count) = state::read_history_cursor(region);
+        Ok((0..count)
+            .map(|i| state::history_sample(region, i))
+            .collect())
+    }
+
+    /// PnL ledger statement for `idx`, read from either slab layout.
+    pub fn pnl_statement(&self, idx: u16) -> Result<PnlStatement, String> {
+        if idx as usize >= MAX_ACCOUNTS {
+            return Err(format!("account index {idx} out of range"));
+        }
+        let start = self.tail_start(constants::PNL_LEDGER_OFF, "PnL ledger")?;
+        let region = &self.bytes()[start..start + constants::PNL_LEDGER_LEN];
+        let index = i128::from_le_bytes(region[..16].try_into().unwrap());
+        let off = 32 + idx as usize * constants::PNL_LEDGER_ENTRY_LEN;
+        let ledger =
+            state::PnlLedger::from_bytes(&region[off..off + constants::PNL_LEDGER_ENTRY_LEN]);
+        let price = self.engine()?.last_oracle_price;
+        let pending = policy::funding_owed(ledger.basis.position_q, ledger.funding_index, index);
+        Ok(PnlStatement {
+            idx,
+            position_q: ledger.basis.position_q,
+            entry_price_e6: ledger.basis.entry_price_e6,
+            realized_pnl: ledger.realized_pnl,
+            unrealized_pnl: policy::unrealized_pnl(&ledger.basis, price),
+            fees_paid: ledger.fees_paid,
+            funding_paid: (ledger.funding_paid as i128).saturating_add(pending),
+        })
+    }
+
+    /// Hyperp mark pusher entries with their push history, read from
+    /// either slab layout. Entry 0 tracks the mark authority, entries 1..
+    /// the keys it added; free entries have a zero key.
+    pub fn hyperp_pushers(&self) -> Result<Vec<state::HyperpPusher>, String> {
+        let start = self.tail_start(constants::HYPERP_PUSHERS_OFF, "Hyperp pusher set")?;
+        let region = &self.bytes()[start..start + constants::HYPERP_PUSHERS_LEN];
+        Ok(region
+            .chunks_exact(constants::HYPERP_PUSHER_LEN)
+            .map(state::HyperpPusher::from_bytes)
+            .collect())
+    }
+
+    /// Whether `key` may push the Hyperp mark: the mark authority, or a
+    /// key it added with AddHyperpPusher while it is not burned.
+    pub fn is_hyperp_pusher(&self, key: &[u8; 32]) -> bool {
+        let authority = self.config().hyperp_authority;
+        if authority == [0u8; 32] || *key == [0u8; 32] {
+            return false;
+        }
+        *key == authority
+            || self
+                .hyperp_pushers()
+                .map(|pushers| pushers[1..].iter().any(|p| p.key == *key))
+                .unwrap_or(false)
+    }
+
+    /// Explorer labels from the slab tail, read from either slab layout.
+    pub fn metadata(&self) -> Result<MarketMetadata, String> {
+        let start = self.tail_start(constants::MARKET_METADATA_OFF, "metadata")?;
+        Ok(MarketMetadata::from_wire(
+            &self.bytes()[start..start + constants::MARKET_METADATA_WIRE_LEN],
+        ))
+    }
+
+    /// Maintenance estimate at the engine's last oracle price.
+    pub fn health_estimate(&self, idx: u16) -> Option<HealthEstimate> {
+        let engine = self.engine().ok()?;
+        let effective_pos_q = self.effective_pos_q(idx)?;
+        let acc = &engine.accounts[idx as usize];
+        let notional =
+            effective_pos_q.unsigned_abs() * engine.last_oracle_price as u128 / POS_SCALE;
+        let maintenance_req = if effective_pos_q == 0 {
+            0
+        } else {
+            (notional * engine.params.maintenance_margin_bps as u128 / 10_000)
+                .max(engine.params.min_nonzero_mm_req)
+        };
+        let equity = (acc.capital.get().min(i128::MAX as u128) as i128).saturating_add(acc.pnl);
+        Some(HealthEstimate {
+            idx,
+            effective_pos_q,
+            notional,
+            equity,
+            maintenance_req,
+            liq_price_e6: self.liq_price(effective_pos_q, equity, 0),
+        })
+    }
+
+    /// Oracle price at which `idx` becomes liquidatable after
+    /// `horizon_slots` of the market's current funding rate and
+    /// maintenance fee (`policy::liquidation_price_e6`), valued from the
+    /// engine's last oracle price. Shares [`Slab::health_estimate`]'s lag.
+    pub fn liquidation_price(&self, idx: u16, horizon_slots: u64) -> Option<u64> {
+        let h = self.health_estimate(idx)?;
+        self.liq_price(h.effective_pos_q, h.equity, horizon_slots)
+    }
+
+    fn liq_price(&self, position_q: i128, equity: i128, horizon_slots: u64) -> Option<u64> {
+        let engine = self.engine().ok()?;
+        let config = self.config();
+        let funding_rate_e9 = policy::funding_rate_e9_from_mark_index(
+            config.mark_ewma_e6,
+            config.last_effective_price_e6,
+            config.funding_horizon_slots,
+            config.funding_k_bps,
+            config.funding_max_premium_bps,
+            config.funding_max_e9_per_slot,
+        )?;
+        policy::liquidation_price_e6(&policy::LiqPriceInputs {
+            position_q,
+            equity,
+            price_e6: engine.last_oracle_price,
+            maintenance_margin_bps: engine.params.maintenance_margin_bps,
+            min_nonzero_mm_req: engine.params.min_nonzero_mm_req,
+            funding_rate_e9,
+            maintenance_fee_per_slot: config.maintenance_fee_per_slot,
+            horizon_slots,
+        })
+    }
+
+    /// Open accounts whose estimated health is below `buffer_bps` of their
+    /// maintenance requirement, closest to (or furthest past) their
+    /// liquidation price first, then by health.
+    pub fn liquidation_candidates(&self, buffer_bps: u64) -> Result<Vec<HealthEstimate>, String> {
+        let price = self.engine()?.last_oracle_price;
+        let mut out: Vec<HealthEstimate> = self
+            .used_indices()?
+            .into_iter()
+            .filter_map(|idx| self.health_estimate(idx))
+            .filter(|h| {
+                h.effective_pos_q != 0
+                    && h.health() < (h.maintenance_req * buffer_bps as u128 / 10_000) as i128
+            })
+            .collect();
+        out.sort_by_key(|h| (h.liq_gap_bps(price), h.health()));
+        Ok(out)
+    }
+}
@@ -0,0 +1,562 @@
+//! Per-owner account statement from a market's transaction history.
+//!
+//! Transport-agnostic core of `percolator-statement`. It takes one
+//! market's transactions oldest first and tracks the owner's account
+//! indices, plus a running cost basis for each index:
+//!
+//! - `Deposit` / `Withdrawal`: successful DepositCollateral /
+//!   WithdrawCollateral signed by the owner, at the requested amount;
+//! - `Fill`: a trade where the owner is the user signer or the LP owner.
+//!   The fill carries that side's size and its half of the trading fee.
+//!   The user half is `fee - fee / 2` and the LP half is `fee / 2`, as in
+//!   `state::PnlLedger`. The fill's realized PnL comes from
+//!   `policy::settle_cost_basis` against the running basis;
+//! - `Funding`: an estimate for each crank interval, from the
+//!   `CrankSummaryEvent` rate applied to the running position at the
+//!   last traded oracle price;
+//! - `Liquidation`: a crank that named one of the owner's indices with a
+//!   liquidation policy and reported liquidations;
+//! - `Close`: CloseAccount.
+//!
+//! Some changes never show up in the owner's history. These include
+//! liquidations found by the crank's own risk scan, ADL, dust sweeps, and
+//! funding that trades accrue between cranks. `Ledger` rows, read from
+//! the current slab (`Slab::pnl_statement`), close the statement with the
+//! on-chain totals.
+
+use crate::{feed::TxUpdate, slab::PnlStatement};
+use percolator::LiquidationPolicy;
+use percolator_prog::{
+    ix::{CrankSummaryEvent, Instruction, TradeResult},
+    policy::{self, CostBasis},
+};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+
+/// One historical transaction touching the market.
+pub struct StatementTx {
+    pub tx: TxUpdate,
+    /// Unix seconds, when the RPC node reports it.
+    pub block_time: Option<i64>,
+    /// `sol_log_data` payloads ("Program data:" lines), decoded, in log
+    /// order.
+    pub log_data: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    User,
+    Lp,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StatementRecord {
+    Deposit {
+        slot: u64,
+        block_time: Option<i64>,
+        signature: String,
+        idx: u16,
+        amount: u64,
+    },
+    Withdrawal {
+        slot: u64,
+        block_time: Option<i64>,
+        signature: String,
+        idx: u16,
+        amount: u64,
+    },
+    Fill {
+        slot: u64,
+        block_time: Option<i64>,
+        signature: String,
+        idx: u16,
+        side: Side,
+        /// This account's side; positive = bought.
+        size_q: i128,
+        exec_price_e6: u64,
+        oracle_price_e6: u64,
+        fee_paid: u128,
+        realized_pnl: i128,
+        /// Running position after the fill.
+        position_q: i128,
+    },
+    Funding {
+        slot: u64,
+        block_time: Option<i64>,
+        signature: String,
+        idx: u16,
+        from_slot: u64,
+        to_slot: u64,
+        funding_rate_e9: i128,
+        position_q: i128,
+        price_e6: u64,
+        /// Estimate; negative when received.
+        funding_paid: i128,
+    },
+    Liquidation {
+        slot: u64,
+        block_time: Option<i64>,
+        signature: String,
+        idx: u16,
+        /// Running position before the crank.
+        position_q: i128,
+        /// Last traded oracle price.
+        price_e6: u64,
+    },
+    Close {
+        slot: u64,
+        block_time: Option<i64>,
+        signature: String,
+        idx: u16,
+    },
+    /// The on-chain PnL ledger at the time of export.
+    Ledger {
+        idx: u16,
+        position_q: i128,
+        entry_price_e6: u64,
+        realized_pnl: i64,
+        unrealized_pnl: i128,
+        fees_paid: u64,
+        funding_paid: i128,
+    },
+}
+
+impl From<PnlStatement> for StatementRecord {
+    fn from(s: PnlStatement) -> Self {
+        StatementRecord::Ledger {
+            idx: s.idx,
+            position_q: s.position_q,
+            entry_price_e6: s.entry_price_e6,
+            realized_pnl: s.realized_pnl,
+            unrealized_pnl: s.unrealized_pnl,
+            fees_paid: s.fees_paid,
+            funding_paid: s.funding_paid,
+        }
+    }
+}
+
+pub const CSV_HEADER: &str = "kind,slot,block_time,signature,idx,side,amount,size_q,\
+price_e6,oracle_price_e6,fee_paid,realized_pnl,unrealized_pnl,funding_paid,position_q";
+
+/// A CSV line's cells, blank where the record has no such column.
+#[derive(Default)]
+struct Row {
+    kind: &'static str,
+    slot: Option<u64>,
+    block_time: Option<i64>,
+    signature: Option<String>,
+    idx: u16,
+    side: Option<&'static str>,
+    amount: Option<u64>,
+    size_q: Option<i128>,
+    price_e6: Option<u64>,
+    oracle_price_e6: Option<u64>,
+    fee_paid: Option<u128>,
+    realized_pnl: Option<i128>,
+    unrealized_pnl: Option<i128>,
+    funding_paid: Option<i128>,
+    position_q: Option<i128>,
+}
+
+fn cell<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+impl StatementRecord {
+    fn row(&self) -> Row {
+        match self.clone() {
+            StatementRecord::Deposit {
+                slot,
+                block_time,
+                signature,
+                idx,
+                amount,
+            }
+            | StatementRecord::Withdrawal {
+                slot,
+                block_time,
+                signature,
+                idx,
+                amount,
+            } => Row {
+                kind: if matches!(self, StatementRecord::Deposit { .. }) {
+                    "deposit"
+                } else {
+                    "withdrawal"
+                },
+                slot: Some(slot),
+                block_time,
+                signature: Some(signature),
+                idx,
+                amount: Some(amount),
+                ..Row::default()
+            },
+            StatementRecord::Fill {
+                slot,
+                block_time,
+                signature,
+                idx,
+                side,
+                size_q,
+                exec_price_e6,
+                oracle_price_e6,
+                fee_paid,
+                realized_pnl,
+                position_q,
+            } => Row {
+                kind: "fill",
+                slot: Some(slot),
+                block_time,
+                signature: Some(signature),
+                idx,
+                side: Some(match side {
+                    Side::User => "user",
+                    Side::Lp => "lp",
+                }),
+                size_q: Some(size_q),
+                price_e6: Some(exec_price_e6),
+                oracle_price_e6: Some(oracle_price_e6),
+                fee_paid: Some(fee_paid),
+                realized_pnl: Some(realized_pnl),
+                position_q: Some(position_q),
+                ..Row::default()
+            },
+            StatementRecord::Funding {
+                slot,
+                block_time,
+                signature,
+                idx,
+                position_q,
+                price_e6,
+                funding_paid,
+                ..
+            } => Row {
+                kind: "funding",
+                slot: Some(slot),
+                block_time,
+                signature: Some(signature),
+                idx,
+                price_e6: Some(price_e6),
+                funding_paid: Some(funding_paid),
+                position_q: Some(position_q),
+                ..Row::default()
+            },
+            StatementRecord::Liquidation {
+                slot,
+                block_time,
+                signature,
+                idx,
+                position_q,
+                price_e6,
+            } => Row {
+                kind: "liquidation",
+                slot: Some(slot),
+                block_time,
+                signature: Some(signature),
+                idx,
+                price_e6: Some(price_e6),
+                position_q: Some(position_q),
+                ..Row::default()
+            },
+            StatementRecord::Close {
+                slot,
+                block_time,
+                signature,
+                idx,
+            } => Row {
+                kind: "close",
+                slot: Some(slot),
+                block_time,
+                signature: Some(signature),
+                idx,
+                ..Row::default()
+            },
+            StatementRecord::Ledger {
+                idx,
+                position_q,
+                entry_price_e6,
+                realized_pnl,
+                unrealized_pnl,
+                fees_paid,
+                funding_paid,
+            } => Row {
+                kind: "ledger",
+                idx,
+                price_e6: Some(entry_price_e6),
+                fee_paid: Some(fees_paid as u128),
+                realized_pnl: Some(realized_pnl as i128),
+                unrealized_pnl: Some(unrealized_pnl),
+                funding_paid: Some(funding_paid),
+                position_q: Some(position_q),
+                ..Row::default()
+            },
+        }
+    }
+
+    /// One line under `CSV_HEADER`, without the newline. No cell needs
+    /// quoting: signatures are base58 and everything else is a number.
+    pub fn csv_row(&self) -> String {
+        let r = self.row();
+        [
+            r.kind.to_string(),
+            cell(&r.slot),
+            cell(&r.block_time),
+            cell(&r.signature),
+            r.idx.to_string(),
+            cell(&r.side),
+            cell(&r.amount),
+            cell(&r.size_q),
+            cell(&r.price_e6),
+            cell(&r.oracle_price_e6),
+            cell(&r.fee_paid),
+            cell(&r.realized_pnl),
+            cell(&r.unrealized_pnl),
+            cell(&r.funding_paid),
+            cell(&r.position_q),
+        ]
+        .join(",")
+    }
+}
+
+/// `CSV_HEADER` and one line per record.
+pub fn to_csv(records: &[StatementRecord]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+    for r in records {
+        out.push_str(&r.csv_row());
+        out.push('\n');
+    }
+    out
+}
+
+pub struct StatementBuilder {
+    program_id: Pubkey,
+    slab: Pubkey,
+    owner: Pubkey,
+    /// The owner's open indices and their running cost basis.
+    accounts: BTreeMap<u16, CostBasis>,
+    last_price_e6: u64,
+    last_crank_slot: Option<u64>,
+}
+
+impl StatementBuilder {
+    pub fn new(program_id: Pubkey, slab: Pubkey, owner: Pubkey) -> Self {
+        Self {
+            program_id,
+            slab,
+            owner,
+            accounts: BTreeMap::new(),
+            last_price_e6: 0,
+            last_crank_slot: None,
+        }
+    }
+
+    /// The owner's indices seen in history and not closed since.
+    pub fn indices(&self) -> Vec<u16> {
+        self.accounts.keys().copied().collect()
+    }
+
+    fn fill(
+        &mut self,
+        tx: &StatementTx,
+        idx: u16,
+        side: Side,
+        size_q: i128,
+        r: &TradeResult,
+        fee_paid: u128,
+    ) -> StatementRecord {
+        let basis = self.accounts.entry(idx).or_default();
+        let (next, realized_pnl) = policy::settle_cost_basis(basis, size_q, r.exec_price_e6)
+            .unwrap_or_else(|| {
+                let pos = basis.position_q.saturating_add(size_q);
+                (policy::reconcile_cost_basis(basis, pos, r.exec_price_e6), 0)
+            });
+        *basis = next;
+        StatementRecord::Fill {
+            slot: tx.tx.slot,
+            block_time: tx.block_time,
+            signature: tx.tx.signature.to_string(),
+            idx,
+            side,
+            size_q,
+            exec_price_e6: r.exec_price_e6,
+            oracle_price_e6: r.oracle_price_e6,
+            fee_paid,
+            realized_pnl,
+            position_q: next.position_q,
+        }
+    }
+
+    fn crank(
+        &mut self,
+        tx: &StatementTx,
+        candidates: &[(u16, Option<LiquidationPolicy>)],
+        summary: Option<CrankSummaryEvent>,
+        out: &mut Vec<StatementRecord>,
+    ) {
+        let Some(summary) = summary else {
+            return;
+        };
+        let from_slot = self.last_crank_slot.replace(summary.crank_slot);
+        if let Some(from_slot) =
+            from_slot.filter(|&s| s < summary.crank_slot && summary.funding_rate_e9 != 0)
+        {
+            let to_index = policy::funding_index_advance(
+                0,
+                self.last_price_e6,
+                summary.funding_rate_e9,
+                summary.crank_slot - from_slot,
+            );
+            for (&idx, basis) in &self.accounts {
+                if basis.position_q == 0 {
+                    continue;
+                }
+                out.push(StatementRecord::Funding {
+                    slot: tx.tx.slot,
+                    block_time: tx.block_time,
+                    signature: tx.tx.signature.to_string(),
+                    idx,
+                    from_slot,
+                    to_slot: summary.crank_slot,
+                    funding_rate_e9: summary.funding_rate_e9,
+                    position_q: basis.position_q,
+                    price_e6: self.last_price_e6,
+                    funding_paid: policy::funding_owed(basis.position_q, 0, to_index),
+                });
+            }
+        }
+        if summary.liquidations == 0 {
+            return;
+        }
+        for &(idx, liq) in candidates {
+            let Some(liq) = liq else { continue };
+            let Some(basis) = self.accounts.get_mut(&idx) else {
+                continue;
+            };
+            if basis.position_q == 0 {
+                continue;
+            }
+            out.push(StatementRecord::Liquidation {
+                slot: tx.tx.slot,
+                block_time: tx.block_time,
+                signature: tx.tx.signature.to_string(),
+                idx,
+                position_q: basis.position_q,
+                price_e6: self.last_price_e6,
+            });
+            let after = match liq {
+                LiquidationPolicy::FullClose => 0,
+                LiquidationPolicy::ExactPartial(q) => {
+                    let left = basis.position_q.unsigned_abs().saturating_sub(q) as i128;
+                    left * basis.position_q.signum()
+                }
+            };
+            *basis = policy::reconcile_cost_basis(basis, after, self.last_price_e6);
+        }
+    }
+
+    pub fn on_transaction(&mut self, tx: &StatementTx) -> Vec<StatementRecord> {
+        let mut out = Vec::new();
+        if !tx.tx.success {
+            return out;
+        }
+        let mut summaries = tx
+            .log_data
+            .iter()
+            .filter_map(|d| CrankSummaryEvent::decode(d));
+        // Return data holds the last trade's result only.
+        let last_trade = tx.tx.instructions.iter().rposition(|i| {
+            i.program_id == self.program_id
+                && matches!(
+                    Instruction::decode(&i.data),
+                    Ok(Instruction::TradeNoCpi { .. } | Instruction::TradeCpi { .. })
+                )
+        });
+        let result = match &tx.tx.return_data {
+            Some((pid, data)) if *pid == self.program_id => TradeResult::decode(data),
+            _ => None,
+        };
+        for (pos, i) in tx.tx.instructions.iter().enumerate() {
+            if i.program_id != self.program_id || !i.accounts.contains(&self.slab) {
+                continue;
+            }
+            let Ok(decoded) = Instruction::decode(&i.data) else {
+                continue;
+            };
+            let signer = i.accounts.first() == Some(&self.owner);
+            let (slot, block_time) = (tx.tx.slot, tx.block_time);
+            let signature = tx.tx.signature.to_string();
+            match decoded {
+                Instruction::DepositCollateral {
+                    user_idx, amount, ..
+                } if signer => {
+                    self.accounts.entry(user_idx).or_default();
+                    out.push(StatementRecord::Deposit {
+                        slot,
+                        block_time,
+                        signature,
+                        idx: user_idx,
+                        amount,
+                    });
+                }
+                Instruction::WithdrawCollateral {
+                    user_idx, amount, ..
+                } if signer => {
+                    self.accounts.entry(user_idx).or_default();
+                    out.push(StatementRecord::Withdrawal {
+                        slot,
+                        block_time,
+                        signature,
+                        idx: user_idx,
+                        amount,
+                    });
+                }
+                Instruction::TradeNoCpi {
+                    lp_idx, user_idx, ..
+                }
+                | Instruction::TradeCpi {
+                    lp_idx, user_idx, ..
+                } => {
+                    let Some(r) = result.filter(|r| r.exec_size != 0 && Some(pos) == last_trade)
+                    else {
+                        continue;
+                    };
+                    self.last_price_e6 = r.oracle_price_e6;
+                    let lp_fee = r.fee_paid / 2;
+                    if signer {
+                        let rec = self.fill(
+                            tx,
+                            user_idx,
+                            Side::User,
+                            r.exec_size,
+                            &r,
+                            r.fee_paid - lp_fee,
+                        );
+                        out.push(rec);
+                    }
+                    if i.accounts.get(1) == Some(&self.owner) {
+                        let rec = self.fill(tx, lp_idx, Side::Lp, -r.exec_size, &r, lp_fee);
+                        out.push(rec);
+                    }
+                }
+                Instruction::KeeperCrank { candidates, .. } => {
+                    self.crank(tx, &candidates, summaries.next(), &mut out);
+                }
+                Instruction::CloseAccount { user_idx, .. } if signer => {
+                    self.accounts.remove(&user_idx);
+                    out.push(StatementRecord::Close {
+                        slot,
+                        block_time,
+                        signature,
+                        idx: user_idx,
+                    });
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+}
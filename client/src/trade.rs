@@ -0,0 +1,139 @@
+//! Trade instructions built from a fetched slab.
+//!
+//! Callers know owners, not slot indices. `TradeBuilder` resolves the user
+//! and LP indices by owner pubkey and supplies the clock and oracle
+//! metas the market expects, so a trade is one call:
+//!
+//! ```ignore
+//! let b = TradeBuilder::fetch(&rpc, &program_id, &slab_key, &oracles)?;
+//! let ix = b.trade_nocpi(&user, &lp_owner, 1_000_000)?;
+//! ```
+//!
+//! Indices come from the slab as fetched; an account closed and reused
+//! in between fails the on-chain owner check rather than trading for
+//! someone else.
+
+use crate::{
+    ix::{self, TradeNoCpiArgs},
+    slab::Slab,
+};
+use percolator_prog::oracle;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, sysvar};
+
+pub struct TradeBuilder {
+    program_id: Pubkey,
+    slab_key: Pubkey,
+    slab: Slab,
+    oracles: Vec<Pubkey>,
+}
+
+impl TradeBuilder {
+    /// `oracles` are the market's leg accounts in config order. Hyperp
+    /// markets may pass none; the clock sysvar fills their unread oracle
+    /// slot, as the keeper does.
+    pub fn new(
+        program_id: &Pubkey,
+        slab_key: &Pubkey,
+        slab: Slab,
+        oracles: &[Pubkey],
+    ) -> Result<Self, String> {
+        let config = slab.config();
+        let oracles = if oracle::is_hyperp_mode(&config) {
+            match oracles.first() {
+                Some(k) => vec![*k],
+                None => vec![sysvar::clock::ID],
+            }
+        } else {
+            let legs = oracle::oracle_leg_count(&config)
+                .map_err(|_| format!("invalid oracle_leg_count {}", config.oracle_leg_count))?;
+            if oracles.len() != legs {
+                return Err(format!(
+                    "market has {legs} oracle leg(s), got {} oracle account(s)",
+                    oracles.len()
+                ));
+            }
+            oracles.to_vec()
+        };
+        Ok(Self {
+            program_id: *program_id,
+            slab_key: *slab_key,
+            slab,
+            oracles,
+        })
+    }
+
+    #[cfg(feature = "rpc")]
+    pub fn fetch(
+        rpc: &solana_client::rpc_client::RpcClient,
+        program_id: &Pubkey,
+        slab_key: &Pubkey,
+        oracles: &[Pubkey],
+    ) -> Result<Self, String> {
+        let data = rpc
+            .get_account_data(slab_key)
+            .map_err(|e| format!("{slab_key}: {e}"))?;
+        Self::new(program_id, slab_key, Slab::from_bytes(&data), oracles)
+    }
+
+    pub fn slab(&self) -> &Slab {
+        &self.slab
+    }
+
+    /// Oracle accounts the trade will pass, after the clock.
+    pub fn oracles(&self) -> &[Pubkey] {
+        &self.oracles
+    }
+
+    pub fn user_index(&self, owner: &Pubkey) -> Result<u16, String> {
+        self.slab
+            .find_account(&owner.to_bytes(), false)?
+            .ok_or_else(|| format!("no user account owned by {owner}"))
+    }
+
+    pub fn lp_index(&self, owner: &Pubkey) -> Result<u16, String> {
+        self.slab
+            .find_account(&owner.to_bytes(), true)?
+            .ok_or_else(|| format!("no LP account owned by {owner}"))
+    }
+
+    /// TradeNoCpi at the wrapper's effective price. Both `user` and
+    /// `lp_owner` must sign.
+    pub fn trade_nocpi(
+        &self,
+        user: &Pubkey,
+        lp_owner: &Pubkey,
+        size: i128,
+    ) -> Result<Instruction, String> {
+        self.trade_nocpi_with(
+            user,
+            lp_owner,
+            TradeNoCpiArgs {
+                size,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// TradeNoCpi with an explicit price and flags; the indices in `args`
+    /// are overwritten with the resolved ones.
+    pub fn trade_nocpi_with(
+        &self,
+        user: &Pubkey,
+        lp_owner: &Pubkey,
+        mut args: TradeNoCpiArgs,
+    ) -> Result<Instruction, String> {
+        if args.size == 0 {
+            return Err("trade size must be nonzero".into());
+        }
+        args.user_idx = self.user_index(user)?;
+        args.lp_idx = self.lp_index(lp_owner)?;
+        Ok(ix::trade_nocpi(
+            &self.program_id,
+            user,
+            lp_owner,
+            &self.slab_key,
+            &self.oracles,
+            &args,
+        ))
+    }
+}
@@ -0,0 +1,658 @@
+//! TypeScript layouts generated from the program source.
+//!
+//! `percolator-tsgen` parses `src/percolator.rs` and emits, for the web
+//! frontend:
+//!
+//! - one layout per instruction: its tag (read off `Instruction::decode`)
+//!   and its fields in wire order (the `Instruction` variant's fields);
+//! - `SlabHeader` and `MarketConfig` with `repr(C)` offsets;
+//! - the `state::offsets::sbf` engine offsets for the build's tier;
+//! - a generic `encodeInstruction` over those layouts.
+//!
+//! Nothing is restated by hand, so a field added to the program shows up
+//! in the next generation, and a type the generator cannot lay out fails
+//! it instead of being skipped. `tests/tsgen.rs` checks the result against
+//! the decoder and `state::offsets`.
+//!
+//! Instructions whose payload is not a flat field list (InitMarket's
+//! tails, KeeperCrank candidates) are emitted as `opaque` with their tag
+//! only; build those with the Rust client or by hand.
+
+use std::collections::BTreeMap;
+use syn::{
+    punctuated::Punctuated, visit::Visit, Attribute, BinOp, Expr, Fields, GenericArgument, Item,
+    Lit, Meta, Pat, PathArguments, Token, Type,
+};
+
+/// How a field is written on the wire. Integers are little-endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    I64,
+    U128,
+    I128,
+    Bytes(usize),
+    /// `u8` count, then that many 32-byte entries.
+    Vec32,
+    /// Trailing `u64`, omitted when absent. Always the last field.
+    OptionalU64,
+}
+
+impl WireKind {
+    fn ts_type(self) -> &'static str {
+        match self {
+            WireKind::U8 | WireKind::U16 | WireKind::U32 => "number",
+            WireKind::U64 | WireKind::I64 | WireKind::U128 | WireKind::I128 => "bigint",
+            WireKind::Bytes(_) => "Uint8Array",
+            WireKind::Vec32 => "Uint8Array[]",
+            WireKind::OptionalU64 => "bigint | undefined",
+        }
+    }
+
+    fn ts_descriptor(self) -> String {
+        match self {
+            WireKind::U8 => "\"u8\"".into(),
+            WireKind::U16 => "\"u16\"".into(),
+            WireKind::U32 => "\"u32\"".into(),
+            WireKind::U64 => "\"u64\"".into(),
+            WireKind::I64 => "\"i64\"".into(),
+            WireKind::U128 => "\"u128\"".into(),
+            WireKind::I128 => "\"i128\"".into(),
+            WireKind::Bytes(n) => format!("{{ bytes: {n} }}"),
+            WireKind::Vec32 => "\"vec32\"".into(),
+            WireKind::OptionalU64 => "\"optionalU64\"".into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IxField {
+    pub name: String,
+    pub kind: WireKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IxLayout {
+    pub name: String,
+    pub tag: u8,
+    /// Empty when `opaque`.
+    pub fields: Vec<IxField>,
+    pub opaque: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructField {
+    pub name: String,
+    /// Rust type as written, e.g. `u64` or `[u8; 32]`.
+    pub rust_type: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructLayout {
+    pub name: String,
+    pub size: usize,
+    pub fields: Vec<StructField>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Generated {
+    pub instructions: Vec<IxLayout>,
+    pub structs: Vec<StructLayout>,
+    /// `state::offsets::sbf`, evaluated for the tier.
+    pub sbf_offsets: Vec<(String, usize)>,
+}
+
+/// Deployment-size tier, as selected by the `small` / `medium` features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tier {
+    pub small: bool,
+    pub medium: bool,
+}
+
+impl Tier {
+    /// The tier this client was built for.
+    pub fn current() -> Self {
+        Self {
+            small: cfg!(feature = "small"),
+            medium: cfg!(feature = "medium"),
+        }
+    }
+}
+
+const SLAB_STRUCTS: [&str; 2] = ["SlabHeader", "MarketConfig"];
+
+pub fn generate(source: &str, tier: Tier) -> Result<Generated, String> {
+    let file = syn::parse_file(source).map_err(|e| format!("parse: {e}"))?;
+    let mut items = Items::default();
+    items.collect(&file.items, &mut Vec::new(), tier);
+
+    let consts = Consts(&items.consts);
+    let tags = items
+        .tags
+        .ok_or("Instruction::decode tag match not found")?;
+    let variants = items.variants.ok_or("enum Instruction not found")?;
+    let mut instructions = Vec::new();
+    for (tag, name) in tags {
+        let fields = variants
+            .get(&name)
+            .ok_or_else(|| format!("decode arm {tag} names unknown variant {name}"))?;
+        let kinds: Option<Vec<IxField>> = match fields {
+            Fields::Named(named) => named
+                .named
+                .iter()
+                .map(|f| {
+                    let kind = wire_kind(&f.ty, &consts)?;
+                    Some(IxField {
+                        name: f.ident.as_ref()?.to_string(),
+                        kind,
+                    })
+                })
+                .collect(),
+            Fields::Unit => Some(Vec::new()),
+            Fields::Unnamed(_) => None,
+        };
+        instructions.push(IxLayout {
+            name,
+            tag,
+            opaque: kinds.is_none(),
+            fields: kinds.unwrap_or_default(),
+        });
+    }
+
+    let mut structs = Vec::new();
+    for name in SLAB_STRUCTS {
+        let fields = items
+            .structs
+            .get(name)
+            .ok_or_else(|| format!("struct {name} not found"))?;
+        structs.push(layout_struct(name, fields, &consts)?);
+    }
+
+    let sbf_offsets = items
+        .sbf
+        .iter()
+        .map(|(name, expr)| {
+            let v = consts
+                .eval_in(expr, &["state", "offsets", "sbf"])
+                .ok_or_else(|| format!("cannot evaluate state::offsets::sbf::{name}"))?;
+            Ok((name.clone(), v))
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(Generated {
+        instructions,
+        structs,
+        sbf_offsets,
+    })
+}
+
+#[derive(Default)]
+struct Items {
+    /// (module path, name) -> expression.
+    consts: BTreeMap<(Vec<String>, String), Expr>,
+    variants: Option<BTreeMap<String, Fields>>,
+    tags: Option<Vec<(u8, String)>>,
+    structs: BTreeMap<String, Fields>,
+    sbf: Vec<(String, Expr)>,
+}
+
+impl Items {
+    fn collect(&mut self, items: &[Item], path: &mut Vec<String>, tier: Tier) {
+        for item in items {
+            match item {
+                Item::Mod(m) if cfg_holds(&m.attrs, tier) => {
+                    if let Some((_, inner)) = &m.content {
+                        path.push(m.ident.to_string());
+                        self.collect(inner, path, tier);
+                        path.pop();
+                    }
+                }
+                Item::Const(c) if cfg_holds(&c.attrs, tier) => {
+                    let name = c.ident.to_string();
+                    if *path == ["state", "offsets", "sbf"] {
+                        self.sbf.push((name.clone(), (*c.expr).clone()));
+                    }
+                    self.consts.insert((path.clone(), name), (*c.expr).clone());
+                }
+                Item::Enum(e) if e.ident == "Instruction" && *path == ["ix"] => {
+                    self.variants = Some(
+                        e.variants
+                            .iter()
+                            .map(|v| (v.ident.to_string(), v.fields.clone()))
+                            .collect(),
+                    );
+                }
+                Item::Struct(s) if *path == ["state"] => {
+                    self.structs.insert(s.ident.to_string(), s.fields.clone());
+                }
+                Item::Impl(i) if *path == ["ix"] => {
+                    if let Type::Path(p) = &*i.self_ty {
+                        if p.path.is_ident("Instruction") {
+                            let mut finder = TagMatch::default();
+                            finder.visit_item_impl(i);
+                            self.tags = finder.tags;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Finds `match tag { N => ... Instruction::Name ... }` in the decoder.
+#[derive(Default)]
+struct TagMatch {
+    tags: Option<Vec<(u8, String)>>,
+}
+
+impl<'ast> Visit<'ast> for TagMatch {
+    fn visit_expr_match(&mut self, m: &'ast syn::ExprMatch) {
+        let on_tag = matches!(&*m.expr, Expr::Path(p) if p.path.is_ident("tag"));
+        if !on_tag || self.tags.is_some() {
+            return syn::visit::visit_expr_match(self, m);
+        }
+        let mut tags = Vec::new();
+        for arm in &m.arms {
+            let Pat::Lit(lit) = &arm.pat else { continue };
+            let Lit::Int(n) = &lit.lit else { continue };
+            let Ok(tag) = n.base10_parse::<u8>() else {
+                continue;
+            };
+            let mut variant = VariantPath(None);
+            variant.visit_expr(&arm.body);
+            // Retired tags decode to an error and name no variant.
+            if let Some(name) = variant.0 {
+                tags.push((tag, name));
+            }
+        }
+        self.tags = Some(tags);
+    }
+}
+
+struct VariantPath(Option<String>);
+
+impl<'ast> Visit<'ast> for VariantPath {
+    fn visit_path(&mut self, p: &'ast syn::Path) {
+        if self.0.is_none() && p.segments.len() == 2 && p.segments[0].ident == "Instruction" {
+            self.0 = Some(p.segments[1].ident.to_string());
+        }
+        syn::visit::visit_path(self, p);
+    }
+}
+
+/// `#[cfg(...)]` over the tier features. Anything else (e.g.
+/// `target_os = "solana"`) is false: the generator describes host builds
+/// of the client, which never see on-chain-only items.
+fn cfg_holds(attrs: &[Attribute], tier: Tier) -> bool {
+    attrs
+        .iter()
+        .filter(|a| a.path().is_ident("cfg"))
+        .all(|a| a.parse_args::<Meta>().is_ok_and(|m| eval_cfg(&m, tier)))
+}
+
+fn eval_cfg(meta: &Meta, tier: Tier) -> bool {
+    match meta {
+        Meta::NameValue(nv) if nv.path.is_ident("feature") => match &nv.value {
+            Expr::Lit(l) => match &l.lit {
+                Lit::Str(s) => match s.value().as_str() {
+                    "small" => tier.small,
+                    "medium" => tier.medium,
+                    _ => false,
+                },
+                _ => false,
+            },
+            _ => false,
+        },
+        Meta::List(list) => {
+            let Ok(nested) = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            else {
+                return false;
+            };
+            if list.path.is_ident("all") {
+                nested.iter().all(|m| eval_cfg(m, tier))
+            } else if list.path.is_ident("any") {
+                nested.iter().any(|m| eval_cfg(m, tier))
+            } else if list.path.is_ident("not") {
+                nested.len() == 1 && !eval_cfg(&nested[0], tier)
+            } else {
+                false
+            }
+        }
+        _ => false,
+    }
+}
+
+struct Consts<'a>(&'a BTreeMap<(Vec<String>, String), Expr>);
+
+impl Consts<'_> {
+    /// Integer constant expressions: literals, `+ - * /`, parentheses,
+    /// casts and references to other consts (same module first, then
+    /// `constants`).
+    fn eval_in(&self, expr: &Expr, module: &[&str]) -> Option<usize> {
+        match expr {
+            Expr::Lit(l) => match &l.lit {
+                Lit::Int(n) => n.base10_parse().ok(),
+                _ => None,
+            },
+            Expr::Paren(p) => self.eval_in(&p.expr, module),
+            Expr::Group(g) => self.eval_in(&g.expr, module),
+            Expr::Cast(c) => self.eval_in(&c.expr, module),
+            Expr::Binary(b) => {
+                let l = self.eval_in(&b.left, module)?;
+                let r = self.eval_in(&b.right, module)?;
+                match b.op {
+                    BinOp::Add(_) => l.checked_add(r),
+                    BinOp::Sub(_) => l.checked_sub(r),
+                    BinOp::Mul(_) => l.checked_mul(r),
+                    BinOp::Div(_) => l.checked_div(r),
+                    _ => None,
+                }
+            }
+            Expr::Path(p) => {
+                let name = p.path.segments.last()?.ident.to_string();
+                let here: Vec<String> = module.iter().map(|s| s.to_string()).collect();
+                let constants = vec!["constants".to_string()];
+                let (key, expr) = [here, constants]
+                    .into_iter()
+                    .find_map(|m| self.0.get_key_value(&(m, name.clone())))?;
+                let at: Vec<&str> = key.0.iter().map(String::as_str).collect();
+                self.eval_in(expr, &at)
+            }
+            _ => None,
+        }
+    }
+
+    fn eval(&self, expr: &Expr) -> Option<usize> {
+        self.eval_in(expr, &["constants"])
+    }
+}
+
+fn last_ident(ty: &Type) -> Option<(String, &PathArguments)> {
+    match ty {
+        Type::Path(p) => {
+            let seg = p.path.segments.last()?;
+            Some((seg.ident.to_string(), &seg.arguments))
+        }
+        _ => None,
+    }
+}
+
+fn first_type_arg(args: &PathArguments) -> Option<&Type> {
+    match args {
+        PathArguments::AngleBracketed(a) => a.args.iter().find_map(|g| match g {
+            GenericArgument::Type(t) => Some(t),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+fn byte_array_len(ty: &Type, consts: &Consts) -> Option<usize> {
+    match ty {
+        Type::Array(a) if last_ident(&a.elem).is_some_and(|(n, _)| n == "u8") => {
+            consts.eval(&a.len)
+        }
+        _ => None,
+    }
+}
+
+/// `None` when the type has no flat wire form, which makes the
+/// instruction opaque.
+fn wire_kind(ty: &Type, consts: &Consts) -> Option<WireKind> {
+    if let Some(n) = byte_array_len(ty, consts) {
+        return Some(WireKind::Bytes(n));
+    }
+    let (name, args) = last_ident(ty)?;
+    Some(match name.as_str() {
+        "u8" => WireKind::U8,
+        "u16" => WireKind::U16,
+        "u32" => WireKind::U32,
+        "u64" => WireKind::U64,
+        "i64" => WireKind::I64,
+        "u128" => WireKind::U128,
+        "i128" => WireKind::I128,
+        "Pubkey" => WireKind::Bytes(32),
+        "MarketMetadata" => {
+            WireKind::Bytes(consts.eval(&syn::parse_str("MARKET_METADATA_WIRE_LEN").ok()?)?)
+        }
+        "Vec" if byte_array_len(first_type_arg(args)?, consts) == Some(32) => WireKind::Vec32,
+        "Option" if last_ident(first_type_arg(args)?).is_some_and(|(n, _)| n == "u64") => {
+            WireKind::OptionalU64
+        }
+        _ => return None,
+    })
+}
+
+/// Size and alignment on sbf, where `u128` aligns to 8.
+fn size_align(ty: &Type, consts: &Consts) -> Option<(usize, usize)> {
+    match ty {
+        Type::Array(a) => {
+            let (size, align) = size_align(&a.elem, consts)?;
+            Some((size * consts.eval_in(&a.len, &["state"])?, align))
+        }
+        _ => match last_ident(ty)?.0.as_str() {
+            "u8" | "i8" => Some((1, 1)),
+            "u16" | "i16" => Some((2, 2)),
+            "u32" | "i32" => Some((4, 4)),
+            "u64" | "i64" => Some((8, 8)),
+            "u128" | "i128" | "U128" | "I128" => Some((16, 8)),
+            _ => None,
+        },
+    }
+}
+
+fn rust_type(ty: &Type) -> String {
+    quote::ToTokens::to_token_stream(ty)
+        .to_string()
+        .replace(" ;", ";")
+        .replace("[ ", "[")
+        .replace(" ]", "]")
+}
+
+fn layout_struct(name: &str, fields: &Fields, consts: &Consts) -> Result<StructLayout, String> {
+    let Fields::Named(named) = fields else {
+        return Err(format!("struct {name} has no named fields"));
+    };
+    let mut offset = 0;
+    let mut max_align = 1;
+    let mut out = Vec::new();
+    for f in &named.named {
+        let field = f.ident.as_ref().map(|i| i.to_string()).unwrap_or_default();
+        let (size, align) = size_align(&f.ty, consts)
+            .ok_or_else(|| format!("{name}.{field}: no layout for `{}`", rust_type(&f.ty)))?;
+        offset = offset.next_multiple_of(align);
+        max_align = max_align.max(align);
+        out.push(StructField {
+            name: field,
+            rust_type: rust_type(&f.ty),
+            offset,
+            size,
+        });
+        offset += size;
+    }
+    Ok(StructLayout {
+        name: name.to_string(),
+        size: offset.next_multiple_of(max_align),
+        fields: out,
+    })
+}
+
+fn camel(snake: &str) -> String {
+    let mut out = String::with_capacity(snake.len());
+    let mut upper = false;
+    for c in snake.trim_start_matches('_').chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn screaming(camel_case: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in camel_case.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.extend(c.to_uppercase());
+    }
+    out
+}
+
+const TS_PRELUDE: &str = r#"// Generated by percolator-tsgen from src/percolator.rs. Do not edit.
+
+export type WireKind =
+  | "u8" | "u16" | "u32" | "u64" | "i64" | "u128" | "i128"
+  | { bytes: number }
+  | "vec32"
+  | "optionalU64";
+
+export interface FieldLayout {
+  readonly name: string;
+  readonly kind: WireKind;
+}
+
+export interface InstructionLayout {
+  readonly tag: number;
+  readonly fields: readonly FieldLayout[];
+  /** Payload is not a flat field list; encode it by hand. */
+  readonly opaque: boolean;
+}
+
+export interface StructFieldLayout {
+  readonly name: string;
+  readonly rustType: string;
+  readonly offset: number;
+  readonly size: number;
+}
+
+export interface StructLayout {
+  readonly size: number;
+  readonly fields: readonly StructFieldLayout[];
+}
+
+/** Little-endian wire encoding of `args` under `layout`. */
+export function encodeInstruction(
+  layout: InstructionLayout,
+  args: Record<string, unknown>,
+): Uint8Array {
+  if (layout.opaque) throw new Error("opaque instruction layout");
+  const out: number[] = [layout.tag];
+  const int = (v: bigint, bytes: number) => {
+    let x = BigInt.asUintN(bytes * 8, v);
+    for (let i = 0; i < bytes; i++) {
+      out.push(Number(x & 0xffn));
+      x >>= 8n;
+    }
+  };
+  const raw = (b: Uint8Array, len: number) => {
+    if (b.length !== len) throw new Error(`expected ${len} bytes, got ${b.length}`);
+    out.push(...b);
+  };
+  for (const f of layout.fields) {
+    const v = args[f.name];
+    switch (f.kind) {
+      case "u8": int(BigInt(v as number), 1); break;
+      case "u16": int(BigInt(v as number), 2); break;
+      case "u32": int(BigInt(v as number), 4); break;
+      case "u64": case "i64": int(v as bigint, 8); break;
+      case "u128": case "i128": int(v as bigint, 16); break;
+      case "vec32": {
+        const items = v as Uint8Array[];
+        out.push(items.length);
+        for (const item of items) raw(item, 32);
+        break;
+      }
+      case "optionalU64": if (v !== undefined) int(v as bigint, 8); break;
+      default: raw(v as Uint8Array, f.kind.bytes);
+    }
+  }
+  return Uint8Array.from(out);
+}
+"#;
+
+impl Generated {
+    pub fn instruction(&self, name: &str) -> Option<&IxLayout> {
+        self.instructions.iter().find(|i| i.name == name)
+    }
+
+    pub fn to_typescript(&self) -> String {
+        let mut ts = String::from(TS_PRELUDE);
+
+        ts.push_str("\nexport const INSTRUCTIONS = {\n");
+        for ix in &self.instructions {
+            ts.push_str(&format!(
+                "  {}: {{ tag: {}, opaque: {}, fields: [",
+                ix.name, ix.tag, ix.opaque
+            ));
+            let fields: Vec<String> = ix
+                .fields
+                .iter()
+                .map(|f| {
+                    format!(
+                        "{{ name: \"{}\", kind: {} }}",
+                        camel(&f.name),
+                        f.kind.ts_descriptor()
+                    )
+                })
+                .collect();
+            ts.push_str(&fields.join(", "));
+            ts.push_str("] },\n");
+        }
+        ts.push_str("} as const satisfies Record<string, InstructionLayout>;\n");
+
+        for ix in self.instructions.iter().filter(|i| !i.opaque) {
+            ts.push_str(&format!("\nexport interface {}Args {{\n", ix.name));
+            for f in &ix.fields {
+                let optional = if f.kind == WireKind::OptionalU64 {
+                    "?"
+                } else {
+                    ""
+                };
+                ts.push_str(&format!(
+                    "  {}{optional}: {};\n",
+                    camel(&f.name),
+                    f.kind.ts_type()
+                ));
+            }
+            ts.push_str("}\n");
+        }
+
+        for s in &self.structs {
+            ts.push_str(&format!(
+                "\nexport const {}: StructLayout = {{\n  size: {},\n  fields: [\n",
+                screaming(&s.name),
+                s.size
+            ));
+            for f in &s.fields {
+                ts.push_str(&format!(
+                    "    {{ name: \"{}\", rustType: \"{}\", offset: {}, size: {} }},\n",
+                    camel(&f.name),
+                    f.rust_type,
+                    f.offset,
+                    f.size
+                ));
+            }
+            ts.push_str("  ],\n};\n");
+        }
+
+        ts.push_str("\n/** Absolute slab offsets of engine fields on chain (sbf). */\n");
+        ts.push_str("export const SBF_OFFSETS = {\n");
+        for (name, v) in &self.sbf_offsets {
+            ts.push_str(&format!("  {name}: {v},\n"));
+        }
+        ts.push_str("} as const;\n");
+        ts
+    }
+}
@@ -0,0 +1,296 @@
+//! Transactions whose fee payer is not the instruction authority.
+//!
+//! The program authorizes every instruction by the authority's signature
+//! alone and never reads the fee payer, so a relayer can pay fees (and,
+//! through `ix::with_rent_payer`, rent) for a user, keeper or admin that
+//! holds no SOL:
+//!
+//! ```ignore
+//! let mut tx = tx::sponsored(&[ix], &relayer.pubkey(), blockhash);
+//! tx.partial_sign(&[&user], blockhash); // on the user's device
+//! tx.partial_sign(&[&relayer], blockhash); // relayer, then submit
+//! ```
+//!
+//! `durable` builds against a nonce account instead of a recent
+//! blockhash: the transaction stays valid until the nonce advances, so it
+//! can be signed ahead of time and resent verbatim until it lands, and
+//! landing consumes it, so a resend never executes twice.
+//!
+//! `TxSender` (feature `rpc`) submits either kind with exponential backoff
+//! under a `SendPolicy`, re-signing a blockhash transaction only once its
+//! blockhash has expired.
+
+use serde::Deserialize;
+#[cfg(feature = "rpc")]
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::state::{Data, State, Versions},
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction, system_program,
+    transaction::Transaction,
+};
+#[cfg(feature = "rpc")]
+use solana_sdk::{signature::Signature, transaction::TransactionError};
+use std::time::Duration;
+
+/// Unsigned `ixs` with `fee_payer` as the first account key.
+pub fn sponsored(ixs: &[Instruction], fee_payer: &Pubkey, blockhash: Hash) -> Transaction {
+    Transaction::new_unsigned(Message::new_with_blockhash(
+        ixs,
+        Some(fee_payer),
+        &blockhash,
+    ))
+}
+
+/// `ixs` signed by `fee_payer` and every authority in `signers`. An
+/// authority that is also the fee payer signs once.
+pub fn signed(
+    ixs: &[Instruction],
+    fee_payer: &dyn Signer,
+    signers: &[&dyn Signer],
+    blockhash: Hash,
+) -> Result<Transaction, String> {
+    let mut all: Vec<&dyn Signer> = vec![fee_payer];
+    for s in signers {
+        if all.iter().all(|a| a.pubkey() != s.pubkey()) {
+            all.push(*s);
+        }
+    }
+    let mut tx = sponsored(ixs, &fee_payer.pubkey(), blockhash);
+    tx.try_sign(&all, blockhash).map_err(|e| e.to_string())?;
+    Ok(tx)
+}
+
+/// A durable nonce account and the key allowed to advance it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nonce {
+    pub account: Pubkey,
+    pub authority: Pubkey,
+}
+
+/// State of an initialized nonce account; `Data::blockhash` is the value
+/// the next durable transaction must carry.
+pub fn nonce_data(account: &Account) -> Result<Data, String> {
+    if account.owner != system_program::ID {
+        return Err(format!("nonce account owned by {}", account.owner));
+    }
+    let versions: Versions = account
+        .deserialize_data()
+        .map_err(|e| format!("nonce account: {e}"))?;
+    match versions.state() {
+        State::Initialized(data) => Ok(data.clone()),
+        State::Uninitialized => Err("nonce account is not initialized".into()),
+    }
+}
+
+/// `ixs` behind the AdvanceNonceAccount instruction the runtime requires
+/// first in a durable transaction.
+pub fn with_advance_nonce(ixs: &[Instruction], nonce: &Nonce) -> Vec<Instruction> {
+    let mut all = vec![system_instruction::advance_nonce_account(
+        &nonce.account,
+        &nonce.authority,
+    )];
+    all.extend_from_slice(ixs);
+    all
+}
+
+/// `signed` against the nonce value `nonce_hash` instead of a recent
+/// blockhash. The nonce authority must be `fee_payer` or in `signers`.
+pub fn durable(
+    ixs: &[Instruction],
+    fee_payer: &dyn Signer,
+    signers: &[&dyn Signer],
+    nonce: &Nonce,
+    nonce_hash: Hash,
+) -> Result<Transaction, String> {
+    signed(
+        &with_advance_nonce(ixs, nonce),
+        fee_payer,
+        signers,
+        nonce_hash,
+    )
+}
+
+/// Submission policy for `TxSender` (the keeper's `[send]` table).
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SendPolicy {
+    /// Submissions of one transaction, counting re-signs, before giving up.
+    pub max_attempts: u32,
+    /// Wait after the first failed attempt; doubled after each further one.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// How long one attempt polls for confirmation.
+    pub confirm_timeout_ms: u64,
+    /// Send without simulating first. Failures then only show on-chain.
+    pub skip_preflight: bool,
+}
+
+impl Default for SendPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+            confirm_timeout_ms: 30_000,
+            skip_preflight: false,
+        }
+    }
+}
+
+impl SendPolicy {
+    /// Wait before attempt `attempt + 1`, given `attempt` (≥ 1) failed.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(32);
+        Duration::from_millis(
+            self.initial_backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms),
+        )
+    }
+}
+
+/// Submits transactions under a `SendPolicy`.
+#[cfg(feature = "rpc")]
+pub struct TxSender<'a> {
+    rpc: &'a RpcClient,
+    policy: SendPolicy,
+}
+
+/// How often an attempt polls the signature status.
+#[cfg(feature = "rpc")]
+const STATUS_POLL: Duration = Duration::from_millis(500);
+
+#[cfg(feature = "rpc")]
+enum Attempt {
+    Landed(Signature),
+    /// Preflight or on-chain failure; resending cannot help.
+    Failed(TransactionError),
+    Pending,
+    /// The blockhash passed its last valid height without the transaction
+    /// landing; only a re-sign can.
+    Expired,
+}
+
+#[cfg(feature = "rpc")]
+impl<'a> TxSender<'a> {
+    pub fn new(rpc: &'a RpcClient, policy: SendPolicy) -> Self {
+        Self { rpc, policy }
+    }
+
+    /// Sign with the latest blockhash through `sign` and send until
+    /// confirmed. The same transaction is resent while its blockhash is
+    /// valid; `sign` is called again only once it has expired, so at most
+    /// one signed version can land. A transaction error, in preflight or
+    /// on-chain, is returned without retrying.
+    pub fn send(
+        &self,
+        sign: impl Fn(Hash) -> Result<Transaction, String>,
+    ) -> Result<Signature, String> {
+        let attempts = self.policy.max_attempts.max(1);
+        let mut current: Option<(Transaction, u64)> = None;
+        let mut last_err = String::new();
+        for attempt in 1..=attempts {
+            if attempt > 1 {
+                std::thread::sleep(self.policy.backoff(attempt - 1));
+            }
+            let (tx, last_valid) = match current.take() {
+                Some(signed) => signed,
+                None => match self
+                    .rpc
+                    .get_latest_blockhash_with_commitment(self.rpc.commitment())
+                {
+                    Ok((blockhash, last_valid)) => (sign(blockhash)?, last_valid),
+                    Err(e) => {
+                        last_err = e.to_string();
+                        continue;
+                    }
+                },
+            };
+            match self.attempt(&tx, Some(last_valid)) {
+                Ok(Attempt::Landed(sig)) => return Ok(sig),
+                Ok(Attempt::Failed(e)) => return Err(format!("{}: {e}", tx.signatures[0])),
+                Ok(Attempt::Expired) => last_err = format!("{} expired", tx.signatures[0]),
+                Ok(Attempt::Pending) => {
+                    last_err = format!("{} not confirmed", tx.signatures[0]);
+                    current = Some((tx, last_valid));
+                }
+                Err(e) => {
+                    last_err = e;
+                    current = Some((tx, last_valid));
+                }
+            }
+        }
+        Err(format!("gave up after {attempts} attempts: {last_err}"))
+    }
+
+    /// Resend a pre-signed durable-nonce transaction verbatim until it
+    /// lands. It cannot expire, and landing advances the nonce, so no
+    /// resend executes twice.
+    pub fn send_durable(&self, tx: &Transaction) -> Result<Signature, String> {
+        let attempts = self.policy.max_attempts.max(1);
+        let mut last_err = String::new();
+        for attempt in 1..=attempts {
+            if attempt > 1 {
+                std::thread::sleep(self.policy.backoff(attempt - 1));
+            }
+            match self.attempt(tx, None) {
+                Ok(Attempt::Landed(sig)) => return Ok(sig),
+                Ok(Attempt::Failed(e)) => return Err(format!("{}: {e}", tx.signatures[0])),
+                Ok(_) => last_err = format!("{} not confirmed", tx.signatures[0]),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(format!("gave up after {attempts} attempts: {last_err}"))
+    }
+
+    /// Submit `tx` once and poll its status for up to the confirm timeout.
+    /// `Err` is an RPC failure worth retrying.
+    fn attempt(
+        &self,
+        tx: &Transaction,
+        last_valid_block_height: Option<u64>,
+    ) -> Result<Attempt, String> {
+        let sig = tx.signatures[0];
+        let config = RpcSendTransactionConfig {
+            skip_preflight: self.policy.skip_preflight,
+            preflight_commitment: Some(self.rpc.commitment().commitment),
+            ..RpcSendTransactionConfig::default()
+        };
+        if let Err(e) = self.rpc.send_transaction_with_config(tx, config) {
+            match e.get_transaction_error() {
+                // An earlier attempt landed, or the node lags the blockhash
+                // (or the nonce already advanced); the polls below decide.
+                Some(TransactionError::AlreadyProcessed | TransactionError::BlockhashNotFound) => {}
+                Some(err) => return Ok(Attempt::Failed(err)),
+                None => return Err(e.to_string()),
+            }
+        }
+        let timeout = Duration::from_millis(self.policy.confirm_timeout_ms);
+        let start = std::time::Instant::now();
+        loop {
+            // Height before status: a transaction that landed by the
+            // expiry height shows up in the status read after it.
+            let expired = last_valid_block_height
+                .is_some_and(|last| self.rpc.get_block_height().is_ok_and(|h| h > last));
+            match self
+                .rpc
+                .get_signature_status_with_commitment(&sig, self.rpc.commitment())
+            {
+                Ok(Some(Ok(()))) => return Ok(Attempt::Landed(sig)),
+                Ok(Some(Err(err))) => return Ok(Attempt::Failed(err)),
+                Ok(None) if expired => return Ok(Attempt::Expired),
+                Ok(None) | Err(_) => {}
+            }
+            if start.elapsed() >= timeout {
+                return Ok(Attempt::Pending);
+            }
+            std::thread::sleep(STATUS_POLL);
+        }
+    }
+}
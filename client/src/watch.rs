@@ -0,0 +1,201 @@
+//! Slab change events from successive snapshots.
+//!
+//! A slab is up to a megabyte and every account-change notification
+//! carries all of it. `SlabWatcher` keeps the previous snapshot, compares
+//! the engine's account records byte for byte and only decodes the ones
+//! that changed, turning a notification into a short list of typed
+//! events. `subscribe` (feature `rpc`) drives it from a WebSocket account
+//! subscription.
+
+use crate::slab::Slab;
+use percolator::{Account, MAX_ACCOUNTS};
+use percolator_prog::{constants::ENGINE_OFF, zc::ACCOUNTS_OFFSET};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlabEvent {
+    AccountOpened {
+        idx: u16,
+        owner: [u8; 32],
+    },
+    AccountClosed {
+        idx: u16,
+    },
+    /// Capital or realized PnL changed.
+    BalanceChanged {
+        idx: u16,
+        capital_before: u128,
+        capital_after: u128,
+        pnl_before: i128,
+        pnl_after: i128,
+    },
+    /// Effective (ADL-scaled) position changed.
+    PositionChanged {
+        idx: u16,
+        before_q: i128,
+        after_q: i128,
+    },
+    /// `mark_ewma_e6` moved.
+    MarkMoved {
+        before_e6: u64,
+        after_e6: u64,
+    },
+    /// The engine's last oracle price moved.
+    OraclePriceMoved {
+        before: u64,
+        after: u64,
+    },
+}
+
+fn account_bytes(slab: &Slab, idx: usize) -> &[u8] {
+    let len = core::mem::size_of::<Account>();
+    let off = ENGINE_OFF + ACCOUNTS_OFFSET + idx * len;
+    &slab.bytes()[off..off + len]
+}
+
+fn is_used(slab: &Slab, idx: usize) -> Result<bool, String> {
+    Ok((slab.engine()?.used[idx >> 6] >> (idx & 63)) & 1 == 1)
+}
+
+/// Events turning `prev` into `next`, in account-index order after the
+/// market-level events.
+pub fn diff(prev: &Slab, next: &Slab) -> Result<Vec<SlabEvent>, String> {
+    let (pe, ne) = (prev.engine()?, next.engine()?);
+    let mut out = Vec::new();
+    let (pm, nm) = (prev.config().mark_ewma_e6, next.config().mark_ewma_e6);
+    if pm != nm {
+        out.push(SlabEvent::MarkMoved {
+            before_e6: pm,
+            after_e6: nm,
+        });
+    }
+    if pe.last_oracle_price != ne.last_oracle_price {
+        out.push(SlabEvent::OraclePriceMoved {
+            before: pe.last_oracle_price,
+            after: ne.last_oracle_price,
+        });
+    }
+    // ADL or a side reset rescales every position on that side without
+    // touching the account records.
+    let side_changed = pe.adl_mult_long != ne.adl_mult_long
+        || pe.adl_mult_short != ne.adl_mult_short
+        || pe.adl_epoch_long != ne.adl_epoch_long
+        || pe.adl_epoch_short != ne.adl_epoch_short;
+
+    for i in 0..MAX_ACCOUNTS {
+        let idx = i as u16;
+        let (was, is) = (is_used(prev, i)?, is_used(next, i)?);
+        match (was, is) {
+            (false, false) => continue,
+            (true, false) => {
+                out.push(SlabEvent::AccountClosed { idx });
+                continue;
+            }
+            (false, true) => out.push(SlabEvent::AccountOpened {
+                idx,
+                owner: ne.accounts[i].owner,
+            }),
+            (true, true) => {}
+        }
+        if was && !side_changed && account_bytes(prev, i) == account_bytes(next, i) {
+            continue;
+        }
+        let (pa, na) = (&pe.accounts[i], &ne.accounts[i]);
+        let (capital_before, capital_after) = if was {
+            (pa.capital.get(), na.capital.get())
+        } else {
+            (0, na.capital.get())
+        };
+        let (pnl_before, pnl_after) = if was { (pa.pnl, na.pnl) } else { (0, na.pnl) };
+        if capital_before != capital_after || pnl_before != pnl_after {
+            out.push(SlabEvent::BalanceChanged {
+                idx,
+                capital_before,
+                capital_after,
+                pnl_before,
+                pnl_after,
+            });
+        }
+        let before_q = if was {
+            prev.effective_pos_q(idx).unwrap_or(0)
+        } else {
+            0
+        };
+        let after_q = next.effective_pos_q(idx).unwrap_or(0);
+        if before_q != after_q {
+            out.push(SlabEvent::PositionChanged {
+                idx,
+                before_q,
+                after_q,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Holds the last snapshot between notifications.
+#[derive(Default)]
+pub struct SlabWatcher {
+    prev: Option<Slab>,
+}
+
+impl SlabWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> Option<&Slab> {
+        self.prev.as_ref()
+    }
+
+    /// Feed the next account image. The first call only records the
+    /// baseline and returns no events.
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<SlabEvent>, String> {
+        let next = Slab::from_bytes(data);
+        next.engine()?;
+        let events = match &self.prev {
+            Some(prev) => diff(prev, &next)?,
+            None => Vec::new(),
+        };
+        self.prev = Some(next);
+        Ok(events)
+    }
+}
+
+/// Subscribe to `slab` over `ws_url` and call `on_events(slot, events)`
+/// for every notification that changed something. Blocks until the
+/// subscription ends or `on_events` returns `false`.
+#[cfg(feature = "rpc")]
+pub fn subscribe(
+    ws_url: &str,
+    slab: &solana_sdk::pubkey::Pubkey,
+    mut on_events: impl FnMut(u64, &[SlabEvent]) -> bool,
+) -> Result<(), String> {
+    use base64::{engine::general_purpose::STANDARD as B64, Engine};
+    use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+    use solana_client::{pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+    use solana_sdk::commitment_config::CommitmentConfig;
+
+    let (mut sub, rx) = PubsubClient::account_subscribe(
+        ws_url,
+        slab,
+        Some(RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..RpcAccountInfoConfig::default()
+        }),
+    )
+    .map_err(|e| format!("{ws_url}: {e}"))?;
+    let mut watcher = SlabWatcher::new();
+    for notification in rx.iter() {
+        let UiAccountData::Binary(b64, UiAccountEncoding::Base64) = &notification.value.data else {
+            return Err(format!("{slab}: unexpected account encoding"));
+        };
+        let data = B64.decode(b64).map_err(|e| format!("{slab}: {e}"))?;
+        let events = watcher.update(&data)?;
+        if !events.is_empty() && !on_events(notification.context.slot, &events) {
+            break;
+        }
+    }
+    let _ = sub.shutdown();
+    Ok(())
+}
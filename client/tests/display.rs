@@ -0,0 +1,67 @@
+//! Engine ↔ displayed price and side conversions.
+
+use percolator_client::display::{Orientation, Side};
+
+const INVERTED: Orientation = Orientation {
+    invert: true,
+    unit_scale: 0,
+};
+
+#[test]
+fn plain_market_is_identity() {
+    let o = Orientation::default();
+    assert_eq!(o.display_price_e6(138_000_000), Some(138_000_000));
+    assert_eq!(o.engine_price_e6(138_000_000), Some(138_000_000));
+    assert_eq!(o.display_size_q(-5), -5);
+    assert_eq!(o.display_side(5), Side::Long);
+    assert_eq!(o.display_price_e6(0), None);
+
+    let scaled = Orientation {
+        invert: false,
+        unit_scale: 1_000,
+    };
+    assert_eq!(scaled.engine_price_e6(138_000_000), Some(138_000));
+    assert_eq!(scaled.display_price_e6(138_000), Some(138_000_000));
+    assert_eq!(scaled.display_price_e6(u64::MAX), None);
+}
+
+#[test]
+fn inverted_market_flips_price_and_side() {
+    let engine = INVERTED.engine_price_e6(138_000_000).unwrap();
+    assert_eq!(engine, 7_246);
+    // Flooring in both directions: within one engine tick of the feed.
+    let back = INVERTED.display_price_e6(engine).unwrap();
+    let tick =
+        INVERTED.display_price_e6(engine).unwrap() - INVERTED.display_price_e6(engine + 1).unwrap();
+    assert!(back.abs_diff(138_000_000) <= tick, "{back}");
+
+    assert_eq!(INVERTED.display_size_q(1_000_000), -1_000_000);
+    assert_eq!(INVERTED.engine_size_q(-1_000_000), 1_000_000);
+    assert_eq!(INVERTED.display_side(1), Side::Short);
+    assert_eq!(INVERTED.display_side(-1), Side::Long);
+    assert_eq!(INVERTED.display_side(0), Side::Flat);
+    assert_eq!(INVERTED.display_size_q(i128::MIN), i128::MAX);
+}
+
+#[test]
+fn displayed_side_and_price_move_agree_with_engine_pnl() {
+    for o in [Orientation::default(), INVERTED] {
+        for (d0, d1) in [(138_000_000u64, 150_000_000u64), (138_000_000, 120_000_000)] {
+            let (p0, p1) = (
+                o.engine_price_e6(d0).unwrap() as i128,
+                o.engine_price_e6(d1).unwrap() as i128,
+            );
+            for engine_q in [1_000_000i128, -1_000_000] {
+                // Engine mark PnL on a position is q * (p1 - p0).
+                let pnl = engine_q * (p1 - p0);
+                let shown = o.display_size_q(engine_q) * (d1 as i128 - d0 as i128);
+                assert_eq!(
+                    pnl.signum(),
+                    shown.signum(),
+                    "invert={} q={engine_q} {d0}->{d1}",
+                    o.invert
+                );
+            }
+        }
+    }
+}
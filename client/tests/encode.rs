@@ -0,0 +1,618 @@
+//! Round-trip the client encoders through the on-chain decoder.
+
+use percolator_client::{
+    config::{BootstrapConfig, KeeperConfig},
+    ix, tx,
+};
+use percolator_prog::ix::Instruction;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+
+const EXAMPLE: &str = include_str!("../market.example.toml");
+
+#[test]
+fn example_config_encodes_a_decodable_init_market() {
+    let cfg = BootstrapConfig::from_toml(EXAMPLE).expect("example config parses");
+    let admin = Pubkey::new_unique();
+    let params = cfg.init_market_params(admin).unwrap();
+    let data = ix::encode_init_market(&params);
+    match Instruction::decode(&data) {
+        Ok(Instruction::InitMarket(args)) => {
+            assert_eq!(args.admin, admin);
+            assert_eq!(args.collateral_mint, params.collateral_mint);
+            assert_eq!(args.index_feed_id, params.index_feed_id);
+            assert_eq!(args.conf_filter_bps, 200);
+            assert_eq!(args.risk_params.maintenance_margin_bps, 500);
+            assert_eq!(args.new_account_fee, 1_000_000);
+            assert_eq!(args.permissionless_resolve_stale_slots, 216_000);
+            assert_eq!(args.oracle_leg_count, 1);
+            assert_eq!(args.metadata.symbol_str(), "SOL-PERP");
+            assert_eq!(args.metadata.base_decimals, 9);
+            assert_eq!(Some(args.metadata), params.metadata);
+        }
+        other => panic!("expected InitMarket, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(cfg.oracle_accounts().unwrap().len(), 1);
+
+    let m = params.metadata.unwrap();
+    match Instruction::decode(&ix::encode_set_market_metadata(&m)) {
+        Ok(Instruction::SetMarketMetadata { metadata }) => assert_eq!(metadata, m),
+        other => panic!("expected SetMarketMetadata, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn keeper_crank_candidates_decode() {
+    let data = ix::encode_keeper_crank(
+        ix::CRANK_PERMISSIONLESS,
+        &[(3, ix::CANDIDATE_FULL_CLOSE), (9, ix::CANDIDATE_TOUCH_ONLY)],
+    );
+    match Instruction::decode(&data) {
+        Ok(Instruction::KeeperCrank {
+            caller_idx,
+            candidates,
+        }) => {
+            assert_eq!(caller_idx, u16::MAX);
+            assert_eq!(candidates.len(), 2);
+            assert_eq!(candidates[0].0, 3);
+            assert!(candidates[0].1.is_some());
+            assert!(candidates[1].1.is_none());
+        }
+        other => panic!("expected KeeperCrank, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn example_keeper_config_parses() {
+    let cfg = KeeperConfig::from_toml(include_str!("../keeper.example.toml"))
+        .expect("keeper example parses");
+    assert_eq!(cfg.slabs.len(), 2);
+    assert_eq!(cfg.slabs[0].oracles.len(), 1);
+    assert!(cfg.slabs[1].oracles.is_empty());
+    assert_eq!(cfg.liquidation_buffer_bps, 2_000);
+    assert_eq!(cfg.crank_due_lag(), 8);
+    assert_eq!(cfg.priority_fee_micro_lamports, 1_000);
+
+    // The interval never exceeds the accrual envelope minus the margin.
+    let lax = KeeperConfig::from_toml(
+        "crank_interval_slots = 1000\n[[slab]]\naddress = \"11111111111111111111111111111111\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        lax.crank_due_lag(),
+        percolator_prog::constants::MAX_ACCRUAL_DT_SLOTS - 2
+    );
+}
+
+#[test]
+fn send_policy_defaults_and_backoff_doubles_to_the_cap() {
+    let cfg = KeeperConfig::from_toml(include_str!("../keeper.example.toml")).unwrap();
+    assert_eq!(cfg.send, tx::SendPolicy::default());
+    let lax = KeeperConfig::from_toml(
+        "[send]\nskip_preflight = true\n[[slab]]\naddress = \"11111111111111111111111111111111\"\n",
+    )
+    .unwrap();
+    assert!(lax.send.skip_preflight);
+    assert_eq!(
+        lax.send.max_attempts,
+        tx::SendPolicy::default().max_attempts
+    );
+
+    let ms = |attempt| cfg.send.backoff(attempt).as_millis();
+    assert_eq!((ms(1), ms(2), ms(3), ms(5)), (500, 1_000, 2_000, 8_000));
+    assert_eq!(ms(u32::MAX), 8_000);
+}
+
+#[test]
+fn push_hyperp_mark_decodes() {
+    let data = ix::encode_push_hyperp_mark(123_456_789, 1_700_000_000);
+    match Instruction::decode(&data) {
+        Ok(Instruction::PushHyperpMark {
+            price_e6,
+            timestamp,
+        }) => {
+            assert_eq!(price_e6, 123_456_789);
+            assert_eq!(timestamp, 1_700_000_000);
+        }
+        other => panic!("expected PushHyperpMark, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn hyperp_pusher_add_and_remove_decode() {
+    let pusher = Pubkey::new_unique();
+    match Instruction::decode(&ix::encode_hyperp_pusher(true, &pusher)) {
+        Ok(Instruction::AddHyperpPusher { pusher: p }) => assert_eq!(p, pusher),
+        other => panic!("expected AddHyperpPusher, got {:?}", other.map(|_| ())),
+    }
+    match Instruction::decode(&ix::encode_hyperp_pusher(false, &pusher)) {
+        Ok(Instruction::RemoveHyperpPusher { pusher: p }) => assert_eq!(p, pusher),
+        other => panic!("expected RemoveHyperpPusher, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn deposit_with_permit_decodes_and_signs_the_payload() {
+    let data = ix::encode_deposit_with_permit(3, 1_000_000, 7, 1_700_000_000);
+    match Instruction::decode(&data) {
+        Ok(Instruction::DepositWithPermit {
+            user_idx,
+            amount,
+            nonce,
+            expiry,
+            allowlist_proof,
+        }) => {
+            assert_eq!(
+                (user_idx, amount, nonce, expiry),
+                (3, 1_000_000, 7, 1_700_000_000)
+            );
+            assert!(allowlist_proof.is_empty());
+        }
+        other => panic!("expected DepositWithPermit, got {:?}", other.map(|_| ())),
+    }
+
+    let owner = Keypair::new();
+    let slab = Pubkey::new_unique();
+    let msg = ix::permit_deposit_payload(&slab, 3, 1_000_000, 7, 1_700_000_000);
+    let verify = ix::ed25519_verify(&owner.pubkey(), &owner.sign_message(&msg), &msg);
+    let (pk, signed) = percolator_prog::policy::ed25519_single_sig_payload(&verify.data).unwrap();
+    assert_eq!(pk, owner.pubkey().to_bytes());
+    assert_eq!(signed, msg.as_slice());
+}
+
+#[test]
+fn trade_nocpi_decodes() {
+    let data = ix::encode_trade_nocpi(&ix::TradeNoCpiArgs {
+        lp_idx: 1,
+        user_idx: 4,
+        size: -1_000_000,
+        exec_price_e6: 2_500_000,
+        flags: 0,
+        ..Default::default()
+    });
+    match Instruction::decode(&data) {
+        Ok(Instruction::TradeNoCpi {
+            lp_idx,
+            user_idx,
+            size,
+            exec_price_e6,
+            flags,
+            client_order_id,
+            client_tag,
+        }) => {
+            assert_eq!((lp_idx, user_idx), (1, 4));
+            assert_eq!(size, -1_000_000);
+            assert_eq!(exec_price_e6, 2_500_000);
+            assert_eq!((flags, client_order_id), (0, 0));
+            assert_eq!(client_tag, [0; 16]);
+        }
+        other => panic!("expected TradeNoCpi, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn compute_budget_covers_published_estimates() {
+    use percolator_client::budget;
+    use solana_sdk::compute_budget;
+
+    let program_id = Pubkey::new_unique();
+    let (user, lp, slab) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let trade = ix::trade_nocpi(
+        &program_id,
+        &user,
+        &lp,
+        &slab,
+        &[Pubkey::new_unique()],
+        &ix::TradeNoCpiArgs {
+            size: 1,
+            ..Default::default()
+        },
+    );
+    let trade_cu = budget::cu_estimate_for_tag(ix::TAG_TRADE_NOCPI);
+    assert_eq!(budget::estimate_cu(&program_id, &[trade.clone()]), trade_cu);
+    // Foreign instructions get a flat allowance; the sum caps at 1.4M.
+    let transfer = solana_sdk::system_instruction::transfer(&user, &lp, 1);
+    assert_eq!(
+        budget::estimate_cu(&program_id, &[trade.clone(), transfer]),
+        trade_cu + budget::FOREIGN_IX_CU
+    );
+    let crank = ix::keeper_crank(&program_id, &user, &slab, &slab, &[]);
+    assert_eq!(
+        budget::estimate_cu(&program_id, &[crank.clone(), crank]),
+        1_400_000
+    );
+
+    let ixs = budget::with_compute_budget(&program_id, &[trade.clone()], 5_000);
+    assert_eq!(ixs.len(), 3);
+    assert!(ixs[..2].iter().all(|i| i.program_id == compute_budget::ID));
+    assert_eq!(ixs[2], trade);
+    // Budget instructions already present are not counted again.
+    assert_eq!(budget::estimate_cu(&program_id, &ixs), trade_cu);
+    // No price instruction without a fee.
+    assert_eq!(budget::compute_budget_ixs(10_000, 0).len(), 1);
+}
+
+#[test]
+fn keeper_jito_section_is_validated() {
+    let base = "[[slab]]\naddress = \"11111111111111111111111111111111\"\n";
+    let jito = |tip: u64| {
+        format!(
+            "[jito]\nurl = \"http://127.0.0.1:1\"\ntip_account = \"{}\"\ntip_lamports = {tip}\n{base}",
+            Pubkey::new_unique()
+        )
+    };
+    assert!(KeeperConfig::from_toml(base).unwrap().jito.is_none());
+    let cfg = KeeperConfig::from_toml(&jito(10_000)).unwrap();
+    let j = cfg.jito.expect("jito section");
+    assert_eq!(j.tip_lamports, 10_000);
+    assert!(!j.fallback_to_rpc);
+    // Below the block engine's minimum tip.
+    assert!(KeeperConfig::from_toml(&jito(999)).is_err());
+    assert!(KeeperConfig::from_toml(
+        &jito(10_000).replace("tip_account = \"", "tip_account = \"x")
+    )
+    .is_err());
+}
+
+#[cfg(feature = "keeper")]
+#[test]
+fn jito_bundle_params_are_base64_wire_transactions() {
+    use percolator_client::jito;
+    use solana_sdk::{
+        hash::Hash,
+        signature::{Keypair, Signer},
+        transaction::Transaction,
+    };
+
+    let payer = Keypair::new();
+    let cfg = percolator_client::config::JitoConfig {
+        url: String::new(),
+        tip_account: Pubkey::new_unique().to_string(),
+        tip_lamports: 5_000,
+        fallback_to_rpc: false,
+    };
+    let tip = jito::tip_ix(&payer.pubkey(), &cfg).unwrap();
+    assert_eq!(tip.accounts[1].pubkey, cfg.tip_account().unwrap());
+    let tx = Transaction::new_signed_with_payer(
+        &[tip],
+        Some(&payer.pubkey()),
+        &[&payer],
+        Hash::default(),
+    );
+    let params = jito::send_bundle_params(&[tx.clone()]).unwrap();
+    assert_eq!(params[1]["encoding"], "base64");
+    let encoded = params[0][0].as_str().unwrap();
+    let wire = bincode::serialize(&tx).unwrap();
+    use base64::Engine;
+    assert_eq!(
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap(),
+        wire
+    );
+}
+
+#[test]
+fn client_tag_tails_decode() {
+    let tag = *b"acme-frontend-01";
+    let data = ix::encode_trade_nocpi(&ix::TradeNoCpiArgs {
+        size: 1,
+        client_tag: tag,
+        ..Default::default()
+    });
+    match Instruction::decode(&data) {
+        Ok(Instruction::TradeNoCpi {
+            client_order_id,
+            client_tag,
+            ..
+        }) => assert_eq!((client_order_id, client_tag), (0, tag)),
+        other => panic!("expected TradeNoCpi, got {:?}", other.map(|_| ())),
+    }
+    match Instruction::decode(&ix::encode_deposit_tagged(7, 1_000, &tag)) {
+        Ok(Instruction::DepositCollateral {
+            allowlist_proof,
+            client_tag,
+            ..
+        }) => {
+            assert!(allowlist_proof.is_empty());
+            assert_eq!(client_tag, tag);
+        }
+        other => panic!("expected DepositCollateral, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn deposit_and_withdraw_decode() {
+    match Instruction::decode(&ix::encode_deposit(7, 1_000)) {
+        Ok(Instruction::DepositCollateral {
+            user_idx,
+            amount,
+            allowlist_proof,
+            client_tag,
+        }) => {
+            assert_eq!((user_idx, amount), (7, 1_000));
+            assert!(allowlist_proof.is_empty());
+            assert_eq!(client_tag, [0; 16]);
+        }
+        other => panic!("expected DepositCollateral, got {:?}", other.map(|_| ())),
+    }
+    for nonce in [None, Some(42)] {
+        match Instruction::decode(&ix::encode_withdraw(7, 1_000, nonce)) {
+            Ok(Instruction::WithdrawCollateral {
+                user_idx,
+                amount,
+                nonce: n,
+            }) => assert_eq!((user_idx, amount, n), (7, 1_000, nonce)),
+            other => panic!("expected WithdrawCollateral, got {:?}", other.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn ata_flows_create_then_use_the_same_account() {
+    use percolator_client::ata;
+    let (program_id, payer, owner, slab, mint, vault) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let user_token = ata::associated_token_address(&owner, &mint);
+
+    let ixs = ata::deposit_from_ata(&program_id, &payer, &owner, &slab, &mint, &vault, 3, 500);
+    assert_eq!(ixs.len(), 2);
+    assert_eq!(ixs[0].program_id, ata::ATA_PROGRAM_ID);
+    assert_eq!(ixs[0].data, vec![1]);
+    assert_eq!(ixs[0].accounts[0].pubkey, payer);
+    assert_eq!(ixs[0].accounts[1].pubkey, user_token);
+    assert_eq!(ixs[1].accounts[2].pubkey, user_token);
+
+    let oracle = Pubkey::new_unique();
+    let ixs = ata::withdraw_to_ata(
+        &program_id,
+        &payer,
+        &owner,
+        &slab,
+        &mint,
+        &vault,
+        &[oracle],
+        3,
+        500,
+        None,
+    );
+    assert_eq!(ixs[0].accounts[1].pubkey, user_token);
+    assert_eq!(ixs[1].accounts[3].pubkey, user_token);
+    assert_eq!(
+        ixs[1].accounts[4].pubkey,
+        percolator_prog::accounts::derive_vault_authority(&program_id, &slab).0
+    );
+    assert_eq!(ixs[1].accounts.last().unwrap().pubkey, oracle);
+
+    let ixs = ata::deposit_sol(&program_id, &owner, &slab, &vault, 3, 500).unwrap();
+    let wsol = ata::associated_token_address(&owner, &spl_token::native_mint::ID);
+    assert_eq!(ixs.len(), 4);
+    assert_eq!(ixs[0].accounts[1].pubkey, wsol);
+    assert_eq!(ixs[3].accounts[2].pubkey, wsol);
+}
+
+#[test]
+fn register_market_targets_the_seed_derived_pointer() {
+    let program_id = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let feed = [5u8; 32];
+    let ix = ix::register_market(
+        &program_id,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &mint,
+        &feed,
+        3,
+    );
+    assert_eq!(
+        ix.accounts[2].pubkey,
+        ix::market_address(&program_id, &mint, &feed, 3)
+    );
+    assert_ne!(
+        ix.accounts[2].pubkey,
+        ix::market_address(&program_id, &mint, &feed, 4)
+    );
+    match Instruction::decode(&ix.data) {
+        Ok(Instruction::RegisterMarket { index }) => assert_eq!(index, 3),
+        other => panic!("expected RegisterMarket, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn init_market_passes_the_vault_ata_and_its_create_accounts() {
+    let cfg = BootstrapConfig::from_toml(EXAMPLE).unwrap();
+    let params = cfg.init_market_params(Pubkey::new_unique()).unwrap();
+    let program_id = Pubkey::new_unique();
+    let slab = Pubkey::new_unique();
+    let oracles = cfg.oracle_accounts().unwrap();
+    let ix = ix::init_market(&program_id, &slab, &oracles, &params);
+    assert_eq!(ix.accounts.len(), 5 + oracles.len() + 4);
+    assert_eq!(
+        ix.accounts[3].pubkey,
+        ix::vault_address(&program_id, &slab, &params.collateral_mint)
+    );
+    assert!(ix.accounts[3].is_writable);
+    let (vault_authority, _) =
+        percolator_prog::accounts::derive_vault_authority(&program_id, &slab);
+    assert_eq!(ix.accounts[5 + oracles.len()].pubkey, vault_authority);
+    assert_eq!(
+        ix.accounts.last().unwrap().pubkey,
+        percolator_prog::accounts::ASSOCIATED_TOKEN_PROGRAM_ID
+    );
+}
+
+#[test]
+fn init_market_with_matcher_ctx_appends_a_signing_context() {
+    let cfg = BootstrapConfig::from_toml(EXAMPLE).unwrap();
+    let params = cfg.init_market_params(Pubkey::new_unique()).unwrap();
+    let oracles = cfg.oracle_accounts().unwrap();
+    let (program_id, slab) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let (matcher, ctx) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let plain = ix::init_market(&program_id, &slab, &oracles, &params);
+    let ix =
+        ix::init_market_with_matcher_ctx(&program_id, &slab, &oracles, &params, &matcher, &ctx);
+    assert_eq!(ix.accounts[..plain.accounts.len()], plain.accounts[..]);
+    assert_eq!(ix.accounts.len(), plain.accounts.len() + 2);
+    let last = ix.accounts.last().unwrap();
+    assert!(last.pubkey == ctx && last.is_signer && last.is_writable);
+    assert_eq!(ix.data, plain.data);
+}
+
+#[test]
+fn with_rent_payer_moves_rent_off_the_admin() {
+    let cfg = BootstrapConfig::from_toml(EXAMPLE).unwrap();
+    let params = cfg.init_market_params(Pubkey::new_unique()).unwrap();
+    let oracles = cfg.oracle_accounts().unwrap();
+    let (program_id, slab, payer) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let plain = ix::init_market(&program_id, &slab, &oracles, &params);
+    let ix = ix::with_rent_payer(plain.clone(), &payer);
+    assert_eq!(ix.accounts.len(), plain.accounts.len() + 1);
+    assert!(ix.accounts[0].is_signer && !ix.accounts[0].is_writable);
+    let last = ix.accounts.last().unwrap();
+    assert!(last.pubkey == payer && last.is_signer && last.is_writable);
+}
+
+#[test]
+fn sponsored_transactions_put_the_relayer_first() {
+    use solana_sdk::{
+        hash::Hash,
+        signature::{Keypair, Signer},
+    };
+
+    let (program_id, slab, vault) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let (owner, relayer) = (Keypair::new(), Keypair::new());
+    let token = Pubkey::new_unique();
+    let deposit = ix::deposit(&program_id, &owner.pubkey(), &slab, &token, &vault, 3, 100);
+    assert!(deposit.accounts[0].is_signer && !deposit.accounts[0].is_writable);
+
+    let tx = tx::sponsored(&[deposit.clone()], &relayer.pubkey(), Hash::default());
+    let msg = &tx.message;
+    assert_eq!(msg.account_keys[0], relayer.pubkey());
+    let i = msg
+        .account_keys
+        .iter()
+        .position(|k| *k == owner.pubkey())
+        .unwrap();
+    assert!(msg.is_signer(i) && !msg.is_writable(i));
+
+    // The owner may also pay for itself; it signs once.
+    let tx = tx::signed(&[deposit], &owner, &[&owner], Hash::default()).unwrap();
+    assert_eq!(tx.signatures.len(), 1);
+    assert!(tx::signed(&[], &relayer, &[&owner], Hash::default()).is_err());
+}
+
+#[test]
+fn durable_transactions_advance_the_nonce_first() {
+    use solana_sdk::{
+        account::Account,
+        hash::Hash,
+        nonce::state::{Data, DurableNonce, State, Versions},
+        signature::{Keypair, Signer},
+        system_program,
+    };
+
+    let keeper = Keypair::new();
+    let stored = Hash::new_unique();
+    let data = Data::new(
+        keeper.pubkey(),
+        DurableNonce::from_blockhash(&stored),
+        5_000,
+    );
+    let state = Versions::new(State::Initialized(data.clone()));
+    let account = Account::new_data(1_500_000, &state, &system_program::ID).unwrap();
+    assert_eq!(tx::nonce_data(&account).unwrap(), data);
+    let uninit = Account::new_data(
+        1_500_000,
+        &Versions::new(State::Uninitialized),
+        &system_program::ID,
+    )
+    .unwrap();
+    assert!(tx::nonce_data(&uninit).is_err());
+    let foreign = Account {
+        owner: Pubkey::new_unique(),
+        ..account
+    };
+    assert!(tx::nonce_data(&foreign).is_err());
+
+    let nonce = tx::Nonce {
+        account: Pubkey::new_unique(),
+        authority: keeper.pubkey(),
+    };
+    let (program_id, slab) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let crank = ix::keeper_crank(
+        &program_id,
+        &keeper.pubkey(),
+        &slab,
+        &Pubkey::new_unique(),
+        &[],
+    );
+    let tx = tx::durable(&[crank], &keeper, &[&keeper], &nonce, data.blockhash()).unwrap();
+    let msg = &tx.message;
+    assert_eq!(msg.recent_blockhash, stored);
+    assert_eq!(
+        msg.account_keys[msg.instructions[0].program_id_index as usize],
+        system_program::ID
+    );
+    assert_eq!(
+        msg.account_keys[msg.instructions[1].program_id_index as usize],
+        program_id
+    );
+    tx.verify().unwrap();
+}
+
+#[test]
+fn init_slab_chunks_cover_the_slab() {
+    use percolator_prog::constants::HEADER_LEN;
+
+    let (program_id, admin, slab) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let chunks = ix::init_slab_chunks(&program_id, &admin, &slab, HEADER_LEN + 1000, 300);
+    assert_eq!(chunks.len(), 4);
+    match Instruction::decode(&chunks[0].data) {
+        Ok(Instruction::InitSlab { max_bytes }) => assert_eq!(max_bytes, 300),
+        other => panic!("expected InitSlab, got {:?}", other.map(|_| ())),
+    }
+    assert!(chunks[0].accounts[0].is_signer && chunks[0].accounts[1].is_writable);
+}
+
+#[test]
+fn migrate_slab_steps_reach_the_current_length() {
+    use percolator_prog::state::offsets::sbf::{SLAB_LEN, SLAB_LEN_V0};
+
+    let (program_id, payer, slab) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let steps = ix::migrate_slab_steps(&program_id, &payer, &slab, SLAB_LEN_V0);
+    assert_eq!(steps.len(), (SLAB_LEN - SLAB_LEN_V0).div_ceil(10_240));
+    assert!(matches!(
+        Instruction::decode(&steps[0].data),
+        Ok(Instruction::MigrateSlab)
+    ));
+    assert!(steps[0].accounts[0].is_signer && steps[0].accounts[1].is_writable);
+    assert!(ix::migrate_slab_steps(&program_id, &payer, &slab, SLAB_LEN).is_empty());
+}
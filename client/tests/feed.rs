@@ -0,0 +1,166 @@
+//! Normalized feed from transaction and slab updates.
+
+use percolator_client::{
+    feed::{AccountUpdate, FeedDecoder, FeedRecord, TxInstruction, TxUpdate},
+    ix,
+};
+use percolator_prog::{constants::SLAB_LEN, ix::TradeResult, zc};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+fn tx(program_id: Pubkey, sig: Signature, slot: u64, data: Vec<u8>) -> TxUpdate {
+    TxUpdate {
+        slot,
+        signature: sig,
+        success: true,
+        instructions: vec![TxInstruction {
+            program_id,
+            accounts: vec![Pubkey::new_unique()],
+            data,
+        }],
+        return_data: None,
+    }
+}
+
+#[test]
+fn trade_return_data_becomes_a_fill() {
+    let program_id = Pubkey::new_unique();
+    let mut d = FeedDecoder::new(program_id);
+    let args = ix::TradeNoCpiArgs {
+        lp_idx: 2,
+        user_idx: 7,
+        size: 500,
+        ..Default::default()
+    };
+    let mut t = tx(
+        program_id,
+        Signature::new_unique(),
+        10,
+        ix::encode_trade_nocpi(&args),
+    );
+    let result = TradeResult {
+        exec_size: 500,
+        exec_price_e6: 1_000_100,
+        oracle_price_e6: 1_000_000,
+        fee_bps: 5,
+        fee_paid: 25,
+    };
+    t.return_data = Some((program_id, result.encode().to_vec()));
+    match d.on_transaction(&t).as_slice() {
+        [FeedRecord::Fill {
+            lp_idx: 2,
+            user_idx: 7,
+            size_q: 500,
+            exec_price_e6: 1_000_100,
+            fee_paid: 25,
+            ..
+        }] => {}
+        other => panic!("expected one fill, got {other:?}"),
+    }
+    // Another program's return data is not ours to decode.
+    t.return_data = Some((Pubkey::new_unique(), result.encode().to_vec()));
+    assert!(d.on_transaction(&t).is_empty());
+}
+
+#[test]
+fn fill_carries_the_client_tag() {
+    let program_id = Pubkey::new_unique();
+    let mut d = FeedDecoder::new(program_id);
+    let args = ix::TradeNoCpiArgs {
+        size: 500,
+        client_tag: *b"acme\0\0\0\0\0\0\0\0\0\0\0\x01",
+        ..Default::default()
+    };
+    let mut t = tx(
+        program_id,
+        Signature::new_unique(),
+        10,
+        ix::encode_trade_nocpi(&args),
+    );
+    let result = TradeResult {
+        exec_size: 500,
+        ..Default::default()
+    };
+    t.return_data = Some((program_id, result.encode().to_vec()));
+    match d.on_transaction(&t).as_slice() {
+        [FeedRecord::Fill { client_tag, .. }] => assert_eq!(
+            client_tag.as_deref(),
+            Some("61636d65000000000000000000000001")
+        ),
+        other => panic!("expected one fill, got {other:?}"),
+    }
+}
+
+#[test]
+fn crank_position_shrinks_pair_in_either_order() {
+    let program_id = Pubkey::new_unique();
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    {
+        let e = zc::engine_mut(data).unwrap();
+        e.params.max_accounts = 8;
+        e.adl_mult_long = 1;
+        e.used[0] = 0b11;
+        for i in 0..2 {
+            e.accounts[i].position_basis_q = 100;
+            e.accounts[i].adl_a_basis = 1;
+        }
+    }
+    let mut d = FeedDecoder::new(program_id);
+    fn update(data: &[u8], sig: Signature) -> AccountUpdate<'_> {
+        AccountUpdate {
+            slot: 20,
+            data,
+            txn_signature: Some(sig),
+        }
+    }
+    assert!(d
+        .on_account(&update(data, Signature::new_unique()))
+        .unwrap()
+        .is_empty());
+
+    let crank = ix::encode_keeper_crank(ix::CRANK_PERMISSIONLESS, &[]);
+    // Account update first, crank transaction second.
+    let sig_a = Signature::new_unique();
+    zc::engine_mut(data).unwrap().accounts[0].position_basis_q = 0;
+    assert!(d.on_account(&update(data, sig_a)).unwrap().is_empty());
+    match d
+        .on_transaction(&tx(program_id, sig_a, 20, crank.clone()))
+        .as_slice()
+    {
+        [FeedRecord::Liquidation {
+            idx: 0,
+            before_q: 100,
+            after_q: 0,
+            ..
+        }] => {}
+        other => panic!("expected liquidation of 0, got {other:?}"),
+    }
+
+    // Transaction first, then the account write.
+    let sig_b = Signature::new_unique();
+    assert!(d
+        .on_transaction(&tx(program_id, sig_b, 21, crank))
+        .is_empty());
+    zc::engine_mut(data).unwrap().accounts[1].position_basis_q = 40;
+    match d.on_account(&update(data, sig_b)).unwrap().as_slice() {
+        [FeedRecord::Liquidation {
+            idx: 1,
+            before_q: 100,
+            after_q: 40,
+            ..
+        }] => {}
+        other => panic!("expected partial liquidation of 1, got {other:?}"),
+    }
+
+    // A trade that reduces a position is not a liquidation.
+    let sig_c = Signature::new_unique();
+    let trade = ix::encode_trade_nocpi(&ix::TradeNoCpiArgs {
+        size: -10,
+        ..Default::default()
+    });
+    assert!(d
+        .on_transaction(&tx(program_id, sig_c, 22, trade))
+        .is_empty());
+    zc::engine_mut(data).unwrap().accounts[1].position_basis_q = 30;
+    assert!(d.on_account(&update(data, sig_c)).unwrap().is_empty());
+}
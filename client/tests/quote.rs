@@ -0,0 +1,69 @@
+//! Local vAMM quotes from raw matcher context bytes.
+
+use percolator_client::quote::{quote, Quote};
+use percolator_prog::matcher_abi::{VAMM_MAGIC, VAMM_MODE_IMPACT, VAMM_MODE_PASSIVE};
+
+const ORACLE: u64 = 100_000_000;
+const UNIT: i128 = percolator::POS_SCALE as i128;
+
+/// A 320-byte context as the matcher lays it out after InitVamm.
+fn ctx(mode: u8, max_fill_abs: u128, inventory: i128, max_inventory_abs: u128) -> Vec<u8> {
+    let mut d = vec![0u8; 320];
+    d[64..72].copy_from_slice(&VAMM_MAGIC.to_le_bytes());
+    d[76] = mode;
+    d[112..116].copy_from_slice(&5u32.to_le_bytes()); // fee
+    d[116..120].copy_from_slice(&10u32.to_le_bytes()); // spread
+    d[120..124].copy_from_slice(&200u32.to_le_bytes()); // max total
+    d[124..128].copy_from_slice(&100u32.to_le_bytes()); // impact k
+    d[128..144].copy_from_slice(&10_000_000_000u128.to_le_bytes());
+    d[144..160].copy_from_slice(&max_fill_abs.to_le_bytes());
+    d[160..176].copy_from_slice(&inventory.to_le_bytes());
+    d[192..208].copy_from_slice(&max_inventory_abs.to_le_bytes());
+    d
+}
+
+#[test]
+fn impact_mode_prices_on_the_curve() {
+    let q = quote(&ctx(VAMM_MODE_IMPACT, 0, 0, 0), ORACLE, 10 * UNIT).unwrap();
+    assert_eq!(
+        q,
+        Quote {
+            exec_price_e6: 100_250_000,
+            fill_size: 10 * UNIT,
+            total_bps: 25,
+            matcher_fee_bps: 5,
+            fill_notional_e6: 1_002_500_000,
+        }
+    );
+    let q = quote(&ctx(VAMM_MODE_IMPACT, 0, 0, 0), ORACLE, -10 * UNIT).unwrap();
+    assert_eq!(q.exec_price_e6, 99_750_000);
+}
+
+#[test]
+fn passive_mode_ignores_impact() {
+    let q = quote(&ctx(VAMM_MODE_PASSIVE, 0, 0, 0), ORACLE, 10 * UNIT).unwrap();
+    assert_eq!(q.total_bps, 15);
+    assert_eq!(q.exec_price_e6, 100_150_000);
+}
+
+#[test]
+fn fill_and_inventory_caps_clamp_the_size() {
+    let q = quote(
+        &ctx(VAMM_MODE_IMPACT, 4 * UNIT as u128, 0, 0),
+        ORACLE,
+        10 * UNIT,
+    )
+    .unwrap();
+    assert_eq!(q.fill_size, 4 * UNIT);
+
+    // LP already at its short cap: a buyer gets nothing.
+    let full = ctx(VAMM_MODE_IMPACT, 0, -5 * UNIT, 5 * UNIT as u128);
+    assert_eq!(quote(&full, ORACLE, UNIT).unwrap(), Quote::default());
+    assert_eq!(quote(&full, ORACLE, -UNIT).unwrap().fill_size, -UNIT);
+}
+
+#[test]
+fn rejects_uninitialized_context() {
+    assert!(quote(&[0u8; 320], ORACLE, UNIT).is_err());
+    assert!(quote(&ctx(VAMM_MODE_IMPACT, 0, 0, 0)[..200], ORACLE, UNIT).is_err());
+}
@@ -0,0 +1,80 @@
+//! Replay fixture format and final-state comparison.
+
+use percolator_client::replay::{diff_slab, ReplayError, ReplayFixture, SlabDiff};
+
+const FIXTURE: &str = r#"{
+    "program_id": "Perco1ator111111111111111111111111111111111",
+    "slab": "11111111111111111111111111111111",
+    "accounts": [],
+    "steps": [
+        {
+            "slot": 1000,
+            "unix_timestamp": 1700000000,
+            "accounts": [
+                {
+                    "address": "SysvarC1ock11111111111111111111111111111111",
+                    "owner": "11111111111111111111111111111111",
+                    "lamports": 1,
+                    "data": "AAEC"
+                }
+            ],
+            "instructions": [
+                {
+                    "program_id": "Perco1ator111111111111111111111111111111111",
+                    "accounts": [
+                        {
+                            "pubkey": "11111111111111111111111111111111",
+                            "is_signer": false,
+                            "is_writable": true
+                        }
+                    ],
+                    "data": "BQ=="
+                }
+            ],
+            "signature": "deadbeef"
+        }
+    ]
+}"#;
+
+#[test]
+fn fixture_parses_with_defaults() {
+    let f = ReplayFixture::from_json(FIXTURE).unwrap();
+    assert_eq!(f.steps.len(), 1);
+    assert!(f.steps[0].success, "recorded steps default to success");
+    assert_eq!(f.steps[0].accounts[0].data, "AAEC");
+    assert!(f.expected_slab.is_none());
+
+    let again = ReplayFixture::from_json(&serde_json::to_string(&f).unwrap()).unwrap();
+    assert_eq!(again.steps[0].instructions[0].data, "BQ==");
+    assert!(ReplayFixture::from_json(r#"{"program_id": "x"}"#).is_err());
+}
+
+#[test]
+fn fixture_without_slab_account_is_rejected() {
+    let f = ReplayFixture::from_json(FIXTURE).unwrap();
+    assert!(matches!(f.run(&[]), Err(ReplayError::Fixture(_))));
+}
+
+#[test]
+fn slab_diff_reports_first_divergence() {
+    assert_eq!(diff_slab(&[1, 2, 3], &[1, 2, 3]), None);
+    assert_eq!(
+        diff_slab(&[1, 9, 3, 9], &[1, 2, 3, 4]),
+        Some(SlabDiff {
+            first_offset: 1,
+            differing_bytes: 2,
+            len_replayed: 4,
+            len_expected: 4,
+        })
+    );
+    // Length mismatch with an equal prefix points at the shorter end.
+    assert_eq!(
+        diff_slab(&[1, 2], &[1, 2, 3]),
+        Some(SlabDiff {
+            first_offset: 2,
+            differing_bytes: 1,
+            len_replayed: 2,
+            len_expected: 3,
+        })
+    );
+}
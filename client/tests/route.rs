@@ -0,0 +1,51 @@
+//! Best-price-first splits across LP quotes.
+
+use percolator_client::route::{plan, RoutePlan, RouteQuote};
+
+fn q(lp_idx: u16, price_e6: u64, max_size_q: u128) -> RouteQuote {
+    RouteQuote {
+        lp_idx,
+        price_e6,
+        max_size_q,
+    }
+}
+
+#[test]
+fn buy_takes_cheapest_first() {
+    let quotes = [
+        q(3, 101_000_000, 50),
+        q(1, 100_000_000, 40),
+        q(2, 102_000_000, 100),
+    ];
+    assert_eq!(
+        plan(&quotes, 120).unwrap(),
+        RoutePlan {
+            legs: vec![50, 40, 30],
+            filled: 120,
+        }
+    );
+}
+
+#[test]
+fn sell_takes_richest_first_and_reports_shortfall() {
+    let quotes = [q(1, 99_000_000, 10), q(2, 100_000_000, 20)];
+    let p = plan(&quotes, -50).unwrap();
+    assert_eq!(p.legs, vec![-10, -20]);
+    assert_eq!(p.filled, 30, "shallow book fills partially");
+}
+
+#[test]
+fn equal_prices_keep_input_order() {
+    let quotes = [q(7, 100, 10), q(8, 100, 10)];
+    assert_eq!(plan(&quotes, 15).unwrap().legs, vec![10, 5]);
+}
+
+#[test]
+fn rejects_malformed_quotes() {
+    assert!(plan(&[q(1, 100, 10), q(1, 101, 10)], 5).is_err());
+    assert!(plan(&[q(1, 0, 10)], 5).is_err());
+    let too_many: Vec<_> = (1..=5).map(|i| q(i, 100, 1)).collect();
+    assert!(plan(&too_many, 5).is_err());
+    assert!(plan(&[q(1, 100, 10)], 0).is_err());
+    assert!(plan(&[q(1, 100, 10)], i128::MIN).is_err());
+}
@@ -0,0 +1,266 @@
+//! Off-chain slab views over raw account bytes.
+
+use percolator_client::{inspect, slab::Slab};
+use percolator_prog::constants::SLAB_LEN;
+
+#[test]
+fn empty_slab_renders_without_accounts() {
+    let slab = Slab::from_bytes(&vec![0u8; SLAB_LEN]);
+    assert!(slab.used_indices().unwrap().is_empty());
+    assert!(slab.liquidation_candidates(0).unwrap().is_empty());
+    let report = inspect::render(&slab).expect("zeroed slab renders");
+    for section in [
+        "== header ==",
+        "== config ==",
+        "== engine ==",
+        "== accounts ==",
+    ] {
+        assert!(report.contains(section), "missing {section}");
+    }
+    assert!(report.contains("oracle                   hyperp"));
+}
+
+#[test]
+fn truncated_slab_is_rejected() {
+    let slab = Slab::from_bytes(&vec![0u8; SLAB_LEN - 1]);
+    assert!(slab.engine().is_err());
+    assert!(inspect::render(&slab).is_err());
+}
+
+#[test]
+fn history_reads_from_the_slab_tail() {
+    use percolator_prog::state;
+    let mut data = vec![0u8; SLAB_LEN];
+    assert!(Slab::from_bytes(&data).history().unwrap().is_empty());
+    for slot in [10, 20] {
+        state::push_history_sample(
+            &mut data,
+            &state::HistorySample {
+                slot,
+                ..Default::default()
+            },
+        );
+    }
+    let slots: Vec<u64> = Slab::from_bytes(&data)
+        .history()
+        .unwrap()
+        .iter()
+        .map(|s| s.slot)
+        .collect();
+    assert_eq!(slots, vec![10, 20]);
+    assert!(Slab::from_bytes(&data[..64]).history().is_err());
+}
+
+#[test]
+fn trade_builder_resolves_indices_by_owner() {
+    use percolator_client::{ix, trade::TradeBuilder};
+    use percolator_prog::{ix::Instruction, zc};
+    use solana_sdk::{pubkey::Pubkey, sysvar};
+
+    let (user, lp) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    {
+        let engine = zc::engine_mut(data).unwrap();
+        engine.params.max_accounts = 8;
+        for (idx, owner, kind) in [
+            (2usize, lp, percolator::Account::KIND_LP),
+            (5, user, percolator::Account::KIND_USER),
+        ] {
+            engine.used[0] |= 1 << idx;
+            engine.accounts[idx].owner = owner.to_bytes();
+            engine.accounts[idx].kind = kind;
+        }
+    }
+    let program_id = Pubkey::new_unique();
+    let slab_key = Pubkey::new_unique();
+    let b = TradeBuilder::new(&program_id, &slab_key, Slab::from_bytes(data), &[]).unwrap();
+    assert_eq!(b.user_index(&user).unwrap(), 5);
+    assert_eq!(b.lp_index(&lp).unwrap(), 2);
+    // Kinds are not interchangeable.
+    assert!(b.user_index(&lp).is_err());
+    assert!(b.lp_index(&user).is_err());
+
+    let trade = b.trade_nocpi(&user, &lp, -7).unwrap();
+    let keys: Vec<Pubkey> = trade.accounts.iter().map(|m| m.pubkey).collect();
+    assert_eq!(
+        keys,
+        vec![user, lp, slab_key, sysvar::clock::ID, sysvar::clock::ID]
+    );
+    assert!(trade.accounts[0].is_signer && trade.accounts[1].is_signer);
+    assert_eq!(trade.data[0], ix::TAG_TRADE_NOCPI);
+    match Instruction::decode(&trade.data) {
+        Ok(Instruction::TradeNoCpi {
+            lp_idx,
+            user_idx,
+            size,
+            exec_price_e6,
+            ..
+        }) => {
+            assert_eq!((lp_idx, user_idx, size, exec_price_e6), (2, 5, -7, 0));
+        }
+        other => panic!("expected TradeNoCpi, got {:?}", other.map(|_| ())),
+    }
+    assert!(b.trade_nocpi(&user, &lp, 0).is_err());
+
+    // External-oracle markets need one account per leg.
+    let mut cfg = percolator_prog::state::read_config(data);
+    cfg.index_feed_id = [7u8; 32];
+    cfg.oracle_leg_count = 2;
+    percolator_prog::state::write_config(data, &cfg);
+    let slab = || Slab::from_bytes(data);
+    let one = [Pubkey::new_unique()];
+    assert!(TradeBuilder::new(&program_id, &slab_key, slab(), &one).is_err());
+    let two = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let b = TradeBuilder::new(&program_id, &slab_key, slab(), &two).unwrap();
+    assert_eq!(b.oracles(), &two);
+}
+
+#[test]
+fn watcher_emits_typed_events_for_changed_accounts() {
+    use percolator_client::watch::{SlabEvent, SlabWatcher};
+    use percolator_prog::{state, zc};
+
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    zc::engine_mut(data).unwrap().params.max_accounts = 8;
+
+    let mut w = SlabWatcher::new();
+    assert!(w.update(data).unwrap().is_empty(), "baseline emits nothing");
+    assert!(w.update(data).unwrap().is_empty(), "unchanged slab");
+
+    {
+        let engine = zc::engine_mut(data).unwrap();
+        engine.used[0] |= 1 << 3;
+        engine.accounts[3].owner = [9u8; 32];
+        engine.accounts[3].capital = percolator::U128::new(500);
+    }
+    assert_eq!(
+        w.update(data).unwrap(),
+        vec![
+            SlabEvent::AccountOpened {
+                idx: 3,
+                owner: [9u8; 32]
+            },
+            SlabEvent::BalanceChanged {
+                idx: 3,
+                capital_before: 0,
+                capital_after: 500,
+                pnl_before: 0,
+                pnl_after: 0,
+            },
+        ]
+    );
+
+    zc::engine_mut(data).unwrap().accounts[3].pnl = -20;
+    let mut cfg = state::read_config(data);
+    cfg.mark_ewma_e6 = 1_500_000;
+    state::write_config(data, &cfg);
+    assert_eq!(
+        w.update(data).unwrap(),
+        vec![
+            SlabEvent::MarkMoved {
+                before_e6: 0,
+                after_e6: 1_500_000
+            },
+            SlabEvent::BalanceChanged {
+                idx: 3,
+                capital_before: 500,
+                capital_after: 500,
+                pnl_before: 0,
+                pnl_after: -20,
+            },
+        ]
+    );
+
+    zc::engine_mut(data).unwrap().used[0] = 0;
+    assert_eq!(
+        w.update(data).unwrap(),
+        vec![SlabEvent::AccountClosed { idx: 3 }]
+    );
+    assert!(w.update(&data[..64]).is_err());
+}
+
+#[test]
+fn candidates_are_ordered_by_distance_to_liquidation_price() {
+    use percolator_prog::zc;
+
+    let unit = percolator::POS_SCALE as i128;
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    {
+        let engine = zc::engine_mut(data).unwrap();
+        engine.params.max_accounts = 8;
+        engine.params.maintenance_margin_bps = 1_000;
+        engine.last_oracle_price = 100_000_000;
+        engine.adl_mult_long = 1;
+        // idx 2: 10 contracts, 5 under maintenance, liquidation price 100.55.
+        // idx 6: 1 contract, 1 under maintenance, liquidation price 101.11.
+        for (idx, q, capital) in [(2usize, 10 * unit, 95_000_000), (6, unit, 9_000_000)] {
+            engine.used[0] |= 1 << idx;
+            let acc = &mut engine.accounts[idx];
+            acc.kind = percolator::Account::KIND_USER;
+            acc.capital = percolator::U128::new(capital);
+            acc.position_basis_q = q;
+            acc.adl_a_basis = 1;
+        }
+    }
+    let slab = Slab::from_bytes(data);
+    let c = slab.liquidation_candidates(0).unwrap();
+    assert_eq!(c.iter().map(|h| h.idx).collect::<Vec<_>>(), vec![6, 2]);
+    assert!(
+        c[1].health() < c[0].health(),
+        "health alone would flip them"
+    );
+    assert_eq!(c[0].liq_price_e6, Some(101_111_110));
+    assert_eq!(c[0].liq_gap_bps(100_000_000), -111);
+    assert_eq!(slab.liquidation_price(6, 0), c[0].liq_price_e6);
+    assert_eq!(slab.liquidation_price(5, 0), None);
+}
+
+#[test]
+fn pnl_statement_values_the_ledger_at_the_oracle_price() {
+    use percolator_prog::{policy::CostBasis, state, zc};
+
+    let unit = percolator::POS_SCALE as i128;
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    zc::engine_mut(data).unwrap().last_oracle_price = 110_000_000;
+    // Index moved 5e12 since the settled snapshot: 1e-6 per slot on a
+    // price of 100 for 50 slots.
+    state::write_funding_index(
+        data,
+        &state::FundingIndex {
+            index: 7_000_000_000_000,
+            slot: 500,
+        },
+    );
+    state::write_pnl_ledger(
+        data,
+        3,
+        &state::PnlLedger {
+            basis: CostBasis {
+                position_q: 10 * unit,
+                entry_price_e6: 100_000_000,
+            },
+            funding_index: 2_000_000_000_000,
+            realized_pnl: -4_000_000,
+            fees_paid: 2_000,
+            funding_paid: 1_000,
+        },
+    );
+    let slab = Slab::from_bytes(data);
+    let s = slab.pnl_statement(3).unwrap();
+    assert_eq!((s.position_q, s.entry_price_e6), (10 * unit, 100_000_000));
+    assert_eq!((s.realized_pnl, s.fees_paid), (-4_000_000, 2_000));
+    assert_eq!(s.unrealized_pnl, 100_000_000);
+    assert_eq!(s.funding_paid, 51_000);
+
+    let flat = slab.pnl_statement(4).unwrap();
+    assert_eq!(
+        (flat.position_q, flat.unrealized_pnl, flat.funding_paid),
+        (0, 0, 0)
+    );
+    assert!(slab.pnl_statement(u16::MAX).is_err());
+    assert!(Slab::from_bytes(&data[..64]).pnl_statement(3).is_err());
+}
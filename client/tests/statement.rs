@@ -0,0 +1,260 @@
+//! Per-owner statements from synthetic transaction history.
+
+use percolator::POS_SCALE;
+use percolator_client::{
+    feed::{TxInstruction, TxUpdate},
+    ix,
+    slab::PnlStatement,
+    statement::{self, Side, StatementBuilder, StatementRecord, StatementTx},
+};
+use percolator_prog::{
+    ix::{CrankSummaryEvent, TradeResult},
+    policy::{self, CostBasis},
+};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+const Q: i128 = POS_SCALE as i128;
+
+struct Market {
+    program_id: Pubkey,
+    slab: Pubkey,
+    slot: u64,
+}
+
+impl Market {
+    fn tx(&mut self, signer: Pubkey, lp_owner: Pubkey, data: Vec<u8>) -> StatementTx {
+        self.slot += 10;
+        StatementTx {
+            tx: TxUpdate {
+                slot: self.slot,
+                signature: Signature::new_unique(),
+                success: true,
+                instructions: vec![TxInstruction {
+                    program_id: self.program_id,
+                    accounts: vec![signer, lp_owner, self.slab],
+                    data,
+                }],
+                return_data: None,
+            },
+            block_time: Some(1_700_000_000 + self.slot as i64),
+            log_data: Vec::new(),
+        }
+    }
+
+    fn trade(&mut self, user: Pubkey, lp_owner: Pubkey, r: TradeResult) -> StatementTx {
+        let args = ix::TradeNoCpiArgs {
+            lp_idx: 1,
+            user_idx: 3,
+            size: r.exec_size,
+            ..Default::default()
+        };
+        let mut t = self.tx(user, lp_owner, ix::encode_trade_nocpi(&args));
+        t.tx.return_data = Some((self.program_id, r.encode().to_vec()));
+        t
+    }
+
+    fn crank(&mut self, candidates: &[(u16, u8)], s: CrankSummaryEvent) -> StatementTx {
+        let data = ix::encode_keeper_crank(u16::MAX, candidates);
+        let mut t = self.tx(Pubkey::new_unique(), Pubkey::new_unique(), data);
+        t.log_data.push(s.encode().to_vec());
+        t
+    }
+}
+
+fn market() -> Market {
+    Market {
+        program_id: Pubkey::new_unique(),
+        slab: Pubkey::new_unique(),
+        slot: 0,
+    }
+}
+
+#[test]
+fn statement_follows_deposits_fills_funding_and_liquidation() {
+    let mut m = market();
+    let (owner, lp) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut b = StatementBuilder::new(m.program_id, m.slab, owner);
+
+    let dep = m.tx(owner, lp, ix::encode_deposit(3, 1_000_000));
+    assert!(matches!(
+        b.on_transaction(&dep).as_slice(),
+        [StatementRecord::Deposit {
+            idx: 3,
+            amount: 1_000_000,
+            block_time: Some(_),
+            ..
+        }]
+    ));
+
+    let open = TradeResult {
+        exec_size: 2 * Q,
+        exec_price_e6: 100_000_000,
+        oracle_price_e6: 100_000_000,
+        fee_bps: 5,
+        fee_paid: 11,
+    };
+    match b.on_transaction(&m.trade(owner, lp, open)).as_slice() {
+        [StatementRecord::Fill {
+            idx: 3,
+            side: Side::User,
+            size_q,
+            fee_paid: 6,
+            realized_pnl: 0,
+            position_q,
+            ..
+        }] if *size_q == 2 * Q && *position_q == 2 * Q => {}
+        other => panic!("expected the opening fill, got {other:?}"),
+    }
+
+    // The first crank only anchors the interval.
+    let summary = CrankSummaryEvent {
+        crank_slot: 100,
+        funding_rate_e9: 10,
+        flags: percolator_prog::ix::CRANK_SUMMARY_CRANKED,
+        ..Default::default()
+    };
+    assert!(b.on_transaction(&m.crank(&[], summary)).is_empty());
+    let summary = CrankSummaryEvent {
+        crank_slot: 200,
+        ..summary
+    };
+    let index = policy::funding_index_advance(0, 100_000_000, 10, 100);
+    match b.on_transaction(&m.crank(&[], summary)).as_slice() {
+        [StatementRecord::Funding {
+            idx: 3,
+            from_slot: 100,
+            to_slot: 200,
+            funding_paid,
+            ..
+        }] => assert_eq!(*funding_paid, policy::funding_owed(2 * Q, 0, index)),
+        other => panic!("expected one funding record, got {other:?}"),
+    }
+
+    // Selling 3 closes the long at a profit and opens a 1-contract short.
+    let flip = TradeResult {
+        exec_size: -3 * Q,
+        exec_price_e6: 110_000_000,
+        ..open
+    };
+    let basis = CostBasis {
+        position_q: 2 * Q,
+        entry_price_e6: 100_000_000,
+    };
+    let (_, realized) = policy::settle_cost_basis(&basis, -3 * Q, 110_000_000).unwrap();
+    assert_eq!(realized, 20_000_000);
+    match b.on_transaction(&m.trade(owner, lp, flip)).as_slice() {
+        [StatementRecord::Fill {
+            realized_pnl,
+            position_q,
+            ..
+        }] => assert_eq!((*realized_pnl, *position_q), (realized, -Q)),
+        other => panic!("expected the flip, got {other:?}"),
+    }
+
+    let liq = CrankSummaryEvent {
+        crank_slot: 300,
+        funding_rate_e9: 0,
+        liquidations: 1,
+        ..summary
+    };
+    let recs = b.on_transaction(&m.crank(&[(3, ix::CANDIDATE_FULL_CLOSE)], liq));
+    assert!(matches!(
+        recs.last(),
+        Some(StatementRecord::Liquidation {
+            idx: 3,
+            price_e6: 100_000_000,
+            position_q,
+            ..
+        }) if *position_q == -Q
+    ));
+
+    // Failed transactions leave no trace.
+    let mut failed = m.tx(owner, lp, ix::encode_withdraw(3, 5, None));
+    failed.tx.success = false;
+    assert!(b.on_transaction(&failed).is_empty());
+    let wd = m.tx(owner, lp, ix::encode_withdraw(3, 500_000, None));
+    assert!(matches!(
+        b.on_transaction(&wd).as_slice(),
+        [StatementRecord::Withdrawal {
+            idx: 3,
+            amount: 500_000,
+            ..
+        }]
+    ));
+
+    assert_eq!(b.indices(), vec![3]);
+    let close = m.tx(owner, lp, vec![8, 3, 0]);
+    assert!(matches!(
+        b.on_transaction(&close).as_slice(),
+        [StatementRecord::Close { idx: 3, .. }]
+    ));
+    assert!(b.indices().is_empty());
+}
+
+#[test]
+fn lp_owner_gets_the_other_side_and_strangers_nothing() {
+    let mut m = market();
+    let (user, lp) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let fill = TradeResult {
+        exec_size: Q,
+        exec_price_e6: 100_000_000,
+        oracle_price_e6: 100_000_000,
+        fee_bps: 5,
+        fee_paid: 11,
+    };
+    let t = m.trade(user, lp, fill);
+
+    let mut as_lp = StatementBuilder::new(m.program_id, m.slab, lp);
+    match as_lp.on_transaction(&t).as_slice() {
+        [StatementRecord::Fill {
+            idx: 1,
+            side: Side::Lp,
+            size_q,
+            fee_paid: 5,
+            ..
+        }] => assert_eq!(*size_q, -Q),
+        other => panic!("expected the LP side, got {other:?}"),
+    }
+
+    let mut stranger = StatementBuilder::new(m.program_id, m.slab, Pubkey::new_unique());
+    assert!(stranger.on_transaction(&t).is_empty());
+    // Another market's instructions are ignored even for the owner.
+    let mut other = StatementBuilder::new(m.program_id, Pubkey::new_unique(), user);
+    assert!(other.on_transaction(&t).is_empty());
+}
+
+#[test]
+fn csv_has_one_line_per_record_under_the_header() {
+    let records = vec![
+        StatementRecord::Deposit {
+            slot: 7,
+            block_time: None,
+            signature: "sig".into(),
+            idx: 3,
+            amount: 100,
+        },
+        StatementRecord::from(PnlStatement {
+            idx: 3,
+            position_q: -Q,
+            entry_price_e6: 110_000_000,
+            realized_pnl: 20_000_000,
+            unrealized_pnl: -1,
+            fees_paid: 12,
+            funding_paid: -4,
+        }),
+    ];
+    let csv = statement::to_csv(&records);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], statement::CSV_HEADER);
+    assert_eq!(lines[1], "deposit,7,,sig,3,,100,,,,,,,,");
+    assert_eq!(
+        lines[2],
+        format!("ledger,,,,3,,,,110000000,,12,20000000,-1,-4,{}", -Q)
+    );
+    let columns = statement::CSV_HEADER.split(',').count();
+    assert!(lines.iter().all(|l| l.split(',').count() == columns));
+
+    let json = serde_json::to_value(&records).unwrap();
+    assert_eq!(json[0]["kind"], "deposit");
+    assert_eq!(json[1]["kind"], "ledger");
+}
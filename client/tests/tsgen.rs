@@ -0,0 +1,134 @@
+//! Generated TypeScript layouts against the program they describe.
+
+use percolator_client::tsgen::{self, Generated, Tier, WireKind};
+use percolator_prog::{
+    ix::{tag_class, Instruction, TagClass},
+    state::{offsets, MarketConfig, SlabHeader},
+};
+
+const SOURCE: &str = include_str!("../../src/percolator.rs");
+
+fn generated() -> Generated {
+    tsgen::generate(SOURCE, Tier::current()).expect("program source generates")
+}
+
+#[test]
+fn every_assigned_tag_has_a_layout() {
+    let g = generated();
+    let assigned = (0..=u8::MAX)
+        .filter(|&t| tag_class(t) == TagClass::Assigned)
+        .count();
+    assert_eq!(g.instructions.len(), assigned);
+    for ix in &g.instructions {
+        assert_eq!(tag_class(ix.tag), TagClass::Assigned, "{}", ix.name);
+    }
+    let opaque: Vec<&str> = g
+        .instructions
+        .iter()
+        .filter(|i| i.opaque)
+        .map(|i| i.name.as_str())
+        .collect();
+    assert_eq!(opaque, ["InitMarket", "KeeperCrank"]);
+}
+
+#[test]
+fn layouts_encode_payloads_the_decoder_accepts() {
+    for ix in generated().instructions.iter().filter(|i| !i.opaque) {
+        let mut data = vec![ix.tag];
+        for f in &ix.fields {
+            let len = match f.kind {
+                WireKind::U8 => 1,
+                WireKind::U16 => 2,
+                WireKind::U32 => 4,
+                WireKind::U64 | WireKind::I64 => 8,
+                WireKind::U128 | WireKind::I128 => 16,
+                WireKind::Bytes(n) => n,
+                WireKind::Vec32 => 1,
+                WireKind::OptionalU64 => 0,
+            };
+            data.resize(data.len() + len, 0);
+        }
+        let decoded = Instruction::decode(&data)
+            .unwrap_or_else(|e| panic!("{} ({} bytes): {e:?}", ix.name, data.len()));
+        assert!(
+            format!("{decoded:?}").starts_with(&ix.name),
+            "tag {} decoded as {decoded:?}",
+            ix.tag
+        );
+    }
+
+    let g = generated();
+    let trade = g.instruction("TradeCpi").unwrap();
+    let names: Vec<&str> = trade.fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "lp_idx",
+            "user_idx",
+            "size",
+            "limit_price_e6",
+            "flags",
+            "client_order_id",
+            "client_tag"
+        ]
+    );
+    let withdraw = g.instruction("WithdrawCollateral").unwrap();
+    assert_eq!(withdraw.fields.last().unwrap().kind, WireKind::OptionalU64);
+}
+
+#[test]
+fn struct_offsets_match_the_program() {
+    let g = generated();
+    let offset = |s: &str, f: &str| {
+        let layout = g.structs.iter().find(|l| l.name == s).unwrap();
+        layout.fields.iter().find(|x| x.name == f).unwrap().offset
+    };
+
+    let header = &g.structs[0];
+    assert_eq!(header.size, core::mem::size_of::<SlabHeader>());
+    assert_eq!(offset("SlabHeader", "admin"), offsets::ADMIN);
+    assert_eq!(
+        offset("SlabHeader", "insurance_operator"),
+        offsets::INSURANCE_OPERATOR
+    );
+
+    let config = &g.structs[1];
+    assert_eq!(config.size, core::mem::size_of::<MarketConfig>());
+    for (field, expected) in [
+        ("vault_pubkey", offsets::VAULT_PUBKEY),
+        ("invert", offsets::INVERT),
+        ("unit_scale", offsets::UNIT_SCALE),
+        ("hyperp_mark_e6", offsets::HYPERP_MARK_E6),
+        ("mark_ewma_last_slot", offsets::MARK_EWMA_LAST_SLOT),
+    ] {
+        assert_eq!(
+            offsets::CONFIG + offset("MarketConfig", field),
+            expected,
+            "{field}"
+        );
+    }
+
+    let sbf = |name: &str| {
+        g.sbf_offsets
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, v)| v)
+    };
+    assert_eq!(sbf("ENGINE"), Some(offsets::sbf::ENGINE));
+    assert_eq!(sbf("C_TOT"), Some(offsets::sbf::C_TOT));
+    assert_eq!(sbf("ACCOUNTS"), Some(offsets::sbf::ACCOUNTS));
+    assert_eq!(sbf("SLAB_LEN"), Some(offsets::sbf::SLAB_LEN));
+}
+
+#[test]
+fn typescript_output_names_every_layout() {
+    let g = generated();
+    let ts = g.to_typescript();
+    assert!(ts.starts_with("// Generated by percolator-tsgen"));
+    let deposit = "  DepositCollateral: { tag: 3, opaque: false, fields: [";
+    assert!(ts.contains(&format!("{deposit}{{ name: \"userIdx\", kind: \"u16\" }}")));
+    assert!(ts.contains("export interface TradeCpiArgs {\n  lpIdx: number;"));
+    assert!(ts.contains("  nonce?: bigint | undefined;"));
+    assert!(ts.contains("export const MARKET_CONFIG: StructLayout"));
+    assert!(ts.contains(&format!("  ENGINE: {},", offsets::sbf::ENGINE)));
+}
@@ -0,0 +1,23 @@
+//! Fuzz `Instruction::decode` with arbitrary instruction data.
+//!
+//! The decoder is the first thing every transaction reaches, so it must
+//! reject malformed input with an error — never panic, never read past
+//! the end of the buffer, and never accept an empty payload.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use percolator_prog::ix::Instruction;
+
+fuzz_target!(|data: &[u8]| {
+    let decoded = Instruction::decode(data);
+    if data.is_empty() {
+        assert!(decoded.is_err(), "empty instruction data must not decode");
+    }
+    // Decoding is a pure function of the input bytes.
+    assert_eq!(
+        decoded.is_ok(),
+        Instruction::decode(data).is_ok(),
+        "decode must be deterministic"
+    );
+});
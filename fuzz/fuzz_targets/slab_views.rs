@@ -0,0 +1,64 @@
+//! Fuzz the zero-copy slab views with arbitrary account data.
+//!
+//! The first input byte picks a mode:
+//! - even: the remaining bytes are the whole account, at whatever length
+//!   the fuzzer chose. `engine_ref` / `engine_mut` must reject anything
+//!   shorter than the engine region instead of reading past the buffer.
+//! - odd: the remaining bytes are tiled over a full-size, engine-aligned
+//!   slab so the invalid-bit-pattern validation runs on every enum/bool
+//!   field. Run with `--features small` to keep SLAB_LEN manageable.
+//!
+//! Header/config readers are only exercised once the buffer is long
+//! enough, mirroring the `slab_guard` length check the processor runs
+//! before touching them.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use percolator_prog::{
+    constants::{CONFIG_LEN, ENGINE_ALIGN, ENGINE_LEN, ENGINE_OFF, HEADER_LEN, SLAB_LEN},
+    state, zc,
+};
+
+fn exercise(data: &mut [u8]) {
+    let len = data.len();
+    if len >= HEADER_LEN + CONFIG_LEN {
+        let _ = state::read_header(data);
+        let _ = state::read_config(data);
+        let _ = state::read_req_nonce(data);
+        let _ = state::read_mat_counter(data);
+        let _ = state::read_account_fees(data);
+        let _ = state::is_cpi_in_progress(data);
+        let _ = state::is_oracle_initialized(data);
+    }
+
+    let aligned = (data.as_ptr() as usize + ENGINE_OFF) % ENGINE_ALIGN == 0;
+    if let Ok(engine) = zc::engine_ref(data) {
+        assert!(len >= ENGINE_OFF + ENGINE_LEN && aligned);
+        core::hint::black_box(&engine.vault);
+    }
+    if zc::engine_mut(data).is_ok() {
+        assert!(len >= ENGINE_OFF + ENGINE_LEN && aligned);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&mode, rest)) = data.split_first() else {
+        return;
+    };
+    if mode & 1 == 0 {
+        let mut buf = rest.to_vec();
+        exercise(&mut buf);
+        return;
+    }
+
+    // u128 backing keeps the engine region aligned on the host.
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let slab = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    if !rest.is_empty() {
+        for (dst, src) in slab.iter_mut().zip(rest.iter().cycle()) {
+            *dst = *src;
+        }
+    }
+    exercise(slab);
+});
@@ -0,0 +1,490 @@
+//! Python bindings for research and backtesting.
+//!
+//! Every number here comes from the code the program and the client
+//! already run, so a notebook sees production math, not a port of it:
+//!
+//! - `Slab`: a slab account snapshot (`percolator_client::slab::Slab`), with
+//!   market parameters, accounts, health estimates and liquidation prices,
+//!   PnL ledger statements, the history ring, the current funding rate and
+//!   `simulate_trade` (`simulate::simulate_trade_nocpi`).
+//! - `quote`: a vAMM fill from matcher context bytes (`quote::quote`).
+//! - `funding_rate_e9`, `funding_bps_bound`: the funding policy
+//!   (`policy::funding_rate_e9_from_mark_index`, `policy::funding_bps_bound`).
+//!
+//! ```python
+//! import percolator_py as pc
+//! slab = pc.Slab(open("slab.bin", "rb").read())
+//! m = slab.market()
+//! q = pc.quote(ctx_bytes, m.last_oracle_price, 10 * pc.POS_SCALE)
+//! p = slab.simulate_trade(lp_idx=0, user_idx=3, size=q.fill_size,
+//!                         exec_price_e6=q.exec_price_e6,
+//!                         price=m.last_oracle_price, now_slot=m.last_market_slot)
+//! ```
+//!
+//! Integers keep their on-chain width (u128 / i128 become Python ints);
+//! pubkeys are base58 strings. Rejections raise `ValueError`.
+
+use percolator::{Account, MarketMode, POS_SCALE};
+use percolator_client::{quote as client_quote, slab::Slab as ClientSlab};
+use percolator_prog::{constants::SLAB_LEN, policy, simulate, state};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+fn value_err(e: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+fn key(bytes: &[u8; 32]) -> String {
+    Pubkey::new_from_array(*bytes).to_string()
+}
+
+/// `__repr__` from `Debug` for the plain record classes.
+macro_rules! debug_repr {
+    ($($ty:ty),* $(,)?) => {$(
+        #[pymethods]
+        impl $ty {
+            fn __repr__(&self) -> String {
+                format!("{self:?}")
+            }
+        }
+    )*};
+}
+
+/// Market parameters and engine aggregates at the snapshot.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct Market {
+    pub admin: String,
+    pub collateral_mint: String,
+    pub vault: String,
+    pub hyperp: bool,
+    pub invert: u8,
+    pub unit_scale: u32,
+    pub resolved: bool,
+    pub last_market_slot: u64,
+    pub last_oracle_price: u64,
+    pub last_effective_price_e6: u64,
+    pub mark_ewma_e6: u64,
+    pub funding_horizon_slots: u64,
+    pub funding_k_bps: u64,
+    pub funding_max_premium_bps: i64,
+    pub funding_max_e9_per_slot: i64,
+    pub trade_fee_base_bps: u64,
+    pub maintenance_margin_bps: u64,
+    pub initial_margin_bps: u64,
+    pub max_trading_fee_bps: u64,
+    pub liquidation_fee_bps: u64,
+    pub vault_balance: u128,
+    pub c_tot: u128,
+    pub insurance: u128,
+    pub oi_eff_long_q: u128,
+    pub oi_eff_short_q: u128,
+    pub num_used_accounts: u64,
+}
+
+/// One used account slot.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct AccountView {
+    pub idx: u16,
+    pub owner: String,
+    pub lp: bool,
+    pub capital: u128,
+    pub pnl: i128,
+    /// After ADL scaling; `None` when the slot's ADL state is corrupt.
+    pub effective_pos_q: Option<i128>,
+}
+
+/// `slab::HealthEstimate`: maintenance at the engine's last oracle price,
+/// excluding mark-to-market since the account's last touch.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct Health {
+    pub idx: u16,
+    pub effective_pos_q: i128,
+    pub notional: u128,
+    pub equity: i128,
+    pub maintenance_req: u128,
+    /// `equity - maintenance_req`; negative means liquidatable.
+    pub health: i128,
+    pub liq_price_e6: Option<u64>,
+}
+
+impl From<percolator_client::slab::HealthEstimate> for Health {
+    fn from(h: percolator_client::slab::HealthEstimate) -> Self {
+        Self {
+            idx: h.idx,
+            effective_pos_q: h.effective_pos_q,
+            notional: h.notional,
+            equity: h.equity,
+            maintenance_req: h.maintenance_req,
+            health: h.health(),
+            liq_price_e6: h.liq_price_e6,
+        }
+    }
+}
+
+/// `state::HistorySample`, one KeeperCrank entry of the history ring.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct HistorySample {
+    pub slot: u64,
+    pub price_e6: u64,
+    pub mark_e6: u64,
+    pub funding_rate_e9: i64,
+    pub oi_long_q: u128,
+    pub oi_short_q: u128,
+}
+
+impl From<state::HistorySample> for HistorySample {
+    fn from(s: state::HistorySample) -> Self {
+        Self {
+            slot: s.slot,
+            price_e6: s.price_e6,
+            mark_e6: s.mark_e6,
+            funding_rate_e9: s.funding_rate_e9,
+            oi_long_q: s.oi_long_q,
+            oi_short_q: s.oi_short_q,
+        }
+    }
+}
+
+/// `slab::PnlStatement`: an account's PnL ledger at the engine's last
+/// oracle price.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct PnlStatement {
+    pub idx: u16,
+    pub position_q: i128,
+    pub entry_price_e6: u64,
+    pub realized_pnl: i64,
+    pub unrealized_pnl: i128,
+    pub fees_paid: u64,
+    pub funding_paid: i128,
+}
+
+impl From<percolator_client::slab::PnlStatement> for PnlStatement {
+    fn from(s: percolator_client::slab::PnlStatement) -> Self {
+        Self {
+            idx: s.idx,
+            position_q: s.position_q,
+            entry_price_e6: s.entry_price_e6,
+            realized_pnl: s.realized_pnl,
+            unrealized_pnl: s.unrealized_pnl,
+            fees_paid: s.fees_paid,
+            funding_paid: s.funding_paid,
+        }
+    }
+}
+
+/// `quote::Quote`.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct Quote {
+    pub exec_price_e6: u64,
+    pub fill_size: i128,
+    pub total_bps: u32,
+    pub matcher_fee_bps: u32,
+    pub fill_notional_e6: u128,
+}
+
+/// `simulate::AccountHealth`, one side after a simulated trade.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct AccountHealth {
+    pub position_q: i128,
+    pub capital: u128,
+    pub pnl: i128,
+    pub notional: u128,
+    pub maintenance_req: u128,
+    pub initial_req: u128,
+    pub equity: i128,
+    pub above_maintenance: bool,
+}
+
+impl From<simulate::AccountHealth> for AccountHealth {
+    fn from(a: simulate::AccountHealth) -> Self {
+        Self {
+            position_q: a.position_q,
+            capital: a.capital,
+            pnl: a.pnl,
+            notional: a.notional,
+            maintenance_req: a.maintenance_req,
+            initial_req: a.initial_req,
+            equity: a.equity(),
+            above_maintenance: a.above_maintenance(),
+        }
+    }
+}
+
+/// `simulate::TradePreview`.
+#[pyclass(module = "percolator_py", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct TradePreview {
+    pub size: i128,
+    pub exec_price: u64,
+    pub oracle_price: u64,
+    pub fee_bps: u64,
+    pub fee_paid: u128,
+    pub user: AccountHealth,
+    pub lp: AccountHealth,
+}
+
+debug_repr!(
+    Market,
+    AccountView,
+    Health,
+    HistorySample,
+    PnlStatement,
+    Quote,
+    AccountHealth,
+    TradePreview,
+);
+
+/// A slab account snapshot, e.g. `getAccountInfo` data or a saved dump.
+#[pyclass(name = "Slab", module = "percolator_py", frozen)]
+pub struct PySlab(ClientSlab);
+
+#[pymethods]
+impl PySlab {
+    #[new]
+    fn new(data: &[u8]) -> PyResult<Self> {
+        let slab = ClientSlab::from_bytes(data);
+        slab.engine().map_err(value_err)?;
+        Ok(Self(slab))
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.bytes().len()
+    }
+
+    fn market(&self) -> PyResult<Market> {
+        let header = self.0.header();
+        let config = self.0.config();
+        let engine = self.0.engine().map_err(value_err)?;
+        let p = &engine.params;
+        Ok(Market {
+            admin: key(&header.admin),
+            collateral_mint: key(&config.collateral_mint),
+            vault: key(&config.vault_pubkey),
+            hyperp: config.index_feed_id == [0u8; 32],
+            invert: config.invert,
+            unit_scale: config.unit_scale,
+            resolved: engine.market_mode == MarketMode::Resolved,
+            last_market_slot: engine.last_market_slot,
+            last_oracle_price: engine.last_oracle_price,
+            last_effective_price_e6: config.last_effective_price_e6,
+            mark_ewma_e6: config.mark_ewma_e6,
+            funding_horizon_slots: config.funding_horizon_slots,
+            funding_k_bps: config.funding_k_bps,
+            funding_max_premium_bps: config.funding_max_premium_bps,
+            funding_max_e9_per_slot: config.funding_max_e9_per_slot,
+            trade_fee_base_bps: config.trade_fee_base_bps,
+            maintenance_margin_bps: p.maintenance_margin_bps,
+            initial_margin_bps: p.initial_margin_bps,
+            max_trading_fee_bps: p.max_trading_fee_bps,
+            liquidation_fee_bps: p.liquidation_fee_bps,
+            vault_balance: engine.vault.get(),
+            c_tot: engine.c_tot.get(),
+            insurance: engine.insurance_fund.balance.get(),
+            oi_eff_long_q: engine.oi_eff_long_q,
+            oi_eff_short_q: engine.oi_eff_short_q,
+            num_used_accounts: engine.num_used_accounts as u64,
+        })
+    }
+
+    fn used_indices(&self) -> PyResult<Vec<u16>> {
+        self.0.used_indices().map_err(value_err)
+    }
+
+    /// Every used slot, in index order.
+    fn accounts(&self) -> PyResult<Vec<AccountView>> {
+        let engine = self.0.engine().map_err(value_err)?;
+        Ok(self
+            .0
+            .used_indices()
+            .map_err(value_err)?
+            .into_iter()
+            .map(|idx| {
+                let acc = &engine.accounts[idx as usize];
+                AccountView {
+                    idx,
+                    owner: key(&acc.owner),
+                    lp: acc.kind == Account::KIND_LP,
+                    capital: acc.capital.get(),
+                    pnl: acc.pnl,
+                    effective_pos_q: self.0.effective_pos_q(idx),
+                }
+            })
+            .collect())
+    }
+
+    #[pyo3(signature = (owner, lp = false))]
+    fn find_account(&self, owner: &str, lp: bool) -> PyResult<Option<u16>> {
+        let owner = Pubkey::from_str(owner).map_err(value_err)?;
+        self.0
+            .find_account(&owner.to_bytes(), lp)
+            .map_err(value_err)
+    }
+
+    fn health(&self, idx: u16) -> Option<Health> {
+        self.0.health_estimate(idx).map(Health::from)
+    }
+
+    /// Oracle price at which `idx` becomes liquidatable after
+    /// `horizon_slots` of the current funding rate and maintenance fee.
+    #[pyo3(signature = (idx, horizon_slots = 0))]
+    fn liquidation_price(&self, idx: u16, horizon_slots: u64) -> Option<u64> {
+        self.0.liquidation_price(idx, horizon_slots)
+    }
+
+    /// Entry price, realized and unrealized PnL, fees and funding from the
+    /// account's on-chain ledger.
+    fn pnl_statement(&self, idx: u16) -> PyResult<PnlStatement> {
+        self.0
+            .pnl_statement(idx)
+            .map(PnlStatement::from)
+            .map_err(value_err)
+    }
+
+    #[pyo3(signature = (buffer_bps = 0))]
+    fn liquidation_candidates(&self, buffer_bps: u64) -> PyResult<Vec<Health>> {
+        Ok(self
+            .0
+            .liquidation_candidates(buffer_bps)
+            .map_err(value_err)?
+            .into_iter()
+            .map(Health::from)
+            .collect())
+    }
+
+    fn history(&self) -> PyResult<Vec<HistorySample>> {
+        Ok(self
+            .0
+            .history()
+            .map_err(value_err)?
+            .into_iter()
+            .map(HistorySample::from)
+            .collect())
+    }
+
+    /// The per-slot rate the next crank would apply, from the stored mark
+    /// EWMA and index.
+    fn funding_rate_e9(&self) -> PyResult<i128> {
+        let c = self.0.config();
+        funding_rate_e9(
+            c.mark_ewma_e6,
+            c.last_effective_price_e6,
+            c.funding_horizon_slots,
+            c.funding_k_bps,
+            c.funding_max_premium_bps,
+            c.funding_max_e9_per_slot,
+        )
+    }
+
+    /// TradeNoCpi against a copy of this snapshot. `price` is the effective
+    /// oracle price the handler would read at `now_slot`; the snapshot
+    /// itself is not modified.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (lp_idx, user_idx, size, exec_price_e6, price, now_slot, flags = 0))]
+    fn simulate_trade(
+        &self,
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        exec_price_e6: u64,
+        price: u64,
+        now_slot: u64,
+        flags: u8,
+    ) -> PyResult<TradePreview> {
+        let mut scratch = vec![0u128; SLAB_LEN.div_ceil(16)];
+        let p = simulate::simulate_trade_nocpi(
+            self.0.bytes(),
+            bytemuck::cast_slice_mut(&mut scratch),
+            lp_idx,
+            user_idx,
+            size,
+            exec_price_e6,
+            flags,
+            now_slot,
+            price,
+        )
+        .map_err(|e| value_err(format!("trade rejected: {e}")))?;
+        Ok(TradePreview {
+            size: p.size,
+            exec_price: p.exec_price,
+            oracle_price: p.oracle_price,
+            fee_bps: p.fee_bps,
+            fee_paid: p.fee_paid,
+            user: p.user.into(),
+            lp: p.lp.into(),
+        })
+    }
+}
+
+/// Fill for `size` (taker side) against a vAMM matcher context.
+#[pyfunction]
+pub fn quote(ctx: &[u8], oracle_price_e6: u64, size: i128) -> PyResult<Quote> {
+    let q = client_quote::quote(ctx, oracle_price_e6, size).map_err(value_err)?;
+    Ok(Quote {
+        exec_price_e6: q.exec_price_e6,
+        fill_size: q.fill_size,
+        total_bps: q.total_bps,
+        matcher_fee_bps: q.matcher_fee_bps,
+        fill_notional_e6: q.fill_notional_e6,
+    })
+}
+
+/// Per-slot funding rate (e9) for a mark/index pair under the given
+/// market parameters.
+#[pyfunction]
+pub fn funding_rate_e9(
+    mark_ewma_e6: u64,
+    index_e6: u64,
+    funding_horizon_slots: u64,
+    funding_k_bps: u64,
+    funding_max_premium_bps: i64,
+    funding_max_e9_per_slot: i64,
+) -> PyResult<i128> {
+    policy::funding_rate_e9_from_mark_index(
+        mark_ewma_e6,
+        index_e6,
+        funding_horizon_slots,
+        funding_k_bps,
+        funding_max_premium_bps,
+        funding_max_e9_per_slot,
+    )
+    .ok_or_else(|| value_err("negative funding cap"))
+}
+
+/// Worst-case funding (bps of notional) one side can pay over `slots`.
+#[pyfunction]
+pub fn funding_bps_bound(funding_max_e9_per_slot: i64, slots: u64) -> PyResult<u128> {
+    policy::funding_bps_bound(funding_max_e9_per_slot, slots)
+        .ok_or_else(|| value_err("negative funding cap"))
+}
+
+/// Adds the classes, functions and constants to `m`.
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySlab>()?;
+    m.add_class::<Market>()?;
+    m.add_class::<AccountView>()?;
+    m.add_class::<Health>()?;
+    m.add_class::<HistorySample>()?;
+    m.add_class::<PnlStatement>()?;
+    m.add_class::<Quote>()?;
+    m.add_class::<AccountHealth>()?;
+    m.add_class::<TradePreview>()?;
+    m.add_function(wrap_pyfunction!(quote, m)?)?;
+    m.add_function(wrap_pyfunction!(funding_rate_e9, m)?)?;
+    m.add_function(wrap_pyfunction!(funding_bps_bound, m)?)?;
+    m.add("POS_SCALE", POS_SCALE)?;
+    m.add("SLAB_LEN", SLAB_LEN)?;
+    Ok(())
+}
+
+#[pymodule]
+fn percolator_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    register(m)
+}
@@ -0,0 +1,171 @@
+//! The Python module driven from an embedded interpreter, checked against
+//! the Rust functions it wraps.
+
+use percolator_prog::{constants::SLAB_LEN, policy, state, zc};
+use pyo3::{prelude::*, types::IntoPyDict};
+use solana_sdk::pubkey::Pubkey;
+
+/// Runs `script` with the module bound as `pc` and `args` as globals.
+fn run(script: &str, args: &[(&str, PyObject)]) {
+    Python::with_gil(|py| {
+        let m = PyModule::new_bound(py, "percolator_py").unwrap();
+        percolator_py::register(&m).unwrap();
+        let globals = [("pc", m.into_any().unbind())]
+            .into_iter()
+            .chain(args.iter().map(|(k, v)| (*k, v.clone_ref(py))))
+            .into_py_dict_bound(py);
+        if let Err(e) = py.run_bound(script, Some(&globals), None) {
+            e.print(py);
+            panic!("python script failed");
+        }
+    });
+}
+
+fn bytes(data: &[u8]) -> PyObject {
+    Python::with_gil(|py| {
+        pyo3::types::PyBytes::new_bound(py, data)
+            .into_any()
+            .unbind()
+    })
+}
+
+fn obj(v: impl IntoPy<PyObject>) -> PyObject {
+    Python::with_gil(|py| v.into_py(py))
+}
+
+#[test]
+fn slab_snapshot_reads_accounts_history_and_funding() {
+    let (lp, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    {
+        let engine = zc::engine_mut(data).unwrap();
+        engine.params.max_accounts = 8;
+        engine.params.maintenance_margin_bps = 500;
+        engine.last_oracle_price = 100_000_000;
+        for (idx, owner, kind, pnl) in [
+            (1usize, lp, percolator::Account::KIND_LP, 0),
+            (4, user, percolator::Account::KIND_USER, -25),
+        ] {
+            engine.used[0] |= 1 << idx;
+            engine.accounts[idx].owner = owner.to_bytes();
+            engine.accounts[idx].kind = kind;
+            engine.accounts[idx].pnl = pnl;
+        }
+    }
+    let mut cfg = state::read_config(data);
+    cfg.mark_ewma_e6 = 101_000_000;
+    cfg.last_effective_price_e6 = 100_000_000;
+    cfg.funding_horizon_slots = 500;
+    cfg.funding_k_bps = 100;
+    cfg.funding_max_premium_bps = 500;
+    cfg.funding_max_e9_per_slot = 1_000;
+    state::write_config(data, &cfg);
+    for slot in [10, 20] {
+        state::push_history_sample(
+            data,
+            &state::HistorySample {
+                slot,
+                funding_rate_e9: -3,
+                ..Default::default()
+            },
+        );
+    }
+    let rate =
+        policy::funding_rate_e9_from_mark_index(101_000_000, 100_000_000, 500, 100, 500, 1_000)
+            .unwrap();
+    assert_ne!(rate, 0);
+
+    run(
+        r#"
+s = pc.Slab(data)
+assert len(s) == pc.SLAB_LEN
+m = s.market()
+assert (m.maintenance_margin_bps, m.last_oracle_price) == (500, 100_000_000)
+assert m.hyperp and not m.resolved
+
+assert s.used_indices() == [1, 4]
+accts = s.accounts()
+assert [(a.idx, a.owner, a.lp) for a in accts] == [(1, lp, True), (4, user, False)]
+assert accts[1].pnl == -25 and accts[1].effective_pos_q == 0
+assert s.find_account(user) == 4
+assert s.find_account(lp, lp=True) == 1
+assert s.find_account(user, lp=True) is None
+
+h = s.health(4)
+assert (h.equity, h.maintenance_req, h.health) == (-25, 0, -25)
+assert s.health(2) is None
+assert s.liquidation_candidates() == []  # flat accounts are never candidates
+
+st = s.pnl_statement(4)
+assert (st.position_q, st.realized_pnl, st.fees_paid, st.funding_paid) == (0, 0, 0, 0)
+
+assert [(x.slot, x.funding_rate_e9) for x in s.history()] == [(10, -3), (20, -3)]
+assert s.funding_rate_e9() == rate
+assert pc.funding_rate_e9(101_000_000, 100_000_000, 500, 100, 500, 1_000) == rate
+
+try:
+    s.simulate_trade(1, 4, pc.POS_SCALE, 100_000_000, 100_000_000, 30)
+    raise AssertionError("uninitialized slab simulated")
+except ValueError as e:
+    assert "trade rejected" in str(e)
+
+try:
+    pc.Slab(data[:64])
+    raise AssertionError("truncated slab accepted")
+except ValueError:
+    pass
+"#,
+        &[
+            ("data", bytes(data)),
+            ("lp", obj(lp.to_string())),
+            ("user", obj(user.to_string())),
+            ("rate", obj(rate)),
+        ],
+    );
+}
+
+#[test]
+fn quotes_and_funding_bounds_match_the_client() {
+    use percolator_prog::matcher_abi::{VAMM_MAGIC, VAMM_MODE_IMPACT};
+
+    let mut ctx = vec![0u8; 320];
+    ctx[64..72].copy_from_slice(&VAMM_MAGIC.to_le_bytes());
+    ctx[76] = VAMM_MODE_IMPACT;
+    ctx[112..116].copy_from_slice(&5u32.to_le_bytes()); // fee
+    ctx[116..120].copy_from_slice(&10u32.to_le_bytes()); // spread
+    ctx[120..124].copy_from_slice(&200u32.to_le_bytes()); // max total
+    ctx[124..128].copy_from_slice(&100u32.to_le_bytes()); // impact k
+    ctx[128..144].copy_from_slice(&10_000_000_000u128.to_le_bytes());
+    let size = -10 * percolator::POS_SCALE as i128;
+    let q = percolator_client::quote::quote(&ctx, 100_000_000, size).unwrap();
+    let bound = policy::funding_bps_bound(1_000, 10_000).unwrap();
+
+    run(
+        r#"
+q = pc.quote(ctx, 100_000_000, size)
+assert (q.exec_price_e6, q.fill_size, q.total_bps) == expected
+assert q.matcher_fee_bps == 5
+assert repr(q).startswith("Quote {")
+
+try:
+    pc.quote(bytes(320), 100_000_000, size)
+    raise AssertionError("uninitialized context quoted")
+except ValueError:
+    pass
+
+assert pc.funding_bps_bound(1_000, 10_000) == bound
+try:
+    pc.funding_bps_bound(-1, 1)
+    raise AssertionError("negative cap accepted")
+except ValueError:
+    pass
+"#,
+        &[
+            ("ctx", bytes(&ctx)),
+            ("size", obj(size)),
+            ("expected", obj((q.exec_price_e6, q.fill_size, q.total_bps))),
+            ("bound", obj(bound)),
+        ],
+    );
+}
@@ -16,6 +16,10 @@ pub mod constants {
     use percolator::RiskEngine;
 
     pub const MAGIC: u64 = 0x504552434f4c4154; // "PERCOLAT"
+    /// Header magic while InitSlab is zeroing a slab in chunks. Anything
+    /// but `MAGIC` reads as uninitialized, so such a slab takes no other
+    /// instruction until InitMarket finalizes it.
+    pub const INITIALIZING_MAGIC: u64 = 0x50455243494e4954; // "PERCINIT"
 
     pub const HEADER_LEN: usize = size_of::<SlabHeader>();
     pub const CONFIG_LEN: usize = size_of::<MarketConfig>();
@@ -37,7 +41,227 @@ pub mod constants {
     /// Used as lp_account_id for per-instance identity across slot reuse.
     pub const GEN_TABLE_OFF: usize = RISK_BUF_OFF + RISK_BUF_LEN;
     pub const GEN_TABLE_LEN: usize = percolator::MAX_ACCOUNTS * 8; // u64 per slot
-    pub const SLAB_LEN: usize = GEN_TABLE_OFF + GEN_TABLE_LEN;
+
+    /// Start of the wrapper's tail regions: everything a version-0 slab
+    /// holds comes first and never moves. Regions are only ever appended,
+    /// with a `SLAB_VERSION` bump, so MigrateSlab can grow a live slab in
+    /// place.
+    pub const TAIL_OFF: usize = GEN_TABLE_OFF + GEN_TABLE_LEN;
+
+    /// Per-account ring of recent trade `client_order_id`s. A repeat of
+    /// any id in the user's ring is a no-op, so bots can retry a trade
+    /// after an RPC timeout without risking a double fill.
+    pub const ORDER_ID_RING: usize = 4;
+    pub const ORDER_ID_TABLE_OFF: usize = TAIL_OFF;
+    pub const ORDER_ID_TABLE_LEN: usize = percolator::MAX_ACCOUNTS * ORDER_ID_RING * 8;
+    /// Per-account replay nonce (u64) for meta-transaction flows. Checked
+    /// and bumped by WithdrawCollateral / CloseAccount when the caller
+    /// supplies one, and by every DepositWithPermit. Never reset,
+    /// including on slot reuse.
+    pub const USER_NONCE_TABLE_OFF: usize = ORDER_ID_TABLE_OFF + ORDER_ID_TABLE_LEN;
+    pub const USER_NONCE_TABLE_LEN: usize = percolator::MAX_ACCOUNTS * 8;
+    /// Guarded-launch limits for user accounts: max capital and max
+    /// position notional (u128 each, engine units; 0 = unlimited). Kept in
+    /// the wrapper because `RiskParams` belongs to the engine crate.
+    pub const ACCOUNT_LIMITS_OFF: usize = USER_NONCE_TABLE_OFF + USER_NONCE_TABLE_LEN;
+    pub const ACCOUNT_LIMITS_LEN: usize = 32;
+    /// Permissioned-market allowlist: a 32-byte merkle root over owner
+    /// pubkeys. All zeros = open market. Set by SetAllowlistRoot.
+    pub const ALLOWLIST_ROOT_OFF: usize = ACCOUNT_LIMITS_OFF + ACCOUNT_LIMITS_LEN;
+    pub const ALLOWLIST_ROOT_LEN: usize = 32;
+    /// Max merkle proof depth accepted in instruction data (2^16 owners).
+    pub const MAX_ALLOWLIST_PROOF_LEN: usize = 16;
+    /// Domain separator hashed ahead of the owner key to form a leaf.
+    pub const ALLOWLIST_LEAF_DOMAIN: &[u8] = b"percolator:allowlist";
+    /// Trading-fee routing set by SetFeeSplit: insurance/treasury bps and
+    /// an enable flag, then the u128 protocol-fee accumulator at +16.
+    pub const FEE_SPLIT_OFF: usize = ALLOWLIST_ROOT_OFF + ALLOWLIST_ROOT_LEN;
+    pub const FEE_SPLIT_LEN: usize = 32;
+    /// Fee authority for ClaimProtocolFees, distinct from admin. Set to
+    /// the creator at InitMarket; rotated or burned via UpdateAuthority.
+    pub const FEE_AUTHORITY_OFF: usize = FEE_SPLIT_OFF + FEE_SPLIT_LEN;
+    pub const FEE_AUTHORITY_LEN: usize = 32;
+    /// Competition epoch clock set by SetEpochLength: epoch length in
+    /// slots (0 = never rolls), current epoch, and its start slot.
+    pub const EPOCH_CONFIG_OFF: usize = FEE_AUTHORITY_OFF + FEE_AUTHORITY_LEN;
+    pub const EPOCH_CONFIG_LEN: usize = 32;
+    /// Per-account epoch PnL/volume counters, `EPOCH_STATS_ENTRY_LEN`
+    /// bytes per slot. Reset lazily on the account's first touch in a new
+    /// epoch; the previous epoch's totals are kept alongside.
+    pub const EPOCH_STATS_ENTRY_LEN: usize = 56;
+    pub const EPOCH_STATS_TABLE_OFF: usize = EPOCH_CONFIG_OFF + EPOCH_CONFIG_LEN;
+    pub const EPOCH_STATS_TABLE_LEN: usize = percolator::MAX_ACCOUNTS * EPOCH_STATS_ENTRY_LEN;
+    /// Crank-liveness guard: `max_crank_staleness_slots` from InitMarket,
+    /// the last live KeeperCrank slot, and the withdraw-only flag.
+    pub const CRANK_GUARD_OFF: usize = EPOCH_STATS_TABLE_OFF + EPOCH_STATS_TABLE_LEN;
+    pub const CRANK_GUARD_LEN: usize = 32;
+    /// EnterWithdrawOnly unlocks once no crank has run for
+    /// `max_crank_staleness_slots * WITHDRAW_ONLY_STALENESS_MULTIPLIER`.
+    pub const WITHDRAW_ONLY_STALENESS_MULTIPLIER: u64 = 10;
+    /// Optional M-of-N admin key set set by SetAdminMultisig: threshold,
+    /// key count, then up to `MAX_ADMIN_SIGNERS` keys at +8. Threshold 0 =
+    /// single-key admin (`header.admin`).
+    pub const MAX_ADMIN_SIGNERS: usize = 5;
+    pub const ADMIN_MULTISIG_OFF: usize = CRANK_GUARD_OFF + CRANK_GUARD_LEN;
+    pub const ADMIN_MULTISIG_LEN: usize = 8 + MAX_ADMIN_SIGNERS * 32;
+    /// Pending admin actions collecting approvals: action hash, approval
+    /// bitmap (bit i = key i) and the slot of the first approval.
+    pub const MAX_PENDING_ADMIN_ACTIONS: usize = 4;
+    pub const PENDING_ADMIN_ACTION_LEN: usize = 48;
+    pub const PENDING_ADMIN_ACTIONS_OFF: usize = ADMIN_MULTISIG_OFF + ADMIN_MULTISIG_LEN;
+    pub const PENDING_ADMIN_ACTIONS_LEN: usize =
+        MAX_PENDING_ADMIN_ACTIONS * PENDING_ADMIN_ACTION_LEN;
+    /// Admin nominated by ProposeAdmin, waiting for AcceptAdmin. All zeros
+    /// = none.
+    pub const PENDING_ADMIN_OFF: usize = PENDING_ADMIN_ACTIONS_OFF + PENDING_ADMIN_ACTIONS_LEN;
+    pub const PENDING_ADMIN_LEN: usize = 32;
+    /// Dust floors set by SetDustLimits: min user fill size and min
+    /// nonzero user position (u128 q-units each, 0 = off), then the LP that
+    /// absorbs crank dust sweeps (u16 at +32).
+    pub const DUST_LIMITS_OFF: usize = PENDING_ADMIN_OFF + PENDING_ADMIN_LEN;
+    pub const DUST_LIMITS_LEN: usize = 48;
+    /// Max dust positions a KeeperCrank closes. Each is a full engine fill,
+    /// so this stays well below the candidate cap.
+    pub const DUST_SWEEP_BUDGET: usize = 2;
+    /// Circuit breaker set by SetCircuitBreaker: max_move_bps, window_slots,
+    /// cooldown_slots, then the anchor price, anchor slot and the slot the
+    /// cooling-off period ends (u64 each).
+    pub const CIRCUIT_BREAKER_OFF: usize = DUST_LIMITS_OFF + DUST_LIMITS_LEN;
+    pub const CIRCUIT_BREAKER_LEN: usize = 48;
+    /// Market history ring written by KeeperCrank: a 16-byte cursor (next
+    /// write index u32, sample count u32), then `HISTORY_CAP` samples of
+    /// slot, oracle price, mark EWMA, funding rate (i64, e9 per slot) and
+    /// long/short OI (u128 q-units). Frontends chart from one fetch.
+    pub const HISTORY_CAP: usize = 256;
+    pub const HISTORY_SAMPLE_LEN: usize = 64;
+    pub const HISTORY_OFF: usize = CIRCUIT_BREAKER_OFF + CIRCUIT_BREAKER_LEN;
+    pub const HISTORY_LEN: usize = 16 + HISTORY_CAP * HISTORY_SAMPLE_LEN;
+    /// Min slots between history samples (~1 min), so the ring covers
+    /// about four hours however often keepers crank.
+    pub const HISTORY_SAMPLE_INTERVAL_SLOTS: u64 = 150;
+    /// Per-LP performance counters, `LP_STATS_CAP` entries keyed by LP
+    /// index and materialization generation (0 = free). An entry whose
+    /// generation no longer matches its index is stale and reused.
+    pub const LP_STATS_CAP: usize = 16;
+    pub const LP_STATS_ENTRY_LEN: usize = 96;
+    pub const LP_STATS_OFF: usize = HISTORY_OFF + HISTORY_LEN;
+    pub const LP_STATS_LEN: usize = LP_STATS_CAP * LP_STATS_ENTRY_LEN;
+    /// Trade rate limit set by SetTradeRateLimit: min slots between a
+    /// user's fills (u64, 0 = off; 8 reserved bytes), then each account's
+    /// last fill slot (u64 per slot, 0 = never).
+    pub const TRADE_RATE_OFF: usize = LP_STATS_OFF + LP_STATS_LEN;
+    pub const TRADE_RATE_LEN: usize = 16 + percolator::MAX_ACCOUNTS * 8;
+    /// Cap on `min_slots_between_trades` (~1 min), so a misconfigured
+    /// market cannot hold users in a position for long.
+    pub const MAX_MIN_SLOTS_BETWEEN_TRADES: u64 = 150;
+    /// Permissioned liquidation window set by SetLiquidatorWindow:
+    /// window_slots (u64), liquidator count (u8 at +8), up to
+    /// `MAX_LIQUIDATORS` keys at +16, then each account's flag slot (u64
+    /// per slot, 0 = not flagged): the first crank that left it below
+    /// maintenance.
+    pub const MAX_LIQUIDATORS: usize = 4;
+    pub const LIQ_WINDOW_OFF: usize = TRADE_RATE_OFF + TRADE_RATE_LEN;
+    pub const LIQ_WINDOW_LEN: usize = 16 + MAX_LIQUIDATORS * 32 + percolator::MAX_ACCOUNTS * 8;
+    /// Cap on the permissioned window (~1 min): past it, liquidation opens
+    /// to every keeper whether or not the liquidator set shows up.
+    pub const MAX_LIQUIDATION_WINDOW_SLOTS: u64 = 150;
+    /// Backstop vault: the vault's engine account index (u16), its share
+    /// of a crank's net liquidation insurance growth (u16 bps at +2), the
+    /// account's materialization generation (u64 at +8, 0 = no vault),
+    /// lifetime rewards paid (u128 at +16), total shares (u128 at +32),
+    /// withdrawal delay in slots (u64 at +48), units left by a vault
+    /// closed after resolution (u64 at +56), then `MAX_BACKSTOP_DEPOSITORS`
+    /// depositor entries of `BACKSTOP_DEPOSITOR_LEN` bytes: owner
+    /// ([u8; 32]), shares (u128 at +32), shares pending withdrawal (u128
+    /// at +48), request slot (u64 at +64).
+    pub const MAX_BACKSTOP_DEPOSITORS: usize = 16;
+    pub const BACKSTOP_DEPOSITOR_LEN: usize = 80;
+    pub const BACKSTOP_OFF: usize = LIQ_WINDOW_OFF + LIQ_WINDOW_LEN;
+    pub const BACKSTOP_LEN: usize = 64 + MAX_BACKSTOP_DEPOSITORS * BACKSTOP_DEPOSITOR_LEN;
+    /// Cap on the backstop share: insurance keeps at least half of what
+    /// liquidations bring in.
+    pub const MAX_BACKSTOP_SHARE_BPS: u16 = 5_000;
+    /// Bounds on the backstop withdrawal delay (~1 min to ~1 day). The
+    /// floor also applies before the admin has set one, so nobody can
+    /// deposit ahead of a known liquidation and leave right after it.
+    pub const MIN_BACKSTOP_WITHDRAW_DELAY_SLOTS: u64 = 150;
+    pub const MAX_BACKSTOP_WITHDRAW_DELAY_SLOTS: u64 = 216_000;
+    /// Stale-oracle fallback set by SetStaleFallback: grace slots (u64),
+    /// haircut ramp slots (u64 at +8), max haircut (u16 bps at +16).
+    pub const STALE_FALLBACK_OFF: usize = BACKSTOP_OFF + BACKSTOP_LEN;
+    pub const STALE_FALLBACK_LEN: usize = 24;
+    /// Cap on the stale-fallback withdrawal haircut.
+    pub const MAX_STALE_HAIRCUT_BPS: u16 = 5_000;
+    /// Open-position bitmap: one bit per account slot, laid out like the
+    /// engine's `used` words. Trades set or clear the bits of both sides
+    /// and a dust sweep sets its LP's; liquidation, ADL and close leave the
+    /// bit set until the crank's risk scan finds the account flat. Always
+    /// a superset of the accounts holding a position.
+    pub const POSITION_BITMAP_OFF: usize = STALE_FALLBACK_OFF + STALE_FALLBACK_LEN;
+    pub const POSITION_BITMAP_LEN: usize = percolator::MAX_ACCOUNTS / 8;
+    /// Liquidation watch index: anchor price (u64), anchor slot (u64 at
+    /// +8), refresh cursor (u64 at +16), then a bitmap of watched accounts
+    /// in the `used` word layout. An open account leaves the set only when
+    /// `policy::liq_watch_clear_ok` shows no price inside the anchor band
+    /// can bring it to maintenance.
+    pub const LIQ_WATCH_OFF: usize = POSITION_BITMAP_OFF + POSITION_BITMAP_LEN;
+    pub const LIQ_WATCH_LEN: usize = 24 + percolator::MAX_ACCOUNTS / 8;
+    /// Half-width of the watch band, in bps of the anchor price. A crank
+    /// whose price, widened by worst-case funding since the anchor, leaves
+    /// the band re-anchors and watches every open account again.
+    pub const LIQ_WATCH_BAND_BPS: u64 = 1_000;
+    /// Re-anchor at least this often (~1 hour at 400ms slots), bounding
+    /// the maintenance-fee drift the band does not model.
+    pub const LIQ_WATCH_MAX_AGE_SLOTS: u64 = 9_000;
+    /// Watched accounts re-checked per crank.
+    pub const LIQ_WATCH_SCAN_WINDOW: usize = 8;
+    /// Per-account PnL ledger for statements: a 32-byte market header
+    /// (cumulative funding index i128, its slot u64 at +16), then
+    /// `PNL_LEDGER_ENTRY_LEN` bytes per slot: ledger position (i128),
+    /// funding-index snapshot (i128 at +16), entry price (u64 at +32),
+    /// realized PnL (i64 at +40), trading fees paid (u64 at +48) and
+    /// funding paid (i64 at +56). Reset when the slot is materialized.
+    pub const PNL_LEDGER_ENTRY_LEN: usize = 64;
+    pub const PNL_LEDGER_OFF: usize = LIQ_WATCH_OFF + LIQ_WATCH_LEN;
+    pub const PNL_LEDGER_LEN: usize = 32 + percolator::MAX_ACCOUNTS * PNL_LEDGER_ENTRY_LEN;
+    /// Approvals older than this are dropped (~1 day at 400ms slots).
+    pub const ADMIN_ACTION_TTL_SLOTS: u64 = 216_000;
+    /// Domain separator for `policy::admin_action_hash`.
+    pub const ADMIN_ACTION_DOMAIN: &[u8] = b"percolator:admin-action";
+
+    /// Market labels set at InitMarket or by SetMarketMetadata: symbol
+    /// ([u8; 16]), oracle description ([u8; 32] at +16), project URI
+    /// hash ([u8; 32] at +48), base asset decimals (u8 at +80).
+    pub const MARKET_METADATA_OFF: usize = PNL_LEDGER_OFF + PNL_LEDGER_LEN;
+    pub const MARKET_METADATA_LEN: usize = 96;
+    /// Wire size of the metadata: the stored fields, without padding.
+    pub const MARKET_METADATA_WIRE_LEN: usize = 16 + 32 + 32 + 1;
+    /// SPL mints allow more, but no price in this program is scaled past it.
+    pub const MAX_BASE_DECIMALS: u8 = 18;
+    /// Market pointer account created by RegisterMarket at
+    /// `["market", collateral_mint, index_feed_id, index]`: magic, slab
+    /// key (at +8), PDA bump (u8 at +40).
+    pub const MARKET_POINTER_LEN: usize = 48;
+    pub const MARKET_POINTER_MAGIC: [u8; 8] = *b"PERCMKT1";
+
+    /// Hyperp mark pushers: `MAX_HYPERP_PUSHERS` entries of
+    /// `HYPERP_PUSHER_LEN` bytes: key ([u8; 32]), last push slot (u64 at
+    /// +32), push count (u64 at +40). Entry 0 tracks the mark authority
+    /// (`config.hyperp_authority`) and restarts when that key rotates;
+    /// entries 1.. hold the extra keys added by AddHyperpPusher. A zero
+    /// key is a free entry.
+    pub const MAX_HYPERP_PUSHERS: usize = 4;
+    pub const HYPERP_PUSHER_LEN: usize = 48;
+    pub const HYPERP_PUSHERS_OFF: usize = MARKET_METADATA_OFF + MARKET_METADATA_LEN;
+    pub const HYPERP_PUSHERS_LEN: usize = MAX_HYPERP_PUSHERS * HYPERP_PUSHER_LEN;
+
+    pub const SLAB_LEN: usize = HYPERP_PUSHERS_OFF + HYPERP_PUSHERS_LEN;
+    pub const TAIL_LEN: usize = SLAB_LEN - TAIL_OFF;
+    /// Layout version InitMarket writes to `SlabHeader::version`.
+    /// Version 0 ends at `TAIL_OFF`; version 1 adds the tail regions.
+    pub const SLAB_VERSION: u32 = 1;
+    /// Slab length of each layout version, indexed by version.
+    pub const SLAB_LEN_BY_VERSION: [usize; SLAB_VERSION as usize + 1] = [TAIL_OFF, SLAB_LEN];
 
     /// Progressive risk-buffer discovery window per crank. Kept small because
     /// this runs after the engine's liquidation cascade; dense worst-case
@@ -224,6 +448,37 @@ pub mod constants {
     pub const MAX_MATCHER_TAIL_ACCOUNTS: usize = 32;
     pub const MATCHER_CALL_TAG: u8 = 0;
     pub const MATCHER_CALL_LEN: usize = 67;
+    /// Ceiling on a vAMM's combined fee + spread + impact (`policy::vamm_params_ok`).
+    pub const MAX_VAMM_TOTAL_BPS: u32 = 5_000;
+
+    /// Trade flags byte (optional TradeNoCpi / TradeCpi tail).
+    /// Reduce-only: the fill is clamped so the user's position never grows
+    /// or flips sign; a trade that cannot reduce is rejected.
+    pub const TRADE_FLAG_REDUCE_ONLY: u8 = 1 << 0;
+    /// TradeCpi only: `limit_price_e6` carries a max slippage in bps versus
+    /// the oracle price read by this instruction, instead of a raw price.
+    pub const TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS: u8 = 1 << 1;
+    /// TradeCpi only: fill-or-kill. Any matcher fill smaller than the
+    /// (reduce-only clamped) request fails the whole instruction. Without
+    /// it TradeCpi is immediate-or-cancel: partial fills are accepted and
+    /// the remainder is dropped. IOC and FOK are the only time-in-force
+    /// values: nothing rests, so GTC, post-only and expiry have no meaning.
+    pub const TRADE_FLAG_FILL_OR_KILL: u8 = 1 << 2;
+    pub const TRADE_FLAGS_MASK: u8 =
+        TRADE_FLAG_REDUCE_ONLY | TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS | TRADE_FLAG_FILL_OR_KILL;
+
+    /// Optional integrator tag on DepositCollateral, TradeNoCpi and
+    /// TradeCpi, echoed into the logs as a `ClientTagEvent`. Opaque to the
+    /// program; all zeros = untagged.
+    pub const CLIENT_TAG_LEN: usize = 16;
+
+    /// Signed PushHyperpMark payload: slab pubkey (32) || price_e6 (8, LE)
+    /// || timestamp (8, LE). The slab key binds the signature to one market.
+    pub const HYPERP_MARK_PAYLOAD_LEN: usize = 48;
+
+    /// Signed DepositWithPermit payload: slab pubkey (32) || user_idx (2)
+    /// || amount (8) || nonce (8) || expiry (8), all LE.
+    pub const PERMIT_DEPOSIT_PAYLOAD_LEN: usize = 58;
 
     /// Sentinel value for permissionless crank (no caller account required)
     pub const CRANK_NO_CALLER: u16 = u16::MAX;
@@ -329,6 +584,33 @@ pub mod constants {
     pub const ORACLE_LEG_FLAG_DIVIDE_LEG2: u8 = 1 << 1;
     pub const ORACLE_LEG_FLAG_DIVIDE_LEG3: u8 = 1 << 2;
     pub const ORACLE_LEG_FLAGS_MASK: u8 = ORACLE_LEG_FLAG_DIVIDE_LEG2 | ORACLE_LEG_FLAG_DIVIDE_LEG3;
+    /// Bit 0 of `oracle_leg_flags` (external markets only): a fresh oracle
+    /// observation further from the engine price than the per-slot
+    /// price-move cap allows is rejected (`OracleInvalid`) instead of being
+    /// stair-cased toward. Off = clamp, the default.
+    pub const ORACLE_FLAG_REJECT_JUMPS: u8 = 1 << 0;
+
+    /// Per-instruction compute-unit ceilings, `(tag, cu)`, for client
+    /// budget requests. `benchmark_all_instructions` asserts every measured
+    /// instruction fits its entry on the default tier, so a change that
+    /// raises a cost must raise the entry too. TradeCpi excludes the
+    /// matcher's own CPI cost. Unlisted tags use `CU_ESTIMATE_DEFAULT`.
+    pub const CU_ESTIMATES: &[(u8, u32)] = &[
+        (1, 60_000),    // InitUser
+        (2, 60_000),    // InitLP
+        (3, 60_000),    // DepositCollateral
+        (4, 150_000),   // WithdrawCollateral
+        (5, 1_400_000), // KeeperCrank (worst case; see cu_benchmark)
+        (6, 200_000),   // TradeNoCpi
+        (8, 100_000),   // CloseAccount
+        (9, 60_000),    // TopUpInsurance
+        (10, 250_000),  // TradeCpi
+        (14, 150_000),  // UpdateConfig
+        (17, 100_000),  // PushHyperpMark
+    ];
+    pub const CU_ESTIMATE_DEFAULT: u32 = 200_000;
+    /// Per-transaction compute-unit cap.
+    pub const MAX_TX_CU: u32 = 1_400_000;
 }
 
 // =============================================================================
@@ -505,6 +787,14 @@ pub mod policy {
             && shape.ctx_len_ok
     }
 
+    /// Context binding: the LP PDA recorded inside the matcher context
+    /// must be this LP's PDA. Without it an LP could register a context
+    /// that a matcher set up for another market or another LP slot.
+    #[inline]
+    pub fn matcher_ctx_bound(ctx_lp_pda: [u8; 32], expected_lp_pda: [u8; 32]) -> bool {
+        ctx_lp_pda != [0u8; 32] && ctx_lp_pda == expected_lp_pda
+    }
+
     /// Check if context length meets minimum requirement.
     #[inline]
     pub fn ctx_len_sufficient(len: usize) -> bool {
@@ -538,6 +828,48 @@ pub mod policy {
         exec_size // Must use exec_size, never requested_size
     }
 
+    /// Pre-CPI capability gate. A request above the matcher's declared
+    /// `max_fill_abs` is only worth sending if the matcher can partially
+    /// fill it and the taker accepts a partial fill.
+    #[inline]
+    pub fn matcher_request_ok(
+        caps_flags: u32,
+        max_fill_abs: u128,
+        req_abs: u128,
+        fill_or_kill: bool,
+    ) -> bool {
+        max_fill_abs == 0
+            || req_abs <= max_fill_abs
+            || (caps_flags & crate::matcher_abi::CAP_PARTIAL_FILL != 0 && !fill_or_kill)
+    }
+
+    /// Post-CPI capability check: the fill stays within what the matcher
+    /// declared, so a header cannot advertise limits it does not keep.
+    #[inline]
+    pub fn matcher_fill_within_caps(
+        caps_flags: u32,
+        max_fill_abs: u128,
+        exec_abs: u128,
+        req_abs: u128,
+    ) -> bool {
+        (max_fill_abs == 0 || exec_abs <= max_fill_abs)
+            && (caps_flags & crate::matcher_abi::CAP_PARTIAL_FILL != 0 || exec_abs == req_abs)
+    }
+
+    /// Post-CPI price bound: a fill executes within `initial_margin_bps` of
+    /// the oracle price the request carried. A worse price hands one side
+    /// an instant loss larger than the margin it posted, so a compromised
+    /// matcher could push an LP into bad debt the insurance fund absorbs.
+    #[inline]
+    pub fn exec_price_within_margin(
+        exec_price_e6: u64,
+        oracle_price_e6: u64,
+        initial_margin_bps: u64,
+    ) -> bool {
+        let diff = exec_price_e6.abs_diff(oracle_price_e6) as u128;
+        diff * 10_000 <= oracle_price_e6 as u128 * initial_margin_bps as u128
+    }
+
     // =========================================================================
     // Account validation helpers
     // =========================================================================
@@ -703,6 +1035,177 @@ pub mod policy {
         Some(per_slot.clamp(-max_rate_e9, max_rate_e9))
     }
 
+    /// Worst-case funding, in bps of notional, that one side can pay over
+    /// `slots` under the per-slot clamp: `ceil(funding_max_e9_per_slot *
+    /// slots / 100_000)` (1 bps = 10^5 e9). With `slots =
+    /// MAX_ACCRUAL_DT_SLOTS` this bounds a single accrual; `None` for a
+    /// negative cap.
+    #[inline]
+    pub fn funding_bps_bound(funding_max_e9_per_slot: i64, slots: u64) -> Option<u128> {
+        if funding_max_e9_per_slot < 0 {
+            return None;
+        }
+        mul_div_ceil(funding_max_e9_per_slot as u128, slots as u128, 100_000)
+    }
+
+    /// Adverse price move (e6) an account can take from its current price
+    /// before equity reaches maintenance, with the requirement growing at
+    /// `mm_bps` of notional on the way: `(equity - mm_req) * POS_SCALE /
+    /// (|q| * (1 + mm_bps / 10_000))`, floored at each step. 0 at or below
+    /// maintenance, `u64::MAX` when flat.
+    pub fn liq_distance_e6(equity: i128, mm_req: u128, abs_q: u128, mm_bps: u64) -> u64 {
+        if abs_q == 0 {
+            return u64::MAX;
+        }
+        if equity <= 0 || equity as u128 <= mm_req {
+            return 0;
+        }
+        let excess = equity as u128 - mm_req;
+        let per_unit = mul_div_floor(excess, percolator::POS_SCALE, abs_q)
+            .unwrap_or_else(|| (excess / abs_q).saturating_mul(percolator::POS_SCALE));
+        let grow = 10_000u128 + mm_bps as u128;
+        let d = mul_div_floor(per_unit, 10_000, grow)
+            .unwrap_or_else(|| (per_unit / grow).saturating_mul(10_000));
+        d.min(u64::MAX as u128) as u64
+    }
+
+    /// One account's inputs to [`liquidation_price_e6`].
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct LiqPriceInputs {
+        /// Effective position (after ADL scaling).
+        pub position_q: i128,
+        /// `capital + pnl`, valued at `price_e6`.
+        pub equity: i128,
+        pub price_e6: u64,
+        pub maintenance_margin_bps: u64,
+        pub min_nonzero_mm_req: u128,
+        /// Per-slot funding rate (e9); positive means longs pay shorts.
+        pub funding_rate_e9: i128,
+        /// Wrapper maintenance fee charged per account per slot.
+        pub maintenance_fee_per_slot: u128,
+        /// Slots of funding and maintenance fees charged before the price
+        /// moves; 0 prices the account as it stands.
+        pub horizon_slots: u64,
+    }
+
+    /// Equity after `horizon_slots` of maintenance fees and funding at
+    /// `price_e6` notional. Funding is paid by the side the rate points at
+    /// and received by the other; payments round up, receipts down.
+    pub fn liq_projected_equity(i: &LiqPriceInputs) -> Option<i128> {
+        let fees = i
+            .maintenance_fee_per_slot
+            .checked_mul(i.horizon_slots as u128)?;
+        let notional = mul_div_ceil(
+            i.position_q.unsigned_abs(),
+            i.price_e6 as u128,
+            percolator::POS_SCALE,
+        )?;
+        let flow = notional
+            .checked_mul(i.funding_rate_e9.unsigned_abs())?
+            .checked_mul(i.horizon_slots as u128)?;
+        let funding = if (i.position_q > 0) == (i.funding_rate_e9 > 0) {
+            -i128::try_from(flow.div_ceil(1_000_000_000)).ok()?
+        } else {
+            i128::try_from(flow / 1_000_000_000).ok()?
+        };
+        i.equity
+            .checked_sub(i128::try_from(fees).ok()?)?
+            .checked_add(funding)
+    }
+
+    /// Whether an account with `equity` at `price_e6` is below maintenance
+    /// once the oracle reads `p`. The move is marked against the account
+    /// (losses round up, gains down) and the requirement is QueryHealth's:
+    /// `max(ceil(|q| * p / POS_SCALE) * mm_bps / 10_000, min_nonzero_mm_req)`.
+    fn liquidatable_at(i: &LiqPriceInputs, equity: i128, p: u64) -> bool {
+        use percolator::wide_math::{mul_div_ceil_u128, mul_div_floor_u128};
+        let abs = i.position_q.unsigned_abs();
+        let moved = p.abs_diff(i.price_e6) as u128;
+        let pnl = if (i.position_q > 0) == (p > i.price_e6) {
+            mul_div_floor_u128(abs, moved, percolator::POS_SCALE).min(i128::MAX as u128) as i128
+        } else {
+            -(mul_div_ceil_u128(abs, moved, percolator::POS_SCALE).min(i128::MAX as u128) as i128)
+        };
+        let notional = mul_div_ceil_u128(abs, p as u128, percolator::POS_SCALE);
+        let req = core::cmp::max(
+            notional.saturating_mul(i.maintenance_margin_bps as u128) / 10_000,
+            i.min_nonzero_mm_req,
+        );
+        equity.saturating_add(pnl) < req.min(i128::MAX as u128) as i128
+    }
+
+    /// Oracle price (e6) at which the account becomes liquidatable after
+    /// `horizon_slots` of fees and funding: the highest such price for a
+    /// long, the lowest for a short. At or past the current price means it
+    /// is (or will be by then) liquidatable already. `None` when flat, when
+    /// no price in `0..=u64::MAX` reaches maintenance, or for
+    /// `maintenance_margin_bps >= 10_000`, where health no longer moves
+    /// one way with the price.
+    ///
+    /// The boundary is found by bisection over `liquidatable_at`, so it is
+    /// exact under that rounding rather than a closed-form approximation.
+    pub fn liquidation_price_e6(i: &LiqPriceInputs) -> Option<u64> {
+        if i.position_q == 0 || i.maintenance_margin_bps >= 10_000 {
+            return None;
+        }
+        let equity = liq_projected_equity(i)?;
+        let long = i.position_q > 0;
+        let (mut lo, mut hi) = (0u64, u64::MAX);
+        // Liquidatable on 0..=x for a long, on x..=u64::MAX for a short.
+        match (
+            long,
+            liquidatable_at(i, equity, lo),
+            liquidatable_at(i, equity, hi),
+        ) {
+            (true, false, _) | (false, _, false) => return None,
+            (true, _, true) => return Some(u64::MAX),
+            (false, true, _) => return Some(0),
+            _ => {}
+        }
+        // Longs: `lo` liquidatable, `hi` not. Shorts: the reverse.
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if liquidatable_at(i, equity, mid) == long {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Some(if long { lo } else { hi })
+    }
+
+    /// Whether an account `distance_e6` from maintenance at `price` may
+    /// leave the watch set anchored at `anchor_price`: the band, measured
+    /// from wherever `price` sits relative to the anchor, must fit inside
+    /// the distance.
+    pub fn liq_watch_clear_ok(
+        distance_e6: u64,
+        price: u64,
+        anchor_price: u64,
+        band_bps: u64,
+    ) -> bool {
+        if anchor_price == 0 {
+            return false;
+        }
+        let band = anchor_price as u128 * band_bps as u128 / 10_000;
+        distance_e6 as u128 >= band + price.abs_diff(anchor_price) as u128
+    }
+
+    /// Whether the watch set anchored at `anchor_price` still holds every
+    /// account that can be below maintenance at `price`, after `drift_bps`
+    /// of worst-case funding since the anchor (charged as an equal price
+    /// move on the larger of the two prices).
+    pub fn liq_watch_covers(anchor_price: u64, price: u64, drift_bps: u128, band_bps: u64) -> bool {
+        if anchor_price == 0 {
+            return false;
+        }
+        let band = anchor_price as u128 * band_bps as u128 / 10_000;
+        let Some(drift) = mul_div_ceil(anchor_price.max(price) as u128, drift_bps, 10_000) else {
+            return false;
+        };
+        (price.abs_diff(anchor_price) as u128).saturating_add(drift) < band
+    }
+
     /// KeeperCrank partial-catchup decision. When the market clock is too far
     /// behind for one inline accrual, the crank may commit one bounded
     /// equity-active segment while keeping the authenticated wall-clock slot
@@ -1417,9 +1920,7 @@ pub mod policy {
             return Some(0);
         }
         let diff = if new > old { new - old } else { old - new } as u128;
-        let num = diff.checked_mul(10_000)?;
-        let den = old as u128;
-        let bps = num.checked_add(den.checked_sub(1)?)? / den;
+        let bps = mul_div_ceil(diff, 10_000, old as u128)?;
         if bps > u64::MAX as u128 {
             None
         } else {
@@ -1432,7 +1933,7 @@ pub mod policy {
         if notional == 0 || fee_bps == 0 {
             return Some(0);
         }
-        let one_side = notional.checked_mul(fee_bps as u128)?.checked_add(9_999)? / 10_000;
+        let one_side = mul_div_ceil(notional, fee_bps as u128, 10_000)?;
         let both_sides = one_side.checked_mul(2)?;
         if both_sides > u64::MAX as u128 {
             None
@@ -1449,6 +1950,29 @@ pub mod policy {
         Some(num.checked_add(den.checked_sub(1)?)? / den)
     }
 
+    /// Fixed-point `floor(a * b / d)`; `None` on overflow or `d == 0`.
+    ///
+    /// Rounding rule for wrapper arithmetic: amounts the vault pays out
+    /// (rewards, redemptions, shares minted) use the floor variant;
+    /// amounts the vault or an LP charges (fees, spreads, worst-case price
+    /// bounds) use `mul_div_ceil`. The residue therefore always stays with
+    /// the protocol side. Engine-internal math (PnL, funding, margin) is
+    /// the `percolator` crate's and is not re-implemented here.
+    #[inline]
+    pub fn mul_div_floor(a: u128, b: u128, d: u128) -> Option<u128> {
+        if d == 0 {
+            return None;
+        }
+        Some(a.checked_mul(b)? / d)
+    }
+
+    /// Fixed-point `ceil(a * b / d)`; `None` on overflow or `d == 0`.
+    /// See `mul_div_floor` for which side of a transfer uses which.
+    #[inline]
+    pub fn mul_div_ceil(a: u128, b: u128, d: u128) -> Option<u128> {
+        ceil_div_u128(a.checked_mul(b)?, d)
+    }
+
     /// Compute the wrapper fee for Hyperp after-hours trading.
     ///
     /// The base product rule is:
@@ -1529,13 +2053,11 @@ pub mod policy {
             let mark_move_bps = price_move_bps_ceil(old_mark_e6, next_mark)?;
             let charged_move_bps = core::cmp::max(mark_move_bps, min_externality_bps);
             let base_fee_paid = two_sided_trade_fee_paid_cap(trade_notional, base_fee_bps)? as u128;
-            let mark_move_fee = ceil_div_u128(
-                mark_externality_notional.checked_mul(charged_move_bps as u128)?,
-                10_000,
-            )?;
+            let mark_move_fee =
+                mul_div_ceil(mark_externality_notional, charged_move_bps as u128, 10_000)?;
             let required_total_fee = base_fee_paid.checked_add(mark_move_fee)?;
             let denominator = trade_notional.checked_mul(2)?;
-            let needed_u128 = ceil_div_u128(required_total_fee.checked_mul(10_000)?, denominator)?;
+            let needed_u128 = mul_div_ceil(required_total_fee, 10_000, denominator)?;
             if needed_u128 > u64::MAX as u128 {
                 return None;
             }
@@ -1551,647 +2073,2468 @@ pub mod policy {
         }
         None
     }
-}
 
-// 2. mod zc (Zero-Copy unsafe island)
-#[allow(unsafe_code)]
-pub mod zc {
-    use crate::constants::{ENGINE_ALIGN, ENGINE_LEN, ENGINE_OFF};
-    use core::mem::offset_of;
-    use percolator::RiskEngine;
-    use solana_program::program_error::ProgramError;
+    /// Client order-id dedup. `0` means "no id" and is never a duplicate.
+    pub fn order_id_seen(ring: &[u64; crate::constants::ORDER_ID_RING], id: u64) -> bool {
+        id != 0 && ring.contains(&id)
+    }
 
-    // Use const to export the actual offset for debugging
-    pub const ACCOUNTS_OFFSET: usize = offset_of!(RiskEngine, accounts);
+    /// Record `id` as the newest entry, evicting the oldest. No-op for 0.
+    pub fn order_id_push(ring: &mut [u64; crate::constants::ORDER_ID_RING], id: u64) {
+        if id == 0 {
+            return;
+        }
+        ring.copy_within(0..crate::constants::ORDER_ID_RING - 1, 1);
+        ring[0] = id;
+    }
 
-    /// Offset of side_mode_long within RiskEngine (repr(u8) enum)
-    const SM_LONG_OFF: usize = offset_of!(RiskEngine, side_mode_long);
-    /// Offset of side_mode_short within RiskEngine (repr(u8) enum)
-    const SM_SHORT_OFF: usize = offset_of!(RiskEngine, side_mode_short);
-    /// Offset of market_mode within RiskEngine (repr(u8) enum)
-    const MM_OFF: usize = offset_of!(RiskEngine, market_mode);
-    /// Offset of bankruptcy_hmax_lock_active within RiskEngine (bool)
-    const BANKRUPTCY_HMAX_LOCK_ACTIVE_OFF: usize =
-        offset_of!(RiskEngine, bankruptcy_hmax_lock_active);
-    // Runtime tripwire: a unit test in tests/unit.rs
-    // (`test_zc_cast_safety_invariant`) asserts that no slab-persisted
-    // field has an invalid bit pattern beyond the enums validated
-    // above. A compile-time size assert was considered but rejected:
-    // sizeof<RiskEngine> differs between x86_64 and sbf targets (u128
-    // alignment), so a const-eval tripwire cannot cover both builds.
-    // The unit test runs on x86_64 but is a structural check — it
-    // inspects type identities, not sizes — so it is target-
-    // independent and still catches the "someone silently added a
-    // bool field" class.
+    /// How a fill of `size_q` splits against the position `pos_q` it lands
+    /// on. `close_q` is the part that reduces the position (opposite sign,
+    /// `|close_q| <= |pos_q|`); `open_q` is what is left, opening or
+    /// growing a position in the fill's direction. A fill that crosses
+    /// zero closes the whole position and opens the remainder, so
+    /// `close_q + open_q == size_q` always and at most one side flips.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct FillSplit {
+        pub close_q: i128,
+        pub open_q: i128,
+    }
 
-    /// Validate ALL fields with invalid bit patterns from raw bytes
-    /// BEFORE casting the slab to &RiskEngine / &mut RiskEngine.
-    /// Required because the cast is `unsafe`: a Rust reference to a
-    /// struct containing an invalid bit pattern is UB on first field
-    /// access, irrespective of whether we read the field.
-    ///
-    /// With the currently pinned engine, the field types in the RiskEngine
-    /// slab with invalid bit patterns are:
-    ///   - SideMode (2 instances at side_mode_long / side_mode_short):
-    ///     valid tag bytes 0 (Normal), 1 (DrainOnly), 2 (ResetPending).
-    ///   - MarketMode (at market_mode): valid tag bytes 0 (Live),
-    ///     1 (Resolved).
-    ///   - bankruptcy_hmax_lock_active: valid bool bytes 0 or 1.
-    /// No other field type in either RiskEngine or Account has invalid
-    /// bit patterns: every other field is u64/u128/i64/i128/[u8; N]/
-    /// wrapper-Pod (U128/I128) or fixed u8 — all-bits-valid types.
-    ///
-    /// If a future revision adds any new enum or bool field to the slab, the
-    /// validation below must be extended before the cast can be considered
-    /// sound.
-    #[inline]
-    fn validate_raw_engine_state_shape(data: &[u8]) -> Result<(), ProgramError> {
-        let base = ENGINE_OFF;
-        // SideMode: valid 0 (Normal), 1 (DrainOnly), 2 (ResetPending)
-        let sm_long = data[base + SM_LONG_OFF];
-        let sm_short = data[base + SM_SHORT_OFF];
-        if sm_long > 2 || sm_short > 2 {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        // MarketMode: valid 0 (Live), 1 (Resolved)
-        let mm = data[base + MM_OFF];
-        if mm > 1 {
-            return Err(ProgramError::InvalidAccountData);
+    pub fn split_fill(pos_q: i128, size_q: i128) -> FillSplit {
+        if pos_q == 0 || size_q == 0 || (pos_q > 0) == (size_q > 0) {
+            return FillSplit {
+                close_q: 0,
+                open_q: size_q,
+            };
         }
-        let hmax = data[base + BANKRUPTCY_HMAX_LOCK_ACTIVE_OFF];
-        if hmax > 1 {
-            return Err(ProgramError::InvalidAccountData);
+        // `-pos_q` is only taken when `|pos_q| < |size_q|`, so pos_q is not
+        // i128::MIN; opposite signs keep `size_q - close_q` in range.
+        let close_q = if size_q.unsigned_abs() <= pos_q.unsigned_abs() {
+            size_q
+        } else {
+            -pos_q
+        };
+        FillSplit {
+            close_q,
+            open_q: size_q - close_q,
         }
-        Ok(())
     }
 
-    #[doc(hidden)]
-    pub fn validate_raw_engine_bytes_for_test(data: &[u8]) -> Result<(), ProgramError> {
-        validate_raw_engine_state_shape(data)
+    /// Reduce-only clamp. Given the user's current effective position and
+    /// the requested signed size, returns the largest size in the same
+    /// direction that only reduces the position: `|result| <= |pos_q|` and
+    /// the sign is opposite to `pos_q`. Returns `None` when the request
+    /// cannot reduce (flat position, same direction, or zero size). This is
+    /// `split_fill`'s close part: a reduce-only fill never flips.
+    pub fn reduce_only_clamp(pos_q: i128, size_q: i128) -> Option<i128> {
+        let close_q = split_fill(pos_q, size_q).close_q;
+        (close_q != 0).then_some(close_q)
     }
 
-    pub fn engine_ref<'a>(data: &'a [u8]) -> Result<&'a RiskEngine, ProgramError> {
-        // Require full ENGINE_LEN to avoid UB from reference extending past buffer
-        if data.len() < ENGINE_OFF + ENGINE_LEN {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        let ptr = unsafe { data.as_ptr().add(ENGINE_OFF) };
-        if (ptr as usize) % ENGINE_ALIGN != 0 {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        // Validate invalid-bit-pattern fields before creating a reference.
-        validate_raw_engine_state_shape(data)?;
-        Ok(unsafe { &*(ptr as *const RiskEngine) })
+    /// A position with the average price it was opened at, for realized /
+    /// unrealized PnL accounting alongside the engine's mark-to-market.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct CostBasis {
+        pub position_q: i128,
+        /// 0 when flat.
+        pub entry_price_e6: u64,
     }
 
-    #[inline]
-    pub fn engine_mut<'a>(data: &'a mut [u8]) -> Result<&'a mut RiskEngine, ProgramError> {
-        if data.len() < ENGINE_OFF + ENGINE_LEN {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        let ptr = unsafe { data.as_mut_ptr().add(ENGINE_OFF) };
-        if (ptr as usize) % ENGINE_ALIGN != 0 {
-            return Err(ProgramError::InvalidAccountData);
+    /// `q * (to - from) / POS_SCALE`, floored toward negative infinity so
+    /// rounding never favors the holder.
+    pub fn pnl_between(q: i128, from_e6: u64, to_e6: u64) -> i128 {
+        use percolator::wide_math::{mul_div_ceil_u128, mul_div_floor_u128};
+        let moved = to_e6.abs_diff(from_e6) as u128;
+        let abs = q.unsigned_abs();
+        if (q > 0) == (to_e6 >= from_e6) {
+            mul_div_floor_u128(abs, moved, percolator::POS_SCALE).min(i128::MAX as u128) as i128
+        } else {
+            -(mul_div_ceil_u128(abs, moved, percolator::POS_SCALE).min(i128::MAX as u128) as i128)
         }
-        validate_raw_engine_state_shape(data)?;
-        Ok(unsafe { &mut *(ptr as *mut RiskEngine) })
     }
 
-    // NOTE: engine_write was removed because it requires passing RiskEngine by value,
-    // which stack-allocates the ~6MB struct and causes stack overflow in BPF.
-    // Use engine_mut() + init_in_place() instead for initialization.
+    /// PnL a position books on its next touch from the side's K/F
+    /// movement since its snapshots:
+    /// `|basis| * (dk * FUNDING_DEN + df) / (a_basis * POS_SCALE * FUNDING_DEN)`,
+    /// floored toward negative infinity as the engine settles it.
+    /// Saturates instead of failing, since callers only read it.
+    pub fn pending_kf_pnl(abs_basis: u128, a_basis: u128, dk: i128, df: i128) -> i128 {
+        use percolator::wide_math::{mul_div_ceil_u128, mul_div_floor_u128};
+        let den = a_basis
+            .saturating_mul(percolator::POS_SCALE)
+            .saturating_mul(percolator::FUNDING_DEN);
+        if abs_basis == 0 || den == 0 {
+            return 0;
+        }
+        let num = dk
+            .saturating_mul(percolator::FUNDING_DEN.min(i128::MAX as u128) as i128)
+            .saturating_add(df);
+        let mag = num.unsigned_abs();
+        if num >= 0 {
+            mul_div_floor_u128(abs_basis, mag, den).min(i128::MAX as u128) as i128
+        } else {
+            -(mul_div_ceil_u128(abs_basis, mag, den).min(i128::MAX as u128) as i128)
+        }
+    }
 
-    use solana_program::{
-        account_info::AccountInfo, instruction::Instruction as SolInstruction,
-        program::invoke_signed,
-    };
+    /// Unrealized PnL of `basis` marked at `mark_e6`.
+    pub fn unrealized_pnl(basis: &CostBasis, mark_e6: u64) -> i128 {
+        pnl_between(basis.position_q, basis.entry_price_e6, mark_e6)
+    }
 
-    /// Invoke the matcher program via CPI. The AccountInfo clones
-    /// satisfy solana_program::program::invoke_signed's ownership
-    /// requirement without relying on lifetime transmutes.
+    /// Apply a fill of `size_q` at `exec_price_e6` to `basis`; returns the
+    /// new basis and the PnL realized by it.
     ///
-    /// `tail` is the caller-supplied variadic account list that
-    /// TradeCpi forwards verbatim to the matcher. The wrapper does
-    /// NOT validate tail contents — the matcher owns that
-    /// responsibility. Tail length is unbounded at the wire level;
-    /// Solana's CPI transaction-size and account-count limits are
-    /// the effective cap.
-    #[inline]
-    pub fn invoke_signed_trade<'a>(
-        ix: &SolInstruction,
-        a_lp_pda: &AccountInfo<'a>,
-        a_matcher_ctx: &AccountInfo<'a>,
-        a_matcher_prog: &AccountInfo<'a>,
-        tail: &[AccountInfo<'a>],
-        seeds: &[&[u8]],
-    ) -> Result<(), ProgramError> {
-        // Infos: lp_pda + matcher_ctx + matcher_prog + tail. The
-        // matcher_prog is always included because invoke_signed needs
-        // it to resolve the destination program; the CPI metas do not
-        // list it (Solana convention).
-        let mut infos: alloc::vec::Vec<AccountInfo<'a>> =
-            alloc::vec::Vec::with_capacity(3 + tail.len());
-        infos.push(a_lp_pda.clone());
-        infos.push(a_matcher_ctx.clone());
-        infos.push(a_matcher_prog.clone());
-        for ai in tail.iter() {
-            infos.push(ai.clone());
+    /// The closed part (see [`split_fill`]) realizes
+    /// `close * (exec - entry)` at the old entry, which it leaves unchanged.
+    /// The open part either starts a position at the fill price (from flat,
+    /// or after crossing zero) or is averaged into the entry, rounded
+    /// against the holder (up for longs, down for shorts). Both roundings
+    /// mean realized plus unrealized never exceeds the exact figure.
+    /// `None` for `exec_price_e6 == 0` with a nonzero fill, or when the
+    /// averaged cost overflows u128.
+    pub fn settle_cost_basis(
+        basis: &CostBasis,
+        size_q: i128,
+        exec_price_e6: u64,
+    ) -> Option<(CostBasis, i128)> {
+        if size_q == 0 {
+            return Some((*basis, 0));
+        }
+        if exec_price_e6 == 0 {
+            return None;
         }
-        invoke_signed(ix, &infos, &[seeds])
+        let FillSplit { close_q, open_q } = split_fill(basis.position_q, size_q);
+        // Closing a long sells: realize as the long (-close_q) moving from
+        // entry to exec.
+        let realized = pnl_between(-close_q, basis.entry_price_e6, exec_price_e6);
+        let remaining = basis.position_q.checked_add(close_q)?;
+        let position_q = remaining.checked_add(open_q)?;
+        let entry_price_e6 = if position_q == 0 {
+            0
+        } else if open_q == 0 {
+            basis.entry_price_e6
+        } else if remaining == 0 {
+            exec_price_e6
+        } else {
+            let (held, added) = (remaining.unsigned_abs(), open_q.unsigned_abs());
+            let total = held.checked_add(added)?;
+            let cost = held
+                .checked_mul(basis.entry_price_e6 as u128)?
+                .checked_add(added.checked_mul(exec_price_e6 as u128)?)?;
+            let avg = if position_q > 0 {
+                cost.div_ceil(total)
+            } else {
+                cost / total
+            };
+            u64::try_from(avg).ok()?
+        };
+        Some((
+            CostBasis {
+                position_q,
+                entry_price_e6,
+            },
+            realized,
+        ))
     }
-}
-
-pub mod matcher_abi {
-    use crate::constants::MATCHER_ABI_VERSION;
-    use solana_program::program_error::ProgramError;
 
-    /// Matcher return flags
-    pub const FLAG_VALID: u32 = 1; // bit0: response is valid
-    pub const FLAG_PARTIAL_OK: u32 = 2; // bit1: partial fill, including zero, allowed
-    pub const FLAG_REJECTED: u32 = 4; // bit2: trade rejected by matcher
+    /// Bring `basis` to `position_q` after a change made outside fills
+    /// (liquidation, ADL, a dust sweep, or a position older than the
+    /// ledger). The difference is applied as a fill at `price_e6`, so a
+    /// reduction keeps the entry and a flip or an increase is priced at
+    /// `price_e6`; what the change realized is not known here and is left
+    /// out of the ledger.
+    pub fn reconcile_cost_basis(basis: &CostBasis, position_q: i128, price_e6: u64) -> CostBasis {
+        if basis.position_q == position_q {
+            return *basis;
+        }
+        position_q
+            .checked_sub(basis.position_q)
+            .and_then(|diff| settle_cost_basis(basis, diff, price_e6))
+            .map(|(b, _)| b)
+            .unwrap_or(CostBasis {
+                position_q,
+                entry_price_e6: if position_q == 0 { 0 } else { price_e6 },
+            })
+    }
 
-    /// Matcher return structure.
-    /// IMPORTANT: exec_price_e6 must be in engine-space (already inverted
-    /// and scaled). The matcher receives oracle_price_e6 in engine-space
-    /// and must return exec_price_e6 in the same space. The wrapper stores
-    /// it directly as the Hyperp mark price without re-normalization.
-    #[repr(C)]
-    #[derive(Debug, Clone, Copy)]
-    pub struct MatcherReturn {
-        pub abi_version: u32,
-        pub flags: u32,
-        pub exec_price_e6: u64,
-        pub exec_size: i128,
-        pub req_id: u64,
-        pub lp_account_id: u64,
-        pub oracle_price_e6: u64,
-        pub reserved: u64,
+    /// Cumulative funding index after `dt_slots` at `funding_rate_e9` per
+    /// slot on `price_e6`. The index is in engine units per `POS_SCALE`
+    /// of long position, scaled by 1e9; it rises when longs pay.
+    /// Saturating.
+    pub fn funding_index_advance(
+        index: i128,
+        price_e6: u64,
+        funding_rate_e9: i128,
+        dt_slots: u64,
+    ) -> i128 {
+        index.saturating_add(
+            (price_e6 as i128)
+                .saturating_mul(funding_rate_e9)
+                .saturating_mul(dt_slots as i128),
+        )
     }
 
-    pub fn read_matcher_return(ctx: &[u8]) -> Result<MatcherReturn, ProgramError> {
-        if ctx.len() < 64 {
-            return Err(ProgramError::InvalidAccountData);
+    /// Funding paid by `position_q` while the index moved from `from` to
+    /// `to`; negative when received. Payments round up and receipts down.
+    pub fn funding_owed(position_q: i128, from: i128, to: i128) -> i128 {
+        use percolator::wide_math::{mul_div_ceil_u128, mul_div_floor_u128};
+        let moved = to.abs_diff(from);
+        let abs = position_q.unsigned_abs();
+        let den = percolator::POS_SCALE * 1_000_000_000;
+        if (position_q > 0) == (to > from) {
+            mul_div_ceil_u128(abs, moved, den).min(i128::MAX as u128) as i128
+        } else {
+            -(mul_div_floor_u128(abs, moved, den).min(i128::MAX as u128) as i128)
         }
-        let abi_version = u32::from_le_bytes(ctx[0..4].try_into().unwrap());
-        let flags = u32::from_le_bytes(ctx[4..8].try_into().unwrap());
-        let exec_price_e6 = u64::from_le_bytes(ctx[8..16].try_into().unwrap());
-        let exec_size = i128::from_le_bytes(ctx[16..32].try_into().unwrap());
-        let req_id = u64::from_le_bytes(ctx[32..40].try_into().unwrap());
-        let lp_account_id = u64::from_le_bytes(ctx[40..48].try_into().unwrap());
-        let oracle_price_e6 = u64::from_le_bytes(ctx[48..56].try_into().unwrap());
-        let reserved = u64::from_le_bytes(ctx[56..64].try_into().unwrap());
+    }
 
-        Ok(MatcherReturn {
-            abi_version,
-            flags,
-            exec_price_e6,
-            exec_size,
-            req_id,
-            lp_account_id,
-            oracle_price_e6,
-            reserved,
-        })
+    /// Split a fill's trading fee into `(insurance, treasury, lp)`. Treasury
+    /// and LP shares round down; insurance takes the remainder, so the
+    /// three always sum to `fee`. `insurance_bps + treasury_bps` must be
+    /// at most 10_000 (SetFeeSplit enforces it); larger inputs route
+    /// everything to insurance.
+    pub fn fee_split(fee: u128, insurance_bps: u16, treasury_bps: u16) -> (u128, u128, u128) {
+        let (ib, tb) = (insurance_bps as u128, treasury_bps as u128);
+        if ib + tb > 10_000 {
+            return (fee, 0, 0);
+        }
+        let treasury = fee / 10_000 * tb + fee % 10_000 * tb / 10_000;
+        let lp_bps = 10_000 - ib - tb;
+        let lp = fee / 10_000 * lp_bps + fee % 10_000 * lp_bps / 10_000;
+        (fee - treasury - lp, treasury, lp)
     }
 
-    pub fn validate_matcher_return(
-        ret: &MatcherReturn,
-        lp_account_id: u64,
-        oracle_price_e6: u64,
-        req_size: i128,
-        req_id: u64,
-    ) -> Result<(), ProgramError> {
-        // Check ABI version
-        if ret.abi_version != MATCHER_ABI_VERSION {
-            return Err(ProgramError::InvalidAccountData);
+    /// Allowlist leaf for `owner`: `sha256(ALLOWLIST_LEAF_DOMAIN || owner)`.
+    pub fn allowlist_leaf(owner: &[u8; 32]) -> [u8; 32] {
+        solana_program::hash::hashv(&[crate::constants::ALLOWLIST_LEAF_DOMAIN, owner]).to_bytes()
+    }
+
+    /// Merkle membership with sorted-pair hashing (`sha256(min || max)` at
+    /// each level), so proofs carry no left/right bits. An all-zero root
+    /// means the market is open and every owner passes.
+    pub fn allowlist_ok(root: &[u8; 32], owner: &[u8; 32], proof: &[[u8; 32]]) -> bool {
+        if *root == [0u8; 32] {
+            return true;
         }
-        // Reject any flag bits outside the known set. Prevents a future
-        // matcher that uses a currently-undefined flag (e.g. a new partial
-        // fill semantics) from being silently accepted by this wrapper —
-        // upgraders must bump the ABI version to signal new flag meaning.
-        const KNOWN_FLAGS: u32 = FLAG_VALID | FLAG_PARTIAL_OK | FLAG_REJECTED;
-        if (ret.flags & !KNOWN_FLAGS) != 0 {
-            return Err(ProgramError::InvalidAccountData);
+        if proof.len() > crate::constants::MAX_ALLOWLIST_PROOF_LEN {
+            return false;
         }
-        // Must have VALID flag set
-        if (ret.flags & FLAG_VALID) == 0 {
-            return Err(ProgramError::InvalidAccountData);
+        let mut node = allowlist_leaf(owner);
+        for sibling in proof {
+            let (a, b) = if node <= *sibling {
+                (&node, sibling)
+            } else {
+                (sibling, &node)
+            };
+            node = solana_program::hash::hashv(&[a, b]).to_bytes();
         }
-        // Must not have REJECTED flag set
-        if (ret.flags & FLAG_REJECTED) != 0 {
-            return Err(ProgramError::InvalidAccountData);
+        node == *root
+    }
+
+    /// Guarded-launch capital cap. `limit == 0` disables it.
+    pub fn capital_limit_ok(capital: u128, limit: u128) -> bool {
+        limit == 0 || capital <= limit
+    }
+
+    /// Guarded-launch position cap: a fill that does not grow `|pos|` is
+    /// always allowed, so users above a lowered limit can still reduce.
+    /// `limit == 0` disables it.
+    pub fn position_limit_ok(
+        pos_before: i128,
+        pos_after: i128,
+        notional_after: u128,
+        limit: u128,
+    ) -> bool {
+        limit == 0
+            || pos_after.unsigned_abs() <= pos_before.unsigned_abs()
+            || notional_after <= limit
+    }
+
+    /// Dust floors for the user side of a fill. A fill that flattens the
+    /// position always passes so dust can be closed; otherwise it must
+    /// trade at least `min_trade_abs` and leave at least
+    /// `min_position_abs`. 0 disables either floor.
+    pub fn dust_fill_ok(
+        size: i128,
+        pos_after: i128,
+        min_trade_abs: u128,
+        min_position_abs: u128,
+    ) -> bool {
+        pos_after == 0
+            || (size.unsigned_abs() >= min_trade_abs
+                && pos_after.unsigned_abs() >= min_position_abs)
+    }
+
+    /// Circuit-breaker trip test: `price` is more than `max_move_bps` away
+    /// from `anchor`. An unset anchor or `max_move_bps == 0` never trips.
+    pub fn circuit_breaker_tripped(anchor: u64, price: u64, max_move_bps: u64) -> bool {
+        anchor != 0
+            && max_move_bps != 0
+            && (price.abs_diff(anchor) as u128) * 10_000 > (anchor as u128) * (max_move_bps as u128)
+    }
+
+    /// LP utilization: open notional over booked equity, in bps. Saturates;
+    /// an LP with exposure and no equity reads `u64::MAX`.
+    pub fn lp_utilization_bps(notional: u128, equity: i128) -> u64 {
+        if notional == 0 {
+            return 0;
+        }
+        if equity <= 0 {
+            return u64::MAX;
         }
+        let bps = notional.saturating_mul(10_000) / equity as u128;
+        bps.min(u64::MAX as u128) as u64
+    }
 
-        // Validate echoed fields match request
-        if ret.lp_account_id != lp_account_id {
-            return Err(ProgramError::InvalidAccountData);
+    /// Instant markout of a fill for the LP: `(exec - oracle) * size`,
+    /// where `size > 0` means the user bought. Positive when the LP sold
+    /// above or bought below the oracle.
+    pub fn lp_spread_pnl(size: i128, exec_price: u64, oracle_price: u64) -> i128 {
+        let edge = percolator::wide_math::mul_div_floor_u128(
+            size.unsigned_abs(),
+            exec_price.abs_diff(oracle_price) as u128,
+            percolator::POS_SCALE,
+        )
+        .min(i128::MAX as u128) as i128;
+        if (exec_price >= oracle_price) == (size > 0) {
+            edge
+        } else {
+            -edge
         }
-        if ret.oracle_price_e6 != oracle_price_e6 {
-            return Err(ProgramError::InvalidAccountData);
+    }
+
+    /// Ring index of the `i`-th oldest of `count` samples when the next
+    /// write goes to `head`.
+    pub fn history_ring_index(head: usize, count: usize, cap: usize, i: usize) -> usize {
+        (head + cap - count.min(cap) + i) % cap
+    }
+
+    /// Whether a user whose last fill was at `last_slot` (0 = never) may
+    /// fill again at `now_slot`. `min_slots == 0` disables the limit.
+    pub fn trade_rate_ok(last_slot: u64, now_slot: u64, min_slots: u64) -> bool {
+        min_slots == 0 || last_slot == 0 || now_slot >= last_slot.saturating_add(min_slots)
+    }
+
+    /// Nonzero position below the dust floor, eligible for a crank sweep.
+    pub fn is_dust_position(pos: i128, min_position_abs: u128) -> bool {
+        pos != 0 && pos.unsigned_abs() < min_position_abs
+    }
+
+    /// Advance the competition epoch clock to `now_slot`. Returns the new
+    /// (epoch, start_slot); whole elapsed epochs are skipped at once so a
+    /// late crank lands on the same boundary grid. `epoch_slots == 0`
+    /// never rolls.
+    pub fn epoch_advance(
+        epoch: u64,
+        start_slot: u64,
+        epoch_slots: u64,
+        now_slot: u64,
+    ) -> (u64, u64) {
+        if epoch_slots == 0 || now_slot < start_slot.saturating_add(epoch_slots) {
+            return (epoch, start_slot);
         }
-        if ret.reserved != 0 {
-            return Err(ProgramError::InvalidAccountData);
+        let elapsed = (now_slot - start_slot) / epoch_slots;
+        (
+            epoch.saturating_add(elapsed),
+            start_slot.saturating_add(elapsed.saturating_mul(epoch_slots)),
+        )
+    }
+
+    /// Whether the crank has been silent long enough for anyone to switch
+    /// the market to withdraw-only: `max_crank_staleness_slots * K` slots
+    /// since the last crank. `max_crank_staleness_slots == 0` never
+    /// qualifies.
+    pub fn withdraw_only_eligible(
+        max_crank_staleness_slots: u64,
+        last_crank_slot: u64,
+        now_slot: u64,
+    ) -> bool {
+        max_crank_staleness_slots != 0
+            && now_slot.saturating_sub(last_crank_slot)
+                >= max_crank_staleness_slots
+                    .saturating_mul(crate::constants::WITHDRAW_ONLY_STALENESS_MULTIPLIER)
+    }
+
+    /// Digest an admin key set approves for one instruction:
+    /// `sha256(ADMIN_ACTION_DOMAIN || slab || u32 len(keys) || keys ||
+    /// instruction_data)`, where `keys` are the instruction's account keys
+    /// in order. Binding the keys pins the executor and every destination
+    /// (WithdrawFees and the CloseSlab drain pay account 0), not just the
+    /// payload.
+    pub fn admin_action_hash(
+        slab: &[u8; 32],
+        account_keys: &[[u8; 32]],
+        instruction_data: &[u8],
+    ) -> [u8; 32] {
+        let mut h = solana_program::hash::Hasher::default();
+        h.hash(crate::constants::ADMIN_ACTION_DOMAIN);
+        h.hash(slab);
+        h.hash(&(account_keys.len() as u32).to_le_bytes());
+        for k in account_keys {
+            h.hash(k);
+        }
+        h.hash(instruction_data);
+        h.result().to_bytes()
+    }
+
+    /// Valid M-of-N key set: `1 <= threshold <= keys.len() <=
+    /// MAX_ADMIN_SIGNERS`, no zero or duplicate keys. Threshold 0 with no
+    /// keys clears the set.
+    pub fn admin_multisig_valid(threshold: u8, keys: &[[u8; 32]]) -> bool {
+        if threshold == 0 {
+            return keys.is_empty();
         }
-        if ret.req_id != req_id {
-            return Err(ProgramError::InvalidAccountData);
+        if keys.len() > crate::constants::MAX_ADMIN_SIGNERS || threshold as usize > keys.len() {
+            return false;
         }
+        keys.iter()
+            .enumerate()
+            .all(|(i, k)| *k != [0u8; 32] && !keys[..i].contains(k))
+    }
 
-        // Require exec_price_e6 != 0 always - avoids "all zeros but valid flag" ambiguity
-        if ret.exec_price_e6 == 0 {
-            return Err(ProgramError::InvalidAccountData);
+    /// Valid liquidator window: `window_slots <= MAX_LIQUIDATION_WINDOW_SLOTS`,
+    /// at most `MAX_LIQUIDATORS` keys, no zero or duplicate keys, and at
+    /// least one key when the window is on. Window 0 with no keys clears it.
+    pub fn liquidator_window_valid(window_slots: u64, keys: &[[u8; 32]]) -> bool {
+        if window_slots > crate::constants::MAX_LIQUIDATION_WINDOW_SLOTS
+            || keys.len() > crate::constants::MAX_LIQUIDATORS
+            || (window_slots != 0 && keys.is_empty())
+        {
+            return false;
         }
+        keys.iter()
+            .enumerate()
+            .all(|(i, k)| *k != [0u8; 32] && !keys[..i].contains(k))
+    }
 
-        // Zero exec_size requires PARTIAL_OK flag
-        if ret.exec_size == 0 {
-            if (ret.flags & FLAG_PARTIAL_OK) == 0 {
-                return Err(ProgramError::InvalidAccountData);
-            }
-            // Zero-fill carries no executable price. Require the matcher to
-            // echo the request oracle as the canonical no-op price so callers,
-            // indexers, and future wrapper code never ingest adversarial
-            // off-market `exec_price_e6` values from a no-fill response.
-            if ret.exec_price_e6 != oracle_price_e6 {
-                return Err(ProgramError::InvalidAccountData);
-            }
-            // Canonical zero fill with PARTIAL_OK is allowed - return early
-            return Ok(());
+    /// Whether a keeper outside the liquidator set may liquidate an
+    /// account flagged at `flag_slot` (0 = not yet flagged, so its window
+    /// has not started). `window_slots == 0` opens liquidation to all.
+    pub fn liquidation_open(flag_slot: u64, now_slot: u64, window_slots: u64) -> bool {
+        window_slots == 0 || (flag_slot != 0 && now_slot >= flag_slot.saturating_add(window_slots))
+    }
+
+    /// Stale-fallback settings are usable: a nonzero grace that ends
+    /// before the permissionless-resolve horizon (`resolve_stale_slots`,
+    /// 0 = none), and a haircut within `MAX_STALE_HAIRCUT_BPS`.
+    pub fn stale_fallback_valid(
+        grace_slots: u64,
+        max_haircut_bps: u16,
+        resolve_stale_slots: u64,
+    ) -> bool {
+        grace_slots != 0
+            && max_haircut_bps <= crate::constants::MAX_STALE_HAIRCUT_BPS
+            && (resolve_stale_slots == 0 || grace_slots < resolve_stale_slots)
+    }
+
+    /// Withdrawal haircut after `stale_slots` without a fresh oracle read:
+    /// zero through the grace window, then rising linearly to
+    /// `max_haircut_bps` over `ramp_slots` (at once when `ramp_slots == 0`).
+    pub fn stale_haircut_bps(
+        stale_slots: u64,
+        grace_slots: u64,
+        ramp_slots: u64,
+        max_haircut_bps: u16,
+    ) -> u16 {
+        if stale_slots <= grace_slots {
+            return 0;
+        }
+        let elapsed = stale_slots - grace_slots;
+        if ramp_slots == 0 || elapsed >= ramp_slots {
+            return max_haircut_bps;
+        }
+        (max_haircut_bps as u128 * elapsed as u128 / ramp_slots as u128) as u16
+    }
+
+    /// A metadata label field: printable ASCII, then zero padding only.
+    /// All zeros (no label) is valid.
+    pub fn metadata_label_ok(label: &[u8]) -> bool {
+        let len = label.iter().position(|&b| b == 0).unwrap_or(label.len());
+        label[..len].iter().all(|b| (0x20..0x7f).contains(b))
+            && label[len..].iter().all(|&b| b == 0)
+    }
+
+    /// Market metadata is storable: both labels well-formed and decimals
+    /// within `MAX_BASE_DECIMALS`.
+    pub fn market_metadata_ok(m: &crate::state::MarketMetadata) -> bool {
+        metadata_label_ok(&m.symbol)
+            && metadata_label_ok(&m.oracle_description)
+            && m.base_decimals <= crate::constants::MAX_BASE_DECIMALS
+    }
+
+    /// Whether an approval bitmap (bit i = key i) reaches `threshold`.
+    pub fn admin_threshold_met(approvals: u8, threshold: u8) -> bool {
+        threshold != 0 && approvals.count_ones() >= threshold as u32
+    }
+
+    /// Epoch PnL: booked equity change net of deposits and withdrawals,
+    /// saturated to i64.
+    pub fn epoch_pnl(equity: i128, start_equity: i64, net_flow: i64) -> i64 {
+        let pnl = equity
+            .saturating_sub(start_equity as i128)
+            .saturating_sub(net_flow as i128);
+        pnl.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+
+    /// Jump-rejection test for `ORACLE_FLAG_REJECT_JUMPS`: whether
+    /// `observed` lies beyond `cap_bps_per_slot * dt` of `anchor`. `dt` is
+    /// floored at 1 so a same-slot tick is held to a one-slot move rather
+    /// than to zero. No anchor or a disabled cap never rejects.
+    pub fn oracle_jump_exceeds_cap(
+        anchor_e6: u64,
+        observed_e6: u64,
+        cap_bps_per_slot: u64,
+        dt_slots: u64,
+    ) -> bool {
+        if anchor_e6 == 0 || observed_e6 == 0 || cap_bps_per_slot == 0 {
+            return false;
         }
+        let diff = anchor_e6.abs_diff(observed_e6) as u128;
+        let allowed = (anchor_e6 as u128)
+            .saturating_mul(cap_bps_per_slot as u128)
+            .saturating_mul(dt_slots.max(1) as u128);
+        diff.saturating_mul(10_000) > allowed
+    }
 
-        // Size constraints (use unsigned_abs to avoid i128::MIN overflow)
-        if ret.exec_size.unsigned_abs() > req_size.unsigned_abs() {
-            return Err(ProgramError::InvalidAccountData);
+    /// Max-slippage bound versus the oracle, in engine space. Longs
+    /// (`size_q > 0`) pay at most `ceil(oracle * (10_000 + bps) / 10_000)`;
+    /// shorts receive at least `floor(oracle * (10_000 - bps) / 10_000)`.
+    /// Bounds round toward the taker's tolerance, never against it.
+    /// `bps > 10_000` is malformed and fails closed.
+    pub fn slippage_bps_ok(
+        exec_price_e6: u64,
+        oracle_price_e6: u64,
+        size_q: i128,
+        bps: u64,
+    ) -> bool {
+        if bps > 10_000 || size_q == 0 {
+            return false;
         }
-        if req_size != 0 {
-            if ret.exec_size.signum() != req_size.signum() {
-                return Err(ProgramError::InvalidAccountData);
+        let exec = exec_price_e6 as u128;
+        let px = oracle_price_e6 as u128;
+        if size_q > 0 {
+            match mul_div_ceil(px, 10_000 + bps as u128, 10_000) {
+                Some(max) => exec <= max,
+                None => false,
             }
+        } else {
+            exec * 10_000 >= px * (10_000 - bps as u128)
         }
-        if ret.exec_size.unsigned_abs() < req_size.unsigned_abs()
-            && (ret.flags & FLAG_PARTIAL_OK) == 0
-        {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        Ok(())
     }
-}
 
-// 3. mod error
-pub mod error {
-    use percolator::RiskError;
-    use solana_program::program_error::ProgramError;
+    /// Message the Hyperp mark authority signs for a relayed PushHyperpMark.
+    pub fn hyperp_mark_payload(
+        slab: &[u8; 32],
+        price_e6: u64,
+        timestamp: i64,
+    ) -> [u8; crate::constants::HYPERP_MARK_PAYLOAD_LEN] {
+        let mut out = [0u8; crate::constants::HYPERP_MARK_PAYLOAD_LEN];
+        out[..32].copy_from_slice(slab);
+        out[32..40].copy_from_slice(&price_e6.to_le_bytes());
+        out[40..48].copy_from_slice(&timestamp.to_le_bytes());
+        out
+    }
 
-    #[derive(Clone, Debug, Eq, PartialEq)]
-    pub enum PercolatorError {
-        InvalidMagic,
-        InvalidVersion,
-        AlreadyInitialized,
-        NotInitialized,
-        InvalidSlabLen,
-        InvalidOracleKey,
-        OracleStale,
-        OracleConfTooWide,
-        InvalidVaultAta,
-        InvalidMint,
-        ExpectedSigner,
-        ExpectedWritable,
-        OracleInvalid,
-        EngineInsufficientBalance,
-        EngineUndercollateralized,
-        EngineUnauthorized,
-        EngineInvalidMatchingEngine,
-        EnginePnlNotWarmedUp,
-        EngineOverflow,
-        EngineAccountNotFound,
-        EngineNotAnLPAccount,
-        EnginePositionSizeMismatch,
-        EngineRiskReductionOnlyMode,
-        EngineAccountKindMismatch,
-        InvalidTokenAccount,
-        InvalidTokenProgram,
-        InvalidConfigParam,
-        HyperpTradeNoCpiDisabled,
-        EngineCorruptState,
-        /// Wrapper-level: the requested operation cannot safely advance market
-        /// time. Caller must run KeeperCrank to commit account-touching market
-        /// progress, then retry the original operation.
-        CatchupRequired,
-        /// Deposit rejected: post-deposit `c_tot` would exceed
-        /// `tvl_insurance_cap_mult * insurance_fund.balance`.
-        /// Only triggered when the admin has enabled the cap via UpdateConfig.
-        DepositCapExceeded,
-        /// `WithdrawInsuranceLimited` called within the configured
-        /// `insurance_withdraw_cooldown_slots` window.
-        InsuranceWithdrawCooldown,
-        /// `WithdrawInsuranceLimited` amount exceeds
-        /// `insurance_withdraw_max_bps * insurance_fund.balance / 10_000`
-        /// (with a minimum floor of 10 units to avoid Zeno's-paradox lockout
-        /// at small bps × small insurance).
-        InsuranceWithdrawCapExceeded,
-        /// Engine reported that bounded public progress cannot continue
-        /// without an explicit terminal-recovery path.
-        EngineRecoveryRequired,
+    /// Message an account owner signs to authorize DepositWithPermit.
+    pub fn permit_deposit_payload(
+        slab: &[u8; 32],
+        user_idx: u16,
+        amount: u64,
+        nonce: u64,
+        expiry: i64,
+    ) -> [u8; crate::constants::PERMIT_DEPOSIT_PAYLOAD_LEN] {
+        let mut out = [0u8; crate::constants::PERMIT_DEPOSIT_PAYLOAD_LEN];
+        out[..32].copy_from_slice(slab);
+        out[32..34].copy_from_slice(&user_idx.to_le_bytes());
+        out[34..42].copy_from_slice(&amount.to_le_bytes());
+        out[42..50].copy_from_slice(&nonce.to_le_bytes());
+        out[50..58].copy_from_slice(&expiry.to_le_bytes());
+        out
     }
 
-    impl From<PercolatorError> for ProgramError {
-        fn from(e: PercolatorError) -> Self {
-            ProgramError::Custom(e as u32)
-        }
+    /// Freshness window for a relayed mark's signed `timestamp`, the same
+    /// one external oracle reads get: not ahead of the cluster clock and
+    /// at most `max_staleness_secs` old. Without it a withheld signature
+    /// could be landed long after its price stopped being true.
+    pub fn signed_mark_fresh(timestamp: i64, now_unix_ts: i64, max_staleness_secs: u64) -> bool {
+        let age = now_unix_ts.saturating_sub(timestamp);
+        age >= 0 && age as u64 <= max_staleness_secs
     }
 
-    pub fn map_risk_error(e: RiskError) -> ProgramError {
-        let err = match e {
-            RiskError::InsufficientBalance => PercolatorError::EngineInsufficientBalance,
-            RiskError::Undercollateralized => PercolatorError::EngineUndercollateralized,
-            RiskError::Unauthorized => PercolatorError::EngineUnauthorized,
-            RiskError::PnlNotWarmedUp => PercolatorError::EnginePnlNotWarmedUp,
-            RiskError::Overflow => PercolatorError::EngineOverflow,
-            RiskError::AccountNotFound => PercolatorError::EngineAccountNotFound,
-            RiskError::SideBlocked => PercolatorError::EngineRiskReductionOnlyMode,
-            RiskError::CorruptState => PercolatorError::EngineCorruptState,
-            RiskError::RecoveryRequired => PercolatorError::EngineRecoveryRequired,
+    /// Extract (pubkey, message) from ed25519 precompile instruction data
+    /// carrying exactly one signature whose key, signature and message all
+    /// live inline in that same instruction (instruction index u16::MAX).
+    /// Anything else — several signatures, offsets pointing at another
+    /// instruction, out-of-range offsets — returns None.
+    pub fn ed25519_single_sig_payload(data: &[u8]) -> Option<([u8; 32], &[u8])> {
+        const OFFSETS_START: usize = 2;
+        const OFFSETS_LEN: usize = 14;
+        if data.len() < OFFSETS_START + OFFSETS_LEN || data[0] != 1 {
+            return None;
+        }
+        let rd = |i: usize| -> u16 {
+            let at = OFFSETS_START + 2 * i;
+            u16::from_le_bytes([data[at], data[at + 1]])
         };
-        ProgramError::Custom(err as u32)
+        let (sig_off, sig_ix) = (rd(0) as usize, rd(1));
+        let (pk_off, pk_ix) = (rd(2) as usize, rd(3));
+        let (msg_off, msg_len, msg_ix) = (rd(4) as usize, rd(5) as usize, rd(6));
+        if sig_ix != u16::MAX || pk_ix != u16::MAX || msg_ix != u16::MAX {
+            return None;
+        }
+        if sig_off.checked_add(64)? > data.len() {
+            return None;
+        }
+        let pk: [u8; 32] = data.get(pk_off..pk_off.checked_add(32)?)?.try_into().ok()?;
+        let msg = data.get(msg_off..msg_off.checked_add(msg_len)?)?;
+        Some((pk, msg))
     }
-}
 
-// 4. mod ix
-pub mod ix {
-    use alloc::boxed::Box;
-    use percolator::{RiskParams, U128};
-    use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+    /// Backstop vault share math. Depositors hold shares of the vault's
+    /// LP account, priced from its equity, `capital + unrealized PnL`.
+    /// These helpers pin the rounding so the vault never issues or
+    /// redeems value it does not hold.
+    ///
+    /// Shares minted for a deposit: `floor(deposit * total_shares / equity)`,
+    /// 1:1 for the first depositor. Returns `None` when existing shares
+    /// back zero equity (a wiped pool must not be re-diluted at 1:1).
+    pub fn lp_shares_for_deposit(deposit: u128, total_shares: u128, equity: u128) -> Option<u128> {
+        if total_shares == 0 {
+            return Some(deposit);
+        }
+        if equity == 0 {
+            return None;
+        }
+        mul_div_floor(deposit, total_shares, equity)
+    }
 
-    #[derive(Debug)]
-    pub struct OracleLegFeeds {
-        pub leg2_feed_id: [u8; 32],
-        pub leg3_feed_id: [u8; 32],
+    /// Collateral paid out for redeeming `shares`:
+    /// `floor(shares * equity / total_shares)`.
+    pub fn lp_redeem_amount(shares: u128, total_shares: u128, equity: u128) -> Option<u128> {
+        if shares > total_shares {
+            return None;
+        }
+        if total_shares == 0 {
+            return Some(0);
+        }
+        mul_div_floor(shares, equity, total_shares)
     }
 
-    #[derive(Debug)]
-    pub struct InitMarketArgs {
-        pub admin: Pubkey,
-        pub collateral_mint: Pubkey,
-        /// Pyth feed ID for the index price (32 bytes).
-        /// If all zeros, enables Hyperp mode (internal mark/index, no external oracle).
-        pub index_feed_id: [u8; 32],
-        /// Optional external oracle legs 2/3 for synthetic cross rates.
-        pub oracle_leg_feeds: Box<OracleLegFeeds>,
-        /// Active leg count: 1, 2, or 3. Hyperp requires 1.
-        pub oracle_leg_count: u8,
-        /// Orientation bits for legs 2/3. Set bit means divide by that leg.
-        pub oracle_leg_flags: u8,
-        /// Maximum staleness in seconds
-        pub max_staleness_secs: u64,
-        pub conf_filter_bps: u16,
-        /// If non-zero, invert oracle price (raw -> 1e12/raw)
-        pub invert: u8,
-        /// Lamports per Unit for boundary conversion (0 = no scaling)
-        pub unit_scale: u32,
-        /// Initial mark price in e6 format. Required (non-zero) if Hyperp mode.
-        pub initial_mark_price_e6: u64,
-        /// Periodic maintenance fee per slot per account (engine units). 0 = disabled.
-        pub maintenance_fee_per_slot: u128,
-        /// Insurance withdrawal: max bps per withdrawal (0 = no live withdrawals)
-        /// High bit enables deposits-only mode for WithdrawInsuranceLimited;
-        /// low 15 bits store the bps value.
-        pub insurance_withdraw_max_bps: u16,
-        /// Insurance withdrawal: cooldown slots between withdrawals
-        pub insurance_withdraw_cooldown_slots: u64,
-        pub risk_params: Box<RiskParams>,
-        /// Wrapper-charged new-account fee (base units). Charged by the
-        /// wrapper at InitUser/InitLP: `fee_payment` is split into
-        /// `new_account_fee` (routed to insurance) + remainder (credited
-        /// as initial capital). Engine never sees this. 0 = disabled.
-        pub new_account_fee: u128,
-        /// Slots of oracle staleness for permissionless resolution. 0 = disabled.
-        pub permissionless_resolve_stale_slots: u64,
-        /// Optional custom funding parameters (override defaults when present)
-        pub funding_horizon_slots: Option<u64>,
-        pub funding_k_bps: Option<u64>,
-        pub funding_max_premium_bps: Option<i64>,
-        pub funding_max_e9_per_slot: Option<i64>,
-        /// Fee-weighted EWMA: min fee for full mark weight. 0 = disabled.
-        pub mark_min_fee: u64,
-        /// Normal wrapper trade fee in bps. Dynamic Hyperp fees add mark
-        /// movement to this base, capped by risk_params.max_trading_fee_bps.
-        pub trade_fee_base_bps: u64,
-        /// Permissionless force-close delay after resolution. 0 = disabled.
-        pub force_close_delay_slots: u64,
+    /// Anti-sandwich withdrawal delay: a withdrawal requested at
+    /// `request_slot` may settle only once `delay_slots` have elapsed, so
+    /// a depositor cannot enter before and exit after a known liquidation.
+    pub fn lp_redeem_unlocked(request_slot: u64, now_slot: u64, delay_slots: u64) -> bool {
+        match request_slot.checked_add(delay_slots) {
+            Some(unlock) => now_slot >= unlock,
+            None => false,
+        }
     }
 
-    #[derive(Debug)]
-    pub enum Instruction {
-        InitMarket(Box<InitMarketArgs>),
-        InitUser {
-            fee_payment: u64,
-        },
-        InitLP {
-            matcher_program: Pubkey,
-            matcher_context: Pubkey,
-            fee_payment: u64,
-        },
-        DepositCollateral {
-            user_idx: u16,
-            amount: u64,
-        },
-        WithdrawCollateral {
-            user_idx: u16,
-            amount: u64,
-        },
-        KeeperCrank {
-            caller_idx: u16,
-            candidates: alloc::vec::Vec<(u16, Option<percolator::LiquidationPolicy>)>,
-        },
-        TradeNoCpi {
-            lp_idx: u16,
-            user_idx: u16,
-            size: i128,
-            /// Optional engine-space execution price. 0 preserves the
-            /// legacy bilateral path that fills at the wrapper's current
-            /// effective price. Nonzero means both signers agree to the
-            /// supplied price; the engine still enforces health and the
-            /// wrapper clamps mark impact separately.
-            exec_price_e6: u64,
-        },
-        CloseAccount {
-            user_idx: u16,
-        },
-        TopUpInsurance {
-            amount: u64,
-        },
-        TradeCpi {
-            lp_idx: u16,
-            user_idx: u16,
-            size: i128,
-            limit_price_e6: u64, // 0 = no limit (backward compat)
-        },
-        /// Close the market slab and recover SOL to the admin-supplied
-        /// destination. Requires: no active accounts, no vault funds,
-        /// no insurance funds.
-        CloseSlab,
-        /// Update configurable funding parameters. Admin only.
-        UpdateConfig {
-            funding_horizon_slots: u64,
-            funding_k_bps: u64,
-            funding_max_premium_bps: i64,
-            funding_max_e9_per_slot: i64,
-            /// Admin-opt-in deposit cap multiplier. 0 disables the check.
-            /// See `MarketConfig.tvl_insurance_cap_mult`.
-            tvl_insurance_cap_mult: u16,
-        },
-        /// Push oracle price (oracle authority only).
-        /// Stores the price for use by crank/trade operations.
-        PushHyperpMark {
-            price_e6: u64,
-            timestamp: i64,
-        },
-        /// Resolve market: force-close all positions at admin oracle price, enter withdraw-only mode.
-        /// Admin only. `mode` selects the §9.8 settlement arm explicitly:
-        ///   0 = Ordinary   (live inputs; rejected when the oracle is stale)
-        ///   1 = Degenerate (settle at engine.last_oracle_price, rate = 0;
-        ///                    wrapper's canonical dead-oracle terminal path).
-        ResolveMarket {
-            mode: u8,
-        },
-        /// Withdraw insurance fund balance (UNBOUNDED). Gated by
-        /// `header.insurance_authority`; requires market resolved +
-        /// all accounts closed. For live, bounded extraction see
-        /// `WithdrawInsuranceLimited` (tag 23). The two paths have
-        /// structurally DISJOINT authority gates — this is what makes
-        /// the bounded path's bps + cooldown bounds un-bypassable.
-        WithdrawInsurance,
-        /// Admin force-close an abandoned account after market resolution.
-        /// Requires RESOLVED flag, zero position, admin signer.
-        AdminForceCloseAccount {
-            user_idx: u16,
-        },
-        /// BOUNDED live insurance withdrawal. Gated by
-        /// `header.insurance_operator` (distinct from `insurance_authority`
-        /// — the split is what makes the bounds meaningful). Per-call
-        /// amount capped at `config.insurance_withdraw_max_bps *
-        /// insurance_fund.balance / 10_000` with a floor of 10 units
-        /// (anti-Zeno). Calls must be at least
-        /// `config.insurance_withdraw_cooldown_slots` apart. Works on
-        /// LIVE markets only; resolved markets use the unbounded tag 20.
-        WithdrawInsuranceLimited {
-            amount: u64,
-        },
-        /// Direct fee-debt repayment (§10.3.1). Owner only.
-        DepositFeeCredits {
-            user_idx: u16,
-            amount: u64,
-        },
-        /// Voluntary PnL conversion with open position (§10.4.1). Owner only.
-        ConvertReleasedPnl {
-            user_idx: u16,
-            amount: u64,
-        },
-        /// Permissionless market resolution after prolonged oracle staleness.
-        /// Anyone can call when the oracle has been stale for at least
-        /// config.permissionless_resolve_stale_slots. Settles at the last
-        /// known good oracle price from engine.last_oracle_price.
-        ResolvePermissionless,
-        /// Permissionless force-close for resolved markets (tag 30).
-        /// Requires RESOLVED + delay. Admin-only. Sends capital to any
-        /// valid SPL token account whose token-owner matches the stored
-        /// owner and whose mint matches `collateral_mint` — the caller
-        /// chooses the destination (typically but not necessarily the
-        /// canonical ATA). The wrapper enforces owner + mint equality
-        /// via `verify_token_account`; it does NOT derive the
-        /// Associated Token Address, so a non-ATA account owned by the
-        /// stored owner is also accepted.
-        ForceCloseResolved {
-            user_idx: u16,
-        },
-        /// Scoped-authority update (tag 32).
-        ///
-        /// kind:
-        ///   0 = AUTHORITY_ADMIN              (header.admin)
-        ///   1 = AUTHORITY_HYPERP_MARK        (config.hyperp_authority)
-        ///   2 = AUTHORITY_INSURANCE          (header.insurance_authority)
-        ///   4 = AUTHORITY_INSURANCE_OPERATOR (header.insurance_operator)
-        /// (kind = 3 / AUTHORITY_CLOSE was deleted; close authority merged
-        /// into admin.)
-        ///
-        /// Authorization: the CURRENT authority of the specified kind
-        /// must sign. When `new_pubkey != Pubkey::default()` the NEW
-        /// pubkey must ALSO sign, proving the receiver consents to
-        /// take the role and the key isn't a typo. When `new_pubkey
-        /// == Pubkey::default()` (burn), only the current authority
-        /// signs.
-        ///
-        /// Each kind is independent — set them all to the same pubkey
-        /// for a "super admin" or delegate/burn them individually for
-        /// capability isolation. Burning `admin` applies the existing
-        /// admin-burn liveness guards (permissionless resolve + force
-        /// close configured). Burning any other kind has no guards —
-        /// it just makes that capability permanently unavailable,
-        /// which is a legitimate rug-proofing configuration.
-        UpdateAuthority {
-            kind: u8,
-            new_pubkey: Pubkey,
-        },
+    /// One market's leg of a cross-market portfolio: signed position
+    /// notional (positive = long) and that market's margin rate.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct PortfolioLeg {
+        pub notional: i128,
+        pub margin_bps: u64,
     }
 
-    impl Instruction {
-        pub fn decode(input: &[u8]) -> Result<Self, ProgramError> {
-            let (&tag, mut rest) = input
-                .split_first()
-                .ok_or(ProgramError::InvalidInstructionData)?;
+    /// Correlation offset between legs `a` and `b`: the share, in bps, of
+    /// the margin on their hedged (opposite-side) notional that is waived.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MarginOffset {
+        pub a: usize,
+        pub b: usize,
+        pub offset_bps: u64,
+    }
 
-            let result = match tag {
-                0 => {
-                    // InitMarket
-                    let admin = read_pubkey(&mut rest)?;
-                    let collateral_mint = read_pubkey(&mut rest)?;
-                    let index_feed_id = read_bytes32(&mut rest)?;
-                    let max_staleness_secs = read_u64(&mut rest)?;
-                    let conf_filter_bps = read_u16(&mut rest)?;
-                    let invert = read_u8(&mut rest)?;
-                    let unit_scale = read_u32(&mut rest)?;
-                    let initial_mark_price_e6 = read_u64(&mut rest)?;
-                    let maintenance_fee_per_slot = read_u128(&mut rest)?; // periodic fee per slot per account
-                                                                          // Insurance withdrawal limits (immutable after init)
-                    let (risk_params, new_account_fee) = read_risk_params(&mut rest)?;
-                    // Extended fields: either ALL present (66 bytes) or NONE.
-                    // No partial tails — prevents silent misparsing of truncated payloads.
-                    // Total: insurance(2+8) + permissionless(8) + funding(8+8+8+8) +
-                    //        mark_min_fee(8) + force_close_delay(8) = 66 bytes
-                    const EXTENDED_TAIL_LEN: usize = 2 + 8 * 8;
-                    let (
-                        insurance_withdraw_max_bps,
-                        insurance_withdraw_cooldown_slots,
-                        permissionless_resolve_stale_slots,
-                        funding_horizon_slots,
-                        funding_k_bps,
-                        funding_max_premium_bps,
-                        funding_max_e9_per_slot,
-                        mark_min_fee,
-                        force_close_delay_slots,
-                    ) = if rest.is_empty() {
-                        // Minimal payload: all extended fields use defaults.
-                        // permissionless_resolve_stale_slots seeds to
-                        // DEFAULT_PERMISSIONLESS_RESOLVE_STALE_SLOTS. With
-                        // the production default of 0, non-Hyperp markets
-                        // must use the extended tail to opt into a
-                        // permissionless exit.
-                        // force_close_delay_slots seeds to 1 slot (minimum
-                        // liveness) to satisfy the init-time validation that
-                        // permissionless_resolve > 0 ⇒ force_close > 0.
-                        (
-                            0u16,
-                            0u64,
-                            crate::constants::DEFAULT_PERMISSIONLESS_RESOLVE_STALE_SLOTS,
-                            None,
-                            None,
-                            None,
-                            None,
-                            0u64,
-                            1u64,
-                        )
-                    } else if rest.len() >= EXTENDED_TAIL_LEN {
-                        // Full extended payload
-                        let iwm = read_u16(&mut rest)?;
-                        let iwc = read_u64(&mut rest)?;
-                        let prs = read_u64(&mut rest)?;
+    /// Cap on `MarginOffset::offset_bps`: even perfectly correlated
+    /// markets basis-trade, so a hedge always keeps some margin.
+    pub const MAX_MARGIN_OFFSET_BPS: u64 = 9_000;
+    /// Markets one `portfolio_margin` call covers.
+    pub const MAX_PORTFOLIO_LEGS: usize = 16;
+
+    /// Margin for `legs` with correlation offsets applied, in notional
+    /// units.
+    ///
+    /// Starts from the naive sum `ceil(|notional| * margin_bps / 10_000)`.
+    /// Offsets apply in order; each pairs the smaller remaining notional
+    /// of two opposite-side legs and credits
+    /// `floor(hedged * (margin_bps_a + margin_bps_b) / 10_000 * offset_bps / 10_000)`.
+    /// Hedged notional is consumed, so a leg never offsets more than its
+    /// size across all pairs. Same-side pairs earn nothing. `None` for
+    /// more than `MAX_PORTFOLIO_LEGS` legs, an out-of-range or self pair,
+    /// an offset above `MAX_MARGIN_OFFSET_BPS`, or overflow.
+    pub fn portfolio_margin(legs: &[PortfolioLeg], offsets: &[MarginOffset]) -> Option<u128> {
+        if legs.len() > MAX_PORTFOLIO_LEGS {
+            return None;
+        }
+        let mut naive: u128 = 0;
+        let mut remaining = [0u128; MAX_PORTFOLIO_LEGS];
+        for (i, leg) in legs.iter().enumerate() {
+            let abs = leg.notional.unsigned_abs();
+            naive = naive.checked_add(mul_div_ceil(abs, leg.margin_bps as u128, 10_000)?)?;
+            remaining[i] = abs;
+        }
+        let mut credit: u128 = 0;
+        for o in offsets {
+            if o.a == o.b
+                || o.a >= legs.len()
+                || o.b >= legs.len()
+                || o.offset_bps > MAX_MARGIN_OFFSET_BPS
+            {
+                return None;
+            }
+            let (la, lb) = (legs[o.a], legs[o.b]);
+            if la.notional.signum() * lb.notional.signum() >= 0 {
+                continue;
+            }
+            let hedged = remaining[o.a].min(remaining[o.b]);
+            remaining[o.a] -= hedged;
+            remaining[o.b] -= hedged;
+            let rate = (la.margin_bps as u128).checked_add(lb.margin_bps as u128)?;
+            let pair_margin = mul_div_floor(hedged, rate, 10_000)?;
+            credit =
+                credit.checked_add(mul_div_floor(pair_margin, o.offset_bps as u128, 10_000)?)?;
+        }
+        Some(naive.saturating_sub(credit))
+    }
+
+    /// Price-impact term of the vAMM matcher curve, in bps.
+    ///
+    /// Linear in fill notional over the LP's configured depth:
+    ///
+    /// ```text
+    /// impact_bps = ceil(fill_notional_e6 * impact_k_bps / liquidity_notional_e6)
+    /// ```
+    ///
+    /// `liquidity_notional_e6 == 0` disables impact (flat-spread matcher).
+    /// Rounded up so the rounding residue stays with the LP, never the taker.
+    pub fn vamm_impact_bps(
+        fill_notional_e6: u128,
+        impact_k_bps: u32,
+        liquidity_notional_e6: u128,
+    ) -> Option<u64> {
+        if liquidity_notional_e6 == 0 || impact_k_bps == 0 || fill_notional_e6 == 0 {
+            return Some(0);
+        }
+        let bps = mul_div_ceil(
+            fill_notional_e6,
+            impact_k_bps as u128,
+            liquidity_notional_e6,
+        )?;
+        if bps > u64::MAX as u128 {
+            None
+        } else {
+            Some(bps as u64)
+        }
+    }
+
+    /// Sanity bounds for a vAMM parameter set, checked by the matcher at
+    /// InitVamm and on every UpdateVammParams:
+    /// - `max_total_bps` at most `MAX_VAMM_TOTAL_BPS`, so a sell quote can
+    ///   never reach zero (`vamm_exec_price_e6` stays `Some`)
+    /// - fee plus base spread within `max_total_bps`, so the cap does not
+    ///   silently swallow the configured spread
+    /// - impact needs depth: `impact_k_bps > 0` requires
+    ///   `liquidity_notional_e6 > 0`
+    /// - a nonzero inventory cap at least one max fill, so the first fill
+    ///   in either direction is always possible
+    pub fn vamm_params_ok(p: &crate::matcher_abi::VammParams) -> bool {
+        p.max_total_bps <= crate::constants::MAX_VAMM_TOTAL_BPS
+            && (p.trading_fee_bps as u64) + (p.base_spread_bps as u64) <= p.max_total_bps as u64
+            && (p.impact_k_bps == 0 || p.liquidity_notional_e6 > 0)
+            && (p.max_inventory_abs == 0
+                || p.max_fill_abs == 0
+                || p.max_inventory_abs >= p.max_fill_abs)
+    }
+
+    /// Reference vAMM quote: the fill the matcher would give a taker
+    /// request of `size_q` given the LP's current `inventory` (LP side,
+    /// so a taker buy lowers it), and the price of that fill.
+    ///
+    /// The fill is `size_q` clamped to `max_fill_abs` and to the room left
+    /// under `max_inventory_abs` (either cap 0 = unbounded). A request
+    /// with no room quotes a zero fill at price 0. Returns `None` where
+    /// `vamm_exec_price_e6` does.
+    pub fn vamm_quote(
+        p: &crate::matcher_abi::VammParams,
+        oracle_price_e6: u64,
+        size_q: i128,
+        inventory: i128,
+    ) -> Option<crate::matcher_abi::VammQuote> {
+        let mut fill_abs = size_q.unsigned_abs();
+        if p.max_fill_abs != 0 {
+            fill_abs = fill_abs.min(p.max_fill_abs);
+        }
+        if p.max_inventory_abs != 0 {
+            // The LP moves against the taker: a buy pushes inventory
+            // toward -max, a sell toward +max.
+            let cap = p.max_inventory_abs.min(i128::MAX as u128) as i128;
+            let room = if size_q > 0 {
+                inventory.saturating_add(cap)
+            } else {
+                cap.saturating_sub(inventory)
+            };
+            fill_abs = fill_abs.min(room.max(0) as u128);
+        }
+        if fill_abs == 0 {
+            return Some(crate::matcher_abi::VammQuote::default());
+        }
+        let fill_size = if size_q > 0 {
+            fill_abs as i128
+        } else {
+            -(fill_abs as i128)
+        };
+        let exec_price_e6 = vamm_exec_price_e6(
+            oracle_price_e6,
+            fill_size,
+            p.trading_fee_bps,
+            p.base_spread_bps,
+            p.max_total_bps,
+            p.impact_k_bps,
+            p.liquidity_notional_e6,
+        )?;
+        // Same total as inside vamm_exec_price_e6; cannot overflow there
+        // without having returned None above.
+        let impact = vamm_impact_bps(
+            fill_abs * oracle_price_e6 as u128 / percolator::POS_SCALE,
+            p.impact_k_bps,
+            p.liquidity_notional_e6,
+        )?;
+        let total_bps = core::cmp::min(
+            (p.trading_fee_bps as u64)
+                .saturating_add(p.base_spread_bps as u64)
+                .saturating_add(impact),
+            p.max_total_bps as u64,
+        ) as u32;
+        Some(crate::matcher_abi::VammQuote {
+            exec_price_e6,
+            fill_size,
+            total_bps,
+        })
+    }
+
+    /// Execution price the vAMM matcher quotes for a fill of `size_q`
+    /// against `oracle_price_e6`:
+    ///
+    /// ```text
+    /// total_bps = min(trading_fee + base_spread + impact_bps, max_total_bps)
+    /// buy  -> ceil (oracle * (10_000 + total_bps) / 10_000)
+    /// sell -> floor(oracle * (10_000 - total_bps) / 10_000)
+    /// ```
+    ///
+    /// Fill notional is `|size_q| * oracle / POS_SCALE`, the same notional
+    /// basis the engine uses for margin. Both roundings favor the LP.
+    /// Returns `None` on a zero size/price, on overflow, or when a sell's
+    /// total spread would consume the whole price.
+    pub fn vamm_exec_price_e6(
+        oracle_price_e6: u64,
+        size_q: i128,
+        trading_fee_bps: u32,
+        base_spread_bps: u32,
+        max_total_bps: u32,
+        impact_k_bps: u32,
+        liquidity_notional_e6: u128,
+    ) -> Option<u64> {
+        if oracle_price_e6 == 0 || size_q == 0 {
+            return None;
+        }
+        let fill_notional_e6 = mul_div_floor(
+            size_q.unsigned_abs(),
+            oracle_price_e6 as u128,
+            percolator::POS_SCALE,
+        )?;
+        let impact = vamm_impact_bps(fill_notional_e6, impact_k_bps, liquidity_notional_e6)?;
+        let uncapped = (trading_fee_bps as u64)
+            .saturating_add(base_spread_bps as u64)
+            .saturating_add(impact);
+        let total_bps = core::cmp::min(uncapped, max_total_bps as u64) as u128;
+        let px = oracle_price_e6 as u128;
+        let exec = if size_q > 0 {
+            mul_div_ceil(px, 10_000 + total_bps, 10_000)?
+        } else {
+            if total_bps >= 10_000 {
+                return None;
+            }
+            mul_div_floor(px, 10_000 - total_bps, 10_000)?
+        };
+        if exec == 0 || exec > u64::MAX as u128 {
+            None
+        } else {
+            Some(exec as u64)
+        }
+    }
+}
+
+// 2. mod zc (Zero-Copy unsafe island)
+#[allow(unsafe_code)]
+pub mod zc {
+    use crate::constants::{ENGINE_ALIGN, ENGINE_LEN, ENGINE_OFF, RISK_BUF_OFF};
+    use core::mem::offset_of;
+    use percolator::RiskEngine;
+    use solana_program::program_error::ProgramError;
+
+    // Use const to export the actual offset for debugging
+    pub const ACCOUNTS_OFFSET: usize = offset_of!(RiskEngine, accounts);
+
+    /// Offset of side_mode_long within RiskEngine (repr(u8) enum)
+    const SM_LONG_OFF: usize = offset_of!(RiskEngine, side_mode_long);
+    /// Offset of side_mode_short within RiskEngine (repr(u8) enum)
+    const SM_SHORT_OFF: usize = offset_of!(RiskEngine, side_mode_short);
+    /// Offset of market_mode within RiskEngine (repr(u8) enum)
+    const MM_OFF: usize = offset_of!(RiskEngine, market_mode);
+    /// Offset of bankruptcy_hmax_lock_active within RiskEngine (bool)
+    const BANKRUPTCY_HMAX_LOCK_ACTIVE_OFF: usize =
+        offset_of!(RiskEngine, bankruptcy_hmax_lock_active);
+    // Runtime tripwire: a unit test in tests/unit.rs
+    // (`test_zc_cast_safety_invariant`) asserts that no slab-persisted
+    // field has an invalid bit pattern beyond the enums validated
+    // above. A compile-time size assert was considered but rejected:
+    // sizeof<RiskEngine> differs between x86_64 and sbf targets (u128
+    // alignment), so a const-eval tripwire cannot cover both builds.
+    // The unit test runs on x86_64 but is a structural check — it
+    // inspects type identities, not sizes — so it is target-
+    // independent and still catches the "someone silently added a
+    // bool field" class.
+
+    /// Validate ALL fields with invalid bit patterns from raw bytes
+    /// BEFORE casting the slab to &RiskEngine / &mut RiskEngine.
+    /// Required because the cast is `unsafe`: a Rust reference to a
+    /// struct containing an invalid bit pattern is UB on first field
+    /// access, irrespective of whether we read the field.
+    ///
+    /// With the currently pinned engine, the field types in the RiskEngine
+    /// slab with invalid bit patterns are:
+    ///   - SideMode (2 instances at side_mode_long / side_mode_short):
+    ///     valid tag bytes 0 (Normal), 1 (DrainOnly), 2 (ResetPending).
+    ///   - MarketMode (at market_mode): valid tag bytes 0 (Live),
+    ///     1 (Resolved).
+    ///   - bankruptcy_hmax_lock_active: valid bool bytes 0 or 1.
+    /// No other field type in either RiskEngine or Account has invalid
+    /// bit patterns: every other field is u64/u128/i64/i128/[u8; N]/
+    /// wrapper-Pod (U128/I128) or fixed u8 — all-bits-valid types.
+    ///
+    /// If a future revision adds any new enum or bool field to the slab, the
+    /// validation below must be extended before the cast can be considered
+    /// sound.
+    #[inline]
+    fn validate_raw_engine_state_shape(data: &[u8]) -> Result<(), ProgramError> {
+        let base = ENGINE_OFF;
+        // SideMode: valid 0 (Normal), 1 (DrainOnly), 2 (ResetPending)
+        let sm_long = data[base + SM_LONG_OFF];
+        let sm_short = data[base + SM_SHORT_OFF];
+        if sm_long > 2 || sm_short > 2 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // MarketMode: valid 0 (Live), 1 (Resolved)
+        let mm = data[base + MM_OFF];
+        if mm > 1 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let hmax = data[base + BANKRUPTCY_HMAX_LOCK_ACTIVE_OFF];
+        if hmax > 1 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    pub fn validate_raw_engine_bytes_for_test(data: &[u8]) -> Result<(), ProgramError> {
+        validate_raw_engine_state_shape(data)
+    }
+
+    pub fn engine_ref<'a>(data: &'a [u8]) -> Result<&'a RiskEngine, ProgramError> {
+        // Require full ENGINE_LEN to avoid UB from reference extending past buffer
+        if data.len() < ENGINE_OFF + ENGINE_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let ptr = unsafe { data.as_ptr().add(ENGINE_OFF) };
+        if (ptr as usize) % ENGINE_ALIGN != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // Validate invalid-bit-pattern fields before creating a reference.
+        validate_raw_engine_state_shape(data)?;
+        Ok(unsafe { &*(ptr as *const RiskEngine) })
+    }
+
+    #[inline]
+    pub fn engine_mut<'a>(data: &'a mut [u8]) -> Result<&'a mut RiskEngine, ProgramError> {
+        if data.len() < ENGINE_OFF + ENGINE_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let ptr = unsafe { data.as_mut_ptr().add(ENGINE_OFF) };
+        if (ptr as usize) % ENGINE_ALIGN != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        validate_raw_engine_state_shape(data)?;
+        Ok(unsafe { &mut *(ptr as *mut RiskEngine) })
+    }
+
+    /// One slab borrow split into non-overlapping regions: header and
+    /// config bytes (`head`, laid out as in the slab, so
+    /// `state::read_config(head)` works), the engine, and every wrapper
+    /// region after it (`tail`, from `RISK_BUF_OFF`). The engine shape is
+    /// validated once, and those regions stay readable and writable while
+    /// the engine is borrowed.
+    pub struct SlabRegions<'a> {
+        pub head: &'a mut [u8],
+        pub engine: &'a mut RiskEngine,
+        pub tail: &'a mut [u8],
+    }
+
+    impl SlabRegions<'_> {
+        /// Bytes of the wrapper region at absolute slab offset `off`
+        /// (a `*_OFF` constant at or after `RISK_BUF_OFF`).
+        pub fn region(&self, off: usize, len: usize) -> &[u8] {
+            &self.tail[off - RISK_BUF_OFF..off - RISK_BUF_OFF + len]
+        }
+
+        pub fn region_mut(&mut self, off: usize, len: usize) -> &mut [u8] {
+            &mut self.tail[off - RISK_BUF_OFF..off - RISK_BUF_OFF + len]
+        }
+    }
+
+    pub fn split_mut(data: &mut [u8]) -> Result<SlabRegions<'_>, ProgramError> {
+        if data.len() < RISK_BUF_OFF {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        validate_raw_engine_state_shape(data)?;
+        let (head, rest) = data.split_at_mut(ENGINE_OFF);
+        let (engine_bytes, tail) = rest.split_at_mut(ENGINE_LEN);
+        let ptr = engine_bytes.as_mut_ptr();
+        if (ptr as usize) % ENGINE_ALIGN != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(SlabRegions {
+            head,
+            engine: unsafe { &mut *(ptr as *mut RiskEngine) },
+            tail,
+        })
+    }
+
+    // NOTE: engine_write was removed because it requires passing RiskEngine by value,
+    // which stack-allocates the ~6MB struct and causes stack overflow in BPF.
+    // Use engine_mut() + init_in_place() instead for initialization.
+
+    use solana_program::{
+        account_info::AccountInfo, instruction::Instruction as SolInstruction,
+        program::invoke_signed,
+    };
+
+    /// Invoke the matcher program via CPI. The AccountInfo clones
+    /// satisfy solana_program::program::invoke_signed's ownership
+    /// requirement without relying on lifetime transmutes.
+    ///
+    /// `tail` is the caller-supplied variadic account list that
+    /// TradeCpi forwards verbatim to the matcher. The wrapper does
+    /// NOT validate tail contents — the matcher owns that
+    /// responsibility. Tail length is unbounded at the wire level;
+    /// Solana's CPI transaction-size and account-count limits are
+    /// the effective cap.
+    #[inline]
+    pub fn invoke_signed_trade<'a>(
+        ix: &SolInstruction,
+        a_lp_pda: &AccountInfo<'a>,
+        a_matcher_ctx: &AccountInfo<'a>,
+        a_matcher_prog: &AccountInfo<'a>,
+        tail: &[AccountInfo<'a>],
+        seeds: &[&[u8]],
+    ) -> Result<(), ProgramError> {
+        // Infos: lp_pda + matcher_ctx + matcher_prog + tail. The
+        // matcher_prog is always included because invoke_signed needs
+        // it to resolve the destination program; the CPI metas do not
+        // list it (Solana convention).
+        let mut infos: alloc::vec::Vec<AccountInfo<'a>> =
+            alloc::vec::Vec::with_capacity(3 + tail.len());
+        infos.push(a_lp_pda.clone());
+        infos.push(a_matcher_ctx.clone());
+        infos.push(a_matcher_prog.clone());
+        for ai in tail.iter() {
+            infos.push(ai.clone());
+        }
+        invoke_signed(ix, &infos, &[seeds])
+    }
+}
+
+pub mod matcher_abi {
+    use crate::constants::MATCHER_ABI_VERSION;
+    use solana_program::program_error::ProgramError;
+
+    /// Matcher return flags
+    pub const FLAG_VALID: u32 = 1; // bit0: response is valid
+    pub const FLAG_PARTIAL_OK: u32 = 2; // bit1: partial fill, including zero, allowed
+    pub const FLAG_REJECTED: u32 = 4; // bit2: trade rejected by matcher
+
+    /// Matcher return structure.
+    /// IMPORTANT: exec_price_e6 must be in engine-space (already inverted
+    /// and scaled). The matcher receives oracle_price_e6 in engine-space
+    /// and must return exec_price_e6 in the same space. The wrapper stores
+    /// it directly as the Hyperp mark price without re-normalization.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct MatcherReturn {
+        pub abi_version: u32,
+        pub flags: u32,
+        pub exec_price_e6: u64,
+        pub exec_size: i128,
+        pub req_id: u64,
+        pub lp_account_id: u64,
+        pub oracle_price_e6: u64,
+        pub reserved: u64,
+    }
+
+    pub fn read_matcher_return(ctx: &[u8]) -> Result<MatcherReturn, ProgramError> {
+        if ctx.len() < 64 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let abi_version = u32::from_le_bytes(ctx[0..4].try_into().unwrap());
+        let flags = u32::from_le_bytes(ctx[4..8].try_into().unwrap());
+        let exec_price_e6 = u64::from_le_bytes(ctx[8..16].try_into().unwrap());
+        let exec_size = i128::from_le_bytes(ctx[16..32].try_into().unwrap());
+        let req_id = u64::from_le_bytes(ctx[32..40].try_into().unwrap());
+        let lp_account_id = u64::from_le_bytes(ctx[40..48].try_into().unwrap());
+        let oracle_price_e6 = u64::from_le_bytes(ctx[48..56].try_into().unwrap());
+        let reserved = u64::from_le_bytes(ctx[56..64].try_into().unwrap());
+
+        Ok(MatcherReturn {
+            abi_version,
+            flags,
+            exec_price_e6,
+            exec_size,
+            req_id,
+            lp_account_id,
+            oracle_price_e6,
+            reserved,
+        })
+    }
+
+    pub fn validate_matcher_return(
+        ret: &MatcherReturn,
+        lp_account_id: u64,
+        oracle_price_e6: u64,
+        req_size: i128,
+        req_id: u64,
+    ) -> Result<(), ProgramError> {
+        // Check ABI version
+        if ret.abi_version != MATCHER_ABI_VERSION {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // Reject any flag bits outside the known set. Prevents a future
+        // matcher that uses a currently-undefined flag (e.g. a new partial
+        // fill semantics) from being silently accepted by this wrapper —
+        // upgraders must bump the ABI version to signal new flag meaning.
+        const KNOWN_FLAGS: u32 = FLAG_VALID | FLAG_PARTIAL_OK | FLAG_REJECTED;
+        if (ret.flags & !KNOWN_FLAGS) != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // Must have VALID flag set
+        if (ret.flags & FLAG_VALID) == 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // Must not have REJECTED flag set
+        if (ret.flags & FLAG_REJECTED) != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Validate echoed fields match request
+        if ret.lp_account_id != lp_account_id {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if ret.oracle_price_e6 != oracle_price_e6 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if ret.reserved != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if ret.req_id != req_id {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Require exec_price_e6 != 0 always - avoids "all zeros but valid flag" ambiguity
+        if ret.exec_price_e6 == 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Zero exec_size requires PARTIAL_OK flag
+        if ret.exec_size == 0 {
+            if (ret.flags & FLAG_PARTIAL_OK) == 0 {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            // Zero-fill carries no executable price. Require the matcher to
+            // echo the request oracle as the canonical no-op price so callers,
+            // indexers, and future wrapper code never ingest adversarial
+            // off-market `exec_price_e6` values from a no-fill response.
+            if ret.exec_price_e6 != oracle_price_e6 {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            // Canonical zero fill with PARTIAL_OK is allowed - return early
+            return Ok(());
+        }
+
+        // Size constraints (use unsigned_abs to avoid i128::MIN overflow)
+        if ret.exec_size.unsigned_abs() > req_size.unsigned_abs() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if req_size != 0 {
+            if ret.exec_size.signum() != req_size.signum() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+        if ret.exec_size.unsigned_abs() < req_size.unsigned_abs()
+            && (ret.flags & FLAG_PARTIAL_OK) == 0
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Offset of the LP PDA the matcher records when its context is
+    /// initialized (vAMM header: magic u64, version u32, kind u8, pad 3).
+    /// The PDA is derived from `["lp", slab, lp_idx]`, so it binds the
+    /// context to one slab and one LP slot.
+    pub const CTX_LP_PDA_OFFSET: usize = 80;
+
+    /// LP PDA the context is bound to.
+    pub fn read_ctx_lp_pda(ctx: &[u8]) -> Result<[u8; 32], ProgramError> {
+        if ctx.len() < CTX_LP_PDA_OFFSET + 32 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(ctx[CTX_LP_PDA_OFFSET..CTX_LP_PDA_OFFSET + 32]
+            .try_into()
+            .unwrap())
+    }
+
+    /// Capability header the matcher keeps in its context. The wrapper
+    /// reads it before the CPI, so a matcher upgrade can change what it
+    /// supports without a wrapper upgrade or a market migration.
+    ///
+    /// ```text
+    /// offset  field         type
+    ///  256    caps_version  u32   0 = no header (pre-capability matcher)
+    ///  260    caps_flags    u32   CAP_* bits
+    ///  264    max_fill_abs  u128  largest |exec_size| per call, 0 = unbounded
+    /// ```
+    ///
+    /// Fields are append-only: a version above `CAPS_VERSION` is read as
+    /// its v1 prefix and unknown flag bits are ignored.
+    pub const CTX_CAPS_OFFSET: usize = 256;
+    pub const CAPS_VERSION: u32 = 1;
+    /// Execution price depends on fill size.
+    pub const CAP_IMPACT_PRICING: u32 = 1;
+    /// Matcher may fill less than requested (including zero).
+    pub const CAP_PARTIAL_FILL: u32 = 2;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MatcherCaps {
+        pub version: u32,
+        pub flags: u32,
+        pub max_fill_abs: u128,
+    }
+
+    impl MatcherCaps {
+        /// What a context without a header is taken to support: anything
+        /// the return ABI allows, as before capabilities existed.
+        pub const LEGACY: Self = Self {
+            version: 0,
+            flags: CAP_IMPACT_PRICING | CAP_PARTIAL_FILL,
+            max_fill_abs: 0,
+        };
+    }
+
+    /// Matcher-side instruction tags (tag 0 is the TradeCpi call,
+    /// `constants::MATCHER_CALL_TAG`). The handlers live in the matcher
+    /// program; the wire formats are defined here so the wrapper's tooling
+    /// and the matcher share one encoding.
+    pub const MATCHER_INIT_VAMM_TAG: u8 = 2;
+    pub const MATCHER_UPDATE_VAMM_TAG: u8 = 3;
+
+    /// Tunable vAMM parameters, in InitVamm / UpdateVammParams wire order:
+    ///
+    /// ```text
+    ///   0..4   trading_fee_bps        u32
+    ///   4..8   base_spread_bps        u32
+    ///   8..12  max_total_bps          u32
+    ///  12..16  impact_k_bps           u32
+    ///  16..32  liquidity_notional_e6  u128
+    ///  32..48  max_fill_abs           u128
+    ///  48..64  max_inventory_abs      u128
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct VammParams {
+        pub trading_fee_bps: u32,
+        pub base_spread_bps: u32,
+        pub max_total_bps: u32,
+        pub impact_k_bps: u32,
+        pub liquidity_notional_e6: u128,
+        pub max_fill_abs: u128,
+        pub max_inventory_abs: u128,
+    }
+
+    pub const VAMM_PARAMS_LEN: usize = 64;
+
+    impl VammParams {
+        pub fn encode(&self) -> [u8; VAMM_PARAMS_LEN] {
+            let mut out = [0u8; VAMM_PARAMS_LEN];
+            out[0..4].copy_from_slice(&self.trading_fee_bps.to_le_bytes());
+            out[4..8].copy_from_slice(&self.base_spread_bps.to_le_bytes());
+            out[8..12].copy_from_slice(&self.max_total_bps.to_le_bytes());
+            out[12..16].copy_from_slice(&self.impact_k_bps.to_le_bytes());
+            out[16..32].copy_from_slice(&self.liquidity_notional_e6.to_le_bytes());
+            out[32..48].copy_from_slice(&self.max_fill_abs.to_le_bytes());
+            out[48..64].copy_from_slice(&self.max_inventory_abs.to_le_bytes());
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != VAMM_PARAMS_LEN {
+                return None;
+            }
+            let u32_at = |o: usize| u32::from_le_bytes(data[o..o + 4].try_into().unwrap());
+            let u128_at = |o: usize| u128::from_le_bytes(data[o..o + 16].try_into().unwrap());
+            Some(Self {
+                trading_fee_bps: u32_at(0),
+                base_spread_bps: u32_at(4),
+                max_total_bps: u32_at(8),
+                impact_k_bps: u32_at(12),
+                liquidity_notional_e6: u128_at(16),
+                max_fill_abs: u128_at(32),
+                max_inventory_abs: u128_at(48),
+            })
+        }
+    }
+
+    /// InitVamm instruction data: tag 2, the mode (`VAMM_MODE_*`), then
+    /// the params. Accounts: `[lp_pda, matcher_ctx (writable)]`; the
+    /// matcher checks the params with `policy::vamm_params_ok`.
+    pub fn encode_init_vamm(mode: u8, p: &VammParams) -> [u8; 2 + VAMM_PARAMS_LEN] {
+        let mut out = [0u8; 2 + VAMM_PARAMS_LEN];
+        out[0] = MATCHER_INIT_VAMM_TAG;
+        out[1] = mode;
+        out[2..].copy_from_slice(&p.encode());
+        out
+    }
+
+    /// UpdateVammParams instruction data: tag 3, then the params.
+    /// Accounts: `[authority (signer), matcher_ctx (writable)]`, where the
+    /// authority is the one the context recorded at InitVamm.
+    pub fn encode_update_vamm_params(p: &VammParams) -> [u8; 1 + VAMM_PARAMS_LEN] {
+        let mut out = [0u8; 1 + VAMM_PARAMS_LEN];
+        out[0] = MATCHER_UPDATE_VAMM_TAG;
+        out[1..].copy_from_slice(&p.encode());
+        out
+    }
+
+    /// vAMM state the matcher keeps after the return prefix:
+    ///
+    /// ```text
+    ///   64..72   magic                  u64   VAMM_MAGIC
+    ///   72..76   version                u32
+    ///   76       mode                   u8    VAMM_MODE_*
+    ///   80..112  lp_pda                 [u8; 32]  (CTX_LP_PDA_OFFSET)
+    ///  112..128  fee, spread, max_total, impact_k   u32 each
+    ///  128..144  liquidity_notional_e6  u128
+    ///  144..160  max_fill_abs           u128
+    ///  160..176  inventory_base         i128  LP side
+    ///  176..192  last oracle / exec price  u64 each
+    ///  192..208  max_inventory_abs      u128
+    /// ```
+    pub const CTX_VAMM_OFFSET: usize = 64;
+    pub const VAMM_MAGIC: u64 = 0x5045_5243_4d41_5443; // "PERCMATC"
+    /// Fee + spread only; the impact term is ignored.
+    pub const VAMM_MODE_PASSIVE: u8 = 0;
+    /// Fee + spread + linear impact over `liquidity_notional_e6`.
+    pub const VAMM_MODE_IMPACT: u8 = 1;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct VammCtx {
+        pub mode: u8,
+        pub params: VammParams,
+        pub inventory: i128,
+    }
+
+    impl VammCtx {
+        /// Parameters as priced: Passive contexts quote without impact.
+        pub fn effective_params(&self) -> VammParams {
+            if self.mode == VAMM_MODE_PASSIVE {
+                VammParams {
+                    impact_k_bps: 0,
+                    ..self.params
+                }
+            } else {
+                self.params
+            }
+        }
+    }
+
+    /// `None` if the context is short or was never initialized as a vAMM.
+    pub fn read_vamm_ctx(ctx: &[u8]) -> Option<VammCtx> {
+        if ctx.len() < 208 {
+            return None;
+        }
+        let u32_at = |o: usize| u32::from_le_bytes(ctx[o..o + 4].try_into().unwrap());
+        let u128_at = |o: usize| u128::from_le_bytes(ctx[o..o + 16].try_into().unwrap());
+        if u64::from_le_bytes(ctx[64..72].try_into().unwrap()) != VAMM_MAGIC {
+            return None;
+        }
+        let mode = ctx[76];
+        if mode != VAMM_MODE_PASSIVE && mode != VAMM_MODE_IMPACT {
+            return None;
+        }
+        Some(VammCtx {
+            mode,
+            params: VammParams {
+                trading_fee_bps: u32_at(112),
+                base_spread_bps: u32_at(116),
+                max_total_bps: u32_at(120),
+                impact_k_bps: u32_at(124),
+                liquidity_notional_e6: u128_at(128),
+                max_fill_abs: u128_at(144),
+                max_inventory_abs: u128_at(192),
+            },
+            inventory: i128::from_le_bytes(ctx[160..176].try_into().unwrap()),
+        })
+    }
+
+    /// Quote instruction: tag 4, `oracle_price_e6` u64, `size` i128
+    /// (taker side, as in the TradeCpi call). Accounts: `[matcher_ctx]`,
+    /// read-only. The matcher prices the request exactly as a call would,
+    /// changes no state and answers through return data (`VammQuote`), so
+    /// clients and on-chain aggregators can read an executable price
+    /// without simulating a trade.
+    pub const MATCHER_QUOTE_TAG: u8 = 4;
+    pub const MATCHER_QUOTE_LEN: usize = 25;
+
+    pub fn encode_quote(oracle_price_e6: u64, size: i128) -> [u8; MATCHER_QUOTE_LEN] {
+        let mut out = [0u8; MATCHER_QUOTE_LEN];
+        out[0] = MATCHER_QUOTE_TAG;
+        out[1..9].copy_from_slice(&oracle_price_e6.to_le_bytes());
+        out[9..25].copy_from_slice(&size.to_le_bytes());
+        out
+    }
+
+    /// Quote return data. Fixed 28-byte little-endian layout:
+    ///
+    /// ```text
+    ///   0..8   exec_price_e6  u64   engine space; 0 = nothing fillable
+    ///   8..24  fill_size      i128  taker side, |fill| <= |requested|
+    ///  24..28  total_bps      u32   fee + spread + impact after the cap
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct VammQuote {
+        pub exec_price_e6: u64,
+        pub fill_size: i128,
+        pub total_bps: u32,
+    }
+
+    pub const VAMM_QUOTE_LEN: usize = 28;
+
+    impl VammQuote {
+        pub fn encode(&self) -> [u8; VAMM_QUOTE_LEN] {
+            let mut out = [0u8; VAMM_QUOTE_LEN];
+            out[0..8].copy_from_slice(&self.exec_price_e6.to_le_bytes());
+            out[8..24].copy_from_slice(&self.fill_size.to_le_bytes());
+            out[24..28].copy_from_slice(&self.total_bps.to_le_bytes());
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != VAMM_QUOTE_LEN {
+                return None;
+            }
+            Some(Self {
+                exec_price_e6: u64::from_le_bytes(data[0..8].try_into().unwrap()),
+                fill_size: i128::from_le_bytes(data[8..24].try_into().unwrap()),
+                total_bps: u32::from_le_bytes(data[24..28].try_into().unwrap()),
+            })
+        }
+    }
+
+    /// `sol_log_data` record the matcher writes on a successful
+    /// UpdateVammParams, so indexers see every live re-pricing:
+    ///
+    /// ```text
+    ///    0..4    magic  b"vamm"
+    ///    4..68   old    VammParams
+    ///   68..132  new    VammParams
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct VammParamsUpdatedEvent {
+        pub old: VammParams,
+        pub new: VammParams,
+    }
+
+    pub const VAMM_PARAMS_EVENT_MAGIC: [u8; 4] = *b"vamm";
+    pub const VAMM_PARAMS_EVENT_LEN: usize = 4 + 2 * VAMM_PARAMS_LEN;
+
+    impl VammParamsUpdatedEvent {
+        pub fn encode(&self) -> [u8; VAMM_PARAMS_EVENT_LEN] {
+            let mut out = [0u8; VAMM_PARAMS_EVENT_LEN];
+            out[0..4].copy_from_slice(&VAMM_PARAMS_EVENT_MAGIC);
+            out[4..68].copy_from_slice(&self.old.encode());
+            out[68..132].copy_from_slice(&self.new.encode());
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != VAMM_PARAMS_EVENT_LEN || data[0..4] != VAMM_PARAMS_EVENT_MAGIC {
+                return None;
+            }
+            Some(Self {
+                old: VammParams::decode(&data[4..68])?,
+                new: VammParams::decode(&data[68..132])?,
+            })
+        }
+    }
+
+    pub fn read_matcher_caps(ctx: &[u8]) -> Result<MatcherCaps, ProgramError> {
+        if ctx.len() < CTX_CAPS_OFFSET + 24 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let h = &ctx[CTX_CAPS_OFFSET..CTX_CAPS_OFFSET + 24];
+        let version = u32::from_le_bytes(h[0..4].try_into().unwrap());
+        if version == 0 {
+            return Ok(MatcherCaps::LEGACY);
+        }
+        Ok(MatcherCaps {
+            version,
+            flags: u32::from_le_bytes(h[4..8].try_into().unwrap()),
+            max_fill_abs: u128::from_le_bytes(h[8..24].try_into().unwrap()),
+        })
+    }
+}
+
+// 3. mod error
+pub mod error {
+    use percolator::RiskError;
+    use solana_program::program_error::ProgramError;
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub enum PercolatorError {
+        InvalidMagic,
+        InvalidVersion,
+        AlreadyInitialized,
+        NotInitialized,
+        InvalidSlabLen,
+        InvalidOracleKey,
+        OracleStale,
+        OracleConfTooWide,
+        InvalidVaultAta,
+        InvalidMint,
+        ExpectedSigner,
+        ExpectedWritable,
+        OracleInvalid,
+        EngineInsufficientBalance,
+        EngineUndercollateralized,
+        EngineUnauthorized,
+        EngineInvalidMatchingEngine,
+        EnginePnlNotWarmedUp,
+        EngineOverflow,
+        EngineAccountNotFound,
+        EngineNotAnLPAccount,
+        EnginePositionSizeMismatch,
+        EngineRiskReductionOnlyMode,
+        EngineAccountKindMismatch,
+        InvalidTokenAccount,
+        InvalidTokenProgram,
+        InvalidConfigParam,
+        HyperpTradeNoCpiDisabled,
+        EngineCorruptState,
+        /// Wrapper-level: the requested operation cannot safely advance market
+        /// time. Caller must run KeeperCrank to commit account-touching market
+        /// progress, then retry the original operation.
+        CatchupRequired,
+        /// Deposit rejected: post-deposit `c_tot` would exceed
+        /// `tvl_insurance_cap_mult * insurance_fund.balance`.
+        /// Only triggered when the admin has enabled the cap via UpdateConfig.
+        DepositCapExceeded,
+        /// `WithdrawInsuranceLimited` called within the configured
+        /// `insurance_withdraw_cooldown_slots` window.
+        InsuranceWithdrawCooldown,
+        /// `WithdrawInsuranceLimited` amount exceeds
+        /// `insurance_withdraw_max_bps * insurance_fund.balance / 10_000`
+        /// (with a minimum floor of 10 units to avoid Zeno's-paradox lockout
+        /// at small bps × small insurance).
+        InsuranceWithdrawCapExceeded,
+        /// Engine reported that bounded public progress cannot continue
+        /// without an explicit terminal-recovery path.
+        EngineRecoveryRequired,
+        /// Reduce-only trade would open, grow, or be left with no position
+        /// to reduce.
+        ReduceOnlyViolation,
+        /// TradeCpi fill price is worse than the taker's limit price or
+        /// max-slippage bound.
+        SlippageExceeded,
+        /// Fill-or-kill TradeCpi was only partially filled (or not at all).
+        FillOrKillNotFilled,
+        /// No free account slot: `max_accounts` (or the compiled
+        /// `MAX_ACCOUNTS`) is already in use.
+        SlabFull,
+        /// TradeCpi matcher program or context differs from the pair the LP
+        /// registered at InitLP.
+        MatcherContextMismatch,
+        /// Supplied replay nonce differs from the account's current nonce.
+        NonceMismatch,
+        /// Trade would take a user's position notional above the market's
+        /// guarded-launch limit.
+        PositionLimitExceeded,
+        /// Market has an allowlist and the signer's merkle proof does not
+        /// verify against it.
+        NotAllowlisted,
+        /// Market is in emergency withdraw-only mode.
+        WithdrawOnly,
+        /// Admin key set is configured and the action does not yet have
+        /// enough approvals.
+        AdminApprovalsPending,
+        /// Fill is below the market's minimum trade size or would leave a
+        /// position below its minimum.
+        BelowMinSize,
+        /// Fill grows a position while the circuit breaker's cooling-off
+        /// period is running.
+        CircuitBreakerTripped,
+        /// The user filled less than `min_slots_between_trades` ago.
+        TradeRateLimited,
+        /// The stale-oracle fallback is engaged: fills are halted, or the
+        /// withdrawal leaves less than the haircut.
+        OracleFallbackActive,
+        /// TradeCpi request or fill is outside the capabilities the matcher
+        /// declares in its context header.
+        MatcherCapabilityExceeded,
+        /// InitMarket on a slab whose chunked InitSlab zeroing has not
+        /// reached the end.
+        SlabInitIncomplete,
+        /// InitMarket / InitSlab on a slab holding less than the rent-exempt
+        /// minimum for its size.
+        SlabNotRentExempt,
+        /// InitMarket on a slab with nonzero bytes; a fresh account or one
+        /// prepared by InitSlab is all zeros.
+        SlabNotZeroed,
+        /// An instruction reached a slab whose TradeCpi is still inside the
+        /// matcher CPI, i.e. the matcher called back into this program.
+        CpiReentrancy,
+        /// Retired: no instruction returns it. Only the owning program can
+        /// write the slab, so the matcher CPI cannot change it. Kept so later
+        /// codes keep their numbers.
+        MatcherModifiedSlab,
+        /// The matcher's fill price is further from the oracle than the
+        /// market's initial margin, which could open a position already
+        /// below margin on one side.
+        MatcherFillOutOfBounds,
+        /// The slab was written by an older layout version; MigrateSlab
+        /// must grow it to `SLAB_LEN` first.
+        SlabMigrationRequired,
+        /// Every backstop depositor entry is held by another owner.
+        BackstopFull,
+        /// No backstop withdrawal is pending, or its delay has not elapsed.
+        BackstopWithdrawLocked,
+        /// Every extra Hyperp pusher entry is taken.
+        HyperpPushersFull,
+        /// A deposit permit's expiry is earlier than the cluster clock.
+        PermitExpired,
+        /// CloseSlab while backstop depositors still hold shares or an
+        /// unredeemed payout of the closed vault account.
+        BackstopNotEmpty,
+        /// ApproveAdminAction for a new action while every pending entry
+        /// is live.
+        AdminActionsFull,
+    }
+
+    impl PercolatorError {
+        /// Variant name, for logs. Kept as a literal table so error paths do
+        /// not pull in `core::fmt`.
+        pub const fn name(&self) -> &'static str {
+            match self {
+                PercolatorError::InvalidMagic => "InvalidMagic",
+                PercolatorError::InvalidVersion => "InvalidVersion",
+                PercolatorError::AlreadyInitialized => "AlreadyInitialized",
+                PercolatorError::NotInitialized => "NotInitialized",
+                PercolatorError::InvalidSlabLen => "InvalidSlabLen",
+                PercolatorError::InvalidOracleKey => "InvalidOracleKey",
+                PercolatorError::OracleStale => "OracleStale",
+                PercolatorError::OracleConfTooWide => "OracleConfTooWide",
+                PercolatorError::InvalidVaultAta => "InvalidVaultAta",
+                PercolatorError::InvalidMint => "InvalidMint",
+                PercolatorError::ExpectedSigner => "ExpectedSigner",
+                PercolatorError::ExpectedWritable => "ExpectedWritable",
+                PercolatorError::OracleInvalid => "OracleInvalid",
+                PercolatorError::EngineInsufficientBalance => "EngineInsufficientBalance",
+                PercolatorError::EngineUndercollateralized => "EngineUndercollateralized",
+                PercolatorError::EngineUnauthorized => "EngineUnauthorized",
+                PercolatorError::EngineInvalidMatchingEngine => "EngineInvalidMatchingEngine",
+                PercolatorError::EnginePnlNotWarmedUp => "EnginePnlNotWarmedUp",
+                PercolatorError::EngineOverflow => "EngineOverflow",
+                PercolatorError::EngineAccountNotFound => "EngineAccountNotFound",
+                PercolatorError::EngineNotAnLPAccount => "EngineNotAnLPAccount",
+                PercolatorError::EnginePositionSizeMismatch => "EnginePositionSizeMismatch",
+                PercolatorError::EngineRiskReductionOnlyMode => "EngineRiskReductionOnlyMode",
+                PercolatorError::EngineAccountKindMismatch => "EngineAccountKindMismatch",
+                PercolatorError::InvalidTokenAccount => "InvalidTokenAccount",
+                PercolatorError::InvalidTokenProgram => "InvalidTokenProgram",
+                PercolatorError::InvalidConfigParam => "InvalidConfigParam",
+                PercolatorError::HyperpTradeNoCpiDisabled => "HyperpTradeNoCpiDisabled",
+                PercolatorError::EngineCorruptState => "EngineCorruptState",
+                PercolatorError::CatchupRequired => "CatchupRequired",
+                PercolatorError::DepositCapExceeded => "DepositCapExceeded",
+                PercolatorError::InsuranceWithdrawCooldown => "InsuranceWithdrawCooldown",
+                PercolatorError::InsuranceWithdrawCapExceeded => "InsuranceWithdrawCapExceeded",
+                PercolatorError::EngineRecoveryRequired => "EngineRecoveryRequired",
+                PercolatorError::ReduceOnlyViolation => "ReduceOnlyViolation",
+                PercolatorError::SlippageExceeded => "SlippageExceeded",
+                PercolatorError::FillOrKillNotFilled => "FillOrKillNotFilled",
+                PercolatorError::SlabFull => "SlabFull",
+                PercolatorError::MatcherContextMismatch => "MatcherContextMismatch",
+                PercolatorError::NonceMismatch => "NonceMismatch",
+                PercolatorError::PositionLimitExceeded => "PositionLimitExceeded",
+                PercolatorError::NotAllowlisted => "NotAllowlisted",
+                PercolatorError::WithdrawOnly => "WithdrawOnly",
+                PercolatorError::AdminApprovalsPending => "AdminApprovalsPending",
+                PercolatorError::BelowMinSize => "BelowMinSize",
+                PercolatorError::CircuitBreakerTripped => "CircuitBreakerTripped",
+                PercolatorError::TradeRateLimited => "TradeRateLimited",
+                PercolatorError::OracleFallbackActive => "OracleFallbackActive",
+                PercolatorError::MatcherCapabilityExceeded => "MatcherCapabilityExceeded",
+                PercolatorError::SlabInitIncomplete => "SlabInitIncomplete",
+                PercolatorError::SlabNotRentExempt => "SlabNotRentExempt",
+                PercolatorError::SlabNotZeroed => "SlabNotZeroed",
+                PercolatorError::CpiReentrancy => "CpiReentrancy",
+                PercolatorError::MatcherModifiedSlab => "MatcherModifiedSlab",
+                PercolatorError::MatcherFillOutOfBounds => "MatcherFillOutOfBounds",
+                PercolatorError::SlabMigrationRequired => "SlabMigrationRequired",
+                PercolatorError::BackstopFull => "BackstopFull",
+                PercolatorError::BackstopWithdrawLocked => "BackstopWithdrawLocked",
+                PercolatorError::HyperpPushersFull => "HyperpPushersFull",
+                PercolatorError::PermitExpired => "PermitExpired",
+                PercolatorError::BackstopNotEmpty => "BackstopNotEmpty",
+                PercolatorError::AdminActionsFull => "AdminActionsFull",
+            }
+        }
+    }
+
+    /// Saturating `u128` → `u64` for log context.
+    pub const fn clamp_u64(v: u128) -> u64 {
+        if v > u64::MAX as u128 {
+            u64::MAX
+        } else {
+            v as u64
+        }
+    }
+
+    /// Log `e` with up to four offending values and convert it.
+    ///
+    /// Emits the variant name, then one `sol_log_64` line whose first word
+    /// is the error code and the rest are `ctx` (zero-filled), so a failed
+    /// transaction's explorer log shows what was compared against what.
+    /// Each call site documents the meaning of its values.
+    pub fn fail(e: PercolatorError, ctx: &[u64]) -> ProgramError {
+        let v = |i: usize| ctx.get(i).copied().unwrap_or(0);
+        solana_program::log::sol_log(e.name());
+        let code = e as u32;
+        solana_program::log::sol_log_64(code as u64, v(0), v(1), v(2), v(3));
+        ProgramError::Custom(code)
+    }
+
+    impl From<PercolatorError> for ProgramError {
+        fn from(e: PercolatorError) -> Self {
+            ProgramError::Custom(e as u32)
+        }
+    }
+
+    pub fn map_risk_error(e: RiskError) -> ProgramError {
+        let err = match e {
+            RiskError::InsufficientBalance => PercolatorError::EngineInsufficientBalance,
+            RiskError::Undercollateralized => PercolatorError::EngineUndercollateralized,
+            RiskError::Unauthorized => PercolatorError::EngineUnauthorized,
+            RiskError::PnlNotWarmedUp => PercolatorError::EnginePnlNotWarmedUp,
+            RiskError::Overflow => PercolatorError::EngineOverflow,
+            RiskError::AccountNotFound => PercolatorError::EngineAccountNotFound,
+            RiskError::SideBlocked => PercolatorError::EngineRiskReductionOnlyMode,
+            RiskError::CorruptState => PercolatorError::EngineCorruptState,
+            RiskError::RecoveryRequired => PercolatorError::EngineRecoveryRequired,
+        };
+        ProgramError::Custom(err as u32)
+    }
+}
+
+// 4. mod ix
+pub mod ix {
+    use alloc::{boxed::Box, vec::Vec};
+    use percolator::{RiskParams, U128};
+    use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+    #[derive(Debug)]
+    pub struct OracleLegFeeds {
+        pub leg2_feed_id: [u8; 32],
+        pub leg3_feed_id: [u8; 32],
+    }
+
+    #[derive(Debug)]
+    pub struct InitMarketArgs {
+        pub admin: Pubkey,
+        pub collateral_mint: Pubkey,
+        /// Pyth feed ID for the index price (32 bytes).
+        /// If all zeros, enables Hyperp mode (internal mark/index, no external oracle).
+        pub index_feed_id: [u8; 32],
+        /// Optional external oracle legs 2/3 for synthetic cross rates.
+        pub oracle_leg_feeds: Box<OracleLegFeeds>,
+        /// Active leg count: 1, 2, or 3. Hyperp requires 1.
+        pub oracle_leg_count: u8,
+        /// Orientation bits for legs 2/3. Set bit means divide by that leg.
+        pub oracle_leg_flags: u8,
+        /// Maximum staleness in seconds
+        pub max_staleness_secs: u64,
+        pub conf_filter_bps: u16,
+        /// If non-zero, invert oracle price (raw -> 1e12/raw)
+        pub invert: u8,
+        /// Lamports per Unit for boundary conversion (0 = no scaling)
+        pub unit_scale: u32,
+        /// Initial mark price in e6 format. Required (non-zero) if Hyperp mode.
+        pub initial_mark_price_e6: u64,
+        /// Periodic maintenance fee per slot per account (engine units). 0 = disabled.
+        pub maintenance_fee_per_slot: u128,
+        /// Insurance withdrawal: max bps per withdrawal (0 = no live withdrawals)
+        /// High bit enables deposits-only mode for WithdrawInsuranceLimited;
+        /// low 15 bits store the bps value.
+        pub insurance_withdraw_max_bps: u16,
+        /// Insurance withdrawal: cooldown slots between withdrawals
+        pub insurance_withdraw_cooldown_slots: u64,
+        pub risk_params: Box<RiskParams>,
+        /// Wrapper-charged new-account fee (base units). Charged by the
+        /// wrapper at InitUser/InitLP: `fee_payment` is split into
+        /// `new_account_fee` (routed to insurance) + remainder (credited
+        /// as initial capital). Engine never sees this. 0 = disabled.
+        pub new_account_fee: u128,
+        /// Slots of oracle staleness for permissionless resolution. 0 = disabled.
+        pub permissionless_resolve_stale_slots: u64,
+        /// Expected keeper cadence. Never reaches the engine; the wrapper
+        /// only uses it to time EnterWithdrawOnly. 0 = disabled.
+        pub max_crank_staleness_slots: u64,
+        /// Optional custom funding parameters (override defaults when present)
+        pub funding_horizon_slots: Option<u64>,
+        pub funding_k_bps: Option<u64>,
+        pub funding_max_premium_bps: Option<i64>,
+        pub funding_max_e9_per_slot: Option<i64>,
+        /// Fee-weighted EWMA: min fee for full mark weight. 0 = disabled.
+        pub mark_min_fee: u64,
+        /// Normal wrapper trade fee in bps. Dynamic Hyperp fees add mark
+        /// movement to this base, capped by risk_params.max_trading_fee_bps.
+        pub trade_fee_base_bps: u64,
+        /// Permissionless force-close delay after resolution. 0 = disabled.
+        pub force_close_delay_slots: u64,
+        /// Optional labels for explorers. Absent = all zeros (unlabelled).
+        pub metadata: crate::state::MarketMetadata,
+    }
+
+    #[derive(Debug)]
+    pub enum Instruction {
+        InitMarket(Box<InitMarketArgs>),
+        InitUser {
+            fee_payment: u64,
+            /// Optional tail: `count: u8` then `count` 32-byte merkle
+            /// siblings proving the signer is on the market allowlist.
+            /// Absent = empty, which only passes on open markets.
+            allowlist_proof: Vec<[u8; 32]>,
+        },
+        InitLP {
+            matcher_program: Pubkey,
+            matcher_context: Pubkey,
+            fee_payment: u64,
+        },
+        DepositCollateral {
+            user_idx: u16,
+            amount: u64,
+            /// Same optional tail as InitUser.
+            allowlist_proof: Vec<[u8; 32]>,
+            /// Optional tail after the proof (count 0 when there is none).
+            client_tag: [u8; crate::constants::CLIENT_TAG_LEN],
+        },
+        /// Pays out to any token account of the market's mint; the
+        /// account owner's signature is the authorization.
+        WithdrawCollateral {
+            user_idx: u16,
+            amount: u64,
+            /// Optional tail: must equal the account's replay nonce, which
+            /// is then bumped. Absent = unchecked.
+            nonce: Option<u64>,
+        },
+        KeeperCrank {
+            caller_idx: u16,
+            candidates: alloc::vec::Vec<(u16, Option<percolator::LiquidationPolicy>)>,
+        },
+        TradeNoCpi {
+            lp_idx: u16,
+            user_idx: u16,
+            size: i128,
+            /// Optional engine-space execution price. 0 preserves the
+            /// legacy bilateral path that fills at the wrapper's current
+            /// effective price. Nonzero means both signers agree to the
+            /// supplied price; the engine still enforces health and the
+            /// wrapper clamps mark impact separately.
+            exec_price_e6: u64,
+            /// `TRADE_FLAG_*` bits. Optional tail after `exec_price_e6`.
+            flags: u8,
+            /// Optional tail after `flags`; 0 = none. See `ORDER_ID_RING`.
+            client_order_id: u64,
+            /// Optional tail after `client_order_id`; see `CLIENT_TAG_LEN`.
+            client_tag: [u8; crate::constants::CLIENT_TAG_LEN],
+        },
+        CloseAccount {
+            user_idx: u16,
+            /// Optional replay nonce; same rules as WithdrawCollateral.
+            nonce: Option<u64>,
+        },
+        TopUpInsurance {
+            amount: u64,
+        },
+        TradeCpi {
+            lp_idx: u16,
+            user_idx: u16,
+            size: i128,
+            limit_price_e6: u64, // 0 = no limit (backward compat)
+            /// `TRADE_FLAG_*` bits. Optional trailing byte; absent = 0.
+            flags: u8,
+            /// Optional tail after `flags`; 0 = none. See `ORDER_ID_RING`.
+            client_order_id: u64,
+            /// Optional tail after `client_order_id`; see `CLIENT_TAG_LEN`.
+            client_tag: [u8; crate::constants::CLIENT_TAG_LEN],
+        },
+        /// Close the market slab and recover SOL to the admin-supplied
+        /// destination. Requires: no active accounts, no vault funds,
+        /// no insurance funds.
+        CloseSlab,
+        /// Update configurable funding parameters. Admin only.
+        UpdateConfig {
+            funding_horizon_slots: u64,
+            funding_k_bps: u64,
+            funding_max_premium_bps: i64,
+            funding_max_e9_per_slot: i64,
+            /// Admin-opt-in deposit cap multiplier. 0 disables the check.
+            /// See `MarketConfig.tvl_insurance_cap_mult`.
+            tvl_insurance_cap_mult: u16,
+        },
+        /// Push oracle price (oracle authority only).
+        /// Stores the price for use by crank/trade operations.
+        PushHyperpMark {
+            price_e6: u64,
+            timestamp: i64,
+        },
+        /// Resolve market: force-close all positions at admin oracle price, enter withdraw-only mode.
+        /// Admin only. `mode` selects the §9.8 settlement arm explicitly:
+        ///   0 = Ordinary   (live inputs; rejected when the oracle is stale)
+        ///   1 = Degenerate (settle at engine.last_oracle_price, rate = 0;
+        ///                    wrapper's canonical dead-oracle terminal path).
+        ResolveMarket {
+            mode: u8,
+        },
+        /// Withdraw insurance fund balance (UNBOUNDED). Gated by
+        /// `header.insurance_authority`; requires market resolved +
+        /// all accounts closed. For live, bounded extraction see
+        /// `WithdrawInsuranceLimited` (tag 23). The two paths have
+        /// structurally DISJOINT authority gates — this is what makes
+        /// the bounded path's bps + cooldown bounds un-bypassable.
+        WithdrawInsurance,
+        /// Admin force-close an abandoned account after market resolution.
+        /// Requires RESOLVED flag, zero position, admin signer.
+        AdminForceCloseAccount {
+            user_idx: u16,
+        },
+        /// BOUNDED live insurance withdrawal. Gated by
+        /// `header.insurance_operator` (distinct from `insurance_authority`
+        /// — the split is what makes the bounds meaningful). Per-call
+        /// amount capped at `config.insurance_withdraw_max_bps *
+        /// insurance_fund.balance / 10_000` with a floor of 10 units
+        /// (anti-Zeno). Calls must be at least
+        /// `config.insurance_withdraw_cooldown_slots` apart. Works on
+        /// LIVE markets only; resolved markets use the unbounded tag 20.
+        WithdrawInsuranceLimited {
+            amount: u64,
+        },
+        /// Direct fee-debt repayment (§10.3.1). Owner only.
+        DepositFeeCredits {
+            user_idx: u16,
+            amount: u64,
+        },
+        /// Voluntary PnL conversion with open position (§10.4.1). Owner only.
+        ConvertReleasedPnl {
+            user_idx: u16,
+            amount: u64,
+        },
+        /// Permissionless market resolution after prolonged oracle staleness.
+        /// Anyone can call when the oracle has been stale for at least
+        /// config.permissionless_resolve_stale_slots. Settles at the last
+        /// known good oracle price from engine.last_oracle_price.
+        ResolvePermissionless,
+        /// Permissionless force-close for resolved markets (tag 30).
+        /// Requires RESOLVED + delay. Admin-only. Sends capital to any
+        /// valid SPL token account whose token-owner matches the stored
+        /// owner and whose mint matches `collateral_mint` — the caller
+        /// chooses the destination (typically but not necessarily the
+        /// canonical ATA). The wrapper enforces owner + mint equality
+        /// via `verify_token_account`; it does NOT derive the
+        /// Associated Token Address, so a non-ATA account owned by the
+        /// stored owner is also accepted.
+        ForceCloseResolved {
+            user_idx: u16,
+        },
+        /// Scoped-authority update (tag 32).
+        ///
+        /// kind:
+        ///   0 = AUTHORITY_ADMIN              (header.admin)
+        ///   1 = AUTHORITY_HYPERP_MARK        (config.hyperp_authority)
+        ///   2 = AUTHORITY_INSURANCE          (header.insurance_authority)
+        ///   4 = AUTHORITY_INSURANCE_OPERATOR (header.insurance_operator)
+        ///   5 = AUTHORITY_FEE                (slab tail fee authority)
+        /// (kind = 3 / AUTHORITY_CLOSE was deleted; close authority merged
+        /// into admin.)
+        ///
+        /// Authorization: the CURRENT authority of the specified kind
+        /// must sign. When `new_pubkey != Pubkey::default()` the NEW
+        /// pubkey must ALSO sign, proving the receiver consents to
+        /// take the role and the key isn't a typo. When `new_pubkey
+        /// == Pubkey::default()` (burn), only the current authority
+        /// signs.
+        ///
+        /// Each kind is independent — set them all to the same pubkey
+        /// for a "super admin" or delegate/burn them individually for
+        /// capability isolation. Burning `admin` applies the existing
+        /// admin-burn liveness guards (permissionless resolve + force
+        /// close configured). Burning any other kind has no guards —
+        /// it just makes that capability permanently unavailable,
+        /// which is a legitimate rug-proofing configuration.
+        UpdateAuthority {
+            kind: u8,
+            new_pubkey: Pubkey,
+        },
+        /// Withdraw collected new-account fees (tag 33). Admin only, LIVE
+        /// markets only. The fees sit in `insurance_fund.balance` (so they
+        /// never count toward `c_tot`) and are tracked by a separate
+        /// accumulator in the slab header; `amount` is capped by
+        /// min(accumulator, insurance). Resolved markets drain insurance,
+        /// fees included, through tag 20.
+        WithdrawFees {
+            amount: u64,
+        },
+        /// Read-only margin summary for one account (tag 34), returned as
+        /// a [`HealthSnapshot`] via `sol_set_return_data`. Accounts:
+        /// `[slab]`, not writable. Valued at the engine's last accrued
+        /// price (the settlement price once resolved) without touching
+        /// the account, so CPI callers should gate on `last_market_slot`.
+        QueryHealth {
+            user_idx: u16,
+        },
+        /// Set the guarded-launch limits for user accounts (tag 35). Admin
+        /// only. Both in engine units, 0 = unlimited; LP accounts are
+        /// exempt. Lowering a limit never force-reduces existing accounts.
+        SetAccountLimits {
+            max_capital: u128,
+            max_position_notional: u128,
+        },
+        /// Replace the allowlist merkle root (tag 36). Admin only. All
+        /// zeros opens the market. Existing accounts keep their positions
+        /// but must prove membership again to deposit.
+        SetAllowlistRoot {
+            root: [u8; 32],
+        },
+        /// Enable trading-fee routing (tag 37). Admin only. Of each fill's
+        /// fee, `insurance_bps` stays in insurance, `treasury_bps` is
+        /// earmarked for the protocol treasury and the rest is credited to
+        /// the LP's capital. The two must sum to at most 10_000.
+        SetFeeSplit {
+            insurance_bps: u16,
+            treasury_bps: u16,
+        },
+        /// Claim accrued protocol fees (tag 38). Fee authority only, LIVE
+        /// markets only. Moves `amount` (base units, capped by
+        /// min(protocol-fee accumulator, insurance)) from the vault into
+        /// the treasury PDA's token account, debiting insurance so
+        /// `engine.vault` keeps matching the SPL vault. When a destination
+        /// owned by the fee authority is passed as the ninth account, the
+        /// treasury's whole token balance is then forwarded to it.
+        ClaimProtocolFees {
+            amount: u64,
+        },
+        /// Set the competition epoch length in slots (tag 39). Admin only.
+        /// Always opens a new epoch at the current slot; KeeperCrank rolls
+        /// later ones on the `epoch_slots` grid. 0 keeps the new epoch open
+        /// until the next call.
+        SetEpochLength {
+            epoch_slots: u64,
+        },
+        /// Permissionless switch to emergency withdraw-only mode (tag 40).
+        /// Accounts: `[slab (writable), clock]`. Allowed once no KeeperCrank
+        /// has run for `max_crank_staleness_slots *
+        /// WITHDRAW_ONLY_STALENESS_MULTIPLIER` slots. Afterwards trades,
+        /// deposits and new accounts fail with `WithdrawOnly`, and
+        /// withdrawals are margined at the engine's last accrued price
+        /// without an oracle read. One-way; resolution still works.
+        EnterWithdrawOnly,
+        /// Configure the M-of-N admin key set (tag 41). Admin-gated like
+        /// any other admin instruction, so once a set exists changing it
+        /// needs its approvals. `threshold = 0` with no keys reverts to
+        /// the single `header.admin` key. Clears pending approvals.
+        SetAdminMultisig {
+            threshold: u8,
+            signers: Vec<[u8; 32]>,
+        },
+        /// Approve an admin action (tag 42). Accounts: `[signer, slab
+        /// (writable)]`; the signer must be in the key set. `action_hash`
+        /// is `policy::admin_action_hash(slab, account_keys,
+        /// instruction_data)` of the admin instruction, so it only
+        /// executes with the same accounts, executor included.
+        ApproveAdminAction {
+            action_hash: [u8; 32],
+        },
+        /// Nominate a new admin (tag 43). Admin-gated. Unlike UpdateAuthority
+        /// the nominee does not sign here; it takes over with AcceptAdmin,
+        /// which lets a governance PDA accept from its own proposal
+        /// execution. The zero key cancels a nomination.
+        ProposeAdmin {
+            new_admin: Pubkey,
+        },
+        /// Accept a pending nomination (tag 44). Accounts: `[nominee
+        /// (signer), slab (writable)]`. Replaces `header.admin`, drops any
+        /// admin key set and its pending approvals.
+        AcceptAdmin,
+        /// Donate collateral to the insurance fund (tag 45). Same accounts
+        /// and checks as TopUpInsurance, but the amount is never added to
+        /// the deposits-only WithdrawInsuranceLimited budget, so it can
+        /// only leave through insurance paths that may also take fee
+        /// growth.
+        DonateInsurance {
+            amount: u64,
+        },
+        /// Set dust floors (tag 46). Admin only. User fills below
+        /// `min_trade_abs`, or leaving a nonzero position below
+        /// `min_position_abs`, fail with `BelowMinSize` unless they
+        /// flatten the position. KeeperCrank closes dust positions named
+        /// as candidates against `sweep_lp_idx` at the crank price.
+        SetDustLimits {
+            min_trade_abs: u128,
+            min_position_abs: u128,
+            sweep_lp_idx: u16,
+        },
+        /// Configure the circuit breaker (tag 47). Admin only. When an
+        /// oracle read lands more than `max_move_bps` from the price
+        /// anchored within the last `window_slots`, fills that grow a
+        /// user position fail with `CircuitBreakerTripped` for
+        /// `cooldown_slots`. `max_move_bps = 0` disables it.
+        SetCircuitBreaker {
+            max_move_bps: u64,
+            window_slots: u64,
+            cooldown_slots: u64,
+        },
+        /// Set the per-account trade rate limit (tag 48). Admin only. A
+        /// user fill less than `min_slots_between_trades` after that
+        /// user's previous fill fails with `TradeRateLimited`. LP accounts
+        /// are exempt; 0 disables it.
+        SetTradeRateLimit {
+            min_slots_between_trades: u64,
+        },
+        /// Configure the permissioned liquidation window (tag 49). Admin
+        /// only. For `window_slots` after a KeeperCrank first leaves an
+        /// account below maintenance, only a crank signed by one of
+        /// `liquidators` may liquidate it; other cranks only touch it.
+        /// `window_slots = 0` with no keys turns it off.
+        SetLiquidatorWindow {
+            window_slots: u64,
+            liquidators: Vec<[u8; 32]>,
+        },
+        /// Configure the backstop vault (tag 50). Admin only. Each
+        /// KeeperCrank that liquidates pays the vault `share_bps` of the
+        /// net insurance growth across the liquidation pass (0 = nothing);
+        /// depositors wait `withdraw_delay_slots` between requesting a
+        /// withdrawal and taking it.
+        SetBackstop {
+            share_bps: u16,
+            withdraw_delay_slots: u64,
+        },
+        /// Configure the stale-oracle fallback (tag 51). Admin only. Once
+        /// no fresh oracle read has landed for `grace_slots`, a stale feed
+        /// no longer fails price reads: the market holds its last accepted
+        /// price, trades fail, and withdrawals from open positions keep a
+        /// haircut that widens to `max_haircut_bps` over `ramp_slots`.
+        /// `grace_slots = 0` turns it off.
+        SetStaleFallback {
+            grace_slots: u64,
+            ramp_slots: u64,
+            max_haircut_bps: u16,
+        },
+        /// Replace the market's metadata labels (tag 52). Admin only.
+        /// Same payload as InitMarket's metadata tail.
+        SetMarketMetadata {
+            metadata: crate::state::MarketMetadata,
+        },
+        /// Create the market's pointer PDA (tag 53). Admin only; the admin
+        /// pays its rent unless a 5th account (writable signer) does. Seeds
+        /// come from the slab's own config plus
+        /// `index`, which tells apart markets with the same collateral and
+        /// feed (and every Hyperp market, whose feed id is zero).
+        RegisterMarket {
+            index: u16,
+        },
+        /// Zero the next `max_bytes` of a program-owned slab (tag 54). The
+        /// first call claims the slab for the signer and marks it
+        /// initializing; later calls need the same signer. InitMarket from
+        /// that signer then skips its own full-slab zeroing.
+        InitSlab {
+            max_bytes: u32,
+        },
+        /// Grow a slab written by an older layout version toward
+        /// `SLAB_LEN` (tag 55), by up to `MAX_PERMITTED_DATA_INCREASE`
+        /// bytes per call. Permissionless: the payer tops the slab up to
+        /// rent exemption for each new length. The call that reaches
+        /// `SLAB_LEN` seeds the new regions and writes `SLAB_VERSION`; on a
+        /// current slab it does nothing.
+        MigrateSlab,
+        /// Deposit `amount` into the backstop vault for shares (tag 56).
+        /// The first deposit creates the vault's LP account, owned by the
+        /// backstop PDA; later ones are priced at the vault's equity.
+        BackstopDeposit {
+            amount: u64,
+        },
+        /// Queue `shares` of the signer's backstop shares for withdrawal
+        /// (tag 57). Queued shares keep their exposure to the vault until
+        /// paid; queueing more restarts the delay.
+        BackstopRequestWithdraw {
+            shares: u128,
+        },
+        /// Pay out the signer's queued backstop shares once the withdrawal
+        /// delay has elapsed (tag 58). After resolution, pays out all of
+        /// the signer's shares with no delay.
+        BackstopWithdraw,
+        /// Let `pusher` push the Hyperp mark alongside the mark authority
+        /// (tag 59). Accounts: `[mark authority (signer), slab]`.
+        AddHyperpPusher {
+            pusher: Pubkey,
+        },
+        /// Revoke a key added by AddHyperpPusher (tag 60). Same accounts.
+        RemoveHyperpPusher {
+            pusher: Pubkey,
+        },
+        /// Deposit on the account owner's off-chain signature (tag 61).
+        /// The preceding instruction must be an ed25519 verification of
+        /// `permit_deposit_payload` by the owner; `nonce` must equal the
+        /// account's replay nonce. The permit PDA moves the tokens as the
+        /// owner's SPL delegate, so any relayer can submit and pay fees.
+        DepositWithPermit {
+            user_idx: u16,
+            amount: u64,
+            nonce: u64,
+            /// Unix seconds; the permit fails after it.
+            expiry: i64,
+            allowlist_proof: Vec<[u8; 32]>,
+        },
+    }
+
+    /// Tag namespace version 1. The first instruction byte is a tag:
+    ///
+    /// ```text
+    ///   0..=127    core instructions, assigned in order. A retired tag is
+    ///              never reassigned, so an old client fails instead of
+    ///              reaching a different instruction.
+    ///   128..=223  reserved for the next core namespace version.
+    ///   224..=254  reserved for compatibility front-ends that map a foreign
+    ///              encoding onto core tags; never dispatched as-is.
+    ///   255        reserved escape for a future multi-byte tag.
+    /// ```
+    ///
+    /// Every tag outside the assigned set fails to decode with
+    /// `InvalidInstructionData`, as does any payload that is short or has
+    /// bytes left over once the variant's fields and optional tails are read.
+    pub const TAG_NAMESPACE_VERSION: u8 = 1;
+    pub const CORE_TAG_MAX: u8 = 127;
+    /// Highest tag in use; the next instruction takes `LAST_ASSIGNED_TAG + 1`.
+    pub const LAST_ASSIGNED_TAG: u8 = 61;
+    /// Tags below `LAST_ASSIGNED_TAG` that were deleted or retired.
+    pub const RETIRED_TAGS: [u8; 11] = [7, 11, 12, 15, 16, 18, 22, 24, 25, 26, 31];
+    pub const RESERVED_CORE_TAGS: core::ops::RangeInclusive<u8> = 128..=223;
+    pub const RESERVED_COMPAT_TAGS: core::ops::RangeInclusive<u8> = 224..=254;
+    pub const RESERVED_ESCAPE_TAG: u8 = 255;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TagClass {
+        Assigned,
+        Retired,
+        /// Core range, not yet assigned.
+        Unassigned,
+        Reserved,
+    }
+
+    pub fn tag_class(tag: u8) -> TagClass {
+        if tag > CORE_TAG_MAX {
+            TagClass::Reserved
+        } else if RETIRED_TAGS.contains(&tag) {
+            TagClass::Retired
+        } else if tag <= LAST_ASSIGNED_TAG {
+            TagClass::Assigned
+        } else {
+            TagClass::Unassigned
+        }
+    }
+
+    /// Anchor-style front-end, behind the `anchor-compat` feature. Anchor
+    /// clients prefix each call with an 8-byte discriminator,
+    /// `sha256("global:<snake_case_name>")[..8]`, instead of our one-byte
+    /// tag. The bytes after it are the core payload: for fixed-width
+    /// fields that is exactly what Borsh writes, so an IDL can declare the
+    /// arguments as-is. Variable-length tails (allowlist proofs, crank
+    /// candidates, multisig and liquidator lists) keep the core `u8`
+    /// count framing and must be passed as raw bytes.
+    #[cfg(feature = "anchor-compat")]
+    pub mod anchor_compat {
+        use super::Instruction;
+        use alloc::vec::Vec;
+        use solana_program::program_error::ProgramError;
+
+        pub const DISCRIMINATOR_LEN: usize = 8;
+
+        /// Discriminator (little-endian `u64` of its 8 bytes), core tag,
+        /// Anchor instruction name.
+        pub const DISCRIMINATORS: [(u64, u8, &str); 51] = [
+            (0xec7f1959740ffd21, 0, "init_market"),
+            (0x669e4eed9f44330e, 1, "init_user"),
+            (0x8e15e1324c82865a, 2, "init_lp"),
+            (0x78a2f792748e839c, 3, "deposit_collateral"),
+            (0x968ad68b6aa88773, 4, "withdraw_collateral"),
+            (0x1f9d0ba1868236a1, 5, "keeper_crank"),
+            (0xf169d07a5001f410, 6, "trade_no_cpi"),
+            (0x1848226e0e95ff7d, 8, "close_account"),
+            (0x6e3147a3c76dbea5, 9, "top_up_insurance"),
+            (0xaa02fbe907f07720, 10, "trade_cpi"),
+            (0x2e0a6aa4e9ac318a, 13, "close_slab"),
+            (0x63db530abffc9e1d, 14, "update_config"),
+            (0x5c8d6980454832e7, 17, "push_hyperp_mark"),
+            (0xef174a2ead50179b, 19, "resolve_market"),
+            (0xbd5a59eb769185c9, 20, "withdraw_insurance"),
+            (0x31d6d38b55573e6c, 21, "admin_force_close_account"),
+            (0x70693b61a4cd5943, 23, "withdraw_insurance_limited"),
+            (0x2ce5acbc1891747a, 27, "deposit_fee_credits"),
+            (0xa25d4f857b22b571, 28, "convert_released_pnl"),
+            (0x3e051715c55eaf45, 29, "resolve_permissionless"),
+            (0x40b3fd04bba16e1b, 30, "force_close_resolved"),
+            (0x58f34b951c402e20, 32, "update_authority"),
+            (0x59aed7906dabd4c6, 33, "withdraw_fees"),
+            (0x69123984f7734225, 34, "query_health"),
+            (0xa2b61c0c9de2f04b, 35, "set_account_limits"),
+            (0x175e030fadfcee91, 36, "set_allowlist_root"),
+            (0xcb5d0b6d82b4baf8, 37, "set_fee_split"),
+            (0x1785366d70db8e22, 38, "claim_protocol_fees"),
+            (0xeefa2f8b78e022db, 39, "set_epoch_length"),
+            (0x1596a114dcadd371, 40, "enter_withdraw_only"),
+            (0x992dfdfca4fb57c7, 41, "set_admin_multisig"),
+            (0x2114063caf6901fd, 42, "approve_admin_action"),
+            (0xea752757d4c7d679, 43, "propose_admin"),
+            (0xaa0db5745a2d2a70, 44, "accept_admin"),
+            (0x5ad0ba7eae840b18, 45, "donate_insurance"),
+            (0x55e3269624e04cd7, 46, "set_dust_limits"),
+            (0xf77b5e981f2ecf87, 47, "set_circuit_breaker"),
+            (0xd5b1200fe9221cea, 48, "set_trade_rate_limit"),
+            (0x83915c608c7b24dc, 49, "set_liquidator_window"),
+            (0xbb4cf74ed37cc664, 50, "set_backstop"),
+            (0xe3245630247f3dd4, 51, "set_stale_fallback"),
+            (0xc30bba1dbb649224, 52, "set_market_metadata"),
+            (0x9224f391b9b7a318, 53, "register_market"),
+            (0x11a0bf6cb9a43a6a, 54, "init_slab"),
+            (0xacafce35fa8426bc, 55, "migrate_slab"),
+            (0x5a53c475e78382ea, 56, "backstop_deposit"),
+            (0x6780cbc85be0bace, 57, "backstop_request_withdraw"),
+            (0xfd24d8a048834846, 58, "backstop_withdraw"),
+            (0xf90e867bfe6f04e0, 59, "add_hyperp_pusher"),
+            (0x117ca6c5e0878688, 60, "remove_hyperp_pusher"),
+            (0x05b76b9e5d3a2f6b, 61, "deposit_with_permit"),
+        ];
+
+        pub fn tag_for(discriminator: [u8; DISCRIMINATOR_LEN]) -> Option<u8> {
+            let d = u64::from_le_bytes(discriminator);
+            DISCRIMINATORS
+                .iter()
+                .find(|&&(known, _, _)| known == d)
+                .map(|&(_, tag, _)| tag)
+        }
+
+        /// Decode a discriminator-prefixed call, or fall back to the core
+        /// encoding when the first 8 bytes are not a known discriminator.
+        pub fn decode(input: &[u8]) -> Result<Instruction, ProgramError> {
+            let tag = input
+                .get(..DISCRIMINATOR_LEN)
+                .and_then(|d| tag_for(d.try_into().ok()?));
+            match tag {
+                Some(tag) => {
+                    let payload = &input[DISCRIMINATOR_LEN..];
+                    let mut tagged = Vec::with_capacity(1 + payload.len());
+                    tagged.push(tag);
+                    tagged.extend_from_slice(payload);
+                    Instruction::decode(&tagged)
+                }
+                None => Instruction::decode(input),
+            }
+        }
+    }
+
+    impl Instruction {
+        pub fn decode(input: &[u8]) -> Result<Self, ProgramError> {
+            let (&tag, mut rest) = input
+                .split_first()
+                .ok_or(ProgramError::InvalidInstructionData)?;
+            if tag_class(tag) != TagClass::Assigned {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+
+            let result = match tag {
+                0 => {
+                    // InitMarket
+                    let admin = read_pubkey(&mut rest)?;
+                    let collateral_mint = read_pubkey(&mut rest)?;
+                    let index_feed_id = read_bytes32(&mut rest)?;
+                    let max_staleness_secs = read_u64(&mut rest)?;
+                    let conf_filter_bps = read_u16(&mut rest)?;
+                    let invert = read_u8(&mut rest)?;
+                    let unit_scale = read_u32(&mut rest)?;
+                    let initial_mark_price_e6 = read_u64(&mut rest)?;
+                    let maintenance_fee_per_slot = read_u128(&mut rest)?; // periodic fee per slot per account
+                                                                          // Insurance withdrawal limits (immutable after init)
+                    let (risk_params, new_account_fee, max_crank_staleness_slots) =
+                        read_risk_params(&mut rest)?;
+                    // Extended fields: either ALL present (66 bytes) or NONE.
+                    // No partial tails — prevents silent misparsing of truncated payloads.
+                    // Total: insurance(2+8) + permissionless(8) + funding(8+8+8+8) +
+                    //        mark_min_fee(8) + force_close_delay(8) = 66 bytes
+                    const EXTENDED_TAIL_LEN: usize = 2 + 8 * 8;
+                    let (
+                        insurance_withdraw_max_bps,
+                        insurance_withdraw_cooldown_slots,
+                        permissionless_resolve_stale_slots,
+                        funding_horizon_slots,
+                        funding_k_bps,
+                        funding_max_premium_bps,
+                        funding_max_e9_per_slot,
+                        mark_min_fee,
+                        force_close_delay_slots,
+                    ) = if rest.is_empty() {
+                        // Minimal payload: all extended fields use defaults.
+                        // permissionless_resolve_stale_slots seeds to
+                        // DEFAULT_PERMISSIONLESS_RESOLVE_STALE_SLOTS. With
+                        // the production default of 0, non-Hyperp markets
+                        // must use the extended tail to opt into a
+                        // permissionless exit.
+                        // force_close_delay_slots seeds to 1 slot (minimum
+                        // liveness) to satisfy the init-time validation that
+                        // permissionless_resolve > 0 ⇒ force_close > 0.
+                        (
+                            0u16,
+                            0u64,
+                            crate::constants::DEFAULT_PERMISSIONLESS_RESOLVE_STALE_SLOTS,
+                            None,
+                            None,
+                            None,
+                            None,
+                            0u64,
+                            1u64,
+                        )
+                    } else if rest.len() >= EXTENDED_TAIL_LEN {
+                        // Full extended payload
+                        let iwm = read_u16(&mut rest)?;
+                        let iwc = read_u64(&mut rest)?;
+                        let prs = read_u64(&mut rest)?;
                         let fh = read_u64(&mut rest)?;
                         let fk = read_u64(&mut rest)?;
                         let fmp = read_i64(&mut rest)?;
@@ -2222,11 +4565,30 @@ pub mod ix {
                     //   dynamic-fee tail:
                     //   trade_fee_base_bps:u64
                     //
+                    //   metadata tail (always last):
+                    //   symbol:[u8;16], oracle_description:[u8;32],
+                    //   uri_hash:[u8;32], base_decimals:u8.
+                    //
                     // Absent oracle-leg tail means legacy single-feed oracle.
                     // Absent dynamic-fee tail means legacy/static behavior:
-                    // base fee == max_trading_fee_bps.
+                    // base fee == max_trading_fee_bps. Every combination has
+                    // a distinct length, so the tails need no framing.
                     const ORACLE_LEG_TAIL_LEN: usize = 2 + 32 + 32;
                     const DYNAMIC_FEE_TAIL_LEN: usize = 8;
+                    const METADATA_TAIL_LEN: usize = crate::constants::MARKET_METADATA_WIRE_LEN;
+                    let metadata = match rest.len().checked_sub(METADATA_TAIL_LEN) {
+                        Some(n)
+                            if n == 0
+                                || n == DYNAMIC_FEE_TAIL_LEN
+                                || n == ORACLE_LEG_TAIL_LEN
+                                || n == ORACLE_LEG_TAIL_LEN + DYNAMIC_FEE_TAIL_LEN =>
+                        {
+                            let (head, mut tail) = rest.split_at(n);
+                            rest = head;
+                            read_market_metadata(&mut tail)?
+                        }
+                        _ => crate::state::MarketMetadata::default(),
+                    };
                     let (
                         oracle_leg_count,
                         oracle_leg_flags,
@@ -2273,6 +4635,7 @@ pub mod ix {
                         risk_params: Box::new(risk_params),
                         new_account_fee,
                         permissionless_resolve_stale_slots,
+                        max_crank_staleness_slots,
                         funding_horizon_slots,
                         funding_k_bps,
                         funding_max_premium_bps,
@@ -2280,12 +4643,17 @@ pub mod ix {
                         mark_min_fee,
                         trade_fee_base_bps,
                         force_close_delay_slots,
+                        metadata,
                     })))
                 }
                 1 => {
                     // InitUser
                     let fee_payment = read_u64(&mut rest)?;
-                    Ok(Instruction::InitUser { fee_payment })
+                    let allowlist_proof = read_allowlist_proof(&mut rest)?;
+                    Ok(Instruction::InitUser {
+                        fee_payment,
+                        allowlist_proof,
+                    })
                 }
                 2 => {
                     // InitLP
@@ -2302,13 +4670,25 @@ pub mod ix {
                     // Deposit
                     let user_idx = read_u16(&mut rest)?;
                     let amount = read_u64(&mut rest)?;
-                    Ok(Instruction::DepositCollateral { user_idx, amount })
+                    let allowlist_proof = read_allowlist_proof(&mut rest)?;
+                    let client_tag = read_client_tag(&mut rest)?;
+                    Ok(Instruction::DepositCollateral {
+                        user_idx,
+                        amount,
+                        allowlist_proof,
+                        client_tag,
+                    })
                 }
                 4 => {
                     // Withdraw
                     let user_idx = read_u16(&mut rest)?;
                     let amount = read_u64(&mut rest)?;
-                    Ok(Instruction::WithdrawCollateral { user_idx, amount })
+                    let nonce = read_optional_u64(&mut rest)?;
+                    Ok(Instruction::WithdrawCollateral {
+                        user_idx,
+                        amount,
+                        nonce,
+                    })
                 }
                 5 => {
                     // KeeperCrank — two-phase: candidates computed off-chain
@@ -2363,20 +4743,32 @@ pub mod ix {
                     let lp_idx = read_u16(&mut rest)?;
                     let user_idx = read_u16(&mut rest)?;
                     let size = read_i128(&mut rest)?;
+                    // Optional tails: exec_price_e6:u64, then flags:u8.
                     let exec_price_e6 = if rest.is_empty() {
                         0
                     } else {
-                        let p = read_u64(&mut rest)?;
-                        if !rest.is_empty() {
-                            return Err(ProgramError::InvalidInstructionData);
-                        }
-                        p
+                        read_u64(&mut rest)?
                     };
+                    let flags = read_trade_flags(&mut rest)?;
+                    // Slippage-bps and fill-or-kill only apply to matcher
+                    // fills; a bilateral NoCpi trade always fills in full.
+                    if flags
+                        & (crate::constants::TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS
+                            | crate::constants::TRADE_FLAG_FILL_OR_KILL)
+                        != 0
+                    {
+                        return Err(ProgramError::InvalidInstructionData);
+                    }
+                    let client_order_id = read_client_order_id(&mut rest)?;
+                    let client_tag = read_client_tag(&mut rest)?;
                     Ok(Instruction::TradeNoCpi {
                         lp_idx,
                         user_idx,
                         size,
                         exec_price_e6,
+                        flags,
+                        client_order_id,
+                        client_tag,
                     })
                 }
                 // Tag 7 (LiquidateAtOracle) retired. Liquidation is routed
@@ -2387,7 +4779,8 @@ pub mod ix {
                 8 => {
                     // CloseAccount
                     let user_idx = read_u16(&mut rest)?;
-                    Ok(Instruction::CloseAccount { user_idx })
+                    let nonce = read_optional_u64(&mut rest)?;
+                    Ok(Instruction::CloseAccount { user_idx, nonce })
                 }
                 9 => {
                     // TopUpInsurance
@@ -2400,11 +4793,22 @@ pub mod ix {
                     let user_idx = read_u16(&mut rest)?;
                     let size = read_i128(&mut rest)?;
                     let limit_price_e6 = read_u64(&mut rest)?;
+                    let flags = read_trade_flags(&mut rest)?;
+                    if flags & crate::constants::TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS != 0
+                        && limit_price_e6 > 10_000
+                    {
+                        return Err(ProgramError::InvalidInstructionData);
+                    }
+                    let client_order_id = read_client_order_id(&mut rest)?;
+                    let client_tag = read_client_tag(&mut rest)?;
                     Ok(Instruction::TradeCpi {
                         lp_idx,
                         user_idx,
                         size,
                         limit_price_e6,
+                        flags,
+                        client_order_id,
+                        client_tag,
                     })
                 }
                 // Tag 12 (UpdateAdmin) deleted — use UpdateAuthority
@@ -2501,2976 +4905,6639 @@ pub mod ix {
                     let new_pubkey = read_pubkey(&mut rest)?;
                     Ok(Instruction::UpdateAuthority { kind, new_pubkey })
                 }
+                33 => {
+                    let amount = read_u64(&mut rest)?;
+                    Ok(Instruction::WithdrawFees { amount })
+                }
+                34 => {
+                    let user_idx = read_u16(&mut rest)?;
+                    Ok(Instruction::QueryHealth { user_idx })
+                }
+                35 => {
+                    let max_capital = read_u128(&mut rest)?;
+                    let max_position_notional = read_u128(&mut rest)?;
+                    Ok(Instruction::SetAccountLimits {
+                        max_capital,
+                        max_position_notional,
+                    })
+                }
+                36 => {
+                    let root = read_bytes32(&mut rest)?;
+                    Ok(Instruction::SetAllowlistRoot { root })
+                }
+                37 => {
+                    let insurance_bps = read_u16(&mut rest)?;
+                    let treasury_bps = read_u16(&mut rest)?;
+                    Ok(Instruction::SetFeeSplit {
+                        insurance_bps,
+                        treasury_bps,
+                    })
+                }
+                38 => {
+                    let amount = read_u64(&mut rest)?;
+                    Ok(Instruction::ClaimProtocolFees { amount })
+                }
+                39 => {
+                    let epoch_slots = read_u64(&mut rest)?;
+                    Ok(Instruction::SetEpochLength { epoch_slots })
+                }
+                40 => Ok(Instruction::EnterWithdrawOnly),
+                41 => {
+                    let threshold = read_u8(&mut rest)?;
+                    let n = read_u8(&mut rest)? as usize;
+                    if n > crate::constants::MAX_ADMIN_SIGNERS {
+                        return Err(ProgramError::InvalidInstructionData);
+                    }
+                    let mut signers = Vec::with_capacity(n);
+                    for _ in 0..n {
+                        signers.push(read_bytes32(&mut rest)?);
+                    }
+                    Ok(Instruction::SetAdminMultisig { threshold, signers })
+                }
+                42 => {
+                    let action_hash = read_bytes32(&mut rest)?;
+                    Ok(Instruction::ApproveAdminAction { action_hash })
+                }
+                43 => {
+                    let new_admin = read_pubkey(&mut rest)?;
+                    Ok(Instruction::ProposeAdmin { new_admin })
+                }
+                44 => Ok(Instruction::AcceptAdmin),
+                45 => {
+                    let amount = read_u64(&mut rest)?;
+                    Ok(Instruction::DonateInsurance { amount })
+                }
+                46 => {
+                    let min_trade_abs = read_u128(&mut rest)?;
+                    let min_position_abs = read_u128(&mut rest)?;
+                    let sweep_lp_idx = read_u16(&mut rest)?;
+                    Ok(Instruction::SetDustLimits {
+                        min_trade_abs,
+                        min_position_abs,
+                        sweep_lp_idx,
+                    })
+                }
+                47 => {
+                    let max_move_bps = read_u64(&mut rest)?;
+                    let window_slots = read_u64(&mut rest)?;
+                    let cooldown_slots = read_u64(&mut rest)?;
+                    Ok(Instruction::SetCircuitBreaker {
+                        max_move_bps,
+                        window_slots,
+                        cooldown_slots,
+                    })
+                }
+                48 => {
+                    let min_slots_between_trades = read_u64(&mut rest)?;
+                    Ok(Instruction::SetTradeRateLimit {
+                        min_slots_between_trades,
+                    })
+                }
+                49 => {
+                    let window_slots = read_u64(&mut rest)?;
+                    let n = read_u8(&mut rest)? as usize;
+                    if n > crate::constants::MAX_LIQUIDATORS {
+                        return Err(ProgramError::InvalidInstructionData);
+                    }
+                    let mut liquidators = Vec::with_capacity(n);
+                    for _ in 0..n {
+                        liquidators.push(read_bytes32(&mut rest)?);
+                    }
+                    Ok(Instruction::SetLiquidatorWindow {
+                        window_slots,
+                        liquidators,
+                    })
+                }
+                50 => {
+                    let share_bps = read_u16(&mut rest)?;
+                    let withdraw_delay_slots = read_u64(&mut rest)?;
+                    Ok(Instruction::SetBackstop {
+                        share_bps,
+                        withdraw_delay_slots,
+                    })
+                }
+                51 => {
+                    let grace_slots = read_u64(&mut rest)?;
+                    let ramp_slots = read_u64(&mut rest)?;
+                    let max_haircut_bps = read_u16(&mut rest)?;
+                    Ok(Instruction::SetStaleFallback {
+                        grace_slots,
+                        ramp_slots,
+                        max_haircut_bps,
+                    })
+                }
+                52 => {
+                    let metadata = read_market_metadata(&mut rest)?;
+                    Ok(Instruction::SetMarketMetadata { metadata })
+                }
+                53 => {
+                    let index = read_u16(&mut rest)?;
+                    Ok(Instruction::RegisterMarket { index })
+                }
+                54 => {
+                    let max_bytes = read_u32(&mut rest)?;
+                    Ok(Instruction::InitSlab { max_bytes })
+                }
+                55 => Ok(Instruction::MigrateSlab),
+                56 => {
+                    let amount = read_u64(&mut rest)?;
+                    Ok(Instruction::BackstopDeposit { amount })
+                }
+                57 => {
+                    let shares = read_u128(&mut rest)?;
+                    Ok(Instruction::BackstopRequestWithdraw { shares })
+                }
+                58 => Ok(Instruction::BackstopWithdraw),
+                59 => {
+                    let pusher = read_pubkey(&mut rest)?;
+                    Ok(Instruction::AddHyperpPusher { pusher })
+                }
+                60 => {
+                    let pusher = read_pubkey(&mut rest)?;
+                    Ok(Instruction::RemoveHyperpPusher { pusher })
+                }
+                61 => {
+                    let user_idx = read_u16(&mut rest)?;
+                    let amount = read_u64(&mut rest)?;
+                    let nonce = read_u64(&mut rest)?;
+                    let expiry = read_i64(&mut rest)?;
+                    let allowlist_proof = read_allowlist_proof(&mut rest)?;
+                    Ok(Instruction::DepositWithPermit {
+                        user_idx,
+                        amount,
+                        nonce,
+                        expiry,
+                        allowlist_proof,
+                    })
+                }
                 _ => Err(ProgramError::InvalidInstructionData),
             };
-            // Trailing-byte guard: every tag above fully consumes its expected
-            // payload. Anything left over is either a malformed client payload
-            // or a future-version wire format the current program cannot safely
-            // interpret. Reject rather than silently ignore — accepting stray
-            // bytes is an ABI footgun that turns into a semantic drift bug as
-            // soon as any instruction grows an optional tail field.
-            // (Tag 0 / InitMarket has its own extended-tail check before
-            //  returning; this final check is a belt-and-braces second line.)
-            if result.is_ok() && !rest.is_empty() {
-                return Err(ProgramError::InvalidInstructionData);
+            // Trailing-byte guard: every tag above fully consumes its expected
+            // payload. Anything left over is either a malformed client payload
+            // or a future-version wire format the current program cannot safely
+            // interpret. Reject rather than silently ignore — accepting stray
+            // bytes is an ABI footgun that turns into a semantic drift bug as
+            // soon as any instruction grows an optional tail field.
+            // (Tag 0 / InitMarket has its own extended-tail check before
+            //  returning; this final check is a belt-and-braces second line.)
+            if result.is_ok() && !rest.is_empty() {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            result
+        }
+    }
+
+    /// Optional trailing trade-flags byte. Absent = 0; unknown bits are
+    /// rejected so a future flag is never silently ignored.
+    fn read_trade_flags(input: &mut &[u8]) -> Result<u8, ProgramError> {
+        if input.is_empty() {
+            return Ok(0);
+        }
+        let flags = read_u8(input)?;
+        if flags & !crate::constants::TRADE_FLAGS_MASK != 0 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Ok(flags)
+    }
+
+    fn read_allowlist_proof(input: &mut &[u8]) -> Result<Vec<[u8; 32]>, ProgramError> {
+        if input.is_empty() {
+            return Ok(Vec::new());
+        }
+        let n = read_u8(input)? as usize;
+        if n > crate::constants::MAX_ALLOWLIST_PROOF_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        (0..n).map(|_| read_bytes32(input)).collect()
+    }
+
+    fn read_optional_u64(input: &mut &[u8]) -> Result<Option<u64>, ProgramError> {
+        if input.is_empty() {
+            return Ok(None);
+        }
+        read_u64(input).map(Some)
+    }
+
+    fn read_client_order_id(input: &mut &[u8]) -> Result<u64, ProgramError> {
+        if input.is_empty() {
+            return Ok(0);
+        }
+        read_u64(input)
+    }
+
+    fn read_client_tag(
+        input: &mut &[u8],
+    ) -> Result<[u8; crate::constants::CLIENT_TAG_LEN], ProgramError> {
+        const N: usize = crate::constants::CLIENT_TAG_LEN;
+        if input.is_empty() {
+            return Ok([0; N]);
+        }
+        if input.len() < N {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(N);
+        *input = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn read_u8(input: &mut &[u8]) -> Result<u8, ProgramError> {
+        let (&val, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        *input = rest;
+        Ok(val)
+    }
+
+    fn read_u16(input: &mut &[u8]) -> Result<u16, ProgramError> {
+        if input.len() < 2 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(2);
+        *input = rest;
+        Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_u32(input: &mut &[u8]) -> Result<u32, ProgramError> {
+        if input.len() < 4 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(4);
+        *input = rest;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_u64(input: &mut &[u8]) -> Result<u64, ProgramError> {
+        if input.len() < 8 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(8);
+        *input = rest;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_i64(input: &mut &[u8]) -> Result<i64, ProgramError> {
+        if input.len() < 8 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(8);
+        *input = rest;
+        Ok(i64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_i128(input: &mut &[u8]) -> Result<i128, ProgramError> {
+        if input.len() < 16 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(16);
+        *input = rest;
+        Ok(i128::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_u128(input: &mut &[u8]) -> Result<u128, ProgramError> {
+        if input.len() < 16 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(16);
+        *input = rest;
+        Ok(u128::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_pubkey(input: &mut &[u8]) -> Result<Pubkey, ProgramError> {
+        if input.len() < 32 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(32);
+        *input = rest;
+        Ok(Pubkey::new_from_array(bytes.try_into().unwrap()))
+    }
+
+    fn read_market_metadata(
+        input: &mut &[u8],
+    ) -> Result<crate::state::MarketMetadata, ProgramError> {
+        if input.len() < crate::constants::MARKET_METADATA_WIRE_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(crate::constants::MARKET_METADATA_WIRE_LEN);
+        *input = rest;
+        Ok(crate::state::MarketMetadata::from_wire(bytes))
+    }
+
+    fn read_bytes32(input: &mut &[u8]) -> Result<[u8; 32], ProgramError> {
+        if input.len() < 32 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (bytes, rest) = input.split_at(32);
+        *input = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn read_risk_params(input: &mut &[u8]) -> Result<(RiskParams, u128, u64), ProgramError> {
+        let h_min = read_u64(input)?;
+        let maintenance_margin_bps = read_u64(input)?;
+        let initial_margin_bps = read_u64(input)?;
+        let max_trading_fee_bps = read_u64(input)?;
+        let max_accounts = read_u64(input)?;
+        // Wrapper-charged new-account fee (base units). At InitUser/InitLP
+        // the wrapper splits `fee_payment` into two parts: `new_account_fee`
+        // is routed to the insurance fund; the remainder is credited as
+        // account capital. Engine never sees the fee. Zero disables.
+        let new_account_fee = read_u128(input)?;
+        let h_max = read_u64(input)?;
+        // Wrapper-only: times EnterWithdrawOnly, never passed to the engine.
+        let max_crank_staleness_slots = read_u64(input)?;
+        let liquidation_fee_bps = read_u64(input)?;
+        let liquidation_fee_cap = U128::new(read_u128(input)?);
+        let resolve_price_deviation_bps = read_u64(input)?; // was _liquidation_buffer_bps
+        let min_liquidation_abs = U128::new(read_u128(input)?);
+        if input.len() < 40 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let min_nonzero_mm_req = read_u128(input)?;
+        let min_nonzero_im_req = read_u128(input)?;
+        // v12.19: per-slot price-move cap (standard bps, 100 = 1%).
+        // Init-immutable per spec §1.4 solvency invariant.
+        let max_price_move_bps_per_slot = read_u64(input)?;
+
+        // §1.4 requires `cfg_max_price_move_bps_per_slot > 0`. Reject before
+        // any engine call so the error surfaces as `InvalidConfigParam`
+        // rather than an engine-side panic.
+        if h_max == 0
+            || h_max < h_min
+            || h_max > crate::constants::MAX_PROFIT_MATURITY_SLOTS
+            || max_price_move_bps_per_slot == 0
+        {
+            return Err(crate::error::PercolatorError::InvalidConfigParam.into());
+        }
+
+        let params = RiskParams {
+            maintenance_margin_bps,
+            initial_margin_bps,
+            max_trading_fee_bps,
+            max_accounts,
+            liquidation_fee_bps,
+            liquidation_fee_cap,
+            min_liquidation_abs,
+            min_nonzero_mm_req,
+            min_nonzero_im_req,
+            h_min,
+            h_max,
+            resolve_price_deviation_bps,
+            // Envelope is fixed by wrapper deployment, not market-specified.
+            // See `crate::constants::{MAX_ACCRUAL_DT_SLOTS,
+            // MAX_ABS_FUNDING_E9_PER_SLOT}` for the invariant proof.
+            max_accrual_dt_slots: crate::constants::MAX_ACCRUAL_DT_SLOTS,
+            max_abs_funding_e9_per_slot: crate::constants::MAX_ABS_FUNDING_E9_PER_SLOT,
+            // Active-positions cap per side (§1.4). Mirrors max_accounts —
+            // no tighter per-side limit is enforced by the wrapper today.
+            max_active_positions_per_side: max_accounts,
+            // Cumulative-funding lifetime (§1.4 v12.18.x). Envelope matches
+            // per-call `max_accrual_dt_slots` at minimum; wrapper uses the
+            // same value because wrapper-deployed markets don't configure
+            // a separate cumulative horizon. The engine asserts this at
+            // init_engine_state and at every accrue.
+            min_funding_lifetime_slots: crate::constants::MIN_FUNDING_LIFETIME_SLOTS,
+            max_price_move_bps_per_slot,
+        };
+        Ok((params, new_account_fee, max_crank_staleness_slots))
+    }
+
+    /// Return data set by TradeNoCpi and TradeCpi via `sol_set_return_data`,
+    /// so CPI callers and `simulateTransaction` clients can read the fill
+    /// without parsing logs. Fixed 56-byte little-endian layout:
+    ///
+    /// ```text
+    ///   0..16  exec_size       i128  user side; 0 = matcher zero-fill
+    ///  16..24  exec_price_e6   u64   engine space
+    ///  24..32  oracle_price_e6 u64   price the trade was margined at
+    ///  32..40  fee_bps         u64
+    ///  40..56  fee_paid        u128  insurance delta across the fill
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct TradeResult {
+        pub exec_size: i128,
+        pub exec_price_e6: u64,
+        pub oracle_price_e6: u64,
+        pub fee_bps: u64,
+        pub fee_paid: u128,
+    }
+
+    pub const TRADE_RESULT_LEN: usize = 56;
+
+    impl TradeResult {
+        pub fn encode(&self) -> [u8; TRADE_RESULT_LEN] {
+            let mut out = [0u8; TRADE_RESULT_LEN];
+            out[0..16].copy_from_slice(&self.exec_size.to_le_bytes());
+            out[16..24].copy_from_slice(&self.exec_price_e6.to_le_bytes());
+            out[24..32].copy_from_slice(&self.oracle_price_e6.to_le_bytes());
+            out[32..40].copy_from_slice(&self.fee_bps.to_le_bytes());
+            out[40..56].copy_from_slice(&self.fee_paid.to_le_bytes());
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != TRADE_RESULT_LEN {
+                return None;
+            }
+            let u64_at = |o: usize| u64::from_le_bytes(data[o..o + 8].try_into().unwrap());
+            Some(Self {
+                exec_size: i128::from_le_bytes(data[0..16].try_into().unwrap()),
+                exec_price_e6: u64_at(16),
+                oracle_price_e6: u64_at(24),
+                fee_bps: u64_at(32),
+                fee_paid: u128::from_le_bytes(data[40..56].try_into().unwrap()),
+            })
+        }
+    }
+
+    /// `sol_log_data` record (one "Program data:" field) written when a
+    /// DepositCollateral, TradeNoCpi or TradeCpi carries a nonzero client
+    /// tag. Fixed 23-byte layout:
+    ///
+    /// ```text
+    ///   0..4   magic       b"ctag"
+    ///   4      ix_tag      u8    3, 6 or 10
+    ///   5..7   user_idx    u16
+    ///   7..23  client_tag  [u8; 16]
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct ClientTagEvent {
+        pub ix_tag: u8,
+        pub user_idx: u16,
+        pub client_tag: [u8; crate::constants::CLIENT_TAG_LEN],
+    }
+
+    pub const CLIENT_TAG_EVENT_MAGIC: [u8; 4] = *b"ctag";
+    pub const CLIENT_TAG_EVENT_LEN: usize = 23;
+
+    impl ClientTagEvent {
+        pub fn encode(&self) -> [u8; CLIENT_TAG_EVENT_LEN] {
+            let mut out = [0u8; CLIENT_TAG_EVENT_LEN];
+            out[0..4].copy_from_slice(&CLIENT_TAG_EVENT_MAGIC);
+            out[4] = self.ix_tag;
+            out[5..7].copy_from_slice(&self.user_idx.to_le_bytes());
+            out[7..23].copy_from_slice(&self.client_tag);
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != CLIENT_TAG_EVENT_LEN || data[0..4] != CLIENT_TAG_EVENT_MAGIC {
+                return None;
+            }
+            Some(Self {
+                ix_tag: data[4],
+                user_idx: u16::from_le_bytes([data[5], data[6]]),
+                client_tag: data[7..23].try_into().unwrap(),
+            })
+        }
+    }
+
+    /// `sol_log_data` record written at the end of every KeeperCrank pass on
+    /// a live market, so keepers can monitor liveness from logs alone.
+    /// Fixed 61-byte layout:
+    ///
+    /// ```text
+    ///   0..4   magic            b"crnk"
+    ///   4..12  slot             u64   clock slot of the crank
+    ///  12..20  crank_slot       u64   slot the engine advanced to
+    ///  20..36  funding_rate_e9  i128  rate applied over the interval
+    ///  36..52  fees_accrued     u128  maintenance fees swept into insurance
+    ///  52..54  candidates       u16   accounts handed to the engine
+    ///  54..56  scanned          u16   accounts seen by the risk-buffer scan
+    ///  56..58  liquidations     u16
+    ///  58..60  next_cursor      u16   risk-buffer scan cursor after the pass
+    ///  60      flags            u8    CRANK_SUMMARY_*
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct CrankSummaryEvent {
+        pub slot: u64,
+        pub crank_slot: u64,
+        pub funding_rate_e9: i128,
+        pub fees_accrued: u128,
+        pub candidates: u16,
+        pub scanned: u16,
+        pub liquidations: u16,
+        pub next_cursor: u16,
+        pub flags: u8,
+    }
+
+    pub const CRANK_SUMMARY_EVENT_MAGIC: [u8; 4] = *b"crnk";
+    pub const CRANK_SUMMARY_EVENT_LEN: usize = 61;
+    /// The engine ran a full progress pass (not a no-op).
+    pub const CRANK_SUMMARY_CRANKED: u8 = 1 << 0;
+    /// The market was too far behind; only one bounded segment was accrued.
+    pub const CRANK_SUMMARY_PARTIAL_CATCHUP: u8 = 1 << 1;
+
+    impl CrankSummaryEvent {
+        pub fn encode(&self) -> [u8; CRANK_SUMMARY_EVENT_LEN] {
+            let mut out = [0u8; CRANK_SUMMARY_EVENT_LEN];
+            out[0..4].copy_from_slice(&CRANK_SUMMARY_EVENT_MAGIC);
+            out[4..12].copy_from_slice(&self.slot.to_le_bytes());
+            out[12..20].copy_from_slice(&self.crank_slot.to_le_bytes());
+            out[20..36].copy_from_slice(&self.funding_rate_e9.to_le_bytes());
+            out[36..52].copy_from_slice(&self.fees_accrued.to_le_bytes());
+            out[52..54].copy_from_slice(&self.candidates.to_le_bytes());
+            out[54..56].copy_from_slice(&self.scanned.to_le_bytes());
+            out[56..58].copy_from_slice(&self.liquidations.to_le_bytes());
+            out[58..60].copy_from_slice(&self.next_cursor.to_le_bytes());
+            out[60] = self.flags;
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != CRANK_SUMMARY_EVENT_LEN || data[0..4] != CRANK_SUMMARY_EVENT_MAGIC {
+                return None;
+            }
+            let u16_at = |o: usize| u16::from_le_bytes([data[o], data[o + 1]]);
+            Some(Self {
+                slot: u64::from_le_bytes(data[4..12].try_into().unwrap()),
+                crank_slot: u64::from_le_bytes(data[12..20].try_into().unwrap()),
+                funding_rate_e9: i128::from_le_bytes(data[20..36].try_into().unwrap()),
+                fees_accrued: u128::from_le_bytes(data[36..52].try_into().unwrap()),
+                candidates: u16_at(52),
+                scanned: u16_at(54),
+                liquidations: u16_at(56),
+                next_cursor: u16_at(58),
+                flags: data[60],
+            })
+        }
+    }
+
+    /// Return data set by QueryHealth. Little-endian, fixed layout:
+    ///
+    /// ```text
+    ///   0..16  equity           i128  capital + pnl, pending K/F PnL included
+    ///  16..32  maintenance_req  u128  0 when flat
+    ///  32..48  initial_req      u128  0 when flat
+    ///  48..64  free_collateral  u128  max(equity - initial_req, 0)
+    ///  64..80  position_q       i128  effective position
+    ///  80..88  price_e6         u64   price the numbers were valued at
+    ///  88..96  last_market_slot u64   engine slot that price belongs to
+    /// ```
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct HealthSnapshot {
+        pub equity: i128,
+        pub maintenance_req: u128,
+        pub initial_req: u128,
+        pub free_collateral: u128,
+        pub position_q: i128,
+        pub price_e6: u64,
+        pub last_market_slot: u64,
+    }
+
+    pub const HEALTH_SNAPSHOT_LEN: usize = 96;
+
+    impl HealthSnapshot {
+        pub fn encode(&self) -> [u8; HEALTH_SNAPSHOT_LEN] {
+            let mut out = [0u8; HEALTH_SNAPSHOT_LEN];
+            out[0..16].copy_from_slice(&self.equity.to_le_bytes());
+            out[16..32].copy_from_slice(&self.maintenance_req.to_le_bytes());
+            out[32..48].copy_from_slice(&self.initial_req.to_le_bytes());
+            out[48..64].copy_from_slice(&self.free_collateral.to_le_bytes());
+            out[64..80].copy_from_slice(&self.position_q.to_le_bytes());
+            out[80..88].copy_from_slice(&self.price_e6.to_le_bytes());
+            out[88..96].copy_from_slice(&self.last_market_slot.to_le_bytes());
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != HEALTH_SNAPSHOT_LEN {
+                return None;
+            }
+            let u128_at = |o: usize| u128::from_le_bytes(data[o..o + 16].try_into().unwrap());
+            let u64_at = |o: usize| u64::from_le_bytes(data[o..o + 8].try_into().unwrap());
+            Some(Self {
+                equity: u128_at(0) as i128,
+                maintenance_req: u128_at(16),
+                initial_req: u128_at(32),
+                free_collateral: u128_at(48),
+                position_q: u128_at(64) as i128,
+                price_e6: u64_at(80),
+                last_market_slot: u64_at(88),
+            })
+        }
+    }
+
+    /// `max(equity - initial_req, 0)`.
+    pub fn free_collateral(equity: i128, initial_req: u128) -> u128 {
+        if equity <= 0 {
+            return 0;
+        }
+        (equity as u128).saturating_sub(initial_req)
+    }
+}
+
+// 5. mod accounts (Pinocchio validation)
+pub mod accounts {
+    use crate::error::PercolatorError;
+    use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+    /// Strict account-count check. Rejects if the caller passes more
+    /// or fewer accounts than the handler expects.
+    pub fn expect_len(accounts: &[AccountInfo], n: usize) -> Result<(), ProgramError> {
+        if !crate::policy::len_ok(accounts.len(), n) {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Ok(())
+    }
+
+    /// Variadic-tail check — used only by instructions with a
+    /// documented tail forwarding convention (TradeCpi).
+    pub fn expect_len_min(accounts: &[AccountInfo], n: usize) -> Result<(), ProgramError> {
+        if !crate::policy::len_at_least(accounts.len(), n) {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Ok(())
+    }
+
+    pub fn expect_signer(ai: &AccountInfo) -> Result<(), ProgramError> {
+        // Signer check via policy helper
+        if !crate::policy::signer_ok(ai.is_signer) {
+            return Err(PercolatorError::ExpectedSigner.into());
+        }
+        Ok(())
+    }
+
+    pub fn expect_writable(ai: &AccountInfo) -> Result<(), ProgramError> {
+        // Writable check via policy helper
+        if !crate::policy::writable_ok(ai.is_writable) {
+            return Err(PercolatorError::ExpectedWritable.into());
+        }
+        Ok(())
+    }
+
+    pub fn expect_key(ai: &AccountInfo, expected: &Pubkey) -> Result<(), ProgramError> {
+        // Key check via policy helper
+        if !crate::policy::pda_key_matches(expected.to_bytes(), ai.key.to_bytes()) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    /// Who must own an account in an [`AccountSpec`].
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Owner {
+        Any,
+        /// The executing (Percolator) program.
+        Program,
+        Token,
+        System,
+    }
+
+    /// Declarative expectations for one position in an instruction's
+    /// account list. [`check_accounts`] runs a whole list up front, before
+    /// the handler touches any data.
+    #[derive(Clone, Copy, Debug)]
+    pub struct AccountSpec {
+        /// Logged on failure as `account #<i> expected <what>`.
+        pub what: &'static str,
+        pub signer: bool,
+        pub writable: bool,
+        pub owner: Owner,
+        /// Fixed address (sysvars, programs), if any.
+        pub key: Option<Pubkey>,
+    }
+
+    impl AccountSpec {
+        pub const fn new(what: &'static str) -> Self {
+            Self {
+                what,
+                signer: false,
+                writable: false,
+                owner: Owner::Any,
+                key: None,
+            }
+        }
+        pub const fn signer(mut self) -> Self {
+            self.signer = true;
+            self
+        }
+        pub const fn writable(mut self) -> Self {
+            self.writable = true;
+            self
+        }
+        pub const fn owner(mut self, owner: Owner) -> Self {
+            self.owner = owner;
+            self
+        }
+        pub const fn key(mut self, key: Pubkey) -> Self {
+            self.key = Some(key);
+            self
+        }
+    }
+
+    /// `[admin (signer), slab (writable, program-owned)]`, the account list
+    /// of every two-account admin instruction.
+    pub const ADMIN_SLAB: &[AccountSpec] = &[
+        AccountSpec::new("admin signer").signer(),
+        AccountSpec::new("writable program-owned slab")
+            .writable()
+            .owner(Owner::Program),
+    ];
+
+    /// `[owner (signer), slab, owner ATA, vault, token program, clock]`:
+    /// DepositCollateral and BackstopDeposit. The token program and token
+    /// accounts are still checked by the handler, which knows the mint.
+    pub const DEPOSIT: &[AccountSpec] = &[
+        AccountSpec::new("owner signer").signer(),
+        AccountSpec::new("writable program-owned slab")
+            .writable()
+            .owner(Owner::Program),
+        AccountSpec::new("owner token account"),
+        AccountSpec::new("vault token account"),
+        AccountSpec::new("token program"),
+        AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+    ];
+
+    /// Fixed head of WithdrawCollateral, CloseAccount and BackstopWithdraw,
+    /// ahead of the oracle tail: `[owner (signer), slab, vault, owner ATA,
+    /// vault authority PDA, token program, clock]`.
+    pub const WITHDRAW_HEAD: &[AccountSpec] = &[
+        AccountSpec::new("owner signer").signer(),
+        AccountSpec::new("writable program-owned slab")
+            .writable()
+            .owner(Owner::Program),
+        AccountSpec::new("vault token account"),
+        AccountSpec::new("owner token account"),
+        AccountSpec::new("vault authority PDA"),
+        AccountSpec::new("token program"),
+        AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+    ];
+
+    /// Exact count, then each account against its spec in order. Errors
+    /// are the same as the single-purpose checks (`ExpectedSigner`,
+    /// `ExpectedWritable`, `IllegalOwner`, `InvalidArgument` for a wrong
+    /// address), preceded by a log line naming the account.
+    pub fn check_accounts(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        specs: &[AccountSpec],
+    ) -> Result<(), ProgramError> {
+        expect_len(accounts, specs.len())?;
+        check_each(program_id, accounts, specs)
+    }
+
+    /// [`check_accounts`] for a list with a variable tail (oracle legs,
+    /// TradeCpi's matcher accounts): at least `specs.len()` accounts, the
+    /// leading ones checked against `specs`. The handler bounds the tail.
+    pub fn check_account_head(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        specs: &[AccountSpec],
+    ) -> Result<(), ProgramError> {
+        expect_len_min(accounts, specs.len())?;
+        check_each(program_id, accounts, specs)
+    }
+
+    fn check_each(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        specs: &[AccountSpec],
+    ) -> Result<(), ProgramError> {
+        for (i, (ai, spec)) in accounts.iter().zip(specs).enumerate() {
+            let res = check_account(program_id, ai, spec);
+            if res.is_err() {
+                log_account_spec_failure(i, spec.what);
+            }
+            res?;
+        }
+        Ok(())
+    }
+
+    fn check_account(
+        program_id: &Pubkey,
+        ai: &AccountInfo,
+        spec: &AccountSpec,
+    ) -> Result<(), ProgramError> {
+        if spec.signer {
+            expect_signer(ai)?;
+        }
+        if spec.writable {
+            expect_writable(ai)?;
+        }
+        let owner_ok = match spec.owner {
+            Owner::Any => true,
+            Owner::Program => ai.owner == program_id,
+            Owner::Token => *ai.owner == spl_token::ID,
+            Owner::System => *ai.owner == solana_program::system_program::ID,
+        };
+        if !owner_ok {
+            return Err(ProgramError::IllegalOwner);
+        }
+        if let Some(key) = &spec.key {
+            expect_key(ai, key)?;
+        }
+        Ok(())
+    }
+
+    /// `account #<i> expected <what>`, built without `core::fmt`.
+    fn log_account_spec_failure(i: usize, what: &str) {
+        let mut buf = [0u8; 96];
+        let mut n = 0;
+        let mut push = |bytes: &[u8]| {
+            let m = bytes.len().min(buf.len() - n);
+            buf[n..n + m].copy_from_slice(&bytes[..m]);
+            n += m;
+        };
+        push(b"account #");
+        let mut digits = [0u8; 3];
+        let mut d = digits.len();
+        let mut v = i.min(999);
+        loop {
+            d -= 1;
+            digits[d] = b'0' + (v % 10) as u8;
+            v /= 10;
+            if v == 0 {
+                break;
+            }
+        }
+        push(&digits[d..]);
+        push(b" expected ");
+        push(what.as_bytes());
+        if let Ok(line) = core::str::from_utf8(&buf[..n]) {
+            solana_program::log::sol_log(line);
+        }
+    }
+
+    pub fn derive_vault_authority(program_id: &Pubkey, slab_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"vault", slab_key.as_ref()], program_id)
+    }
+
+    /// Derive vault authority from stored bump (saves ~1300 CU vs find_program_address)
+    pub fn derive_vault_authority_with_bump(
+        program_id: &Pubkey,
+        slab_key: &Pubkey,
+        bump: u8,
+    ) -> Result<Pubkey, ProgramError> {
+        Pubkey::create_program_address(&[b"vault", slab_key.as_ref(), &[bump]], program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)
+    }
+
+    /// SPL Associated Token Account program.
+    pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+        solana_program::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+    /// `owner`'s associated token account for `mint` (SPL token program).
+    pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[owner.as_ref(), spl_token::ID.as_ref(), mint.as_ref()],
+            &ASSOCIATED_TOKEN_PROGRAM_ID,
+        )
+        .0
+    }
+
+    /// The market's collateral vault: the vault authority PDA's ATA for
+    /// `mint`. InitMarket accepts no other account, so every market's
+    /// vault is derivable from its slab key and mint.
+    pub fn derive_vault(program_id: &Pubkey, slab_key: &Pubkey, mint: &Pubkey) -> Pubkey {
+        associated_token_address(&derive_vault_authority(program_id, slab_key).0, mint)
+    }
+
+    /// Per-market treasury PDA. Owns the token account that
+    /// ClaimProtocolFees sweeps protocol fees into.
+    pub fn derive_treasury(program_id: &Pubkey, slab_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"treasury", slab_key.as_ref()], program_id)
+    }
+
+    /// Backstop PDA: owner of the backstop vault's LP account. Nothing
+    /// signs as it, so only the backstop instructions move that account's
+    /// capital.
+    pub fn derive_backstop_authority(program_id: &Pubkey, slab_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"backstop", slab_key.as_ref()], program_id)
+    }
+
+    /// Permit PDA: the SPL delegate users approve on their token accounts
+    /// so DepositWithPermit can pull a signed amount. Only that instruction
+    /// signs as it.
+    pub fn derive_permit_authority(program_id: &Pubkey, slab_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"permit", slab_key.as_ref()], program_id)
+    }
+
+    /// Market pointer PDA: lets clients find a slab from what it trades.
+    pub fn derive_market(
+        program_id: &Pubkey,
+        collateral_mint: &[u8; 32],
+        index_feed_id: &[u8; 32],
+        index: u16,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"market",
+                collateral_mint,
+                index_feed_id,
+                &index.to_le_bytes(),
+            ],
+            program_id,
+        )
+    }
+}
+
+// 6. mod state
+pub mod state {
+    use crate::constants::{CONFIG_LEN, HEADER_LEN};
+    use bytemuck::{Pod, Zeroable};
+    use core::cell::RefMut;
+    use core::mem::offset_of;
+    use solana_program::account_info::AccountInfo;
+    use solana_program::program_error::ProgramError;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    pub struct SlabHeader {
+        pub magic: u64,
+        pub version: u32,
+        pub bump: u8,
+        pub _padding: [u8; 3],
+        pub admin: [u8; 32],
+        pub _reserved: [u8; 24], // [0..8]=nonce, [8..16]=mat counter, [16..24]=account fees
+        /// Scoped authority: may execute WithdrawInsurance (and the
+        /// admin-only bounded WithdrawInsuranceLimited policy-setter
+        /// path, once refactored). Independent of `admin`; can be
+        /// delegated or burned via UpdateAuthority { kind=INSURANCE }.
+        /// Initialized to the creator's pubkey at InitMarket, which
+        /// yields a functional "super admin" by default.
+        pub insurance_authority: [u8; 32],
+        /// Scoped authority: may execute `WithdrawInsuranceLimited`
+        /// (tag 23) — a bounded live fee-extraction path enforcing
+        /// `config.insurance_withdraw_max_bps` per withdrawal and
+        /// `config.insurance_withdraw_cooldown_slots` between them.
+        /// Structurally CANNOT call tag 20 (`WithdrawInsurance`),
+        /// whose unbounded drain is gated on `insurance_authority`.
+        /// The auth split is load-bearing: it's what makes the bounds
+        /// un-bypassable. Burn to lock fee extraction. Independent of
+        /// all other authorities; rotated via
+        /// UpdateAuthority { kind=INSURANCE_OPERATOR }.
+        pub insurance_operator: [u8; 32],
+    }
+
+    /// Offset of _reserved field in SlabHeader, derived from offset_of! for correctness.
+    pub const RESERVED_OFF: usize = offset_of!(SlabHeader, _reserved);
+
+    // Portable compile-time assertion that RESERVED_OFF is 48 (expected layout).
+    // Subsequent authority fields (insurance_authority, insurance_operator) sit
+    // after _reserved, so this offset is stable at 48.
+    const _: [(); 48] = [(); RESERVED_OFF];
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    pub struct MarketConfig {
+        pub collateral_mint: [u8; 32],
+        pub vault_pubkey: [u8; 32],
+        /// Pyth/Chainlink feed ID for oracle leg 1.
+        pub index_feed_id: [u8; 32],
+        /// Optional oracle leg 2 feed ID. Zero when inactive.
+        pub oracle_leg2_feed_id: [u8; 32],
+        /// Optional oracle leg 3 feed ID. Zero when inactive.
+        pub oracle_leg3_feed_id: [u8; 32],
+        /// Active external oracle leg count. 1 for legacy single-feed markets.
+        pub oracle_leg_count: u8,
+        /// Per-leg orientation bits. Leg 1 always multiplies; bits 1/2 divide
+        /// legs 2/3 when set, otherwise those legs multiply.
+        pub oracle_leg_flags: u8,
+        pub _oracle_leg_padding: [u8; 14],
+        /// Maximum staleness in seconds (Pyth Pull uses unix timestamps)
+        pub max_staleness_secs: u64,
+        pub conf_filter_bps: u16,
+        pub vault_authority_bump: u8,
+        /// If non-zero, invert the oracle price (raw -> 1e12/raw)
+        pub invert: u8,
+        /// Lamports per Unit for conversion (e.g., 1000 means 1 SOL = 1,000,000 Units)
+        /// If 0, no scaling is applied (1:1 lamports to units)
+        pub unit_scale: u32,
+
+        // ========================================
+        // Funding Parameters (configurable)
+        // ========================================
+        /// Funding horizon in slots (~4 min at 500 slots)
+        pub funding_horizon_slots: u64,
+        /// Funding rate multiplier in basis points (100 = 1.00x)
+        pub funding_k_bps: u64,
+        /// Max premium in basis points (500 = 5%)
+        pub funding_max_premium_bps: i64,
+        /// Max funding rate per slot in basis points
+        pub funding_max_e9_per_slot: i64,
+
+        // ========================================
+        // Oracle Authority (optional signer-based oracle)
+        // ========================================
+        /// Oracle price authority pubkey. If non-zero, this signer can push prices
+        /// directly instead of requiring Pyth/Chainlink. All zeros = disabled.
+        pub hyperp_authority: [u8; 32],
+        /// Last price pushed by the Hyperp mark authority (e6, already
+        /// invert+scale normalized to engine space).
+        pub hyperp_mark_e6: u64,
+        /// Most recently accepted external oracle observation timestamp
+        /// (Pyth `publish_time` or Chainlink `timestamp`, in seconds).
+        /// Kept for ABI compatibility and liveness accounting; the raw
+        /// source target is stored separately in `oracle_target_*`, and
+        /// `last_effective_price_e6` is the dt-capped price fed to the engine.
+        pub last_oracle_publish_time: i64,
+
+        /// Last effective oracle/index price in e6 format. External mode stores
+        /// the dt-capped staircase value, not necessarily the raw oracle target;
+        /// Hyperp mode stores the rate-limited index.
+        pub last_effective_price_e6: u64,
+
+        // ========================================
+        // Insurance Withdrawal Limits (set at InitMarket, immutable)
+        // ========================================
+        /// Max bps of insurance fund withdrawable per withdrawal (1-10000).
+        /// 0 = disabled (no live-market withdrawals allowed).
+        pub insurance_withdraw_max_bps: u16,
+        /// Admin-opt-in deposit cap: total user capital `c_tot` after a
+        /// deposit must satisfy `c_tot_new <= tvl_insurance_cap_mult *
+        /// insurance_fund.balance`. 0 disables the check (default).
+        /// Tuned by admin via UpdateConfig; typical production values are
+        /// 10–100 (mature perp DEXs run ~20× insurance coverage).
+        pub tvl_insurance_cap_mult: u16,
+        /// Optional bounded-withdrawal mode. 0 = legacy bounded withdrawals
+        /// may withdraw live insurance subject to bps/cooldown. 1 = tag 23
+        /// may withdraw only the remaining amount deposited through
+        /// TopUpInsurance; fee/trading/liquidation growth stays behind.
+        pub insurance_withdraw_deposits_only: u8,
+        /// Padding for alignment (was [u8; 4]; shrunk when deposit-only mode
+        /// claimed 1 byte of the former slot).
+        pub _iw_padding: [u8; 3],
+        /// Minimum slots between insurance withdrawals.
+        pub insurance_withdraw_cooldown_slots: u64,
+        /// Latest raw external oracle target in engine-space e6. Non-Hyperp
+        /// only; Hyperp markets keep this equal to their initialized index.
+        pub oracle_target_price_e6: u64,
+        /// Timestamp of `oracle_target_price_e6`.
+        pub oracle_target_publish_time: i64,
+        /// Last accepted raw e6 price for each external oracle leg.
+        /// Used to reject same-timestamp conflicting updates on composite
+        /// oracles. Hyperp markets leave this zeroed.
+        pub oracle_leg_prices_e6: [u64; crate::constants::ORACLE_LEG_CAP],
+        /// Last accepted publish time for each external oracle leg.
+        /// Composite reads reject per-leg rollback, so a caller cannot pair a
+        /// fresh leg with an older already-observed leg to manufacture a cross.
+        pub oracle_leg_publish_times: [i64; crate::constants::ORACLE_LEG_CAP],
+        pub last_hyperp_index_slot: u64,
+        pub last_mark_push_slot: u128,
+        /// Last slot when insurance was withdrawn (for live-market cooldown tracking).
+        /// Uses a dedicated field to avoid overwriting oracle config fields.
+        pub last_insurance_withdraw_slot: u64,
+        /// Remaining TopUpInsurance principal withdrawable through tag 23
+        /// when `insurance_withdraw_deposits_only == 1`. This tracks only
+        /// explicit top-ups: it increases on TopUpInsurance and decreases on
+        /// WithdrawInsuranceLimited. Insurance growth from fees/liquidations
+        /// never increments this budget.
+        pub insurance_withdraw_deposit_remaining: u64,
+
+        // ========================================
+        // Mark EWMA (trade-derived mark price for funding)
+        // ========================================
+        /// EWMA of execution prices (e6). Updated on every TradeCpi fill.
+        pub mark_ewma_e6: u64,
+        /// Slot when mark_ewma_e6 was last updated.
+        pub mark_ewma_last_slot: u64,
+        /// EWMA decay half-life in slots. 0 = last trade price directly.
+        pub mark_ewma_halflife_slots: u64,
+        /// `LastRestartSlot` sysvar reading captured at InitMarket, used to
+        /// detect post-init cluster restarts. Once any observer sees
+        /// `LastRestartSlot::get() > init_restart_slot`, the market is
+        /// considered dead regardless of oracle-staleness configuration —
+        /// `permissionless_stale_matured` returns true and resolution settles
+        /// at `engine.last_oracle_price` (the pre-restart cached price).
+        /// Occupies the slot previously reserved as u128-alignment padding
+        /// for the `maintenance_fee_per_slot` u128 that follows.
+        pub init_restart_slot: u64,
+
+        // ========================================
+        // Permissionless Resolution
+        // ========================================
+        /// Slots of oracle staleness required before anyone can resolve.
+        /// 0 = disabled (admin-only resolution). Set at InitMarket, immutable.
+        pub permissionless_resolve_stale_slots: u64,
+        /// Slot of last successful external oracle read (non-Hyperp only).
+        /// Authoritative liveness signal under the strict hard-timeout
+        /// model: `clock.slot - last_good_oracle_slot >=
+        /// permissionless_resolve_stale_slots` makes the market stale
+        /// and eligible for ResolvePermissionless, and causes
+        /// read_price_and_stamp to reject further price-taking ops.
+        /// Seeded to clock.slot at InitMarket.
+        pub last_good_oracle_slot: u64,
+
+        // ========================================
+        // Fee-Weighted EWMA
+        // ========================================
+        /// Periodic maintenance fee per slot per account (engine units).
+        /// Wrapper charges via engine.sync_account_fee_to_slot_not_atomic.
+        /// 0 = disabled. Set at InitMarket, immutable.
+        pub maintenance_fee_per_slot: u128,
+        /// Incremental fee-sweep cursor: next bitmap word to scan on the
+        /// next KeeperCrank. Per-account `last_fee_slot` on the engine side
+        /// keeps the sweep correct across cranks — each account pays for
+        /// its full elapsed interval the first time the cursor reaches it.
+        /// Scanning is O(FEE_SWEEP_BUDGET) per crank regardless of
+        /// max_accounts, so a 4096-account market doesn't blow the CU
+        /// budget on a single crank.
+        ///
+        /// Repurposed from the former `last_fee_charge_slot` (now dead —
+        /// replaced by per-account `Account::last_fee_slot`). Same 8-byte
+        /// slot, same wire offset; only u16 is meaningful.
+        pub fee_sweep_cursor_word: u64,
+        /// Bit position within `fee_sweep_cursor_word` at which the next sweep
+        /// resumes. Stored so the sweep can stop EXACTLY at FEE_SWEEP_BUDGET
+        /// mid-word without losing remaining set bits to budget truncation.
+        /// Only values 0..=63 are meaningful; wider values are normalized.
+        /// Repurposed from the former `_fee_padding`.
+        pub fee_sweep_cursor_bit: u64,
+        /// Minimum fee (in engine units, same as insurance_fund.balance) for full mark EWMA weight.
+        /// Trades with fee below this get proportionally reduced alpha.
+        /// 0 = disabled (all trades get full weight, backward compat).
+        /// Set at InitMarket, immutable.
+        pub mark_min_fee: u64,
+        /// Normal wrapper trade fee in bps. The engine's
+        /// `RiskParams::max_trading_fee_bps` is the hard cap; Hyperp
+        /// after-hours mode may raise the current fee above this base by the
+        /// EWMA mark movement it causes. Defaults to max_trading_fee_bps for
+        /// legacy/static-fee markets.
+        pub trade_fee_base_bps: u64,
+        /// Wrapper trade-fee mode. Reuses the former padding slot, so the
+        /// persisted layout is unchanged.
+        pub trade_fee_mode: u64,
+        /// Minimum slots after resolution before permissionless force-close.
+        /// 0 = disabled. Set at InitMarket, immutable.
+        pub force_close_delay_slots: u64,
+        /// Wrapper-charged new-account fee (base units). At InitUser/InitLP
+        /// the wrapper requires `fee_payment >= new_account_fee`; the fee
+        /// goes to `insurance_fund.balance`, the remainder becomes capital.
+        /// 0 = disabled (full `fee_payment` goes to capital).
+        pub new_account_fee: u128,
+    }
+
+    pub fn slab_data_mut<'a, 'b>(
+        ai: &'b AccountInfo<'a>,
+    ) -> Result<RefMut<'b, &'a mut [u8]>, ProgramError> {
+        Ok(ai.try_borrow_mut_data()?)
+    }
+
+    pub fn read_header(data: &[u8]) -> SlabHeader {
+        let mut h = SlabHeader::zeroed();
+        let src = &data[..HEADER_LEN];
+        let dst = bytemuck::bytes_of_mut(&mut h);
+        dst.copy_from_slice(src);
+        h
+    }
+
+    pub fn write_header(data: &mut [u8], h: &SlabHeader) {
+        let src = bytemuck::bytes_of(h);
+        let dst = &mut data[..HEADER_LEN];
+        dst.copy_from_slice(src);
+    }
+
+    /// Read the request nonce from the reserved field in slab header.
+    /// The nonce is stored at RESERVED_OFF..RESERVED_OFF+8 as little-endian u64.
+    pub fn read_req_nonce(data: &[u8]) -> u64 {
+        u64::from_le_bytes(data[RESERVED_OFF..RESERVED_OFF + 8].try_into().unwrap())
+    }
+
+    /// Write the request nonce to the reserved field in slab header.
+    /// The nonce is stored in _reserved[0..8] as little-endian u64.
+    /// Uses offset_of! for correctness even if SlabHeader layout changes.
+    pub fn write_req_nonce(data: &mut [u8], nonce: u64) {
+        #[cfg(debug_assertions)]
+        debug_assert!(HEADER_LEN >= RESERVED_OFF + 16);
+        data[RESERVED_OFF..RESERVED_OFF + 8].copy_from_slice(&nonce.to_le_bytes());
+    }
+
+    /// Monotonic materialization counter stored in _reserved[8..16].
+    /// Incremented on every InitUser/InitLP. Used as lp_account_id
+    /// to provide a true per-instance identity that survives slot reuse.
+    pub fn read_mat_counter(data: &[u8]) -> u64 {
+        u64::from_le_bytes(
+            data[RESERVED_OFF + 8..RESERVED_OFF + 16]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    pub fn write_mat_counter(data: &mut [u8], counter: u64) {
+        data[RESERVED_OFF + 8..RESERVED_OFF + 16].copy_from_slice(&counter.to_le_bytes());
+    }
+
+    /// Increment the materialization counter and return the NEW value.
+    /// Each account gets a globally unique ID at creation time.
+    /// Returns None if the counter would overflow (0 is reserved as "never materialized").
+    pub fn next_mat_counter(data: &mut [u8]) -> Option<u64> {
+        let old = read_mat_counter(data);
+        let c = old.checked_add(1)?;
+        write_mat_counter(data, c);
+        Some(c)
+    }
+
+    /// Uncollected new-account fees (engine units) stored in _reserved[16..24].
+    /// Credited at InitUser/InitLP alongside the insurance top-up and
+    /// debited by WithdrawFees; the backing tokens are part of
+    /// `insurance_fund.balance`, never `c_tot`.
+    pub fn read_account_fees(data: &[u8]) -> u64 {
+        u64::from_le_bytes(
+            data[RESERVED_OFF + 16..RESERVED_OFF + 24]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    pub fn write_account_fees(data: &mut [u8], fees: u64) {
+        data[RESERVED_OFF + 16..RESERVED_OFF + 24].copy_from_slice(&fees.to_le_bytes());
+    }
+
+    /// InitSlab progress: bytes of the slab zeroed so far, stored in
+    /// _reserved[0..8] (the request nonce's slot, unused until InitMarket
+    /// rewrites the header). Only meaningful under `INITIALIZING_MAGIC`.
+    pub fn read_init_cursor(data: &[u8]) -> u64 {
+        read_req_nonce(data)
+    }
+
+    pub fn write_init_cursor(data: &mut [u8], cursor: u64) {
+        write_req_nonce(data, cursor)
+    }
+
+    // ========================================
+    // Market Flags (stored in _padding[0] at offset 13)
+    // ========================================
+
+    /// Offset of flags byte in SlabHeader (_padding[0])
+    pub const FLAGS_OFF: usize = 13;
+
+    // Bit 1 (formerly FLAG_POLICY_CONFIGURED) is unused — the
+    // policy-configured flag was removed along with the
+    // SetInsuranceWithdrawPolicy / WithdrawInsuranceLimited
+    // instructions. Do NOT reuse this bit without a state migration
+    // (it may be set on any pre-delete slab; fresh deploy only).
+    /// Flag bit: CPI is in progress (reentrancy guard for TradeCpi).
+    /// Set before matcher CPI, cleared after. Any reentrant instruction
+    /// that sees this flag must abort.
+    pub const FLAG_CPI_IN_PROGRESS: u8 = 1 << 2;
+    /// Flag bit: engine has received a real oracle price (not the init sentinel).
+    /// Set on first successful oracle read (crank/trade/settle).
+    /// Eliminates the "price 1 means uninitialized" sentinel overload.
+    pub const FLAG_ORACLE_INITIALIZED: u8 = 1 << 3;
+
+    /// Read market flags from _padding[0].
+    pub fn read_flags(data: &[u8]) -> u8 {
+        data[FLAGS_OFF]
+    }
+
+    /// Write market flags to _padding[0].
+    pub fn write_flags(data: &mut [u8], flags: u8) {
+        data[FLAGS_OFF] = flags;
+    }
+
+    /// Check if CPI is in progress (reentrancy guard).
+    pub fn is_cpi_in_progress(data: &[u8]) -> bool {
+        read_flags(data) & FLAG_CPI_IN_PROGRESS != 0
+    }
+
+    /// Set CPI-in-progress flag (call before matcher CPI).
+    pub fn set_cpi_in_progress(data: &mut [u8]) {
+        let flags = read_flags(data) | FLAG_CPI_IN_PROGRESS;
+        write_flags(data, flags);
+    }
+
+    /// Clear CPI-in-progress flag (call after matcher CPI returns).
+    pub fn clear_cpi_in_progress(data: &mut [u8]) {
+        let flags = read_flags(data) & !FLAG_CPI_IN_PROGRESS;
+        write_flags(data, flags);
+    }
+
+    /// Check if engine has received a real oracle price.
+    pub fn is_oracle_initialized(data: &[u8]) -> bool {
+        read_flags(data) & FLAG_ORACLE_INITIALIZED != 0
+    }
+
+    /// Mark engine as having received a real oracle price.
+    pub fn set_oracle_initialized(data: &mut [u8]) {
+        let flags = read_flags(data) | FLAG_ORACLE_INITIALIZED;
+        write_flags(data, flags);
+    }
+
+    pub fn read_config(data: &[u8]) -> MarketConfig {
+        let mut c = MarketConfig::zeroed();
+        let src = &data[HEADER_LEN..HEADER_LEN + CONFIG_LEN];
+        let dst = bytemuck::bytes_of_mut(&mut c);
+        dst.copy_from_slice(src);
+        c
+    }
+
+    pub fn write_config(data: &mut [u8], c: &MarketConfig) {
+        let src = bytemuck::bytes_of(c);
+        let dst = &mut data[HEADER_LEN..HEADER_LEN + CONFIG_LEN];
+        dst.copy_from_slice(src);
+    }
+
+    pub fn read_risk_buffer(data: &[u8]) -> crate::risk_buffer::RiskBuffer {
+        use crate::constants::RISK_BUF_LEN;
+        use crate::constants::RISK_BUF_OFF;
+        risk_buffer_from_bytes(&data[RISK_BUF_OFF..RISK_BUF_OFF + RISK_BUF_LEN])
+    }
+
+    /// Sanitized copy of the `RISK_BUF_LEN` bytes of a risk buffer, e.g.
+    /// `zc::SlabRegions::region(RISK_BUF_OFF, RISK_BUF_LEN)`.
+    pub fn risk_buffer_from_bytes(src: &[u8]) -> crate::risk_buffer::RiskBuffer {
+        let mut buf = crate::risk_buffer::RiskBuffer::zeroed();
+        bytemuck::bytes_of_mut(&mut buf).copy_from_slice(src);
+        // Full sanitization against corrupted slab data:
+        // 1. Clamp count
+        if buf.count as usize > crate::constants::RISK_BUF_CAP {
+            buf.count = crate::constants::RISK_BUF_CAP as u8;
+        }
+        // 2. Zero entries past count
+        for i in buf.count as usize..crate::constants::RISK_BUF_CAP {
+            buf.entries[i] = crate::risk_buffer::RiskEntry::zeroed();
+        }
+        // 3. Filter invalid idx values
+        for i in (0..buf.count as usize).rev() {
+            if buf.entries[i].idx as usize >= percolator::MAX_ACCOUNTS {
+                buf.remove(buf.entries[i].idx);
+            }
+        }
+        // 4. Recompute min_notional from sanitized entries
+        buf.recompute_min();
+        // 5. Clamp scan_cursor
+        if buf.scan_cursor as usize >= percolator::MAX_ACCOUNTS {
+            buf.scan_cursor = 0;
+        }
+        buf
+    }
+
+    pub fn write_risk_buffer(data: &mut [u8], buf: &crate::risk_buffer::RiskBuffer) {
+        use crate::constants::RISK_BUF_LEN;
+        use crate::constants::RISK_BUF_OFF;
+        let src = bytemuck::bytes_of(buf);
+        data[RISK_BUF_OFF..RISK_BUF_OFF + RISK_BUF_LEN].copy_from_slice(src);
+    }
+
+    /// Word `w` of the open-position bitmap, given the region's bytes
+    /// (`POSITION_BITMAP_LEN` long, e.g. from `zc::SlabRegions::region`).
+    pub fn position_word(region: &[u8], w: usize) -> u64 {
+        u64::from_le_bytes(region[w * 8..w * 8 + 8].try_into().unwrap())
+    }
+
+    pub fn has_position_bit(data: &[u8], idx: u16) -> bool {
+        let i = idx as usize;
+        if i >= percolator::MAX_ACCOUNTS {
+            return false;
+        }
+        data[crate::constants::POSITION_BITMAP_OFF + i / 8] & (1 << (i % 8)) != 0
+    }
+
+    pub fn set_position_bit(data: &mut [u8], idx: u16, open: bool) {
+        let i = idx as usize;
+        if i >= percolator::MAX_ACCOUNTS {
+            return;
+        }
+        let b = &mut data[crate::constants::POSITION_BITMAP_OFF + i / 8];
+        if open {
+            *b |= 1 << (i % 8);
+        } else {
+            *b &= !(1 << (i % 8));
+        }
+    }
+
+    /// Liquidation watch index header; the bitmap follows at +24.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct LiqWatch {
+        /// Price the band is centred on, 0 = never anchored.
+        pub anchor_price_e6: u64,
+        pub anchor_slot: u64,
+        /// Next account index the crank re-checks.
+        pub cursor: u64,
+    }
+
+    pub fn read_liq_watch(data: &[u8]) -> LiqWatch {
+        let off = crate::constants::LIQ_WATCH_OFF;
+        let u64_at = |o: usize| u64::from_le_bytes(data[off + o..off + o + 8].try_into().unwrap());
+        LiqWatch {
+            anchor_price_e6: u64_at(0),
+            anchor_slot: u64_at(8),
+            cursor: u64_at(16),
+        }
+    }
+
+    pub fn write_liq_watch(data: &mut [u8], w: &LiqWatch) {
+        let off = crate::constants::LIQ_WATCH_OFF;
+        for (i, v) in [w.anchor_price_e6, w.anchor_slot, w.cursor]
+            .iter()
+            .enumerate()
+        {
+            data[off + i * 8..off + i * 8 + 8].copy_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    pub fn is_watched(data: &[u8], idx: u16) -> bool {
+        let i = idx as usize;
+        if i >= percolator::MAX_ACCOUNTS {
+            return false;
+        }
+        data[crate::constants::LIQ_WATCH_OFF + 24 + i / 8] & (1 << (i % 8)) != 0
+    }
+
+    pub fn set_watched(data: &mut [u8], idx: u16, watched: bool) {
+        let i = idx as usize;
+        if i >= percolator::MAX_ACCOUNTS {
+            return;
+        }
+        let b = &mut data[crate::constants::LIQ_WATCH_OFF + 24 + i / 8];
+        if watched {
+            *b |= 1 << (i % 8);
+        } else {
+            *b &= !(1 << (i % 8));
+        }
+    }
+
+    /// Read per-account materialization generation (u64).
+    /// Returns 0 for never-materialized slots (zero-initialized slab).
+    pub fn read_account_generation(data: &[u8], idx: u16) -> u64 {
+        let off = crate::constants::GEN_TABLE_OFF + (idx as usize) * 8;
+        u64::from_le_bytes(data[off..off + 8].try_into().unwrap())
+    }
+
+    /// Write per-account materialization generation.
+    pub fn write_account_generation(data: &mut [u8], idx: u16, gen: u64) {
+        let off = crate::constants::GEN_TABLE_OFF + (idx as usize) * 8;
+        data[off..off + 8].copy_from_slice(&gen.to_le_bytes());
+    }
+
+    /// Trading-fee routing. Disabled (the default) = every fee stays in
+    /// insurance, as before the split existed.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct FeeSplit {
+        pub enabled: bool,
+        pub insurance_bps: u16,
+        pub treasury_bps: u16,
+    }
+
+    pub fn read_fee_split(data: &[u8]) -> FeeSplit {
+        let off = crate::constants::FEE_SPLIT_OFF;
+        FeeSplit {
+            insurance_bps: u16::from_le_bytes([data[off], data[off + 1]]),
+            treasury_bps: u16::from_le_bytes([data[off + 2], data[off + 3]]),
+            enabled: data[off + 4] != 0,
+        }
+    }
+
+    pub fn write_fee_split(data: &mut [u8], split: &FeeSplit) {
+        let off = crate::constants::FEE_SPLIT_OFF;
+        data[off..off + 2].copy_from_slice(&split.insurance_bps.to_le_bytes());
+        data[off + 2..off + 4].copy_from_slice(&split.treasury_bps.to_le_bytes());
+        data[off + 4] = split.enabled as u8;
+    }
+
+    /// Treasury share of trading fees not yet claimed (engine units). The
+    /// backing tokens sit in `insurance_fund.balance`, like the
+    /// new-account fee accumulator.
+    pub fn read_protocol_fees(data: &[u8]) -> u128 {
+        let off = crate::constants::FEE_SPLIT_OFF + 16;
+        u128::from_le_bytes(data[off..off + 16].try_into().unwrap())
+    }
+
+    pub fn write_protocol_fees(data: &mut [u8], fees: u128) {
+        let off = crate::constants::FEE_SPLIT_OFF + 16;
+        data[off..off + 16].copy_from_slice(&fees.to_le_bytes());
+    }
+
+    pub fn read_fee_authority(data: &[u8]) -> [u8; 32] {
+        let off = crate::constants::FEE_AUTHORITY_OFF;
+        data[off..off + 32].try_into().unwrap()
+    }
+
+    pub fn write_fee_authority(data: &mut [u8], authority: &[u8; 32]) {
+        let off = crate::constants::FEE_AUTHORITY_OFF;
+        data[off..off + 32].copy_from_slice(authority);
+    }
+
+    pub fn read_allowlist_root(data: &[u8]) -> [u8; 32] {
+        let off = crate::constants::ALLOWLIST_ROOT_OFF;
+        data[off..off + 32].try_into().unwrap()
+    }
+
+    pub fn write_allowlist_root(data: &mut [u8], root: &[u8; 32]) {
+        let off = crate::constants::ALLOWLIST_ROOT_OFF;
+        data[off..off + 32].copy_from_slice(root);
+    }
+
+    /// Per-market guarded-launch limits, set by SetAccountLimits.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct AccountLimits {
+        /// Max user-account capital after a deposit. 0 = unlimited.
+        pub max_capital: u128,
+        /// Max user position notional after a position-increasing trade.
+        /// 0 = unlimited.
+        pub max_position_notional: u128,
+    }
+
+    pub fn read_account_limits(data: &[u8]) -> AccountLimits {
+        let off = crate::constants::ACCOUNT_LIMITS_OFF;
+        AccountLimits {
+            max_capital: u128::from_le_bytes(data[off..off + 16].try_into().unwrap()),
+            max_position_notional: u128::from_le_bytes(
+                data[off + 16..off + 32].try_into().unwrap(),
+            ),
+        }
+    }
+
+    pub fn write_account_limits(data: &mut [u8], limits: &AccountLimits) {
+        let off = crate::constants::ACCOUNT_LIMITS_OFF;
+        data[off..off + 16].copy_from_slice(&limits.max_capital.to_le_bytes());
+        data[off + 16..off + 32].copy_from_slice(&limits.max_position_notional.to_le_bytes());
+    }
+
+    /// Dust floors and crank sweep counterparty, set by SetDustLimits.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct DustLimits {
+        /// Min `|size|` of a user fill that does not flatten. 0 = off.
+        pub min_trade_abs: u128,
+        /// Min nonzero user `|position|`. 0 = off, and no crank sweep.
+        pub min_position_abs: u128,
+        /// LP that takes the other side of crank dust sweeps.
+        pub sweep_lp_idx: u16,
+    }
+
+    pub fn read_dust_limits(data: &[u8]) -> DustLimits {
+        let off = crate::constants::DUST_LIMITS_OFF;
+        DustLimits {
+            min_trade_abs: u128::from_le_bytes(data[off..off + 16].try_into().unwrap()),
+            min_position_abs: u128::from_le_bytes(data[off + 16..off + 32].try_into().unwrap()),
+            sweep_lp_idx: u16::from_le_bytes([data[off + 32], data[off + 33]]),
+        }
+    }
+
+    pub fn write_dust_limits(data: &mut [u8], dust: &DustLimits) {
+        let off = crate::constants::DUST_LIMITS_OFF;
+        data[off..off + 16].copy_from_slice(&dust.min_trade_abs.to_le_bytes());
+        data[off + 16..off + 32].copy_from_slice(&dust.min_position_abs.to_le_bytes());
+        data[off + 32..off + 34].copy_from_slice(&dust.sweep_lp_idx.to_le_bytes());
+    }
+
+    /// Price-move circuit breaker: config plus the rolling anchor.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct CircuitBreaker {
+        /// Move from the anchor that trips the breaker. 0 = off.
+        pub max_move_bps: u64,
+        /// Anchor lifetime; the next read after it re-anchors.
+        pub window_slots: u64,
+        pub cooldown_slots: u64,
+        pub anchor_price_e6: u64,
+        pub anchor_slot: u64,
+        /// Growing fills are rejected while `slot < cooling_until_slot`.
+        pub cooling_until_slot: u64,
+    }
+
+    impl CircuitBreaker {
+        pub fn cooling(&self, slot: u64) -> bool {
+            slot < self.cooling_until_slot
+        }
+    }
+
+    pub fn read_circuit_breaker(data: &[u8]) -> CircuitBreaker {
+        let off = crate::constants::CIRCUIT_BREAKER_OFF;
+        let word =
+            |i: usize| u64::from_le_bytes(data[off + i * 8..off + i * 8 + 8].try_into().unwrap());
+        CircuitBreaker {
+            max_move_bps: word(0),
+            window_slots: word(1),
+            cooldown_slots: word(2),
+            anchor_price_e6: word(3),
+            anchor_slot: word(4),
+            cooling_until_slot: word(5),
+        }
+    }
+
+    pub fn write_circuit_breaker(data: &mut [u8], cb: &CircuitBreaker) {
+        let off = crate::constants::CIRCUIT_BREAKER_OFF;
+        let words = [
+            cb.max_move_bps,
+            cb.window_slots,
+            cb.cooldown_slots,
+            cb.anchor_price_e6,
+            cb.anchor_slot,
+            cb.cooling_until_slot,
+        ];
+        for (i, w) in words.iter().enumerate() {
+            data[off + i * 8..off + i * 8 + 8].copy_from_slice(&w.to_le_bytes());
+        }
+    }
+
+    /// Trade rate limit and one account's last fill slot.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct TradeRate {
+        /// Min slots between a user's fills. 0 = off.
+        pub min_slots_between_trades: u64,
+        /// Slot of the account's last fill, 0 = never.
+        pub last_trade_slot: u64,
+    }
+
+    pub fn read_trade_rate(data: &[u8], user_idx: u16) -> TradeRate {
+        let off = crate::constants::TRADE_RATE_OFF;
+        let last_trade_slot = if (user_idx as usize) < percolator::MAX_ACCOUNTS {
+            let o = off + 16 + user_idx as usize * 8;
+            u64::from_le_bytes(data[o..o + 8].try_into().unwrap())
+        } else {
+            0
+        };
+        TradeRate {
+            min_slots_between_trades: u64::from_le_bytes(data[off..off + 8].try_into().unwrap()),
+            last_trade_slot,
+        }
+    }
+
+    pub fn write_min_trade_interval(data: &mut [u8], min_slots_between_trades: u64) {
+        let off = crate::constants::TRADE_RATE_OFF;
+        data[off..off + 8].copy_from_slice(&min_slots_between_trades.to_le_bytes());
+    }
+
+    pub fn write_last_trade_slot(data: &mut [u8], idx: u16, slot: u64) {
+        let o = crate::constants::TRADE_RATE_OFF + 16 + idx as usize * 8;
+        data[o..o + 8].copy_from_slice(&slot.to_le_bytes());
+    }
+
+    /// Permissioned liquidation window and the keepers it admits.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct LiquidatorWindow {
+        /// Slots after an account is flagged during which only
+        /// `liquidators` may liquidate it. 0 = off.
+        pub window_slots: u64,
+        pub len: u8,
+        pub liquidators: [[u8; 32]; crate::constants::MAX_LIQUIDATORS],
+    }
+
+    impl LiquidatorWindow {
+        pub fn active(&self) -> bool {
+            self.window_slots != 0
+        }
+
+        pub fn is_liquidator(&self, key: &[u8; 32]) -> bool {
+            self.liquidators[..self.len as usize].contains(key)
+        }
+    }
+
+    pub fn read_liquidator_window(data: &[u8]) -> LiquidatorWindow {
+        let off = crate::constants::LIQ_WINDOW_OFF;
+        let mut w = LiquidatorWindow {
+            window_slots: u64::from_le_bytes(data[off..off + 8].try_into().unwrap()),
+            len: data[off + 8].min(crate::constants::MAX_LIQUIDATORS as u8),
+            ..LiquidatorWindow::default()
+        };
+        for (i, key) in w.liquidators.iter_mut().enumerate() {
+            let o = off + 16 + i * 32;
+            *key = data[o..o + 32].try_into().unwrap();
+        }
+        w
+    }
+
+    pub fn write_liquidator_window(data: &mut [u8], w: &LiquidatorWindow) {
+        let off = crate::constants::LIQ_WINDOW_OFF;
+        data[off..off + 8].copy_from_slice(&w.window_slots.to_le_bytes());
+        data[off + 8] = w.len;
+        for (i, key) in w.liquidators.iter().enumerate() {
+            let o = off + 16 + i * 32;
+            data[o..o + 32].copy_from_slice(key);
+        }
+    }
+
+    fn liq_flag_off(idx: u16) -> usize {
+        crate::constants::LIQ_WINDOW_OFF
+            + 16
+            + crate::constants::MAX_LIQUIDATORS * 32
+            + idx as usize * 8
+    }
+
+    /// Slot the account was first left below maintenance by a crank, 0 if
+    /// not flagged or `idx` is out of range.
+    pub fn read_liq_flag_slot(data: &[u8], idx: u16) -> u64 {
+        if idx as usize >= percolator::MAX_ACCOUNTS {
+            return 0;
+        }
+        let o = liq_flag_off(idx);
+        u64::from_le_bytes(data[o..o + 8].try_into().unwrap())
+    }
+
+    pub fn write_liq_flag_slot(data: &mut [u8], idx: u16, slot: u64) {
+        let o = liq_flag_off(idx);
+        data[o..o + 8].copy_from_slice(&slot.to_le_bytes());
+    }
+
+    /// Backstop vault settings and share totals.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Backstop {
+        pub vault_idx: u16,
+        pub share_bps: u16,
+        /// Generation of `vault_idx` when the vault account was created; a
+        /// reused slot no longer matches. 0 = no vault account.
+        pub generation: u64,
+        pub rewards_paid: u128,
+        pub total_shares: u128,
+        pub withdraw_delay_slots: u64,
+        /// Payout of a vault account closed after resolution, not yet
+        /// redeemed by its depositors.
+        pub closed_units: u64,
+    }
+
+    impl Backstop {
+        pub fn active(&self) -> bool {
+            self.generation != 0 && self.share_bps != 0
+        }
+
+        /// Delay in force: the stored one, never below the floor.
+        pub fn effective_withdraw_delay(&self) -> u64 {
+            self.withdraw_delay_slots
+                .max(crate::constants::MIN_BACKSTOP_WITHDRAW_DELAY_SLOTS)
+        }
+    }
+
+    pub fn read_backstop(data: &[u8]) -> Backstop {
+        let off = crate::constants::BACKSTOP_OFF;
+        Backstop {
+            vault_idx: u16::from_le_bytes([data[off], data[off + 1]]),
+            share_bps: u16::from_le_bytes([data[off + 2], data[off + 3]]),
+            generation: u64::from_le_bytes(data[off + 8..off + 16].try_into().unwrap()),
+            rewards_paid: u128::from_le_bytes(data[off + 16..off + 32].try_into().unwrap()),
+            total_shares: u128::from_le_bytes(data[off + 32..off + 48].try_into().unwrap()),
+            withdraw_delay_slots: u64::from_le_bytes(data[off + 48..off + 56].try_into().unwrap()),
+            closed_units: u64::from_le_bytes(data[off + 56..off + 64].try_into().unwrap()),
+        }
+    }
+
+    pub fn write_backstop(data: &mut [u8], b: &Backstop) {
+        let off = crate::constants::BACKSTOP_OFF;
+        data[off..off + 2].copy_from_slice(&b.vault_idx.to_le_bytes());
+        data[off + 2..off + 4].copy_from_slice(&b.share_bps.to_le_bytes());
+        data[off + 8..off + 16].copy_from_slice(&b.generation.to_le_bytes());
+        data[off + 16..off + 32].copy_from_slice(&b.rewards_paid.to_le_bytes());
+        data[off + 32..off + 48].copy_from_slice(&b.total_shares.to_le_bytes());
+        data[off + 48..off + 56].copy_from_slice(&b.withdraw_delay_slots.to_le_bytes());
+        data[off + 56..off + 64].copy_from_slice(&b.closed_units.to_le_bytes());
+    }
+
+    /// One backstop depositor. An entry with no shares and nothing
+    /// pending is free.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct BackstopDepositor {
+        pub owner: [u8; 32],
+        pub shares: u128,
+        pub pending_shares: u128,
+        pub request_slot: u64,
+    }
+
+    impl BackstopDepositor {
+        pub fn is_free(&self) -> bool {
+            self.shares == 0 && self.pending_shares == 0
+        }
+    }
+
+    fn backstop_depositor_off(i: usize) -> usize {
+        crate::constants::BACKSTOP_OFF + 64 + i * crate::constants::BACKSTOP_DEPOSITOR_LEN
+    }
+
+    pub fn read_backstop_depositor(data: &[u8], i: usize) -> BackstopDepositor {
+        let o = backstop_depositor_off(i);
+        BackstopDepositor {
+            owner: data[o..o + 32].try_into().unwrap(),
+            shares: u128::from_le_bytes(data[o + 32..o + 48].try_into().unwrap()),
+            pending_shares: u128::from_le_bytes(data[o + 48..o + 64].try_into().unwrap()),
+            request_slot: u64::from_le_bytes(data[o + 64..o + 72].try_into().unwrap()),
+        }
+    }
+
+    pub fn write_backstop_depositor(data: &mut [u8], i: usize, d: &BackstopDepositor) {
+        let o = backstop_depositor_off(i);
+        data[o..o + 32].copy_from_slice(&d.owner);
+        data[o + 32..o + 48].copy_from_slice(&d.shares.to_le_bytes());
+        data[o + 48..o + 64].copy_from_slice(&d.pending_shares.to_le_bytes());
+        data[o + 64..o + 72].copy_from_slice(&d.request_slot.to_le_bytes());
+    }
+
+    /// `owner`'s entry, else the first free one. `None` when every entry
+    /// belongs to someone else.
+    pub fn find_backstop_depositor(data: &[u8], owner: &[u8; 32]) -> Option<usize> {
+        let mut free = None;
+        for i in 0..crate::constants::MAX_BACKSTOP_DEPOSITORS {
+            let d = read_backstop_depositor(data, i);
+            if d.is_free() {
+                free = free.or(Some(i));
+            } else if &d.owner == owner {
+                return Some(i);
+            }
+        }
+        free
+    }
+
+    /// Zero the depositor table, for a vault that starts over.
+    pub fn clear_backstop_depositors(data: &mut [u8]) {
+        let o = backstop_depositor_off(0);
+        let len =
+            crate::constants::MAX_BACKSTOP_DEPOSITORS * crate::constants::BACKSTOP_DEPOSITOR_LEN;
+        data[o..o + len].fill(0);
+    }
+
+    /// Contents of a RegisterMarket pointer account.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct MarketPointer {
+        pub slab: [u8; 32],
+        pub bump: u8,
+    }
+
+    impl MarketPointer {
+        pub fn encode(&self) -> [u8; crate::constants::MARKET_POINTER_LEN] {
+            let mut out = [0u8; crate::constants::MARKET_POINTER_LEN];
+            out[0..8].copy_from_slice(&crate::constants::MARKET_POINTER_MAGIC);
+            out[8..40].copy_from_slice(&self.slab);
+            out[40] = self.bump;
+            out
+        }
+
+        pub fn decode(data: &[u8]) -> Option<Self> {
+            if data.len() != crate::constants::MARKET_POINTER_LEN
+                || data[0..8] != crate::constants::MARKET_POINTER_MAGIC
+            {
+                return None;
+            }
+            Some(Self {
+                slab: data[8..40].try_into().unwrap(),
+                bump: data[40],
+            })
+        }
+    }
+
+    /// Market labels for explorers and UIs. Informational only: nothing
+    /// on-chain reads them.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct MarketMetadata {
+        /// e.g. `b"SOL-PERP"`, zero padded.
+        pub symbol: [u8; 16],
+        /// e.g. `b"Pyth SOL/USD"`, zero padded.
+        pub oracle_description: [u8; 32],
+        /// sha256 of the project URI; the URI itself lives off-chain.
+        pub uri_hash: [u8; 32],
+        pub base_decimals: u8,
+    }
+
+    impl MarketMetadata {
+        /// Parse `MARKET_METADATA_WIRE_LEN` bytes in storage order.
+        pub fn from_wire(bytes: &[u8]) -> Self {
+            Self {
+                symbol: bytes[0..16].try_into().unwrap(),
+                oracle_description: bytes[16..48].try_into().unwrap(),
+                uri_hash: bytes[48..80].try_into().unwrap(),
+                base_decimals: bytes[80],
+            }
+        }
+
+        pub fn to_wire(&self) -> [u8; crate::constants::MARKET_METADATA_WIRE_LEN] {
+            let mut out = [0u8; crate::constants::MARKET_METADATA_WIRE_LEN];
+            out[0..16].copy_from_slice(&self.symbol);
+            out[16..48].copy_from_slice(&self.oracle_description);
+            out[48..80].copy_from_slice(&self.uri_hash);
+            out[80] = self.base_decimals;
+            out
+        }
+
+        /// The symbol without its padding; empty when unset or malformed.
+        pub fn symbol_str(&self) -> &str {
+            label_str(&self.symbol)
+        }
+
+        pub fn oracle_description_str(&self) -> &str {
+            label_str(&self.oracle_description)
+        }
+    }
+
+    fn label_str(label: &[u8]) -> &str {
+        let len = label.iter().position(|&b| b == 0).unwrap_or(label.len());
+        core::str::from_utf8(&label[..len]).unwrap_or("")
+    }
+
+    pub fn read_market_metadata(data: &[u8]) -> MarketMetadata {
+        let off = crate::constants::MARKET_METADATA_OFF;
+        MarketMetadata::from_wire(&data[off..off + crate::constants::MARKET_METADATA_WIRE_LEN])
+    }
+
+    pub fn write_market_metadata(data: &mut [u8], m: &MarketMetadata) {
+        let off = crate::constants::MARKET_METADATA_OFF;
+        data[off..off + crate::constants::MARKET_METADATA_WIRE_LEN].copy_from_slice(&m.to_wire());
+    }
+
+    /// One Hyperp mark pusher and its push history.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct HyperpPusher {
+        pub key: [u8; 32],
+        /// Slot of the key's last accepted push, 0 = never.
+        pub last_push_slot: u64,
+        pub pushes: u64,
+    }
+
+    impl HyperpPusher {
+        pub fn is_free(&self) -> bool {
+            self.key == [0u8; 32]
+        }
+
+        /// Decode one `HYPERP_PUSHER_LEN`-byte entry.
+        pub fn from_bytes(b: &[u8]) -> Self {
+            Self {
+                key: b[0..32].try_into().unwrap(),
+                last_push_slot: u64::from_le_bytes(b[32..40].try_into().unwrap()),
+                pushes: u64::from_le_bytes(b[40..48].try_into().unwrap()),
+            }
+        }
+    }
+
+    fn hyperp_pusher_off(i: usize) -> usize {
+        crate::constants::HYPERP_PUSHERS_OFF + i * crate::constants::HYPERP_PUSHER_LEN
+    }
+
+    pub fn read_hyperp_pusher(data: &[u8], i: usize) -> HyperpPusher {
+        let o = hyperp_pusher_off(i);
+        HyperpPusher::from_bytes(&data[o..o + crate::constants::HYPERP_PUSHER_LEN])
+    }
+
+    pub fn write_hyperp_pusher(data: &mut [u8], i: usize, p: &HyperpPusher) {
+        let o = hyperp_pusher_off(i);
+        data[o..o + 32].copy_from_slice(&p.key);
+        data[o + 32..o + 40].copy_from_slice(&p.last_push_slot.to_le_bytes());
+        data[o + 40..o + 48].copy_from_slice(&p.pushes.to_le_bytes());
+    }
+
+    /// Entry `key` pushes through: 0 for the mark authority, else the
+    /// extra entry holding it. `None` when `key` may not push, including
+    /// every key once the authority is burned.
+    pub fn find_hyperp_pusher(data: &[u8], authority: &[u8; 32], key: &[u8; 32]) -> Option<usize> {
+        if *authority == [0u8; 32] {
+            return None;
+        }
+        if key == authority {
+            return Some(0);
+        }
+        (1..crate::constants::MAX_HYPERP_PUSHERS).find(|&i| {
+            let p = read_hyperp_pusher(data, i);
+            !p.is_free() && &p.key == key
+        })
+    }
+
+    /// Count an accepted push by `key` in entry `i`. An entry that held a
+    /// different key (the authority rotated) starts over.
+    pub fn record_hyperp_push(data: &mut [u8], i: usize, key: [u8; 32], slot: u64) {
+        let mut p = read_hyperp_pusher(data, i);
+        if p.key != key {
+            p = HyperpPusher {
+                key,
+                ..HyperpPusher::default()
+            };
+        }
+        p.last_push_slot = slot;
+        p.pushes = p.pushes.saturating_add(1);
+        write_hyperp_pusher(data, i, &p);
+    }
+
+    /// Stale-oracle fallback settings.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct StaleFallback {
+        /// Slots without a fresh oracle read before the fallback engages.
+        /// 0 = off.
+        pub grace_slots: u64,
+        pub ramp_slots: u64,
+        pub max_haircut_bps: u16,
+    }
+
+    impl StaleFallback {
+        /// Engaged when the last fresh read is more than `grace_slots` old.
+        pub fn engaged(&self, now_slot: u64, last_good_oracle_slot: u64) -> bool {
+            self.grace_slots != 0
+                && now_slot.saturating_sub(last_good_oracle_slot) > self.grace_slots
+        }
+
+        pub fn haircut_bps(&self, now_slot: u64, last_good_oracle_slot: u64) -> u16 {
+            if self.grace_slots == 0 {
+                return 0;
             }
-            result
+            crate::policy::stale_haircut_bps(
+                now_slot.saturating_sub(last_good_oracle_slot),
+                self.grace_slots,
+                self.ramp_slots,
+                self.max_haircut_bps,
+            )
         }
     }
 
-    fn read_u8(input: &mut &[u8]) -> Result<u8, ProgramError> {
-        let (&val, rest) = input
-            .split_first()
-            .ok_or(ProgramError::InvalidInstructionData)?;
-        *input = rest;
-        Ok(val)
+    pub fn read_stale_fallback(data: &[u8]) -> StaleFallback {
+        let off = crate::constants::STALE_FALLBACK_OFF;
+        StaleFallback {
+            grace_slots: u64::from_le_bytes(data[off..off + 8].try_into().unwrap()),
+            ramp_slots: u64::from_le_bytes(data[off + 8..off + 16].try_into().unwrap()),
+            max_haircut_bps: u16::from_le_bytes([data[off + 16], data[off + 17]]),
+        }
     }
 
-    fn read_u16(input: &mut &[u8]) -> Result<u16, ProgramError> {
-        if input.len() < 2 {
-            return Err(ProgramError::InvalidInstructionData);
+    pub fn write_stale_fallback(data: &mut [u8], f: &StaleFallback) {
+        let off = crate::constants::STALE_FALLBACK_OFF;
+        data[off..off + 8].copy_from_slice(&f.grace_slots.to_le_bytes());
+        data[off + 8..off + 16].copy_from_slice(&f.ramp_slots.to_le_bytes());
+        data[off + 16..off + 18].copy_from_slice(&f.max_haircut_bps.to_le_bytes());
+    }
+
+    /// One market-history sample.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct HistorySample {
+        pub slot: u64,
+        /// Engine oracle price after the crank's accrual.
+        pub price_e6: u64,
+        pub mark_e6: u64,
+        /// Funding rate applied from this slot, saturated to i64.
+        pub funding_rate_e9: i64,
+        pub oi_long_q: u128,
+        pub oi_short_q: u128,
+    }
+
+    /// Per-LP counters, updated on every fill the LP takes and refreshed
+    /// (utilization only) when a crank touches it.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct LpStats {
+        /// Materialization generation of the LP this entry belongs to.
+        pub generation: u64,
+        pub idx: u16,
+        /// Filled notional at execution price.
+        pub volume: u128,
+        /// LP share of trading fees credited under the fee split.
+        pub fees_earned: u128,
+        /// Sum of `policy::lp_spread_pnl` over fills.
+        pub spread_pnl: i128,
+        pub fills: u64,
+        pub last_fill_slot: u64,
+        /// `policy::lp_utilization_bps` as of the last fill or crank touch.
+        pub utilization_bps: u64,
+    }
+
+    pub fn read_lp_stats(data: &[u8], slot: usize) -> LpStats {
+        use crate::constants::{LP_STATS_ENTRY_LEN, LP_STATS_OFF};
+        let off = LP_STATS_OFF + slot * LP_STATS_ENTRY_LEN;
+        let u64_at = |o: usize| u64::from_le_bytes(data[off + o..off + o + 8].try_into().unwrap());
+        let u128_at =
+            |o: usize| u128::from_le_bytes(data[off + o..off + o + 16].try_into().unwrap());
+        LpStats {
+            generation: u64_at(0),
+            idx: u16::from_le_bytes([data[off + 8], data[off + 9]]),
+            volume: u128_at(16),
+            fees_earned: u128_at(32),
+            spread_pnl: u128_at(48) as i128,
+            fills: u64_at(64),
+            last_fill_slot: u64_at(72),
+            utilization_bps: u64_at(80),
         }
-        let (bytes, rest) = input.split_at(2);
-        *input = rest;
-        Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
     }
 
-    fn read_u32(input: &mut &[u8]) -> Result<u32, ProgramError> {
-        if input.len() < 4 {
-            return Err(ProgramError::InvalidInstructionData);
+    pub fn write_lp_stats(data: &mut [u8], slot: usize, stats: &LpStats) {
+        use crate::constants::{LP_STATS_ENTRY_LEN, LP_STATS_OFF};
+        let off = LP_STATS_OFF + slot * LP_STATS_ENTRY_LEN;
+        let e = &mut data[off..off + LP_STATS_ENTRY_LEN];
+        e.fill(0);
+        e[0..8].copy_from_slice(&stats.generation.to_le_bytes());
+        e[8..10].copy_from_slice(&stats.idx.to_le_bytes());
+        e[16..32].copy_from_slice(&stats.volume.to_le_bytes());
+        e[32..48].copy_from_slice(&stats.fees_earned.to_le_bytes());
+        e[48..64].copy_from_slice(&stats.spread_pnl.to_le_bytes());
+        e[64..72].copy_from_slice(&stats.fills.to_le_bytes());
+        e[72..80].copy_from_slice(&stats.last_fill_slot.to_le_bytes());
+        e[80..88].copy_from_slice(&stats.utilization_bps.to_le_bytes());
+    }
+
+    /// Entry for LP `idx`, if one is live.
+    pub fn find_lp_stats(data: &[u8], idx: u16) -> Option<usize> {
+        let gen = read_account_generation(data, idx);
+        (0..crate::constants::LP_STATS_CAP).find(|&slot| {
+            let s = read_lp_stats(data, slot);
+            gen != 0 && s.generation == gen && s.idx == idx
+        })
+    }
+
+    /// The history ring within a full slab. Readers of an account in the
+    /// other (sbf / host) layout slice it from the end instead; tail
+    /// regions sit at the same distance from the end in both.
+    pub fn history_region(data: &[u8]) -> &[u8] {
+        use crate::constants::{HISTORY_LEN, HISTORY_OFF};
+        &data[HISTORY_OFF..HISTORY_OFF + HISTORY_LEN]
+    }
+
+    /// `(head, count)`: next write index and number of valid samples.
+    pub fn read_history_cursor(region: &[u8]) -> (usize, usize) {
+        let head = u32::from_le_bytes(region[0..4].try_into().unwrap()) as usize;
+        let count = u32::from_le_bytes(region[4..8].try_into().unwrap()) as usize;
+        let cap = crate::constants::HISTORY_CAP;
+        (head % cap, count.min(cap))
+    }
+
+    pub fn read_history_sample(region: &[u8], ring_idx: usize) -> HistorySample {
+        let off = 16 + ring_idx * crate::constants::HISTORY_SAMPLE_LEN;
+        let u64_at =
+            |o: usize| u64::from_le_bytes(region[off + o..off + o + 8].try_into().unwrap());
+        HistorySample {
+            slot: u64_at(0),
+            price_e6: u64_at(8),
+            mark_e6: u64_at(16),
+            funding_rate_e9: u64_at(24) as i64,
+            oi_long_q: u128::from_le_bytes(region[off + 32..off + 48].try_into().unwrap()),
+            oi_short_q: u128::from_le_bytes(region[off + 48..off + 64].try_into().unwrap()),
         }
-        let (bytes, rest) = input.split_at(4);
-        *input = rest;
-        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
     }
 
-    fn read_u64(input: &mut &[u8]) -> Result<u64, ProgramError> {
-        if input.len() < 8 {
-            return Err(ProgramError::InvalidInstructionData);
-        }
-        let (bytes, rest) = input.split_at(8);
-        *input = rest;
-        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    /// The `i`-th oldest sample, `i < count`.
+    pub fn history_sample(region: &[u8], i: usize) -> HistorySample {
+        let (head, count) = read_history_cursor(region);
+        let cap = crate::constants::HISTORY_CAP;
+        read_history_sample(
+            region,
+            crate::policy::history_ring_index(head, count, cap, i),
+        )
+    }
+
+    /// Append `sample`, overwriting the oldest once the ring is full.
+    pub fn push_history_sample(data: &mut [u8], sample: &HistorySample) {
+        use crate::constants::{HISTORY_CAP, HISTORY_LEN, HISTORY_OFF, HISTORY_SAMPLE_LEN};
+        let region = &mut data[HISTORY_OFF..HISTORY_OFF + HISTORY_LEN];
+        let (head, count) = read_history_cursor(region);
+        let off = 16 + head * HISTORY_SAMPLE_LEN;
+        let s = &mut region[off..off + HISTORY_SAMPLE_LEN];
+        s[0..8].copy_from_slice(&sample.slot.to_le_bytes());
+        s[8..16].copy_from_slice(&sample.price_e6.to_le_bytes());
+        s[16..24].copy_from_slice(&sample.mark_e6.to_le_bytes());
+        s[24..32].copy_from_slice(&sample.funding_rate_e9.to_le_bytes());
+        s[32..48].copy_from_slice(&sample.oi_long_q.to_le_bytes());
+        s[48..64].copy_from_slice(&sample.oi_short_q.to_le_bytes());
+        let head = (head + 1) % HISTORY_CAP;
+        let count = (count + 1).min(HISTORY_CAP);
+        region[0..4].copy_from_slice(&(head as u32).to_le_bytes());
+        region[4..8].copy_from_slice(&(count as u32).to_le_bytes());
+    }
+
+    /// Crank-liveness guard for the emergency withdraw-only mode.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct CrankGuard {
+        /// From InitMarket. 0 = EnterWithdrawOnly is never available.
+        pub max_crank_staleness_slots: u64,
+        /// Slot of the last KeeperCrank on a live market (InitMarket slot
+        /// before the first one).
+        pub last_crank_slot: u64,
+        pub withdraw_only: bool,
+    }
+
+    pub fn read_crank_guard(data: &[u8]) -> CrankGuard {
+        let off = crate::constants::CRANK_GUARD_OFF;
+        CrankGuard {
+            max_crank_staleness_slots: u64::from_le_bytes(data[off..off + 8].try_into().unwrap()),
+            last_crank_slot: u64::from_le_bytes(data[off + 8..off + 16].try_into().unwrap()),
+            withdraw_only: data[off + 16] != 0,
+        }
+    }
+
+    pub fn write_crank_guard(data: &mut [u8], guard: &CrankGuard) {
+        let off = crate::constants::CRANK_GUARD_OFF;
+        data[off..off + 8].copy_from_slice(&guard.max_crank_staleness_slots.to_le_bytes());
+        data[off + 8..off + 16].copy_from_slice(&guard.last_crank_slot.to_le_bytes());
+        data[off + 16] = guard.withdraw_only as u8;
+    }
+
+    /// M-of-N admin key set. `threshold == 0` means single-key admin.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct AdminMultisig {
+        pub threshold: u8,
+        pub len: u8,
+        pub signers: [[u8; 32]; crate::constants::MAX_ADMIN_SIGNERS],
+    }
+
+    impl AdminMultisig {
+        pub fn active(&self) -> bool {
+            self.threshold != 0
+        }
+
+        pub fn signer_index(&self, key: &[u8; 32]) -> Option<usize> {
+            self.signers[..self.len as usize]
+                .iter()
+                .position(|k| k == key)
+        }
+    }
+
+    pub fn read_admin_multisig(data: &[u8]) -> AdminMultisig {
+        let off = crate::constants::ADMIN_MULTISIG_OFF;
+        let mut ms = AdminMultisig {
+            threshold: data[off],
+            len: data[off + 1].min(crate::constants::MAX_ADMIN_SIGNERS as u8),
+            ..AdminMultisig::default()
+        };
+        for (i, key) in ms.signers.iter_mut().enumerate() {
+            let o = off + 8 + i * 32;
+            *key = data[o..o + 32].try_into().unwrap();
+        }
+        ms
+    }
+
+    pub fn write_admin_multisig(data: &mut [u8], ms: &AdminMultisig) {
+        let off = crate::constants::ADMIN_MULTISIG_OFF;
+        data[off] = ms.threshold;
+        data[off + 1] = ms.len;
+        for (i, key) in ms.signers.iter().enumerate() {
+            let o = off + 8 + i * 32;
+            data[o..o + 32].copy_from_slice(key);
+        }
+    }
+
+    pub fn read_pending_admin(data: &[u8]) -> [u8; 32] {
+        let off = crate::constants::PENDING_ADMIN_OFF;
+        data[off..off + 32].try_into().unwrap()
+    }
+
+    pub fn write_pending_admin(data: &mut [u8], admin: &[u8; 32]) {
+        let off = crate::constants::PENDING_ADMIN_OFF;
+        data[off..off + 32].copy_from_slice(admin);
+    }
+
+    /// One admin action collecting approvals. An all-zero hash is a free
+    /// slot.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct PendingAdminAction {
+        pub action_hash: [u8; 32],
+        pub approvals: u8,
+        pub created_slot: u64,
+    }
+
+    pub fn read_pending_admin_action(data: &[u8], i: usize) -> PendingAdminAction {
+        use crate::constants::{PENDING_ADMIN_ACTIONS_OFF, PENDING_ADMIN_ACTION_LEN};
+        let off = PENDING_ADMIN_ACTIONS_OFF + i * PENDING_ADMIN_ACTION_LEN;
+        PendingAdminAction {
+            action_hash: data[off..off + 32].try_into().unwrap(),
+            approvals: data[off + 32],
+            created_slot: u64::from_le_bytes(data[off + 40..off + 48].try_into().unwrap()),
+        }
+    }
+
+    pub fn write_pending_admin_action(data: &mut [u8], i: usize, action: &PendingAdminAction) {
+        use crate::constants::{PENDING_ADMIN_ACTIONS_OFF, PENDING_ADMIN_ACTION_LEN};
+        let off = PENDING_ADMIN_ACTIONS_OFF + i * PENDING_ADMIN_ACTION_LEN;
+        data[off..off + 32].copy_from_slice(&action.action_hash);
+        data[off + 32] = action.approvals;
+        data[off + 40..off + 48].copy_from_slice(&action.created_slot.to_le_bytes());
+    }
+
+    /// Competition epoch clock.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct EpochConfig {
+        /// Epoch length in slots. 0 = the current epoch never ends.
+        pub epoch_slots: u64,
+        pub epoch: u64,
+        pub start_slot: u64,
+    }
+
+    pub fn read_epoch_config(data: &[u8]) -> EpochConfig {
+        let off = crate::constants::EPOCH_CONFIG_OFF;
+        let u64_at = |o: usize| u64::from_le_bytes(data[off + o..off + o + 8].try_into().unwrap());
+        EpochConfig {
+            epoch_slots: u64_at(0),
+            epoch: u64_at(8),
+            start_slot: u64_at(16),
+        }
+    }
+
+    pub fn write_epoch_config(data: &mut [u8], cfg: &EpochConfig) {
+        let off = crate::constants::EPOCH_CONFIG_OFF;
+        data[off..off + 8].copy_from_slice(&cfg.epoch_slots.to_le_bytes());
+        data[off + 8..off + 16].copy_from_slice(&cfg.epoch.to_le_bytes());
+        data[off + 16..off + 24].copy_from_slice(&cfg.start_slot.to_le_bytes());
+    }
+
+    /// Per-account epoch counters, in engine units (saturating 64-bit).
+    /// Current-epoch PnL is `policy::epoch_pnl(capital + pnl,
+    /// start_equity, net_flow)`; it is only materialized into `last_pnl`
+    /// when the account rolls into a later epoch.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct EpochStats {
+        /// Epoch the `start_equity` / `net_flow` / `volume` fields track.
+        pub epoch: u64,
+        /// Booked equity (capital + pnl) when the epoch was opened.
+        pub start_equity: i64,
+        /// Deposits minus withdrawals during the epoch.
+        pub net_flow: i64,
+        /// Filled notional at execution price during the epoch.
+        pub volume: u64,
+        /// Totals for the epoch this account was last active in before
+        /// `epoch`.
+        pub last_epoch: u64,
+        pub last_pnl: i64,
+        pub last_volume: u64,
+    }
+
+    pub fn read_epoch_stats(data: &[u8], idx: u16) -> EpochStats {
+        use crate::constants::{EPOCH_STATS_ENTRY_LEN, EPOCH_STATS_TABLE_OFF};
+        let off = EPOCH_STATS_TABLE_OFF + (idx as usize) * EPOCH_STATS_ENTRY_LEN;
+        let b8 = |o: usize| -> [u8; 8] { data[off + o..off + o + 8].try_into().unwrap() };
+        EpochStats {
+            epoch: u64::from_le_bytes(b8(0)),
+            start_equity: i64::from_le_bytes(b8(8)),
+            net_flow: i64::from_le_bytes(b8(16)),
+            volume: u64::from_le_bytes(b8(24)),
+            last_epoch: u64::from_le_bytes(b8(32)),
+            last_pnl: i64::from_le_bytes(b8(40)),
+            last_volume: u64::from_le_bytes(b8(48)),
+        }
+    }
+
+    pub fn write_epoch_stats(data: &mut [u8], idx: u16, stats: &EpochStats) {
+        use crate::constants::{EPOCH_STATS_ENTRY_LEN, EPOCH_STATS_TABLE_OFF};
+        let off = EPOCH_STATS_TABLE_OFF + (idx as usize) * EPOCH_STATS_ENTRY_LEN;
+        let fields: [[u8; 8]; 7] = [
+            stats.epoch.to_le_bytes(),
+            stats.start_equity.to_le_bytes(),
+            stats.net_flow.to_le_bytes(),
+            stats.volume.to_le_bytes(),
+            stats.last_epoch.to_le_bytes(),
+            stats.last_pnl.to_le_bytes(),
+            stats.last_volume.to_le_bytes(),
+        ];
+        for (i, f) in fields.iter().enumerate() {
+            data[off + i * 8..off + i * 8 + 8].copy_from_slice(f);
+        }
+    }
+
+    /// Market funding index for the PnL ledger, advanced by fills and
+    /// cranks (see `policy::funding_index_advance`).
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct FundingIndex {
+        pub index: i128,
+        /// Slot the index was last advanced to; 0 = never.
+        pub slot: u64,
+    }
+
+    pub fn read_funding_index(data: &[u8]) -> FundingIndex {
+        let off = crate::constants::PNL_LEDGER_OFF;
+        FundingIndex {
+            index: i128::from_le_bytes(data[off..off + 16].try_into().unwrap()),
+            slot: u64::from_le_bytes(data[off + 16..off + 24].try_into().unwrap()),
+        }
+    }
+
+    pub fn write_funding_index(data: &mut [u8], idx: &FundingIndex) {
+        let off = crate::constants::PNL_LEDGER_OFF;
+        data[off..off + 16].copy_from_slice(&idx.index.to_le_bytes());
+        data[off + 16..off + 24].copy_from_slice(&idx.slot.to_le_bytes());
+    }
+
+    /// Per-account PnL ledger, in engine units (saturating 64-bit), kept
+    /// beside the engine's mark-to-market so statements need no fill
+    /// history. Updated for both sides of every TradeNoCpi / TradeCpi
+    /// fill, and by KeeperCrank for the liquidations, ADL and dust sweeps
+    /// it applies; unrealized PnL is `policy::unrealized_pnl` of the basis.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct PnlLedger {
+        /// Position and average entry as of the account's last fill.
+        pub basis: crate::policy::CostBasis,
+        /// Funding index when funding was last settled into
+        /// `funding_paid`.
+        pub funding_index: i128,
+        /// PnL realized by fills against the entry price.
+        pub realized_pnl: i64,
+        /// The account's half of each fill's trading fee.
+        pub fees_paid: u64,
+        /// Funding paid on the ledger position; negative when received.
+        pub funding_paid: i64,
+    }
+
+    impl PnlLedger {
+        /// Parse one `PNL_LEDGER_ENTRY_LEN` entry in storage order.
+        pub fn from_bytes(bytes: &[u8]) -> Self {
+            let b8 = |o: usize| -> [u8; 8] { bytes[o..o + 8].try_into().unwrap() };
+            let b16 = |o: usize| -> [u8; 16] { bytes[o..o + 16].try_into().unwrap() };
+            Self {
+                basis: crate::policy::CostBasis {
+                    position_q: i128::from_le_bytes(b16(0)),
+                    entry_price_e6: u64::from_le_bytes(b8(32)),
+                },
+                funding_index: i128::from_le_bytes(b16(16)),
+                realized_pnl: i64::from_le_bytes(b8(40)),
+                fees_paid: u64::from_le_bytes(b8(48)),
+                funding_paid: i64::from_le_bytes(b8(56)),
+            }
+        }
+
+        pub fn to_bytes(&self) -> [u8; crate::constants::PNL_LEDGER_ENTRY_LEN] {
+            let mut out = [0u8; crate::constants::PNL_LEDGER_ENTRY_LEN];
+            out[0..16].copy_from_slice(&self.basis.position_q.to_le_bytes());
+            out[16..32].copy_from_slice(&self.funding_index.to_le_bytes());
+            out[32..40].copy_from_slice(&self.basis.entry_price_e6.to_le_bytes());
+            out[40..48].copy_from_slice(&self.realized_pnl.to_le_bytes());
+            out[48..56].copy_from_slice(&self.fees_paid.to_le_bytes());
+            out[56..64].copy_from_slice(&self.funding_paid.to_le_bytes());
+            out
+        }
+    }
+
+    fn pnl_ledger_off(idx: u16) -> usize {
+        use crate::constants::{PNL_LEDGER_ENTRY_LEN, PNL_LEDGER_OFF};
+        PNL_LEDGER_OFF + 32 + (idx as usize) * PNL_LEDGER_ENTRY_LEN
+    }
+
+    pub fn read_pnl_ledger(data: &[u8], idx: u16) -> PnlLedger {
+        let off = pnl_ledger_off(idx);
+        PnlLedger::from_bytes(&data[off..off + crate::constants::PNL_LEDGER_ENTRY_LEN])
+    }
+
+    pub fn write_pnl_ledger(data: &mut [u8], idx: u16, ledger: &PnlLedger) {
+        let off = pnl_ledger_off(idx);
+        data[off..off + crate::constants::PNL_LEDGER_ENTRY_LEN].copy_from_slice(&ledger.to_bytes());
+    }
+
+    /// Read an account's replay nonce (next expected value).
+    pub fn read_user_nonce(data: &[u8], idx: u16) -> u64 {
+        let off = crate::constants::USER_NONCE_TABLE_OFF + (idx as usize) * 8;
+        u64::from_le_bytes(data[off..off + 8].try_into().unwrap())
     }
 
-    fn read_i64(input: &mut &[u8]) -> Result<i64, ProgramError> {
-        if input.len() < 8 {
-            return Err(ProgramError::InvalidInstructionData);
-        }
-        let (bytes, rest) = input.split_at(8);
-        *input = rest;
-        Ok(i64::from_le_bytes(bytes.try_into().unwrap()))
+    pub fn write_user_nonce(data: &mut [u8], idx: u16, nonce: u64) {
+        let off = crate::constants::USER_NONCE_TABLE_OFF + (idx as usize) * 8;
+        data[off..off + 8].copy_from_slice(&nonce.to_le_bytes());
     }
 
-    fn read_i128(input: &mut &[u8]) -> Result<i128, ProgramError> {
-        if input.len() < 16 {
-            return Err(ProgramError::InvalidInstructionData);
+    /// Read an account's recent client order ids, newest first.
+    pub fn read_order_ids(data: &[u8], idx: u16) -> [u64; crate::constants::ORDER_ID_RING] {
+        use crate::constants::{ORDER_ID_RING, ORDER_ID_TABLE_OFF};
+        let base = ORDER_ID_TABLE_OFF + (idx as usize) * ORDER_ID_RING * 8;
+        let mut ring = [0u64; ORDER_ID_RING];
+        for (i, id) in ring.iter_mut().enumerate() {
+            let off = base + i * 8;
+            *id = u64::from_le_bytes(data[off..off + 8].try_into().unwrap());
         }
-        let (bytes, rest) = input.split_at(16);
-        *input = rest;
-        Ok(i128::from_le_bytes(bytes.try_into().unwrap()))
+        ring
     }
 
-    fn read_u128(input: &mut &[u8]) -> Result<u128, ProgramError> {
-        if input.len() < 16 {
-            return Err(ProgramError::InvalidInstructionData);
+    /// Write an account's order-id ring. Zeroed when a slot is
+    /// materialized so ids never carry over to a reused index.
+    pub fn write_order_ids(
+        data: &mut [u8],
+        idx: u16,
+        ring: &[u64; crate::constants::ORDER_ID_RING],
+    ) {
+        use crate::constants::{ORDER_ID_RING, ORDER_ID_TABLE_OFF};
+        let base = ORDER_ID_TABLE_OFF + (idx as usize) * ORDER_ID_RING * 8;
+        for (i, id) in ring.iter().enumerate() {
+            let off = base + i * 8;
+            data[off..off + 8].copy_from_slice(&id.to_le_bytes());
         }
-        let (bytes, rest) = input.split_at(16);
-        *input = rest;
-        Ok(u128::from_le_bytes(bytes.try_into().unwrap()))
     }
 
-    fn read_pubkey(input: &mut &[u8]) -> Result<Pubkey, ProgramError> {
-        if input.len() < 32 {
-            return Err(ProgramError::InvalidInstructionData);
+    /// Absolute slab byte offsets for fields read by off-chain clients.
+    ///
+    /// Header and config contain no implicit padding, so their offsets are
+    /// the same on every target and are asserted against literals here.
+    /// The engine region is not: `u128` aligns to 16 on x86_64 and 8 on
+    /// sbf. Host-side readers of on-chain slabs must use [`sbf`], whose
+    /// literals are checked against `offset_of!` whenever the program is
+    /// built for sbf, so a layout change fails the deploy build instead of
+    /// silently skewing clients. All integers are little-endian.
+    pub mod offsets {
+        use super::{MarketConfig, SlabHeader};
+        use crate::constants::HEADER_LEN;
+        use core::mem::offset_of;
+
+        pub const MAGIC: usize = offset_of!(SlabHeader, magic);
+        pub const VERSION: usize = offset_of!(SlabHeader, version);
+        pub const ADMIN: usize = offset_of!(SlabHeader, admin);
+        pub const INSURANCE_AUTHORITY: usize = offset_of!(SlabHeader, insurance_authority);
+        pub const INSURANCE_OPERATOR: usize = offset_of!(SlabHeader, insurance_operator);
+
+        pub const CONFIG: usize = HEADER_LEN;
+        pub const COLLATERAL_MINT: usize = CONFIG + offset_of!(MarketConfig, collateral_mint);
+        pub const VAULT_PUBKEY: usize = CONFIG + offset_of!(MarketConfig, vault_pubkey);
+        pub const INDEX_FEED_ID: usize = CONFIG + offset_of!(MarketConfig, index_feed_id);
+        pub const INVERT: usize = CONFIG + offset_of!(MarketConfig, invert);
+        pub const UNIT_SCALE: usize = CONFIG + offset_of!(MarketConfig, unit_scale);
+        pub const HYPERP_AUTHORITY: usize = CONFIG + offset_of!(MarketConfig, hyperp_authority);
+        /// u64, last pushed Hyperp mark (engine-space e6).
+        pub const HYPERP_MARK_E6: usize = CONFIG + offset_of!(MarketConfig, hyperp_mark_e6);
+        /// u64, index price last fed to the engine (e6).
+        pub const LAST_EFFECTIVE_PRICE_E6: usize =
+            CONFIG + offset_of!(MarketConfig, last_effective_price_e6);
+        /// u64, trade-derived mark price used for funding (e6).
+        pub const MARK_EWMA_E6: usize = CONFIG + offset_of!(MarketConfig, mark_ewma_e6);
+        pub const MARK_EWMA_LAST_SLOT: usize =
+            CONFIG + offset_of!(MarketConfig, mark_ewma_last_slot);
+
+        const _: () = assert!(MAGIC == 0 && VERSION == 8 && ADMIN == 16);
+        const _: () = assert!(INSURANCE_AUTHORITY == 72 && INSURANCE_OPERATOR == 104);
+        const _: () = assert!(CONFIG == 136);
+        const _: () = assert!(COLLATERAL_MINT == 136 && VAULT_PUBKEY == 168);
+        const _: () = assert!(INDEX_FEED_ID == 200);
+        const _: () = assert!(INVERT == 323 && UNIT_SCALE == 324);
+        const _: () = assert!(HYPERP_AUTHORITY == 360 && HYPERP_MARK_E6 == 392);
+        const _: () = assert!(LAST_EFFECTIVE_PRICE_E6 == 408);
+        const _: () = assert!(MARK_EWMA_E6 == 536 && MARK_EWMA_LAST_SLOT == 544);
+
+        /// On-chain (sbf) engine offsets, as absolute slab offsets except
+        /// where named `*_IN_ACCOUNT`. Account `i` starts at
+        /// `ACCOUNTS + i * ACCOUNT_SIZE`.
+        ///
+        /// Only fields whose sbf position has been observed against the
+        /// compiled program are listed. Open interest (`oi_eff_long_q` /
+        /// `oi_eff_short_q`) is not pinned yet; readers that need it must
+        /// deserialize through the engine type on a matching target.
+        pub mod sbf {
+            use crate::constants::{GEN_TABLE_LEN, RISK_BUF_LEN, TAIL_LEN};
+
+            pub const ENGINE: usize = 664;
+            /// U128 engine vault balance.
+            pub const VAULT: usize = ENGINE;
+            /// U128 insurance fund balance (first field of `insurance_fund`).
+            pub const INSURANCE_BALANCE: usize = ENGINE + 16;
+            pub const PARAMS: usize = ENGINE + 32;
+            pub const RESOLVED_PRICE: usize = ENGINE + 216;
+            /// U128 sum of user capital.
+            pub const C_TOT: usize = ENGINE + 312;
+            pub const SWEEP_GENERATION: usize = ENGINE + 848;
+            /// u64, engine mark/oracle price (e6).
+            pub const LAST_ORACLE_PRICE: usize = ENGINE + 1000;
+            pub const LAST_MARKET_SLOT: usize = ENGINE + 1016;
+            /// `[u64; MAX_ACCOUNTS / 64]` used-slot bitmap.
+            pub const USED_BITMAP: usize = ENGINE + 1088;
+
+            #[cfg(all(feature = "small", not(feature = "medium")))]
+            pub const NUM_USED_ACCOUNTS: usize = ENGINE + 1120;
+            #[cfg(all(feature = "small", not(feature = "medium")))]
+            pub const ACCOUNTS: usize = ENGINE + 2152;
+            #[cfg(all(feature = "small", not(feature = "medium")))]
+            pub const SLAB_LEN: usize = 177128;
+
+            #[cfg(all(feature = "medium", not(feature = "small")))]
+            pub const NUM_USED_ACCOUNTS: usize = ENGINE + 1216;
+            #[cfg(all(feature = "medium", not(feature = "small")))]
+            pub const ACCOUNTS: usize = ENGINE + 5320;
+            #[cfg(all(feature = "medium", not(feature = "small")))]
+            pub const SLAB_LEN: usize = 641288;
+
+            #[cfg(not(any(feature = "small", feature = "medium")))]
+            pub const NUM_USED_ACCOUNTS: usize = ENGINE + 1600;
+            #[cfg(not(any(feature = "small", feature = "medium")))]
+            pub const ACCOUNTS: usize = ENGINE + 17992;
+            #[cfg(not(any(feature = "small", feature = "medium")))]
+            pub const SLAB_LEN: usize = 2497928;
+
+            /// Length of a version-0 slab, which ends at the generation
+            /// table. Such a slab takes nothing but MigrateSlab.
+            #[cfg(all(feature = "small", not(feature = "medium")))]
+            pub const SLAB_LEN_V0: usize = 111520;
+            #[cfg(all(feature = "medium", not(feature = "small")))]
+            pub const SLAB_LEN_V0: usize = 440320;
+            #[cfg(not(any(feature = "small", feature = "medium")))]
+            pub const SLAB_LEN_V0: usize = 1755520;
+
+            /// Everything after the engine has the same layout on both
+            /// targets: risk buffer, generation table, then the tail.
+            pub const RISK_BUF: usize = SLAB_LEN_V0 - GEN_TABLE_LEN - RISK_BUF_LEN;
+            pub const GEN_TABLE: usize = SLAB_LEN_V0 - GEN_TABLE_LEN;
+            pub const TAIL: usize = SLAB_LEN_V0;
+            const _: () = assert!(TAIL + TAIL_LEN == SLAB_LEN);
+
+            /// Stride of the account array.
+            pub const ACCOUNT_SIZE: usize = 416;
+            pub const CAPITAL_IN_ACCOUNT: usize = 0;
+            pub const KIND_IN_ACCOUNT: usize = 16;
+            /// i128.
+            pub const PNL_IN_ACCOUNT: usize = 24;
+            pub const RESERVED_PNL_IN_ACCOUNT: usize = 40;
+            pub const POSITION_BASIS_Q_IN_ACCOUNT: usize = 56;
+            pub const OWNER_IN_ACCOUNT: usize = 248;
+            pub const FEE_CREDITS_IN_ACCOUNT: usize = 280;
+            pub const LAST_FEE_SLOT_IN_ACCOUNT: usize = 296;
+
+            #[cfg(target_os = "solana")]
+            mod check {
+                use super::*;
+                use crate::constants::{ENGINE_OFF, SLAB_LEN as TARGET_SLAB_LEN, TAIL_OFF};
+                use core::mem::{offset_of, size_of};
+                use percolator::{Account, RiskEngine};
+
+                const _: () = assert!(ENGINE == ENGINE_OFF);
+                const _: () = assert!(SLAB_LEN == TARGET_SLAB_LEN);
+                const _: () = assert!(TAIL == TAIL_OFF);
+                const _: () = assert!(RISK_BUF == ENGINE + size_of::<RiskEngine>());
+                const _: () = assert!(VAULT == ENGINE + offset_of!(RiskEngine, vault));
+                const _: () =
+                    assert!(INSURANCE_BALANCE == ENGINE + offset_of!(RiskEngine, insurance_fund));
+                const _: () = assert!(PARAMS == ENGINE + offset_of!(RiskEngine, params));
+                const _: () =
+                    assert!(RESOLVED_PRICE == ENGINE + offset_of!(RiskEngine, resolved_price));
+                const _: () = assert!(C_TOT == ENGINE + offset_of!(RiskEngine, c_tot));
+                const _: () =
+                    assert!(SWEEP_GENERATION == ENGINE + offset_of!(RiskEngine, sweep_generation));
+                const _: () = assert!(
+                    LAST_ORACLE_PRICE == ENGINE + offset_of!(RiskEngine, last_oracle_price)
+                );
+                const _: () =
+                    assert!(LAST_MARKET_SLOT == ENGINE + offset_of!(RiskEngine, last_market_slot));
+                const _: () = assert!(USED_BITMAP == ENGINE + offset_of!(RiskEngine, used));
+                const _: () = assert!(
+                    NUM_USED_ACCOUNTS == ENGINE + offset_of!(RiskEngine, num_used_accounts)
+                );
+                const _: () = assert!(ACCOUNTS == ENGINE + offset_of!(RiskEngine, accounts));
+                const _: () = assert!(ACCOUNT_SIZE == size_of::<Account>());
+                const _: () = assert!(CAPITAL_IN_ACCOUNT == offset_of!(Account, capital));
+                const _: () = assert!(KIND_IN_ACCOUNT == offset_of!(Account, kind));
+                const _: () = assert!(PNL_IN_ACCOUNT == offset_of!(Account, pnl));
+                const _: () = assert!(RESERVED_PNL_IN_ACCOUNT == offset_of!(Account, reserved_pnl));
+                const _: () =
+                    assert!(POSITION_BASIS_Q_IN_ACCOUNT == offset_of!(Account, position_basis_q));
+                const _: () = assert!(OWNER_IN_ACCOUNT == offset_of!(Account, owner));
+                const _: () = assert!(FEE_CREDITS_IN_ACCOUNT == offset_of!(Account, fee_credits));
+                const _: () =
+                    assert!(LAST_FEE_SLOT_IN_ACCOUNT == offset_of!(Account, last_fee_slot));
+            }
         }
-        let (bytes, rest) = input.split_at(32);
-        *input = rest;
-        Ok(Pubkey::new_from_array(bytes.try_into().unwrap()))
     }
+}
 
-    fn read_bytes32(input: &mut &[u8]) -> Result<[u8; 32], ProgramError> {
-        if input.len() < 32 {
-            return Err(ProgramError::InvalidInstructionData);
+// 7. mod units - base token/units conversion at instruction boundaries
+pub mod units {
+    /// Convert base token amount to units, returning (units, dust).
+    /// Base token is the collateral (e.g., lamports for SOL, satoshis for BTC).
+    /// If scale is 0, returns (base, 0) - no scaling.
+    #[inline]
+    pub fn base_to_units(base: u64, scale: u32) -> (u64, u64) {
+        if scale == 0 {
+            return (base, 0);
         }
-        let (bytes, rest) = input.split_at(32);
-        *input = rest;
-        Ok(bytes.try_into().unwrap())
+        let s = scale as u64;
+        (base / s, base % s)
     }
 
-    fn read_risk_params(input: &mut &[u8]) -> Result<(RiskParams, u128), ProgramError> {
-        let h_min = read_u64(input)?;
-        let maintenance_margin_bps = read_u64(input)?;
-        let initial_margin_bps = read_u64(input)?;
-        let max_trading_fee_bps = read_u64(input)?;
-        let max_accounts = read_u64(input)?;
-        // Wrapper-charged new-account fee (base units). At InitUser/InitLP
-        // the wrapper splits `fee_payment` into two parts: `new_account_fee`
-        // is routed to the insurance fund; the remainder is credited as
-        // account capital. Engine never sees the fee. Zero disables.
-        let new_account_fee = read_u128(input)?;
-        let h_max = read_u64(input)?;
-        let _max_crank_staleness_slots = read_u64(input)?;
-        let liquidation_fee_bps = read_u64(input)?;
-        let liquidation_fee_cap = U128::new(read_u128(input)?);
-        let resolve_price_deviation_bps = read_u64(input)?; // was _liquidation_buffer_bps
-        let min_liquidation_abs = U128::new(read_u128(input)?);
-        if input.len() < 40 {
-            return Err(ProgramError::InvalidInstructionData);
+    /// Convert units to base token amount with overflow check.
+    /// Returns None if overflow would occur.
+    #[inline]
+    pub fn units_to_base_checked(units: u64, scale: u32) -> Option<u64> {
+        if scale == 0 {
+            return Some(units);
         }
-        let min_nonzero_mm_req = read_u128(input)?;
-        let min_nonzero_im_req = read_u128(input)?;
-        // v12.19: per-slot price-move cap (standard bps, 100 = 1%).
-        // Init-immutable per spec §1.4 solvency invariant.
-        let max_price_move_bps_per_slot = read_u64(input)?;
+        units.checked_mul(scale as u64)
+    }
 
-        // §1.4 requires `cfg_max_price_move_bps_per_slot > 0`. Reject before
-        // any engine call so the error surfaces as `InvalidConfigParam`
-        // rather than an engine-side panic.
-        if h_max == 0
-            || h_max < h_min
-            || h_max > crate::constants::MAX_PROFIT_MATURITY_SLOTS
-            || max_price_move_bps_per_slot == 0
-        {
-            return Err(crate::error::PercolatorError::InvalidConfigParam.into());
-        }
+    /// Largest base amount `<= base` that converts to units with zero dust.
+    /// Deposits, InitUser fee payments and withdrawals must be aligned;
+    /// clients round user input through this before building the ix.
+    #[inline]
+    pub fn align_base_down(base: u64, scale: u32) -> u64 {
+        base - base_to_units(base, scale).1
+    }
 
-        let params = RiskParams {
-            maintenance_margin_bps,
-            initial_margin_bps,
-            max_trading_fee_bps,
-            max_accounts,
-            liquidation_fee_bps,
-            liquidation_fee_cap,
-            min_liquidation_abs,
-            min_nonzero_mm_req,
-            min_nonzero_im_req,
-            h_min,
-            h_max,
-            resolve_price_deviation_bps,
-            // Envelope is fixed by wrapper deployment, not market-specified.
-            // See `crate::constants::{MAX_ACCRUAL_DT_SLOTS,
-            // MAX_ABS_FUNDING_E9_PER_SLOT}` for the invariant proof.
-            max_accrual_dt_slots: crate::constants::MAX_ACCRUAL_DT_SLOTS,
-            max_abs_funding_e9_per_slot: crate::constants::MAX_ABS_FUNDING_E9_PER_SLOT,
-            // Active-positions cap per side (§1.4). Mirrors max_accounts —
-            // no tighter per-side limit is enforced by the wrapper today.
-            max_active_positions_per_side: max_accounts,
-            // Cumulative-funding lifetime (§1.4 v12.18.x). Envelope matches
-            // per-call `max_accrual_dt_slots` at minimum; wrapper uses the
-            // same value because wrapper-deployed markets don't configure
-            // a separate cumulative horizon. The engine asserts this at
-            // init_engine_state and at every accrue.
-            min_funding_lifetime_slots: crate::constants::MIN_FUNDING_LIFETIME_SLOTS,
-            max_price_move_bps_per_slot,
-        };
-        Ok((params, new_account_fee))
+    /// Undo the unit-scale step of `policy::to_engine_price`: an
+    /// engine-space price back to collateral base units per e6 of size.
+    /// Inversion is not undone. Truncation in the forward direction is
+    /// lossy, so this returns the price the engine actually used.
+    #[inline]
+    pub fn unscale_price_e6(engine_price_e6: u64, scale: u32) -> Option<u64> {
+        if scale <= 1 {
+            return Some(engine_price_e6);
+        }
+        engine_price_e6.checked_mul(scale as u64)
     }
 }
 
-// 5. mod accounts (Pinocchio validation)
-pub mod accounts {
-    use crate::error::PercolatorError;
-    use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+// 8. mod oracle
+pub mod oracle {
+    use crate::error::{fail, PercolatorError};
+    use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+    /// Pyth Solana Receiver program ID
+    /// rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ
+    pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+        0x0c, 0xb7, 0xfa, 0xbb, 0x52, 0xf7, 0xa6, 0x48, 0xbb, 0x5b, 0x31, 0x7d, 0x9a, 0x01, 0x8b,
+        0x90, 0x57, 0xcb, 0x02, 0x47, 0x74, 0xfa, 0xfe, 0x01, 0xe6, 0xc4, 0xdf, 0x98, 0xcc, 0x38,
+        0x58, 0x81,
+    ]);
+
+    /// Chainlink OCR2 Store program ID
+    /// HEvSKofvBgfaexv23kMabbYqxasxU3mQ4ibBMEmJWHny
+    pub const CHAINLINK_OCR2_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+        0xf1, 0x4b, 0xf6, 0x5a, 0xd5, 0x6b, 0xd2, 0xba, 0x71, 0x5e, 0x45, 0x74, 0x2c, 0x23, 0x1f,
+        0x27, 0xd6, 0x36, 0x21, 0xcf, 0x5b, 0x77, 0x8f, 0x37, 0xc1, 0xa2, 0x48, 0x95, 0x1d, 0x17,
+        0x56, 0x02,
+    ]);
+
+    // PriceUpdateV2 account layout. PriceUpdateV2::LEN = 134 is the
+    // MAXIMUM allocation; the actual byte count of USED bytes depends on
+    // the VerificationLevel variant because Borsh-serialized enums are
+    // variable-size:
+    //
+    //   Partial { num_signatures: u8 } → 2 bytes (disc=0x00 + 1-byte u8)
+    //   Full                           → 1 byte  (disc=0x01, no payload)
+    //
+    // Full variant layout (133 used bytes + 1 trailing unused):
+    //   discriminator(8) + write_authority(32) + verification_level(1)
+    //     + PriceFeedMessage(84) + posted_slot(8) = 133
+    //
+    // Partial variant layout (134 used bytes):
+    //   discriminator(8) + write_authority(32) + verification_level(2)
+    //     + PriceFeedMessage(84) + posted_slot(8) = 134
+    //
+    // Since the wrapper REJECTS non-Full verification, it only ever
+    // deserializes messages whose price_message starts at byte 41 (not
+    // 42). The earlier constant OFF_PRICE_FEED_MESSAGE = 42 silently
+    // shifted every field by one byte: feed_id at bytes 42..74 is in
+    // fact `price_message[1..33]` of the real account — which always
+    // mismatches the expected feed_id and returns InvalidOracleKey.
+    //
+    // The price-message block is parsed as the canonical pythnet_sdk
+    // struct `pythnet_sdk::messages::PriceFeedMessage` via its
+    // BorshDeserialize impl. Any breaking layout change Pyth ships
+    // (field insertion, reordering, type change) surfaces as a
+    // deserialize error at runtime or a compile error here.
+    //
+    // PriceFeedMessage fields (84 bytes, in Borsh declaration order per
+    // pythnet-sdk 2.3.1 src/messages.rs):
+    //   feed_id: [u8; 32]          (+32 →  32)
+    //   price: i64                 (+ 8 →  40)
+    //   conf: u64                  (+ 8 →  48)
+    //   exponent: i32              (+ 4 →  52)
+    //   publish_time: i64          (+ 8 →  60)
+    //   prev_publish_time: i64     (+ 8 →  68)
+    //   ema_price: i64             (+ 8 →  76)
+    //   ema_conf: u64              (+ 8 →  84)
+    const PRICE_UPDATE_V2_MIN_LEN: usize = 134;
+    const OFF_VERIFICATION_LEVEL: usize = 40; // u8 variant discriminant
+    /// PriceFeedMessage starts immediately after the 1-byte Full
+    /// discriminator. The wrapper rejects Partial upstream; offset 41
+    /// is correct for every price-message the wrapper ever deserializes.
+    const OFF_PRICE_FEED_MESSAGE: usize = 41;
+    /// Anchor discriminator for `PriceUpdateV2`: sha256("account:PriceUpdateV2")[0..8].
+    const PYTH_PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] =
+        [0x22, 0xf1, 0x23, 0x63, 0x9d, 0x7e, 0xf4, 0xcd];
+    /// Pyth VerificationLevel::Full — enum tag value the Anchor
+    /// serializer emits for the Full variant. Anchor writes the
+    /// variant discriminant as one u8 followed by the variant payload
+    /// (empty for Full, 1 byte num_signatures for Partial). Full is
+    /// the second variant → tag byte = 1.
+    const PYTH_VERIFICATION_FULL_TAG: u8 = 1;
+
+    /// Compile-time assertion: LEN must match the upstream Pyth
+    /// constant (sum of 8 + 32 + 2 + 84 + 8 = 134, with 2-byte
+    /// verification_level budget). Pyth allocates max size regardless
+    /// of variant, so the account is always 134 bytes.
+    const _: () = assert!(PRICE_UPDATE_V2_MIN_LEN == 134);
+
+    // Chainlink OCR2 State/Aggregator account layout offsets
+    // Note: Different from the Transmissions ring buffer format in older docs
+    // Must cover the last byte the parser reads: CL_OFF_ANSWER (216) + 16
+    // bytes for the i128 answer = 232. The prior `224` let a truncated
+    // Chainlink-owned feed (length 224..231) panic on the answer slice.
+    const CL_MIN_LEN: usize = 232;
+    const CL_OFF_DECIMALS: usize = 138; // u8 - number of decimals
+                                        // Skip unused: latest_round_id (143), live_length (148), live_cursor (152)
+                                        // The actual price data is stored directly at tail:
+    const CL_OFF_TIMESTAMP: usize = 208; // u64 - unix timestamp (seconds)
+    const CL_OFF_ANSWER: usize = 216; // i128 - price answer
+
+    // Maximum supported exponent to prevent overflow (10^18 fits in u128)
+    const MAX_EXPO_ABS: i32 = 18;
+
+    /// Read price from a Pyth PriceUpdateV2 account.
+    ///
+    /// Parameters:
+    /// - price_ai: The PriceUpdateV2 account
+    /// - expected_feed_id: The expected Pyth feed ID (must match account's feed_id)
+    /// - now_unix_ts: Current unix timestamp (from clock.unix_timestamp)
+    /// - max_staleness_secs: Maximum age in seconds
+    /// - conf_bps: Maximum confidence interval in basis points
+    ///
+    /// Returns `(price_e6, publish_time)` where `publish_time` is the Pyth
+    /// off-chain network's timestamp for this observation. The caller is
+    /// expected to enforce monotonicity against any previously-accepted
+    /// `publish_time` — see `clamp_external_price`.
+    pub fn read_pyth_price_e6(
+        price_ai: &AccountInfo,
+        expected_feed_id: &[u8; 32],
+        now_unix_ts: i64,
+        max_staleness_secs: u64,
+        conf_bps: u16,
+    ) -> Result<(u64, i64), ProgramError> {
+        use pythnet_sdk::messages::PriceFeedMessage;
+
+        // Validate oracle owner.
+        if *price_ai.owner != PYTH_RECEIVER_PROGRAM_ID {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        let data = price_ai.try_borrow_data()?;
+        if data.len() < PRICE_UPDATE_V2_MIN_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if data[..8] != PYTH_PRICE_UPDATE_V2_DISCRIMINATOR {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
 
-    /// Strict account-count check. Rejects if the caller passes more
-    /// or fewer accounts than the handler expects.
-    pub fn expect_len(accounts: &[AccountInfo], n: usize) -> Result<(), ProgramError> {
-        if !crate::policy::len_ok(accounts.len(), n) {
-            return Err(ProgramError::NotEnoughAccountKeys);
+        // Reject partially verified Pyth updates (only Full is safe).
+        if data[OFF_VERIFICATION_LEVEL] != PYTH_VERIFICATION_FULL_TAG {
+            return Err(PercolatorError::OracleInvalid.into());
         }
-        Ok(())
-    }
 
-    /// Variadic-tail check — used only by instructions with a
-    /// documented tail forwarding convention (TradeCpi).
-    pub fn expect_len_min(accounts: &[AccountInfo], n: usize) -> Result<(), ProgramError> {
-        if !crate::policy::len_at_least(accounts.len(), n) {
-            return Err(ProgramError::NotEnoughAccountKeys);
+        // Deserialize the PriceFeedMessage block via the canonical
+        // pythnet-sdk struct. This replaces the prior hand-rolled
+        // fixed-offset reads — any layout change in Pyth's struct
+        // surfaces as a borsh deserialize error here, not silent
+        // garbage. See read_price_clamped comments for the outer
+        // wrapper (discriminator + write_authority + verification
+        // _level) which is still pinned by offset since
+        // PriceUpdateV2 lives in the Anchor-heavy receiver SDK that
+        // we deliberately do not pull in as a dep.
+        let msg_slice = &data[OFF_PRICE_FEED_MESSAGE..];
+        let msg = <PriceFeedMessage as borsh::BorshDeserialize>::deserialize(&mut &msg_slice[..])
+            .map_err(|_| PercolatorError::OracleInvalid)?;
+
+        // Validate feed_id matches expected
+        if &msg.feed_id != expected_feed_id {
+            return Err(PercolatorError::InvalidOracleKey.into());
         }
-        Ok(())
-    }
 
-    pub fn expect_signer(ai: &AccountInfo) -> Result<(), ProgramError> {
-        // Signer check via policy helper
-        if !crate::policy::signer_ok(ai.is_signer) {
-            return Err(PercolatorError::ExpectedSigner.into());
+        let price = msg.price;
+        let conf = msg.conf;
+        let expo = msg.exponent;
+        let publish_time = msg.publish_time;
+
+        if price <= 0 {
+            return Err(PercolatorError::OracleInvalid.into());
         }
-        Ok(())
-    }
 
-    pub fn expect_writable(ai: &AccountInfo) -> Result<(), ProgramError> {
-        // Writable check via policy helper
-        if !crate::policy::writable_ok(ai.is_writable) {
-            return Err(PercolatorError::ExpectedWritable.into());
+        // SECURITY (C3): Bound exponent to prevent overflow in pow()
+        // Use explicit range check instead of abs() — i32::MIN.abs() overflows.
+        if expo < -MAX_EXPO_ABS || expo > MAX_EXPO_ABS {
+            return Err(PercolatorError::OracleInvalid.into());
         }
-        Ok(())
-    }
 
-    pub fn expect_key(ai: &AccountInfo, expected: &Pubkey) -> Result<(), ProgramError> {
-        // Key check via policy helper
-        if !crate::policy::pda_key_matches(expected.to_bytes(), ai.key.to_bytes()) {
-            return Err(ProgramError::InvalidArgument);
+        // Staleness check
+        {
+            let age = now_unix_ts.saturating_sub(publish_time);
+            if age < 0 || age as u64 > max_staleness_secs {
+                // ctx: publish_time, now, max_staleness_secs
+                return Err(fail(
+                    PercolatorError::OracleStale,
+                    &[publish_time as u64, now_unix_ts as u64, max_staleness_secs],
+                ));
+            }
         }
-        Ok(())
-    }
 
-    pub fn derive_vault_authority(program_id: &Pubkey, slab_key: &Pubkey) -> (Pubkey, u8) {
-        Pubkey::find_program_address(&[b"vault", slab_key.as_ref()], program_id)
-    }
+        // Confidence check (0 = disabled)
+        let price_u = price as u128;
+        if conf_bps != 0 {
+            let lhs = (conf as u128) * 10_000;
+            let rhs = price_u * (conf_bps as u128);
+            if lhs > rhs {
+                // ctx: conf, price, conf_filter_bps
+                return Err(fail(
+                    PercolatorError::OracleConfTooWide,
+                    &[conf, price as u64, conf_bps as u64],
+                ));
+            }
+        }
 
-    /// Derive vault authority from stored bump (saves ~1300 CU vs find_program_address)
-    pub fn derive_vault_authority_with_bump(
-        program_id: &Pubkey,
-        slab_key: &Pubkey,
-        bump: u8,
-    ) -> Result<Pubkey, ProgramError> {
-        Pubkey::create_program_address(&[b"vault", slab_key.as_ref(), &[bump]], program_id)
-            .map_err(|_| ProgramError::InvalidSeeds)
-    }
-}
+        // Convert to e6 format
+        let scale = expo + 6;
+        let final_price_u128 = if scale >= 0 {
+            let mul = 10u128.pow(scale as u32);
+            price_u
+                .checked_mul(mul)
+                .ok_or(PercolatorError::EngineOverflow)?
+        } else {
+            let div = 10u128.pow((-scale) as u32);
+            price_u / div
+        };
 
-// 6. mod state
-pub mod state {
-    use crate::constants::{CONFIG_LEN, HEADER_LEN};
-    use bytemuck::{Pod, Zeroable};
-    use core::cell::RefMut;
-    use core::mem::offset_of;
-    use solana_program::account_info::AccountInfo;
-    use solana_program::program_error::ProgramError;
+        if final_price_u128 == 0 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        if final_price_u128 > u64::MAX as u128 {
+            return Err(PercolatorError::EngineOverflow.into());
+        }
 
-    #[repr(C)]
-    #[derive(Clone, Copy, Pod, Zeroable)]
-    pub struct SlabHeader {
-        pub magic: u64,
-        pub version: u32,
-        pub bump: u8,
-        pub _padding: [u8; 3],
-        pub admin: [u8; 32],
-        pub _reserved: [u8; 24], // [0..8]=nonce, [8..24]=unused
-        /// Scoped authority: may execute WithdrawInsurance (and the
-        /// admin-only bounded WithdrawInsuranceLimited policy-setter
-        /// path, once refactored). Independent of `admin`; can be
-        /// delegated or burned via UpdateAuthority { kind=INSURANCE }.
-        /// Initialized to the creator's pubkey at InitMarket, which
-        /// yields a functional "super admin" by default.
-        pub insurance_authority: [u8; 32],
-        /// Scoped authority: may execute `WithdrawInsuranceLimited`
-        /// (tag 23) — a bounded live fee-extraction path enforcing
-        /// `config.insurance_withdraw_max_bps` per withdrawal and
-        /// `config.insurance_withdraw_cooldown_slots` between them.
-        /// Structurally CANNOT call tag 20 (`WithdrawInsurance`),
-        /// whose unbounded drain is gated on `insurance_authority`.
-        /// The auth split is load-bearing: it's what makes the bounds
-        /// un-bypassable. Burn to lock fee extraction. Independent of
-        /// all other authorities; rotated via
-        /// UpdateAuthority { kind=INSURANCE_OPERATOR }.
-        pub insurance_operator: [u8; 32],
+        Ok((final_price_u128 as u64, publish_time))
     }
 
-    /// Offset of _reserved field in SlabHeader, derived from offset_of! for correctness.
-    pub const RESERVED_OFF: usize = offset_of!(SlabHeader, _reserved);
+    /// Read price from a Chainlink OCR2 State/Aggregator account.
+    ///
+    /// Parameters:
+    /// - price_ai: The Chainlink aggregator account
+    /// - expected_feed_pubkey: The expected feed account pubkey (for validation)
+    /// - now_unix_ts: Current unix timestamp (from clock.unix_timestamp)
+    /// - max_staleness_secs: Maximum age in seconds
+    ///
+    /// Returns `(price_e6, observation_timestamp)` where the timestamp is
+    /// the Chainlink off-chain reporters' unix timestamp for this round.
+    /// The caller is expected to enforce monotonicity against any
+    /// previously-accepted timestamp — see `clamp_external_price`.
+    /// Note: Chainlink doesn't have confidence intervals, so conf_bps is not used.
+    pub fn read_chainlink_price_e6(
+        price_ai: &AccountInfo,
+        expected_feed_pubkey: &[u8; 32],
+        now_unix_ts: i64,
+        max_staleness_secs: u64,
+    ) -> Result<(u64, i64), ProgramError> {
+        // Validate oracle owner.
+        if *price_ai.owner != CHAINLINK_OCR2_PROGRAM_ID {
+            return Err(ProgramError::IllegalOwner);
+        }
 
-    // Portable compile-time assertion that RESERVED_OFF is 48 (expected layout).
-    // Subsequent authority fields (insurance_authority, insurance_operator) sit
-    // after _reserved, so this offset is stable at 48.
-    const _: [(); 48] = [(); RESERVED_OFF];
+        // Validate feed pubkey matches expected
+        if price_ai.key.to_bytes() != *expected_feed_pubkey {
+            return Err(PercolatorError::InvalidOracleKey.into());
+        }
 
-    #[repr(C)]
-    #[derive(Clone, Copy, Pod, Zeroable)]
-    pub struct MarketConfig {
-        pub collateral_mint: [u8; 32],
-        pub vault_pubkey: [u8; 32],
-        /// Pyth/Chainlink feed ID for oracle leg 1.
-        pub index_feed_id: [u8; 32],
-        /// Optional oracle leg 2 feed ID. Zero when inactive.
-        pub oracle_leg2_feed_id: [u8; 32],
-        /// Optional oracle leg 3 feed ID. Zero when inactive.
-        pub oracle_leg3_feed_id: [u8; 32],
-        /// Active external oracle leg count. 1 for legacy single-feed markets.
-        pub oracle_leg_count: u8,
-        /// Per-leg orientation bits. Leg 1 always multiplies; bits 1/2 divide
-        /// legs 2/3 when set, otherwise those legs multiply.
-        pub oracle_leg_flags: u8,
-        pub _oracle_leg_padding: [u8; 14],
-        /// Maximum staleness in seconds (Pyth Pull uses unix timestamps)
-        pub max_staleness_secs: u64,
-        pub conf_filter_bps: u16,
-        pub vault_authority_bump: u8,
-        /// If non-zero, invert the oracle price (raw -> 1e12/raw)
-        pub invert: u8,
-        /// Lamports per Unit for conversion (e.g., 1000 means 1 SOL = 1,000,000 Units)
-        /// If 0, no scaling is applied (1:1 lamports to units)
-        pub unit_scale: u32,
+        let data = price_ai.try_borrow_data()?;
+        if data.len() < CL_MIN_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        // ========================================
-        // Funding Parameters (configurable)
-        // ========================================
-        /// Funding horizon in slots (~4 min at 500 slots)
-        pub funding_horizon_slots: u64,
-        /// Funding rate multiplier in basis points (100 = 1.00x)
-        pub funding_k_bps: u64,
-        /// Max premium in basis points (500 = 5%)
-        pub funding_max_premium_bps: i64,
-        /// Max funding rate per slot in basis points
-        pub funding_max_e9_per_slot: i64,
+        // Read header fields
+        let decimals = data[CL_OFF_DECIMALS];
 
-        // ========================================
-        // Oracle Authority (optional signer-based oracle)
-        // ========================================
-        /// Oracle price authority pubkey. If non-zero, this signer can push prices
-        /// directly instead of requiring Pyth/Chainlink. All zeros = disabled.
-        pub hyperp_authority: [u8; 32],
-        /// Last price pushed by the Hyperp mark authority (e6, already
-        /// invert+scale normalized to engine space).
-        pub hyperp_mark_e6: u64,
-        /// Most recently accepted external oracle observation timestamp
-        /// (Pyth `publish_time` or Chainlink `timestamp`, in seconds).
-        /// Kept for ABI compatibility and liveness accounting; the raw
-        /// source target is stored separately in `oracle_target_*`, and
-        /// `last_effective_price_e6` is the dt-capped price fed to the engine.
-        pub last_oracle_publish_time: i64,
+        // Read price data directly from fixed offsets
+        let timestamp = u64::from_le_bytes(
+            data[CL_OFF_TIMESTAMP..CL_OFF_TIMESTAMP + 8]
+                .try_into()
+                .unwrap(),
+        );
+        // Read answer as i128 (16 bytes), but only bottom 8 bytes are typically used
+        let answer =
+            i128::from_le_bytes(data[CL_OFF_ANSWER..CL_OFF_ANSWER + 16].try_into().unwrap());
 
-        /// Last effective oracle/index price in e6 format. External mode stores
-        /// the dt-capped staircase value, not necessarily the raw oracle target;
-        /// Hyperp mode stores the rate-limited index.
-        pub last_effective_price_e6: u64,
+        if answer <= 0 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
 
-        // ========================================
-        // Insurance Withdrawal Limits (set at InitMarket, immutable)
-        // ========================================
-        /// Max bps of insurance fund withdrawable per withdrawal (1-10000).
-        /// 0 = disabled (no live-market withdrawals allowed).
-        pub insurance_withdraw_max_bps: u16,
-        /// Admin-opt-in deposit cap: total user capital `c_tot` after a
-        /// deposit must satisfy `c_tot_new <= tvl_insurance_cap_mult *
-        /// insurance_fund.balance`. 0 disables the check (default).
-        /// Tuned by admin via UpdateConfig; typical production values are
-        /// 10–100 (mature perp DEXs run ~20× insurance coverage).
-        pub tvl_insurance_cap_mult: u16,
-        /// Optional bounded-withdrawal mode. 0 = legacy bounded withdrawals
-        /// may withdraw live insurance subject to bps/cooldown. 1 = tag 23
-        /// may withdraw only the remaining amount deposited through
-        /// TopUpInsurance; fee/trading/liquidation growth stays behind.
-        pub insurance_withdraw_deposits_only: u8,
-        /// Padding for alignment (was [u8; 4]; shrunk when deposit-only mode
-        /// claimed 1 byte of the former slot).
-        pub _iw_padding: [u8; 3],
-        /// Minimum slots between insurance withdrawals.
-        pub insurance_withdraw_cooldown_slots: u64,
-        /// Latest raw external oracle target in engine-space e6. Non-Hyperp
-        /// only; Hyperp markets keep this equal to their initialized index.
-        pub oracle_target_price_e6: u64,
-        /// Timestamp of `oracle_target_price_e6`.
-        pub oracle_target_publish_time: i64,
-        /// Last accepted raw e6 price for each external oracle leg.
-        /// Used to reject same-timestamp conflicting updates on composite
-        /// oracles. Hyperp markets leave this zeroed.
-        pub oracle_leg_prices_e6: [u64; crate::constants::ORACLE_LEG_CAP],
-        /// Last accepted publish time for each external oracle leg.
-        /// Composite reads reject per-leg rollback, so a caller cannot pair a
-        /// fresh leg with an older already-observed leg to manufacture a cross.
-        pub oracle_leg_publish_times: [i64; crate::constants::ORACLE_LEG_CAP],
-        pub last_hyperp_index_slot: u64,
-        pub last_mark_push_slot: u128,
-        /// Last slot when insurance was withdrawn (for live-market cooldown tracking).
-        /// Uses a dedicated field to avoid overwriting oracle config fields.
-        pub last_insurance_withdraw_slot: u64,
-        /// Remaining TopUpInsurance principal withdrawable through tag 23
-        /// when `insurance_withdraw_deposits_only == 1`. This tracks only
-        /// explicit top-ups: it increases on TopUpInsurance and decreases on
-        /// WithdrawInsuranceLimited. Insurance growth from fees/liquidations
-        /// never increments this budget.
-        pub insurance_withdraw_deposit_remaining: u64,
+        // SECURITY (C3): Bound decimals to prevent overflow in pow()
+        if decimals > MAX_EXPO_ABS as u8 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
 
-        // ========================================
-        // Mark EWMA (trade-derived mark price for funding)
-        // ========================================
-        /// EWMA of execution prices (e6). Updated on every TradeCpi fill.
-        pub mark_ewma_e6: u64,
-        /// Slot when mark_ewma_e6 was last updated.
-        pub mark_ewma_last_slot: u64,
-        /// EWMA decay half-life in slots. 0 = last trade price directly.
-        pub mark_ewma_halflife_slots: u64,
-        /// `LastRestartSlot` sysvar reading captured at InitMarket, used to
-        /// detect post-init cluster restarts. Once any observer sees
-        /// `LastRestartSlot::get() > init_restart_slot`, the market is
-        /// considered dead regardless of oracle-staleness configuration —
-        /// `permissionless_stale_matured` returns true and resolution settles
-        /// at `engine.last_oracle_price` (the pre-restart cached price).
-        /// Occupies the slot previously reserved as u128-alignment padding
-        /// for the `maintenance_fee_per_slot` u128 that follows.
-        pub init_restart_slot: u64,
+        // Staleness check
+        {
+            // Validate timestamp fits in i64 before cast (year 2262+ overflow)
+            if timestamp > i64::MAX as u64 {
+                return Err(PercolatorError::OracleStale.into());
+            }
+            let age = now_unix_ts.saturating_sub(timestamp as i64);
+            if age < 0 || age as u64 > max_staleness_secs {
+                // ctx: timestamp, now, max_staleness_secs
+                return Err(fail(
+                    PercolatorError::OracleStale,
+                    &[timestamp, now_unix_ts as u64, max_staleness_secs],
+                ));
+            }
+        }
 
-        // ========================================
-        // Permissionless Resolution
-        // ========================================
-        /// Slots of oracle staleness required before anyone can resolve.
-        /// 0 = disabled (admin-only resolution). Set at InitMarket, immutable.
-        pub permissionless_resolve_stale_slots: u64,
-        /// Slot of last successful external oracle read (non-Hyperp only).
-        /// Authoritative liveness signal under the strict hard-timeout
-        /// model: `clock.slot - last_good_oracle_slot >=
-        /// permissionless_resolve_stale_slots` makes the market stale
-        /// and eligible for ResolvePermissionless, and causes
-        /// read_price_and_stamp to reject further price-taking ops.
-        /// Seeded to clock.slot at InitMarket.
-        pub last_good_oracle_slot: u64,
+        // Convert to e6 format
+        // Chainlink decimals work like: price = answer / 10^decimals
+        // We want e6, so: price_e6 = answer * 10^6 / 10^decimals = answer * 10^(6-decimals)
+        let price_u = answer as u128;
+        let scale = 6i32 - decimals as i32;
+        let final_price_u128 = if scale >= 0 {
+            let mul = 10u128.pow(scale as u32);
+            price_u
+                .checked_mul(mul)
+                .ok_or(PercolatorError::EngineOverflow)?
+        } else {
+            let div = 10u128.pow((-scale) as u32);
+            price_u / div
+        };
 
-        // ========================================
-        // Fee-Weighted EWMA
-        // ========================================
-        /// Periodic maintenance fee per slot per account (engine units).
-        /// Wrapper charges via engine.sync_account_fee_to_slot_not_atomic.
-        /// 0 = disabled. Set at InitMarket, immutable.
-        pub maintenance_fee_per_slot: u128,
-        /// Incremental fee-sweep cursor: next bitmap word to scan on the
-        /// next KeeperCrank. Per-account `last_fee_slot` on the engine side
-        /// keeps the sweep correct across cranks — each account pays for
-        /// its full elapsed interval the first time the cursor reaches it.
-        /// Scanning is O(FEE_SWEEP_BUDGET) per crank regardless of
-        /// max_accounts, so a 4096-account market doesn't blow the CU
-        /// budget on a single crank.
-        ///
-        /// Repurposed from the former `last_fee_charge_slot` (now dead —
-        /// replaced by per-account `Account::last_fee_slot`). Same 8-byte
-        /// slot, same wire offset; only u16 is meaningful.
-        pub fee_sweep_cursor_word: u64,
-        /// Bit position within `fee_sweep_cursor_word` at which the next sweep
-        /// resumes. Stored so the sweep can stop EXACTLY at FEE_SWEEP_BUDGET
-        /// mid-word without losing remaining set bits to budget truncation.
-        /// Only values 0..=63 are meaningful; wider values are normalized.
-        /// Repurposed from the former `_fee_padding`.
-        pub fee_sweep_cursor_bit: u64,
-        /// Minimum fee (in engine units, same as insurance_fund.balance) for full mark EWMA weight.
-        /// Trades with fee below this get proportionally reduced alpha.
-        /// 0 = disabled (all trades get full weight, backward compat).
-        /// Set at InitMarket, immutable.
-        pub mark_min_fee: u64,
-        /// Normal wrapper trade fee in bps. The engine's
-        /// `RiskParams::max_trading_fee_bps` is the hard cap; Hyperp
-        /// after-hours mode may raise the current fee above this base by the
-        /// EWMA mark movement it causes. Defaults to max_trading_fee_bps for
-        /// legacy/static-fee markets.
-        pub trade_fee_base_bps: u64,
-        /// Wrapper trade-fee mode. Reuses the former padding slot, so the
-        /// persisted layout is unchanged.
-        pub trade_fee_mode: u64,
-        /// Minimum slots after resolution before permissionless force-close.
-        /// 0 = disabled. Set at InitMarket, immutable.
-        pub force_close_delay_slots: u64,
-        /// Wrapper-charged new-account fee (base units). At InitUser/InitLP
-        /// the wrapper requires `fee_payment >= new_account_fee`; the fee
-        /// goes to `insurance_fund.balance`, the remainder becomes capital.
-        /// 0 = disabled (full `fee_payment` goes to capital).
-        pub new_account_fee: u128,
-    }
+        if final_price_u128 == 0 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        if final_price_u128 > u64::MAX as u128 {
+            return Err(PercolatorError::EngineOverflow.into());
+        }
 
-    pub fn slab_data_mut<'a, 'b>(
-        ai: &'b AccountInfo<'a>,
-    ) -> Result<RefMut<'b, &'a mut [u8]>, ProgramError> {
-        Ok(ai.try_borrow_mut_data()?)
+        Ok((final_price_u128 as u64, timestamp as i64))
     }
 
-    pub fn read_header(data: &[u8]) -> SlabHeader {
-        let mut h = SlabHeader::zeroed();
-        let src = &data[..HEADER_LEN];
-        let dst = bytemuck::bytes_of_mut(&mut h);
-        dst.copy_from_slice(src);
-        h
-    }
+    /// Read a single raw oracle leg in e6, before final market-level
+    /// inversion/unit scaling.
+    pub fn read_raw_oracle_price_e6(
+        price_ai: &AccountInfo,
+        expected_feed_id: &[u8; 32],
+        now_unix_ts: i64,
+        max_staleness_secs: u64,
+        conf_bps: u16,
+    ) -> Result<(u64, i64), ProgramError> {
+        // Detect oracle type by account owner and dispatch
+        let (raw_price, publish_time) = if *price_ai.owner == PYTH_RECEIVER_PROGRAM_ID {
+            read_pyth_price_e6(
+                price_ai,
+                expected_feed_id,
+                now_unix_ts,
+                max_staleness_secs,
+                conf_bps,
+            )?
+        } else if *price_ai.owner == CHAINLINK_OCR2_PROGRAM_ID {
+            // Chainlink safety: the feed pubkey check ensures only the
+            // specific account stored in index_feed_id at InitMarket can be read.
+            // A different Chainlink-owned account would fail the pubkey match.
+            read_chainlink_price_e6(price_ai, expected_feed_id, now_unix_ts, max_staleness_secs)?
+        } else {
+            return Err(ProgramError::IllegalOwner);
+        };
 
-    pub fn write_header(data: &mut [u8], h: &SlabHeader) {
-        let src = bytemuck::bytes_of(h);
-        let dst = &mut data[..HEADER_LEN];
-        dst.copy_from_slice(src);
+        if raw_price > percolator::MAX_ORACLE_PRICE {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        Ok((raw_price, publish_time))
     }
 
-    /// Read the request nonce from the reserved field in slab header.
-    /// The nonce is stored at RESERVED_OFF..RESERVED_OFF+8 as little-endian u64.
-    pub fn read_req_nonce(data: &[u8]) -> u64 {
-        u64::from_le_bytes(data[RESERVED_OFF..RESERVED_OFF + 8].try_into().unwrap())
-    }
+    fn apply_engine_price_transform(
+        raw_price: u64,
+        invert: u8,
+        unit_scale: u32,
+    ) -> Result<u64, ProgramError> {
+        // Same normalization (invert, then unit-scale) as every other price
+        // ingress — InitMarket, PushHyperpMark, TradeCpi limits — so the
+        // engine only ever sees engine-space prices for mark, funding,
+        // margin and liquidation.
+        let engine_price = crate::policy::to_engine_price(raw_price, invert, unit_scale)
+            .ok_or(PercolatorError::OracleInvalid)?;
 
-    /// Write the request nonce to the reserved field in slab header.
-    /// The nonce is stored in _reserved[0..8] as little-endian u64.
-    /// Uses offset_of! for correctness even if SlabHeader layout changes.
-    pub fn write_req_nonce(data: &mut [u8], nonce: u64) {
-        #[cfg(debug_assertions)]
-        debug_assert!(HEADER_LEN >= RESERVED_OFF + 16);
-        data[RESERVED_OFF..RESERVED_OFF + 8].copy_from_slice(&nonce.to_le_bytes());
+        // Enforce MAX_ORACLE_PRICE at ingress
+        if engine_price > percolator::MAX_ORACLE_PRICE {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        Ok(engine_price)
     }
 
-    /// Monotonic materialization counter stored in _reserved[8..16].
-    /// Incremented on every InitUser/InitLP. Used as lp_account_id
-    /// to provide a true per-instance identity that survives slot reuse.
-    pub fn read_mat_counter(data: &[u8]) -> u64 {
-        u64::from_le_bytes(
-            data[RESERVED_OFF + 8..RESERVED_OFF + 16]
-                .try_into()
-                .unwrap(),
-        )
+    /// Read oracle price for engine use, applying inversion and unit scaling if configured.
+    ///
+    /// Automatically detects oracle type by account owner:
+    /// - PYTH_RECEIVER_PROGRAM_ID: reads Pyth PriceUpdateV2
+    /// - CHAINLINK_OCR2_PROGRAM_ID: reads Chainlink OCR2 Transmissions
+    ///
+    /// Transformations applied in order:
+    /// 1. If invert != 0: inverted price = 1e12 / raw_e6
+    /// 2. If unit_scale > 1: scaled price = price / unit_scale
+    ///
+    /// CRITICAL: The unit_scale transformation ensures oracle-derived values (entry_price,
+    /// mark_pnl, position_value) are in the same scale as capital (which is stored in units).
+    /// Without this scaling, margin checks would compare units to base tokens incorrectly.
+    ///
+    /// The raw oracle is validated (staleness, confidence for Pyth) BEFORE transformations.
+    pub fn read_engine_price_e6(
+        price_ai: &AccountInfo,
+        expected_feed_id: &[u8; 32],
+        now_unix_ts: i64,
+        max_staleness_secs: u64,
+        conf_bps: u16,
+        invert: u8,
+        unit_scale: u32,
+    ) -> Result<(u64, i64), ProgramError> {
+        let (raw_price, publish_time) = read_raw_oracle_price_e6(
+            price_ai,
+            expected_feed_id,
+            now_unix_ts,
+            max_staleness_secs,
+            conf_bps,
+        )?;
+        Ok((
+            apply_engine_price_transform(raw_price, invert, unit_scale)?,
+            publish_time,
+        ))
     }
 
-    pub fn write_mat_counter(data: &mut [u8], counter: u64) {
-        data[RESERVED_OFF + 8..RESERVED_OFF + 16].copy_from_slice(&counter.to_le_bytes());
+    #[inline]
+    pub fn oracle_leg_count(config: &super::state::MarketConfig) -> Result<usize, ProgramError> {
+        match config.oracle_leg_count {
+            0 | 1 => Ok(1), // tolerate zeroed legacy configs as single-leg
+            2 => Ok(2),
+            3 => Ok(3),
+            _ => Err(PercolatorError::InvalidConfigParam.into()),
+        }
     }
 
-    /// Increment the materialization counter and return the NEW value.
-    /// Each account gets a globally unique ID at creation time.
-    /// Returns None if the counter would overflow (0 is reserved as "never materialized").
-    pub fn next_mat_counter(data: &mut [u8]) -> Option<u64> {
-        let old = read_mat_counter(data);
-        let c = old.checked_add(1)?;
-        write_mat_counter(data, c);
-        Some(c)
+    #[inline]
+    fn oracle_leg_feed_id(config: &super::state::MarketConfig, idx: usize) -> &[u8; 32] {
+        match idx {
+            0 => &config.index_feed_id,
+            1 => &config.oracle_leg2_feed_id,
+            _ => &config.oracle_leg3_feed_id,
+        }
     }
 
-    // ========================================
-    // Market Flags (stored in _padding[0] at offset 13)
-    // ========================================
-
-    /// Offset of flags byte in SlabHeader (_padding[0])
-    pub const FLAGS_OFF: usize = 13;
-
-    // Bit 1 (formerly FLAG_POLICY_CONFIGURED) is unused — the
-    // policy-configured flag was removed along with the
-    // SetInsuranceWithdrawPolicy / WithdrawInsuranceLimited
-    // instructions. Do NOT reuse this bit without a state migration
-    // (it may be set on any pre-delete slab; fresh deploy only).
-    /// Flag bit: CPI is in progress (reentrancy guard for TradeCpi).
-    /// Set before matcher CPI, cleared after. Any reentrant instruction
-    /// that sees this flag must abort.
-    pub const FLAG_CPI_IN_PROGRESS: u8 = 1 << 2;
-    /// Flag bit: engine has received a real oracle price (not the init sentinel).
-    /// Set on first successful oracle read (crank/trade/settle).
-    /// Eliminates the "price 1 means uninitialized" sentinel overload.
-    pub const FLAG_ORACLE_INITIALIZED: u8 = 1 << 3;
-
-    /// Read market flags from _padding[0].
-    pub fn read_flags(data: &[u8]) -> u8 {
-        data[FLAGS_OFF]
+    #[inline]
+    fn oracle_leg_divides(config: &super::state::MarketConfig, idx: usize) -> bool {
+        match idx {
+            1 => (config.oracle_leg_flags & crate::constants::ORACLE_LEG_FLAG_DIVIDE_LEG2) != 0,
+            2 => (config.oracle_leg_flags & crate::constants::ORACLE_LEG_FLAG_DIVIDE_LEG3) != 0,
+            _ => false,
+        }
     }
 
-    /// Write market flags to _padding[0].
-    pub fn write_flags(data: &mut [u8], flags: u8) {
-        data[FLAGS_OFF] = flags;
+    pub fn oracle_leg_config_ok(
+        is_hyperp: bool,
+        count: u8,
+        flags: u8,
+        leg1: &[u8; 32],
+        leg2: &[u8; 32],
+        leg3: &[u8; 32],
+    ) -> bool {
+        if flags
+            & !(crate::constants::ORACLE_LEG_FLAGS_MASK
+                | crate::constants::ORACLE_FLAG_REJECT_JUMPS)
+            != 0
+        {
+            return false;
+        }
+        if is_hyperp {
+            return count == 1 && flags == 0 && *leg2 == [0u8; 32] && *leg3 == [0u8; 32];
+        }
+        // Jump rejection is market-wide, not per leg.
+        let flags = flags & crate::constants::ORACLE_LEG_FLAGS_MASK;
+        if count == 0 || count > crate::constants::ORACLE_LEG_CAP as u8 || *leg1 == [0u8; 32] {
+            return false;
+        }
+        if count == 1 {
+            return flags == 0 && *leg2 == [0u8; 32] && *leg3 == [0u8; 32];
+        }
+        if *leg2 == [0u8; 32] || leg2 == leg1 {
+            return false;
+        }
+        if count == 2 {
+            return (flags & crate::constants::ORACLE_LEG_FLAG_DIVIDE_LEG3) == 0
+                && *leg3 == [0u8; 32];
+        }
+        if *leg3 == [0u8; 32] || leg3 == leg1 || leg3 == leg2 {
+            return false;
+        }
+        true
     }
 
-    /// Check if CPI is in progress (reentrancy guard).
-    pub fn is_cpi_in_progress(data: &[u8]) -> bool {
-        read_flags(data) & FLAG_CPI_IN_PROGRESS != 0
+    fn compose_oracle_leg(acc_e6: u64, leg_e6: u64, divide: bool) -> Result<u64, ProgramError> {
+        if leg_e6 == 0 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        let next = if divide {
+            (acc_e6 as u128)
+                .checked_mul(1_000_000u128)
+                .ok_or(PercolatorError::EngineOverflow)?
+                / (leg_e6 as u128)
+        } else {
+            (acc_e6 as u128)
+                .checked_mul(leg_e6 as u128)
+                .ok_or(PercolatorError::EngineOverflow)?
+                / 1_000_000u128
+        };
+        if next == 0 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        if next > percolator::MAX_ORACLE_PRICE as u128 {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        Ok(next as u64)
     }
 
-    /// Set CPI-in-progress flag (call before matcher CPI).
-    pub fn set_cpi_in_progress(data: &mut [u8]) {
-        let flags = read_flags(data) | FLAG_CPI_IN_PROGRESS;
-        write_flags(data, flags);
-    }
+    /// Read the configured external oracle, which may be a synthetic cross
+    /// composed from up to 3 legs. Returns `(engine_price_e6, publish_time,
+    /// advanced)`. `advanced` is true only when at least one active leg has a
+    /// strictly newer publish time than the one already stored in config.
+    ///
+    /// Composite mode stores per-leg publish times/prices and rejects rollback:
+    /// a caller cannot pair a fresh leg with an older already-observed leg to
+    /// manufacture a cross. All supplied leg accounts must still be fresh under
+    /// `max_staleness_secs`.
+    pub fn read_external_price_e6(
+        config: &mut super::state::MarketConfig,
+        oracle_accounts: &[AccountInfo],
+        now_unix_ts: i64,
+    ) -> Result<(u64, i64, bool), ProgramError> {
+        let count = oracle_leg_count(config)?;
+        if oracle_accounts.len() < count {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        if !oracle_leg_config_ok(
+            false,
+            count as u8,
+            config.oracle_leg_flags,
+            &config.index_feed_id,
+            &config.oracle_leg2_feed_id,
+            &config.oracle_leg3_feed_id,
+        ) {
+            return Err(PercolatorError::InvalidConfigParam.into());
+        }
 
-    /// Clear CPI-in-progress flag (call after matcher CPI returns).
-    pub fn clear_cpi_in_progress(data: &mut [u8]) {
-        let flags = read_flags(data) & !FLAG_CPI_IN_PROGRESS;
-        write_flags(data, flags);
-    }
+        let mut acc: u64 = 0;
+        let mut max_publish_time = i64::MIN;
+        let mut advanced = false;
 
-    /// Check if engine has received a real oracle price.
-    pub fn is_oracle_initialized(data: &[u8]) -> bool {
-        read_flags(data) & FLAG_ORACLE_INITIALIZED != 0
-    }
+        let mut i = 0usize;
+        while i < count {
+            let (price, publish_time) = read_raw_oracle_price_e6(
+                &oracle_accounts[i],
+                oracle_leg_feed_id(config, i),
+                now_unix_ts,
+                config.max_staleness_secs,
+                config.conf_filter_bps,
+            )?;
+            let prev_time = config.oracle_leg_publish_times[i];
+            let prev_price = config.oracle_leg_prices_e6[i];
+            if prev_time != 0 {
+                if publish_time < prev_time {
+                    return Err(PercolatorError::OracleStale.into());
+                }
+                if publish_time == prev_time && prev_price != 0 && price != prev_price {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
+            }
+            if publish_time > prev_time {
+                config.oracle_leg_publish_times[i] = publish_time;
+                config.oracle_leg_prices_e6[i] = price;
+                advanced = true;
+            }
+            max_publish_time = core::cmp::max(max_publish_time, publish_time);
+            acc = if i == 0 {
+                price
+            } else {
+                compose_oracle_leg(acc, price, oracle_leg_divides(config, i))?
+            };
+            i += 1;
+        }
 
-    /// Mark engine as having received a real oracle price.
-    pub fn set_oracle_initialized(data: &mut [u8]) {
-        let flags = read_flags(data) | FLAG_ORACLE_INITIALIZED;
-        write_flags(data, flags);
+        Ok((
+            apply_engine_price_transform(acc, config.invert, config.unit_scale)?,
+            max_publish_time,
+            advanced,
+        ))
     }
 
-    pub fn read_config(data: &[u8]) -> MarketConfig {
-        let mut c = MarketConfig::zeroed();
-        let src = &data[HEADER_LEN..HEADER_LEN + CONFIG_LEN];
-        let dst = bytemuck::bytes_of_mut(&mut c);
-        dst.copy_from_slice(src);
-        c
+    /// Clamp `raw_price` so it cannot move more than `max_change_e2bps` from `last_price`.
+    /// Units: 1_000_000 e2bps = 100%. 0 = disabled (no cap). last_price == 0 = first-time.
+    pub fn clamp_oracle_price(last_price: u64, raw_price: u64, max_change_bps: u64) -> u64 {
+        if max_change_bps == 0 || last_price == 0 {
+            return raw_price;
+        }
+        let max_delta_128 = (last_price as u128) * (max_change_bps as u128) / 10_000;
+        let max_delta = core::cmp::min(max_delta_128, u64::MAX as u128) as u64;
+        let lower = last_price.saturating_sub(max_delta);
+        let upper = last_price.saturating_add(max_delta);
+        raw_price.clamp(lower, upper)
     }
 
-    pub fn write_config(data: &mut [u8], c: &MarketConfig) {
-        let src = bytemuck::bytes_of(c);
-        let dst = &mut data[HEADER_LEN..HEADER_LEN + CONFIG_LEN];
-        dst.copy_from_slice(src);
+    /// Read the external (Pyth/Chainlink) oracle price.
+    ///
+    /// Pyth/Chainlink is the only price source for non-Hyperp markets.
+    /// Any parse error (stale, wide confidence, wrong feed, malformed)
+    /// propagates to the caller — no authority fallback. If Pyth is
+    /// terminally dead, the market freezes until `permissionless_resolve
+    /// _stale_slots` matures and settles at `engine.last_oracle_price`
+    /// via the Degenerate arm of ResolveMarket / ResolvePermissionless.
+    pub fn read_price_clamped(
+        config: &mut super::state::MarketConfig,
+        oracle_accounts: &[AccountInfo],
+        now_unix_ts: i64,
+        max_change_bps: u64,
+        p_last: u64,
+        price_move_dt_slots: u64,
+        oi_any: bool,
+    ) -> Result<(u64, bool), ProgramError> {
+        let external = read_external_price_e6(config, oracle_accounts, now_unix_ts);
+        clamp_external_price(
+            config,
+            external,
+            p_last,
+            max_change_bps,
+            price_move_dt_slots,
+            oi_any,
+        )
     }
 
-    pub fn read_risk_buffer(data: &[u8]) -> crate::risk_buffer::RiskBuffer {
-        use crate::constants::RISK_BUF_LEN;
-        use crate::constants::RISK_BUF_OFF;
-        let mut buf = crate::risk_buffer::RiskBuffer::zeroed();
-        let src = &data[RISK_BUF_OFF..RISK_BUF_OFF + RISK_BUF_LEN];
-        bytemuck::bytes_of_mut(&mut buf).copy_from_slice(src);
-        // Full sanitization against corrupted slab data:
-        // 1. Clamp count
-        if buf.count as usize > crate::constants::RISK_BUF_CAP {
-            buf.count = crate::constants::RISK_BUF_CAP as u8;
-        }
-        // 2. Zero entries past count
-        for i in buf.count as usize..crate::constants::RISK_BUF_CAP {
-            buf.entries[i] = crate::risk_buffer::RiskEntry::zeroed();
-        }
-        // 3. Filter invalid idx values
-        for i in (0..buf.count as usize).rev() {
-            if buf.entries[i].idx as usize >= percolator::MAX_ACCOUNTS {
-                buf.remove(buf.entries[i].idx);
+    /// Accept an already-parsed external observation into a target/effective split.
+    ///
+    /// Fresh source observations update `oracle_target_*` with the raw signed
+    /// target. The returned/stored effective price is then capped from engine
+    /// `P_last` toward that target by `max_change_bps * price_move_dt_slots`.
+    /// Duplicate or older observations do not replace the target, but they may
+    /// continue the staircase toward the already-persisted target as time moves.
+    pub fn clamp_external_price(
+        config: &mut super::state::MarketConfig,
+        external: Result<(u64, i64, bool), ProgramError>,
+        p_last: u64,
+        max_change_bps: u64,
+        price_move_dt_slots: u64,
+        oi_any: bool,
+    ) -> Result<(u64, bool), ProgramError> {
+        let (ext_price, publish_time, advanced) = external?;
+        if advanced && (config.oracle_leg_flags & crate::constants::ORACLE_FLAG_REJECT_JUMPS) != 0 {
+            let prev = if p_last != 0 {
+                p_last
+            } else {
+                config.last_effective_price_e6
+            };
+            if crate::policy::oracle_jump_exceeds_cap(
+                prev,
+                ext_price,
+                max_change_bps,
+                price_move_dt_slots,
+            ) {
+                return Err(PercolatorError::OracleInvalid.into());
             }
         }
-        // 4. Recompute min_notional from sanitized entries
-        buf.recompute_min();
-        // 5. Clamp scan_cursor
-        if buf.scan_cursor as usize >= percolator::MAX_ACCOUNTS {
-            buf.scan_cursor = 0;
+        if advanced {
+            config.oracle_target_price_e6 = ext_price;
+            config.oracle_target_publish_time = publish_time;
+            config.last_oracle_publish_time = publish_time;
+        } else if config.oracle_target_price_e6 == 0 {
+            config.oracle_target_price_e6 = ext_price;
+            config.oracle_target_publish_time = publish_time;
+            config.last_oracle_publish_time = publish_time;
         }
-        buf
-    }
-
-    pub fn write_risk_buffer(data: &mut [u8], buf: &crate::risk_buffer::RiskBuffer) {
-        use crate::constants::RISK_BUF_LEN;
-        use crate::constants::RISK_BUF_OFF;
-        let src = bytemuck::bytes_of(buf);
-        data[RISK_BUF_OFF..RISK_BUF_OFF + RISK_BUF_LEN].copy_from_slice(src);
-    }
 
-    /// Read per-account materialization generation (u64).
-    /// Returns 0 for never-materialized slots (zero-initialized slab).
-    pub fn read_account_generation(data: &[u8], idx: u16) -> u64 {
-        let off = crate::constants::GEN_TABLE_OFF + (idx as usize) * 8;
-        u64::from_le_bytes(data[off..off + 8].try_into().unwrap())
+        let target = config.oracle_target_price_e6;
+        let anchor = if p_last != 0 {
+            p_last
+        } else if config.last_effective_price_e6 != 0 {
+            config.last_effective_price_e6
+        } else {
+            target
+        };
+        let effective = effective_price_from_target(
+            anchor,
+            target,
+            max_change_bps,
+            price_move_dt_slots,
+            oi_any,
+        );
+        config.last_effective_price_e6 = effective;
+        Ok((effective, advanced))
     }
 
-    /// Write per-account materialization generation.
-    pub fn write_account_generation(data: &mut [u8], idx: u16, gen: u64) {
-        let off = crate::constants::GEN_TABLE_OFF + (idx as usize) * 8;
-        data[off..off + 8].copy_from_slice(&gen.to_le_bytes());
-    }
-}
+    // =========================================================================
+    // Hyperp mode helpers (internal mark/index, no external oracle)
+    // =========================================================================
 
-// 7. mod units - base token/units conversion at instruction boundaries
-pub mod units {
-    /// Convert base token amount to units, returning (units, dust).
-    /// Base token is the collateral (e.g., lamports for SOL, satoshis for BTC).
-    /// If scale is 0, returns (base, 0) - no scaling.
+    /// Check if Hyperp mode is active (internal mark/index pricing).
+    /// Hyperp mode is active when index_feed_id is all zeros.
     #[inline]
-    pub fn base_to_units(base: u64, scale: u32) -> (u64, u64) {
-        if scale == 0 {
-            return (base, 0);
-        }
-        let s = scale as u64;
-        (base / s, base % s)
+    pub fn is_hyperp_mode(config: &super::state::MarketConfig) -> bool {
+        config.index_feed_id == [0u8; 32]
     }
 
-    /// Convert units to base token amount with overflow check.
-    /// Returns None if overflow would occur.
+    /// Hybrid after-hours mode: an external-oracle market that reserves the
+    /// dynamic Hyperp fee headroom. Fresh external reads own the index during
+    /// market hours; after the wrapper's own last-good external read becomes
+    /// soft-stale, price-taking paths fall back to the trade-derived EWMA mark
+    /// and charge the dynamic mark-movement fee.
     #[inline]
-    pub fn units_to_base_checked(units: u64, scale: u32) -> Option<u64> {
-        if scale == 0 {
-            return Some(units);
-        }
-        units.checked_mul(scale as u64)
+    pub fn is_hybrid_after_hours_mode(config: &super::state::MarketConfig) -> bool {
+        !is_hyperp_mode(config)
+            && config.trade_fee_mode == crate::constants::TRADE_FEE_MODE_HYBRID_AFTER_HOURS
     }
-}
-
-// 8. mod oracle
-pub mod oracle {
-    use crate::error::PercolatorError;
-    use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
-
-    /// Pyth Solana Receiver program ID
-    /// rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ
-    pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
-        0x0c, 0xb7, 0xfa, 0xbb, 0x52, 0xf7, 0xa6, 0x48, 0xbb, 0x5b, 0x31, 0x7d, 0x9a, 0x01, 0x8b,
-        0x90, 0x57, 0xcb, 0x02, 0x47, 0x74, 0xfa, 0xfe, 0x01, 0xe6, 0xc4, 0xdf, 0x98, 0xcc, 0x38,
-        0x58, 0x81,
-    ]);
-
-    /// Chainlink OCR2 Store program ID
-    /// HEvSKofvBgfaexv23kMabbYqxasxU3mQ4ibBMEmJWHny
-    pub const CHAINLINK_OCR2_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
-        0xf1, 0x4b, 0xf6, 0x5a, 0xd5, 0x6b, 0xd2, 0xba, 0x71, 0x5e, 0x45, 0x74, 0x2c, 0x23, 0x1f,
-        0x27, 0xd6, 0x36, 0x21, 0xcf, 0x5b, 0x77, 0x8f, 0x37, 0xc1, 0xa2, 0x48, 0x95, 0x1d, 0x17,
-        0x56, 0x02,
-    ]);
-
-    // PriceUpdateV2 account layout. PriceUpdateV2::LEN = 134 is the
-    // MAXIMUM allocation; the actual byte count of USED bytes depends on
-    // the VerificationLevel variant because Borsh-serialized enums are
-    // variable-size:
-    //
-    //   Partial { num_signatures: u8 } → 2 bytes (disc=0x00 + 1-byte u8)
-    //   Full                           → 1 byte  (disc=0x01, no payload)
-    //
-    // Full variant layout (133 used bytes + 1 trailing unused):
-    //   discriminator(8) + write_authority(32) + verification_level(1)
-    //     + PriceFeedMessage(84) + posted_slot(8) = 133
-    //
-    // Partial variant layout (134 used bytes):
-    //   discriminator(8) + write_authority(32) + verification_level(2)
-    //     + PriceFeedMessage(84) + posted_slot(8) = 134
-    //
-    // Since the wrapper REJECTS non-Full verification, it only ever
-    // deserializes messages whose price_message starts at byte 41 (not
-    // 42). The earlier constant OFF_PRICE_FEED_MESSAGE = 42 silently
-    // shifted every field by one byte: feed_id at bytes 42..74 is in
-    // fact `price_message[1..33]` of the real account — which always
-    // mismatches the expected feed_id and returns InvalidOracleKey.
-    //
-    // The price-message block is parsed as the canonical pythnet_sdk
-    // struct `pythnet_sdk::messages::PriceFeedMessage` via its
-    // BorshDeserialize impl. Any breaking layout change Pyth ships
-    // (field insertion, reordering, type change) surfaces as a
-    // deserialize error at runtime or a compile error here.
-    //
-    // PriceFeedMessage fields (84 bytes, in Borsh declaration order per
-    // pythnet-sdk 2.3.1 src/messages.rs):
-    //   feed_id: [u8; 32]          (+32 →  32)
-    //   price: i64                 (+ 8 →  40)
-    //   conf: u64                  (+ 8 →  48)
-    //   exponent: i32              (+ 4 →  52)
-    //   publish_time: i64          (+ 8 →  60)
-    //   prev_publish_time: i64     (+ 8 →  68)
-    //   ema_price: i64             (+ 8 →  76)
-    //   ema_conf: u64              (+ 8 →  84)
-    const PRICE_UPDATE_V2_MIN_LEN: usize = 134;
-    const OFF_VERIFICATION_LEVEL: usize = 40; // u8 variant discriminant
-    /// PriceFeedMessage starts immediately after the 1-byte Full
-    /// discriminator. The wrapper rejects Partial upstream; offset 41
-    /// is correct for every price-message the wrapper ever deserializes.
-    const OFF_PRICE_FEED_MESSAGE: usize = 41;
-    /// Anchor discriminator for `PriceUpdateV2`: sha256("account:PriceUpdateV2")[0..8].
-    const PYTH_PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] =
-        [0x22, 0xf1, 0x23, 0x63, 0x9d, 0x7e, 0xf4, 0xcd];
-    /// Pyth VerificationLevel::Full — enum tag value the Anchor
-    /// serializer emits for the Full variant. Anchor writes the
-    /// variant discriminant as one u8 followed by the variant payload
-    /// (empty for Full, 1 byte num_signatures for Partial). Full is
-    /// the second variant → tag byte = 1.
-    const PYTH_VERIFICATION_FULL_TAG: u8 = 1;
-
-    /// Compile-time assertion: LEN must match the upstream Pyth
-    /// constant (sum of 8 + 32 + 2 + 84 + 8 = 134, with 2-byte
-    /// verification_level budget). Pyth allocates max size regardless
-    /// of variant, so the account is always 134 bytes.
-    const _: () = assert!(PRICE_UPDATE_V2_MIN_LEN == 134);
-
-    // Chainlink OCR2 State/Aggregator account layout offsets
-    // Note: Different from the Transmissions ring buffer format in older docs
-    // Must cover the last byte the parser reads: CL_OFF_ANSWER (216) + 16
-    // bytes for the i128 answer = 232. The prior `224` let a truncated
-    // Chainlink-owned feed (length 224..231) panic on the answer slice.
-    const CL_MIN_LEN: usize = 232;
-    const CL_OFF_DECIMALS: usize = 138; // u8 - number of decimals
-                                        // Skip unused: latest_round_id (143), live_length (148), live_cursor (152)
-                                        // The actual price data is stored directly at tail:
-    const CL_OFF_TIMESTAMP: usize = 208; // u64 - unix timestamp (seconds)
-    const CL_OFF_ANSWER: usize = 216; // i128 - price answer
-
-    // Maximum supported exponent to prevent overflow (10^18 fits in u128)
-    const MAX_EXPO_ABS: i32 = 18;
 
-    /// Read price from a Pyth PriceUpdateV2 account.
-    ///
-    /// Parameters:
-    /// - price_ai: The PriceUpdateV2 account
-    /// - expected_feed_id: The expected Pyth feed ID (must match account's feed_id)
-    /// - now_unix_ts: Current unix timestamp (from clock.unix_timestamp)
-    /// - max_staleness_secs: Maximum age in seconds
-    /// - conf_bps: Maximum confidence interval in basis points
-    ///
-    /// Returns `(price_e6, publish_time)` where `publish_time` is the Pyth
-    /// off-chain network's timestamp for this observation. The caller is
-    /// expected to enforce monotonicity against any previously-accepted
-    /// `publish_time` — see `clamp_external_price`.
-    pub fn read_pyth_price_e6(
-        price_ai: &AccountInfo,
-        expected_feed_id: &[u8; 32],
-        now_unix_ts: i64,
-        max_staleness_secs: u64,
-        conf_bps: u16,
-    ) -> Result<(u64, i64), ProgramError> {
-        use pythnet_sdk::messages::PriceFeedMessage;
+    #[inline]
+    pub fn hybrid_soft_stale_matured(config: &super::state::MarketConfig, clock_slot: u64) -> bool {
+        if !is_hybrid_after_hours_mode(config) {
+            return false;
+        }
+        let max_stale_slots = config.max_staleness_secs.saturating_mul(3);
+        clock_slot.saturating_sub(config.last_good_oracle_slot) > max_stale_slots
+    }
 
-        // Validate oracle owner.
-        if *price_ai.owner != PYTH_RECEIVER_PROGRAM_ID {
-            return Err(ProgramError::IllegalOwner);
+    #[inline]
+    pub fn hybrid_after_hours_target(config: &super::state::MarketConfig) -> u64 {
+        if config.mark_ewma_e6 > 0 {
+            config.mark_ewma_e6
+        } else if config.hyperp_mark_e6 > 0 {
+            config.hyperp_mark_e6
+        } else {
+            config.last_effective_price_e6
         }
+    }
 
-        let data = price_ai.try_borrow_data()?;
-        if data.len() < PRICE_UPDATE_V2_MIN_LEN {
-            return Err(ProgramError::InvalidAccountData);
+    /// Hard-timeout predicate: has the market's configured oracle been
+    /// stale for >= permissionless_resolve_stale_slots?
+    ///
+    /// Returns false when permissionless_resolve_stale_slots == 0
+    /// (feature disabled — admin-only resolution).
+    ///
+    /// "Liveness slot" is:
+    ///   non-Hyperp → config.last_good_oracle_slot (advances on successful
+    ///                external Pyth/Chainlink reads)
+    ///   Hyperp     → config.last_mark_push_slot (advances ONLY on
+    ///                full-weight mark observations: PushHyperpMark,
+    ///                or a TradeCpi fill whose fee paid the mark_min
+    ///                _fee threshold). mark_ewma_last_slot is the
+    ///                EWMA-math clock, NOT a liveness signal —
+    ///                partial-fee sub-threshold trades advance the
+    ///                EWMA clock so dt stays correct for weighting,
+    ///                but they must NOT extend market life.
+    ///
+    /// Once this returns true, the market is DEAD: ResolvePermissionless
+    /// may be called, and every price-taking live instruction
+    /// (read_price_and_stamp for non-Hyperp, get_engine_oracle_price_e6
+    /// for Hyperp) rejects further price reads to prevent state drift
+    /// before terminal resolution.
+    pub fn permissionless_stale_matured(
+        config: &super::state::MarketConfig,
+        clock_slot: u64,
+    ) -> bool {
+        // Cluster-restart gate (SIMD-0047 `LastRestartSlot` sysvar):
+        // any hard-fork restart after `InitMarket` freezes the market
+        // unconditionally, even when slot-based staleness is disabled.
+        // Resolution flows through the Degenerate arm and settles at the
+        // last cached pre-restart oracle price.
+        if cluster_restarted_since_init(config) {
+            return true;
         }
-        if data[..8] != PYTH_PRICE_UPDATE_V2_DISCRIMINATOR {
-            return Err(PercolatorError::OracleInvalid.into());
+        if config.permissionless_resolve_stale_slots == 0 {
+            return false;
         }
+        let last_live_slot = if is_hyperp_mode(config) {
+            config.last_mark_push_slot as u64
+        } else {
+            config.last_good_oracle_slot
+        };
+        clock_slot.saturating_sub(last_live_slot) >= config.permissionless_resolve_stale_slots
+    }
 
-        // Reject partially verified Pyth updates (only Full is safe).
-        if data[OFF_VERIFICATION_LEVEL] != PYTH_VERIFICATION_FULL_TAG {
-            return Err(PercolatorError::OracleInvalid.into());
+    /// Pure comparison the on-chain path uses after reading the sysvar.
+    /// Separated so proof harnesses can check it symbolically without stubbing syscalls.
+    #[inline]
+    pub fn restart_detected(init_restart_slot: u64, current_last_restart_slot: u64) -> bool {
+        current_last_restart_slot > init_restart_slot
+    }
+
+    /// On-chain restart check. Invokes `sol_get_last_restart_slot` and
+    /// compares against the slot captured at `InitMarket`. Returns false
+    /// under `cfg(kani)` so verification harnesses don't need to stub the
+    /// syscall — the pure comparison is proved separately via
+    /// `restart_detected`.
+    #[cfg(not(feature = "kani"))]
+    #[inline]
+    pub fn cluster_restarted_since_init(config: &super::state::MarketConfig) -> bool {
+        use solana_program::sysvar::last_restart_slot::LastRestartSlot;
+        use solana_program::sysvar::Sysvar;
+        match LastRestartSlot::get() {
+            Ok(lrs) => restart_detected(config.init_restart_slot, lrs.last_restart_slot),
+            Err(_) => false,
         }
+    }
 
-        // Deserialize the PriceFeedMessage block via the canonical
-        // pythnet-sdk struct. This replaces the prior hand-rolled
-        // fixed-offset reads — any layout change in Pyth's struct
-        // surfaces as a borsh deserialize error here, not silent
-        // garbage. See read_price_clamped comments for the outer
-        // wrapper (discriminator + write_authority + verification
-        // _level) which is still pinned by offset since
-        // PriceUpdateV2 lives in the Anchor-heavy receiver SDK that
-        // we deliberately do not pull in as a dep.
-        let msg_slice = &data[OFF_PRICE_FEED_MESSAGE..];
-        let msg = <PriceFeedMessage as borsh::BorshDeserialize>::deserialize(&mut &msg_slice[..])
-            .map_err(|_| PercolatorError::OracleInvalid)?;
+    #[cfg(feature = "kani")]
+    #[inline]
+    pub fn cluster_restarted_since_init(_config: &super::state::MarketConfig) -> bool {
+        false
+    }
 
-        // Validate feed_id matches expected
-        if &msg.feed_id != expected_feed_id {
-            return Err(PercolatorError::InvalidOracleKey.into());
+    /// External-oracle target/effective staircase. Unlike the Hyperp helper
+    /// below, this intentionally does not cap accumulated dt; the caller passes
+    /// the engine-relevant residual dt for the actual accrual step.
+    pub fn clamp_toward_engine_dt(p_last: u64, target: u64, cap_bps: u64, dt_slots: u64) -> u64 {
+        if p_last == 0 || target == 0 {
+            return target;
+        }
+        if cap_bps == 0 || dt_slots == 0 {
+            return p_last;
         }
 
-        let price = msg.price;
-        let conf = msg.conf;
-        let expo = msg.exponent;
-        let publish_time = msg.publish_time;
+        let max_delta_u128 = (p_last as u128)
+            .saturating_mul(cap_bps as u128)
+            .saturating_mul(dt_slots as u128)
+            / 10_000u128;
+        let max_delta = core::cmp::min(max_delta_u128, u64::MAX as u128) as u64;
+        if target > p_last {
+            core::cmp::min(target, p_last.saturating_add(max_delta))
+        } else {
+            core::cmp::max(target, p_last.saturating_sub(max_delta))
+        }
+    }
 
-        if price <= 0 {
-            return Err(PercolatorError::OracleInvalid.into());
+    /// Spec §3 target/effective price law used by both external-oracle and
+    /// Hyperp ingress. With exposed OI, the engine receives a capped staircase
+    /// price from its current effective price toward the raw target. With no
+    /// OI, the wrapper may adopt the raw target directly because no live
+    /// position can lose equity from the jump.
+    #[inline]
+    pub fn effective_price_from_target(
+        anchor: u64,
+        target: u64,
+        max_change_bps: u64,
+        price_move_dt_slots: u64,
+        oi_any: bool,
+    ) -> u64 {
+        if oi_any {
+            clamp_toward_engine_dt(anchor, target, max_change_bps, price_move_dt_slots)
+        } else {
+            target
         }
+    }
 
-        // SECURITY (C3): Bound exponent to prevent overflow in pow()
-        // Use explicit range check instead of abs() — i32::MIN.abs() overflows.
-        if expo < -MAX_EXPO_ABS || expo > MAX_EXPO_ABS {
-            return Err(PercolatorError::OracleInvalid.into());
+    /// Move `index` toward `mark`, but clamp movement by cap_bps * dt_slots.
+    /// cap_bps units: standard bps (10_000 = 100%).
+    /// Returns the new index value.
+    ///
+    /// Security: When dt_slots == 0 (same slot) or cap_bps == 0 (cap disabled),
+    /// returns index unchanged to prevent bypassing rate limits.
+    /// Maximum effective dt for rate-limiting. Caps accumulated movement to
+    /// prevent a crank pause from allowing a full-magnitude index jump.
+    /// ~1 hour at 2.5 slots/sec = 9000 slots.
+    const MAX_CLAMP_DT_SLOTS: u64 = 9_000;
+
+    pub fn clamp_toward_with_dt(index: u64, mark: u64, cap_bps: u64, dt_slots: u64) -> u64 {
+        if index == 0 {
+            return mark;
+        }
+        if cap_bps == 0 || dt_slots == 0 {
+            return index;
         }
 
-        // Staleness check
-        {
-            let age = now_unix_ts.saturating_sub(publish_time);
-            if age < 0 || age as u64 > max_staleness_secs {
-                return Err(PercolatorError::OracleStale.into());
-            }
+        // Cap dt to bound accumulated movement after crank pauses
+        let capped_dt = dt_slots.min(MAX_CLAMP_DT_SLOTS);
+
+        let max_delta_u128 = (index as u128)
+            .saturating_mul(cap_bps as u128)
+            .saturating_mul(capped_dt as u128)
+            / 10_000u128;
+
+        let max_delta = core::cmp::min(max_delta_u128, u64::MAX as u128) as u64;
+        let lo = index.saturating_sub(max_delta);
+        let hi = index.saturating_add(max_delta);
+        mark.clamp(lo, hi)
+    }
+
+    /// Get engine oracle price (unified: external oracle vs Hyperp mode).
+    /// In Hyperp mode: updates index toward mark with rate limiting.
+    ///   Mark staleness enforced via last_mark_push_slot.
+    /// In external mode: reads the signed Pyth/Chainlink observation directly.
+    pub fn get_engine_oracle_price_e6(
+        engine_last_oracle_price: u64,
+        price_move_dt_slots: u64,
+        now_slot: u64,
+        now_unix_ts: i64,
+        config: &mut super::state::MarketConfig,
+        oracle_accounts: &[AccountInfo],
+        max_change_bps: u64,
+        oi_any: bool,
+    ) -> Result<u64, ProgramError> {
+        // Strict hard-timeout gate (applies to both Hyperp and non-Hyperp):
+        // once the oracle has been stale for >=
+        // permissionless_resolve_stale_slots, no price read succeeds.
+        // The market must be resolved before any further price-taking op.
+        if permissionless_stale_matured(config, now_slot) {
+            return Err(super::error::PercolatorError::OracleStale.into());
         }
+        // Hyperp mode: index_feed_id == 0
+        if is_hyperp_mode(config) {
+            // Mark source: prefer trade-derived EWMA, fall back to authority push
+            let mark = if config.mark_ewma_e6 > 0 {
+                config.mark_ewma_e6
+            } else {
+                config.hyperp_mark_e6
+            };
+            if mark == 0 {
+                return Err(super::error::PercolatorError::OracleInvalid.into());
+            }
+            // Staleness: keyed off last trade OR last authority push (whichever is newer)
+            let last_update = core::cmp::max(
+                config.mark_ewma_last_slot,
+                config.last_mark_push_slot as u64,
+            );
+            let last_push = last_update;
+            if last_push > 0 {
+                let max_stale_slots = if config.max_staleness_secs > u64::MAX / 3 {
+                    u64::MAX
+                } else {
+                    config.max_staleness_secs * 3
+                };
+                if now_slot.saturating_sub(last_push) > max_stale_slots {
+                    return Err(super::error::PercolatorError::OracleStale.into());
+                }
+            }
+
+            // Hyperp uses the same target/effective split as external
+            // oracles: mark/EWMA is the target, and the engine-fed index
+            // moves from engine P_last over the residual dt that the next
+            // accrue may legally consume. Do not key this off
+            // last_hyperp_index_slot; repeated partial catchups must advance
+            // from the engine's stored price and remaining accrual window.
+            let anchor = if engine_last_oracle_price != 0 {
+                engine_last_oracle_price
+            } else if config.last_effective_price_e6 != 0 {
+                config.last_effective_price_e6
+            } else {
+                mark
+            };
+            let new_index = effective_price_from_target(
+                anchor,
+                mark,
+                max_change_bps,
+                price_move_dt_slots,
+                oi_any,
+            );
 
-        // Confidence check (0 = disabled)
-        let price_u = price as u128;
-        if conf_bps != 0 {
-            let lhs = (conf as u128) * 10_000;
-            let rhs = price_u * (conf_bps as u128);
-            if lhs > rhs {
-                return Err(PercolatorError::OracleConfTooWide.into());
+            config.last_effective_price_e6 = new_index;
+            if new_index != anchor || new_index == mark {
+                config.last_hyperp_index_slot = now_slot;
             }
+            return Ok(new_index);
         }
 
-        // Convert to e6 format
-        let scale = expo + 6;
-        let final_price_u128 = if scale >= 0 {
-            let mul = 10u128.pow(scale as u32);
-            price_u
-                .checked_mul(mul)
-                .ok_or(PercolatorError::EngineOverflow)?
-        } else {
-            let div = 10u128.pow((-scale) as u32);
-            price_u / div
-        };
-
-        if final_price_u128 == 0 {
-            return Err(PercolatorError::OracleInvalid.into());
-        }
-        if final_price_u128 > u64::MAX as u128 {
-            return Err(PercolatorError::EngineOverflow.into());
-        }
-
-        Ok((final_price_u128 as u64, publish_time))
+        // Non-Hyperp: source signed Pyth/Chainlink price; the engine enforces
+        // the dt-scaled movement cap during accrual.
+        read_price_clamped(
+            config,
+            oracle_accounts,
+            now_unix_ts,
+            max_change_bps,
+            engine_last_oracle_price,
+            price_move_dt_slots,
+            oi_any,
+        )
+        .map(|(price, _advanced)| price)
     }
+}
 
-    /// Read price from a Chainlink OCR2 State/Aggregator account.
-    ///
-    /// Parameters:
-    /// - price_ai: The Chainlink aggregator account
-    /// - expected_feed_pubkey: The expected feed account pubkey (for validation)
-    /// - now_unix_ts: Current unix timestamp (from clock.unix_timestamp)
-    /// - max_staleness_secs: Maximum age in seconds
-    ///
-    /// Returns `(price_e6, observation_timestamp)` where the timestamp is
-    /// the Chainlink off-chain reporters' unix timestamp for this round.
-    /// The caller is expected to enforce monotonicity against any
-    /// previously-accepted timestamp — see `clamp_external_price`.
-    /// Note: Chainlink doesn't have confidence intervals, so conf_bps is not used.
-    pub fn read_chainlink_price_e6(
-        price_ai: &AccountInfo,
-        expected_feed_pubkey: &[u8; 32],
-        now_unix_ts: i64,
-        max_staleness_secs: u64,
-    ) -> Result<(u64, i64), ProgramError> {
-        // Validate oracle owner.
-        if *price_ai.owner != CHAINLINK_OCR2_PROGRAM_ID {
-            return Err(ProgramError::IllegalOwner);
-        }
+// 9. mod collateral
+pub mod collateral {
+    use solana_program::{account_info::AccountInfo, program_error::ProgramError};
 
-        // Validate feed pubkey matches expected
-        if price_ai.key.to_bytes() != *expected_feed_pubkey {
-            return Err(PercolatorError::InvalidOracleKey.into());
+    use solana_program::program::{invoke, invoke_signed};
+
+    pub fn deposit<'a>(
+        token_program: &AccountInfo<'a>,
+        source: &AccountInfo<'a>,
+        dest: &AccountInfo<'a>,
+        authority: &AccountInfo<'a>,
+        amount: u64,
+    ) -> Result<(), ProgramError> {
+        if amount == 0 {
+            return Ok(());
         }
+        let ix = spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            dest.key,
+            authority.key,
+            &[],
+            amount,
+        )?;
+        invoke(
+            &ix,
+            &[
+                source.clone(),
+                dest.clone(),
+                authority.clone(),
+                token_program.clone(),
+            ],
+        )
+    }
 
-        let data = price_ai.try_borrow_data()?;
-        if data.len() < CL_MIN_LEN {
-            return Err(ProgramError::InvalidAccountData);
+    pub fn withdraw<'a>(
+        token_program: &AccountInfo<'a>,
+        source: &AccountInfo<'a>,
+        dest: &AccountInfo<'a>,
+        authority: &AccountInfo<'a>,
+        amount: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<(), ProgramError> {
+        if amount == 0 {
+            return Ok(());
         }
+        let ix = spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            dest.key,
+            authority.key,
+            &[],
+            amount,
+        )?;
+        invoke_signed(
+            &ix,
+            &[
+                source.clone(),
+                dest.clone(),
+                authority.clone(),
+                token_program.clone(),
+            ],
+            signer_seeds,
+        )
+    }
+}
 
-        // Read header fields
-        let decimals = data[CL_OFF_DECIMALS];
+// 9. mod processor
+pub mod processor {
+    use crate::{
+        accounts, collateral,
+        constants::{
+            DEFAULT_FUNDING_HORIZON_SLOTS, DEFAULT_FUNDING_K_BPS, DEFAULT_FUNDING_MAX_E9_PER_SLOT,
+            DEFAULT_FUNDING_MAX_PREMIUM_BPS, DEFAULT_MARK_EWMA_HALFLIFE_SLOTS, MAGIC,
+            MATCHER_CALL_LEN, MATCHER_CALL_TAG, MAX_MATCHER_TAIL_ACCOUNTS, SLAB_LEN,
+        },
+        error::{clamp_u64, fail, map_risk_error, PercolatorError},
+        ix::Instruction,
+        oracle,
+        state::{self, MarketConfig, SlabHeader},
+        zc,
+    };
+    use bytemuck::Zeroable;
+    use percolator::{RiskEngine, RiskError};
 
-        // Read price data directly from fixed offsets
-        let timestamp = u64::from_le_bytes(
-            data[CL_OFF_TIMESTAMP..CL_OFF_TIMESTAMP + 8]
-                .try_into()
-                .unwrap(),
-        );
-        // Read answer as i128 (16 bytes), but only bottom 8 bytes are typically used
-        let answer =
-            i128::from_le_bytes(data[CL_OFF_ANSWER..CL_OFF_ANSWER + 16].try_into().unwrap());
+    // settle_and_close_resolved removed — replaced by engine
+    // force_close_resolved_with_fee_not_atomic(), which handles K-pair PnL,
+    // checked arithmetic, fee-after-loss ordering, and settlement internally.
 
-        if answer <= 0 {
-            return Err(PercolatorError::OracleInvalid.into());
+    /// Read oracle price for non-Hyperp markets and stamp
+    /// `last_good_oracle_slot`. Any Pyth/Chainlink parse error propagates
+    /// unchanged — there is no authority fallback.
+    ///
+    /// STRICT HARD-TIMEOUT GATE: if the hard stale window has matured
+    /// (clock.slot - last_good_oracle_slot >=
+    /// permissionless_resolve_stale_slots), this function rejects with
+    /// OracleStale even when a fresh external price is supplied. That
+    /// prevents price-taking instructions (Trade, Withdraw, Crank,
+    /// Settle, Convert, Catchup) from reviving a terminally dead market
+    /// — they must route to ResolvePermissionless instead.
+    fn read_price_and_stamp(
+        config: &mut state::MarketConfig,
+        oracle_accounts: &[AccountInfo],
+        clock_unix_ts: i64,
+        clock_slot: u64,
+        slab_data: &mut [u8],
+    ) -> Result<u64, ProgramError> {
+        if oracle::permissionless_stale_matured(config, clock_slot) {
+            return Err(PercolatorError::OracleStale.into());
         }
 
-        // SECURITY (C3): Bound decimals to prevent overflow in pow()
-        if decimals > MAX_EXPO_ABS as u8 {
-            return Err(PercolatorError::OracleInvalid.into());
-        }
+        // Source the per-slot price-move cap from RiskParams (init-
+        // immutable per spec §1.4 solvency envelope). Standard bps.
+        let (p_last, max_change_bps, price_move_dt_slots, oi_any) = {
+            let engine = zc::engine_ref(slab_data)?;
+            (
+                engine.last_oracle_price,
+                engine.params.max_price_move_bps_per_slot,
+                price_move_residual_dt(engine, clock_slot)?,
+                engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0,
+            )
+        };
 
-        // Staleness check
-        {
-            // Validate timestamp fits in i64 before cast (year 2262+ overflow)
-            if timestamp > i64::MAX as u64 {
-                return Err(PercolatorError::OracleStale.into());
+        // Snapshot the source-feed clock before the call so we can
+        // tell whether THIS read advanced state. Stale/duplicate
+        // observations get the cached price from
+        // `clamp_external_price` without advancing the timestamp; we
+        // must not stamp the liveness cursor on those — otherwise an
+        // attacker can replay an old Pyth account to extend market
+        // life past `permissionless_resolve_stale_slots`.
+        let price_advanced = match oracle::read_price_clamped(
+            config,
+            oracle_accounts,
+            clock_unix_ts,
+            max_change_bps,
+            p_last,
+            price_move_dt_slots,
+            oi_any,
+        ) {
+            Ok(v) => v,
+            Err(e)
+                if e == ProgramError::from(PercolatorError::OracleStale)
+                    && oracle::hybrid_soft_stale_matured(config, clock_slot) =>
+            {
+                let target = oracle::hybrid_after_hours_target(config);
+                if target == 0 {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
+                let anchor = if p_last != 0 {
+                    p_last
+                } else if config.last_effective_price_e6 != 0 {
+                    config.last_effective_price_e6
+                } else {
+                    target
+                };
+                let price = oracle::effective_price_from_target(
+                    anchor,
+                    target,
+                    max_change_bps,
+                    price_move_dt_slots,
+                    oi_any,
+                );
+                config.oracle_target_price_e6 = target;
+                config.last_effective_price_e6 = price;
+                if price != anchor || price == target {
+                    config.last_hyperp_index_slot = clock_slot;
+                }
+                return Ok(price);
             }
-            let age = now_unix_ts.saturating_sub(timestamp as i64);
-            if age < 0 || age as u64 > max_staleness_secs {
-                return Err(PercolatorError::OracleStale.into());
+            Err(e)
+                if e == ProgramError::from(PercolatorError::OracleStale)
+                    && state::read_stale_fallback(slab_data)
+                        .engaged(clock_slot, config.last_good_oracle_slot) =>
+            {
+                // Stale fallback: hold the last accepted price. Any pending
+                // target is dropped so the market is not left target-lagged
+                // behind a feed that is gone; no liveness stamp.
+                let price = if p_last != 0 {
+                    p_last
+                } else {
+                    config.last_effective_price_e6
+                };
+                if price == 0 {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
+                config.oracle_target_price_e6 = price;
+                config.last_effective_price_e6 = price;
+                return Ok(price);
             }
-        }
-
-        // Convert to e6 format
-        // Chainlink decimals work like: price = answer / 10^decimals
-        // We want e6, so: price_e6 = answer * 10^6 / 10^decimals = answer * 10^(6-decimals)
-        let price_u = answer as u128;
-        let scale = 6i32 - decimals as i32;
-        let final_price_u128 = if scale >= 0 {
-            let mul = 10u128.pow(scale as u32);
-            price_u
-                .checked_mul(mul)
-                .ok_or(PercolatorError::EngineOverflow)?
-        } else {
-            let div = 10u128.pow((-scale) as u32);
-            price_u / div
+            Err(e) => return Err(e),
         };
-
-        if final_price_u128 == 0 {
-            return Err(PercolatorError::OracleInvalid.into());
+        let (price, advanced) = price_advanced;
+        if advanced {
+            config.last_good_oracle_slot = clock_slot;
         }
-        if final_price_u128 > u64::MAX as u128 {
-            return Err(PercolatorError::EngineOverflow.into());
+        if oracle::is_hybrid_after_hours_mode(config)
+            && (advanced || config.mark_ewma_e6 != price || config.hyperp_mark_e6 != price)
+        {
+            // During regular hours, the trusted external composite owns the
+            // hybrid mark baseline. A fresh publish_time starts/updates the
+            // raw target, and duplicate reads may continue the already-
+            // accepted target/effective staircase without refreshing the
+            // liveness cursor. The mark must follow those accepted effective
+            // steps too; otherwise regular-hours duplicate cranks can create
+            // artificial mark/index funding divergence before after-hours
+            // fallback has actually started.
+            config.hyperp_mark_e6 = price;
+            config.mark_ewma_e6 = price;
+            config.mark_ewma_last_slot = clock_slot;
         }
-
-        Ok((final_price_u128 as u64, timestamp as i64))
+        Ok(price)
     }
 
-    /// Read a single raw oracle leg in e6, before final market-level
-    /// inversion/unit scaling.
-    pub fn read_raw_oracle_price_e6(
-        price_ai: &AccountInfo,
-        expected_feed_id: &[u8; 32],
-        now_unix_ts: i64,
-        max_staleness_secs: u64,
-        conf_bps: u16,
-    ) -> Result<(u64, i64), ProgramError> {
-        // Detect oracle type by account owner and dispatch
-        let (raw_price, publish_time) = if *price_ai.owner == PYTH_RECEIVER_PROGRAM_ID {
-            read_pyth_price_e6(
-                price_ai,
-                expected_feed_id,
-                now_unix_ts,
-                max_staleness_secs,
-                conf_bps,
-            )?
-        } else if *price_ai.owner == CHAINLINK_OCR2_PROGRAM_ID {
-            // Chainlink safety: the feed pubkey check ensures only the
-            // specific account stored in index_feed_id at InitMarket can be read.
-            // A different Chainlink-owned account would fail the pubkey match.
-            read_chainlink_price_e6(price_ai, expected_feed_id, now_unix_ts, max_staleness_secs)?
-        } else {
-            return Err(ProgramError::IllegalOwner);
-        };
+    /// Feed an accepted oracle price to the circuit breaker. The anchor
+    /// rolls forward once it is older than `window_slots`; a move past
+    /// `max_move_bps` starts (or extends) the cooling-off period and
+    /// re-anchors at the new price, so a continuing move keeps it tripped.
+    pub(crate) fn observe_circuit_breaker(slab_data: &mut [u8], slot: u64, price: u64) {
+        let mut cb = state::read_circuit_breaker(slab_data);
+        if cb.max_move_bps == 0 {
+            return;
+        }
+        let expired = slot.saturating_sub(cb.anchor_slot) > cb.window_slots;
+        if crate::policy::circuit_breaker_tripped(cb.anchor_price_e6, price, cb.max_move_bps)
+            && !expired
+        {
+            cb.cooling_until_slot = cb
+                .cooling_until_slot
+                .max(slot.saturating_add(cb.cooldown_slots));
+        } else if cb.anchor_price_e6 != 0 && !expired {
+            return;
+        }
+        cb.anchor_price_e6 = price;
+        cb.anchor_slot = slot;
+        state::write_circuit_breaker(slab_data, &cb);
+    }
 
-        if raw_price > percolator::MAX_ORACLE_PRICE {
-            return Err(PercolatorError::OracleInvalid.into());
-        }
-        Ok((raw_price, publish_time))
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct TradeExecution {
+        /// Actual execution price (may differ from oracle/requested price)
+        pub price: u64,
+        /// Actual executed size (may be partial fill)
+        pub size: i128,
     }
 
-    fn apply_engine_price_transform(
-        raw_price: u64,
-        invert: u8,
-        unit_scale: u32,
-    ) -> Result<u64, ProgramError> {
-        // Step 1: Apply inversion if configured (uses policy::invert_price_e6)
-        let price_after_invert = crate::policy::invert_price_e6(raw_price, invert)
-            .ok_or(PercolatorError::OracleInvalid)?;
+    /// Trait for pluggable matching engines
+    pub trait MatchingEngine {
+        fn execute_match(
+            &self,
+            lp_program: &[u8; 32],
+            lp_context: &[u8; 32],
+            lp_account_id: u64,
+            oracle_price: u64,
+            size: i128,
+        ) -> Result<TradeExecution, RiskError>;
+    }
 
-        // Step 2: Apply unit scaling if configured (uses policy::scale_price_e6)
-        // This ensures oracle-derived values match capital scale (stored in units)
-        let engine_price = crate::policy::scale_price_e6(price_after_invert, unit_scale)
-            .ok_or(PercolatorError::OracleInvalid)?;
+    /// No-op matching engine (for testing/TradeNoCpi)
+    pub struct NoOpMatcher;
 
-        // Enforce MAX_ORACLE_PRICE at ingress
-        if engine_price > percolator::MAX_ORACLE_PRICE {
-            return Err(PercolatorError::OracleInvalid.into());
+    impl MatchingEngine for NoOpMatcher {
+        fn execute_match(
+            &self,
+            _lp_program: &[u8; 32],
+            _lp_context: &[u8; 32],
+            _lp_account_id: u64,
+            oracle_price: u64,
+            size: i128,
+        ) -> Result<TradeExecution, RiskError> {
+            Ok(TradeExecution {
+                price: oracle_price,
+                size,
+            })
         }
-        Ok(engine_price)
     }
 
-    /// Read oracle price for engine use, applying inversion and unit scaling if configured.
-    ///
-    /// Automatically detects oracle type by account owner:
-    /// - PYTH_RECEIVER_PROGRAM_ID: reads Pyth PriceUpdateV2
-    /// - CHAINLINK_OCR2_PROGRAM_ID: reads Chainlink OCR2 Transmissions
-    ///
-    /// Transformations applied in order:
-    /// 1. If invert != 0: inverted price = 1e12 / raw_e6
-    /// 2. If unit_scale > 1: scaled price = price / unit_scale
-    ///
-    /// CRITICAL: The unit_scale transformation ensures oracle-derived values (entry_price,
-    /// mark_pnl, position_value) are in the same scale as capital (which is stored in units).
-    /// Without this scaling, margin checks would compare units to base tokens incorrectly.
-    ///
-    /// The raw oracle is validated (staleness, confidence for Pyth) BEFORE transformations.
-    pub fn read_engine_price_e6(
-        price_ai: &AccountInfo,
-        expected_feed_id: &[u8; 32],
-        now_unix_ts: i64,
-        max_staleness_secs: u64,
-        conf_bps: u16,
-        invert: u8,
-        unit_scale: u32,
-    ) -> Result<(u64, i64), ProgramError> {
-        let (raw_price, publish_time) = read_raw_oracle_price_e6(
-            price_ai,
-            expected_feed_id,
-            now_unix_ts,
-            max_staleness_secs,
-            conf_bps,
-        )?;
-        Ok((
-            apply_engine_price_transform(raw_price, invert, unit_scale)?,
-            publish_time,
-        ))
+    struct CpiMatcher {
+        exec_price: u64,
+        exec_size: i128,
     }
 
-    #[inline]
-    pub fn oracle_leg_count(config: &super::state::MarketConfig) -> Result<usize, ProgramError> {
-        match config.oracle_leg_count {
-            0 | 1 => Ok(1), // tolerate zeroed legacy configs as single-leg
-            2 => Ok(2),
-            3 => Ok(3),
-            _ => Err(PercolatorError::InvalidConfigParam.into()),
+    impl MatchingEngine for CpiMatcher {
+        fn execute_match(
+            &self,
+            _lp_program: &[u8; 32],
+            _lp_context: &[u8; 32],
+            _lp_account_id: u64,
+            _oracle_price: u64,
+            _size: i128,
+        ) -> Result<TradeExecution, RiskError> {
+            Ok(TradeExecution {
+                price: self.exec_price,
+                size: self.exec_size,
+            })
         }
     }
 
-    #[inline]
-    fn oracle_leg_feed_id(config: &super::state::MarketConfig, idx: usize) -> &[u8; 32] {
-        match idx {
-            0 => &config.index_feed_id,
-            1 => &config.oracle_leg2_feed_id,
-            _ => &config.oracle_leg3_feed_id,
+    /// Compute funding rate from mark-index premium (all market types).
+    /// Uses trade-derived EWMA mark vs oracle index.
+    /// Returns 0 if no trades yet (mark_ewma == 0) or params unset.
+    /// Compute funding rate in e9-per-slot (ppb) directly.
+    /// Avoids bps quantization: sub-bps rates are preserved as nonzero ppb values.
+    /// Realize due maintenance fees for a single account up to `now_slot`.
+    /// Pre-touch recurring maintenance fees are loss-senior only for accounts
+    /// that are already flat/current. Nonflat or stale accounts must first be
+    /// touched by an engine path that settles lazy A/K/F and side effects.
+    fn recurring_fee_pre_touch_safe(engine: &RiskEngine, idx: usize) -> Result<bool, ProgramError> {
+        if !idx_used_in_market(engine, idx) {
+            return Ok(false);
+        }
+        let acc = &engine.accounts[idx];
+        let fc = acc.fee_credits.get();
+        if acc.position_basis_q == i128::MIN || acc.pnl == i128::MIN || fc == i128::MIN || fc > 0 {
+            return Err(PercolatorError::EngineCorruptState.into());
+        }
+        let effective_pos_q = if acc.position_basis_q == 0 {
+            effective_pos_q_checked(engine, idx)?
+        } else {
+            1
+        };
+        Ok(crate::policy::recurring_fee_pre_touch_safe_shape(
+            true,
+            acc.position_basis_q,
+            effective_pos_q,
+            acc.pnl,
+            acc.reserved_pnl,
+            acc.sched_present,
+            acc.pending_present,
+            fc,
+        ))
+    }
+
+    /// Recurring fee sync for accounts that have already gone through an
+    /// authoritative engine local touch in this instruction. Unlike
+    /// pre-touch fee sweeping, this intentionally does not require the account
+    /// to be flat: the loss-senior precondition was satisfied by the preceding
+    /// `settle_account_not_atomic` / keeper touch, so nonflat current accounts
+    /// must become fee-current before health-sensitive actions.
+    fn sync_account_fee_after_authoritative_touch(
+        engine: &mut RiskEngine,
+        config: &MarketConfig,
+        idx: u16,
+        now_slot: u64,
+    ) -> Result<(), ProgramError> {
+        if config.maintenance_fee_per_slot == 0 {
+            return Ok(());
+        }
+        check_idx(engine, idx)?;
+        let is_resolved = engine_is_resolved(engine);
+        let resolved_slot = if is_resolved {
+            engine_resolved_context(engine).1
+        } else {
+            0
+        };
+        if !crate::policy::fee_sync_anchor_within_accrued_boundary(
+            is_resolved,
+            now_slot,
+            engine.last_market_slot,
+            resolved_slot,
+        ) {
+            return Err(PercolatorError::CatchupRequired.into());
         }
+        engine
+            .sync_account_fee_to_slot_not_atomic(idx, now_slot, config.maintenance_fee_per_slot)
+            .map_err(map_risk_error)
     }
 
-    #[inline]
-    fn oracle_leg_divides(config: &super::state::MarketConfig, idx: usize) -> bool {
-        match idx {
-            1 => (config.oracle_leg_flags & crate::constants::ORACLE_LEG_FLAG_DIVIDE_LEG2) != 0,
-            2 => (config.oracle_leg_flags & crate::constants::ORACLE_LEG_FLAG_DIVIDE_LEG3) != 0,
-            _ => false,
-        }
+    fn settle_account_then_sync_fee_current(
+        engine: &mut RiskEngine,
+        config: &MarketConfig,
+        idx: u16,
+        now_slot: u64,
+        price: u64,
+        funding_rate_e9: i128,
+        admit_h_min: u64,
+        admit_h_max: u64,
+        admit_threshold: Option<u128>,
+    ) -> Result<(), ProgramError> {
+        engine
+            .settle_account_not_atomic(
+                idx,
+                price,
+                now_slot,
+                funding_rate_e9,
+                admit_h_min,
+                admit_h_max,
+                admit_threshold,
+            )
+            .map_err(map_risk_error)?;
+        sync_account_fee_after_authoritative_touch(engine, config, idx, now_slot)
     }
 
-    pub fn oracle_leg_config_ok(
-        is_hyperp: bool,
-        count: u8,
-        flags: u8,
-        leg1: &[u8; 32],
-        leg2: &[u8; 32],
-        leg3: &[u8; 32],
-    ) -> bool {
-        if flags & !crate::constants::ORACLE_LEG_FLAGS_MASK != 0 {
-            return false;
-        }
-        if is_hyperp {
-            return count == 1 && flags == 0 && *leg2 == [0u8; 32] && *leg3 == [0u8; 32];
-        }
-        if count == 0 || count > crate::constants::ORACLE_LEG_CAP as u8 || *leg1 == [0u8; 32] {
-            return false;
-        }
-        if count == 1 {
-            return flags == 0 && *leg2 == [0u8; 32] && *leg3 == [0u8; 32];
-        }
-        if *leg2 == [0u8; 32] || leg2 == leg1 {
-            return false;
-        }
-        if count == 2 {
-            return (flags & crate::constants::ORACLE_LEG_FLAG_DIVIDE_LEG3) == 0
-                && *leg3 == [0u8; 32];
-        }
-        if *leg3 == [0u8; 32] || leg3 == leg1 || leg3 == leg2 {
-            return false;
-        }
-        true
+    fn settle_pair_then_sync_fee_current(
+        engine: &mut RiskEngine,
+        config: &MarketConfig,
+        a: u16,
+        b: u16,
+        now_slot: u64,
+        price: u64,
+        funding_rate_e9: i128,
+        admit_h_min: u64,
+        admit_h_max: u64,
+        admit_threshold: Option<u128>,
+    ) -> Result<(), ProgramError> {
+        let (first, second) = if a <= b { (a, b) } else { (b, a) };
+        settle_account_then_sync_fee_current(
+            engine,
+            config,
+            first,
+            now_slot,
+            price,
+            funding_rate_e9,
+            admit_h_min,
+            admit_h_max,
+            admit_threshold,
+        )?;
+        settle_account_then_sync_fee_current(
+            engine,
+            config,
+            second,
+            now_slot,
+            price,
+            funding_rate_e9,
+            admit_h_min,
+            admit_h_max,
+            admit_threshold,
+        )
     }
 
-    fn compose_oracle_leg(acc_e6: u64, leg_e6: u64, divide: bool) -> Result<u64, ProgramError> {
-        if leg_e6 == 0 {
-            return Err(PercolatorError::OracleInvalid.into());
-        }
-        let next = if divide {
-            (acc_e6 as u128)
-                .checked_mul(1_000_000u128)
-                .ok_or(PercolatorError::EngineOverflow)?
-                / (leg_e6 as u128)
-        } else {
-            (acc_e6 as u128)
-                .checked_mul(leg_e6 as u128)
-                .ok_or(PercolatorError::EngineOverflow)?
-                / 1_000_000u128
-        };
-        if next == 0 {
-            return Err(PercolatorError::OracleInvalid.into());
+    /// Fee-sync variant for no-oracle instructions. Caps the fee anchor
+    /// at `engine.last_market_slot`, leaving full realization of fees
+    /// accrued over `[last_market_slot, clock.slot]` to the next
+    /// oracle-backed instruction. Prevents the `current_slot >
+    /// last_market_slot` split that would otherwise brick later
+    /// accrual.
+    ///
+    /// Acceptable trade-off: fees from the unaccrued tail are realized
+    /// slightly later (on the next trade/crank/withdraw) instead of now.
+    /// Correctness is preserved because the engine's per-account
+    /// `last_fee_slot` still advances monotonically to the
+    /// already-accrued boundary; subsequent sync calls cover the rest.
+    fn sync_account_fee_bounded_to_market(
+        engine: &mut RiskEngine,
+        config: &MarketConfig,
+        idx: u16,
+        wallclock_slot: u64,
+    ) -> Result<(), ProgramError> {
+        if config.maintenance_fee_per_slot == 0 {
+            return Ok(());
         }
-        if next > percolator::MAX_ORACLE_PRICE as u128 {
-            return Err(PercolatorError::OracleInvalid.into());
+        check_idx(engine, idx)?;
+        if !recurring_fee_pre_touch_safe(engine, idx as usize)? {
+            return Ok(());
         }
-        Ok(next as u64)
+        let Some(anchor) = crate::policy::no_oracle_fee_sync_anchor(
+            wallclock_slot,
+            engine.last_market_slot,
+            engine.current_slot,
+            engine.accounts[idx as usize].last_fee_slot,
+        ) else {
+            return Ok(());
+        };
+        engine
+            .sync_account_fee_to_slot_not_atomic(idx, anchor, config.maintenance_fee_per_slot)
+            .map_err(map_risk_error)
     }
 
-    /// Read the configured external oracle, which may be a synthetic cross
-    /// composed from up to 3 legs. Returns `(engine_price_e6, publish_time,
-    /// advanced)`. `advanced` is true only when at least one active leg has a
-    /// strictly newer publish time than the one already stored in config.
-    ///
-    /// Composite mode stores per-leg publish times/prices and rejects rollback:
-    /// a caller cannot pair a fresh leg with an older already-observed leg to
-    /// manufacture a cross. All supplied leg accounts must still be fresh under
-    /// `max_staleness_secs`.
-    pub fn read_external_price_e6(
-        config: &mut super::state::MarketConfig,
-        oracle_accounts: &[AccountInfo],
-        now_unix_ts: i64,
-    ) -> Result<(u64, i64, bool), ProgramError> {
-        let count = oracle_leg_count(config)?;
-        if oracle_accounts.len() < count {
-            return Err(ProgramError::NotEnoughAccountKeys);
-        }
-        if !oracle_leg_config_ok(
-            false,
-            count as u8,
-            config.oracle_leg_flags,
-            &config.index_feed_id,
-            &config.oracle_leg2_feed_id,
-            &config.oracle_leg3_feed_id,
-        ) {
-            return Err(PercolatorError::InvalidConfigParam.into());
+    fn check_no_oracle_live_envelope(
+        engine: &RiskEngine,
+        wallclock_slot: u64,
+    ) -> Result<(), ProgramError> {
+        let gap = wallclock_slot
+            .checked_sub(engine.last_market_slot)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+        if oi_any && gap > engine.params.max_accrual_dt_slots {
+            return Err(PercolatorError::CatchupRequired.into());
         }
+        Ok(())
+    }
 
-        let mut acc: u64 = 0;
-        let mut max_publish_time = i64::MIN;
-        let mut advanced = false;
+    fn price_move_residual_dt(
+        engine: &RiskEngine,
+        wallclock_slot: u64,
+    ) -> Result<u64, ProgramError> {
+        price_move_residual_dt_from_parts(
+            engine.last_market_slot,
+            engine.params.max_accrual_dt_slots,
+            wallclock_slot,
+        )
+    }
 
-        let mut i = 0usize;
-        while i < count {
-            let (price, publish_time) = read_raw_oracle_price_e6(
-                &oracle_accounts[i],
-                oracle_leg_feed_id(config, i),
-                now_unix_ts,
-                config.max_staleness_secs,
-                config.conf_filter_bps,
-            )?;
-            let prev_time = config.oracle_leg_publish_times[i];
-            let prev_price = config.oracle_leg_prices_e6[i];
-            if prev_time != 0 {
-                if publish_time < prev_time {
-                    return Err(PercolatorError::OracleStale.into());
-                }
-                if publish_time == prev_time && prev_price != 0 && price != prev_price {
-                    return Err(PercolatorError::OracleInvalid.into());
-                }
-            }
-            if publish_time > prev_time {
-                config.oracle_leg_publish_times[i] = publish_time;
-                config.oracle_leg_prices_e6[i] = price;
-                advanced = true;
-            }
-            max_publish_time = core::cmp::max(max_publish_time, publish_time);
-            acc = if i == 0 {
-                price
-            } else {
-                compose_oracle_leg(acc, price, oracle_leg_divides(config, i))?
-            };
-            i += 1;
+    fn price_move_residual_dt_from_parts(
+        last_market_slot: u64,
+        max_dt: u64,
+        wallclock_slot: u64,
+    ) -> Result<u64, ProgramError> {
+        let gap = wallclock_slot
+            .checked_sub(last_market_slot)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        if max_dt == 0 || gap <= max_dt {
+            return Ok(gap);
         }
-
-        Ok((
-            apply_engine_price_transform(acc, config.invert, config.unit_scale)?,
-            max_publish_time,
-            advanced,
-        ))
+        let rem = gap % max_dt;
+        Ok(if rem == 0 { max_dt } else { rem })
     }
 
-    /// Clamp `raw_price` so it cannot move more than `max_change_e2bps` from `last_price`.
-    /// Units: 1_000_000 e2bps = 100%. 0 = disabled (no cap). last_price == 0 = first-time.
-    pub fn clamp_oracle_price(last_price: u64, raw_price: u64, max_change_bps: u64) -> u64 {
-        if max_change_bps == 0 || last_price == 0 {
-            return raw_price;
+    fn hyperp_target_price(config: &MarketConfig) -> u64 {
+        if config.mark_ewma_e6 > 0 {
+            config.mark_ewma_e6
+        } else {
+            config.hyperp_mark_e6
         }
-        let max_delta_128 = (last_price as u128) * (max_change_bps as u128) / 10_000;
-        let max_delta = core::cmp::min(max_delta_128, u64::MAX as u128) as u64;
-        let lower = last_price.saturating_sub(max_delta);
-        let upper = last_price.saturating_add(max_delta);
-        raw_price.clamp(lower, upper)
     }
 
-    /// Read the external (Pyth/Chainlink) oracle price.
-    ///
-    /// Pyth/Chainlink is the only price source for non-Hyperp markets.
-    /// Any parse error (stale, wide confidence, wrong feed, malformed)
-    /// propagates to the caller — no authority fallback. If Pyth is
-    /// terminally dead, the market freezes until `permissionless_resolve
-    /// _stale_slots` matures and settles at `engine.last_oracle_price`
-    /// via the Degenerate arm of ResolveMarket / ResolvePermissionless.
-    pub fn read_price_clamped(
-        config: &mut super::state::MarketConfig,
-        oracle_accounts: &[AccountInfo],
-        now_unix_ts: i64,
-        max_change_bps: u64,
-        p_last: u64,
-        price_move_dt_slots: u64,
-        oi_any: bool,
-    ) -> Result<(u64, bool), ProgramError> {
-        let external = read_external_price_e6(config, oracle_accounts, now_unix_ts);
-        clamp_external_price(
-            config,
-            external,
-            p_last,
-            max_change_bps,
-            price_move_dt_slots,
-            oi_any,
+    fn oracle_target_pending(config: &MarketConfig, engine: &RiskEngine) -> bool {
+        !crate::policy::user_value_op_allowed_after_accrual(
+            oracle::is_hyperp_mode(config),
+            config.oracle_target_price_e6,
+            hyperp_target_price(config),
+            engine.last_oracle_price,
         )
     }
 
-    /// Accept an already-parsed external observation into a target/effective split.
-    ///
-    /// Fresh source observations update `oracle_target_*` with the raw signed
-    /// target. The returned/stored effective price is then capped from engine
-    /// `P_last` toward that target by `max_change_bps * price_move_dt_slots`.
-    /// Duplicate or older observations do not replace the target, but they may
-    /// continue the staircase toward the already-persisted target as time moves.
-    pub fn clamp_external_price(
-        config: &mut super::state::MarketConfig,
-        external: Result<(u64, i64, bool), ProgramError>,
-        p_last: u64,
-        max_change_bps: u64,
-        price_move_dt_slots: u64,
-        oi_any: bool,
-    ) -> Result<(u64, bool), ProgramError> {
-        let (ext_price, publish_time, advanced) = external?;
-        if advanced {
-            config.oracle_target_price_e6 = ext_price;
-            config.oracle_target_publish_time = publish_time;
-            config.last_oracle_publish_time = publish_time;
-        } else if config.oracle_target_price_e6 == 0 {
-            config.oracle_target_price_e6 = ext_price;
-            config.oracle_target_publish_time = publish_time;
-            config.last_oracle_publish_time = publish_time;
+    fn reject_any_target_lag(
+        config: &MarketConfig,
+        engine: &RiskEngine,
+    ) -> Result<(), ProgramError> {
+        if engine.oi_eff_long_q == 0 && engine.oi_eff_short_q == 0 {
+            return Ok(());
         }
-
-        let target = config.oracle_target_price_e6;
-        let anchor = if p_last != 0 {
-            p_last
-        } else if config.last_effective_price_e6 != 0 {
-            config.last_effective_price_e6
-        } else {
-            target
-        };
-        let effective = effective_price_from_target(
-            anchor,
-            target,
-            max_change_bps,
-            price_move_dt_slots,
-            oi_any,
-        );
-        config.last_effective_price_e6 = effective;
-        Ok((effective, advanced))
+        if oracle_target_pending(config, engine) {
+            return Err(PercolatorError::CatchupRequired.into());
+        }
+        Ok(())
     }
 
-    // =========================================================================
-    // Hyperp mode helpers (internal mark/index, no external oracle)
-    // =========================================================================
-
-    /// Check if Hyperp mode is active (internal mark/index pricing).
-    /// Hyperp mode is active when index_feed_id is all zeros.
     #[inline]
-    pub fn is_hyperp_mode(config: &super::state::MarketConfig) -> bool {
-        config.index_feed_id == [0u8; 32]
+    fn engine_is_resolved(engine: &RiskEngine) -> bool {
+        engine.market_mode == percolator::MarketMode::Resolved
     }
 
-    /// Hybrid after-hours mode: an external-oracle market that reserves the
-    /// dynamic Hyperp fee headroom. Fresh external reads own the index during
-    /// market hours; after the wrapper's own last-good external read becomes
-    /// soft-stale, price-taking paths fall back to the trade-derived EWMA mark
-    /// and charge the dynamic mark-movement fee.
     #[inline]
-    pub fn is_hybrid_after_hours_mode(config: &super::state::MarketConfig) -> bool {
-        !is_hyperp_mode(config)
-            && config.trade_fee_mode == crate::constants::TRADE_FEE_MODE_HYBRID_AFTER_HOURS
+    fn engine_resolved_context(engine: &RiskEngine) -> (u64, u64) {
+        (engine.resolved_price, engine.resolved_slot)
     }
 
     #[inline]
-    pub fn hybrid_soft_stale_matured(config: &super::state::MarketConfig, clock_slot: u64) -> bool {
-        if !is_hybrid_after_hours_mode(config) {
+    fn engine_is_used(engine: &RiskEngine, idx: usize) -> bool {
+        if idx >= percolator::MAX_ACCOUNTS {
             return false;
         }
-        let max_stale_slots = config.max_staleness_secs.saturating_mul(3);
-        clock_slot.saturating_sub(config.last_good_oracle_slot) > max_stale_slots
+        ((engine.used[idx >> 6] >> (idx & 63)) & 1) == 1
     }
 
-    #[inline]
-    pub fn hybrid_after_hours_target(config: &super::state::MarketConfig) -> u64 {
-        if config.mark_ewma_e6 > 0 {
-            config.mark_ewma_e6
-        } else if config.hyperp_mark_e6 > 0 {
-            config.hyperp_mark_e6
-        } else {
-            config.last_effective_price_e6
+    fn effective_pos_q_checked(engine: &RiskEngine, idx: usize) -> Result<i128, ProgramError> {
+        if idx >= percolator::MAX_ACCOUNTS || (idx as u64) >= engine.params.max_accounts {
+            return Err(PercolatorError::EngineAccountNotFound.into());
         }
-    }
-
-    /// Hard-timeout predicate: has the market's configured oracle been
-    /// stale for >= permissionless_resolve_stale_slots?
-    ///
-    /// Returns false when permissionless_resolve_stale_slots == 0
-    /// (feature disabled — admin-only resolution).
-    ///
-    /// "Liveness slot" is:
-    ///   non-Hyperp → config.last_good_oracle_slot (advances on successful
-    ///                external Pyth/Chainlink reads)
-    ///   Hyperp     → config.last_mark_push_slot (advances ONLY on
-    ///                full-weight mark observations: PushHyperpMark,
-    ///                or a TradeCpi fill whose fee paid the mark_min
-    ///                _fee threshold). mark_ewma_last_slot is the
-    ///                EWMA-math clock, NOT a liveness signal —
-    ///                partial-fee sub-threshold trades advance the
-    ///                EWMA clock so dt stays correct for weighting,
-    ///                but they must NOT extend market life.
-    ///
-    /// Once this returns true, the market is DEAD: ResolvePermissionless
-    /// may be called, and every price-taking live instruction
-    /// (read_price_and_stamp for non-Hyperp, get_engine_oracle_price_e6
-    /// for Hyperp) rejects further price reads to prevent state drift
-    /// before terminal resolution.
-    pub fn permissionless_stale_matured(
-        config: &super::state::MarketConfig,
-        clock_slot: u64,
-    ) -> bool {
-        // Cluster-restart gate (SIMD-0047 `LastRestartSlot` sysvar):
-        // any hard-fork restart after `InitMarket` freezes the market
-        // unconditionally, even when slot-based staleness is disabled.
-        // Resolution flows through the Degenerate arm and settles at the
-        // last cached pre-restart oracle price.
-        if cluster_restarted_since_init(config) {
-            return true;
+        if !engine_is_used(engine, idx) {
+            return Err(PercolatorError::EngineAccountNotFound.into());
         }
-        if config.permissionless_resolve_stale_slots == 0 {
-            return false;
+
+        let account = &engine.accounts[idx];
+        let basis = account.position_basis_q;
+        if basis == 0 {
+            return Ok(0);
         }
-        let last_live_slot = if is_hyperp_mode(config) {
-            config.last_mark_push_slot as u64
+
+        let (epoch_side, side_mode, a_side) = if basis > 0 {
+            (
+                engine.adl_epoch_long,
+                engine.side_mode_long,
+                engine.adl_mult_long,
+            )
+        } else if basis < 0 {
+            (
+                engine.adl_epoch_short,
+                engine.side_mode_short,
+                engine.adl_mult_short,
+            )
         } else {
-            config.last_good_oracle_slot
+            return Ok(0);
         };
-        clock_slot.saturating_sub(last_live_slot) >= config.permissionless_resolve_stale_slots
-    }
 
-    /// Pure comparison the on-chain path uses after reading the sysvar.
-    /// Separated so proof harnesses can check it symbolically without stubbing syscalls.
-    #[inline]
-    pub fn restart_detected(init_restart_slot: u64, current_last_restart_slot: u64) -> bool {
-        current_last_restart_slot > init_restart_slot
-    }
+        if account.adl_epoch_snap != epoch_side {
+            if side_mode != percolator::SideMode::ResetPending
+                || account.adl_epoch_snap.checked_add(1) != Some(epoch_side)
+            {
+                return Err(PercolatorError::EngineCorruptState.into());
+            }
+            return Ok(0);
+        }
 
-    /// On-chain restart check. Invokes `sol_get_last_restart_slot` and
-    /// compares against the slot captured at `InitMarket`. Returns false
-    /// under `cfg(kani)` so verification harnesses don't need to stub the
-    /// syscall — the pure comparison is proved separately via
-    /// `restart_detected`.
-    #[cfg(not(feature = "kani"))]
-    #[inline]
-    pub fn cluster_restarted_since_init(config: &super::state::MarketConfig) -> bool {
-        use solana_program::sysvar::last_restart_slot::LastRestartSlot;
-        use solana_program::sysvar::Sysvar;
-        match LastRestartSlot::get() {
-            Ok(lrs) => restart_detected(config.init_restart_slot, lrs.last_restart_slot),
-            Err(_) => false,
+        if account.adl_a_basis == 0 {
+            return Err(PercolatorError::EngineCorruptState.into());
+        }
+
+        let effective_abs = percolator::wide_math::mul_div_floor_u128(
+            basis.unsigned_abs(),
+            a_side,
+            account.adl_a_basis,
+        );
+        if effective_abs > i128::MAX as u128 {
+            return Err(PercolatorError::EngineCorruptState.into());
+        }
+        if basis < 0 {
+            if effective_abs == 0 {
+                Ok(0)
+            } else {
+                Ok(-(effective_abs as i128))
+            }
+        } else {
+            Ok(effective_abs as i128)
         }
     }
 
-    #[cfg(feature = "kani")]
-    #[inline]
-    pub fn cluster_restarted_since_init(_config: &super::state::MarketConfig) -> bool {
-        false
+    fn risk_notional_ceil(eff: i128, price: u64) -> u128 {
+        percolator::wide_math::mul_div_ceil_u128(
+            eff.unsigned_abs(),
+            price as u128,
+            percolator::POS_SCALE,
+        )
     }
 
-    /// External-oracle target/effective staircase. Unlike the Hyperp helper
-    /// below, this intentionally does not cap accumulated dt; the caller passes
-    /// the engine-relevant residual dt for the actual accrual step.
-    pub fn clamp_toward_engine_dt(p_last: u64, target: u64, cap_bps: u64, dt_slots: u64) -> u64 {
-        if p_last == 0 || target == 0 {
-            return target;
+    fn current_trade_fee_paid_cap(
+        size: i128,
+        exec_price: u64,
+        trading_fee_bps: u64,
+    ) -> Result<u128, ProgramError> {
+        if trading_fee_bps == 0 || size == 0 {
+            return Ok(0);
         }
-        if cap_bps == 0 || dt_slots == 0 {
-            return p_last;
+        let notional = safe_mul_div_floor_u128(
+            size.unsigned_abs(),
+            exec_price as u128,
+            percolator::POS_SCALE,
+        )?;
+        if notional == 0 {
+            return Ok(0);
         }
+        let one_side_fee = safe_mul_div_ceil_u128(notional, trading_fee_bps as u128, 10_000)?;
+        one_side_fee
+            .checked_mul(2)
+            .ok_or_else(|| PercolatorError::EngineOverflow.into())
+    }
 
-        let max_delta_u128 = (p_last as u128)
-            .saturating_mul(cap_bps as u128)
-            .saturating_mul(dt_slots as u128)
-            / 10_000u128;
-        let max_delta = core::cmp::min(max_delta_u128, u64::MAX as u128) as u64;
-        if target > p_last {
-            core::cmp::min(target, p_last.saturating_add(max_delta))
-        } else {
-            core::cmp::max(target, p_last.saturating_sub(max_delta))
+    fn trade_fee_bps_for_execution(
+        config: &MarketConfig,
+        engine: &RiskEngine,
+        now_slot: u64,
+        oracle_price: u64,
+        exec_price: u64,
+        trade_size: i128,
+    ) -> Result<u64, ProgramError> {
+        let hybrid_after_hours_fallback = oracle::is_hybrid_after_hours_mode(config)
+            && oracle::hybrid_soft_stale_matured(config, now_slot);
+        let dynamic_mark_fee_enabled = (oracle::is_hyperp_mode(config)
+            || hybrid_after_hours_fallback)
+            && config.trade_fee_base_bps < engine.params.max_trading_fee_bps;
+        if !dynamic_mark_fee_enabled {
+            return Ok(config.trade_fee_base_bps);
         }
-    }
 
-    /// Spec §3 target/effective price law used by both external-oracle and
-    /// Hyperp ingress. With exposed OI, the engine receives a capped staircase
-    /// price from its current effective price toward the raw target. With no
-    /// OI, the wrapper may adopt the raw target directly because no live
-    /// position can lose equity from the jump.
-    #[inline]
-    pub fn effective_price_from_target(
-        anchor: u64,
-        target: u64,
-        max_change_bps: u64,
-        price_move_dt_slots: u64,
-        oi_any: bool,
-    ) -> u64 {
-        if oi_any {
-            clamp_toward_engine_dt(anchor, target, max_change_bps, price_move_dt_slots)
+        let trade_notional = safe_mul_div_floor_u128(
+            trade_size.unsigned_abs(),
+            exec_price as u128,
+            percolator::POS_SCALE,
+        )?;
+        let clamped_exec = oracle::clamp_oracle_price(
+            crate::policy::mark_ewma_clamp_base(config.last_effective_price_e6),
+            exec_price,
+            engine.params.max_price_move_bps_per_slot,
+        );
+        let max_side_oi_q = core::cmp::max(engine.oi_eff_long_q, engine.oi_eff_short_q);
+        let max_side_notional =
+            safe_mul_div_ceil_u128(max_side_oi_q, oracle_price as u128, percolator::POS_SCALE)?;
+        let mark_externality_notional = core::cmp::max(max_side_notional, trade_notional)
+            .checked_mul(2)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        let min_externality_bps = if oracle::is_hybrid_after_hours_mode(config)
+            && oracle::hybrid_soft_stale_matured(config, now_slot)
+        {
+            engine.params.max_price_move_bps_per_slot
         } else {
-            target
-        }
+            0
+        };
+        crate::policy::dynamic_fee_bps_with_externality_floor(
+            config.trade_fee_base_bps,
+            config.mark_ewma_e6,
+            clamped_exec,
+            config.mark_ewma_halflife_slots,
+            config.mark_ewma_last_slot,
+            now_slot,
+            trade_notional,
+            mark_externality_notional,
+            config.mark_min_fee,
+            min_externality_bps,
+        )
+        .ok_or(PercolatorError::InvalidConfigParam.into())
     }
 
-    /// Move `index` toward `mark`, but clamp movement by cap_bps * dt_slots.
-    /// cap_bps units: standard bps (10_000 = 100%).
-    /// Returns the new index value.
-    ///
-    /// Security: When dt_slots == 0 (same slot) or cap_bps == 0 (cap disabled),
-    /// returns index unchanged to prevent bypassing rate limits.
-    /// Maximum effective dt for rate-limiting. Caps accumulated movement to
-    /// prevent a crank pause from allowing a full-magnitude index jump.
-    /// ~1 hour at 2.5 slots/sec = 9000 slots.
-    const MAX_CLAMP_DT_SLOTS: u64 = 9_000;
+    #[inline]
+    fn trade_flow_mark_observation_enabled(config: &MarketConfig, now_slot: u64) -> bool {
+        // Ordinary external markets and Hyperp markets use trade flow as the
+        // mark/funding observation source. Hybrid markets are different:
+        // regular-hours fresh/duplicate oracle reads own the mark baseline,
+        // and trade flow may update the EWMA only once the wrapper is actually
+        // in stale-oracle after-hours fallback.
+        !oracle::is_hybrid_after_hours_mode(config)
+            || oracle::hybrid_soft_stale_matured(config, now_slot)
+    }
 
-    pub fn clamp_toward_with_dt(index: u64, mark: u64, cap_bps: u64, dt_slots: u64) -> u64 {
-        if index == 0 {
-            return mark;
-        }
-        if cap_bps == 0 || dt_slots == 0 {
-            return index;
+    fn safe_mul_div_floor_u128(a: u128, b: u128, d: u128) -> Result<u128, ProgramError> {
+        if d == 0 {
+            return Err(PercolatorError::EngineOverflow.into());
         }
+        let q = percolator::wide_math::mul_div_floor_u256(
+            percolator::wide_math::U256::from_u128(a),
+            percolator::wide_math::U256::from_u128(b),
+            percolator::wide_math::U256::from_u128(d),
+        );
+        q.try_into_u128()
+            .ok_or_else(|| PercolatorError::EngineOverflow.into())
+    }
 
-        // Cap dt to bound accumulated movement after crank pauses
-        let capped_dt = dt_slots.min(MAX_CLAMP_DT_SLOTS);
-
-        let max_delta_u128 = (index as u128)
-            .saturating_mul(cap_bps as u128)
-            .saturating_mul(capped_dt as u128)
-            / 10_000u128;
-
-        let max_delta = core::cmp::min(max_delta_u128, u64::MAX as u128) as u64;
-        let lo = index.saturating_sub(max_delta);
-        let hi = index.saturating_add(max_delta);
-        mark.clamp(lo, hi)
+    fn safe_mul_div_ceil_u128(a: u128, b: u128, d: u128) -> Result<u128, ProgramError> {
+        if d == 0 {
+            return Err(PercolatorError::EngineOverflow.into());
+        }
+        let q = percolator::wide_math::checked_mul_div_ceil_u256(
+            percolator::wide_math::U256::from_u128(a),
+            percolator::wide_math::U256::from_u128(b),
+            percolator::wide_math::U256::from_u128(d),
+        )
+        .ok_or(PercolatorError::EngineOverflow)?;
+        q.try_into_u128()
+            .ok_or_else(|| PercolatorError::EngineOverflow.into())
     }
 
-    /// Get engine oracle price (unified: external oracle vs Hyperp mode).
-    /// In Hyperp mode: updates index toward mark with rate limiting.
-    ///   Mark staleness enforced via last_mark_push_slot.
-    /// In external mode: reads the signed Pyth/Chainlink observation directly.
-    pub fn get_engine_oracle_price_e6(
-        engine_last_oracle_price: u64,
-        price_move_dt_slots: u64,
+    fn reject_stuck_target_accrual(
+        config: &MarketConfig,
+        engine: &RiskEngine,
         now_slot: u64,
-        now_unix_ts: i64,
-        config: &mut super::state::MarketConfig,
-        oracle_accounts: &[AccountInfo],
-        max_change_bps: u64,
-        oi_any: bool,
-    ) -> Result<u64, ProgramError> {
-        // Strict hard-timeout gate (applies to both Hyperp and non-Hyperp):
-        // once the oracle has been stale for >=
-        // permissionless_resolve_stale_slots, no price read succeeds.
-        // The market must be resolved before any further price-taking op.
-        if permissionless_stale_matured(config, now_slot) {
-            return Err(super::error::PercolatorError::OracleStale.into());
+        price: u64,
+    ) -> Result<(), ProgramError> {
+        let dt = now_slot
+            .checked_sub(engine.last_market_slot)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+        if dt > 0
+            && oi_any
+            && oracle_target_pending(config, engine)
+            && price == engine.last_oracle_price
+        {
+            return Err(PercolatorError::CatchupRequired.into());
         }
-        // Hyperp mode: index_feed_id == 0
-        if is_hyperp_mode(config) {
-            // Mark source: prefer trade-derived EWMA, fall back to authority push
-            let mark = if config.mark_ewma_e6 > 0 {
-                config.mark_ewma_e6
-            } else {
-                config.hyperp_mark_e6
-            };
-            if mark == 0 {
-                return Err(super::error::PercolatorError::OracleInvalid.into());
-            }
-            // Staleness: keyed off last trade OR last authority push (whichever is newer)
-            let last_update = core::cmp::max(
-                config.mark_ewma_last_slot,
-                config.last_mark_push_slot as u64,
-            );
-            let last_push = last_update;
-            if last_push > 0 {
-                let max_stale_slots = if config.max_staleness_secs > u64::MAX / 3 {
-                    u64::MAX
-                } else {
-                    config.max_staleness_secs * 3
-                };
-                if now_slot.saturating_sub(last_push) > max_stale_slots {
-                    return Err(super::error::PercolatorError::OracleStale.into());
-                }
-            }
-
-            // Hyperp uses the same target/effective split as external
-            // oracles: mark/EWMA is the target, and the engine-fed index
-            // moves from engine P_last over the residual dt that the next
-            // accrue may legally consume. Do not key this off
-            // last_hyperp_index_slot; repeated partial catchups must advance
-            // from the engine's stored price and remaining accrual window.
-            let anchor = if engine_last_oracle_price != 0 {
-                engine_last_oracle_price
-            } else if config.last_effective_price_e6 != 0 {
-                config.last_effective_price_e6
-            } else {
-                mark
-            };
-            let new_index = effective_price_from_target(
-                anchor,
-                mark,
-                max_change_bps,
-                price_move_dt_slots,
-                oi_any,
-            );
+        Ok(())
+    }
 
-            config.last_effective_price_e6 = new_index;
-            if new_index != anchor || new_index == mark {
-                config.last_hyperp_index_slot = now_slot;
-            }
-            return Ok(new_index);
+    /// Whether `client_order_id` is already in `user_idx`'s recent-id ring.
+    /// Out-of-range indices are left for `check_idx` to reject.
+    fn is_duplicate_order(data: &[u8], user_idx: u16, client_order_id: u64) -> bool {
+        if client_order_id == 0 || user_idx as usize >= percolator::MAX_ACCOUNTS {
+            return false;
+        }
+        let dup =
+            crate::policy::order_id_seen(&state::read_order_ids(data, user_idx), client_order_id);
+        if dup {
+            solana_program::log::sol_log("duplicate client_order_id: no-op");
         }
+        dup
+    }
 
-        // Non-Hyperp: source signed Pyth/Chainlink price; the engine enforces
-        // the dt-scaled movement cap during accrual.
-        read_price_clamped(
-            config,
-            oracle_accounts,
-            now_unix_ts,
-            max_change_bps,
-            engine_last_oracle_price,
-            price_move_dt_slots,
-            oi_any,
-        )
-        .map(|(price, _advanced)| price)
+    fn record_order_id(data: &mut [u8], user_idx: u16, client_order_id: u64) {
+        if client_order_id == 0 {
+            return;
+        }
+        let mut ring = state::read_order_ids(data, user_idx);
+        crate::policy::order_id_push(&mut ring, client_order_id);
+        state::write_order_ids(data, user_idx, &ring);
     }
-}
 
-// 9. mod collateral
-pub mod collateral {
-    use solana_program::{account_info::AccountInfo, program_error::ProgramError};
+    /// Split a fill's trading fee, which the engine books entirely to
+    /// insurance. The LP share (`10_000 - insurance_bps - treasury_bps`)
+    /// moves from insurance to the fill's LP account; insurance keeps the
+    /// rest, of which the treasury share is returned as `(treasury, lp)`
+    /// for the caller to add to the ClaimProtocolFees accumulator.
+    fn route_trade_fee(
+        engine: &mut RiskEngine,
+        split: &state::FeeSplit,
+        lp_idx: u16,
+        fee: u128,
+        now_slot: u64,
+    ) -> Result<(u128, u128), ProgramError> {
+        if !split.enabled || fee == 0 {
+            return Ok((0, 0));
+        }
+        let (_, treasury, lp) =
+            crate::policy::fee_split(fee, split.insurance_bps, split.treasury_bps);
+        if lp > 0 {
+            engine
+                .credit_account_from_insurance_not_atomic(lp_idx, lp, now_slot)
+                .map_err(map_risk_error)?;
+        }
+        Ok((treasury, lp))
+    }
 
-    use solana_program::program::{invoke, invoke_signed};
+    /// Index of the backstop vault's LP account, if the vault has one and
+    /// the slot still holds the account the vault created.
+    fn backstop_vault_idx(data: &[u8]) -> Option<u16> {
+        let b = state::read_backstop(data);
+        let live = b.generation != 0
+            && (b.vault_idx as usize) < percolator::MAX_ACCOUNTS
+            && state::read_account_generation(data, b.vault_idx) == b.generation;
+        live.then_some(b.vault_idx)
+    }
 
-    pub fn deposit<'a>(
-        token_program: &AccountInfo<'a>,
-        source: &AccountInfo<'a>,
-        dest: &AccountInfo<'a>,
-        authority: &AccountInfo<'a>,
-        amount: u64,
-    ) -> Result<(), ProgramError> {
-        if amount == 0 {
-            return Ok(());
+    /// Pay the backstop vault `share_bps` of the insurance growth across a
+    /// crank's liquidation pass (`ins_before` to now). Net growth only: a
+    /// pass whose bankruptcies cost insurance more than its fees brought
+    /// in pays nothing. Returns the amount credited.
+    fn pay_backstop_vault(
+        engine: &mut RiskEngine,
+        vault_idx: u16,
+        share_bps: u16,
+        ins_before: u128,
+        now_slot: u64,
+    ) -> Result<u128, ProgramError> {
+        if !idx_used_in_market(engine, vault_idx as usize)
+            || engine.accounts[vault_idx as usize].kind != percolator::Account::KIND_LP
+        {
+            return Ok(0);
         }
-        let ix = spl_token::instruction::transfer(
-            token_program.key,
-            source.key,
-            dest.key,
-            authority.key,
-            &[],
-            amount,
-        )?;
-        invoke(
-            &ix,
-            &[
-                source.clone(),
-                dest.clone(),
-                authority.clone(),
-                token_program.clone(),
-            ],
-        )
+        let growth = engine
+            .insurance_fund
+            .balance
+            .get()
+            .saturating_sub(ins_before);
+        let reward = crate::policy::mul_div_floor(growth, share_bps as u128, 10_000)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        if reward > 0 {
+            engine
+                .credit_account_from_insurance_not_atomic(vault_idx, reward, now_slot)
+                .map_err(map_risk_error)?;
+        }
+        Ok(reward)
     }
 
-    pub fn withdraw<'a>(
-        token_program: &AccountInfo<'a>,
-        source: &AccountInfo<'a>,
-        dest: &AccountInfo<'a>,
-        authority: &AccountInfo<'a>,
-        amount: u64,
-        signer_seeds: &[&[&[u8]]],
-    ) -> Result<(), ProgramError> {
+    fn accrue_protocol_fees(data: &mut [u8], amount: u128) -> Result<(), ProgramError> {
         if amount == 0 {
             return Ok(());
         }
-        let ix = spl_token::instruction::transfer(
-            token_program.key,
-            source.key,
-            dest.key,
-            authority.key,
-            &[],
-            amount,
-        )?;
-        invoke_signed(
-            &ix,
-            &[
-                source.clone(),
-                dest.clone(),
-                authority.clone(),
-                token_program.clone(),
-            ],
-            signer_seeds,
-        )
+        let fees = state::read_protocol_fees(data)
+            .checked_add(amount)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        state::write_protocol_fees(data, fees);
+        Ok(())
     }
-}
 
-// 9. mod processor
-pub mod processor {
-    use crate::{
-        accounts, collateral,
-        constants::{
-            DEFAULT_FUNDING_HORIZON_SLOTS, DEFAULT_FUNDING_K_BPS, DEFAULT_FUNDING_MAX_E9_PER_SLOT,
-            DEFAULT_FUNDING_MAX_PREMIUM_BPS, DEFAULT_MARK_EWMA_HALFLIFE_SLOTS, MAGIC,
-            MATCHER_CALL_LEN, MATCHER_CALL_TAG, MAX_MATCHER_TAIL_ACCOUNTS, SLAB_LEN,
-        },
-        error::{map_risk_error, PercolatorError},
-        ix::Instruction,
-        oracle,
-        state::{self, MarketConfig, SlabHeader},
-        zc,
-    };
-    use bytemuck::Zeroable;
-    use percolator::{RiskEngine, RiskError};
+    /// Booked equity (capital + realized pnl) as the engine last stored it.
+    fn booked_equity(engine: &RiskEngine, idx: u16) -> i128 {
+        let acc = &engine.accounts[idx as usize];
+        (acc.capital.get().min(i128::MAX as u128) as i128).saturating_add(acc.pnl)
+    }
 
-    // settle_and_close_resolved removed — replaced by engine
-    // force_close_resolved_with_fee_not_atomic(), which handles K-pair PnL,
-    // checked arithmetic, fee-after-loss ordering, and settlement internally.
+    fn saturate_i64(v: i128) -> i64 {
+        v.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
 
-    /// Read oracle price for non-Hyperp markets and stamp
-    /// `last_good_oracle_slot`. Any Pyth/Chainlink parse error propagates
-    /// unchanged — there is no authority fallback.
-    ///
-    /// STRICT HARD-TIMEOUT GATE: if the hard stale window has matured
-    /// (clock.slot - last_good_oracle_slot >=
-    /// permissionless_resolve_stale_slots), this function rejects with
-    /// OracleStale even when a fresh external price is supplied. That
-    /// prevents price-taking instructions (Trade, Withdraw, Crank,
-    /// Settle, Convert, Catchup) from reviving a terminally dead market
-    /// — they must route to ResolvePermissionless instead.
-    fn read_price_and_stamp(
-        config: &mut state::MarketConfig,
-        oracle_accounts: &[AccountInfo],
-        clock_unix_ts: i64,
-        clock_slot: u64,
-        slab_data: &mut [u8],
-    ) -> Result<u64, ProgramError> {
-        if oracle::permissionless_stale_matured(config, clock_slot) {
-            return Err(PercolatorError::OracleStale.into());
+    /// Open fresh epoch counters for a newly materialized account.
+    fn open_epoch_stats(data: &mut [u8], idx: u16, equity: i128) {
+        let epoch = state::read_epoch_config(data).epoch;
+        state::write_epoch_stats(
+            data,
+            idx,
+            &state::EpochStats {
+                epoch,
+                start_equity: saturate_i64(equity),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Add a flow (deposit > 0, withdrawal < 0) and/or filled notional to
+    /// `idx`'s epoch counters. `equity_before` is the booked equity just
+    /// before this instruction's own effect; when the account is still on
+    /// an older epoch it closes that epoch's totals and opens the current
+    /// one.
+    fn record_epoch_activity(
+        data: &mut [u8],
+        idx: u16,
+        equity_before: i128,
+        flow: i64,
+        volume: u128,
+    ) {
+        let epoch = state::read_epoch_config(data).epoch;
+        let mut stats = state::read_epoch_stats(data, idx);
+        if stats.epoch != epoch {
+            stats = state::EpochStats {
+                epoch,
+                start_equity: saturate_i64(equity_before),
+                net_flow: 0,
+                volume: 0,
+                last_epoch: stats.epoch,
+                last_pnl: crate::policy::epoch_pnl(
+                    equity_before,
+                    stats.start_equity,
+                    stats.net_flow,
+                ),
+                last_volume: stats.volume,
+            };
         }
+        stats.net_flow = stats.net_flow.saturating_add(flow);
+        stats.volume = stats.volume.saturating_add(clamp_u64(volume));
+        state::write_epoch_stats(data, idx, &stats);
+    }
 
-        // Source the per-slot price-move cap from RiskParams (init-
-        // immutable per spec §1.4 solvency envelope). Standard bps.
-        let (p_last, max_change_bps, price_move_dt_slots, oi_any) = {
-            let engine = zc::engine_ref(slab_data)?;
-            (
-                engine.last_oracle_price,
-                engine.params.max_price_move_bps_per_slot,
-                price_move_residual_dt(engine, clock_slot)?,
-                engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0,
-            )
-        };
+    /// Open a zeroed PnL ledger for a newly materialized account, with
+    /// funding counted from the current index.
+    fn open_pnl_ledger(data: &mut [u8], idx: u16) {
+        let funding_index = state::read_funding_index(data).index;
+        state::write_pnl_ledger(
+            data,
+            idx,
+            &state::PnlLedger {
+                funding_index,
+                ..Default::default()
+            },
+        );
+    }
 
-        // Snapshot the source-feed clock before the call so we can
-        // tell whether THIS read advanced state. Stale/duplicate
-        // observations get the cached price from
-        // `clamp_external_price` without advancing the timestamp; we
-        // must not stamp the liveness cursor on those — otherwise an
-        // attacker can replay an old Pyth account to extend market
-        // life past `permissionless_resolve_stale_slots`.
-        let price_advanced = match oracle::read_price_clamped(
-            config,
-            oracle_accounts,
-            clock_unix_ts,
-            max_change_bps,
-            p_last,
-            price_move_dt_slots,
-            oi_any,
-        ) {
-            Ok(v) => v,
-            Err(e)
-                if e == ProgramError::from(PercolatorError::OracleStale)
-                    && oracle::hybrid_soft_stale_matured(config, clock_slot) =>
-            {
-                let target = oracle::hybrid_after_hours_target(config);
-                if target == 0 {
-                    return Err(PercolatorError::OracleInvalid.into());
-                }
-                let anchor = if p_last != 0 {
-                    p_last
-                } else if config.last_effective_price_e6 != 0 {
-                    config.last_effective_price_e6
-                } else {
-                    target
-                };
-                let price = oracle::effective_price_from_target(
-                    anchor,
-                    target,
-                    max_change_bps,
-                    price_move_dt_slots,
-                    oi_any,
-                );
-                config.oracle_target_price_e6 = target;
-                config.last_effective_price_e6 = price;
-                if price != anchor || price == target {
-                    config.last_hyperp_index_slot = clock_slot;
-                }
-                return Ok(price);
-            }
-            Err(e) => return Err(e),
+    /// Advance the ledger's funding index to `now_slot`, before the
+    /// engine accrues. Like the engine, funding flows on `fund_px_last`
+    /// and only while both sides hold open interest. The rate is the
+    /// pre-read one the caller hands the engine, so the index follows the
+    /// engine's accrual at fill and crank granularity.
+    fn advance_funding_index(
+        data: &mut [u8],
+        now_slot: u64,
+        funding_rate_e9: i128,
+    ) -> Result<(), ProgramError> {
+        let engine = zc::engine_ref(data)?;
+        let price = engine.fund_px_last;
+        let rate = if engine.oi_eff_long_q != 0 && engine.oi_eff_short_q != 0 {
+            funding_rate_e9
+        } else {
+            0
         };
-        let (price, advanced) = price_advanced;
-        if advanced {
-            config.last_good_oracle_slot = clock_slot;
+        let mut fi = state::read_funding_index(data);
+        if now_slot <= fi.slot {
+            return Ok(());
         }
-        if oracle::is_hybrid_after_hours_mode(config)
-            && (advanced || config.mark_ewma_e6 != price || config.hyperp_mark_e6 != price)
-        {
-            // During regular hours, the trusted external composite owns the
-            // hybrid mark baseline. A fresh publish_time starts/updates the
-            // raw target, and duplicate reads may continue the already-
-            // accepted target/effective staircase without refreshing the
-            // liveness cursor. The mark must follow those accepted effective
-            // steps too; otherwise regular-hours duplicate cranks can create
-            // artificial mark/index funding divergence before after-hours
-            // fallback has actually started.
-            config.hyperp_mark_e6 = price;
-            config.mark_ewma_e6 = price;
-            config.mark_ewma_last_slot = clock_slot;
+        if fi.slot != 0 {
+            fi.index =
+                crate::policy::funding_index_advance(fi.index, price, rate, now_slot - fi.slot);
         }
-        Ok(price)
+        fi.slot = now_slot;
+        state::write_funding_index(data, &fi);
+        Ok(())
     }
 
-    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-    pub struct TradeExecution {
-        /// Actual execution price (may differ from oracle/requested price)
-        pub price: u64,
-        /// Actual executed size (may be partial fill)
-        pub size: i128,
+    /// Settle one side of a fill into `idx`'s PnL ledger: funding on the
+    /// ledger position since its last settlement, then the position
+    /// brought to `pos_before` (`policy::reconcile_cost_basis`), then the
+    /// fill of `size` at `exec_price` and the side's fee. The result is
+    /// reconciled to the engine's `pos_after`.
+    #[allow(clippy::too_many_arguments)]
+    fn record_pnl_fill(
+        data: &mut [u8],
+        idx: u16,
+        pos_before: i128,
+        pos_after: i128,
+        size: i128,
+        exec_price: u64,
+        price: u64,
+        fee: u128,
+    ) {
+        let index = state::read_funding_index(data).index;
+        let mut ledger = state::read_pnl_ledger(data, idx);
+        let funding =
+            crate::policy::funding_owed(ledger.basis.position_q, ledger.funding_index, index);
+        ledger.funding_paid = saturate_i64((ledger.funding_paid as i128).saturating_add(funding));
+        ledger.funding_index = index;
+        let basis = crate::policy::reconcile_cost_basis(&ledger.basis, pos_before, price);
+        let (basis, realized) =
+            crate::policy::settle_cost_basis(&basis, size, exec_price).unwrap_or((basis, 0));
+        ledger.basis = crate::policy::reconcile_cost_basis(&basis, pos_after, price);
+        ledger.realized_pnl = saturate_i64((ledger.realized_pnl as i128).saturating_add(realized));
+        ledger.fees_paid = ledger.fees_paid.saturating_add(clamp_u64(fee));
+        state::write_pnl_ledger(data, idx, &ledger);
+    }
+
+    /// Book into the PnL ledger what a crank did to these accounts'
+    /// positions outside fills: liquidation closes, ADL reductions and
+    /// dust sweeps. Each change is booked as a fill at the crank price
+    /// with no fee (a liquidation fee comes out of capital, not
+    /// `fees_paid`). Accounts the crank freed or left unchanged are
+    /// skipped, so repeating an index is harmless.
+    fn record_crank_position_changes(
+        data: &mut [u8],
+        idxs: impl Iterator<Item = u16>,
+        price: u64,
+    ) -> Result<(), ProgramError> {
+        for idx in idxs {
+            let pos = {
+                let engine = zc::engine_ref(data)?;
+                if !idx_used_in_market(engine, idx as usize) {
+                    continue;
+                }
+                effective_pos_q_checked(engine, idx as usize)?
+            };
+            let before = state::read_pnl_ledger(data, idx).basis.position_q;
+            if pos != before {
+                record_pnl_fill(
+                    data,
+                    idx,
+                    before,
+                    pos,
+                    pos.saturating_sub(before),
+                    price,
+                    price,
+                    0,
+                );
+            }
+        }
+        Ok(())
     }
 
-    /// Trait for pluggable matching engines
-    pub trait MatchingEngine {
-        fn execute_match(
-            &self,
-            lp_program: &[u8; 32],
-            lp_context: &[u8; 32],
-            lp_account_id: u64,
-            oracle_price: u64,
-            size: i128,
-        ) -> Result<TradeExecution, RiskError>;
+    /// LP utilization at `price` from the engine's stored state.
+    fn lp_utilization(engine: &RiskEngine, lp_idx: u16, price: u64) -> Result<u64, ProgramError> {
+        let pos = effective_pos_q_checked(engine, lp_idx as usize)?;
+        Ok(crate::policy::lp_utilization_bps(
+            risk_notional_ceil(pos, price),
+            booked_equity(engine, lp_idx),
+        ))
     }
 
-    /// No-op matching engine (for testing/TradeNoCpi)
-    pub struct NoOpMatcher;
+    /// Add a fill to `lp_idx`'s counters, claiming a free or stale entry
+    /// on its first fill. LPs beyond `LP_STATS_CAP` are not tracked.
+    fn record_lp_fill(
+        data: &mut [u8],
+        lp_idx: u16,
+        notional: u128,
+        fee_share: u128,
+        spread_pnl: i128,
+        now_slot: u64,
+        price: u64,
+    ) -> Result<(), ProgramError> {
+        let utilization_bps = lp_utilization(zc::engine_ref(data)?, lp_idx, price)?;
+        let gen = state::read_account_generation(data, lp_idx);
+        let slot = match state::find_lp_stats(data, lp_idx) {
+            Some(slot) => slot,
+            None => {
+                let free = (0..crate::constants::LP_STATS_CAP).find(|&slot| {
+                    let s = state::read_lp_stats(data, slot);
+                    s.generation == 0 || state::read_account_generation(data, s.idx) != s.generation
+                });
+                match free {
+                    Some(slot) if gen != 0 => {
+                        state::write_lp_stats(
+                            data,
+                            slot,
+                            &state::LpStats {
+                                generation: gen,
+                                idx: lp_idx,
+                                ..Default::default()
+                            },
+                        );
+                        slot
+                    }
+                    _ => return Ok(()),
+                }
+            }
+        };
+        let mut stats = state::read_lp_stats(data, slot);
+        stats.volume = stats.volume.saturating_add(notional);
+        stats.fees_earned = stats.fees_earned.saturating_add(fee_share);
+        stats.spread_pnl = stats.spread_pnl.saturating_add(spread_pnl);
+        stats.fills = stats.fills.saturating_add(1);
+        stats.last_fill_slot = now_slot;
+        stats.utilization_bps = utilization_bps;
+        state::write_lp_stats(data, slot, &stats);
+        Ok(())
+    }
 
-    impl MatchingEngine for NoOpMatcher {
-        fn execute_match(
-            &self,
-            _lp_program: &[u8; 32],
-            _lp_context: &[u8; 32],
-            _lp_account_id: u64,
-            oracle_price: u64,
-            size: i128,
-        ) -> Result<TradeExecution, RiskError> {
-            Ok(TradeExecution {
-                price: oracle_price,
-                size,
-            })
+    /// Crank refresh of a tracked LP's utilization at the crank price.
+    fn refresh_lp_utilization(data: &mut [u8], idx: u16, price: u64) -> Result<(), ProgramError> {
+        let Some(slot) = state::find_lp_stats(data, idx) else {
+            return Ok(());
+        };
+        let engine = zc::engine_ref(data)?;
+        if !idx_used_in_market(engine, idx as usize) {
+            return Ok(());
         }
+        let utilization_bps = lp_utilization(engine, idx, price)?;
+        let mut stats = state::read_lp_stats(data, slot);
+        stats.utilization_bps = utilization_bps;
+        state::write_lp_stats(data, slot, &stats);
+        Ok(())
     }
 
-    struct CpiMatcher {
-        exec_price: u64,
-        exec_size: i128,
+    /// Roll the competition epoch clock forward during crank.
+    fn advance_epoch(data: &mut [u8], now_slot: u64) {
+        let mut cfg = state::read_epoch_config(data);
+        let (epoch, start_slot) =
+            crate::policy::epoch_advance(cfg.epoch, cfg.start_slot, cfg.epoch_slots, now_slot);
+        if epoch != cfg.epoch {
+            cfg.epoch = epoch;
+            cfg.start_slot = start_slot;
+            state::write_epoch_config(data, &cfg);
+        }
     }
 
-    impl MatchingEngine for CpiMatcher {
-        fn execute_match(
-            &self,
-            _lp_program: &[u8; 32],
-            _lp_context: &[u8; 32],
-            _lp_account_id: u64,
-            _oracle_price: u64,
-            _size: i128,
-        ) -> Result<TradeExecution, RiskError> {
-            Ok(TradeExecution {
-                price: self.exec_price,
-                size: self.exec_size,
-            })
+    /// Append a history sample after a full crank, at most once per
+    /// `HISTORY_SAMPLE_INTERVAL_SLOTS`.
+    fn record_history_sample(
+        data: &mut [u8],
+        now_slot: u64,
+        config: &MarketConfig,
+    ) -> Result<(), ProgramError> {
+        let region = state::history_region(data);
+        let (_, count) = state::read_history_cursor(region);
+        if count > 0 {
+            let newest = state::history_sample(region, count - 1);
+            if now_slot
+                < newest
+                    .slot
+                    .saturating_add(crate::constants::HISTORY_SAMPLE_INTERVAL_SLOTS)
+            {
+                return Ok(());
+            }
         }
+        let funding_rate_e9 = saturate_i64(compute_current_funding_rate_e9(config)?);
+        let engine = zc::engine_ref(data)?;
+        let sample = state::HistorySample {
+            slot: now_slot,
+            price_e6: engine.last_oracle_price,
+            mark_e6: config.mark_ewma_e6,
+            funding_rate_e9,
+            oi_long_q: engine.oi_eff_long_q,
+            oi_short_q: engine.oi_eff_short_q,
+        };
+        state::push_history_sample(data, &sample);
+        Ok(())
     }
 
-    /// Compute funding rate from mark-index premium (all market types).
-    /// Uses trade-derived EWMA mark vs oracle index.
-    /// Returns 0 if no trades yet (mark_ewma == 0) or params unset.
-    /// Compute funding rate in e9-per-slot (ppb) directly.
-    /// Avoids bps quantization: sub-bps rates are preserved as nonzero ppb values.
-    /// Realize due maintenance fees for a single account up to `now_slot`.
-    /// Pre-touch recurring maintenance fees are loss-senior only for accounts
-    /// that are already flat/current. Nonflat or stale accounts must first be
-    /// touched by an engine path that settles lazy A/K/F and side effects.
-    fn recurring_fee_pre_touch_safe(engine: &RiskEngine, idx: usize) -> Result<bool, ProgramError> {
-        if !idx_used_in_market(engine, idx) {
-            return Ok(false);
+    /// Emergency withdraw-only mode blocks every path that adds capital,
+    /// accounts or risk.
+    fn reject_withdraw_only(data: &[u8]) -> Result<(), ProgramError> {
+        if state::read_crank_guard(data).withdraw_only {
+            return Err(PercolatorError::WithdrawOnly.into());
         }
-        let acc = &engine.accounts[idx];
-        let fc = acc.fee_credits.get();
-        if acc.position_basis_q == i128::MIN || acc.pnl == i128::MIN || fc == i128::MIN || fc > 0 {
-            return Err(PercolatorError::EngineCorruptState.into());
+        Ok(())
+    }
+
+    /// Permissioned-market gate for user onboarding and deposits.
+    fn check_allowlist(
+        root: &[u8; 32],
+        owner: &Pubkey,
+        proof: &[[u8; 32]],
+    ) -> Result<(), ProgramError> {
+        if !crate::policy::allowlist_ok(root, &owner.to_bytes(), proof) {
+            // ctx: proof length
+            return Err(fail(PercolatorError::NotAllowlisted, &[proof.len() as u64]));
         }
-        let effective_pos_q = if acc.position_basis_q == 0 {
-            effective_pos_q_checked(engine, idx)?
-        } else {
-            1
-        };
-        Ok(crate::policy::recurring_fee_pre_touch_safe_shape(
-            true,
-            acc.position_basis_q,
-            effective_pos_q,
-            acc.pnl,
-            acc.reserved_pnl,
-            acc.sched_present,
-            acc.pending_present,
-            fc,
-        ))
+        Ok(())
     }
 
-    /// Recurring fee sync for accounts that have already gone through an
-    /// authoritative engine local touch in this instruction. Unlike
-    /// pre-touch fee sweeping, this intentionally does not require the account
-    /// to be flat: the loss-senior precondition was satisfied by the preceding
-    /// `settle_account_not_atomic` / keeper touch, so nonflat current accounts
-    /// must become fee-current before health-sensitive actions.
-    fn sync_account_fee_after_authoritative_touch(
-        engine: &mut RiskEngine,
-        config: &MarketConfig,
+    /// Guarded-launch capital cap for user accounts, checked after the
+    /// engine credits a deposit.
+    fn check_capital_limit(
+        engine: &RiskEngine,
         idx: u16,
-        now_slot: u64,
+        limits: &state::AccountLimits,
     ) -> Result<(), ProgramError> {
-        if config.maintenance_fee_per_slot == 0 {
+        let acc = &engine.accounts[idx as usize];
+        if acc.kind == percolator::Account::KIND_LP {
             return Ok(());
         }
-        check_idx(engine, idx)?;
-        let is_resolved = engine_is_resolved(engine);
-        let resolved_slot = if is_resolved {
-            engine_resolved_context(engine).1
-        } else {
-            0
-        };
-        if !crate::policy::fee_sync_anchor_within_accrued_boundary(
-            is_resolved,
-            now_slot,
-            engine.last_market_slot,
-            resolved_slot,
-        ) {
-            return Err(PercolatorError::CatchupRequired.into());
+        let capital = acc.capital.get();
+        if !crate::policy::capital_limit_ok(capital, limits.max_capital) {
+            // ctx: capital after deposit, limit
+            return Err(fail(
+                PercolatorError::DepositCapExceeded,
+                &[clamp_u64(capital), clamp_u64(limits.max_capital)],
+            ));
         }
-        engine
-            .sync_account_fee_to_slot_not_atomic(idx, now_slot, config.maintenance_fee_per_slot)
-            .map_err(map_risk_error)
+        Ok(())
     }
 
-    fn settle_account_then_sync_fee_current(
-        engine: &mut RiskEngine,
-        config: &MarketConfig,
-        idx: u16,
-        now_slot: u64,
+    /// Guarded-launch position cap for the user side of a fill, valued at
+    /// the oracle price the trade was margined at.
+    fn check_position_limit(
+        engine: &RiskEngine,
+        user_idx: u16,
+        pos_before: i128,
         price: u64,
-        funding_rate_e9: i128,
-        admit_h_min: u64,
-        admit_h_max: u64,
-        admit_threshold: Option<u128>,
+        limits: &state::AccountLimits,
     ) -> Result<(), ProgramError> {
-        engine
-            .settle_account_not_atomic(
-                idx,
-                price,
-                now_slot,
-                funding_rate_e9,
-                admit_h_min,
-                admit_h_max,
-                admit_threshold,
-            )
-            .map_err(map_risk_error)?;
-        sync_account_fee_after_authoritative_touch(engine, config, idx, now_slot)
+        if limits.max_position_notional == 0
+            || engine.accounts[user_idx as usize].kind == percolator::Account::KIND_LP
+        {
+            return Ok(());
+        }
+        let pos_after = effective_pos_q_checked(engine, user_idx as usize)?;
+        let notional = risk_notional_ceil(pos_after, price);
+        if !crate::policy::position_limit_ok(
+            pos_before,
+            pos_after,
+            notional,
+            limits.max_position_notional,
+        ) {
+            // ctx: notional after fill, limit
+            return Err(fail(
+                PercolatorError::PositionLimitExceeded,
+                &[clamp_u64(notional), clamp_u64(limits.max_position_notional)],
+            ));
+        }
+        Ok(())
     }
 
-    fn settle_pair_then_sync_fee_current(
-        engine: &mut RiskEngine,
-        config: &MarketConfig,
-        a: u16,
-        b: u16,
+    /// Circuit breaker for the user side of a fill: while cooling, only
+    /// fills that do not grow `|position|` pass. Liquidations and crank
+    /// sweeps do not go through here.
+    fn check_circuit_breaker(
+        engine: &RiskEngine,
+        user_idx: u16,
+        pos_before: i128,
+        cb: &state::CircuitBreaker,
         now_slot: u64,
-        price: u64,
-        funding_rate_e9: i128,
-        admit_h_min: u64,
-        admit_h_max: u64,
-        admit_threshold: Option<u128>,
     ) -> Result<(), ProgramError> {
-        let (first, second) = if a <= b { (a, b) } else { (b, a) };
-        settle_account_then_sync_fee_current(
-            engine,
-            config,
-            first,
-            now_slot,
-            price,
-            funding_rate_e9,
-            admit_h_min,
-            admit_h_max,
-            admit_threshold,
-        )?;
-        settle_account_then_sync_fee_current(
-            engine,
-            config,
-            second,
-            now_slot,
-            price,
-            funding_rate_e9,
-            admit_h_min,
-            admit_h_max,
-            admit_threshold,
-        )
+        if !cb.cooling(now_slot)
+            || engine.accounts[user_idx as usize].kind == percolator::Account::KIND_LP
+        {
+            return Ok(());
+        }
+        let pos_after = effective_pos_q_checked(engine, user_idx as usize)?;
+        if pos_after.unsigned_abs() > pos_before.unsigned_abs() {
+            // ctx: current slot, cooling-off end slot
+            return Err(fail(
+                PercolatorError::CircuitBreakerTripped,
+                &[now_slot, cb.cooling_until_slot],
+            ));
+        }
+        Ok(())
     }
 
-    /// Fee-sync variant for no-oracle instructions. Caps the fee anchor
-    /// at `engine.last_market_slot`, leaving full realization of fees
-    /// accrued over `[last_market_slot, clock.slot]` to the next
-    /// oracle-backed instruction. Prevents the `current_slot >
-    /// last_market_slot` split that would otherwise brick later
-    /// accrual.
-    ///
-    /// Acceptable trade-off: fees from the unaccrued tail are realized
-    /// slightly later (on the next trade/crank/withdraw) instead of now.
-    /// Correctness is preserved because the engine's per-account
-    /// `last_fee_slot` still advances monotonically to the
-    /// already-accrued boundary; subsequent sync calls cover the rest.
-    fn sync_account_fee_bounded_to_market(
-        engine: &mut RiskEngine,
-        config: &MarketConfig,
-        idx: u16,
-        wallclock_slot: u64,
+    /// Stale-oracle fallback: while engaged no voluntary fill executes.
+    /// The held price is not a market price, so a close at it would hand
+    /// the gap to the real price to whichever side knows it, the same as
+    /// an open would. Settlement and liquidation still run at the held
+    /// price.
+    fn check_stale_fallback(
+        engine: &RiskEngine,
+        user_idx: u16,
+        pos_before: i128,
+        engaged: bool,
     ) -> Result<(), ProgramError> {
-        if config.maintenance_fee_per_slot == 0 {
+        if !engaged {
             return Ok(());
         }
-        check_idx(engine, idx)?;
-        if !recurring_fee_pre_touch_safe(engine, idx as usize)? {
+        let pos_after = effective_pos_q_checked(engine, user_idx as usize)?;
+        // ctx: position before, position after (magnitudes)
+        Err(fail(
+            PercolatorError::OracleFallbackActive,
+            &[
+                clamp_u64(pos_before.unsigned_abs()),
+                clamp_u64(pos_after.unsigned_abs()),
+            ],
+        ))
+    }
+
+    /// Permissioned liquidation window for a crank whose caller is outside
+    /// the liquidator set: liquidation hints on accounts still inside
+    /// their window become touch-only.
+    fn gate_liquidation_hints(
+        data: &[u8],
+        combined: &mut [(u16, Option<percolator::LiquidationPolicy>)],
+        now_slot: u64,
+        window_slots: u64,
+    ) {
+        for (idx, policy) in combined.iter_mut() {
+            if policy.is_some()
+                && !crate::policy::liquidation_open(
+                    state::read_liq_flag_slot(data, *idx),
+                    now_slot,
+                    window_slots,
+                )
+            {
+                *policy = None;
+            }
+        }
+    }
+
+    /// Flag accounts the crank left below maintenance (keeping an existing
+    /// flag), and clear the flag of any that are flat, healthy or freed.
+    fn update_liquidation_flags(
+        data: &mut [u8],
+        combined: &[(u16, Option<percolator::LiquidationPolicy>)],
+        now_slot: u64,
+        price: u64,
+    ) -> Result<(), ProgramError> {
+        for &(idx, _) in combined {
+            if idx as usize >= percolator::MAX_ACCOUNTS {
+                continue;
+            }
+            let underwater = {
+                let engine = zc::engine_ref(data)?;
+                if idx_used_in_market(engine, idx as usize) {
+                    let health = account_health(engine, idx, price)?;
+                    health.position_q != 0 && !health.above_maintenance()
+                } else {
+                    false
+                }
+            };
+            let flag = state::read_liq_flag_slot(data, idx);
+            if !underwater && flag != 0 {
+                state::write_liq_flag_slot(data, idx, 0);
+            } else if underwater && flag == 0 {
+                state::write_liq_flag_slot(data, idx, now_slot);
+            }
+        }
+        Ok(())
+    }
+
+    /// Per-account trade rate limit for the user side of a fill.
+    fn check_trade_rate(
+        engine: &RiskEngine,
+        user_idx: u16,
+        rate: &state::TradeRate,
+        now_slot: u64,
+    ) -> Result<(), ProgramError> {
+        if engine.accounts[user_idx as usize].kind == percolator::Account::KIND_LP
+            || crate::policy::trade_rate_ok(
+                rate.last_trade_slot,
+                now_slot,
+                rate.min_slots_between_trades,
+            )
+        {
             return Ok(());
         }
-        let Some(anchor) = crate::policy::no_oracle_fee_sync_anchor(
-            wallclock_slot,
-            engine.last_market_slot,
-            engine.current_slot,
-            engine.accounts[idx as usize].last_fee_slot,
-        ) else {
-            return Ok(());
-        };
-        engine
-            .sync_account_fee_to_slot_not_atomic(idx, anchor, config.maintenance_fee_per_slot)
-            .map_err(map_risk_error)
+        // ctx: current slot, first slot the next fill is allowed
+        Err(fail(
+            PercolatorError::TradeRateLimited,
+            &[
+                now_slot,
+                rate.last_trade_slot
+                    .saturating_add(rate.min_slots_between_trades),
+            ],
+        ))
     }
 
-    fn check_no_oracle_live_envelope(
+    /// Dust floors for the user side of a fill. `size` is the executed
+    /// (post reduce-only clamp) size.
+    fn check_dust_limits(
         engine: &RiskEngine,
-        wallclock_slot: u64,
+        user_idx: u16,
+        size: i128,
+        dust: &state::DustLimits,
     ) -> Result<(), ProgramError> {
-        let gap = wallclock_slot
-            .checked_sub(engine.last_market_slot)
-            .ok_or(PercolatorError::EngineOverflow)?;
-        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
-        if oi_any && gap > engine.params.max_accrual_dt_slots {
-            return Err(PercolatorError::CatchupRequired.into());
+        if (dust.min_trade_abs == 0 && dust.min_position_abs == 0)
+            || engine.accounts[user_idx as usize].kind == percolator::Account::KIND_LP
+        {
+            return Ok(());
+        }
+        let pos_after = effective_pos_q_checked(engine, user_idx as usize)?;
+        if !crate::policy::dust_fill_ok(size, pos_after, dust.min_trade_abs, dust.min_position_abs)
+        {
+            // ctx: |size|, |position after|, min trade, min position
+            return Err(fail(
+                PercolatorError::BelowMinSize,
+                &[
+                    clamp_u64(size.unsigned_abs()),
+                    clamp_u64(pos_after.unsigned_abs()),
+                    clamp_u64(dust.min_trade_abs),
+                    clamp_u64(dust.min_position_abs),
+                ],
+            ));
         }
         Ok(())
     }
 
-    fn price_move_residual_dt(
-        engine: &RiskEngine,
-        wallclock_slot: u64,
-    ) -> Result<u64, ProgramError> {
-        price_move_residual_dt_from_parts(
-            engine.last_market_slot,
-            engine.params.max_accrual_dt_slots,
-            wallclock_slot,
-        )
+    /// Wrapper-side trade limits, read once per trade from their tail
+    /// regions and checked together after the engine fill. Shared by
+    /// TradeNoCpi, TradeCpi and `simulate::simulate_trade_nocpi`.
+    pub(crate) struct TradeGuards {
+        pub limits: state::AccountLimits,
+        pub dust: state::DustLimits,
+        pub circuit_breaker: state::CircuitBreaker,
+        pub rate: state::TradeRate,
+        /// Stale-oracle fallback engaged: no fills.
+        pub oracle_held: bool,
+        pub fee_split: state::FeeSplit,
     }
 
-    fn price_move_residual_dt_from_parts(
-        last_market_slot: u64,
-        max_dt: u64,
-        wallclock_slot: u64,
-    ) -> Result<u64, ProgramError> {
-        let gap = wallclock_slot
-            .checked_sub(last_market_slot)
-            .ok_or(PercolatorError::EngineOverflow)?;
-        if max_dt == 0 || gap <= max_dt {
-            return Ok(gap);
+    impl TradeGuards {
+        /// Feeds the circuit breaker `price` before reading it, as every
+        /// trade does.
+        pub(crate) fn load(
+            data: &mut [u8],
+            user_idx: u16,
+            now_slot: u64,
+            price: u64,
+            last_good_oracle_slot: u64,
+        ) -> Self {
+            observe_circuit_breaker(data, now_slot, price);
+            Self {
+                limits: state::read_account_limits(data),
+                dust: state::read_dust_limits(data),
+                circuit_breaker: state::read_circuit_breaker(data),
+                rate: state::read_trade_rate(data, user_idx),
+                oracle_held: state::read_stale_fallback(data)
+                    .engaged(now_slot, last_good_oracle_slot),
+                fee_split: state::read_fee_split(data),
+            }
         }
-        let rem = gap % max_dt;
-        Ok(if rem == 0 { max_dt } else { rem })
-    }
 
-    fn hyperp_target_price(config: &MarketConfig) -> u64 {
-        if config.mark_ewma_e6 > 0 {
-            config.mark_ewma_e6
-        } else {
-            config.hyperp_mark_e6
+        pub(crate) fn check(
+            &self,
+            engine: &RiskEngine,
+            user_idx: u16,
+            pos_before: i128,
+            size: i128,
+            price: u64,
+            now_slot: u64,
+        ) -> Result<(), ProgramError> {
+            check_position_limit(engine, user_idx, pos_before, price, &self.limits)?;
+            check_dust_limits(engine, user_idx, size, &self.dust)?;
+            check_circuit_breaker(
+                engine,
+                user_idx,
+                pos_before,
+                &self.circuit_breaker,
+                now_slot,
+            )?;
+            check_trade_rate(engine, user_idx, &self.rate, now_slot)?;
+            check_stale_fallback(engine, user_idx, pos_before, self.oracle_held)
         }
     }
 
-    fn oracle_target_pending(config: &MarketConfig, engine: &RiskEngine) -> bool {
-        !crate::policy::user_value_op_allowed_after_accrual(
-            oracle::is_hyperp_mode(config),
-            config.oracle_target_price_e6,
-            hyperp_target_price(config),
-            engine.last_oracle_price,
-        )
-    }
-
-    fn reject_any_target_lag(
-        config: &MarketConfig,
-        engine: &RiskEngine,
+    /// Check and bump `user_idx`'s replay nonce when the caller supplied
+    /// one. Runs before the owner check; a later failure rolls it back.
+    fn consume_user_nonce(
+        data: &mut [u8],
+        user_idx: u16,
+        nonce: Option<u64>,
     ) -> Result<(), ProgramError> {
-        if engine.oi_eff_long_q == 0 && engine.oi_eff_short_q == 0 {
+        let Some(nonce) = nonce else {
             return Ok(());
+        };
+        if user_idx as usize >= percolator::MAX_ACCOUNTS {
+            return Err(PercolatorError::EngineAccountNotFound.into());
         }
-        if oracle_target_pending(config, engine) {
-            return Err(PercolatorError::CatchupRequired.into());
+        let current = state::read_user_nonce(data, user_idx);
+        if nonce != current {
+            // ctx: expected, supplied
+            return Err(fail(PercolatorError::NonceMismatch, &[current, nonce]));
         }
+        let next = current
+            .checked_add(1)
+            .ok_or(PercolatorError::EngineOverflow)?;
+        state::write_user_nonce(data, user_idx, next);
         Ok(())
     }
 
-    #[inline]
-    fn engine_is_resolved(engine: &RiskEngine) -> bool {
-        engine.market_mode == percolator::MarketMode::Resolved
-    }
+    /// DepositCollateral and DepositWithPermit past their own
+    /// authorization. `owner_key` authorized the deposit and must own both
+    /// the engine account and the source token account; `transfer` moves
+    /// `amount` into the vault once every pre-transfer check has passed.
+    #[allow(clippy::too_many_arguments)]
+    fn deposit_for_owner(
+        program_id: &Pubkey,
+        a_slab: &AccountInfo,
+        a_source: &AccountInfo,
+        a_vault: &AccountInfo,
+        a_clock: &AccountInfo,
+        owner_key: &Pubkey,
+        user_idx: u16,
+        amount: u64,
+        nonce: Option<u64>,
+        allowlist_proof: &[[u8; 32]],
+        transfer: impl FnOnce() -> ProgramResult,
+    ) -> ProgramResult {
+        let mut data = state::slab_data_mut(a_slab)?;
+        slab_guard(program_id, a_slab, &data)?;
+        require_initialized(&data)?;
+        reject_withdraw_only(&data)?;
+        consume_user_nonce(&mut data, user_idx, nonce)?;
 
-    #[inline]
-    fn engine_resolved_context(engine: &RiskEngine) -> (u64, u64) {
-        (engine.resolved_price, engine.resolved_slot)
-    }
+        // Block deposits when market is resolved
+        if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-    #[inline]
-    fn engine_is_used(engine: &RiskEngine, idx: usize) -> bool {
-        if idx >= percolator::MAX_ACCOUNTS {
-            return false;
+        let config = state::read_config(&data);
+        let mint = Pubkey::new_from_array(config.collateral_mint);
+
+        let auth = accounts::derive_vault_authority_with_bump(
+            program_id,
+            a_slab.key,
+            config.vault_authority_bump,
+        )?;
+        verify_vault(
+            a_vault,
+            &auth,
+            &mint,
+            &Pubkey::new_from_array(config.vault_pubkey),
+        )?;
+        verify_token_account(a_source, owner_key, &mint)?;
+
+        let clock = Clock::from_account_info(a_clock)?;
+
+        // Hard-timeout gate: once the market has been oracle-stale
+        // for >= permissionless_resolve_stale_slots, it is
+        // terminally dead. No live mutations — including
+        // no-oracle deposits — should proceed. Users must exit
+        // via ResolvePermissionless + resolved-market close
+        // paths. Rejecting BEFORE the SPL transfer so funds
+        // are not moved into a dead market.
+        if oracle::permissionless_stale_matured(&config, clock.slot) {
+            return Err(PercolatorError::OracleStale.into());
         }
-        ((engine.used[idx >> 6] >> (idx & 63)) & 1) == 1
-    }
+        check_no_oracle_live_envelope(zc::engine_ref(&data)?, clock.slot)?;
 
-    fn effective_pos_q_checked(engine: &RiskEngine, idx: usize) -> Result<i128, ProgramError> {
-        if idx >= percolator::MAX_ACCOUNTS || (idx as u64) >= engine.params.max_accounts {
-            return Err(PercolatorError::EngineAccountNotFound.into());
+        // Reject misaligned deposits — dust would be silently donated
+        let (_units_check, dust_check) = crate::units::base_to_units(amount, config.unit_scale);
+        if dust_check != 0 {
+            return Err(ProgramError::InvalidArgument);
         }
-        if !engine_is_used(engine, idx) {
-            return Err(PercolatorError::EngineAccountNotFound.into());
+
+        // TVL:insurance cap (admin opt-in). Enforced BEFORE the
+        // SPL transfer so rejected deposits don't move funds.
+        // Formula: `c_tot_new <= k * insurance_fund.balance`.
+        // k=0 disables the check; nonzero k with zero insurance
+        // means no deposits accepted — operator is expected to
+        // seed insurance (via TopUpInsurance or fee accumulation)
+        // before enabling or raising k. No fee split here: the
+        // full amount credits capital against the current
+        // insurance balance.
+        if config.tvl_insurance_cap_mult > 0 {
+            let (capital_units_sim, _) = crate::units::base_to_units(amount, config.unit_scale);
+            let engine_r = zc::engine_ref(&data)?;
+            let ins = engine_r.insurance_fund.balance.get();
+            let c_tot_new = engine_r
+                .c_tot
+                .get()
+                .saturating_add(capital_units_sim as u128);
+            let cap = ins.saturating_mul(config.tvl_insurance_cap_mult as u128);
+            if c_tot_new > cap {
+                // ctx: c_tot after deposit, cap
+                return Err(fail(
+                    PercolatorError::DepositCapExceeded,
+                    &[clamp_u64(c_tot_new), clamp_u64(cap)],
+                ));
+            }
         }
 
-        let account = &engine.accounts[idx];
-        let basis = account.position_basis_q;
-        if basis == 0 {
-            return Ok(0);
+        // Transfer base tokens to vault
+        transfer()?;
+
+        // Convert base tokens to units for engine
+        let (units, _dust) = crate::units::base_to_units(amount, config.unit_scale);
+
+        let limits = state::read_account_limits(&data);
+        let allowlist_root = state::read_allowlist_root(&data);
+        let engine = zc::engine_mut(&mut data)?;
+
+        check_idx(engine, user_idx)?;
+
+        // Owner authorization via policy helper
+        let owner = engine.accounts[user_idx as usize].owner;
+        if !crate::policy::owner_ok(owner, owner_key.to_bytes()) {
+            return Err(PercolatorError::EngineUnauthorized.into());
         }
+        // LP accounts are operator-run and exempt, as with the
+        // guarded-launch limits.
+        if engine.accounts[user_idx as usize].kind != percolator::Account::KIND_LP {
+            check_allowlist(&allowlist_root, owner_key, allowlist_proof)?;
+        }
+
+        // No-oracle path: pass clock.slot to deposit_not_atomic.
+        // The engine's check_live_accrual_envelope gates on dt =
+        // clock.slot - last_market_slot <= max_accrual_dt_slots —
+        // the same safety bound that ensures the next oracle-
+        // backed instruction's accrue won't exceed its envelope.
+        // Fee anchoring is capped at last_market_slot by
+        // sync_account_fee_bounded_to_market (per spec §10.7: no
+        // accrue in this no-oracle path); the residual tail
+        // (last_market_slot, clock.slot] is realized by the
+        // next oracle-backed op via ensure_market_accrued_to_now.
+        sync_account_fee_bounded_to_market(engine, &config, user_idx, clock.slot)?;
+
+        let equity_before = booked_equity(engine, user_idx);
+        engine
+            .deposit_not_atomic(user_idx, units as u128, clock.slot)
+            .map_err(map_risk_error)?;
+        check_capital_limit(engine, user_idx, &limits)?;
+        record_epoch_activity(
+            &mut data,
+            user_idx,
+            equity_before,
+            saturate_i64(units as i128),
+            0,
+        );
+        Ok(())
+    }
 
-        let (epoch_side, side_mode, a_side) = if basis > 0 {
-            (
-                engine.adl_epoch_long,
-                engine.side_mode_long,
-                engine.adl_mult_long,
-            )
-        } else if basis < 0 {
-            (
-                engine.adl_epoch_short,
-                engine.side_mode_short,
-                engine.adl_mult_short,
-            )
-        } else {
-            return Ok(0);
-        };
+    fn prepare_lazy_free_head(engine: &mut RiskEngine) -> Result<u16, ProgramError> {
+        let max_accounts = core::cmp::min(
+            engine.params.max_accounts as usize,
+            percolator::MAX_ACCOUNTS,
+        );
+        let idx = engine.free_head;
+        if idx == u16::MAX || (idx as usize) >= max_accounts {
+            // ctx: accounts in use, max_accounts
+            return Err(fail(
+                PercolatorError::SlabFull,
+                &[engine.num_used_accounts as u64, max_accounts as u64],
+            ));
+        }
+        if engine_is_used(engine, idx as usize) {
+            return Err(PercolatorError::EngineOverflow.into());
+        }
 
-        if account.adl_epoch_snap != epoch_side {
-            if side_mode != percolator::SideMode::ResetPending
-                || account.adl_epoch_snap.checked_add(1) != Some(epoch_side)
-            {
-                return Err(PercolatorError::EngineCorruptState.into());
+        let i = idx as usize;
+        let valid_head = engine.prev_free[i] == u16::MAX
+            && (engine.next_free[i] == u16::MAX
+                || ((engine.next_free[i] as usize) < max_accounts
+                    && !engine_is_used(engine, engine.next_free[i] as usize)
+                    && engine.prev_free[engine.next_free[i] as usize] == idx));
+        if !valid_head {
+            if idx as u64 != engine.num_used_accounts as u64 {
+                return Err(PercolatorError::EngineOverflow.into());
             }
-            return Ok(0);
+            let next = if i + 1 < max_accounts {
+                (i + 1) as u16
+            } else {
+                u16::MAX
+            };
+            engine.prev_free[i] = u16::MAX;
+            engine.next_free[i] = next;
+            if next != u16::MAX {
+                engine.prev_free[next as usize] = idx;
+            }
+        }
+
+        Ok(idx)
+    }
+
+    /// Maximum number of max_dt chunks the in-line catchup can advance per
+    /// instruction. Bounded by CU budget — each `accrue_market_to` is cheap
+    /// but not free. For gaps beyond this, callers must use KeeperCrank to
+    /// commit account-touching progress before attempting a main operation.
+    ///
+    /// 20 × max_dt = 20 × 10 = 200 slots per single instruction. Larger
+    /// gaps require multiple KeeperCrank calls — that's the design
+    /// contract, not a misconfig.
+    const CATCHUP_CHUNKS_MAX: u32 = 20;
+
+    /// Pre-chunk market-clock advancement when the gap since the last
+    /// engine *accrue* exceeds `params.max_accrual_dt_slots`. The engine
+    /// rejects any single `accrue_market_to` whose funding-active dt
+    /// exceeds the envelope (spec §1.4 / §5.5 clause 6), so every
+    /// accrue-bearing instruction (KeeperCrank, TradeCpi, TradeNoCpi,
+    /// Withdraw, Liquidate, Close, Settle, Convert, live Insurance
+    /// withdraw, Ordinary ResolveMarket, UpdateConfig) must close that
+    /// gap before its own accrue.
+    ///
+    /// Cursor: loops on `engine.last_market_slot`, NOT `current_slot`.
+    /// `last_market_slot` is the only cursor `accrue_market_to` uses to
+    /// compute `total_dt = now_slot - last_market_slot`; `current_slot`
+    /// can be advanced by non-accruing public endpoints (fee sync on Live,
+    /// deposit/top-up without oracle) so it does not track market accrual.
+    /// Earlier versions chunked from `current_slot`, which after any
+    /// no-oracle self-advance would under-report the real gap and let the
+    /// caller's own `accrue_market_to` hit Overflow on the residual.
+    ///
+    /// Caller supplies the catchup price and funding rate. Typical usage:
+    /// the pre-oracle-read funding rate (`funding_rate_e9_pre`) and the
+    /// fresh (or about-to-be-set) `oracle_price`. Using the caller-supplied
+    /// rate (not 0) preserves anti-retroactivity — the rate reflects the
+    /// mark/index state as it was before this instruction, not what the
+    /// idle interval "should have" been (which is unknowable).
+    ///
+    /// If the gap exceeds `CATCHUP_CHUNKS_MAX × max_dt`, returns `Err`
+    /// with `CatchupRequired` for user/admin operations. KeeperCrank uses a
+    /// separate partial-progress wrapper so the crank itself can commit a
+    /// bounded chunk instead of rolling back.
+    ///
+    /// No-op when the gap is already within the envelope, or when
+    /// `max_dt == 0` (misconfiguration guard), or when the engine has never
+    /// seen a real oracle observation (`last_oracle_price == 0`; the
+    /// caller's own `_not_atomic` call will seed it).
+    fn catchup_accrue(
+        engine: &mut RiskEngine,
+        now_slot: u64,
+        price: u64,
+        funding_rate_e9: i128,
+    ) -> Result<(), ProgramError> {
+        let max_dt = engine.params.max_accrual_dt_slots;
+        if max_dt == 0 {
+            return Ok(());
         }
-
-        if account.adl_a_basis == 0 {
-            return Err(PercolatorError::EngineCorruptState.into());
+        if now_slot <= engine.last_market_slot {
+            return Ok(());
         }
-
-        let effective_abs = percolator::wide_math::mul_div_floor_u128(
-            basis.unsigned_abs(),
-            a_side,
-            account.adl_a_basis,
-        );
-        if effective_abs > i128::MAX as u128 {
-            return Err(PercolatorError::EngineCorruptState.into());
+        // Market never had a real oracle observation — nothing to catch up.
+        // The caller's own _not_atomic call will seed last_oracle_price.
+        if engine.last_oracle_price == 0 {
+            return Ok(());
         }
-        if basis < 0 {
-            if effective_abs == 0 {
-                Ok(0)
-            } else {
-                Ok(-(effective_abs as i128))
-            }
-        } else {
-            Ok(effective_abs as i128)
+        // Mirror the engine's own envelope predicate (§5.5 clause 6, v12.19):
+        // accrue_market_to rejects `total_dt > max_dt` when EITHER funding
+        // or price movement would drain equity:
+        //
+        //   funding_active    = rate != 0 AND both OI sides live AND fund_px_last > 0
+        //   price_move_active = P_last > 0 AND oracle_price != P_last AND any OI live
+        //
+        // Prior versions chunked only on `funding_active`. A zero-funding
+        // market with live OI and a fresh oracle price different from
+        // P_last would then skip catchup, and the caller's final
+        // `accrue_market_to(now, fresh, rate)` would itself trip the
+        // envelope (and/or the §5.5 step-9 per-slot price-move cap) and
+        // make the market unrecoverable in-line.
+        //
+        // Do not invent intermediate oracle prices. If the clock gap is too
+        // large, catch up time using stored P_last only, then let the final
+        // real observation pass or fail the engine's dt-scaled price cap.
+        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+        let funding_active = funding_rate_e9 != 0
+            && engine.oi_eff_long_q != 0
+            && engine.oi_eff_short_q != 0
+            && engine.fund_px_last > 0;
+        let price_move_active =
+            engine.last_oracle_price > 0 && price != engine.last_oracle_price && oi_any;
+        if !funding_active && !price_move_active {
+            // Neither accrual driver is active — the engine's envelope
+            // predicate will permit a single-call jump. Caller's final
+            // accrue_market_to handles it in one shot.
+            return Ok(());
         }
-    }
-
-    fn risk_notional_ceil(eff: i128, price: u64) -> u128 {
-        percolator::wide_math::mul_div_ceil_u128(
-            eff.unsigned_abs(),
-            price as u128,
-            percolator::POS_SCALE,
-        )
-    }
-
-    fn current_trade_fee_paid_cap(
-        size: i128,
-        exec_price: u64,
-        trading_fee_bps: u64,
-    ) -> Result<u128, ProgramError> {
-        if trading_fee_bps == 0 || size == 0 {
-            return Ok(0);
+        let cap_bps = engine.params.max_price_move_bps_per_slot;
+        let mut chunks: u32 = 0;
+        while now_slot.saturating_sub(engine.last_market_slot) > max_dt {
+            if chunks >= CATCHUP_CHUNKS_MAX {
+                // Silently returning Ok here would let the caller's
+                // main accrue hit Overflow on the residual, rolling
+                // back ALL catchup progress. Surface CatchupRequired
+                // so the caller routes to KeeperCrank, which commits
+                // account-touching progress before the main op is retried.
+                return Err(PercolatorError::CatchupRequired.into());
+            }
+            let chunk_dt = max_dt;
+            let step_slot = engine.last_market_slot.saturating_add(chunk_dt);
+            let prev_price = engine.last_oracle_price;
+            engine
+                .accrue_market_to(step_slot, prev_price, funding_rate_e9)
+                .map_err(map_risk_error)?;
+            chunks = chunks.saturating_add(1);
         }
-        let notional = safe_mul_div_floor_u128(
-            size.unsigned_abs(),
-            exec_price as u128,
-            percolator::POS_SCALE,
-        )?;
-        if notional == 0 {
-            return Ok(0);
+        if price_move_active {
+            let remaining = now_slot.saturating_sub(engine.last_market_slot);
+            let prev = engine.last_oracle_price;
+            let abs_delta = if price >= prev {
+                price - prev
+            } else {
+                prev - price
+            };
+            if abs_delta != 0 {
+                if remaining == 0 {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
+                let lhs = (abs_delta as u128).saturating_mul(10_000u128);
+                let rhs = (cap_bps as u128)
+                    .saturating_mul(remaining as u128)
+                    .saturating_mul(prev as u128);
+                if lhs > rhs {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
+            }
         }
-        let one_side_fee = safe_mul_div_ceil_u128(notional, trading_fee_bps as u128, 10_000)?;
-        one_side_fee
-            .checked_mul(2)
-            .ok_or_else(|| PercolatorError::EngineOverflow.into())
+        Ok(())
     }
 
-    fn trade_fee_bps_for_execution(
-        config: &MarketConfig,
+    fn oversized_catchup_target(
         engine: &RiskEngine,
         now_slot: u64,
-        oracle_price: u64,
-        exec_price: u64,
-        trade_size: i128,
-    ) -> Result<u64, ProgramError> {
-        let hybrid_after_hours_fallback = oracle::is_hybrid_after_hours_mode(config)
-            && oracle::hybrid_soft_stale_matured(config, now_slot);
-        let dynamic_mark_fee_enabled = (oracle::is_hyperp_mode(config)
-            || hybrid_after_hours_fallback)
-            && config.trade_fee_base_bps < engine.params.max_trading_fee_bps;
-        if !dynamic_mark_fee_enabled {
-            return Ok(config.trade_fee_base_bps);
+        price: u64,
+        funding_rate_e9: i128,
+    ) -> Result<Option<u64>, ProgramError> {
+        match crate::policy::oversized_crank_catchup_target(
+            engine.params.max_accrual_dt_slots,
+            1,
+            engine.last_market_slot,
+            now_slot,
+            engine.last_oracle_price,
+            price,
+            funding_rate_e9,
+            engine.oi_eff_long_q,
+            engine.oi_eff_short_q,
+            engine.fund_px_last,
+        ) {
+            crate::policy::CrankCatchupTarget::None => Ok(None),
+            crate::policy::CrankCatchupTarget::Target(target) => Ok(Some(target)),
         }
+    }
 
-        let trade_notional = safe_mul_div_floor_u128(
-            trade_size.unsigned_abs(),
-            exec_price as u128,
-            percolator::POS_SCALE,
-        )?;
-        let clamped_exec = oracle::clamp_oracle_price(
-            crate::policy::mark_ewma_clamp_base(config.last_effective_price_e6),
-            exec_price,
-            engine.params.max_price_move_bps_per_slot,
-        );
-        let max_side_oi_q = core::cmp::max(engine.oi_eff_long_q, engine.oi_eff_short_q);
-        let max_side_notional =
-            safe_mul_div_ceil_u128(max_side_oi_q, oracle_price as u128, percolator::POS_SCALE)?;
-        let mark_externality_notional = core::cmp::max(max_side_notional, trade_notional)
-            .checked_mul(2)
-            .ok_or(PercolatorError::EngineOverflow)?;
-        let min_externality_bps = if oracle::is_hybrid_after_hours_mode(config)
-            && oracle::hybrid_soft_stale_matured(config, now_slot)
-        {
-            engine.params.max_price_move_bps_per_slot
-        } else {
-            0
-        };
-        crate::policy::dynamic_fee_bps_with_externality_floor(
-            config.trade_fee_base_bps,
-            config.mark_ewma_e6,
-            clamped_exec,
-            config.mark_ewma_halflife_slots,
-            config.mark_ewma_last_slot,
-            now_slot,
-            trade_notional,
-            mark_externality_notional,
-            config.mark_min_fee,
-            min_externality_bps,
-        )
-        .ok_or(PercolatorError::InvalidConfigParam.into())
+    fn partial_crank_config_fields(
+        config: MarketConfig,
+    ) -> crate::policy::PartialCrankConfigFields {
+        crate::policy::PartialCrankConfigFields {
+            last_effective_price_e6: config.last_effective_price_e6,
+            last_hyperp_index_slot: config.last_hyperp_index_slot,
+            last_good_oracle_slot: config.last_good_oracle_slot,
+            last_oracle_publish_time: config.last_oracle_publish_time,
+            oracle_target_price_e6: config.oracle_target_price_e6,
+            oracle_target_publish_time: config.oracle_target_publish_time,
+            fee_sweep_cursor_word: config.fee_sweep_cursor_word,
+            fee_sweep_cursor_bit: config.fee_sweep_cursor_bit,
+        }
     }
 
-    #[inline]
-    fn trade_flow_mark_observation_enabled(config: &MarketConfig, now_slot: u64) -> bool {
-        // Ordinary external markets and Hyperp markets use trade flow as the
-        // mark/funding observation source. Hybrid markets are different:
-        // regular-hours fresh/duplicate oracle reads own the mark baseline,
-        // and trade flow may update the EWMA only once the wrapper is actually
-        // in stale-oracle after-hours fallback.
-        !oracle::is_hybrid_after_hours_mode(config)
-            || oracle::hybrid_soft_stale_matured(config, now_slot)
+    fn apply_partial_crank_config_fields(
+        config: &mut MarketConfig,
+        fields: crate::policy::PartialCrankConfigFields,
+    ) {
+        config.last_effective_price_e6 = fields.last_effective_price_e6;
+        config.last_hyperp_index_slot = fields.last_hyperp_index_slot;
+        config.last_good_oracle_slot = fields.last_good_oracle_slot;
+        config.last_oracle_publish_time = fields.last_oracle_publish_time;
+        config.oracle_target_price_e6 = fields.oracle_target_price_e6;
+        config.oracle_target_publish_time = fields.oracle_target_publish_time;
+        config.fee_sweep_cursor_word = fields.fee_sweep_cursor_word;
+        config.fee_sweep_cursor_bit = fields.fee_sweep_cursor_bit;
     }
 
-    fn safe_mul_div_floor_u128(a: u128, b: u128, d: u128) -> Result<u128, ProgramError> {
-        if d == 0 {
-            return Err(PercolatorError::EngineOverflow.into());
-        }
-        let q = percolator::wide_math::mul_div_floor_u256(
-            percolator::wide_math::U256::from_u128(a),
-            percolator::wide_math::U256::from_u128(b),
-            percolator::wide_math::U256::from_u128(d),
+    fn partial_crank_config_to_write(
+        before_read: MarketConfig,
+        after_read_and_sweep: MarketConfig,
+    ) -> MarketConfig {
+        let fields = crate::policy::partial_crank_config_fields_to_write(
+            partial_crank_config_fields(before_read),
+            partial_crank_config_fields(after_read_and_sweep),
         );
-        q.try_into_u128()
-            .ok_or_else(|| PercolatorError::EngineOverflow.into())
+        let mut restored = before_read;
+        apply_partial_crank_config_fields(&mut restored, fields);
+        restored
     }
 
-    fn safe_mul_div_ceil_u128(a: u128, b: u128, d: u128) -> Result<u128, ProgramError> {
-        if d == 0 {
-            return Err(PercolatorError::EngineOverflow.into());
+    /// Fully advance the engine's market clock to `now_slot` before any
+    /// per-account fee sync. This is an explicit-ordering helper:
+    /// `catchup_accrue` brings the gap within the envelope, then a final
+    /// `accrue_market_to(now_slot)` closes the residual so subsequent
+    /// `sync_account_fee_to_slot_not_atomic(..., now_slot, ...)` runs
+    /// against a fully-accrued market.
+    ///
+    /// Why explicit, when the engine already self-handles it via the main
+    /// op's internal accrue? Because even though the engine uses
+    /// `last_market_slot` (not `current_slot`) for funding dt — so the
+    /// interval is never erased (see
+    /// `test_fee_sync_does_not_erase_market_accrual_interval`) — making
+    /// the ordering explicit in the wrapper removes all ambiguity and
+    /// aligns with the auditor-requested pattern:
+    /// `ensure_market_accrued_to_now; touch/settle; sync fee; engine.<op>_not_atomic`.
+    ///
+    /// The main op's internal `accrue_market_to(now_slot, price, rate)`
+    /// then hits the same-slot + same-price no-op branch (engine §5.4
+    /// early return) — about 150 CU of redundancy, bought for ordering
+    /// clarity.
+    ///
+    /// No-op when the engine has no oracle observation yet (price=0
+    /// catchup is unsafe). Same-slot price replacement is allowed only
+    /// for flat markets; live OI still requires elapsed slot budget.
+    fn ensure_market_accrued_to_now(
+        engine: &mut RiskEngine,
+        now_slot: u64,
+        price: u64,
+        funding_rate_e9: i128,
+    ) -> Result<(), ProgramError> {
+        catchup_accrue(engine, now_slot, price, funding_rate_e9)?;
+        let flat_same_slot_price_update = price > 0
+            && now_slot == engine.last_market_slot
+            && price != engine.last_oracle_price
+            && engine.oi_eff_long_q == 0
+            && engine.oi_eff_short_q == 0;
+        if price > 0 && (now_slot > engine.last_market_slot || flat_same_slot_price_update) {
+            engine
+                .accrue_market_to(now_slot, price, funding_rate_e9)
+                .map_err(map_risk_error)?;
         }
-        let q = percolator::wide_math::checked_mul_div_ceil_u256(
-            percolator::wide_math::U256::from_u128(a),
-            percolator::wide_math::U256::from_u128(b),
-            percolator::wide_math::U256::from_u128(d),
-        )
-        .ok_or(PercolatorError::EngineOverflow)?;
-        q.try_into_u128()
-            .ok_or_else(|| PercolatorError::EngineOverflow.into())
+        Ok(())
     }
 
-    fn reject_stuck_target_accrual(
-        config: &MarketConfig,
+    fn reject_account_limited_market_progress(
         engine: &RiskEngine,
         now_slot: u64,
         price: u64,
+        funding_rate_e9: i128,
     ) -> Result<(), ProgramError> {
-        let dt = now_slot
-            .checked_sub(engine.last_market_slot)
-            .ok_or(PercolatorError::EngineOverflow)?;
-        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
-        if dt > 0
-            && oi_any
-            && oracle_target_pending(config, engine)
-            && price == engine.last_oracle_price
-        {
+        let dt_slots = now_slot.saturating_sub(engine.last_market_slot);
+        if !crate::policy::account_limited_op_allows_accrual(
+            engine.oi_eff_long_q,
+            engine.oi_eff_short_q,
+            engine.last_oracle_price,
+            price,
+            funding_rate_e9,
+            engine.fund_px_last,
+            dt_slots,
+        ) {
             return Err(PercolatorError::CatchupRequired.into());
         }
         Ok(())
     }
 
-    fn prepare_lazy_free_head(engine: &mut RiskEngine) -> Result<u16, ProgramError> {
-        let max_accounts = core::cmp::min(
-            engine.params.max_accounts as usize,
-            percolator::MAX_ACCOUNTS,
-        );
-        let idx = engine.free_head;
-        if idx == u16::MAX || (idx as usize) >= max_accounts || engine_is_used(engine, idx as usize)
-        {
-            return Err(PercolatorError::EngineOverflow.into());
+    fn ensure_market_accrued_to_now_for_account_limited_op(
+        engine: &mut RiskEngine,
+        config: &MarketConfig,
+        now_slot: u64,
+        price: u64,
+        funding_rate_e9: i128,
+    ) -> Result<(), ProgramError> {
+        reject_stuck_target_accrual(config, engine, now_slot, price)?;
+        reject_account_limited_market_progress(engine, now_slot, price, funding_rate_e9)?;
+        ensure_market_accrued_to_now(engine, now_slot, price, funding_rate_e9)
+    }
+
+    fn keeper_candidate_inspection_cap() -> u16 {
+        core::cmp::min(
+            percolator::MAX_TOUCHED_PER_INSTRUCTION as u16,
+            crate::constants::CANDIDATE_INSPECTION_BUDGET_PER_CRANK,
+        )
+    }
+
+    fn candidate_list_contains(
+        candidates: &[(u16, Option<percolator::LiquidationPolicy>)],
+        idx: u16,
+    ) -> bool {
+        candidates
+            .iter()
+            .any(|&(candidate_idx, _)| candidate_idx == idx)
+    }
+
+    /// Promote the upcoming Phase-2 structural window into the engine's Phase-1
+    /// candidate stream. Phase 2 is intentionally touch-only; without this
+    /// promotion, a caller-supplied candidate prefix can make the cursor settle
+    /// a later losing account without giving the engine a same-crank
+    /// liquidation opportunity. Caller candidates remain untrusted hints: they
+    /// are skipped here so padding cannot consume this structural budget twice.
+    fn append_phase2_fullclose_candidates(
+        engine: &RiskEngine,
+        combined: &mut alloc::vec::Vec<(u16, Option<percolator::LiquidationPolicy>)>,
+        caller_candidates: &[(u16, Option<percolator::LiquidationPolicy>)],
+        rr_touch_limit: u64,
+        rr_scan_limit: u64,
+        combined_cap: usize,
+    ) -> Result<(), ProgramError> {
+        if rr_touch_limit == 0 || rr_scan_limit == 0 || combined.len() >= combined_cap {
+            return Ok(());
+        }
+        let wrap_bound =
+            core::cmp::min(engine.params.max_accounts, percolator::MAX_ACCOUNTS as u64);
+        if wrap_bound == 0 || engine.rr_cursor_position >= wrap_bound {
+            return Err(PercolatorError::EngineCorruptState.into());
         }
 
-        let i = idx as usize;
-        let valid_head = engine.prev_free[i] == u16::MAX
-            && (engine.next_free[i] == u16::MAX
-                || ((engine.next_free[i] as usize) < max_accounts
-                    && !engine_is_used(engine, engine.next_free[i] as usize)
-                    && engine.prev_free[engine.next_free[i] as usize] == idx));
-        if !valid_head {
-            if idx as u64 != engine.num_used_accounts as u64 {
-                return Err(PercolatorError::EngineOverflow.into());
+        let scan_cap = core::cmp::min(rr_scan_limit, wrap_bound);
+        let mut i = engine.rr_cursor_position;
+        let mut inspected = 0u64;
+        let mut touched = 0u64;
+        while inspected < scan_cap && touched < rr_touch_limit && combined.len() < combined_cap {
+            let idx = i as usize;
+            if idx_used_in_market(engine, idx) {
+                touched = touched
+                    .checked_add(1)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                let idx_u16: u16 = idx
+                    .try_into()
+                    .map_err(|_| PercolatorError::EngineOverflow)?;
+                if !candidate_list_contains(caller_candidates, idx_u16)
+                    && !candidate_list_contains(combined, idx_u16)
+                    && effective_pos_q_checked(engine, idx)? != 0
+                {
+                    combined.push((idx_u16, Some(percolator::LiquidationPolicy::FullClose)));
+                }
             }
-            let next = if i + 1 < max_accounts {
-                (i + 1) as u16
-            } else {
-                u16::MAX
-            };
-            engine.prev_free[i] = u16::MAX;
-            engine.next_free[i] = next;
-            if next != u16::MAX {
-                engine.prev_free[next as usize] = idx;
+
+            i = i.checked_add(1).ok_or(PercolatorError::EngineOverflow)?;
+            inspected = inspected
+                .checked_add(1)
+                .ok_or(PercolatorError::EngineOverflow)?;
+            if i == wrap_bound {
+                break;
             }
         }
 
-        Ok(idx)
+        Ok(())
     }
 
-    /// Maximum number of max_dt chunks the in-line catchup can advance per
-    /// instruction. Bounded by CU budget — each `accrue_market_to` is cheap
-    /// but not free. For gaps beyond this, callers must use KeeperCrank to
-    /// commit account-touching progress before attempting a main operation.
-    ///
-    /// 20 × max_dt = 20 × 10 = 200 slots per single instruction. Larger
-    /// gaps require multiple KeeperCrank calls — that's the design
-    /// contract, not a misconfig.
-    const CATCHUP_CHUNKS_MAX: u32 = 20;
-
-    /// Pre-chunk market-clock advancement when the gap since the last
-    /// engine *accrue* exceeds `params.max_accrual_dt_slots`. The engine
-    /// rejects any single `accrue_market_to` whose funding-active dt
-    /// exceeds the envelope (spec §1.4 / §5.5 clause 6), so every
-    /// accrue-bearing instruction (KeeperCrank, TradeCpi, TradeNoCpi,
-    /// Withdraw, Liquidate, Close, Settle, Convert, live Insurance
-    /// withdraw, Ordinary ResolveMarket, UpdateConfig) must close that
-    /// gap before its own accrue.
-    ///
-    /// Cursor: loops on `engine.last_market_slot`, NOT `current_slot`.
-    /// `last_market_slot` is the only cursor `accrue_market_to` uses to
-    /// compute `total_dt = now_slot - last_market_slot`; `current_slot`
-    /// can be advanced by non-accruing public endpoints (fee sync on Live,
-    /// deposit/top-up without oracle) so it does not track market accrual.
-    /// Earlier versions chunked from `current_slot`, which after any
-    /// no-oracle self-advance would under-report the real gap and let the
-    /// caller's own `accrue_market_to` hit Overflow on the residual.
-    ///
-    /// Caller supplies the catchup price and funding rate. Typical usage:
-    /// the pre-oracle-read funding rate (`funding_rate_e9_pre`) and the
-    /// fresh (or about-to-be-set) `oracle_price`. Using the caller-supplied
-    /// rate (not 0) preserves anti-retroactivity — the rate reflects the
-    /// mark/index state as it was before this instruction, not what the
-    /// idle interval "should have" been (which is unknowable).
+    fn sync_keeper_candidate_fees_after_crank(
+        engine: &mut RiskEngine,
+        combined: &[(u16, Option<percolator::LiquidationPolicy>)],
+        now_slot: u64,
+        maintenance_fee_per_slot: u128,
+    ) -> Result<usize, ProgramError> {
+        if maintenance_fee_per_slot == 0 {
+            return Ok(0);
+        }
+        let cap = core::cmp::min(
+            crate::constants::LIQ_BUDGET_PER_CRANK as usize,
+            crate::constants::FEE_SWEEP_BUDGET,
+        );
+        let mut synced: [u16; crate::constants::LIQ_BUDGET_PER_CRANK as usize] =
+            [u16::MAX; crate::constants::LIQ_BUDGET_PER_CRANK as usize];
+        let mut synced_count = 0usize;
+        let mut attempts = 0usize;
+        let mut candidate_syncs = 0usize;
+        for &(idx, _policy) in combined.iter() {
+            if attempts >= cap || candidate_syncs >= crate::constants::FEE_SWEEP_BUDGET {
+                break;
+            }
+            let i = idx as usize;
+            if !idx_used_in_market(engine, i) {
+                continue;
+            }
+            attempts += 1;
+            let mut already = false;
+            for j in 0..synced_count {
+                if synced[j] == idx {
+                    already = true;
+                    break;
+                }
+            }
+            if already {
+                continue;
+            }
+            // Candidate fees run only after the engine's authoritative
+            // candidate pass, and only when the account is now flat/current.
+            // That keeps recurring fees junior to lazy mark/funding losses
+            // realized by the touch.
+            if engine.accounts[i].last_fee_slot >= now_slot {
+                continue;
+            }
+            if !recurring_fee_pre_touch_safe(engine, i)? {
+                continue;
+            }
+            engine
+                .sync_account_fee_to_slot_not_atomic(idx, now_slot, maintenance_fee_per_slot)
+                .map_err(map_risk_error)?;
+            synced[synced_count] = idx;
+            synced_count += 1;
+            candidate_syncs += 1;
+        }
+        Ok(candidate_syncs)
+    }
+
+    /// Incrementally sweep maintenance fees from the current cursor position.
+    /// Scans bitmap words starting at `(fee_sweep_cursor_word,
+    /// fee_sweep_cursor_bit)`, calling `sync_account_fee_to_slot_not_atomic`
+    /// on every set bit. Stops EXACTLY at `FEE_SWEEP_BUDGET` syncs — the bit
+    /// cursor lets us pause mid-word without losing remaining set bits to
+    /// budget truncation.
     ///
-    /// If the gap exceeds `CATCHUP_CHUNKS_MAX × max_dt`, returns `Err`
-    /// with `CatchupRequired` for user/admin operations. KeeperCrank uses a
-    /// separate partial-progress wrapper so the crank itself can commit a
-    /// bounded chunk instead of rolling back.
+    /// Correctness: the engine's per-account `last_fee_slot` is the source of
+    /// truth. When the cursor reaches an account, that account's sync call
+    /// realizes fees for the *entire* elapsed interval
+    /// `[account.last_fee_slot, now_slot]` in one charge — no fees are lost
+    /// between cursor visits. Self-acting accounts realize their own fees inline
+    /// on every capital-sensitive instruction after the engine local touch; the
+    /// sweep handles flat/current accounts that have not self-acted.
     ///
-    /// No-op when the gap is already within the envelope, or when
-    /// `max_dt == 0` (misconfiguration guard), or when the engine has never
-    /// seen a real oracle observation (`last_oracle_price == 0`; the
-    /// caller's own `_not_atomic` call will seed it).
-    fn catchup_accrue(
+    /// CU bound: at most `FEE_SWEEP_BUDGET` sync calls per crank (strictly,
+    /// thanks to the bit cursor), plus O(BITMAP_WORDS) word reads. Constant
+    /// in `max_accounts`, so a 4096-slot market is handled the same as a
+    /// 64-slot market.
+    fn sweep_maintenance_fees(
         engine: &mut RiskEngine,
+        config: &mut MarketConfig,
         now_slot: u64,
-        price: u64,
-        funding_rate_e9: i128,
+        max_syncs: usize,
     ) -> Result<(), ProgramError> {
-        let max_dt = engine.params.max_accrual_dt_slots;
-        if max_dt == 0 {
-            return Ok(());
-        }
-        if now_slot <= engine.last_market_slot {
-            return Ok(());
-        }
-        // Market never had a real oracle observation — nothing to catch up.
-        // The caller's own _not_atomic call will seed last_oracle_price.
-        if engine.last_oracle_price == 0 {
+        if config.maintenance_fee_per_slot == 0 {
             return Ok(());
         }
-        // Mirror the engine's own envelope predicate (§5.5 clause 6, v12.19):
-        // accrue_market_to rejects `total_dt > max_dt` when EITHER funding
-        // or price movement would drain equity:
-        //
-        //   funding_active    = rate != 0 AND both OI sides live AND fund_px_last > 0
-        //   price_move_active = P_last > 0 AND oracle_price != P_last AND any OI live
-        //
-        // Prior versions chunked only on `funding_active`. A zero-funding
-        // market with live OI and a fresh oracle price different from
-        // P_last would then skip catchup, and the caller's final
-        // `accrue_market_to(now, fresh, rate)` would itself trip the
-        // envelope (and/or the §5.5 step-9 per-slot price-move cap) and
-        // make the market unrecoverable in-line.
-        //
-        // Do not invent intermediate oracle prices. If the clock gap is too
-        // large, catch up time using stored P_last only, then let the final
-        // real observation pass or fail the engine's dt-scaled price cap.
-        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
-        let funding_active = funding_rate_e9 != 0
-            && engine.oi_eff_long_q != 0
-            && engine.oi_eff_short_q != 0
-            && engine.fund_px_last > 0;
-        let price_move_active =
-            engine.last_oracle_price > 0 && price != engine.last_oracle_price && oi_any;
-        if !funding_active && !price_move_active {
-            // Neither accrual driver is active — the engine's envelope
-            // predicate will permit a single-call jump. Caller's final
-            // accrue_market_to handles it in one shot.
+        // Early-out when the caller has already exhausted the per-
+        // instruction sync budget on pre-sweep candidate syncs.
+        if max_syncs == 0 {
             return Ok(());
         }
-        let cap_bps = engine.params.max_price_move_bps_per_slot;
-        let mut chunks: u32 = 0;
-        while now_slot.saturating_sub(engine.last_market_slot) > max_dt {
-            if chunks >= CATCHUP_CHUNKS_MAX {
-                // Silently returning Ok here would let the caller's
-                // main accrue hit Overflow on the residual, rolling
-                // back ALL catchup progress. Surface CatchupRequired
-                // so the caller routes to KeeperCrank, which commits
-                // account-touching progress before the main op is retried.
-                return Err(PercolatorError::CatchupRequired.into());
-            }
-            let chunk_dt = max_dt;
-            let step_slot = engine.last_market_slot.saturating_add(chunk_dt);
-            let prev_price = engine.last_oracle_price;
-            engine
-                .accrue_market_to(step_slot, prev_price, funding_rate_e9)
-                .map_err(map_risk_error)?;
-            chunks = chunks.saturating_add(1);
-        }
-        if price_move_active {
-            let remaining = now_slot.saturating_sub(engine.last_market_slot);
-            let prev = engine.last_oracle_price;
-            let abs_delta = if price >= prev {
-                price - prev
+        const BITMAP_WORDS: usize = (percolator::MAX_ACCOUNTS + 63) / 64;
+        // Normalize cursor in case of stale/corrupt values.
+        let mut word_cursor = (config.fee_sweep_cursor_word as usize) % BITMAP_WORDS;
+        let mut bit_cursor = (config.fee_sweep_cursor_bit as usize) & 63;
+        let mut syncs_done: usize = 0;
+        let mut words_scanned: usize = 0;
+        // Budget check is inside the inner loop so we can stop exactly at
+        // max_syncs, not after completing the current word.
+        'outer: while words_scanned < BITMAP_WORDS {
+            // Skip bits below bit_cursor on the resume word.
+            let resume_mask = if bit_cursor == 0 {
+                u64::MAX
             } else {
-                prev - price
+                // Clear bits 0..bit_cursor (they were already processed last call).
+                !((1u64 << bit_cursor).wrapping_sub(1))
             };
-            if abs_delta != 0 {
-                if remaining == 0 {
-                    return Err(PercolatorError::OracleInvalid.into());
+            let mut bits = engine.used[word_cursor] & resume_mask;
+            while bits != 0 {
+                if syncs_done >= max_syncs {
+                    // Stop EXACTLY at budget. Save the next unprocessed bit
+                    // as the resume point for the following crank.
+                    let next_bit = bits.trailing_zeros() as usize;
+                    config.fee_sweep_cursor_word = word_cursor as u64;
+                    config.fee_sweep_cursor_bit = next_bit as u64;
+                    return Ok(());
                 }
-                let lhs = (abs_delta as u128).saturating_mul(10_000u128);
-                let rhs = (cap_bps as u128)
-                    .saturating_mul(remaining as u128)
-                    .saturating_mul(prev as u128);
-                if lhs > rhs {
-                    return Err(PercolatorError::OracleInvalid.into());
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let idx = word_cursor * 64 + bit;
+                if !idx_within_market_capacity(engine, idx) {
+                    continue;
+                }
+                // Keeper fee sweeping is loss-senior: do not charge recurring
+                // fees from exposed, stale-epoch, reserved, or negative-PnL
+                // accounts before their local losses and side effects have
+                // been settled by an engine touch. The bitmap sweep is for
+                // flat/current accounts only.
+                if !recurring_fee_pre_touch_safe(engine, idx)? {
+                    continue;
                 }
+                engine
+                    .sync_account_fee_to_slot_not_atomic(
+                        idx as u16,
+                        now_slot,
+                        config.maintenance_fee_per_slot,
+                    )
+                    .map_err(map_risk_error)?;
+                syncs_done += 1;
+
+                // Permissionless dust reclaim: fee accrual just charged
+                // this account; if that drained capital to zero on a
+                // flat account (no position, no PnL, no reserve, no
+                // pending, no positive fee_credits), free the slot now.
+                // Without this, an attacker could fill `max_accounts`
+                // with dust and brick onboarding even when fees drain
+                // capital, because slot reclamation would otherwise
+                // require a targeted crank candidate.
+                //
+                // All six flat-clean predicates the engine's reclaim
+                // checks are mirrored here so the call CANNOT hit an
+                // `Undercollateralized` / `CorruptState` early return.
+                // That lets us propagate any remaining error with `?`
+                // rather than silently swallowing a `_not_atomic`
+                // failure — per the engine contract, a failing
+                // `_not_atomic` may have already mutated state and the
+                // caller must abort the transaction. Envelope /
+                // market-mode guards upstream (KeeperCrank's oracle
+                // read + is_resolved gate + accrue_market_to) ensure
+                // the remaining engine preconditions hold, so in
+                // practice the `?` is unreachable — but if a future
+                // engine change introduces a new precondition, we get
+                // a transaction rollback instead of silent corruption.
+                reclaim_flat_zero_account_if_eligible(engine, idx as u16, now_slot)?;
+            }
+            // Word fully drained — advance to next word, reset bit cursor.
+            word_cursor = (word_cursor + 1) % BITMAP_WORDS;
+            bit_cursor = 0;
+            words_scanned += 1;
+            // Budget may have hit right at the end of the word — avoid one
+            // wasted iteration on the next (empty in the caller's view) word.
+            if syncs_done >= max_syncs {
+                break 'outer;
             }
         }
+        config.fee_sweep_cursor_word = word_cursor as u64;
+        config.fee_sweep_cursor_bit = 0;
         Ok(())
     }
 
-    fn oversized_catchup_target(
-        engine: &RiskEngine,
+    #[inline]
+    /// Crank dust sweep: close a user position below the dust floor
+    /// against the sweep LP at the crank price with no fee, turning it
+    /// into realized PnL. A position the sweep LP cannot take goes to the
+    /// backstop vault instead, if it can. Returns the LP that took it;
+    /// ineligible candidates are ignored.
+    #[allow(clippy::too_many_arguments)]
+    fn sweep_dust_position_if_eligible(
+        engine: &mut RiskEngine,
+        config: &MarketConfig,
+        dust: &state::DustLimits,
+        backstop_vault: Option<u16>,
+        idx: u16,
         now_slot: u64,
         price: u64,
         funding_rate_e9: i128,
-    ) -> Result<Option<u64>, ProgramError> {
-        match crate::policy::oversized_crank_catchup_target(
-            engine.params.max_accrual_dt_slots,
-            1,
-            engine.last_market_slot,
-            now_slot,
-            engine.last_oracle_price,
-            price,
-            funding_rate_e9,
-            engine.oi_eff_long_q,
-            engine.oi_eff_short_q,
-            engine.fund_px_last,
-        ) {
-            crate::policy::CrankCatchupTarget::None => Ok(None),
-            crate::policy::CrankCatchupTarget::Target(target) => Ok(Some(target)),
+    ) -> Result<Option<u16>, ProgramError> {
+        if dust.min_position_abs == 0
+            || engine_is_resolved(engine)
+            || !idx_used_in_market(engine, idx as usize)
+            || engine.accounts[idx as usize].kind == percolator::Account::KIND_LP
+        {
+            return Ok(None);
         }
-    }
-
-    fn partial_crank_config_fields(
-        config: MarketConfig,
-    ) -> crate::policy::PartialCrankConfigFields {
-        crate::policy::PartialCrankConfigFields {
-            last_effective_price_e6: config.last_effective_price_e6,
-            last_hyperp_index_slot: config.last_hyperp_index_slot,
-            last_good_oracle_slot: config.last_good_oracle_slot,
-            last_oracle_publish_time: config.last_oracle_publish_time,
-            oracle_target_price_e6: config.oracle_target_price_e6,
-            oracle_target_publish_time: config.oracle_target_publish_time,
-            fee_sweep_cursor_word: config.fee_sweep_cursor_word,
-            fee_sweep_cursor_bit: config.fee_sweep_cursor_bit,
+        if !crate::policy::is_dust_position(
+            effective_pos_q_checked(engine, idx as usize)?,
+            dust.min_position_abs,
+        ) {
+            return Ok(None);
         }
+        let takers = [Some(dust.sweep_lp_idx), backstop_vault];
+        for lp_idx in takers.into_iter().flatten() {
+            if lp_idx == idx
+                || !idx_used_in_market(engine, lp_idx as usize)
+                || engine.accounts[lp_idx as usize].kind != percolator::Account::KIND_LP
+            {
+                continue;
+            }
+            settle_pair_then_sync_fee_current(
+                engine,
+                config,
+                idx,
+                lp_idx,
+                now_slot,
+                price,
+                funding_rate_e9,
+                engine.params.h_min,
+                engine.params.h_max,
+                Some(engine.params.maintenance_margin_bps as u128),
+            )?;
+            let pos = effective_pos_q_checked(engine, idx as usize)?;
+            if pos == 0 {
+                return Ok(None);
+            }
+            if !sweep_lp_can_absorb(engine, lp_idx, pos, price)? {
+                continue;
+            }
+            execute_trade_with_matcher(
+                engine,
+                &CpiMatcher {
+                    exec_price: price,
+                    exec_size: -pos,
+                },
+                lp_idx,
+                idx,
+                now_slot,
+                price,
+                -pos,
+                funding_rate_e9,
+                0,
+                0,
+                0,
+            )
+            .map_err(map_risk_error)?;
+            return Ok(Some(lp_idx));
+        }
+        Ok(None)
     }
 
-    fn apply_partial_crank_config_fields(
-        config: &mut MarketConfig,
-        fields: crate::policy::PartialCrankConfigFields,
-    ) {
-        config.last_effective_price_e6 = fields.last_effective_price_e6;
-        config.last_hyperp_index_slot = fields.last_hyperp_index_slot;
-        config.last_good_oracle_slot = fields.last_good_oracle_slot;
-        config.last_oracle_publish_time = fields.last_oracle_publish_time;
-        config.oracle_target_price_e6 = fields.oracle_target_price_e6;
-        config.oracle_target_publish_time = fields.oracle_target_publish_time;
-        config.fee_sweep_cursor_word = fields.fee_sweep_cursor_word;
-        config.fee_sweep_cursor_bit = fields.fee_sweep_cursor_bit;
-    }
-
-    fn partial_crank_config_to_write(
-        before_read: MarketConfig,
-        after_read_and_sweep: MarketConfig,
-    ) -> MarketConfig {
-        let fields = crate::policy::partial_crank_config_fields_to_write(
-            partial_crank_config_fields(before_read),
-            partial_crank_config_fields(after_read_and_sweep),
+    /// Whether the (settled) sweep LP can take over `pos` at `price`
+    /// without the fill being rejected: a reduction always can; growing
+    /// the LP's exposure needs its side open to new OI and equity at or
+    /// above the initial requirement on the new size. A sweep the LP
+    /// cannot take is skipped, not failed, so one thin LP cannot stall
+    /// the crank.
+    fn sweep_lp_can_absorb(
+        engine: &RiskEngine,
+        lp_idx: u16,
+        pos: i128,
+        price: u64,
+    ) -> Result<bool, ProgramError> {
+        let before = effective_pos_q_checked(engine, lp_idx as usize)?;
+        let after = before.saturating_add(pos);
+        if after == 0
+            || (after.signum() == before.signum() && after.unsigned_abs() <= before.unsigned_abs())
+        {
+            return Ok(true);
+        }
+        let side_mode = if after > 0 {
+            engine.side_mode_long
+        } else {
+            engine.side_mode_short
+        };
+        if side_mode != percolator::SideMode::Normal {
+            return Ok(false);
+        }
+        let h = account_health(engine, lp_idx, price)?;
+        let req = core::cmp::max(
+            risk_notional_ceil(after, price)
+                .saturating_mul(engine.params.initial_margin_bps as u128)
+                / 10_000,
+            engine.params.min_nonzero_im_req,
         );
-        let mut restored = before_read;
-        apply_partial_crank_config_fields(&mut restored, fields);
-        restored
+        Ok(h.equity() >= req.min(i128::MAX as u128) as i128)
     }
 
-    /// Fully advance the engine's market clock to `now_slot` before any
-    /// per-account fee sync. This is an explicit-ordering helper:
-    /// `catchup_accrue` brings the gap within the envelope, then a final
-    /// `accrue_market_to(now_slot)` closes the residual so subsequent
-    /// `sync_account_fee_to_slot_not_atomic(..., now_slot, ...)` runs
-    /// against a fully-accrued market.
-    ///
-    /// Why explicit, when the engine already self-handles it via the main
-    /// op's internal accrue? Because even though the engine uses
-    /// `last_market_slot` (not `current_slot`) for funding dt — so the
-    /// interval is never erased (see
-    /// `test_fee_sync_does_not_erase_market_accrual_interval`) — making
-    /// the ordering explicit in the wrapper removes all ambiguity and
-    /// aligns with the auditor-requested pattern:
-    /// `ensure_market_accrued_to_now; touch/settle; sync fee; engine.<op>_not_atomic`.
-    ///
-    /// The main op's internal `accrue_market_to(now_slot, price, rate)`
-    /// then hits the same-slot + same-price no-op branch (engine §5.4
-    /// early return) — about 150 CU of redundancy, bought for ordering
-    /// clarity.
-    ///
-    /// No-op when the engine has no oracle observation yet (price=0
-    /// catchup is unsafe). Same-slot price replacement is allowed only
-    /// for flat markets; live OI still requires elapsed slot budget.
-    fn ensure_market_accrued_to_now(
+    fn reclaim_flat_zero_account_if_eligible(
         engine: &mut RiskEngine,
+        idx: u16,
         now_slot: u64,
-        price: u64,
-        funding_rate_e9: i128,
     ) -> Result<(), ProgramError> {
-        catchup_accrue(engine, now_slot, price, funding_rate_e9)?;
-        let flat_same_slot_price_update = price > 0
-            && now_slot == engine.last_market_slot
-            && price != engine.last_oracle_price
-            && engine.oi_eff_long_q == 0
-            && engine.oi_eff_short_q == 0;
-        if price > 0 && (now_slot > engine.last_market_slot || flat_same_slot_price_update) {
+        if !idx_used_in_market(engine, idx as usize) {
+            return Ok(());
+        }
+        let acc = &engine.accounts[idx as usize];
+        let fee_credits = acc.fee_credits.get();
+        if acc.capital.is_zero()
+            && acc.position_basis_q == 0
+            && acc.pnl == 0
+            && acc.reserved_pnl == 0
+            && acc.sched_present == 0
+            && acc.pending_present == 0
+            && fee_credits <= 0
+        {
+            if fee_credits == i128::MIN {
+                return Err(PercolatorError::EngineCorruptState.into());
+            }
             engine
-                .accrue_market_to(now_slot, price, funding_rate_e9)
+                .reclaim_empty_account_not_atomic(idx, now_slot)
                 .map_err(map_risk_error)?;
         }
         Ok(())
     }
 
-    fn reject_account_limited_market_progress(
-        engine: &RiskEngine,
+    fn compute_current_funding_rate_e9(config: &MarketConfig) -> Result<i128, ProgramError> {
+        crate::policy::funding_rate_e9_from_mark_index(
+            config.mark_ewma_e6,
+            config.last_effective_price_e6,
+            config.funding_horizon_slots,
+            config.funding_k_bps,
+            config.funding_max_premium_bps,
+            config.funding_max_e9_per_slot,
+        )
+        .ok_or(PercolatorError::InvalidConfigParam.into())
+    }
+
+    fn execute_trade_with_matcher<M: MatchingEngine>(
+        engine: &mut RiskEngine,
+        matcher: &M,
+        lp_idx: u16,
+        user_idx: u16,
         now_slot: u64,
-        price: u64,
+        oracle_price: u64,
+        size: i128,
         funding_rate_e9: i128,
-    ) -> Result<(), ProgramError> {
-        let dt_slots = now_slot.saturating_sub(engine.last_market_slot);
-        if !crate::policy::account_limited_op_allows_accrual(
-            engine.oi_eff_long_q,
-            engine.oi_eff_short_q,
-            engine.last_oracle_price,
-            price,
+        lp_account_id: u64,
+        trade_fee_bps: u64,
+        _maintenance_fee_per_slot: u128,
+    ) -> Result<(), RiskError> {
+        let lp = &engine.accounts[lp_idx as usize];
+        let exec = matcher.execute_match(
+            &lp.matcher_program,
+            &lp.matcher_context,
+            lp_account_id,
+            oracle_price,
+            size,
+        )?;
+        // POS_SCALE = 1_000_000 in spec v11.5, same as instruction units.
+        // No conversion needed.
+        let size_q: i128 = exec.size;
+        // Spec v12: size_q must be > 0. Account `a` buys from `b`.
+        // Positive size = user buys from LP (user goes long).
+        // Negative size = LP buys from user (user goes short) — swap order.
+        let (a, b, abs_size) = if size_q > 0 {
+            (user_idx, lp_idx, size_q)
+        } else if size_q < 0 {
+            // checked_neg rejects i128::MIN (which has no positive counterpart)
+            let pos = size_q.checked_neg().ok_or(RiskError::Overflow)?;
+            (lp_idx, user_idx, pos)
+        } else {
+            return Err(RiskError::Overflow);
+        };
+        let admit_h_min = engine.params.h_min;
+        let admit_h_max = engine.params.h_max;
+        let admit_threshold = Some(engine.params.maintenance_margin_bps as u128);
+        engine.execute_trade_not_atomic(
+            a,
+            b,
+            oracle_price,
+            now_slot,
+            abs_size,
+            exec.price,
             funding_rate_e9,
-            engine.fund_px_last,
-            dt_slots,
-        ) {
-            return Err(PercolatorError::CatchupRequired.into());
-        }
-        Ok(())
+            trade_fee_bps,
+            admit_h_min,
+            admit_h_max,
+            admit_threshold,
+        )
     }
 
-    fn ensure_market_accrued_to_now_for_account_limited_op(
+    /// One fill as agreed by both signers (TradeNoCpi) or as returned by
+    /// the LP's matcher (TradeCpi).
+    struct Fill {
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        exec_price: u64,
+        lp_account_id: u64,
+        client_order_id: u64,
+    }
+
+    /// What `execute_fill` leaves for the caller's bookkeeping.
+    struct FillExecution {
+        result: crate::ix::TradeResult,
+        /// `result.fee_paid` capped at the nominal fee for the fill.
+        fee: u128,
+        user_pos_before: i128,
+        lp_pos_before: i128,
+        treasury_share: u128,
+        lp_fee_share: u128,
+    }
+
+    /// Engine side of a fill, shared by `settle_fill` and the TradeNoCpi
+    /// preview so the two cannot drift: prices the fee, executes the
+    /// trade, checks the wrapper guards and routes the fee.
+    fn execute_fill(
         engine: &mut RiskEngine,
         config: &MarketConfig,
+        guards: &TradeGuards,
+        fill: &Fill,
         now_slot: u64,
         price: u64,
         funding_rate_e9: i128,
-    ) -> Result<(), ProgramError> {
-        reject_stuck_target_accrual(config, engine, now_slot, price)?;
-        reject_account_limited_market_progress(engine, now_slot, price, funding_rate_e9)?;
-        ensure_market_accrued_to_now(engine, now_slot, price, funding_rate_e9)
-    }
-
-    fn keeper_candidate_inspection_cap() -> u16 {
-        core::cmp::min(
-            percolator::MAX_TOUCHED_PER_INSTRUCTION as u16,
-            crate::constants::CANDIDATE_INSPECTION_BUDGET_PER_CRANK,
+    ) -> Result<FillExecution, ProgramError> {
+        let Fill {
+            lp_idx,
+            user_idx,
+            size,
+            exec_price,
+            lp_account_id,
+            ..
+        } = *fill;
+        // Snapshot insurance fund balance for fee-weighted EWMA after
+        // recurring fees. The delta after execute_trade is bounded to
+        // trade-fee impact below.
+        // NOTE: If loss absorption occurs during the same trade (spec §5.4),
+        // delta undercounts the actual fee. This is the conservative direction:
+        // mark is stickier during volatile loss-absorption events, never
+        // more manipulable. A future engine API could expose fee_paid directly.
+        let fee_bps =
+            trade_fee_bps_for_execution(config, engine, now_slot, price, exec_price, size)?;
+        let fee_cap = current_trade_fee_paid_cap(size, exec_price, fee_bps)?;
+        let ins_before = engine.insurance_fund.balance.get();
+        let user_pos_before = effective_pos_q_checked(engine, user_idx as usize)?;
+        let lp_pos_before = effective_pos_q_checked(engine, lp_idx as usize)?;
+
+        execute_trade_with_matcher(
+            engine,
+            &CpiMatcher {
+                exec_price,
+                exec_size: size,
+            },
+            lp_idx,
+            user_idx,
+            now_slot,
+            price,
+            size,
+            funding_rate_e9,
+            lp_account_id,
+            fee_bps,
+            0,
         )
+        .map_err(map_risk_error)?;
+        guards.check(engine, user_idx, user_pos_before, size, price, now_slot)?;
+        let fee_paid = engine
+            .insurance_fund
+            .balance
+            .get()
+            .saturating_sub(ins_before);
+        // Routing moves the LP share out of insurance only after the
+        // delta is read, so mark weighting still sees the full fee.
+        let fee = fee_paid.min(fee_cap);
+        let (treasury_share, lp_fee_share) =
+            route_trade_fee(engine, &guards.fee_split, lp_idx, fee, now_slot)?;
+        Ok(FillExecution {
+            result: crate::ix::TradeResult {
+                exec_size: size,
+                exec_price_e6: exec_price,
+                oracle_price_e6: price,
+                fee_bps,
+                fee_paid,
+            },
+            fee,
+            user_pos_before,
+            lp_pos_before,
+            treasury_share,
+            lp_fee_share,
+        })
     }
 
-    fn candidate_list_contains(
-        candidates: &[(u16, Option<percolator::LiquidationPolicy>)],
-        idx: u16,
-    ) -> bool {
-        candidates
-            .iter()
-            .any(|&(candidate_idx, _)| candidate_idx == idx)
-    }
-
-    /// Promote the upcoming Phase-2 structural window into the engine's Phase-1
-    /// candidate stream. Phase 2 is intentionally touch-only; without this
-    /// promotion, a caller-supplied candidate prefix can make the cursor settle
-    /// a later losing account without giving the engine a same-crank
-    /// liquidation opportunity. Caller candidates remain untrusted hints: they
-    /// are skipped here so padding cannot consume this structural budget twice.
-    fn append_phase2_fullclose_candidates(
-        engine: &RiskEngine,
-        combined: &mut alloc::vec::Vec<(u16, Option<percolator::LiquidationPolicy>)>,
-        caller_candidates: &[(u16, Option<percolator::LiquidationPolicy>)],
-        rr_touch_limit: u64,
-        rr_scan_limit: u64,
-        combined_cap: usize,
-    ) -> Result<(), ProgramError> {
-        if rr_touch_limit == 0 || rr_scan_limit == 0 || combined.len() >= combined_cap {
-            return Ok(());
-        }
-        let wrap_bound =
-            core::cmp::min(engine.params.max_accounts, percolator::MAX_ACCOUNTS as u64);
-        if wrap_bound == 0 || engine.rr_cursor_position >= wrap_bound {
-            return Err(PercolatorError::EngineCorruptState.into());
-        }
+    /// Fill settlement shared by TradeNoCpi and TradeCpi. Runs once both
+    /// counterparties are settled and fee-current and the final size is
+    /// known: executes the fill, checks the wrapper guards, feeds the mark
+    /// EWMA, routes the fee and writes the config and per-account tail
+    /// bookkeeping. The handlers differ only in how they obtain the fill.
+    #[allow(clippy::too_many_arguments)]
+    fn settle_fill(
+        data: &mut [u8],
+        config: &mut MarketConfig,
+        guards: &TradeGuards,
+        fill: &Fill,
+        now_slot: u64,
+        price: u64,
+        funding_rate_e9: i128,
+    ) -> Result<crate::ix::TradeResult, ProgramError> {
+        let Fill {
+            lp_idx,
+            user_idx,
+            size,
+            exec_price,
+            client_order_id,
+            ..
+        } = *fill;
+        advance_funding_index(data, now_slot, funding_rate_e9)?;
+        let engine = zc::engine_mut(data)?;
+        let user_equity_before = booked_equity(engine, user_idx);
+        let lp_equity_before = booked_equity(engine, lp_idx);
+        let FillExecution {
+            result: trade_result,
+            fee,
+            user_pos_before,
+            lp_pos_before,
+            treasury_share,
+            lp_fee_share,
+        } = execute_fill(
+            engine,
+            config,
+            guards,
+            fill,
+            now_slot,
+            price,
+            funding_rate_e9,
+        )?;
 
-        let scan_cap = core::cmp::min(rr_scan_limit, wrap_bound);
-        let mut i = engine.rr_cursor_position;
-        let mut inspected = 0u64;
-        let mut touched = 0u64;
-        while inspected < scan_cap && touched < rr_touch_limit && combined.len() < combined_cap {
-            let idx = i as usize;
-            if idx_used_in_market(engine, idx) {
-                touched = touched
-                    .checked_add(1)
-                    .ok_or(PercolatorError::EngineOverflow)?;
-                let idx_u16: u16 = idx
-                    .try_into()
-                    .map_err(|_| PercolatorError::EngineOverflow)?;
-                if !candidate_list_contains(caller_candidates, idx_u16)
-                    && !candidate_list_contains(combined, idx_u16)
-                    && effective_pos_q_checked(engine, idx)? != 0
-                {
-                    combined.push((idx_u16, Some(percolator::LiquidationPolicy::FullClose)));
+        // Update mark EWMA from the execution price when this market mode
+        // accepts trade flow as a mark observation. Hybrid regular-hours
+        // reads are excluded: the external oracle owns the mark baseline
+        // until stale fallback is active. The mark impact is clamped
+        // independently from execution price.
+        // Per-slot price-move cap is init-immutable (engine RiskParams).
+        let max_change_bps = engine.params.max_price_move_bps_per_slot;
+        if max_change_bps > 0 && trade_flow_mark_observation_enabled(config, now_slot) {
+            let clamped_price = oracle::clamp_oracle_price(
+                crate::policy::mark_ewma_clamp_base(config.last_effective_price_e6),
+                exec_price,
+                max_change_bps,
+            );
+            // fee_paid = actual fee collected into insurance (post - pre).
+            let fee_paid = if config.mark_min_fee > 0 {
+                core::cmp::min(fee, u64::MAX as u128) as u64
+            } else {
+                0u64
+            };
+            let old_ewma = config.mark_ewma_e6;
+            // N4 fix: seed EWMA at oracle price on first trade (not exec price).
+            // Prevents attacker from imprinting a biased mark on the first fill.
+            let ewma_price = if old_ewma == 0 && config.last_effective_price_e6 > 0 {
+                config.last_effective_price_e6
+            } else {
+                clamped_price
+            };
+            config.mark_ewma_e6 = crate::policy::ewma_update(
+                old_ewma,
+                ewma_price,
+                config.mark_ewma_halflife_slots,
+                config.mark_ewma_last_slot,
+                now_slot,
+                fee_paid,
+                config.mark_min_fee,
+            );
+            let trade_mark_moved = config.mark_ewma_e6 != old_ewma;
+            // Only full-weight observations that actually move the EWMA
+            // advance its clock. Sub-threshold fills and same-price wash
+            // trades can still nudge the value via partial alpha, but a
+            // clock bump would let a controlled counterparty pin future
+            // alpha, or cheapen the next dynamic-fee trade by shrinking
+            // its EWMA elapsed time, for only the base fee.
+            let full_weight_observation =
+                config.mark_min_fee == 0 || fee_paid >= config.mark_min_fee;
+            if full_weight_observation && trade_mark_moved {
+                config.mark_ewma_last_slot = now_slot;
+            }
+            // Hyperp liveness (last_mark_push_slot) follows the same rule:
+            // sub-threshold dust-wash trades must not keep a dead market
+            // artificially alive.
+            if oracle::is_hyperp_mode(config) {
+                config.hyperp_mark_e6 = oracle::clamp_oracle_price(
+                    config.last_effective_price_e6,
+                    exec_price,
+                    max_change_bps,
+                );
+                if full_weight_observation && trade_mark_moved {
+                    config.last_mark_push_slot = now_slot as u128;
                 }
             }
+            // NOTE: do NOT stamp funding rate here — execute_trade_not_atomic
+            // handles it via the funding_rate parameter (§5.5 anti-retroactivity).
+        }
+
+        let user_eff = effective_pos_q_checked(engine, user_idx as usize)?;
+        let lp_eff = effective_pos_q_checked(engine, lp_idx as usize)?;
+
+        if !state::is_oracle_initialized(data) {
+            state::set_oracle_initialized(data);
+        }
+        state::write_config(data, config);
+        record_order_id(data, user_idx, client_order_id);
+        state::write_last_trade_slot(data, user_idx, now_slot);
+        accrue_protocol_fees(data, treasury_share)?;
+        let fill_notional = risk_notional_ceil(size, exec_price);
+        record_epoch_activity(data, user_idx, user_equity_before, 0, fill_notional);
+        record_epoch_activity(data, lp_idx, lp_equity_before, 0, fill_notional);
+        // Both sides pay the per-side fee; the user's half takes the odd unit.
+        record_pnl_fill(
+            data,
+            user_idx,
+            user_pos_before,
+            user_eff,
+            size,
+            exec_price,
+            price,
+            fee - fee / 2,
+        );
+        record_pnl_fill(
+            data,
+            lp_idx,
+            lp_pos_before,
+            lp_eff,
+            -size,
+            exec_price,
+            price,
+            fee / 2,
+        );
+        record_lp_fill(
+            data,
+            lp_idx,
+            fill_notional,
+            lp_fee_share,
+            crate::policy::lp_spread_pnl(size, exec_price, price),
+            now_slot,
+            price,
+        )?;
 
-            i = i.checked_add(1).ok_or(PercolatorError::EngineOverflow)?;
-            inspected = inspected
-                .checked_add(1)
-                .ok_or(PercolatorError::EngineOverflow)?;
-            if i == wrap_bound {
-                break;
+        // Risk buffer ranks by oracle notional (H1/M9): exec_price is
+        // gameable by a colluding matcher, the oracle price is not.
+        let mut buf = state::read_risk_buffer(data);
+        for &(idx, eff) in &[(user_idx, user_eff), (lp_idx, lp_eff)] {
+            if eff == 0 {
+                buf.remove(idx);
+            } else {
+                buf.upsert(idx, risk_notional_ceil(eff, price));
             }
         }
-
-        Ok(())
+        state::write_risk_buffer(data, &buf);
+        state::set_position_bit(data, user_idx, user_eff != 0);
+        state::set_position_bit(data, lp_idx, lp_eff != 0);
+        refresh_liq_watch(data, &[user_idx, lp_idx], price)?;
+        Ok(trade_result)
     }
 
-    fn sync_keeper_candidate_fees_after_crank(
+    /// TradeNoCpi steps between authorization and `settle_fill`, shared
+    /// with the preview: accrue the market, settle both counterparties and
+    /// apply the reduce-only clamp. Returns the size to fill.
+    #[allow(clippy::too_many_arguments)]
+    fn prepare_bilateral_fill(
         engine: &mut RiskEngine,
-        combined: &[(u16, Option<percolator::LiquidationPolicy>)],
+        config: &MarketConfig,
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        flags: u8,
         now_slot: u64,
-        maintenance_fee_per_slot: u128,
-    ) -> Result<usize, ProgramError> {
-        if maintenance_fee_per_slot == 0 {
-            return Ok(0);
-        }
-        let cap = core::cmp::min(
-            crate::constants::LIQ_BUDGET_PER_CRANK as usize,
-            crate::constants::FEE_SWEEP_BUDGET,
-        );
-        let mut synced: [u16; crate::constants::LIQ_BUDGET_PER_CRANK as usize] =
-            [u16::MAX; crate::constants::LIQ_BUDGET_PER_CRANK as usize];
-        let mut synced_count = 0usize;
-        let mut attempts = 0usize;
-        let mut candidate_syncs = 0usize;
-        for &(idx, _policy) in combined.iter() {
-            if attempts >= cap || candidate_syncs >= crate::constants::FEE_SWEEP_BUDGET {
-                break;
-            }
-            let i = idx as usize;
-            if !idx_used_in_market(engine, i) {
-                continue;
-            }
-            attempts += 1;
-            let mut already = false;
-            for j in 0..synced_count {
-                if synced[j] == idx {
-                    already = true;
-                    break;
-                }
-            }
-            if already {
-                continue;
-            }
-            // Candidate fees run only after the engine's authoritative
-            // candidate pass, and only when the account is now flat/current.
-            // That keeps recurring fees junior to lazy mark/funding losses
-            // realized by the touch.
-            if engine.accounts[i].last_fee_slot >= now_slot {
-                continue;
-            }
-            if !recurring_fee_pre_touch_safe(engine, i)? {
-                continue;
-            }
-            engine
-                .sync_account_fee_to_slot_not_atomic(idx, now_slot, maintenance_fee_per_slot)
-                .map_err(map_risk_error)?;
-            synced[synced_count] = idx;
-            synced_count += 1;
-            candidate_syncs += 1;
+        price: u64,
+        funding_rate_e9: i128,
+    ) -> Result<i128, ProgramError> {
+        ensure_market_accrued_to_now(engine, now_slot, price, funding_rate_e9)?;
+
+        // Make both counterparties authoritative, then sync recurring
+        // fees before the trade's fee-current margin checks. This
+        // preserves loss-senior ordering while still charging nonflat
+        // current accounts.
+        settle_pair_then_sync_fee_current(
+            engine,
+            config,
+            user_idx,
+            lp_idx,
+            now_slot,
+            price,
+            funding_rate_e9,
+            engine.params.h_min,
+            engine.params.h_max,
+            Some(engine.params.maintenance_margin_bps as u128),
+        )?;
+
+        // Reduce-only: clamp against the authoritative post-settle
+        // position so the fill can never grow or flip the user.
+        if flags & crate::constants::TRADE_FLAG_REDUCE_ONLY != 0 {
+            let user_pos = effective_pos_q_checked(engine, user_idx as usize)?;
+            crate::policy::reduce_only_clamp(user_pos, size)
+                .ok_or_else(|| PercolatorError::ReduceOnlyViolation.into())
+        } else {
+            Ok(size)
         }
-        Ok(candidate_syncs)
     }
 
-    /// Incrementally sweep maintenance fees from the current cursor position.
-    /// Scans bitmap words starting at `(fee_sweep_cursor_word,
-    /// fee_sweep_cursor_bit)`, calling `sync_account_fee_to_slot_not_atomic`
-    /// on every set bit. Stops EXACTLY at `FEE_SWEEP_BUDGET` syncs — the bit
-    /// cursor lets us pause mid-word without losing remaining set bits to
-    /// budget truncation.
-    ///
-    /// Correctness: the engine's per-account `last_fee_slot` is the source of
-    /// truth. When the cursor reaches an account, that account's sync call
-    /// realizes fees for the *entire* elapsed interval
-    /// `[account.last_fee_slot, now_slot]` in one charge — no fees are lost
-    /// between cursor visits. Self-acting accounts realize their own fees inline
-    /// on every capital-sensitive instruction after the engine local touch; the
-    /// sweep handles flat/current accounts that have not self-acted.
-    ///
-    /// CU bound: at most `FEE_SWEEP_BUDGET` sync calls per crank (strictly,
-    /// thanks to the bit cursor), plus O(BITMAP_WORDS) word reads. Constant
-    /// in `max_accounts`, so a 4096-slot market is handled the same as a
-    /// 64-slot market.
-    fn sweep_maintenance_fees(
+    /// Engine-side TradeNoCpi sequence, from just after the oracle read, on
+    /// a scratch engine for `crate::simulate`. Runs the handler's own
+    /// `prepare_bilateral_fill` and `execute_fill`; the mark-EWMA and
+    /// tail writes `settle_fill` adds do not affect the engine and are
+    /// skipped.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn preview_trade_nocpi(
         engine: &mut RiskEngine,
-        config: &mut MarketConfig,
+        config: &MarketConfig,
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        exec_price_e6: u64,
+        flags: u8,
         now_slot: u64,
-        max_syncs: usize,
-    ) -> Result<(), ProgramError> {
-        if config.maintenance_fee_per_slot == 0 {
-            return Ok(());
+        price: u64,
+        guards: &TradeGuards,
+    ) -> Result<crate::simulate::TradePreview, ProgramError> {
+        if size == 0 || size == i128::MIN {
+            return Err(ProgramError::InvalidInstructionData);
         }
-        // Early-out when the caller has already exhausted the per-
-        // instruction sync budget on pre-sweep candidate syncs.
-        if max_syncs == 0 {
-            return Ok(());
+        if size.unsigned_abs() > percolator::MAX_TRADE_SIZE_Q {
+            return Err(ProgramError::InvalidInstructionData);
         }
-        const BITMAP_WORDS: usize = (percolator::MAX_ACCOUNTS + 63) / 64;
-        // Normalize cursor in case of stale/corrupt values.
-        let mut word_cursor = (config.fee_sweep_cursor_word as usize) % BITMAP_WORDS;
-        let mut bit_cursor = (config.fee_sweep_cursor_bit as usize) & 63;
-        let mut syncs_done: usize = 0;
-        let mut words_scanned: usize = 0;
-        // Budget check is inside the inner loop so we can stop exactly at
-        // max_syncs, not after completing the current word.
-        'outer: while words_scanned < BITMAP_WORDS {
-            // Skip bits below bit_cursor on the resume word.
-            let resume_mask = if bit_cursor == 0 {
-                u64::MAX
-            } else {
-                // Clear bits 0..bit_cursor (they were already processed last call).
-                !((1u64 << bit_cursor).wrapping_sub(1))
-            };
-            let mut bits = engine.used[word_cursor] & resume_mask;
-            while bits != 0 {
-                if syncs_done >= max_syncs {
-                    // Stop EXACTLY at budget. Save the next unprocessed bit
-                    // as the resume point for the following crank.
-                    let next_bit = bits.trailing_zeros() as usize;
-                    config.fee_sweep_cursor_word = word_cursor as u64;
-                    config.fee_sweep_cursor_bit = next_bit as u64;
-                    return Ok(());
-                }
-                let bit = bits.trailing_zeros() as usize;
-                bits &= bits - 1;
-                let idx = word_cursor * 64 + bit;
-                if !idx_within_market_capacity(engine, idx) {
-                    continue;
-                }
-                // Keeper fee sweeping is loss-senior: do not charge recurring
-                // fees from exposed, stale-epoch, reserved, or negative-PnL
-                // accounts before their local losses and side effects have
-                // been settled by an engine touch. The bitmap sweep is for
-                // flat/current accounts only.
-                if !recurring_fee_pre_touch_safe(engine, idx)? {
-                    continue;
-                }
-                engine
-                    .sync_account_fee_to_slot_not_atomic(
-                        idx as u16,
-                        now_slot,
-                        config.maintenance_fee_per_slot,
-                    )
-                    .map_err(map_risk_error)?;
-                syncs_done += 1;
+        if engine.market_mode == percolator::MarketMode::Resolved {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let funding_rate_e9 = compute_current_funding_rate_e9(config)?;
+        let exec_price = if exec_price_e6 == 0 {
+            price
+        } else {
+            exec_price_e6
+        };
+        if exec_price == 0 || exec_price > percolator::MAX_ORACLE_PRICE {
+            return Err(PercolatorError::OracleInvalid.into());
+        }
+        check_idx(engine, lp_idx)?;
+        check_idx(engine, user_idx)?;
 
-                // Permissionless dust reclaim: fee accrual just charged
-                // this account; if that drained capital to zero on a
-                // flat account (no position, no PnL, no reserve, no
-                // pending, no positive fee_credits), free the slot now.
-                // Without this, an attacker could fill `max_accounts`
-                // with dust and brick onboarding even when fees drain
-                // capital, because slot reclamation would otherwise
-                // require a targeted crank candidate.
-                //
-                // All six flat-clean predicates the engine's reclaim
-                // checks are mirrored here so the call CANNOT hit an
-                // `Undercollateralized` / `CorruptState` early return.
-                // That lets us propagate any remaining error with `?`
-                // rather than silently swallowing a `_not_atomic`
-                // failure — per the engine contract, a failing
-                // `_not_atomic` may have already mutated state and the
-                // caller must abort the transaction. Envelope /
-                // market-mode guards upstream (KeeperCrank's oracle
-                // read + is_resolved gate + accrue_market_to) ensure
-                // the remaining engine preconditions hold, so in
-                // practice the `?` is unreachable — but if a future
-                // engine change introduces a new precondition, we get
-                // a transaction rollback instead of silent corruption.
-                reclaim_flat_zero_account_if_eligible(engine, idx as u16, now_slot)?;
-            }
-            // Word fully drained — advance to next word, reset bit cursor.
-            word_cursor = (word_cursor + 1) % BITMAP_WORDS;
-            bit_cursor = 0;
-            words_scanned += 1;
-            // Budget may have hit right at the end of the word — avoid one
-            // wasted iteration on the next (empty in the caller's view) word.
-            if syncs_done >= max_syncs {
-                break 'outer;
-            }
+        let size = prepare_bilateral_fill(
+            engine,
+            config,
+            lp_idx,
+            user_idx,
+            size,
+            flags,
+            now_slot,
+            price,
+            funding_rate_e9,
+        )?;
+        let result = execute_fill(
+            engine,
+            config,
+            guards,
+            &Fill {
+                lp_idx,
+                user_idx,
+                size,
+                exec_price,
+                lp_account_id: 0,
+                client_order_id: 0,
+            },
+            now_slot,
+            price,
+            funding_rate_e9,
+        )?
+        .result;
+
+        Ok(crate::simulate::TradePreview {
+            size,
+            exec_price,
+            oracle_price: price,
+            fee_bps: result.fee_bps,
+            fee_paid: result.fee_paid,
+            user: account_health(engine, user_idx, price)?,
+            lp: account_health(engine, lp_idx, price)?,
+        })
+    }
+
+    /// PnL `idx` has accrued lazily and books on its next touch: the side's
+    /// K/F movement since the account's snapshots (price moves and funding
+    /// up to the engine's last accrual), plus the mark move from
+    /// `last_oracle_price` to `price`. An account whose side was reset
+    /// since its snapshot is flat and carries no pending mark PnL.
+    fn pending_pnl(engine: &RiskEngine, idx: u16, eff: i128, price: u64) -> i128 {
+        let acc = &engine.accounts[idx as usize];
+        let basis = acc.position_basis_q;
+        let (epoch, k, f) = if basis > 0 {
+            (
+                engine.adl_epoch_long,
+                engine.adl_coeff_long,
+                engine.f_long_num,
+            )
+        } else {
+            (
+                engine.adl_epoch_short,
+                engine.adl_coeff_short,
+                engine.f_short_num,
+            )
+        };
+        if basis == 0 || acc.adl_epoch_snap != epoch {
+            return 0;
         }
-        config.fee_sweep_cursor_word = word_cursor as u64;
-        config.fee_sweep_cursor_bit = 0;
-        Ok(())
+        let kf = crate::policy::pending_kf_pnl(
+            basis.unsigned_abs(),
+            acc.adl_a_basis,
+            k.saturating_sub(acc.adl_k_snap),
+            f.saturating_sub(acc.f_snap),
+        );
+        let mark = if engine.last_oracle_price != 0 {
+            crate::policy::pnl_between(eff, engine.last_oracle_price, price)
+        } else {
+            0
+        };
+        kf.saturating_add(mark)
     }
 
-    #[inline]
-    fn reclaim_flat_zero_account_if_eligible(
-        engine: &mut RiskEngine,
+    /// Margin figures for one account at `price`, without settlement or
+    /// fee sync. `pnl` is the stored PnL plus what the next touch would
+    /// book (`pending_pnl`), so equity matches a settled account. Shared
+    /// by the trade preview, QueryHealth and the liquidation watch.
+    pub(crate) fn account_health(
+        engine: &RiskEngine,
         idx: u16,
-        now_slot: u64,
-    ) -> Result<(), ProgramError> {
-        if !idx_used_in_market(engine, idx as usize) {
-            return Ok(());
-        }
+        price: u64,
+    ) -> Result<crate::simulate::AccountHealth, ProgramError> {
+        let eff = effective_pos_q_checked(engine, idx as usize)?;
         let acc = &engine.accounts[idx as usize];
-        let fee_credits = acc.fee_credits.get();
-        if acc.capital.is_zero()
-            && acc.position_basis_q == 0
-            && acc.pnl == 0
-            && acc.reserved_pnl == 0
-            && acc.sched_present == 0
-            && acc.pending_present == 0
-            && fee_credits <= 0
-        {
-            if fee_credits == i128::MIN {
-                return Err(PercolatorError::EngineCorruptState.into());
+        let pnl = acc.pnl.saturating_add(pending_pnl(engine, idx, eff, price));
+        let notional = risk_notional_ceil(eff, price);
+        let req = |bps: u64, floor: u128| {
+            if eff == 0 {
+                0
+            } else {
+                core::cmp::max(notional.saturating_mul(bps as u128) / 10_000, floor)
             }
-            engine
-                .reclaim_empty_account_not_atomic(idx, now_slot)
-                .map_err(map_risk_error)?;
+        };
+        Ok(crate::simulate::AccountHealth {
+            position_q: eff,
+            capital: acc.capital.get(),
+            pnl,
+            notional,
+            maintenance_req: req(
+                engine.params.maintenance_margin_bps,
+                engine.params.min_nonzero_mm_req,
+            ),
+            initial_req: req(
+                engine.params.initial_margin_bps,
+                engine.params.min_nonzero_im_req,
+            ),
+        })
+    }
+
+    /// QueryHealth's figures for `idx`: valued at the settlement price once
+    /// resolved, at the engine's last oracle price otherwise.
+    pub(crate) fn health_snapshot(
+        engine: &RiskEngine,
+        idx: u16,
+    ) -> Result<crate::ix::HealthSnapshot, ProgramError> {
+        check_idx(engine, idx)?;
+        let price = if engine_is_resolved(engine) {
+            engine_resolved_context(engine).0
+        } else {
+            engine.last_oracle_price
+        };
+        let h = account_health(engine, idx, price)?;
+        Ok(crate::ix::HealthSnapshot {
+            equity: h.equity(),
+            maintenance_req: h.maintenance_req,
+            initial_req: h.initial_req,
+            free_collateral: crate::ix::free_collateral(h.equity(), h.initial_req),
+            position_q: h.position_q,
+            price_e6: price,
+            last_market_slot: engine.last_market_slot,
+        })
+    }
+
+    /// Whether `idx` belongs in the liquidation watch set anchored at
+    /// `anchor_price`, judged from its figures at `price` (the QueryHealth
+    /// view). Equity includes the K/F PnL and funding the account has not
+    /// settled yet, so an account the crank's round-robin has not reached
+    /// is judged as if it had been. Free and flat accounts do not.
+    fn liq_watch_needed(
+        engine: &RiskEngine,
+        idx: u16,
+        price: u64,
+        anchor_price: u64,
+    ) -> Result<bool, ProgramError> {
+        if !idx_used_in_market(engine, idx as usize) {
+            return Ok(false);
         }
-        Ok(())
+        let h = account_health(engine, idx, price)?;
+        if h.position_q == 0 {
+            return Ok(false);
+        }
+        let distance = crate::policy::liq_distance_e6(
+            h.equity(),
+            h.maintenance_req,
+            h.position_q.unsigned_abs(),
+            engine.params.maintenance_margin_bps,
+        );
+        Ok(!crate::policy::liq_watch_clear_ok(
+            distance,
+            price,
+            anchor_price,
+            crate::constants::LIQ_WATCH_BAND_BPS,
+        ))
     }
 
-    fn compute_current_funding_rate_e9(config: &MarketConfig) -> Result<i128, ProgramError> {
-        crate::policy::funding_rate_e9_from_mark_index(
-            config.mark_ewma_e6,
-            config.last_effective_price_e6,
-            config.funding_horizon_slots,
-            config.funding_k_bps,
-            config.funding_max_premium_bps,
-            config.funding_max_e9_per_slot,
-        )
-        .ok_or(PercolatorError::InvalidConfigParam.into())
+    /// Re-check `idxs` against the watch band after their position or
+    /// equity changed.
+    fn refresh_liq_watch(data: &mut [u8], idxs: &[u16], price: u64) -> Result<(), ProgramError> {
+        let anchor = state::read_liq_watch(data).anchor_price_e6;
+        for &idx in idxs {
+            let watched = liq_watch_needed(zc::engine_ref(data)?, idx, price, anchor)?;
+            state::set_watched(data, idx, watched);
+        }
+        Ok(())
     }
 
-    fn execute_trade_with_matcher<M: MatchingEngine>(
-        engine: &mut RiskEngine,
-        matcher: &M,
-        lp_idx: u16,
-        user_idx: u16,
+    /// Crank upkeep of the liquidation watch index. When the price,
+    /// widened by worst-case funding since the anchor, has left the band
+    /// or the anchor is older than `LIQ_WATCH_MAX_AGE_SLOTS`, re-anchor at
+    /// `price` and watch every account the open-position bitmap names, an
+    /// O(words) copy. Then re-check up to `LIQ_WATCH_SCAN_WINDOW` watched
+    /// accounts from the cursor, dropping the ones the band cannot reach.
+    fn crank_liq_watch(
+        data: &mut [u8],
+        config: &MarketConfig,
         now_slot: u64,
-        oracle_price: u64,
-        size: i128,
-        funding_rate_e9: i128,
-        lp_account_id: u64,
-        trade_fee_bps: u64,
-        _maintenance_fee_per_slot: u128,
-    ) -> Result<(), RiskError> {
-        let lp = &engine.accounts[lp_idx as usize];
-        let exec = matcher.execute_match(
-            &lp.matcher_program,
-            &lp.matcher_context,
-            lp_account_id,
-            oracle_price,
-            size,
-        )?;
-        // POS_SCALE = 1_000_000 in spec v11.5, same as instruction units.
-        // No conversion needed.
-        let size_q: i128 = exec.size;
-        // Spec v12: size_q must be > 0. Account `a` buys from `b`.
-        // Positive size = user buys from LP (user goes long).
-        // Negative size = LP buys from user (user goes short) — swap order.
-        let (a, b, abs_size) = if size_q > 0 {
-            (user_idx, lp_idx, size_q)
-        } else if size_q < 0 {
-            // checked_neg rejects i128::MIN (which has no positive counterpart)
-            let pos = size_q.checked_neg().ok_or(RiskError::Overflow)?;
-            (lp_idx, user_idx, pos)
-        } else {
-            return Err(RiskError::Overflow);
+        price: u64,
+    ) -> Result<(), ProgramError> {
+        use crate::constants::{
+            LIQ_WATCH_BAND_BPS, LIQ_WATCH_MAX_AGE_SLOTS, LIQ_WATCH_OFF, LIQ_WATCH_SCAN_WINDOW,
+            POSITION_BITMAP_LEN, POSITION_BITMAP_OFF, RISK_BUF_OFF,
         };
-        let admit_h_min = engine.params.h_min;
-        let admit_h_max = engine.params.h_max;
-        let admit_threshold = Some(engine.params.maintenance_margin_bps as u128);
-        engine.execute_trade_not_atomic(
-            a,
-            b,
-            oracle_price,
-            now_slot,
-            abs_size,
-            exec.price,
-            funding_rate_e9,
-            trade_fee_bps,
-            admit_h_min,
-            admit_h_max,
-            admit_threshold,
-        )
+        let mut w = state::read_liq_watch(data);
+        let age = now_slot.saturating_sub(w.anchor_slot);
+        let drift = crate::policy::funding_bps_bound(config.funding_max_e9_per_slot, age)
+            .unwrap_or(u128::MAX);
+        if age > LIQ_WATCH_MAX_AGE_SLOTS
+            || !crate::policy::liq_watch_covers(w.anchor_price_e6, price, drift, LIQ_WATCH_BAND_BPS)
+        {
+            data.copy_within(
+                POSITION_BITMAP_OFF..POSITION_BITMAP_OFF + POSITION_BITMAP_LEN,
+                LIQ_WATCH_OFF + 24,
+            );
+            w = state::LiqWatch {
+                anchor_price_e6: price,
+                anchor_slot: now_slot,
+                cursor: 0,
+            };
+        }
+
+        let zc::SlabRegions { engine, tail, .. } = zc::split_mut(data)?;
+        let bits_off = LIQ_WATCH_OFF + 24 - RISK_BUF_OFF;
+        let watch = &mut tail[bits_off..bits_off + POSITION_BITMAP_LEN];
+        let words = percolator::MAX_ACCOUNTS / 64;
+        let start = w.cursor as usize % percolator::MAX_ACCOUNTS;
+        let mut checked = 0usize;
+        let mut next = start;
+        // One extra word revisits the start word's low bits after wrapping.
+        'scan: for step in 0..=words {
+            let word = (start / 64 + step) % words;
+            let mut bits = engine.used[word] & state::position_word(watch, word);
+            if step == 0 {
+                bits &= u64::MAX << (start % 64);
+            } else if step == words {
+                bits &= (1u64 << (start % 64)).wrapping_sub(1);
+            }
+            while bits != 0 {
+                let idx = word * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                if !liq_watch_needed(engine, idx as u16, price, w.anchor_price_e6)? {
+                    watch[idx / 8] &= !(1 << (idx % 8));
+                }
+                next = (idx + 1) % percolator::MAX_ACCOUNTS;
+                checked += 1;
+                if checked >= LIQ_WATCH_SCAN_WINDOW {
+                    break 'scan;
+                }
+            }
+        }
+        w.cursor = next as u64;
+        state::write_liq_watch(data, &w);
+        Ok(())
     }
 
     use solana_program::instruction::{AccountMeta, Instruction as SolInstruction};
@@ -5502,11 +11569,24 @@ pub mod processor {
                 return Err(ProgramError::IllegalOwner);
             }
             solana_program::log::sol_log_64(SLAB_LEN as u64, data.len() as u64, 0, 0, 0);
+            if slab_needs_migration(data) {
+                return Err(PercolatorError::SlabMigrationRequired.into());
+            }
             return Err(PercolatorError::InvalidSlabLen.into());
         }
         Ok(())
     }
 
+    /// An initialized slab of an older layout version, at its own length
+    /// or partway through MigrateSlab.
+    fn slab_needs_migration(data: &[u8]) -> bool {
+        if data.len() < crate::constants::SLAB_LEN_BY_VERSION[0] || data.len() >= SLAB_LEN {
+            return false;
+        }
+        let header = state::read_header(data);
+        header.magic == MAGIC && header.version < crate::constants::SLAB_VERSION
+    }
+
     fn slab_guard(
         program_id: &Pubkey,
         slab: &AccountInfo,
@@ -5517,7 +11597,41 @@ pub mod processor {
         // A malicious matcher can re-enter any permissionless instruction during
         // TradeCpi's matcher CPI, manipulating engine state mid-instruction.
         if state::is_cpi_in_progress(data) {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(fail(PercolatorError::CpiReentrancy, &[]));
+        }
+        Ok(())
+    }
+
+    /// Init-time slab check on top of `slab_shape_guard` (owner:
+    /// `IllegalOwner`, size: `InvalidSlabLen`): the account must be rent
+    /// exempt, so it cannot be garbage-collected under a live market.
+    /// Host-side unit tests run on mock accounts without a rent sysvar.
+    #[allow(unused_variables)]
+    fn require_slab_rent_exempt(slab: &AccountInfo, len: usize) -> Result<(), ProgramError> {
+        #[cfg(target_os = "solana")]
+        {
+            let minimum = solana_program::sysvar::rent::Rent::get()?.minimum_balance(len);
+            if slab.lamports() < minimum {
+                return Err(fail(
+                    PercolatorError::SlabNotRentExempt,
+                    &[slab.lamports(), minimum],
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reject any nonzero byte in `data[from..]`, logging the first
+    /// offset. Compares 1 KiB blocks through the memcmp syscall, so a full
+    /// slab costs a few thousand CU instead of a per-byte loop.
+    fn require_slab_zeroed(data: &[u8], from: usize) -> Result<(), ProgramError> {
+        static ZERO: [u8; 1024] = [0u8; 1024];
+        for (i, block) in data[from..].chunks(ZERO.len()).enumerate() {
+            let n = block.len();
+            if solana_program::program_memory::sol_memcmp(block, &ZERO[..n], n) != 0 {
+                let off = from + i * ZERO.len() + block.iter().position(|&b| b != 0).unwrap_or(0);
+                return Err(fail(PercolatorError::SlabNotZeroed, &[off as u64]));
+            }
         }
         Ok(())
     }
@@ -5532,6 +11646,8 @@ pub mod processor {
 
     /// Require that the signer is the current admin.
     /// If admin is burned (all zeros), admin operations are permanently disabled.
+    /// Only `is_signer` and the key are checked, never that the key is on
+    /// the curve, so a PDA signing through `invoke_signed` passes.
     /// Admin authorization via policy helper
     fn require_admin(header_admin: [u8; 32], signer: &Pubkey) -> Result<(), ProgramError> {
         if !crate::policy::admin_ok(header_admin, signer.to_bytes()) {
@@ -5540,6 +11656,95 @@ pub mod processor {
         Ok(())
     }
 
+    /// Gate for `header.admin` instructions. Without an admin key set this
+    /// is `require_admin`. With one, the signer must be a member, and its
+    /// own approval plus those recorded by ApproveAdminAction for
+    /// `admin_action_hash(slab, account keys, instruction_data)` must reach
+    /// the threshold; the pending entry is then consumed. `accounts` is the
+    /// instruction's full, ordered account list. A burned admin disables
+    /// both paths.
+    fn require_admin_action(
+        data: &mut [u8],
+        slab: &Pubkey,
+        signer: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> Result<(), ProgramError> {
+        let header_admin = state::read_header(data).admin;
+        let ms = state::read_admin_multisig(data);
+        if !ms.active() {
+            return require_admin(header_admin, signer);
+        }
+        if header_admin == [0u8; 32] {
+            return Err(PercolatorError::EngineUnauthorized.into());
+        }
+        let own = ms
+            .signer_index(&signer.to_bytes())
+            .map(|i| 1u8 << i)
+            .ok_or(PercolatorError::EngineUnauthorized)?;
+        if crate::policy::admin_threshold_met(own, ms.threshold) {
+            return Ok(());
+        }
+        let keys: alloc::vec::Vec<[u8; 32]> = accounts.iter().map(|a| a.key.to_bytes()).collect();
+        let hash = crate::policy::admin_action_hash(&slab.to_bytes(), &keys, instruction_data);
+        let now = Clock::get()
+            .map_err(|_| ProgramError::UnsupportedSysvar)?
+            .slot;
+        let mut approvals = own;
+        for i in 0..crate::constants::MAX_PENDING_ADMIN_ACTIONS {
+            let pending = state::read_pending_admin_action(data, i);
+            if pending.action_hash == hash
+                && now.saturating_sub(pending.created_slot)
+                    <= crate::constants::ADMIN_ACTION_TTL_SLOTS
+            {
+                approvals |= pending.approvals;
+                if crate::policy::admin_threshold_met(approvals, ms.threshold) {
+                    state::write_pending_admin_action(
+                        data,
+                        i,
+                        &state::PendingAdminAction::default(),
+                    );
+                    return Ok(());
+                }
+                break;
+            }
+        }
+        // ctx: approvals incl. signer, threshold
+        Err(fail(
+            PercolatorError::AdminApprovalsPending,
+            &[approvals.count_ones() as u64, ms.threshold as u64],
+        ))
+    }
+
+    /// Relayed PushHyperpMark and DepositWithPermit: the instruction
+    /// immediately before this one must be an ed25519 precompile
+    /// verification of `expected`. The precompile has already checked the
+    /// signature when we run; this only binds its message to ours and
+    /// returns the key that signed it.
+    fn verify_preceding_ed25519(
+        ix_sysvar: &AccountInfo,
+        expected: &[u8],
+    ) -> Result<[u8; 32], ProgramError> {
+        use solana_program::sysvar::instructions::{
+            load_current_index_checked, load_instruction_at_checked,
+        };
+        accounts::expect_key(ix_sysvar, &solana_program::sysvar::instructions::ID)?;
+        let current = load_current_index_checked(ix_sysvar)?;
+        if current == 0 {
+            return Err(PercolatorError::EngineUnauthorized.into());
+        }
+        let ix = load_instruction_at_checked(current as usize - 1, ix_sysvar)?;
+        if ix.program_id != solana_program::ed25519_program::ID {
+            return Err(PercolatorError::EngineUnauthorized.into());
+        }
+        let (signer, msg) = crate::policy::ed25519_single_sig_payload(&ix.data)
+            .ok_or(PercolatorError::EngineUnauthorized)?;
+        if msg != expected {
+            return Err(PercolatorError::EngineUnauthorized.into());
+        }
+        Ok(signer)
+    }
+
     #[inline]
     fn idx_within_market_capacity(engine: &RiskEngine, idx: usize) -> bool {
         crate::policy::market_idx_within_capacity(idx, engine.params.max_accounts)
@@ -5770,7 +11975,7 @@ pub mod processor {
     fn write_init_header(data: &mut [u8], admin_key: &Pubkey, bump: u8) {
         let header = SlabHeader {
             magic: MAGIC,
-            version: 0,
+            version: crate::constants::SLAB_VERSION,
             bump,
             _padding: [0; 3],
             admin: admin_key.to_bytes(),
@@ -5781,6 +11986,45 @@ pub mod processor {
         state::write_header(data, &header);
     }
 
+    /// Seed the regions slab version 1 added, once MigrateSlab has grown
+    /// a version-0 slab to `SLAB_LEN`, then stamp the version. The new
+    /// bytes are zero, which is each region's "off" state; only what zero
+    /// would misstate is written. The fee authority defaults to the admin,
+    /// as at InitMarket. The crank guard stays disabled, since version 0
+    /// promised no withdraw-only fallback. Every used slot goes into the
+    /// open-position bitmap, a superset of the holders that the crank's
+    /// risk scan trims. The liquidation watch, anchored at zero,
+    /// re-anchors on the next crank, and PnL ledgers pick up open
+    /// positions at the price of their next fill.
+    fn seed_v1_tail(data: &mut [u8]) -> Result<(), ProgramError> {
+        use crate::constants::{POSITION_BITMAP_LEN, POSITION_BITMAP_OFF};
+        let mut header = state::read_header(data);
+        state::write_fee_authority(data, &header.admin);
+        let zc::SlabRegions { engine, tail, .. } = zc::split_mut(data)?;
+        let last_crank_slot = engine.current_slot;
+        let bits_off = POSITION_BITMAP_OFF - crate::constants::RISK_BUF_OFF;
+        for (bytes, word) in tail[bits_off..bits_off + POSITION_BITMAP_LEN]
+            .chunks_exact_mut(8)
+            .zip(engine.used.iter())
+        {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        state::write_crank_guard(
+            data,
+            &state::CrankGuard {
+                max_crank_staleness_slots: 0,
+                last_crank_slot,
+                withdraw_only: false,
+            },
+        );
+        header.version = crate::constants::SLAB_VERSION;
+        state::write_header(data, &header);
+        Ok(())
+    }
+
+    /// Vault checks. `InvalidVaultAta` logs a reason code: 1 key, 2 token
+    /// program owner, 3 data length, 4 token-account owner (vault PDA),
+    /// 5 not initialized, 6 delegate or close authority set.
     fn verify_vault(
         a_vault: &AccountInfo,
         expected_owner: &Pubkey,
@@ -5788,13 +12032,13 @@ pub mod processor {
         expected_pubkey: &Pubkey,
     ) -> Result<(), ProgramError> {
         if a_vault.key != expected_pubkey {
-            return Err(PercolatorError::InvalidVaultAta.into());
+            return Err(fail(PercolatorError::InvalidVaultAta, &[1]));
         }
         if a_vault.owner != &spl_token::ID {
-            return Err(PercolatorError::InvalidVaultAta.into());
+            return Err(fail(PercolatorError::InvalidVaultAta, &[2]));
         }
         if a_vault.data_len() != spl_token::state::Account::LEN {
-            return Err(PercolatorError::InvalidVaultAta.into());
+            return Err(fail(PercolatorError::InvalidVaultAta, &[3]));
         }
 
         let data = a_vault.try_borrow_data()?;
@@ -5803,66 +12047,271 @@ pub mod processor {
             return Err(PercolatorError::InvalidMint.into());
         }
         if tok.owner != *expected_owner {
-            return Err(PercolatorError::InvalidVaultAta.into());
+            return Err(fail(PercolatorError::InvalidVaultAta, &[4]));
         }
         // SECURITY (H3): Verify vault token account is initialized
         // Uninitialized vault could brick deposits/withdrawals
         if tok.state != spl_token::state::AccountState::Initialized {
-            return Err(PercolatorError::InvalidVaultAta.into());
+            return Err(fail(PercolatorError::InvalidVaultAta, &[5]));
         }
         // Reject vault with pre-set delegate or close_authority — these allow
         // a third party to drain or close the vault outside program control.
         if tok.delegate.is_some() || tok.close_authority.is_some() {
-            return Err(PercolatorError::InvalidVaultAta.into());
+            return Err(fail(PercolatorError::InvalidVaultAta, &[6]));
+        }
+        Ok(())
+    }
+
+    /// verify_vault + require zero balance (for InitMarket).
+    /// Reuses the unpack from verify_vault logic (single unpack).
+    fn verify_vault_empty(
+        a_vault: &AccountInfo,
+        expected_owner: &Pubkey,
+        expected_mint: &Pubkey,
+        expected_pubkey: &Pubkey,
+    ) -> Result<(), ProgramError> {
+        if a_vault.key != expected_pubkey {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[1]));
+        }
+        if a_vault.owner != &spl_token::ID {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[2]));
+        }
+        if a_vault.data_len() != spl_token::state::Account::LEN {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[3]));
+        }
+        let data = a_vault.try_borrow_data()?;
+        let tok = spl_token::state::Account::unpack(&data)?;
+        if tok.mint != *expected_mint {
+            return Err(PercolatorError::InvalidMint.into());
+        }
+        if tok.owner != *expected_owner {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[4]));
+        }
+        if tok.state != spl_token::state::AccountState::Initialized {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[5]));
+        }
+        if tok.delegate.is_some() || tok.close_authority.is_some() {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[6]));
+        }
+        if tok.amount != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// CreateIdempotent the vault ATA for InitMarket, with the admin paying
+    /// rent. An ATA someone created beforehand is left as is and still has
+    /// to pass `verify_vault_empty`.
+    fn create_vault_ata<'a>(
+        a_payer: &AccountInfo<'a>,
+        a_vault: &AccountInfo<'a>,
+        a_mint: &AccountInfo<'a>,
+        extra: &[AccountInfo<'a>],
+        vault_authority: &Pubkey,
+        expected_vault: &Pubkey,
+    ) -> ProgramResult {
+        let [a_auth, a_system, a_token, a_ata] = extra else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        if a_vault.key != expected_vault {
+            return Err(fail(PercolatorError::InvalidVaultAta, &[1]));
+        }
+        accounts::expect_key(a_auth, vault_authority)?;
+        accounts::expect_key(a_system, &solana_program::system_program::ID)?;
+        accounts::expect_key(a_token, &spl_token::ID)?;
+        accounts::expect_key(a_ata, &accounts::ASSOCIATED_TOKEN_PROGRAM_ID)?;
+        let ix = SolInstruction {
+            program_id: accounts::ASSOCIATED_TOKEN_PROGRAM_ID,
+            accounts: alloc::vec![
+                AccountMeta::new(*a_payer.key, true),
+                AccountMeta::new(*a_vault.key, false),
+                AccountMeta::new_readonly(*vault_authority, false),
+                AccountMeta::new_readonly(*a_mint.key, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+            ],
+            // AssociatedTokenAccountInstruction::CreateIdempotent
+            data: alloc::vec![1],
+        };
+        solana_program::program::invoke(
+            &ix,
+            &[
+                a_payer.clone(),
+                a_vault.clone(),
+                a_auth.clone(),
+                a_mint.clone(),
+                a_system.clone(),
+                a_token.clone(),
+                a_ata.clone(),
+            ],
+        )
+    }
+
+    /// Create a fresh `MATCHER_CONTEXT_LEN`-byte account owned by the
+    /// matcher program for InitMarket's one-transaction bootstrap. The
+    /// context keypair signs the outer transaction; the matcher still
+    /// initializes it and InitLP binds it as usual.
+    fn create_matcher_context<'a>(
+        a_payer: &AccountInfo<'a>,
+        a_system: &AccountInfo<'a>,
+        a_matcher_prog: &AccountInfo<'a>,
+        a_matcher_ctx: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        if !a_matcher_prog.executable || a_matcher_ctx.key == a_matcher_prog.key {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        accounts::expect_signer(a_matcher_ctx)?;
+        accounts::expect_writable(a_matcher_ctx)?;
+        if a_matcher_ctx.lamports() != 0 {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        let len = crate::constants::MATCHER_CONTEXT_LEN;
+        let rent = solana_program::sysvar::rent::Rent::get()?.minimum_balance(len);
+        solana_program::program::invoke(
+            &solana_program::system_instruction::create_account(
+                a_payer.key,
+                a_matcher_ctx.key,
+                rent,
+                len as u64,
+                a_matcher_prog.key,
+            ),
+            &[a_payer.clone(), a_matcher_ctx.clone(), a_system.clone()],
+        )
+    }
+
+    /// Verify a user's token account: owner, mint, and initialized state.
+    /// Skip in tests to allow mock accounts.
+    #[allow(unused_variables)]
+    fn verify_token_account(
+        a_token_account: &AccountInfo,
+        expected_owner: &Pubkey,
+        expected_mint: &Pubkey,
+    ) -> Result<(), ProgramError> {
+        let tok = verify_token_account_mint(a_token_account, expected_mint)?;
+        if tok.owner != *expected_owner {
+            return Err(PercolatorError::InvalidTokenAccount.into());
+        }
+        Ok(())
+    }
+
+    /// The matcher context must record `expected_lp_pda` as its LP.
+    fn check_matcher_ctx_bound(
+        a_matcher_ctx: &AccountInfo,
+        expected_lp_pda: &Pubkey,
+        lp_idx: u16,
+    ) -> Result<(), ProgramError> {
+        let ctx_lp_pda = crate::matcher_abi::read_ctx_lp_pda(&a_matcher_ctx.try_borrow_data()?)?;
+        if !crate::policy::matcher_ctx_bound(ctx_lp_pda, expected_lp_pda.to_bytes()) {
+            return Err(fail(
+                PercolatorError::MatcherContextMismatch,
+                &[lp_idx as u64],
+            ));
         }
         Ok(())
     }
 
-    /// verify_vault + require zero balance (for InitMarket).
-    /// Reuses the unpack from verify_vault logic (single unpack).
-    fn verify_vault_empty(
-        a_vault: &AccountInfo,
-        expected_owner: &Pubkey,
-        expected_mint: &Pubkey,
-        expected_pubkey: &Pubkey,
+    /// InitLP-time matcher checks: the supplied context is the registered
+    /// one, is owned by the registered matcher program, and is bound to
+    /// the LP slot being materialized on this slab.
+    fn verify_matcher_ctx_binding(
+        program_id: &Pubkey,
+        slab_key: &Pubkey,
+        lp_idx: u16,
+        matcher_program: &Pubkey,
+        matcher_context: &Pubkey,
+        a_matcher_ctx: &AccountInfo,
     ) -> Result<(), ProgramError> {
-        if a_vault.key != expected_pubkey {
-            return Err(PercolatorError::InvalidVaultAta.into());
-        }
-        if a_vault.owner != &spl_token::ID {
-            return Err(PercolatorError::InvalidVaultAta.into());
-        }
-        if a_vault.data_len() != spl_token::state::Account::LEN {
-            return Err(PercolatorError::InvalidVaultAta.into());
-        }
-        let data = a_vault.try_borrow_data()?;
-        let tok = spl_token::state::Account::unpack(&data)?;
-        if tok.mint != *expected_mint {
-            return Err(PercolatorError::InvalidMint.into());
-        }
-        if tok.owner != *expected_owner {
-            return Err(PercolatorError::InvalidVaultAta.into());
-        }
-        if tok.state != spl_token::state::AccountState::Initialized {
-            return Err(PercolatorError::InvalidVaultAta.into());
+        if a_matcher_ctx.key != matcher_context
+            || a_matcher_ctx.owner != matcher_program
+            || a_matcher_ctx.executable
+            || !crate::policy::ctx_len_sufficient(a_matcher_ctx.data_len())
+        {
+            return Err(fail(
+                PercolatorError::MatcherContextMismatch,
+                &[lp_idx as u64],
+            ));
+        }
+        let (lp_pda, _) = Pubkey::find_program_address(
+            &[b"lp", slab_key.as_ref(), &lp_idx.to_le_bytes()],
+            program_id,
+        );
+        check_matcher_ctx_bound(a_matcher_ctx, &lp_pda, lp_idx)
+    }
+
+    /// The account funding rent for an admin instruction: an optional
+    /// trailing `sponsor` (any writable signer, e.g. a relayer that is also
+    /// the fee payer), else the admin. Authorization never looks at it.
+    fn rent_payer<'b, 'a>(
+        a_admin: &'b AccountInfo<'a>,
+        sponsor: Option<&'b AccountInfo<'a>>,
+    ) -> Result<&'b AccountInfo<'a>, ProgramError> {
+        let payer = sponsor.unwrap_or(a_admin);
+        accounts::expect_signer(payer)?;
+        accounts::expect_writable(payer)?;
+        Ok(payer)
+    }
+
+    /// Create a program-owned PDA of `len` bytes, rent paid by `payer`.
+    /// Lamports already sent to the address (which would make a plain
+    /// CreateAccount fail) are kept and topped up instead.
+    fn create_pda_account<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        target: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        len: usize,
+        seeds: &[&[u8]],
+    ) -> ProgramResult {
+        use solana_program::{program::invoke, program::invoke_signed, system_instruction};
+        let rent = solana_program::sysvar::rent::Rent::get()?.minimum_balance(len);
+        let infos = [payer.clone(), target.clone(), system_program.clone()];
+        if target.lamports() == 0 {
+            let ix = system_instruction::create_account(
+                payer.key, target.key, rent, len as u64, program_id,
+            );
+            return invoke_signed(&ix, &infos, &[seeds]);
         }
-        if tok.delegate.is_some() || tok.close_authority.is_some() {
-            return Err(PercolatorError::InvalidVaultAta.into());
+        let shortfall = rent.saturating_sub(target.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, target.key, shortfall),
+                &infos,
+            )?;
         }
-        if tok.amount != 0 {
-            return Err(ProgramError::InvalidAccountData);
+        invoke_signed(
+            &system_instruction::allocate(target.key, len as u64),
+            &infos,
+            &[seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(target.key, program_id),
+            &infos,
+            &[seeds],
+        )
+    }
+
+    /// Echo a nonzero client tag into the logs for indexers.
+    fn log_client_tag(
+        ix_tag: u8,
+        user_idx: u16,
+        client_tag: &[u8; crate::constants::CLIENT_TAG_LEN],
+    ) {
+        if client_tag.iter().all(|&b| b == 0) {
+            return;
         }
-        Ok(())
+        let event = crate::ix::ClientTagEvent {
+            ix_tag,
+            user_idx,
+            client_tag: *client_tag,
+        };
+        solana_program::log::sol_log_data(&[&event.encode()]);
     }
 
-    /// Verify a user's token account: owner, mint, and initialized state.
-    /// Skip in tests to allow mock accounts.
-    #[allow(unused_variables)]
-    fn verify_token_account(
+    /// Initialized SPL token account of `expected_mint`, any owner.
+    fn verify_token_account_mint(
         a_token_account: &AccountInfo,
-        expected_owner: &Pubkey,
         expected_mint: &Pubkey,
-    ) -> Result<(), ProgramError> {
+    ) -> Result<spl_token::state::Account, ProgramError> {
         if a_token_account.owner != &spl_token::ID {
             return Err(PercolatorError::InvalidTokenAccount.into());
         }
@@ -5875,13 +12324,10 @@ pub mod processor {
         if tok.mint != *expected_mint {
             return Err(PercolatorError::InvalidMint.into());
         }
-        if tok.owner != *expected_owner {
-            return Err(PercolatorError::InvalidTokenAccount.into());
-        }
         if tok.state != spl_token::state::AccountState::Initialized {
             return Err(PercolatorError::InvalidTokenAccount.into());
         }
-        Ok(())
+        Ok(tok)
     }
 
     /// Verify the token program account is valid.
@@ -5904,6 +12350,8 @@ pub mod processor {
     /// Scoped live-withdrawal authority. Cannot call tag 20
     /// (unbounded), only tag 23 (`WithdrawInsuranceLimited`).
     pub const AUTHORITY_INSURANCE_OPERATOR: u8 = 4;
+    /// Protocol-fee claimer. Only tag 38 (`ClaimProtocolFees`).
+    pub const AUTHORITY_FEE: u8 = 5;
 
     /// Standalone handler for UpdateAuthority. Extracted from
     /// process_instruction to keep its stack frame independent —
@@ -5916,6 +12364,7 @@ pub mod processor {
         accounts: &[AccountInfo<'a>],
         kind: u8,
         new_pubkey: Pubkey,
+        instruction_data: &[u8],
     ) -> Result<(), ProgramError> {
         accounts::expect_len(accounts, 3)?;
         let a_current = &accounts[0];
@@ -5962,9 +12411,20 @@ pub mod processor {
             AUTHORITY_HYPERP_MARK => config.hyperp_authority,
             AUTHORITY_INSURANCE => header.insurance_authority,
             AUTHORITY_INSURANCE_OPERATOR => header.insurance_operator,
+            AUTHORITY_FEE => state::read_fee_authority(&data),
             _ => return Err(ProgramError::InvalidInstructionData),
         };
-        require_admin(current_bytes, a_current.key)?;
+        if kind == AUTHORITY_ADMIN {
+            require_admin_action(
+                &mut data,
+                a_slab.key,
+                a_current.key,
+                accounts,
+                instruction_data,
+            )?;
+        } else {
+            require_admin(current_bytes, a_current.key)?;
+        }
 
         // Kind-specific invariants at assignment time.
         match kind {
@@ -6014,7 +12474,7 @@ pub mod processor {
                     return Err(PercolatorError::InvalidConfigParam.into());
                 }
             }
-            AUTHORITY_INSURANCE | AUTHORITY_INSURANCE_OPERATOR => {
+            AUTHORITY_INSURANCE | AUTHORITY_INSURANCE_OPERATOR | AUTHORITY_FEE => {
                 // No per-kind invariants. Burning is a legitimate
                 // no-rug configuration; setting to any pubkey is a
                 // normal delegation. The insurance_operator kind is
@@ -6031,6 +12491,7 @@ pub mod processor {
             AUTHORITY_ADMIN => {
                 header.admin = new_bytes;
                 state::write_header(&mut data, &header);
+                state::write_pending_admin(&mut data, &[0u8; 32]);
             }
             AUTHORITY_HYPERP_MARK => {
                 config.hyperp_authority = new_bytes;
@@ -6044,11 +12505,269 @@ pub mod processor {
                 header.insurance_operator = new_bytes;
                 state::write_header(&mut data, &header);
             }
+            AUTHORITY_FEE => {
+                state::write_fee_authority(&mut data, &new_bytes);
+            }
             _ => unreachable!(),
         }
         Ok(())
     }
 
+    /// TopUpInsurance (tag 9) and DonateInsurance (tag 45): anyone moves
+    /// collateral into the vault, credited only to the insurance fund.
+    /// `principal` top-ups also grow the deposits-only withdrawal budget
+    /// of WithdrawInsuranceLimited; donations do not.
+    #[inline(never)]
+    fn handle_top_up_insurance<'a>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo<'a>],
+        amount: u64,
+        principal: bool,
+    ) -> Result<(), ProgramError> {
+        accounts::expect_len(accounts, 6)?;
+        let a_user = &accounts[0];
+        let a_slab = &accounts[1];
+        let a_user_ata = &accounts[2];
+        let a_vault = &accounts[3];
+        let a_token = &accounts[4];
+        let a_clock = &accounts[5];
+
+        accounts::expect_signer(a_user)?;
+        accounts::expect_writable(a_slab)?;
+        verify_token_program(a_token)?;
+        if amount == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut data = state::slab_data_mut(a_slab)?;
+        slab_guard(program_id, a_slab, &data)?;
+        require_initialized(&data)?;
+
+        // Block insurance top-up when market is resolved
+        if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut config = state::read_config(&data);
+        insurance_withdraw_deposits_only(&config)?;
+        let mint = Pubkey::new_from_array(config.collateral_mint);
+
+        let auth = accounts::derive_vault_authority_with_bump(
+            program_id,
+            a_slab.key,
+            config.vault_authority_bump,
+        )?;
+        verify_vault(
+            a_vault,
+            &auth,
+            &mint,
+            &Pubkey::new_from_array(config.vault_pubkey),
+        )?;
+        verify_token_account(a_user_ata, a_user.key, &mint)?;
+
+        let clock = Clock::from_account_info(a_clock)?;
+        // Hard-timeout gate: reject before moving tokens into a
+        // terminally-stale market.
+        if oracle::permissionless_stale_matured(&config, clock.slot) {
+            return Err(PercolatorError::OracleStale.into());
+        }
+        check_no_oracle_live_envelope(zc::engine_ref(&data)?, clock.slot)?;
+
+        // Reject misaligned deposits — dust would be silently donated
+        let (_units_check, dust_check) = crate::units::base_to_units(amount, config.unit_scale);
+        if dust_check != 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Convert base tokens to units for engine
+        let (units, _dust) = crate::units::base_to_units(amount, config.unit_scale);
+        // Donations never become withdrawable top-up principal.
+        let new_deposit_remaining = if principal {
+            config
+                .insurance_withdraw_deposit_remaining
+                .checked_add(units)
+                .ok_or(PercolatorError::EngineOverflow)?
+        } else {
+            config.insurance_withdraw_deposit_remaining
+        };
+
+        // Transfer base tokens to vault
+        collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount)?;
+
+        let engine = zc::engine_mut(&mut data)?;
+        engine
+            .top_up_insurance_fund(units as u128, clock.slot)
+            .map_err(map_risk_error)?;
+        config.insurance_withdraw_deposit_remaining = new_deposit_remaining;
+        state::write_config(&mut data, &config);
+        Ok(())
+    }
+
+    /// Standalone handler for ClaimProtocolFees (tag 38), kept out of
+    /// process_instruction for the same stack reason as UpdateAuthority.
+    #[inline(never)]
+    fn handle_claim_protocol_fees<'a>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo<'a>],
+        amount: u64,
+    ) -> ProgramResult {
+        // Two layouts:
+        //   [fee_authority, slab, treasury_ata, vault, token, vault_pda,
+        //    clock, treasury_pda]            — sweep into the treasury
+        //   [..., destination]               — then forward the treasury
+        //     balance to a token account owned by the fee authority
+        use accounts::{AccountSpec, Owner};
+        const SPECS: &[AccountSpec] = &[
+            AccountSpec::new("fee authority signer").signer(),
+            AccountSpec::new("writable program-owned slab")
+                .writable()
+                .owner(Owner::Program),
+            AccountSpec::new("treasury token account"),
+            AccountSpec::new("vault token account"),
+            AccountSpec::new("token program"),
+            AccountSpec::new("vault authority PDA"),
+            AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+            AccountSpec::new("treasury PDA"),
+        ];
+        let forward = accounts.len() == SPECS.len() + 1;
+        let head = if forward {
+            &accounts[..SPECS.len()]
+        } else {
+            accounts
+        };
+        accounts::check_accounts(program_id, head, SPECS)?;
+        let a_authority = &accounts[0];
+        let a_slab = &accounts[1];
+        let a_treasury_ata = &accounts[2];
+        let a_vault = &accounts[3];
+        let a_token = &accounts[4];
+        let a_vault_pda = &accounts[5];
+        let a_clock = &accounts[6];
+        let a_treasury = &accounts[7];
+
+        verify_token_program(a_token)?;
+
+        let mut data = state::slab_data_mut(a_slab)?;
+        slab_guard(program_id, a_slab, &data)?;
+        require_initialized(&data)?;
+
+        // Live markets only. Resolved markets drain insurance, protocol
+        // fees included, through tag 20.
+        if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        require_admin(state::read_fee_authority(&data), a_authority.key)?;
+
+        let config = state::read_config(&data);
+        let clock = Clock::from_account_info(a_clock)?;
+        if oracle::permissionless_stale_matured(&config, clock.slot) {
+            return Err(PercolatorError::OracleStale.into());
+        }
+
+        let (amount_units, dust) = crate::units::base_to_units(amount, config.unit_scale);
+        if dust != 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mint = Pubkey::new_from_array(config.collateral_mint);
+        let (treasury, treasury_bump) = accounts::derive_treasury(program_id, a_slab.key);
+        accounts::expect_key(a_treasury, &treasury)?;
+        verify_token_account(a_treasury_ata, &treasury, &mint)?;
+
+        // amount == 0 skips the sweep (forward-only call).
+        if amount_units != 0 {
+            // Same live-market health gate as tags 23 and 33: protocol
+            // fees are paid out of insurance.
+            {
+                let engine = zc::engine_ref(&data)?;
+                reject_any_target_lag(&config, engine)?;
+                let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+                if oi_any && engine.last_market_slot != clock.slot {
+                    return Err(PercolatorError::CatchupRequired.into());
+                }
+                let stress_envelope_active = engine.stress_consumed_bps_e9_since_envelope != 0
+                    || engine.stress_envelope_remaining_indices != 0
+                    || engine.bankruptcy_hmax_lock_active;
+                if !crate::policy::live_insurance_withdraw_market_healthy(
+                    engine.vault.get(),
+                    engine.c_tot.get(),
+                    engine.insurance_fund.balance.get(),
+                    stress_envelope_active,
+                ) {
+                    return Err(PercolatorError::EngineInsufficientBalance.into());
+                }
+            }
+
+            // Cap: the accumulator, clamped to what insurance still holds.
+            let fees = state::read_protocol_fees(&data);
+            let ins = zc::engine_ref(&data)?.insurance_fund.balance.get();
+            let cap = core::cmp::min(fees, ins);
+            if (amount_units as u128) > cap {
+                // ctx: requested units, cap
+                return Err(fail(
+                    PercolatorError::InsuranceWithdrawCapExceeded,
+                    &[amount_units, clamp_u64(cap)],
+                ));
+            }
+
+            let auth = accounts::derive_vault_authority_with_bump(
+                program_id,
+                a_slab.key,
+                config.vault_authority_bump,
+            )?;
+            verify_vault(
+                a_vault,
+                &auth,
+                &mint,
+                &Pubkey::new_from_array(config.vault_pubkey),
+            )?;
+            accounts::expect_key(a_vault_pda, &auth)?;
+
+            // State first, then the SPL Token CPI (cannot re-enter).
+            {
+                let engine = zc::engine_mut(&mut data)?;
+                engine
+                    .withdraw_live_insurance_not_atomic(amount_units as u128, clock.slot)
+                    .map_err(map_risk_error)?;
+            }
+            state::write_protocol_fees(&mut data, fees - amount_units as u128);
+        }
+        drop(data);
+
+        if amount_units != 0 {
+            let bump_arr: [u8; 1] = [config.vault_authority_bump];
+            let seeds: [&[u8]; 3] = [b"vault", a_slab.key.as_ref(), &bump_arr];
+            collateral::withdraw(
+                a_token,
+                a_vault,
+                a_treasury_ata,
+                a_vault_pda,
+                amount,
+                &[&seeds],
+            )?;
+        }
+
+        if forward {
+            let a_dest = &accounts[8];
+            verify_token_account(a_dest, a_authority.key, &mint)?;
+            let balance = {
+                let tdata = a_treasury_ata.try_borrow_data()?;
+                spl_token::state::Account::unpack(&tdata)?.amount
+            };
+            let bump_arr: [u8; 1] = [treasury_bump];
+            let seeds: [&[u8]; 3] = [b"treasury", a_slab.key.as_ref(), &bump_arr];
+            collateral::withdraw(
+                a_token,
+                a_treasury_ata,
+                a_dest,
+                a_treasury,
+                balance,
+                &[&seeds],
+            )?;
+        }
+        Ok(())
+    }
+
     #[inline(never)]
     fn handle_init_market<'a, 'b>(
         program_id: &Pubkey,
@@ -6073,6 +12792,7 @@ pub mod processor {
             risk_params,
             new_account_fee,
             permissionless_resolve_stale_slots,
+            max_crank_staleness_slots,
             funding_horizon_slots: custom_funding_horizon,
             funding_k_bps: custom_funding_k,
             funding_max_premium_bps: custom_max_premium,
@@ -6080,6 +12800,7 @@ pub mod processor {
             mark_min_fee,
             trade_fee_base_bps,
             force_close_delay_slots,
+            metadata,
         } = *args;
         let oracle_leg2_feed_id = &oracle_leg_feeds.leg2_feed_id;
         let oracle_leg3_feed_id = &oracle_leg_feeds.leg3_feed_id;
@@ -6123,6 +12844,9 @@ pub mod processor {
         if invert > 1 {
             return Err(ProgramError::InvalidInstructionData);
         }
+        if !crate::policy::market_metadata_ok(&metadata) {
+            return Err(PercolatorError::InvalidConfigParam.into());
+        }
         // Confidence filter: require a nonzero, operationally sane
         // range. Disabling confidence checks is too sharp for public
         // deployments; wide confidence bands are equivalent to
@@ -6198,7 +12922,20 @@ pub mod processor {
         } else {
             oracle_leg_count as usize
         };
-        accounts::expect_len(accounts, 5 + init_oracle_account_count)?;
+        // Optional trailing [vault_authority, system_program, token_program,
+        // ata_program]: InitMarket then creates the vault ATA itself. Two
+        // more, [matcher_program, matcher_ctx (signer)], also create an
+        // empty matcher context, so a pre-created slab needs one tx. A
+        // final odd account is the rent payer for both; absent, the admin
+        // pays.
+        let vault_create_off = 5 + init_oracle_account_count;
+        let trailing = accounts.len().saturating_sub(vault_create_off);
+        if !(4..=7).contains(&trailing) {
+            accounts::expect_len(accounts, vault_create_off)?;
+        }
+        let create_vault = trailing >= 4;
+        let create_matcher_ctx = trailing >= 6;
+        let sponsor = (trailing % 2 == 1).then(|| &accounts[accounts.len() - 1]);
 
         // Normalize initial mark price to engine-space (invert + scale).
         // All Hyperp internal prices must be in engine-space.
@@ -6378,6 +13115,7 @@ pub mod processor {
 
         let mut data = state::slab_data_mut(a_slab)?;
         slab_shape_guard(program_id, a_slab, &data)?;
+        require_slab_rent_exempt(a_slab, data.len())?;
 
         // Check magic BEFORE any unsafe cast — raw bytes may contain
         // invalid enum discriminants that would be UB if cast to RiskEngine.
@@ -6385,16 +13123,49 @@ pub mod processor {
         if header.magic == MAGIC {
             return Err(PercolatorError::AlreadyInitialized.into());
         }
-        // Canonicalize the entire slab before the first RiskEngine
-        // reference. An uninitialized System account is normally
-        // zero-filled, but `magic != MAGIC` does not prove stale bytes
-        // in the engine/config/cache regions are valid or canonical.
-        for b in data.iter_mut() {
-            *b = 0;
+        if header.magic == crate::constants::INITIALIZING_MAGIC {
+            // InitSlab already zeroed everything past the header; only
+            // the signer that claimed the slab may finalize it.
+            require_admin(header.admin, a_admin.key)?;
+            if state::read_init_cursor(&data) != SLAB_LEN as u64 {
+                return Err(PercolatorError::SlabInitIncomplete.into());
+            }
+            data[..crate::constants::HEADER_LEN].fill(0);
+        } else {
+            // The slab must be canonical before the first RiskEngine
+            // reference. A fresh System account is zero-filled, but
+            // `magic != MAGIC` does not prove stale bytes in the
+            // engine/config/cache regions are valid, so check rather than
+            // trust it.
+            require_slab_zeroed(&data, 0)?;
         }
 
         let (auth, bump) = accounts::derive_vault_authority(program_id, a_slab.key);
-        verify_vault_empty(a_vault, &auth, a_mint.key, a_vault.key)?;
+        let expected_vault = accounts::associated_token_address(&auth, a_mint.key);
+        let a_payer = if create_vault {
+            rent_payer(a_admin, sponsor)?
+        } else {
+            a_admin
+        };
+        if create_vault {
+            create_vault_ata(
+                a_payer,
+                a_vault,
+                a_mint,
+                &accounts[vault_create_off..],
+                &auth,
+                &expected_vault,
+            )?;
+        }
+        verify_vault_empty(a_vault, &auth, a_mint.key, &expected_vault)?;
+        if create_matcher_ctx {
+            create_matcher_context(
+                a_payer,
+                &accounts[vault_create_off + 1],
+                &accounts[vault_create_off + 4],
+                &accounts[vault_create_off + 5],
+            )?;
+        }
 
         // Initialize engine in-place (zero-copy) to avoid stack overflow.
         let a_clock = &accounts[4];
@@ -6525,6 +13296,16 @@ pub mod processor {
         // who want capability isolation call UpdateAuthority with the
         // specific kind.
         write_init_header(&mut data, a_admin.key, bump);
+        state::write_fee_authority(&mut data, &a_admin.key.to_bytes());
+        state::write_market_metadata(&mut data, &metadata);
+        state::write_crank_guard(
+            &mut data,
+            &state::CrankGuard {
+                max_crank_staleness_slots,
+                last_crank_slot: clock.slot,
+                withdraw_only: false,
+            },
+        );
         // Step 4: Explicitly initialize nonce to 0 for determinism
         state::write_req_nonce(&mut data, 0);
         // Oracle is now initialized from genesis in both modes:
@@ -6546,9 +13327,17 @@ pub mod processor {
         // actually enforceable. Timing-sensitive operations should rely on
         // slot/timestamp freshness checks instead.
 
-        match Instruction::decode(instruction_data)? {
+        #[cfg(feature = "anchor-compat")]
+        let instruction = crate::ix::anchor_compat::decode(instruction_data)?;
+        #[cfg(not(feature = "anchor-compat"))]
+        let instruction = Instruction::decode(instruction_data)?;
+
+        match instruction {
             Instruction::InitMarket(args) => handle_init_market(program_id, accounts, args)?,
-            Instruction::InitUser { fee_payment } => {
+            Instruction::InitUser {
+                fee_payment,
+                allowlist_proof,
+            } => {
                 // Spec §10.2: deposit is the canonical materialization path
                 // — pure capital transfer, MUST NOT accrue_market_to, MUST
                 // NOT mutate side state. Therefore InitUser does not read
@@ -6575,11 +13364,17 @@ pub mod processor {
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+                reject_withdraw_only(&data)?;
 
                 // Block new users when market is resolved
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
+                check_allowlist(
+                    &state::read_allowlist_root(&data),
+                    a_user.key,
+                    &allowlist_proof,
+                )?;
                 let config = state::read_config(&data);
                 let mint = Pubkey::new_from_array(config.collateral_mint);
 
@@ -6614,7 +13409,8 @@ pub mod processor {
                 }
 
                 // InitUser splits `fee_payment` into:
-                //   - `new_account_fee` → insurance (wrapper-charged)
+                //   - `new_account_fee` → insurance (wrapper-charged), also
+                //     credited to the header fee accumulator for WithdrawFees
                 //   - remainder → capital
                 //
                 // Engine requires `amount > 0` on materialization (§10.2);
@@ -6655,7 +13451,11 @@ pub mod processor {
                         .saturating_add(capital_units_sim as u128);
                     let cap = ins_new.saturating_mul(config.tvl_insurance_cap_mult as u128);
                     if c_tot_new > cap {
-                        return Err(PercolatorError::DepositCapExceeded.into());
+                        // ctx: c_tot after deposit, cap
+                        return Err(fail(
+                            PercolatorError::DepositCapExceeded,
+                            &[clamp_u64(c_tot_new), clamp_u64(cap)],
+                        ));
                     }
                 }
 
@@ -6669,20 +13469,32 @@ pub mod processor {
                     return Err(PercolatorError::EngineInsufficientBalance.into());
                 }
 
+                let limits = state::read_account_limits(&data);
                 let engine = zc::engine_mut(&mut data)?;
                 let idx = prepare_lazy_free_head(engine)?;
                 engine
                     .deposit_not_atomic(idx, capital_units as u128, clock.slot)
                     .map_err(map_risk_error)?;
                 set_wrapper_owner(engine, idx, a_user.key.to_bytes())?;
+                check_capital_limit(engine, idx, &limits)?;
+                let equity = booked_equity(engine, idx);
                 if fee_units > 0 {
                     engine
                         .top_up_insurance_fund(fee_units as u128, clock.slot)
                         .map_err(map_risk_error)?;
                 }
+                let fees = state::read_account_fees(&data)
+                    .checked_add(fee_units)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                state::write_account_fees(&mut data, fees);
                 let gen =
                     state::next_mat_counter(&mut data).ok_or(PercolatorError::EngineOverflow)?;
                 state::write_account_generation(&mut data, idx, gen);
+                state::write_order_ids(&mut data, idx, &[0; crate::constants::ORDER_ID_RING]);
+                state::write_last_trade_slot(&mut data, idx, 0);
+                state::write_liq_flag_slot(&mut data, idx, 0);
+                open_epoch_stats(&mut data, idx, equity);
+                open_pnl_ledger(&mut data, idx);
             }
             Instruction::InitLP {
                 matcher_program,
@@ -6692,8 +13504,13 @@ pub mod processor {
                 // Same 6-account layout and pure-deposit semantics as
                 // InitUser: spec §10.2 makes account creation a pure
                 // capital path that must not read the oracle or call
-                // accrue_market_to.
-                accounts::expect_len(accounts, 6)?;
+                // accrue_market_to. An optional 7th account is the
+                // matcher context, checked for ownership and binding
+                // here instead of at the first TradeCpi.
+                let with_ctx = accounts.len() == 7;
+                if !with_ctx {
+                    accounts::expect_len(accounts, 6)?;
+                }
                 let a_user = &accounts[0];
                 let a_slab = &accounts[1];
                 let a_user_ata = &accounts[2];
@@ -6711,6 +13528,7 @@ pub mod processor {
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+                reject_withdraw_only(&data)?;
 
                 // Block new LPs when market is resolved
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
@@ -6779,7 +13597,11 @@ pub mod processor {
                         .saturating_add(capital_units_sim as u128);
                     let cap = ins_new.saturating_mul(config.tvl_insurance_cap_mult as u128);
                     if c_tot_new > cap {
-                        return Err(PercolatorError::DepositCapExceeded.into());
+                        // ctx: c_tot after deposit, cap
+                        return Err(fail(
+                            PercolatorError::DepositCapExceeded,
+                            &[clamp_u64(c_tot_new), clamp_u64(cap)],
+                        ));
                     }
                 }
 
@@ -6794,6 +13616,16 @@ pub mod processor {
 
                 let engine = zc::engine_mut(&mut data)?;
                 let idx = prepare_lazy_free_head(engine)?;
+                if with_ctx {
+                    verify_matcher_ctx_binding(
+                        program_id,
+                        a_slab.key,
+                        idx,
+                        &matcher_program,
+                        &matcher_context,
+                        &accounts[6],
+                    )?;
+                }
                 engine
                     .deposit_not_atomic(idx, capital_units as u128, clock.slot)
                     .map_err(map_risk_error)?;
@@ -6801,17 +13633,32 @@ pub mod processor {
                 engine.accounts[idx as usize].kind = percolator::Account::KIND_LP;
                 engine.accounts[idx as usize].matcher_program = matcher_program.to_bytes();
                 engine.accounts[idx as usize].matcher_context = matcher_context.to_bytes();
+                let equity = booked_equity(engine, idx);
                 if fee_units > 0 {
                     engine
                         .top_up_insurance_fund(fee_units as u128, clock.slot)
                         .map_err(map_risk_error)?;
                 }
+                let fees = state::read_account_fees(&data)
+                    .checked_add(fee_units)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                state::write_account_fees(&mut data, fees);
                 let gen =
                     state::next_mat_counter(&mut data).ok_or(PercolatorError::EngineOverflow)?;
                 state::write_account_generation(&mut data, idx, gen);
+                state::write_order_ids(&mut data, idx, &[0; crate::constants::ORDER_ID_RING]);
+                state::write_last_trade_slot(&mut data, idx, 0);
+                state::write_liq_flag_slot(&mut data, idx, 0);
+                open_epoch_stats(&mut data, idx, equity);
+                open_pnl_ledger(&mut data, idx);
             }
-            Instruction::DepositCollateral { user_idx, amount } => {
-                accounts::expect_len(accounts, 6)?;
+            Instruction::DepositCollateral {
+                user_idx,
+                amount,
+                allowlist_proof,
+                client_tag,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::DEPOSIT)?;
                 let a_user = &accounts[0];
                 let a_slab = &accounts[1];
                 let a_user_ata = &accounts[2];
@@ -6819,117 +13666,33 @@ pub mod processor {
                 let a_token = &accounts[4];
                 let a_clock = &accounts[5];
 
-                accounts::expect_signer(a_user)?;
-                accounts::expect_writable(a_slab)?;
                 verify_token_program(a_token)?;
-                if amount == 0 {
-                    return Err(ProgramError::InvalidArgument);
-                }
-
-                let mut data = state::slab_data_mut(a_slab)?;
-                slab_guard(program_id, a_slab, &data)?;
-                require_initialized(&data)?;
-
-                // Block deposits when market is resolved
-                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
-                    return Err(ProgramError::InvalidAccountData);
-                }
-
-                let config = state::read_config(&data);
-                let mint = Pubkey::new_from_array(config.collateral_mint);
-
-                let auth = accounts::derive_vault_authority_with_bump(
-                    program_id,
-                    a_slab.key,
-                    config.vault_authority_bump,
-                )?;
-                verify_vault(
-                    a_vault,
-                    &auth,
-                    &mint,
-                    &Pubkey::new_from_array(config.vault_pubkey),
-                )?;
-                verify_token_account(a_user_ata, a_user.key, &mint)?;
-
-                let clock = Clock::from_account_info(a_clock)?;
-
-                // Hard-timeout gate: once the market has been oracle-stale
-                // for >= permissionless_resolve_stale_slots, it is
-                // terminally dead. No live mutations — including
-                // no-oracle deposits — should proceed. Users must exit
-                // via ResolvePermissionless + resolved-market close
-                // paths. Rejecting BEFORE the SPL transfer so funds
-                // are not moved into a dead market.
-                if oracle::permissionless_stale_matured(&config, clock.slot) {
-                    return Err(PercolatorError::OracleStale.into());
-                }
-                check_no_oracle_live_envelope(zc::engine_ref(&data)?, clock.slot)?;
-
-                // Reject misaligned deposits — dust would be silently donated
-                let (_units_check, dust_check) =
-                    crate::units::base_to_units(amount, config.unit_scale);
-                if dust_check != 0 {
-                    return Err(ProgramError::InvalidArgument);
-                }
-
-                // TVL:insurance cap (admin opt-in). Enforced BEFORE the
-                // SPL transfer so rejected deposits don't move funds.
-                // Formula: `c_tot_new <= k * insurance_fund.balance`.
-                // k=0 disables the check; nonzero k with zero insurance
-                // means no deposits accepted — operator is expected to
-                // seed insurance (via TopUpInsurance or fee accumulation)
-                // before enabling or raising k. No fee split here: the
-                // full amount credits capital against the current
-                // insurance balance.
-                if config.tvl_insurance_cap_mult > 0 {
-                    let (capital_units_sim, _) =
-                        crate::units::base_to_units(amount, config.unit_scale);
-                    let engine_r = zc::engine_ref(&data)?;
-                    let ins = engine_r.insurance_fund.balance.get();
-                    let c_tot_new = engine_r
-                        .c_tot
-                        .get()
-                        .saturating_add(capital_units_sim as u128);
-                    let cap = ins.saturating_mul(config.tvl_insurance_cap_mult as u128);
-                    if c_tot_new > cap {
-                        return Err(PercolatorError::DepositCapExceeded.into());
-                    }
-                }
-
-                // Transfer base tokens to vault
-                collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount)?;
-
-                // Convert base tokens to units for engine
-                let (units, _dust) = crate::units::base_to_units(amount, config.unit_scale);
-
-                let engine = zc::engine_mut(&mut data)?;
-
-                check_idx(engine, user_idx)?;
-
-                // Owner authorization via policy helper
-                let owner = engine.accounts[user_idx as usize].owner;
-                if !crate::policy::owner_ok(owner, a_user.key.to_bytes()) {
-                    return Err(PercolatorError::EngineUnauthorized.into());
+                if amount == 0 {
+                    return Err(ProgramError::InvalidArgument);
                 }
 
-                // No-oracle path: pass clock.slot to deposit_not_atomic.
-                // The engine's check_live_accrual_envelope gates on dt =
-                // clock.slot - last_market_slot <= max_accrual_dt_slots —
-                // the same safety bound that ensures the next oracle-
-                // backed instruction's accrue won't exceed its envelope.
-                // Fee anchoring is capped at last_market_slot by
-                // sync_account_fee_bounded_to_market (per spec §10.7: no
-                // accrue in this no-oracle path); the residual tail
-                // (last_market_slot, clock.slot] is realized by the
-                // next oracle-backed op via ensure_market_accrued_to_now.
-                sync_account_fee_bounded_to_market(engine, &config, user_idx, clock.slot)?;
-
-                engine
-                    .deposit_not_atomic(user_idx, units as u128, clock.slot)
-                    .map_err(map_risk_error)?;
+                deposit_for_owner(
+                    program_id,
+                    a_slab,
+                    a_user_ata,
+                    a_vault,
+                    a_clock,
+                    a_user.key,
+                    user_idx,
+                    amount,
+                    None,
+                    &allowlist_proof,
+                    || collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount),
+                )?;
+                log_client_tag(3, user_idx, &client_tag);
             }
-            Instruction::WithdrawCollateral { user_idx, amount } => {
+            Instruction::WithdrawCollateral {
+                user_idx,
+                amount,
+                nonce,
+            } => {
                 accounts::expect_len_min(accounts, 8)?;
+                accounts::check_account_head(program_id, accounts, accounts::WITHDRAW_HEAD)?;
                 let a_user = &accounts[0];
                 let a_slab = &accounts[1];
                 let a_vault = &accounts[2];
@@ -6938,8 +13701,6 @@ pub mod processor {
                 let a_token = &accounts[5];
                 let a_clock = &accounts[6];
 
-                accounts::expect_signer(a_user)?;
-                accounts::expect_writable(a_slab)?;
                 verify_token_program(a_token)?;
                 if amount == 0 {
                     return Err(ProgramError::InvalidArgument);
@@ -6967,7 +13728,13 @@ pub mod processor {
                     &mint,
                     &Pubkey::new_from_array(config.vault_pubkey),
                 )?;
-                verify_token_account(a_user_ata, a_user.key, &mint)?;
+                // The owner's signature authorizes the payout, so the
+                // destination may be any token account of the mint
+                // (treasury, cold wallet), just not the vault itself.
+                verify_token_account_mint(a_user_ata, &mint)?;
+                if a_user_ata.key == a_vault.key {
+                    return Err(PercolatorError::InvalidTokenAccount.into());
+                }
 
                 // Block withdrawals on resolved markets.
                 // The engine's withdraw_not_atomic requires MarketMode::Live.
@@ -6977,9 +13744,14 @@ pub mod processor {
                 }
 
                 let clock = Clock::from_account_info(a_clock)?;
+                // Withdraw-only mode values the account at the engine's last
+                // accrued price and slot: no oracle read, no time advance.
+                let withdraw_only = state::read_crank_guard(&data).withdraw_only;
                 // Anti-retroactivity: capture funding rate before oracle read (§5.5)
                 let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
-                let price = {
+                let price = if withdraw_only {
+                    zc::engine_ref(&data)?.last_oracle_price
+                } else {
                     let is_hyperp = oracle::is_hyperp_mode(&config);
                     let px = if is_hyperp {
                         let eng = zc::engine_ref(&data)?;
@@ -7010,6 +13782,9 @@ pub mod processor {
                     px
                 };
 
+                consume_user_nonce(&mut data, user_idx, nonce)?;
+                let haircut_bps = state::read_stale_fallback(&data)
+                    .haircut_bps(clock.slot, config.last_good_oracle_slot);
                 let engine = zc::engine_mut(&mut data)?;
 
                 check_idx(engine, user_idx)?;
@@ -7025,7 +13800,11 @@ pub mod processor {
 
                 let (units_requested, _) = crate::units::base_to_units(amount, config.unit_scale);
 
-                let withdraw_slot = clock.slot;
+                let withdraw_slot = if withdraw_only {
+                    core::cmp::max(engine.current_slot, engine.last_market_slot)
+                } else {
+                    clock.slot
+                };
                 let admit_h_min = engine.params.h_min;
                 let admit_h_max = engine.params.h_max;
                 // Withdraw is account-limited: it only touches this user, so
@@ -7035,19 +13814,21 @@ pub mod processor {
                 // cascade first. When the strict gate allows progress, accrue
                 // first, then make this account authoritative and fee-current
                 // before the withdrawal health check.
-                ensure_market_accrued_to_now_for_account_limited_op(
-                    engine,
-                    &config,
-                    clock.slot,
-                    price,
-                    funding_rate_e9,
-                )?;
-                reject_any_target_lag(&config, engine)?;
+                if !withdraw_only {
+                    ensure_market_accrued_to_now_for_account_limited_op(
+                        engine,
+                        &config,
+                        clock.slot,
+                        price,
+                        funding_rate_e9,
+                    )?;
+                    reject_any_target_lag(&config, engine)?;
+                }
                 settle_account_then_sync_fee_current(
                     engine,
                     &config,
                     user_idx,
-                    clock.slot,
+                    withdraw_slot,
                     price,
                     funding_rate_e9,
                     admit_h_min,
@@ -7055,6 +13836,27 @@ pub mod processor {
                     Some(engine.params.maintenance_margin_bps as u128),
                 )?;
                 let admit_threshold = Some(engine.params.maintenance_margin_bps as u128);
+                let equity_before = booked_equity(engine, user_idx);
+                if haircut_bps != 0 {
+                    // Stale fallback: an open position keeps `haircut_bps`
+                    // of its notional at the held price as extra equity, for
+                    // the move the market has not seen.
+                    let pos = effective_pos_q_checked(engine, user_idx as usize)?;
+                    let keep = crate::policy::mul_div_ceil(
+                        risk_notional_ceil(pos, price),
+                        haircut_bps as u128,
+                        10_000,
+                    )
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                    let equity_after = equity_before.saturating_sub(units_requested as i128);
+                    if pos != 0 && equity_after < keep.min(i128::MAX as u128) as i128 {
+                        // ctx: haircut bps, equity to keep
+                        return Err(fail(
+                            PercolatorError::OracleFallbackActive,
+                            &[haircut_bps as u64, clamp_u64(keep)],
+                        ));
+                    }
+                }
                 engine
                     .withdraw_not_atomic(
                         user_idx,
@@ -7067,6 +13869,14 @@ pub mod processor {
                         admit_threshold,
                     )
                     .map_err(map_risk_error)?;
+                record_epoch_activity(
+                    &mut data,
+                    user_idx,
+                    equity_before,
+                    -saturate_i64(units_requested as i128),
+                    0,
+                );
+                refresh_liq_watch(&mut data, &[user_idx], price)?;
                 if !state::is_oracle_initialized(&data) {
                     state::set_oracle_initialized(&mut data);
                 }
@@ -7097,20 +13907,36 @@ pub mod processor {
                 candidates,
             } => {
                 use crate::constants::CRANK_NO_CALLER;
-
-                accounts::expect_len_min(accounts, 4)?;
-                let a_caller = &accounts[0];
-                let a_slab = &accounts[1];
-                let a_clock = &accounts[2];
+                use accounts::{AccountSpec, Owner};
+                // [caller, slab, clock], then the oracle accounts. The
+                // caller signs only when cranking as a named account.
+                const SIGNED: &[AccountSpec] = &[
+                    AccountSpec::new("caller signer").signer(),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+                ];
+                const PERMISSIONLESS: &[AccountSpec] =
+                    &[AccountSpec::new("caller"), SIGNED[1], SIGNED[2]];
 
                 // Permissionless mode: caller_idx == u16::MAX means anyone can crank.
                 // Resolved markets are always permissionless (settlement is idempotent).
                 let permissionless = caller_idx == CRANK_NO_CALLER;
 
-                if !permissionless {
-                    accounts::expect_signer(a_caller)?;
-                }
-                accounts::expect_writable(a_slab)?;
+                accounts::expect_len_min(accounts, 4)?;
+                accounts::check_account_head(
+                    program_id,
+                    accounts,
+                    if permissionless {
+                        PERMISSIONLESS
+                    } else {
+                        SIGNED
+                    },
+                )?;
+                let a_caller = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_clock = &accounts[2];
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
@@ -7193,7 +14019,13 @@ pub mod processor {
 
                 // Read risk buffer BEFORE engine borrow (disjoint regions,
                 // but borrow checker can't see that).
+                observe_circuit_breaker(&mut data, clock.slot, price);
+                advance_funding_index(&mut data, clock.slot, funding_rate_e9_pre)?;
                 let buf = state::read_risk_buffer(&data);
+                let dust = state::read_dust_limits(&data);
+                let liq_window = state::read_liquidator_window(&data);
+                let backstop_share_bps = state::read_backstop(&data).share_bps;
+                let backstop_vault = backstop_vault_idx(&data);
 
                 let engine = zc::engine_mut(&mut data)?;
 
@@ -7402,12 +14234,25 @@ pub mod processor {
                 if combined.len() > COMBINED_CAP {
                     combined.truncate(COMBINED_CAP);
                 }
+                // Flag slots live outside the engine: gate, then re-borrow.
+                if liq_window.active()
+                    && !(a_caller.is_signer && liq_window.is_liquidator(&a_caller.key.to_bytes()))
+                {
+                    gate_liquidation_hints(
+                        &data,
+                        &mut combined,
+                        clock.slot,
+                        liq_window.window_slots,
+                    );
+                }
+                let engine = zc::engine_mut(&mut data)?;
                 let bounded_engine_candidates = combined
                     .iter()
                     .map(|&(idx, policy)| (idx, policy))
                     .collect::<alloc::vec::Vec<_>>();
                 let engine_candidates: &[(u16, Option<percolator::LiquidationPolicy>)] =
                     &bounded_engine_candidates;
+                let ins_before_progress = engine.insurance_fund.balance.get();
                 let progress_outcome = engine
                     .permissionless_progress_not_atomic(percolator::PermissionlessProgressRequest {
                         now_slot: crank_slot,
@@ -7445,6 +14290,26 @@ pub mod processor {
                     progress_outcome,
                     percolator::PermissionlessProgressOutcome::Cranked(_)
                 );
+                // Backstop vault's share of liquidation fees, measured over
+                // the progress call alone (fee syncs and the reward sweep
+                // run later). Partial catchup leaves the market loss-stale,
+                // so nothing leaves insurance until a full crank.
+                let backstop_paid = match (&progress_outcome, backstop_vault) {
+                    (percolator::PermissionlessProgressOutcome::Cranked(outcome), Some(vault))
+                        if backstop_share_bps != 0
+                            && !partial_catchup
+                            && outcome.num_liquidations > 0 =>
+                    {
+                        pay_backstop_vault(
+                            engine,
+                            vault,
+                            backstop_share_bps,
+                            ins_before_progress,
+                            crank_slot,
+                        )?
+                    }
+                    _ => 0,
+                };
                 #[cfg(feature = "cu-audit")]
                 {
                     msg!("CU_CHECKPOINT: keeper_crank_end");
@@ -7456,13 +14321,14 @@ pub mod processor {
                 // mark/funding losses. Candidate syncs are bounded and not
                 // rewardable; the reward delta is measured only over the
                 // bitmap sweep that follows them.
-                let sweep_delta = if !progress_was_crank || partial_catchup {
+                let (sweep_delta, fees_accrued) = if !progress_was_crank || partial_catchup {
                     // The market remains loss-stale. Do not charge recurring
                     // fees until a later crank makes the market loss-current;
                     // fee sync would otherwise be senior to the remaining
                     // unapplied historical loss segment.
-                    0
+                    (0, 0)
                 } else {
+                    let ins_before_fee_syncs = engine.insurance_fund.balance.get();
                     let candidate_syncs = sync_keeper_candidate_fees_after_crank(
                         engine,
                         &combined,
@@ -7481,11 +14347,11 @@ pub mod processor {
                         0
                     };
                     sweep_maintenance_fees(engine, &mut config, crank_slot, remaining_budget)?;
-                    engine
-                        .insurance_fund
-                        .balance
-                        .get()
-                        .saturating_sub(ins_before_reward_sweep)
+                    let ins_after_fees = engine.insurance_fund.balance.get();
+                    (
+                        ins_after_fees.saturating_sub(ins_before_reward_sweep),
+                        ins_after_fees.saturating_sub(ins_before_fee_syncs),
+                    )
                 };
 
                 // Pay the crank reward AFTER keeper_crank_not_atomic has run
@@ -7536,6 +14402,34 @@ pub mod processor {
                     }
                 }
 
+                // Dust sweep: keepers name sub-floor positions as candidates
+                // (touch-only is enough); the first DUST_SWEEP_BUDGET are
+                // closed against the sweep LP, or the backstop vault when
+                // the sweep LP cannot take them.
+                let mut dust_takers: [Option<u16>; 2] = [None, None];
+                if progress_was_crank && !partial_catchup && dust.min_position_abs != 0 {
+                    let mut swept = 0usize;
+                    for &(idx, _) in candidates.iter() {
+                        if swept >= crate::constants::DUST_SWEEP_BUDGET {
+                            break;
+                        }
+                        if let Some(lp_idx) = sweep_dust_position_if_eligible(
+                            engine,
+                            &config,
+                            &dust,
+                            backstop_vault,
+                            idx,
+                            crank_slot,
+                            crank_price,
+                            funding_rate_e9_pre,
+                        )? {
+                            swept += 1;
+                            let slot = usize::from(lp_idx != dust.sweep_lp_idx);
+                            dust_takers[slot] = Some(lp_idx);
+                        }
+                    }
+                }
+
                 // Public ReclaimEmptyAccount is retired. Keepers that want
                 // targeted empty-account cleanup submit the account as a
                 // touch-only candidate; after the engine's account-touching
@@ -7551,7 +14445,6 @@ pub mod processor {
                 // Copy stats and drop engine mutable borrow.
                 // Use the actual crank outcome so observability/telemetry
                 // reflects real liquidations, not a hard-coded zero.
-                #[cfg(feature = "cu-audit")]
                 let liqs = match progress_outcome {
                     percolator::PermissionlessProgressOutcome::Cranked(outcome) => {
                         outcome.num_liquidations as u64
@@ -7579,13 +14472,53 @@ pub mod processor {
                     config
                 };
                 state::write_config(&mut data, &config_to_write);
+                advance_epoch(&mut data, clock.slot);
+                {
+                    let mut guard = state::read_crank_guard(&data);
+                    guard.last_crank_slot = clock.slot;
+                    state::write_crank_guard(&mut data, &guard);
+                }
+                if progress_was_crank && !partial_catchup && !engine_resolved_after_progress {
+                    record_history_sample(&mut data, clock.slot, &config_to_write)?;
+                    for &(idx, _) in candidates.iter() {
+                        refresh_lp_utilization(&mut data, idx, crank_price)?;
+                    }
+                }
+                if liq_window.active() && !engine_resolved_after_progress {
+                    update_liquidation_flags(&mut data, &combined, clock.slot, crank_price)?;
+                }
+                if progress_was_crank && !engine_resolved_after_progress {
+                    let touched = combined
+                        .iter()
+                        .chain(candidates.iter())
+                        .map(|&(idx, _)| idx)
+                        .chain(dust_takers.into_iter().flatten());
+                    record_crank_position_changes(&mut data, touched, crank_price)?;
+                }
+                for lp_idx in dust_takers.into_iter().flatten() {
+                    state::set_position_bit(&mut data, lp_idx, true);
+                    state::set_watched(&mut data, lp_idx, true);
+                }
+                if progress_was_crank && !partial_catchup && !engine_resolved_after_progress {
+                    crank_liq_watch(&mut data, &config_to_write, clock.slot, crank_price)?;
+                }
+                if backstop_paid > 0 {
+                    let mut b = state::read_backstop(&data);
+                    b.rewards_paid = b.rewards_paid.saturating_add(backstop_paid);
+                    state::write_backstop(&mut data, &b);
+                }
 
                 // ── RiskBuffer maintenance (engine borrow dropped) ──
-                {
-                    let mut buf = state::read_risk_buffer(&data);
-                    let engine = zc::engine_ref(&data)?;
+                let (scanned, next_cursor) = 'risk: {
+                    use crate::constants::{
+                        POSITION_BITMAP_LEN, POSITION_BITMAP_OFF, RISK_BUF_LEN, RISK_BUF_OFF,
+                    };
+                    let mut regions = zc::split_mut(&mut data)?;
+                    let mut buf =
+                        state::risk_buffer_from_bytes(regions.region(RISK_BUF_OFF, RISK_BUF_LEN));
+                    let engine = &*regions.engine;
                     if engine.market_mode == percolator::MarketMode::Resolved {
-                        return Ok(());
+                        break 'risk (0, buf.scan_cursor);
                     }
 
                     // Phase A: scrub dead entries
@@ -7620,11 +14553,13 @@ pub mod processor {
                         sol_log_compute_units();
                     }
 
-                    // Phase C: progressive discovery scan over the used-account
-                    // bitmap. The budget is spent on live slots, not empty
-                    // storage indices; sparse markets can therefore refill the
-                    // risk buffer in one crank instead of waiting for a dense
-                    // cursor window to walk across empty slots.
+                    // Phase C: progressive discovery scan over used accounts
+                    // with the open-position bit set. The budget is spent on
+                    // accounts that can carry risk, not empty storage indices
+                    // or flat accounts; sparse markets can therefore refill
+                    // the risk buffer in one crank instead of waiting for a
+                    // dense cursor window to walk across empty slots. Stale
+                    // bits found flat are cleared below.
                     let scan_mod = engine.params.max_accounts as usize;
                     let scan_mod = if scan_mod == 0 || scan_mod > percolator::MAX_ACCOUNTS {
                         percolator::MAX_ACCOUNTS
@@ -7632,6 +14567,9 @@ pub mod processor {
                         scan_mod
                     };
                     let scan_start = (buf.scan_cursor as usize) % scan_mod;
+                    let positions = regions.region(POSITION_BITMAP_OFF, POSITION_BITMAP_LEN);
+                    let mut flat_seen = [0u16; crate::constants::RISK_SCAN_WINDOW];
+                    let mut flat_count = 0usize;
                     let mut used_seen = 0usize;
                     let mut next_cursor = scan_start;
                     let mut completed_cycle = true;
@@ -7671,7 +14609,10 @@ pub mod processor {
                             } else {
                                 (1u64 << upper_bit_exclusive) - 1
                             };
-                            let mut bits = engine.used[word_cursor] & lower_mask & upper_mask;
+                            let mut bits = engine.used[word_cursor]
+                                & state::position_word(positions, word_cursor)
+                                & lower_mask
+                                & upper_mask;
 
                             while bits != 0 {
                                 let bit = bits.trailing_zeros() as usize;
@@ -7684,6 +14625,8 @@ pub mod processor {
                                 let eff = effective_pos_q_checked(engine, idx)?;
                                 if eff == 0 {
                                     buf.remove(idx as u16);
+                                    flat_seen[flat_count] = idx as u16;
+                                    flat_count += 1;
                                 } else {
                                     let notional = risk_notional_ceil(eff, crank_price);
                                     buf.upsert(idx as u16, notional);
@@ -7731,34 +14674,72 @@ pub mod processor {
                         candidate_ingests += 1;
                     }
 
-                    state::write_risk_buffer(&mut data, &buf);
+                    regions
+                        .region_mut(RISK_BUF_OFF, RISK_BUF_LEN)
+                        .copy_from_slice(bytemuck::bytes_of(&buf));
+                    let positions = regions.region_mut(POSITION_BITMAP_OFF, POSITION_BITMAP_LEN);
+                    for &idx in &flat_seen[..flat_count] {
+                        positions[idx as usize / 8] &= !(1 << (idx % 8));
+                    }
                     #[cfg(feature = "cu-audit")]
                     {
                         msg!("CU_CHECKPOINT: risk_buffer_phase_d_end");
                         sol_log_compute_units();
                     }
-                }
+                    (used_seen, buf.scan_cursor)
+                };
 
                 #[cfg(feature = "cu-audit")]
                 {
                     msg!("CRANK_STATS");
                     sol_log_64(0xC8A4C, liqs, percolator::MAX_ACCOUNTS as u64, ins_low, 0);
                 }
+
+                let mut flags = 0u8;
+                if progress_was_crank {
+                    flags |= crate::ix::CRANK_SUMMARY_CRANKED;
+                }
+                if partial_catchup {
+                    flags |= crate::ix::CRANK_SUMMARY_PARTIAL_CATCHUP;
+                }
+                let summary = crate::ix::CrankSummaryEvent {
+                    slot: clock.slot,
+                    crank_slot,
+                    funding_rate_e9: funding_rate_e9_pre,
+                    fees_accrued,
+                    candidates: combined.len() as u16,
+                    scanned: scanned as u16,
+                    liquidations: liqs.min(u16::MAX as u64) as u16,
+                    next_cursor,
+                    flags,
+                };
+                solana_program::log::sol_log_data(&[&summary.encode()]);
             }
             Instruction::TradeNoCpi {
                 lp_idx,
                 user_idx,
                 size,
                 exec_price_e6,
+                flags,
+                client_order_id,
+                client_tag,
             } => {
+                use accounts::{AccountSpec, Owner};
+                // [user, lp, slab, clock], then the oracle accounts.
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("user signer").signer(),
+                    AccountSpec::new("LP owner signer").signer(),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+                ];
                 accounts::expect_len_min(accounts, 5)?;
+                accounts::check_account_head(program_id, accounts, SPECS)?;
                 let a_user = &accounts[0];
                 let a_lp = &accounts[1];
                 let a_slab = &accounts[2];
 
-                accounts::expect_signer(a_user)?;
-                accounts::expect_signer(a_lp)?;
-                accounts::expect_writable(a_slab)?;
                 if size == 0 || size == i128::MIN {
                     return Err(ProgramError::InvalidInstructionData);
                 }
@@ -7769,11 +14750,15 @@ pub mod processor {
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+                reject_withdraw_only(&data)?;
 
                 // Block trading when market is resolved
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
+                if is_duplicate_order(&data, user_idx, client_order_id) {
+                    return Ok(());
+                }
 
                 let mut config = state::read_config(&data);
                 expect_len_with_oracles(accounts, 5, &config)?;
@@ -7812,6 +14797,13 @@ pub mod processor {
                 };
                 state::write_config(&mut data, &config);
 
+                let guards = TradeGuards::load(
+                    &mut data,
+                    user_idx,
+                    clock.slot,
+                    price,
+                    config.last_good_oracle_slot,
+                );
                 let engine = zc::engine_mut(&mut data)?;
                 let exec_price = if exec_price_e6 == 0 {
                     price
@@ -7846,169 +14838,57 @@ pub mod processor {
                 // price, then either accepts the agreed execution price under
                 // its health rules or rejects atomically. Other extraction
                 // paths remain target-lag gated.
-                ensure_market_accrued_to_now(engine, clock.slot, price, funding_rate_e9)?;
-
-                // Make both counterparties authoritative, then sync recurring
-                // fees before the trade's fee-current margin checks. This
-                // preserves loss-senior ordering while still charging nonflat
-                // current accounts.
-                settle_pair_then_sync_fee_current(
+                let size = prepare_bilateral_fill(
                     engine,
                     &config,
-                    user_idx,
                     lp_idx,
+                    user_idx,
+                    size,
+                    flags,
                     clock.slot,
                     price,
                     funding_rate_e9,
-                    engine.params.h_min,
-                    engine.params.h_max,
-                    Some(engine.params.maintenance_margin_bps as u128),
-                )?;
-
-                // Snapshot insurance fund balance for fee-weighted EWMA after
-                // recurring fees. The delta after execute_trade is bounded to
-                // trade-fee impact below.
-                // NOTE: If loss absorption occurs during the same trade (spec §5.4),
-                // delta undercounts the actual fee. This is the conservative direction:
-                // mark is stickier during volatile loss-absorption events, never
-                // more manipulable. A future engine API could expose fee_paid directly.
-                let current_trade_fee_bps = trade_fee_bps_for_execution(
-                    &config, engine, clock.slot, price, exec_price, size,
                 )?;
-                let current_fee_paid_cap =
-                    current_trade_fee_paid_cap(size, exec_price, current_trade_fee_bps)?;
-                let ins_before = engine.insurance_fund.balance.get();
 
                 #[cfg(feature = "cu-audit")]
                 {
                     msg!("CU_CHECKPOINT: trade_nocpi_execute_start");
                     sol_log_compute_units();
                 }
-                execute_trade_with_matcher(
-                    engine,
-                    &CpiMatcher {
+                // The bilateral matcher shim ignores lp_account_id.
+                let trade_result = settle_fill(
+                    &mut data,
+                    &mut config,
+                    &guards,
+                    &Fill {
+                        lp_idx,
+                        user_idx,
+                        size,
                         exec_price,
-                        exec_size: size,
+                        lp_account_id: 0,
+                        client_order_id,
                     },
-                    lp_idx,
-                    user_idx,
                     clock.slot,
                     price,
-                    size,
                     funding_rate_e9,
-                    0, // bilateral matcher shim ignores lp_account_id
-                    current_trade_fee_bps,
-                    0,
-                )
-                .map_err(map_risk_error)?;
-
-                // Update mark EWMA from the agreed execution price. The mark
-                // impact is clamped independently from execution price, so
-                // bilateral NoCpi and matcher Cpi trades share the same
-                // price-flexibility semantics.
-                // Per-slot price-move cap is init-immutable (engine RiskParams).
-                let max_change_bps = engine.params.max_price_move_bps_per_slot;
-                if max_change_bps > 0 && trade_flow_mark_observation_enabled(&config, clock.slot) {
-                    let clamped_price = oracle::clamp_oracle_price(
-                        crate::policy::mark_ewma_clamp_base(config.last_effective_price_e6),
-                        exec_price,
-                        max_change_bps,
-                    );
-                    // fee_paid = actual fee collected into insurance (post - pre).
-                    // This is exact: no overestimate from pre-trade capital snapshot.
-                    let fee_paid_nocpi = if config.mark_min_fee > 0 {
-                        let ins_after = engine.insurance_fund.balance.get();
-                        let delta = ins_after
-                            .saturating_sub(ins_before)
-                            .min(current_fee_paid_cap);
-                        core::cmp::min(delta, u64::MAX as u128) as u64
-                    } else {
-                        0u64
-                    };
-                    let old_ewma = config.mark_ewma_e6;
-                    // N4 fix: seed EWMA at oracle price on first trade (not exec price).
-                    // Prevents attacker from imprinting a biased mark on the first fill.
-                    let ewma_price = if old_ewma == 0 && config.last_effective_price_e6 > 0 {
-                        config.last_effective_price_e6
-                    } else {
-                        clamped_price
-                    };
-                    config.mark_ewma_e6 = crate::policy::ewma_update(
-                        old_ewma,
-                        ewma_price,
-                        config.mark_ewma_halflife_slots,
-                        config.mark_ewma_last_slot,
-                        clock.slot,
-                        fee_paid_nocpi,
-                        config.mark_min_fee,
-                    );
-                    let trade_mark_moved_nocpi = config.mark_ewma_e6 != old_ewma;
-                    // Only full-weight observations that actually move the
-                    // EWMA advance the EWMA clock. Sub-threshold trades can
-                    // still nudge the EWMA value via partial alpha, but their
-                    // clock bump would make the clock a liveness/fee signal
-                    // attackers can cheaply refresh. Same-price TradeNoCpi
-                    // fills are especially important in hybrid after-hours
-                    // mode: they execute at the fallback mark, so advancing
-                    // the clock without mark movement would cheapen the next
-                    // dynamic-fee TradeCpi by shrinking its EWMA elapsed time.
-                    let full_weight_observation_nocpi =
-                        config.mark_min_fee == 0 || fee_paid_nocpi >= config.mark_min_fee;
-                    if full_weight_observation_nocpi && trade_mark_moved_nocpi {
-                        config.mark_ewma_last_slot = clock.slot;
-                    }
-                    if is_hyperp {
-                        config.hyperp_mark_e6 = oracle::clamp_oracle_price(
-                            config.last_effective_price_e6,
-                            exec_price,
-                            max_change_bps,
-                        );
-                        if full_weight_observation_nocpi && trade_mark_moved_nocpi {
-                            config.last_mark_push_slot = clock.slot as u128;
-                        }
-                    }
-                    // NOTE: do NOT stamp funding rate here — execute_trade_not_atomic
-                    // handles it via the funding_rate parameter (§5.5 anti-retroactivity).
-                }
-
-                // v12.17: funding rate is passed to accrue_market_to, not stored directly.
-                // The next accrual (crank/trade/settle) will use the updated mark EWMA.
-
-                // Collect post-trade positions for risk buffer
-                let user_eff_nocpi = effective_pos_q_checked(engine, user_idx as usize)?;
-                let lp_eff_nocpi = effective_pos_q_checked(engine, lp_idx as usize)?;
-                if !state::is_oracle_initialized(&data) {
-                    state::set_oracle_initialized(&mut data);
-                }
-
-                // Write updated config (mark_ewma changed)
-                state::write_config(&mut data, &config);
-
-                // Update risk buffer
-                {
-                    let mut buf = state::read_risk_buffer(&data);
-                    for &(idx, eff) in &[(user_idx, user_eff_nocpi), (lp_idx, lp_eff_nocpi)] {
-                        if eff == 0 {
-                            buf.remove(idx);
-                        } else {
-                            let notional = risk_notional_ceil(eff, price);
-                            buf.upsert(idx, notional);
-                        }
-                    }
-                    state::write_risk_buffer(&mut data, &buf);
-                }
+                )?;
 
                 #[cfg(feature = "cu-audit")]
                 {
                     msg!("CU_CHECKPOINT: trade_nocpi_execute_end");
                     sol_log_compute_units();
                 }
+                log_client_tag(6, user_idx, &client_tag);
+                solana_program::program::set_return_data(&trade_result.encode());
             }
             Instruction::TradeCpi {
                 lp_idx,
                 user_idx,
                 size,
                 limit_price_e6,
+                flags,
+                client_order_id,
+                client_tag,
             } => {
                 // Account layout:
                 //   [0]  user (signer)
@@ -8041,16 +14921,31 @@ pub mod processor {
                 //      (preserving signer/writable flags from the outer
                 //      transaction), so the matcher cannot use them to
                 //      gain privileges the caller didn't already grant.
+                //      The one exception is the slab, which is always
+                //      forwarded read-only.
                 // Typical uses: pyth/chainlink feeds for matcher-side
                 // pricing, on-chain whitelist PDAs, cross-program
                 // inventory state, etc.
+                //
+                // Only the head ahead of the oracle accounts sits at fixed
+                // indices; the matcher accounts are located once the
+                // oracle count is known.
+                use accounts::{AccountSpec, Owner};
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("user signer").signer(),
+                    AccountSpec::new("LP owner"),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+                ];
                 accounts::expect_len_min(accounts, 8)?;
+                accounts::check_account_head(program_id, accounts, SPECS)?;
                 let a_user = &accounts[0];
                 let a_lp_owner = &accounts[1];
                 let a_slab = &accounts[2];
                 let a_clock = &accounts[3];
 
-                accounts::expect_signer(a_user)?;
                 // Reject zero-size requests at entry — zero-fill path should only
                 // be reached via matcher returning exec_size == 0 on a nonzero request.
                 // Also reject i128::MIN before oracle/CPI work; it has no positive
@@ -8067,12 +14962,13 @@ pub mod processor {
                 // Note: a_lp_owner does NOT need to be a signer for TradeCpi.
                 // LP owner delegated trade authorization to the matcher program.
                 // The matcher CPI (via LP PDA invoke_signed) validates the trade.
-                accounts::expect_writable(a_slab)?;
 
                 // Phase 3 & 4: Read engine state, generate nonce, validate matcher identity
                 // Note: Use immutable borrow for reading to avoid ExternalAccountDataModified
                 // Nonce write is deferred until after execute_trade
+                let reduce_only = flags & crate::constants::TRADE_FLAG_REDUCE_ONLY != 0;
                 let (
+                    size,
                     lp_account_id,
                     mut config,
                     config_pre_oracle,
@@ -8084,10 +14980,12 @@ pub mod processor {
                     engine_max_accrual_dt_slots,
                     engine_cap_bps,
                     engine_oi_any,
+                    engine_initial_margin_bps,
                 ) = {
                     let data = a_slab.try_borrow_data()?;
                     slab_guard(program_id, a_slab, &*data)?;
                     require_initialized(&*data)?;
+                    reject_withdraw_only(&*data)?;
 
                     // Block trading when market is resolved
                     if zc::engine_ref(&*data)?.market_mode == percolator::MarketMode::Resolved {
@@ -8097,7 +14995,7 @@ pub mod processor {
                     // Prevents malicious matcher from re-entering TradeCpi during
                     // its callback, which would execute two trades for one user signature.
                     if state::is_cpi_in_progress(&*data) {
-                        return Err(ProgramError::InvalidAccountData);
+                        return Err(fail(PercolatorError::CpiReentrancy, &[]));
                     }
 
                     let config = state::read_config(&*data);
@@ -8133,6 +15031,10 @@ pub mod processor {
                     if !crate::policy::owner_ok(l_owner, a_lp_owner.key.to_bytes()) {
                         return Err(PercolatorError::EngineUnauthorized.into());
                     }
+                    // Retried submission: no matcher CPI, no state change.
+                    if is_duplicate_order(&*data, user_idx, client_order_id) {
+                        return Ok(());
+                    }
 
                     let lp_acc = &engine.accounts[lp_idx as usize];
                     // Per-materialization instance ID from generation table.
@@ -8143,7 +15045,18 @@ pub mod processor {
                     if lp_instance_id == 0 {
                         return Err(PercolatorError::EngineAccountNotFound.into());
                     }
+                    // Reduce-only: clamp the request BEFORE the matcher
+                    // quotes it, so the matcher prices exactly the size
+                    // the engine may fill. Re-verified after settlement.
+                    let size = if reduce_only {
+                        let user_pos = effective_pos_q_checked(engine, user_idx as usize)?;
+                        crate::policy::reduce_only_clamp(user_pos, size)
+                            .ok_or(PercolatorError::ReduceOnlyViolation)?
+                    } else {
+                        size
+                    };
                     (
+                        size,
                         lp_instance_id,
                         config,
                         config_pre_oracle,
@@ -8155,6 +15068,7 @@ pub mod processor {
                         engine.params.max_accrual_dt_slots,
                         engine.params.max_price_move_bps_per_slot,
                         engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0,
+                        engine.params.initial_margin_bps,
                     )
                 };
 
@@ -8206,7 +15120,31 @@ pub mod processor {
                     a_matcher_prog.key.to_bytes(),
                     a_matcher_ctx.key.to_bytes(),
                 ) {
-                    return Err(PercolatorError::EngineInvalidMatchingEngine.into());
+                    return Err(fail(
+                        PercolatorError::MatcherContextMismatch,
+                        &[lp_idx as u64],
+                    ));
+                }
+                // The registered context must also be bound to this LP's
+                // PDA, i.e. to this slab and this LP slot.
+                check_matcher_ctx_bound(a_matcher_ctx, &expected_lp_pda, lp_idx)?;
+                let caps =
+                    crate::matcher_abi::read_matcher_caps(&a_matcher_ctx.try_borrow_data()?)?;
+                if !crate::policy::matcher_request_ok(
+                    caps.flags,
+                    caps.max_fill_abs,
+                    size.unsigned_abs(),
+                    flags & crate::constants::TRADE_FLAG_FILL_OR_KILL != 0,
+                ) {
+                    // ctx: |requested|, declared max fill, capability flags
+                    return Err(fail(
+                        PercolatorError::MatcherCapabilityExceeded,
+                        &[
+                            clamp_u64(size.unsigned_abs()),
+                            clamp_u64(caps.max_fill_abs),
+                            caps.flags as u64,
+                        ],
+                    ));
                 }
 
                 let clock = Clock::from_account_info(a_clock)?;
@@ -8272,10 +15210,11 @@ pub mod processor {
                 metas.push(AccountMeta::new_readonly(*a_lp_pda.key, true));
                 metas.push(AccountMeta::new(*a_matcher_ctx.key, false));
                 for tail_ai in a_tail.iter() {
+                    // A matcher may read the slab but never gets it writable.
                     metas.push(AccountMeta {
                         pubkey: *tail_ai.key,
                         is_signer: tail_ai.is_signer,
-                        is_writable: tail_ai.is_writable,
+                        is_writable: tail_ai.is_writable && tail_ai.key != a_slab.key,
                     });
                 }
 
@@ -8305,7 +15244,10 @@ pub mod processor {
                     seeds,
                 )?;
 
-                // Clear reentrancy guard after CPI returns.
+                // Clear reentrancy guard after CPI returns. The matcher
+                // cannot have written the slab: this program owns it, so the
+                // runtime rejects any other program's write, and the CPI
+                // forwards it read-only besides.
                 {
                     let mut data = state::slab_data_mut(a_slab)?;
                     state::clear_cpi_in_progress(&mut data);
@@ -8328,12 +15270,59 @@ pub mod processor {
                     return Err(ProgramError::InvalidAccountData);
                 }
                 drop(ctx_data);
+                if ret.exec_size != 0
+                    && !crate::policy::exec_price_within_margin(
+                        ret.exec_price_e6,
+                        price,
+                        engine_initial_margin_bps,
+                    )
+                {
+                    // ctx: exec price, oracle price, initial margin bps
+                    return Err(fail(
+                        PercolatorError::MatcherFillOutOfBounds,
+                        &[ret.exec_price_e6, price, engine_initial_margin_bps],
+                    ));
+                }
+                if !crate::policy::matcher_fill_within_caps(
+                    caps.flags,
+                    caps.max_fill_abs,
+                    ret.exec_size.unsigned_abs(),
+                    size.unsigned_abs(),
+                ) {
+                    // ctx: |filled|, declared max fill, capability flags
+                    return Err(fail(
+                        PercolatorError::MatcherCapabilityExceeded,
+                        &[
+                            clamp_u64(ret.exec_size.unsigned_abs()),
+                            clamp_u64(caps.max_fill_abs),
+                            caps.flags as u64,
+                        ],
+                    ));
+                }
 
-                // User-side slippage protection.
-                // Normalize limit to engine-space (same invert+scale as exec_price).
-                // For inverted markets, inversion is order-reversing: a "better"
-                // raw buy price maps to a larger engine price, so inequalities flip.
-                if limit_price_e6 != 0 && ret.exec_size != 0 {
+                // User-side slippage protection, bps form: bound the fill
+                // against the oracle price this instruction read (engine
+                // space on both sides, so no invert/scale handling).
+                if flags & crate::constants::TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS != 0 {
+                    if ret.exec_size != 0
+                        && !crate::policy::slippage_bps_ok(
+                            ret.exec_price_e6,
+                            price,
+                            ret.exec_size,
+                            limit_price_e6,
+                        )
+                    {
+                        // ctx: exec price, oracle price, max slippage bps
+                        return Err(fail(
+                            PercolatorError::SlippageExceeded,
+                            &[ret.exec_price_e6, price, limit_price_e6],
+                        ));
+                    }
+                } else if limit_price_e6 != 0 && ret.exec_size != 0 {
+                    // Raw limit-price form.
+                    // Normalize limit to engine-space (same invert+scale as exec_price).
+                    // For inverted markets, inversion is order-reversing: a "better"
+                    // raw buy price maps to a larger engine price, so inequalities flip.
                     let limit_eng = crate::policy::to_engine_price(
                         limit_price_e6,
                         config.invert,
@@ -8351,7 +15340,11 @@ pub mod processor {
                             ret.exec_price_e6 > limit_eng
                         };
                         if bad {
-                            return Err(ProgramError::InvalidAccountData);
+                            // ctx: exec price, engine-space limit
+                            return Err(fail(
+                                PercolatorError::SlippageExceeded,
+                                &[ret.exec_price_e6, limit_eng],
+                            ));
                         }
                     } else {
                         // Selling: raw user wants exec >= limit (receive no less)
@@ -8363,11 +15356,28 @@ pub mod processor {
                             ret.exec_price_e6 < limit_eng
                         };
                         if bad {
-                            return Err(ProgramError::InvalidAccountData);
+                            // ctx: exec price, engine-space limit
+                            return Err(fail(
+                                PercolatorError::SlippageExceeded,
+                                &[ret.exec_price_e6, limit_eng],
+                            ));
                         }
                     }
                 }
 
+                // Fill-or-kill: reject anything short of the full request,
+                // including the zero-fill no-op below.
+                if flags & crate::constants::TRADE_FLAG_FILL_OR_KILL != 0 && ret.exec_size != size {
+                    // ctx: |filled|, |requested|
+                    return Err(fail(
+                        PercolatorError::FillOrKillNotFilled,
+                        &[
+                            clamp_u64(ret.exec_size.unsigned_abs()),
+                            clamp_u64(size.unsigned_abs()),
+                        ],
+                    ));
+                }
+
                 // Zero-fill: ABI-valid no-op when matcher returns exec_size == 0
                 // with FLAG_PARTIAL_OK. The engine's trade path is skipped
                 // (size_q == 0 would be rejected), but we DO advance the
@@ -8440,6 +15450,15 @@ pub mod processor {
                     restored.last_hyperp_index_slot = config.last_hyperp_index_slot;
                     state::write_config(&mut data, &restored);
                     state::write_req_nonce(&mut data, req_id);
+                    record_order_id(&mut data, user_idx, client_order_id);
+                    log_client_tag(10, user_idx, &client_tag);
+                    solana_program::program::set_return_data(
+                        &crate::ix::TradeResult {
+                            oracle_price_e6: price,
+                            ..Default::default()
+                        }
+                        .encode(),
+                    );
                     return Ok(());
                 }
 
@@ -8450,17 +15469,24 @@ pub mod processor {
                     return Err(PercolatorError::OracleInvalid.into());
                 }
 
-                {
+                let trade_result = {
                     let mut data = state::slab_data_mut(a_slab)?;
+                    let guards = TradeGuards::load(
+                        &mut data,
+                        user_idx,
+                        clock.slot,
+                        price,
+                        config.last_good_oracle_slot,
+                    );
                     let engine = zc::engine_mut(&mut data)?;
 
                     // Trades are a liveness path for the two consenting
                     // counterparties. The engine accrues to the current
                     // effective price, then either accepts the matcher price
-                    // under its health rules or rejects atomically. The
-                    // wrapper no longer applies an oracle-relative execution
-                    // band: user limits and matcher policy express consent,
-                    // while mark impact is clamped separately below.
+                    // under its health rules or rejects atomically. The only
+                    // oracle-relative band is the initial-margin bound on
+                    // the matcher's price, checked above; TradeNoCpi has
+                    // none. Mark impact is clamped separately in settle_fill.
                     ensure_market_accrued_to_now(engine, clock.slot, price, funding_rate_e9_pre)?;
 
                     // Same loss-senior fee-current policy as TradeNoCpi:
@@ -8483,184 +15509,54 @@ pub mod processor {
                     if trade_size.unsigned_abs() > percolator::MAX_TRADE_SIZE_Q {
                         return Err(ProgramError::InvalidInstructionData);
                     }
-                    let current_trade_fee_bps = trade_fee_bps_for_execution(
-                        &config, engine, clock.slot, price, exec_price, trade_size,
-                    )?;
-                    let current_fee_paid_cap =
-                        current_trade_fee_paid_cap(trade_size, exec_price, current_trade_fee_bps)?;
-
-                    // Snapshot insurance for fee-weighted EWMA (delta approach).
-                    // Recurring fees were synced before this snapshot; the
-                    // delta below is bounded to trade-fee impact.
-                    // NOTE: Conservative undercount during volatile
-                    // loss-absorption events (see TradeNoCpi comment).
-                    let ins_before_cpi = engine.insurance_fund.balance.get();
+                    // Settlement can shrink the position (ADL); the fill
+                    // must still be a pure reduction of what is left.
+                    if reduce_only {
+                        let user_pos = effective_pos_q_checked(engine, user_idx as usize)?;
+                        if crate::policy::reduce_only_clamp(user_pos, trade_size)
+                            != Some(trade_size)
+                        {
+                            return Err(PercolatorError::ReduceOnlyViolation.into());
+                        }
+                    }
 
                     #[cfg(feature = "cu-audit")]
                     {
                         msg!("CU_CHECKPOINT: trade_cpi_execute_start");
                         sol_log_compute_units();
                     }
-                    let matcher = CpiMatcher {
-                        exec_price,
-                        exec_size: trade_size,
-                    };
-                    // Use pre-oracle-read funding rate (anti-retroactivity §5.5).
-                    execute_trade_with_matcher(
-                        engine,
-                        &matcher,
-                        lp_idx,
-                        user_idx,
-                        clock.slot,
-                        price,
-                        trade_size,
-                        funding_rate_e9_pre,
-                        lp_account_id,
-                        current_trade_fee_bps,
-                        0,
-                    )
-                    .map_err(map_risk_error)?;
-                    #[cfg(feature = "cu-audit")]
-                    {
-                        msg!("CU_CHECKPOINT: trade_cpi_execute_end");
-                        sol_log_compute_units();
-                    }
-                    // Capture pre-trade EWMA so both the EWMA-clock refresh
-                    // (inside the cap-active branch) and the Hyperp
-                    // liveness refresh (after the block) can check
-                    // whether the mark actually moved.
-                    let old_ewma_cpi = config.mark_ewma_e6;
-                    let mut trade_mark_moved_cpi = false;
-                    // Update trade-derived mark EWMA when this market mode
-                    // accepts trade flow as a mark observation. Hybrid
-                    // regular-hours reads are excluded: the external oracle
-                    // owns the mark baseline until stale fallback is active.
-                    // Per-slot price-move cap is init-immutable (engine RiskParams).
-                    let max_change_bps_cpi = engine.params.max_price_move_bps_per_slot;
-                    if max_change_bps_cpi > 0
-                        && trade_flow_mark_observation_enabled(&config, clock.slot)
-                    {
-                        let clamped_exec = oracle::clamp_oracle_price(
-                            crate::policy::mark_ewma_clamp_base(config.last_effective_price_e6),
-                            ret.exec_price_e6,
-                            max_change_bps_cpi,
-                        );
-                        // fee_paid = actual fee collected into insurance (post - pre).
-                        let fee_paid_cpi = if config.mark_min_fee > 0 {
-                            let ins_after_cpi = engine.insurance_fund.balance.get();
-                            let delta = ins_after_cpi
-                                .saturating_sub(ins_before_cpi)
-                                .min(current_fee_paid_cap);
-                            core::cmp::min(delta, u64::MAX as u128) as u64
-                        } else {
-                            0u64
-                        };
-                        // N4 fix: seed at oracle on first trade
-                        let ewma_price_cpi =
-                            if old_ewma_cpi == 0 && config.last_effective_price_e6 > 0 {
-                                config.last_effective_price_e6
-                            } else {
-                                clamped_exec
-                            };
-                        config.mark_ewma_e6 = crate::policy::ewma_update(
-                            old_ewma_cpi,
-                            ewma_price_cpi,
-                            config.mark_ewma_halflife_slots,
-                            config.mark_ewma_last_slot,
-                            clock.slot,
-                            fee_paid_cpi,
-                            config.mark_min_fee,
-                        );
-                        trade_mark_moved_cpi = config.mark_ewma_e6 != old_ewma_cpi;
-                        // Only full-weight observations that actually move
-                        // the EWMA advance its clock. Sub-threshold fills
-                        // and same-price wash trades can mutate/pay as
-                        // configured, but they do not reset the EWMA clock:
-                        // otherwise a controlled matcher can pin future
-                        // alpha by repeatedly trading at the old mark for
-                        // only the base fee.
-                        let full_weight_observation =
-                            config.mark_min_fee == 0 || fee_paid_cpi >= config.mark_min_fee;
-                        if full_weight_observation && trade_mark_moved_cpi {
-                            config.mark_ewma_last_slot = clock.slot;
-                        }
-                        // NOTE: do NOT stamp funding rate here — execute_trade_not_atomic
-                        // handles it via the funding_rate parameter (§5.5 anti-retroactivity).
-                    }
-
-                    // Hyperp: also update hyperp_mark_e6.
-                    // Hyperp-liveness clock (last_mark_push_slot) refreshes
-                    // ONLY on full-weight observations — sub-threshold
-                    // dust-wash trades must not keep a dead market
-                    // artificially alive. This is the ONLY Hyperp
-                    // liveness signal: permissionless_stale_matured for
-                    // Hyperp uses last_mark_push_slot, not
-                    // max(mark_ewma_last_slot, last_mark_push_slot),
-                    // so partial-fee EWMA-math clock advances don't
-                    // accidentally extend market life.
-                    if is_hyperp {
-                        config.hyperp_mark_e6 = oracle::clamp_oracle_price(
-                            config.last_effective_price_e6,
-                            ret.exec_price_e6,
-                            max_change_bps_cpi,
-                        );
-                        // Full-weight observation check: recompute here
-                        // because the earlier `full_weight_observation`
-                        // binding is in the cap>0 branch's scope. Hyperp
-                        // liveness also requires actual EWMA movement, so
-                        // same-price wash trades cannot keep a pinned mark
-                        // live indefinitely.
-                        let fee_paid_hyperp = if config.mark_min_fee > 0 {
-                            let ins_after_cpi = engine.insurance_fund.balance.get();
-                            let delta = ins_after_cpi
-                                .saturating_sub(ins_before_cpi)
-                                .min(current_fee_paid_cap);
-                            core::cmp::min(delta, u64::MAX as u128) as u64
-                        } else {
-                            0u64
-                        };
-                        let full_weight =
-                            config.mark_min_fee == 0 || fee_paid_hyperp >= config.mark_min_fee;
-                        if full_weight && trade_mark_moved_cpi {
-                            config.last_mark_push_slot = clock.slot as u128;
-                        }
-                    }
-                }
-                // Engine borrow dropped.
-                // Collect post-trade positions for risk buffer (re-borrow as ref)
-                let (user_eff_cpi, lp_eff_cpi) = {
-                    let data = a_slab.try_borrow_data()?;
-                    let engine = zc::engine_ref(&data)?;
-                    (
-                        effective_pos_q_checked(engine, user_idx as usize)?,
-                        effective_pos_q_checked(engine, lp_idx as usize)?,
-                    )
-                };
-                // Write nonce + config + risk buffer + oracle flag.
-                {
-                    let mut data = state::slab_data_mut(a_slab)?;
-                    state::write_req_nonce(&mut data, req_id);
-                    state::write_config(&mut data, &config);
-                    if !state::is_oracle_initialized(&data) {
-                        state::set_oracle_initialized(&mut data);
-                    }
-                    // v12.17: funding rate passed to accrue_market_to, not stored directly.
-                    // Update risk buffer — use oracle price for notional ranking (H1/M9).
-                    // exec_price is gameable by a colluding matcher; oracle price is not.
-                    let mut buf = state::read_risk_buffer(&data);
-                    for &(idx, eff) in &[(user_idx, user_eff_cpi), (lp_idx, lp_eff_cpi)] {
-                        if eff == 0 {
-                            buf.remove(idx);
-                        } else {
-                            let notional = risk_notional_ceil(eff, price);
-                            buf.upsert(idx, notional);
-                        }
+                    // Use pre-oracle-read funding rate (anti-retroactivity §5.5).
+                    let trade_result = settle_fill(
+                        &mut data,
+                        &mut config,
+                        &guards,
+                        &Fill {
+                            lp_idx,
+                            user_idx,
+                            size: trade_size,
+                            exec_price,
+                            lp_account_id,
+                            client_order_id,
+                        },
+                        clock.slot,
+                        price,
+                        funding_rate_e9_pre,
+                    )?;
+                    #[cfg(feature = "cu-audit")]
+                    {
+                        msg!("CU_CHECKPOINT: trade_cpi_execute_end");
+                        sol_log_compute_units();
                     }
-                    state::write_risk_buffer(&mut data, &buf);
-                }
+                    // The nonce is consumed only by an executed fill.
+                    state::write_req_nonce(&mut data, req_id);
+                    trade_result
+                };
+                log_client_tag(10, user_idx, &client_tag);
+                solana_program::program::set_return_data(&trade_result.encode());
             }
-            Instruction::CloseAccount { user_idx } => {
+            Instruction::CloseAccount { user_idx, nonce } => {
                 accounts::expect_len_min(accounts, 8)?;
+                accounts::check_account_head(program_id, accounts, accounts::WITHDRAW_HEAD)?;
                 let a_user = &accounts[0];
                 let a_slab = &accounts[1];
                 let a_vault = &accounts[2];
@@ -8668,8 +15564,6 @@ pub mod processor {
                 let a_pda = &accounts[4];
                 let a_token = &accounts[5];
 
-                accounts::expect_signer(a_user)?;
-                accounts::expect_writable(a_slab)?;
                 verify_token_program(a_token)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
@@ -8737,6 +15631,7 @@ pub mod processor {
                     px
                 };
 
+                consume_user_nonce(&mut data, user_idx, nonce)?;
                 let engine = zc::engine_mut(&mut data)?;
 
                 check_idx(engine, user_idx)?;
@@ -8813,269 +15708,605 @@ pub mod processor {
                     sol_log_compute_units();
                 }
 
-                let amt_units_u64: u64 = amt_units
-                    .try_into()
-                    .map_err(|_| PercolatorError::EngineOverflow)?;
+                let amt_units_u64: u64 = amt_units
+                    .try_into()
+                    .map_err(|_| PercolatorError::EngineOverflow)?;
+
+                // Remove from risk buffer (drop engine borrow first to release data)
+                // Live close processes a real oracle price through the engine
+                if !resolved && !state::is_oracle_initialized(&data) {
+                    state::set_oracle_initialized(&mut data);
+                }
+                {
+                    let mut buf = state::read_risk_buffer(&data);
+                    buf.remove(user_idx);
+                    state::write_risk_buffer(&mut data, &buf);
+                }
+
+                // Convert units to base tokens for payout (checked to prevent silent overflow)
+                let base_to_pay =
+                    crate::units::units_to_base_checked(amt_units_u64, config.unit_scale)
+                        .ok_or(PercolatorError::EngineOverflow)?;
+
+                if base_to_pay > 0 {
+                    verify_token_account(a_user_ata, a_user.key, &mint)?;
+
+                    let seed1: &[u8] = b"vault";
+                    let seed2: &[u8] = a_slab.key.as_ref();
+                    let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                    let seed3: &[u8] = &bump_arr;
+                    let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                    let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+
+                    collateral::withdraw(
+                        a_token,
+                        a_vault,
+                        a_user_ata,
+                        a_pda,
+                        base_to_pay,
+                        &signer_seeds,
+                    )?;
+                }
+            }
+            Instruction::TopUpInsurance { amount } => {
+                handle_top_up_insurance(program_id, accounts, amount, true)?;
+            }
+            Instruction::DonateInsurance { amount } => {
+                handle_top_up_insurance(program_id, accounts, amount, false)?;
+            }
+
+            Instruction::CloseSlab => {
+                accounts::expect_len(accounts, 6)?;
+                let a_dest = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_vault = &accounts[2];
+                let a_vault_auth = &accounts[3];
+                let a_dest_ata = &accounts[4];
+                let a_token = &accounts[5];
+
+                accounts::expect_signer(a_dest)?;
+                accounts::expect_writable(a_dest)?;
+                accounts::expect_writable(a_slab)?;
+                verify_token_program(a_token)?;
+
+                {
+                    let mut data = state::slab_data_mut(a_slab)?;
+                    slab_guard(program_id, a_slab, &data)?;
+                    require_initialized(&data)?;
+
+                    // Require resolved — enforce lifecycle ordering
+                    if !engine_is_resolved(zc::engine_ref(&data)?) {
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+
+                    // CloseSlab is gated by `admin`. Operators who burn
+                    // admin for rug-proofing trap the slab rent (~0.04 SOL),
+                    // which is the accepted cost of the fully admin-free
+                    // terminal state.
+                    require_admin_action(
+                        &mut data,
+                        a_slab.key,
+                        a_dest.key,
+                        accounts,
+                        instruction_data,
+                    )?;
+                    let config = state::read_config(&data);
+
+                    let mint = Pubkey::new_from_array(config.collateral_mint);
+                    let auth = accounts::derive_vault_authority_with_bump(
+                        program_id,
+                        a_slab.key,
+                        config.vault_authority_bump,
+                    )?;
+                    verify_vault(
+                        a_vault,
+                        &auth,
+                        &mint,
+                        &Pubkey::new_from_array(config.vault_pubkey),
+                    )?;
+                    accounts::expect_key(a_vault_auth, &auth)?;
+
+                    // A closed vault account's payout sits in the SPL vault
+                    // outside engine accounting until its depositors redeem
+                    // it; the drain below would otherwise hand it to admin.
+                    let backstop = state::read_backstop(&data);
+                    if backstop.closed_units != 0 || backstop.total_shares != 0 {
+                        // ctx: unredeemed units, outstanding shares
+                        return Err(fail(
+                            PercolatorError::BackstopNotEmpty,
+                            &[backstop.closed_units, clamp_u64(backstop.total_shares)],
+                        ));
+                    }
+
+                    let engine = zc::engine_ref(&data)?;
+                    if !engine.vault.is_zero() {
+                        return Err(PercolatorError::EngineInsufficientBalance.into());
+                    }
+                    if !engine.insurance_fund.balance.is_zero() {
+                        return Err(PercolatorError::EngineInsufficientBalance.into());
+                    }
+                    if engine.num_used_accounts != 0 {
+                        return Err(PercolatorError::EngineAccountNotFound.into());
+                    }
+
+                    // Drain any stranded vault tokens (unsolicited transfers or
+                    // sub-scale dust) to admin's ATA. This is the terminal cleanup
+                    // path — engine accounting is already zero.
+                    let vault_data = a_vault.try_borrow_data()?;
+                    let vault_token = spl_token::state::Account::unpack(&vault_data)?;
+                    let stranded = vault_token.amount;
+                    drop(vault_data);
+
+                    if stranded > 0 {
+                        // Validate admin's token account before drain
+                        verify_token_account(a_dest_ata, a_dest.key, &mint)?;
+
+                        let seed1: &[u8] = b"vault";
+                        let seed2: &[u8] = a_slab.key.as_ref();
+                        let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                        let seed3: &[u8] = &bump_arr;
+                        let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                        let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+                        // Drain stranded vault tokens → admin ATA
+                        collateral::withdraw(
+                            a_token,
+                            a_vault,
+                            a_dest_ata,
+                            a_vault_auth,
+                            stranded,
+                            &signer_seeds,
+                        )?;
+                    }
+
+                    // Close the vault token account to recover its rent.
+                    // SPL Token CloseAccount transfers remaining rent to destination.
+                    {
+                        let seed1: &[u8] = b"vault";
+                        let seed2: &[u8] = a_slab.key.as_ref();
+                        let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                        let seed3: &[u8] = &bump_arr;
+                        let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                        let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+
+                        let close_ix = spl_token::instruction::close_account(
+                            a_token.key,
+                            a_vault.key,
+                            a_dest.key, // rent destination
+                            a_vault_auth.key,
+                            &[],
+                        )?;
+                        solana_program::program::invoke_signed(
+                            &close_ix,
+                            &[
+                                a_vault.clone(),
+                                a_dest.clone(),
+                                a_vault_auth.clone(),
+                                a_token.clone(),
+                            ],
+                            &signer_seeds,
+                        )?;
+                    }
+
+                    // Zero out the slab data to prevent reuse
+                    for b in data.iter_mut() {
+                        *b = 0;
+                    }
+                }
+
+                // Transfer all lamports from slab to destination
+                let slab_lamports = a_slab.lamports();
+                **a_slab.lamports.borrow_mut() = 0;
+                **a_dest.lamports.borrow_mut() = a_dest
+                    .lamports()
+                    .checked_add(slab_lamports)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+            }
+
+            Instruction::UpdateConfig {
+                funding_horizon_slots,
+                funding_k_bps,
+                funding_max_premium_bps,
+                funding_max_e9_per_slot,
+                tvl_insurance_cap_mult,
+            } => {
+                // Accounts: (admin, slab, clock, oracle).
+                // For non-Hyperp markets the oracle is REQUIRED. Allowing the
+                // caller to omit the oracle account used to be a degenerate
+                // escape hatch: with no oracle, the accrual rate was forced to
+                // zero regardless of the actual live mark-vs-index premium,
+                // which meant admin could retroactively erase elapsed funding
+                // just by leaving the oracle off the instruction. Following
+                // the ResolveMarket pattern, the degenerate (rate=0) branch is
+                // reserved for the *engine-confirmed-dead* oracle case only
+                // (read_price_and_stamp returns OracleStale); admin may not
+                // choose degenerate by omission.
+                accounts::expect_len_min(accounts, 4)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_clock = &accounts[2];
+
+                accounts::expect_signer(a_admin)?;
+                accounts::expect_writable(a_slab)?;
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                // Validate parameters
+                if funding_horizon_slots == 0 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                // Reject negative funding bounds — reversed clamp bounds panic.
+                // Also gate against the engine's stored per-market envelope
+                // (params.max_abs_funding_e9_per_slot), NOT the crate-global
+                // MAX_ABS_FUNDING_E9_PER_SLOT, so UpdateConfig cannot accept a cap
+                // that the engine would later reject at accrue time.
+                // Compare in i128 space: `as u64` would wrap modulo 2^64 for
+                // huge positive inputs (e.g., i64::MAX * 1e5 ≈ 9e23) and
+                // silently pass the envelope.
+                if funding_max_premium_bps < 0 || funding_max_e9_per_slot < 0 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                let engine_envelope = zc::engine_ref(&data)?.params.max_abs_funding_e9_per_slot;
+                if (funding_max_e9_per_slot as i128) > engine_envelope as i128 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+
+                // Read existing config
+                let mut config = state::read_config(&data);
+                expect_len_with_oracles(accounts, 4, &config)?;
+                let oracle_count = oracle_account_count(&config)?;
+                let oracle_accounts = &accounts[3..3 + oracle_count];
+
+                if funding_k_bps > 100_000 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+
+                // Anti-retroactivity: capture funding rate before any config mutation (§5.5)
+                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
 
-                // Remove from risk buffer (drop engine borrow first to release data)
-                // Live close processes a real oracle price through the engine
-                if !resolved && !state::is_oracle_initialized(&data) {
-                    state::set_oracle_initialized(&mut data);
+                let clock = Clock::from_account_info(a_clock)?;
+                // Hard-timeout gate: UpdateConfig must not mutate a
+                // terminally-stale market. Admin has no "emergency
+                // reconfigure" path past the hard timeout — the market
+                // is dead, users exit via ResolvePermissionless.
+                if oracle::permissionless_stale_matured(&config, clock.slot) {
+                    return Err(PercolatorError::OracleStale.into());
+                }
+                // Flush Hyperp index WITHOUT external staleness check
+                // (admin recovery path; the hard-timeout gate above
+                // handles the terminal case).
+                if oracle::is_hyperp_mode(&config) {
+                    let engine = zc::engine_ref(&data)?;
+                    let max_change_bps = engine.params.max_price_move_bps_per_slot;
+                    let mark = hyperp_target_price(&config);
+                    if mark > 0 {
+                        let anchor = if engine.last_oracle_price != 0 {
+                            engine.last_oracle_price
+                        } else if config.last_effective_price_e6 != 0 {
+                            config.last_effective_price_e6
+                        } else {
+                            mark
+                        };
+                        let dt = price_move_residual_dt(engine, clock.slot)?;
+                        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+                        let new_index = if oi_any {
+                            oracle::clamp_toward_engine_dt(anchor, mark, max_change_bps, dt)
+                        } else {
+                            mark
+                        };
+                        config.last_effective_price_e6 = new_index;
+                        if new_index != anchor || new_index == mark {
+                            config.last_hyperp_index_slot = clock.slot;
+                        }
+                    }
+                    state::write_config(&mut data, &config);
                 }
+                // Accrue to boundary. The ordinary/degenerate split mirrors
+                // Resolve{Market,Permissionless}:
+                //   ORDINARY  — fresh non-Hyperp oracle reading (or Hyperp flushed index):
+                //       accrual_price = fresh reading (or index), rate = captured.
+                //   DEGENERATE — non-Hyperp oracle *engine-confirmed dead*:
+                //       accrual_price = P_last = engine.last_oracle_price, rate = 0.
+                // The degenerate arm is entered only when read_price_and_stamp
+                // returns OracleStale, i.e. the passed oracle is genuinely dead.
+                // Admin cannot select the degenerate arm by omitting the oracle
+                // account — the caller-supplied oracle is a required input now
+                // (enforced at expect_len(4) above).
                 {
-                    let mut buf = state::read_risk_buffer(&data);
-                    buf.remove(user_idx);
-                    state::write_risk_buffer(&mut data, &buf);
+                    let (accrual_price, rate_for_accrual): (u64, i128) =
+                        if oracle::is_hyperp_mode(&config) {
+                            (config.last_effective_price_e6, funding_rate_e9)
+                        } else {
+                            match read_price_and_stamp(
+                                &mut config,
+                                oracle_accounts,
+                                clock.unix_timestamp,
+                                clock.slot,
+                                &mut data,
+                            ) {
+                                Ok(price) => {
+                                    state::write_config(&mut data, &config);
+                                    (price, funding_rate_e9)
+                                }
+                                Err(e) => {
+                                    // v12.19.6: propagate the oracle error.
+                                    // UpdateConfig must NOT enter a
+                                    // degenerate-price + rate=0 arm on
+                                    // OracleStale / OracleConfTooWide — that
+                                    // would erase elapsed funding on a live
+                                    // market (current-slot advances but no
+                                    // accrual lands). Admin MUST route stale
+                                    // markets through Resolve{Market,
+                                    // Permissionless}; the hard-timeout gate
+                                    // above is the "market is dead" signal.
+                                    return Err(e);
+                                }
+                            }
+                        };
+                    if accrual_price > 0 {
+                        {
+                            let engine = zc::engine_mut(&mut data)?;
+                            reject_stuck_target_accrual(
+                                &config,
+                                engine,
+                                clock.slot,
+                                accrual_price,
+                            )?;
+                            reject_account_limited_market_progress(
+                                engine,
+                                clock.slot,
+                                accrual_price,
+                                rate_for_accrual,
+                            )?;
+                            // Pre-chunk catch-up so accrue_market_to below sees
+                            // dt ≤ max_accrual_dt_slots (Finding 4). Use the
+                            // same (price, rate) the final accrue uses so the
+                            // catchup chunks are consistent with the boundary.
+                            catchup_accrue(engine, clock.slot, accrual_price, rate_for_accrual)?;
+                            engine
+                                .accrue_market_to(clock.slot, accrual_price, rate_for_accrual)
+                                .map_err(map_risk_error)?;
+                        }
+                        // Engine processed real price — last_oracle_price is no longer sentinel
+                        if !state::is_oracle_initialized(&data) {
+                            state::set_oracle_initialized(&mut data);
+                        }
+                    }
                 }
 
-                // Convert units to base tokens for payout (checked to prevent silent overflow)
-                let base_to_pay =
-                    crate::units::units_to_base_checked(amt_units_u64, config.unit_scale)
-                        .ok_or(PercolatorError::EngineOverflow)?;
-
-                if base_to_pay > 0 {
-                    verify_token_account(a_user_ata, a_user.key, &mint)?;
+                reject_any_target_lag(&config, zc::engine_ref(&data)?)?;
 
-                    let seed1: &[u8] = b"vault";
-                    let seed2: &[u8] = a_slab.key.as_ref();
-                    let bump_arr: [u8; 1] = [config.vault_authority_bump];
-                    let seed3: &[u8] = &bump_arr;
-                    let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
-                    let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+                config.funding_horizon_slots = funding_horizon_slots;
+                config.funding_k_bps = funding_k_bps;
+                config.funding_max_premium_bps = funding_max_premium_bps;
+                config.funding_max_e9_per_slot = funding_max_e9_per_slot;
+                config.tvl_insurance_cap_mult = tvl_insurance_cap_mult;
+                // Engine v12.18.1: accrue_market_to only updates market-global state
+                // (K/F/slot_last). No per-account touches means no resets to
+                // schedule or finalize, so the end-of-instruction lifecycle — which
+                // the engine no longer exposes publicly — is structurally a no-op
+                // on this path.
+                state::write_config(&mut data, &config);
+            }
 
-                    collateral::withdraw(
-                        a_token,
-                        a_vault,
-                        a_user_ata,
-                        a_pda,
-                        base_to_pay,
-                        &signer_seeds,
-                    )?;
+            Instruction::PushHyperpMark {
+                price_e6,
+                timestamp,
+            } => {
+                // Two layouts:
+                //   [pusher (signer), slab]                   — direct push
+                //   [relayer, slab, instructions sysvar]      — signed payload;
+                //     a pusher's ed25519 signature over
+                //     hyperp_mark_payload() sits in the preceding instruction
+                // A pusher is the mark authority or a key it added with
+                // AddHyperpPusher.
+                let relayed = accounts.len() == 3;
+                if !relayed {
+                    accounts::expect_len(accounts, 2)?;
                 }
-            }
-            Instruction::TopUpInsurance { amount } => {
-                accounts::expect_len(accounts, 6)?;
-                let a_user = &accounts[0];
+                let a_authority = &accounts[0];
                 let a_slab = &accounts[1];
-                let a_user_ata = &accounts[2];
-                let a_vault = &accounts[3];
-                let a_token = &accounts[4];
-                let a_clock = &accounts[5];
 
-                accounts::expect_signer(a_user)?;
-                accounts::expect_writable(a_slab)?;
-                verify_token_program(a_token)?;
-                if amount == 0 {
-                    return Err(ProgramError::InvalidArgument);
+                if !relayed {
+                    accounts::expect_signer(a_authority)?;
                 }
+                accounts::expect_writable(a_slab)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
-
-                // Block insurance top-up when market is resolved
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
 
                 let mut config = state::read_config(&data);
-                insurance_withdraw_deposits_only(&config)?;
-                let mint = Pubkey::new_from_array(config.collateral_mint);
-
-                let auth = accounts::derive_vault_authority_with_bump(
-                    program_id,
-                    a_slab.key,
-                    config.vault_authority_bump,
-                )?;
-                verify_vault(
-                    a_vault,
-                    &auth,
-                    &mint,
-                    &Pubkey::new_from_array(config.vault_pubkey),
-                )?;
-                verify_token_account(a_user_ata, a_user.key, &mint)?;
-
-                let clock = Clock::from_account_info(a_clock)?;
-                // Hard-timeout gate: reject before moving tokens into a
-                // terminally-stale market.
-                if oracle::permissionless_stale_matured(&config, clock.slot) {
+                // Hyperp-only: `PushHyperpMark` is the admin mark-push
+                // for internally-priced markets. Non-Hyperp markets
+                // price exclusively off Pyth/Chainlink with no authority
+                // path — the instruction is rejected.
+                if !oracle::is_hyperp_mode(&config) {
+                    return Err(PercolatorError::EngineUnauthorized.into());
+                }
+                if config.hyperp_authority == [0u8; 32] {
+                    return Err(PercolatorError::EngineUnauthorized.into());
+                }
+                let push_clock = Clock::get().map_err(|_| ProgramError::UnsupportedSysvar)?;
+                let pusher = if relayed {
+                    let payload = crate::policy::hyperp_mark_payload(
+                        &a_slab.key.to_bytes(),
+                        price_e6,
+                        timestamp,
+                    );
+                    verify_preceding_ed25519(&accounts[2], &payload)?
+                } else {
+                    a_authority.key.to_bytes()
+                };
+                // The mark authority or one of its extra pushers.
+                let pusher_slot =
+                    state::find_hyperp_pusher(&data, &config.hyperp_authority, &pusher)
+                        .ok_or(PercolatorError::EngineUnauthorized)?;
+                if relayed {
+                    // A signed payload can be landed by anyone, so its
+                    // timestamp is the replay guard: fresh against the
+                    // clock and strictly increasing across pushes.
+                    // Persisted by the write_config below.
+                    if !crate::policy::signed_mark_fresh(
+                        timestamp,
+                        push_clock.unix_timestamp,
+                        config.max_staleness_secs,
+                    ) {
+                        // ctx: timestamp, now, max_staleness_secs
+                        return Err(fail(
+                            PercolatorError::OracleStale,
+                            &[
+                                timestamp as u64,
+                                push_clock.unix_timestamp as u64,
+                                config.max_staleness_secs,
+                            ],
+                        ));
+                    }
+                    if timestamp <= config.last_oracle_publish_time {
+                        return Err(PercolatorError::OracleStale.into());
+                    }
+                    config.last_oracle_publish_time = timestamp;
+                } else {
+                    // For direct pushes `timestamp` is legacy wire data —
+                    // non-Hyperp consumed it as a staleness reference,
+                    // Hyperp ignores it. The cursor still moves to the
+                    // clock so a signed payload from before this push
+                    // cannot land after it and roll the mark back.
+                    config.last_oracle_publish_time = config
+                        .last_oracle_publish_time
+                        .max(push_clock.unix_timestamp);
+                }
+                // Anti-retroactivity: capture funding rate before any config mutation (§5.5)
+                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
+                // Hard-timeout gate: once clock.slot - last_live_slot >=
+                // permissionless_resolve_stale_slots, the market is
+                // TERMINALLY dead. Reject before any mutation.
+                if oracle::permissionless_stale_matured(&config, push_clock.slot) {
                     return Err(PercolatorError::OracleStale.into());
                 }
-                check_no_oracle_live_envelope(zc::engine_ref(&data)?, clock.slot)?;
-
-                // Reject misaligned deposits — dust would be silently donated
-                let (_units_check, dust_check) =
-                    crate::units::base_to_units(amount, config.unit_scale);
-                if dust_check != 0 {
-                    return Err(ProgramError::InvalidArgument);
+                // Flush index WITHOUT external staleness check
+                // (the hard-timeout gate above covers mark staleness).
+                let max_change_bps = zc::engine_ref(&data)?.params.max_price_move_bps_per_slot;
+                {
+                    let engine = zc::engine_ref(&data)?;
+                    let mark = hyperp_target_price(&config);
+                    if mark > 0 {
+                        let anchor = if engine.last_oracle_price != 0 {
+                            engine.last_oracle_price
+                        } else if config.last_effective_price_e6 != 0 {
+                            config.last_effective_price_e6
+                        } else {
+                            mark
+                        };
+                        let dt = price_move_residual_dt(engine, push_clock.slot)?;
+                        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+                        let new_index = if oi_any {
+                            oracle::clamp_toward_engine_dt(anchor, mark, max_change_bps, dt)
+                        } else {
+                            mark
+                        };
+                        config.last_effective_price_e6 = new_index;
+                        if new_index != anchor || new_index == mark {
+                            config.last_hyperp_index_slot = push_clock.slot;
+                        }
+                    }
+                    state::write_config(&mut data, &config);
+                    config = state::read_config(&data);
                 }
 
-                // Convert base tokens to units for engine
-                let (units, _dust) = crate::units::base_to_units(amount, config.unit_scale);
-                let new_deposit_remaining = config
-                    .insurance_withdraw_deposit_remaining
-                    .checked_add(units)
-                    .ok_or(PercolatorError::EngineOverflow)?;
-
-                // Transfer base tokens to vault
-                collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount)?;
-
-                let engine = zc::engine_mut(&mut data)?;
-                engine
-                    .top_up_insurance_fund(units as u128, clock.slot)
-                    .map_err(map_risk_error)?;
-                config.insurance_withdraw_deposit_remaining = new_deposit_remaining;
-                state::write_config(&mut data, &config);
-            }
+                if price_e6 == 0 {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
 
-            Instruction::CloseSlab => {
-                accounts::expect_len(accounts, 6)?;
-                let a_dest = &accounts[0];
-                let a_slab = &accounts[1];
-                let a_vault = &accounts[2];
-                let a_vault_auth = &accounts[3];
-                let a_dest_ata = &accounts[4];
-                let a_token = &accounts[5];
+                let normalized_price =
+                    crate::policy::to_engine_price(price_e6, config.invert, config.unit_scale)
+                        .ok_or(PercolatorError::OracleInvalid)?;
 
-                accounts::expect_signer(a_dest)?;
-                accounts::expect_writable(a_dest)?;
-                accounts::expect_writable(a_slab)?;
-                verify_token_program(a_token)?;
+                if normalized_price > percolator::MAX_ORACLE_PRICE {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
 
+                // Hyperp stale-recovery policy (deliberate):
+                //   If mark liveness has been lost beyond the catchup
+                //   envelope while funding is active, the catchup_accrue
+                //   call below can return CatchupRequired. Such a market
+                //   is RESOLVE-ONLY (mark-staleness branch of
+                //   ResolvePermissionless). Operators who want revivable
+                //   Hyperp markets must push frequently enough to stay
+                //   within the catchup envelope.
                 {
-                    let mut data = state::slab_data_mut(a_slab)?;
-                    slab_guard(program_id, a_slab, &data)?;
-                    require_initialized(&data)?;
-
-                    // Require resolved — enforce lifecycle ordering
-                    if !engine_is_resolved(zc::engine_ref(&data)?) {
-                        return Err(ProgramError::InvalidAccountData);
-                    }
-
-                    // CloseSlab is gated by `admin`. Operators who burn
-                    // admin for rug-proofing trap the slab rent (~0.04 SOL),
-                    // which is the accepted cost of the fully admin-free
-                    // terminal state.
-                    let header = state::read_header(&data);
-                    require_admin(header.admin, a_dest.key)?;
-                    let config = state::read_config(&data);
-
-                    let mint = Pubkey::new_from_array(config.collateral_mint);
-                    let auth = accounts::derive_vault_authority_with_bump(
-                        program_id,
-                        a_slab.key,
-                        config.vault_authority_bump,
+                    let engine = zc::engine_mut(&mut data)?;
+                    reject_stuck_target_accrual(
+                        &config,
+                        engine,
+                        push_clock.slot,
+                        config.last_effective_price_e6,
                     )?;
-                    verify_vault(
-                        a_vault,
-                        &auth,
-                        &mint,
-                        &Pubkey::new_from_array(config.vault_pubkey),
+                    reject_account_limited_market_progress(
+                        engine,
+                        push_clock.slot,
+                        config.last_effective_price_e6,
+                        funding_rate_e9,
                     )?;
-                    accounts::expect_key(a_vault_auth, &auth)?;
-
-                    let engine = zc::engine_ref(&data)?;
-                    if !engine.vault.is_zero() {
-                        return Err(PercolatorError::EngineInsufficientBalance.into());
-                    }
-                    if !engine.insurance_fund.balance.is_zero() {
-                        return Err(PercolatorError::EngineInsufficientBalance.into());
-                    }
-                    if engine.num_used_accounts != 0 {
-                        return Err(PercolatorError::EngineAccountNotFound.into());
-                    }
-
-                    // Drain any stranded vault tokens (unsolicited transfers or
-                    // sub-scale dust) to admin's ATA. This is the terminal cleanup
-                    // path — engine accounting is already zero.
-                    let vault_data = a_vault.try_borrow_data()?;
-                    let vault_token = spl_token::state::Account::unpack(&vault_data)?;
-                    let stranded = vault_token.amount;
-                    drop(vault_data);
-
-                    if stranded > 0 {
-                        // Validate admin's token account before drain
-                        verify_token_account(a_dest_ata, a_dest.key, &mint)?;
-
-                        let seed1: &[u8] = b"vault";
-                        let seed2: &[u8] = a_slab.key.as_ref();
-                        let bump_arr: [u8; 1] = [config.vault_authority_bump];
-                        let seed3: &[u8] = &bump_arr;
-                        let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
-                        let signer_seeds: [&[&[u8]]; 1] = [&seeds];
-                        // Drain stranded vault tokens → admin ATA
-                        collateral::withdraw(
-                            a_token,
-                            a_vault,
-                            a_dest_ata,
-                            a_vault_auth,
-                            stranded,
-                            &signer_seeds,
-                        )?;
-                    }
-
-                    // Close the vault token account to recover its rent.
-                    // SPL Token CloseAccount transfers remaining rent to destination.
-                    {
-                        let seed1: &[u8] = b"vault";
-                        let seed2: &[u8] = a_slab.key.as_ref();
-                        let bump_arr: [u8; 1] = [config.vault_authority_bump];
-                        let seed3: &[u8] = &bump_arr;
-                        let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
-                        let signer_seeds: [&[&[u8]]; 1] = [&seeds];
-
-                        let close_ix = spl_token::instruction::close_account(
-                            a_token.key,
-                            a_vault.key,
-                            a_dest.key, // rent destination
-                            a_vault_auth.key,
-                            &[],
-                        )?;
-                        solana_program::program::invoke_signed(
-                            &close_ix,
-                            &[
-                                a_vault.clone(),
-                                a_dest.clone(),
-                                a_vault_auth.clone(),
-                                a_token.clone(),
-                            ],
-                            &signer_seeds,
-                        )?;
-                    }
-
-                    // Zero out the slab data to prevent reuse
-                    for b in data.iter_mut() {
-                        *b = 0;
-                    }
+                    catchup_accrue(
+                        engine,
+                        push_clock.slot,
+                        config.last_effective_price_e6,
+                        funding_rate_e9,
+                    )?;
+                    engine
+                        .accrue_market_to(
+                            push_clock.slot,
+                            config.last_effective_price_e6,
+                            funding_rate_e9,
+                        )
+                        .map_err(map_risk_error)?;
                 }
 
-                // Transfer all lamports from slab to destination
-                let slab_lamports = a_slab.lamports();
-                **a_slab.lamports.borrow_mut() = 0;
-                **a_dest.lamports.borrow_mut() = a_dest
-                    .lamports()
-                    .checked_add(slab_lamports)
-                    .ok_or(PercolatorError::EngineOverflow)?;
+                // Clamp against index (last_effective_price_e6). This
+                // bounds the mark-index gap to one cap-width regardless
+                // of how many same-slot pushes occur; the index itself
+                // only moves per-slot via clamp_toward_with_dt.
+                let clamp_base = config.last_effective_price_e6;
+                let clamped =
+                    oracle::clamp_oracle_price(clamp_base, normalized_price, max_change_bps);
+                config.hyperp_mark_e6 = clamped;
+                config.last_mark_push_slot = push_clock.slot as u128;
+                // Admin push feeds through EWMA like trades (full weight).
+                config.mark_ewma_e6 = crate::policy::ewma_update(
+                    config.mark_ewma_e6,
+                    clamped,
+                    config.mark_ewma_halflife_slots,
+                    config.mark_ewma_last_slot,
+                    push_clock.slot,
+                    config.mark_min_fee,
+                    config.mark_min_fee,
+                );
+                config.mark_ewma_last_slot = push_clock.slot;
+                state::write_config(&mut data, &config);
+                state::record_hyperp_push(&mut data, pusher_slot, pusher, push_clock.slot);
             }
 
-            Instruction::UpdateConfig {
-                funding_horizon_slots,
-                funding_k_bps,
-                funding_max_premium_bps,
-                funding_max_e9_per_slot,
-                tvl_insurance_cap_mult,
-            } => {
-                // Accounts: (admin, slab, clock, oracle).
-                // For non-Hyperp markets the oracle is REQUIRED. Allowing the
-                // caller to omit the oracle account used to be a degenerate
-                // escape hatch: with no oracle, the accrual rate was forced to
-                // zero regardless of the actual live mark-vs-index premium,
-                // which meant admin could retroactively erase elapsed funding
-                // just by leaving the oracle off the instruction. Following
-                // the ResolveMarket pattern, the degenerate (rate=0) branch is
-                // reserved for the *engine-confirmed-dead* oracle case only
-                // (read_price_and_stamp returns OracleStale); admin may not
-                // choose degenerate by omission.
+            Instruction::ResolveMarket { mode } => {
+                // Resolve market: snapshot resolution slot, set RESOLVED flag.
+                // Caller picks the §9.8 settlement arm explicitly via `mode`
+                // (0 = Ordinary, 1 = Degenerate). The wrapper no longer
+                // silently promotes a stale Ordinary call to Degenerate —
+                // clients that want dead-oracle settlement must ask for it.
                 accounts::expect_len_min(accounts, 4)?;
                 let a_admin = &accounts[0];
                 let a_slab = &accounts[1];
@@ -9087,59 +16318,124 @@ pub mod processor {
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                // Can't re-resolve
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
-                let header = state::read_header(&data);
-                require_admin(header.admin, a_admin.key)?;
 
-                // Validate parameters
-                if funding_horizon_slots == 0 {
-                    return Err(PercolatorError::InvalidConfigParam.into());
-                }
-                // Reject negative funding bounds — reversed clamp bounds panic.
-                // Also gate against the engine's stored per-market envelope
-                // (params.max_abs_funding_e9_per_slot), NOT the crate-global
-                // MAX_ABS_FUNDING_E9_PER_SLOT, so UpdateConfig cannot accept a cap
-                // that the engine would later reject at accrue time.
-                // Compare in i128 space: `as u64` would wrap modulo 2^64 for
-                // huge positive inputs (e.g., i64::MAX * 1e5 ≈ 9e23) and
-                // silently pass the envelope.
-                if funding_max_premium_bps < 0 || funding_max_e9_per_slot < 0 {
-                    return Err(PercolatorError::InvalidConfigParam.into());
-                }
-                let engine_envelope = zc::engine_ref(&data)?.params.max_abs_funding_e9_per_slot;
-                if (funding_max_e9_per_slot as i128) > engine_envelope as i128 {
-                    return Err(PercolatorError::InvalidConfigParam.into());
+                // Require admin oracle price to be set (hyperp_mark_e6 > 0)
+                let mut config = state::read_config(&data);
+                expect_len_with_oracles(accounts, 4, &config)?;
+                let oracle_count = oracle_account_count(&config)?;
+                let oracle_accounts = &accounts[3..3 + oracle_count];
+                // Per-slot price-move cap (init-immutable via RiskParams).
+                let max_change_bps = zc::engine_ref(&data)?.params.max_price_move_bps_per_slot;
+                // Anti-retroactivity: capture funding rate before any config mutation (§5.5)
+                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
+
+                let clock_gate = Clock::from_account_info(a_clock)?;
+
+                // Explicit Degenerate branch: settle at engine.last_oracle_price
+                // with rate = 0. For non-Hyperp Pyth Pull markets this is
+                // gated only by the hard stale/restart predicate below: a
+                // caller-selected stale/conf-wide PriceUpdateV2 account proves
+                // only that this account is bad, not that the feed has no
+                // fresh update. Hyperp has no external update account, so its
+                // admin emergency gate is based on stored mark liveness.
+                if mode == 1 {
+                    let stale_or_restarted =
+                        oracle::permissionless_stale_matured(&config, clock_gate.slot);
+                    if !stale_or_restarted {
+                        if oracle::is_hyperp_mode(&config) {
+                            let oracle_initialized = state::is_oracle_initialized(&data);
+                            let last_update = core::cmp::max(
+                                config.mark_ewma_last_slot,
+                                config.last_mark_push_slot as u64,
+                            );
+                            let max_stale_slots = config.max_staleness_secs.saturating_mul(3);
+                            let hyperp_stale = clock_gate.slot.saturating_sub(last_update)
+                                > max_stale_slots
+                                && oracle_initialized;
+                            if !hyperp_stale {
+                                return Err(PercolatorError::OracleInvalid.into());
+                            }
+                        } else {
+                            return Err(PercolatorError::OracleStale.into());
+                        }
+                    }
+                    let engine = zc::engine_mut(&mut data)?;
+                    let p_last = engine.last_oracle_price;
+                    if p_last == 0 {
+                        return Err(PercolatorError::OracleInvalid.into());
+                    }
+                    engine
+                        .resolve_market_not_atomic(
+                            percolator::ResolveMode::Degenerate,
+                            p_last,
+                            p_last,
+                            clock_gate.slot,
+                            0,
+                        )
+                        .map_err(map_risk_error)?;
+                    config.hyperp_mark_e6 = p_last;
+                    state::write_config(&mut data, &config);
+                    return Ok(());
+                }
+
+                // Ordinary branch: require live oracle. If the stale gate has
+                // matured the caller must switch to Degenerate (mode = 1) —
+                // we don't quietly settle against a dead oracle.
+                if oracle::permissionless_stale_matured(&config, clock_gate.slot) {
+                    return Err(PercolatorError::OracleStale.into());
                 }
 
-                // Read existing config
-                let mut config = state::read_config(&data);
-                expect_len_with_oracles(accounts, 4, &config)?;
-                let oracle_count = oracle_account_count(&config)?;
-                let oracle_accounts = &accounts[3..3 + oracle_count];
-
-                if funding_k_bps > 100_000 {
-                    return Err(PercolatorError::InvalidConfigParam.into());
+                // Hyperp markets need their mark initialized to settle.
+                // Non-Hyperp markets settle at the fresh external oracle
+                // (read below) — no pre-push required.
+                if oracle::is_hyperp_mode(&config) && config.hyperp_mark_e6 == 0 {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                // Read fresh external oracle for two purposes:
+                // 1. Pass as live_oracle_price to the engine for
+                //    self-synchronizing final accrual and the
+                //    §9.8 deviation band check.
+                // 2. Non-Hyperp: also serves as the settlement price
+                //    (admin resolves at the current market price).
+                // Hyperp: admin's pushed mark IS the price source;
+                // live_oracle = index, settlement_price = EWMA/mark.
+                // If the external oracle is genuinely dead (stale or
+                // conf-too-wide), Ordinary rejects; callers must explicitly
+                // select mode = 1 for the gated Degenerate recovery arm.
+                // Other parse errors propagate (bad account, wrong feed).
+                let mut fresh_live_oracle: Option<u64> = None;
+                if !oracle::is_hyperp_mode(&config) {
+                    let clock_tmp = Clock::from_account_info(a_clock)?;
+                    let fresh = read_price_and_stamp(
+                        &mut config,
+                        oracle_accounts,
+                        clock_tmp.unix_timestamp,
+                        clock_tmp.slot,
+                        &mut data,
+                    )?;
+                    fresh_live_oracle = Some(fresh);
                 }
-
-                // Anti-retroactivity: capture funding rate before any config mutation (§5.5)
-                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
 
                 let clock = Clock::from_account_info(a_clock)?;
-                // Hard-timeout gate: UpdateConfig must not mutate a
-                // terminally-stale market. Admin has no "emergency
-                // reconfigure" path past the hard timeout — the market
-                // is dead, users exit via ResolvePermissionless.
-                if oracle::permissionless_stale_matured(&config, clock.slot) {
-                    return Err(PercolatorError::OracleStale.into());
-                }
-                // Flush Hyperp index WITHOUT external staleness check
-                // (admin recovery path; the hard-timeout gate above
-                // handles the terminal case).
+                let mut config = config;
+
+                // Flush Hyperp index to resolution slot WITHOUT staleness check.
+                // Admin must be able to resolve even if mark is stale.
                 if oracle::is_hyperp_mode(&config) {
                     let engine = zc::engine_ref(&data)?;
-                    let max_change_bps = engine.params.max_price_move_bps_per_slot;
                     let mark = hyperp_target_price(&config);
                     if mark > 0 {
                         let anchor = if engine.last_oracle_price != 0 {
@@ -9163,774 +16459,791 @@ pub mod processor {
                     }
                     state::write_config(&mut data, &config);
                 }
-                // Accrue to boundary. The ordinary/degenerate split mirrors
-                // Resolve{Market,Permissionless}:
-                //   ORDINARY  — fresh non-Hyperp oracle reading (or Hyperp flushed index):
-                //       accrual_price = fresh reading (or index), rate = captured.
-                //   DEGENERATE — non-Hyperp oracle *engine-confirmed dead*:
-                //       accrual_price = P_last = engine.last_oracle_price, rate = 0.
-                // The degenerate arm is entered only when read_price_and_stamp
-                // returns OracleStale, i.e. the passed oracle is genuinely dead.
-                // Admin cannot select the degenerate arm by omitting the oracle
-                // account — the caller-supplied oracle is a required input now
-                // (enforced at expect_len(4) above).
-                {
-                    let (accrual_price, rate_for_accrual): (u64, i128) =
-                        if oracle::is_hyperp_mode(&config) {
-                            (config.last_effective_price_e6, funding_rate_e9)
-                        } else {
-                            match read_price_and_stamp(
-                                &mut config,
-                                oracle_accounts,
-                                clock.unix_timestamp,
-                                clock.slot,
-                                &mut data,
-                            ) {
-                                Ok(price) => {
-                                    state::write_config(&mut data, &config);
-                                    (price, funding_rate_e9)
-                                }
-                                Err(e) => {
-                                    // v12.19.6: propagate the oracle error.
-                                    // UpdateConfig must NOT enter a
-                                    // degenerate-price + rate=0 arm on
-                                    // OracleStale / OracleConfTooWide — that
-                                    // would erase elapsed funding on a live
-                                    // market (current-slot advances but no
-                                    // accrual lands). Admin MUST route stale
-                                    // markets through Resolve{Market,
-                                    // Permissionless}; the hard-timeout gate
-                                    // above is the "market is dead" signal.
-                                    return Err(e);
-                                }
-                            }
-                        };
-                    if accrual_price > 0 {
-                        {
-                            let engine = zc::engine_mut(&mut data)?;
-                            reject_stuck_target_accrual(
-                                &config,
-                                engine,
-                                clock.slot,
-                                accrual_price,
-                            )?;
-                            reject_account_limited_market_progress(
-                                engine,
-                                clock.slot,
-                                accrual_price,
-                                rate_for_accrual,
-                            )?;
-                            // Pre-chunk catch-up so accrue_market_to below sees
-                            // dt ≤ max_accrual_dt_slots (Finding 4). Use the
-                            // same (price, rate) the final accrue uses so the
-                            // catchup chunks are consistent with the boundary.
-                            catchup_accrue(engine, clock.slot, accrual_price, rate_for_accrual)?;
-                            engine
-                                .accrue_market_to(clock.slot, accrual_price, rate_for_accrual)
-                                .map_err(map_risk_error)?;
-                        }
-                        // Engine processed real price — last_oracle_price is no longer sentinel
-                        if !state::is_oracle_initialized(&data) {
-                            state::set_oracle_initialized(&mut data);
+
+                // Determine canonical settlement price.
+                //   Hyperp: mark EWMA (smoothed observable price), or
+                //     hyperp_mark_e6 if EWMA is uninitialized.
+                //   Non-Hyperp: the fresh external oracle reading
+                //     (if available). If external is dead, the
+                //     Degenerate arm below settles at
+                //     engine.last_oracle_price.
+                let settlement_price = if oracle::is_hyperp_mode(&config) {
+                    let mark = config.mark_ewma_e6;
+                    if mark > 0 {
+                        mark
+                    } else {
+                        config.hyperp_mark_e6
+                    }
+                } else {
+                    match fresh_live_oracle {
+                        Some(fresh) => fresh,
+                        None => {
+                            let engine_r = zc::engine_ref(&data)?;
+                            engine_r.last_oracle_price
                         }
                     }
+                };
+
+                // Resolution uses two DISJOINT branches:
+                //
+                //   ORDINARY  — live-synchronizing inputs (fresh oracle or Hyperp index):
+                //       live_oracle_price = fresh external reading (or flushed index)
+                //       funding_rate_e9   = captured pre-oracle-mutation (§5.5)
+                //
+                //   DEGENERATE — oracle is confirmed dead (non-Hyperp only):
+                //       live_oracle_price = P_last = engine.last_oracle_price
+                //       funding_rate_e9   = 0   (no live signal over the dead interval)
+                //
+                // Conflating the two would apply a stale mark-vs-index rate over
+                // a dead interval, moving funds with no economic signal backing
+                // the transfer. Branch selection is explicit and logged via the
+                // distinct arms below.
+                let oracle_initialized = state::is_oracle_initialized(&data);
+                let engine = zc::engine_mut(&mut data)?;
+                let is_hyperp_local = oracle::is_hyperp_mode(&config);
+
+                // Detect stale Hyperp mark. If the Hyperp mark signal
+                // (max(mark_ewma_last_slot, last_mark_push_slot)) has
+                // been silent for longer than 3 × max_staleness_secs,
+                // the interval is signal-free. Ordinary rejects and the
+                // caller must explicitly choose mode = 1, which is separately
+                // gated above before Degenerate settlement at P_last/rate=0.
+                let hyperp_stale = if is_hyperp_local {
+                    let last_update = core::cmp::max(
+                        config.mark_ewma_last_slot,
+                        config.last_mark_push_slot as u64,
+                    );
+                    let max_stale_slots = config.max_staleness_secs.saturating_mul(3);
+                    clock.slot.saturating_sub(last_update) > max_stale_slots && oracle_initialized
+                } else {
+                    false
+                };
+
+                // Mode = 0 (Ordinary) policy already stated above: require a
+                // live input. If none is available (oracle never initialized,
+                // Hyperp mark silent beyond 3× max_staleness, or external
+                // oracle missing on non-Hyperp) the caller must switch to
+                // mode = 1 (Degenerate); we no longer silently promote.
+                let (live_oracle, rate_for_final_accrual): (u64, i128) = if let Some(fresh) =
+                    fresh_live_oracle
+                {
+                    if config.oracle_target_price_e6 != 0 && fresh != config.oracle_target_price_e6
+                    {
+                        return Err(PercolatorError::CatchupRequired.into());
+                    }
+                    (fresh, funding_rate_e9)
+                } else if is_hyperp_local && !hyperp_stale {
+                    (config.last_effective_price_e6, funding_rate_e9)
+                } else {
+                    return Err(PercolatorError::OracleStale.into());
+                };
+                let _ = oracle_initialized;
+                // Engine v12.18.5+: resolve_market_not_atomic takes an explicit
+                // ResolveMode selector (Goal 51). This arm is always Ordinary;
+                // Degenerate is reached only via the mode == 1 branch below.
+                let resolve_mode = percolator::ResolveMode::Ordinary;
+                // Pre-chunk catch-up. Ordinary accrues through the final step
+                // and would otherwise hit Overflow when the gap exceeds
+                // max_dt. Catchup uses the same (price, rate) the final
+                // accrue will use, preserving anti-retroactivity (Finding 2).
+                reject_stuck_target_accrual(&config, engine, clock.slot, live_oracle)?;
+                catchup_accrue(engine, clock.slot, live_oracle, rate_for_final_accrual)?;
+                engine
+                    .resolve_market_not_atomic(
+                        resolve_mode,
+                        settlement_price,
+                        live_oracle,
+                        clock.slot,
+                        rate_for_final_accrual,
+                    )
+                    .map_err(map_risk_error)?;
+
+                state::write_config(&mut data, &config);
+            }
+
+            Instruction::WithdrawInsurance => {
+                // Withdraw insurance fund. Gated by insurance_authority
+                // (scoped), not general admin — operators who have
+                // burned admin can still withdraw here if they kept a
+                // separate insurance_authority, or lock withdrawal
+                // forever by burning insurance_authority.
+                accounts::expect_len(accounts, 6)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_admin_ata = &accounts[2];
+                let a_vault = &accounts[3];
+                let a_token = &accounts[4];
+                let a_vault_pda = &accounts[5];
+
+                accounts::expect_signer(a_admin)?;
+                accounts::expect_writable(a_slab)?;
+                verify_token_program(a_token)?;
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+
+                let header = state::read_header(&data);
+                require_admin(header.insurance_authority, a_admin.key)?;
+
+                // Must be resolved
+                if !engine_is_resolved(zc::engine_ref(&data)?) {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+
+                let config = state::read_config(&data);
+
+                let mint = Pubkey::new_from_array(config.collateral_mint);
+
+                let auth = accounts::derive_vault_authority_with_bump(
+                    program_id,
+                    a_slab.key,
+                    config.vault_authority_bump,
+                )?;
+                verify_vault(
+                    a_vault,
+                    &auth,
+                    &mint,
+                    &Pubkey::new_from_array(config.vault_pubkey),
+                )?;
+                verify_token_account(a_admin_ata, a_admin.key, &mint)?;
+                accounts::expect_key(a_vault_pda, &auth)?;
+
+                let payout_units = {
+                    let engine = zc::engine_mut(&mut data)?;
+
+                    // Require all accounts to be fully closed (not just
+                    // effective_pos_q==0, which returns 0 for epoch-mismatched
+                    // stale positions). Any used account means unsettled state
+                    // may remain.
+                    if engine.num_used_accounts != 0 {
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+
+                    engine
+                        .withdraw_resolved_insurance_not_atomic()
+                        .map_err(map_risk_error)?
+                };
+                if payout_units == 0 {
+                    return Ok(()); // Nothing to withdraw
                 }
 
-                reject_any_target_lag(&config, zc::engine_ref(&data)?)?;
+                // Reject if payout exceeds u64 — silent truncation would
+                // zero the engine balance but only pay out a capped amount.
+                let units_u64: u64 = payout_units
+                    .try_into()
+                    .map_err(|_| PercolatorError::EngineOverflow)?;
+                let base_amount = crate::units::units_to_base_checked(units_u64, config.unit_scale)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                drop(data);
 
-                config.funding_horizon_slots = funding_horizon_slots;
-                config.funding_k_bps = funding_k_bps;
-                config.funding_max_premium_bps = funding_max_premium_bps;
-                config.funding_max_e9_per_slot = funding_max_e9_per_slot;
-                config.tvl_insurance_cap_mult = tvl_insurance_cap_mult;
-                // Engine v12.18.1: accrue_market_to only updates market-global state
-                // (K/F/slot_last). No per-account touches means no resets to
-                // schedule or finalize, so the end-of-instruction lifecycle — which
-                // the engine no longer exposes publicly — is structurally a no-op
-                // on this path.
-                state::write_config(&mut data, &config);
+                // Transfer from vault to admin
+                let seed1: &[u8] = b"vault";
+                let seed2: &[u8] = a_slab.key.as_ref();
+                let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                let seed3: &[u8] = &bump_arr;
+                let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+
+                collateral::withdraw(
+                    a_token,
+                    a_vault,
+                    a_admin_ata,
+                    a_vault_pda,
+                    base_amount,
+                    &signer_seeds,
+                )?;
             }
 
-            Instruction::PushHyperpMark {
-                price_e6,
-                timestamp,
-            } => {
-                accounts::expect_len(accounts, 2)?;
-                let a_authority = &accounts[0];
+            Instruction::WithdrawInsuranceLimited { amount } => {
+                // BOUNDED live insurance withdrawal.
+                //
+                // Auth: `header.insurance_operator` (distinct from
+                // `insurance_authority` which gates the unbounded tag 20).
+                // This auth split is structural — an operator with only
+                // `insurance_operator` CANNOT bypass the bounds by
+                // calling tag 20.
+                //
+                // Bounds:
+                //   per-call amount ≤ max(10, bps_cap), clamped to insurance
+                //     where bps_cap = insurance × max_bps / 10_000
+                //   cooldown: clock.slot - last_withdraw_slot ≥ cooldown_slots
+                //
+                // The 10-unit floor (anti-Zeno) lets the fund drain to zero
+                // even when max_bps × insurance < 10.
+                //
+                // Live markets only. For resolved markets use tag 20
+                // (whose terminal-surplus sweep also folds residue into
+                // the payout — not replicated here).
+                const MIN_WITHDRAW_FLOOR_UNITS: u128 = 10;
+
+                accounts::expect_len(accounts, 7)?;
+                let a_operator = &accounts[0];
                 let a_slab = &accounts[1];
+                let a_operator_ata = &accounts[2];
+                let a_vault = &accounts[3];
+                let a_token = &accounts[4];
+                let a_vault_pda = &accounts[5];
+                let a_clock = &accounts[6];
 
-                accounts::expect_signer(a_authority)?;
+                accounts::expect_signer(a_operator)?;
                 accounts::expect_writable(a_slab)?;
+                verify_token_program(a_token)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+
+                // Live markets only. Resolved markets go through tag 20.
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
 
+                let header = state::read_header(&data);
+                require_admin(header.insurance_operator, a_operator.key)?;
+
                 let mut config = state::read_config(&data);
-                // Hyperp-only: `PushHyperpMark` is the admin mark-push
-                // for internally-priced markets. Non-Hyperp markets
-                // price exclusively off Pyth/Chainlink with no authority
-                // path — the instruction is rejected.
-                if !oracle::is_hyperp_mode(&config) {
-                    return Err(PercolatorError::EngineUnauthorized.into());
-                }
-                if config.hyperp_authority == [0u8; 32]
-                    || config.hyperp_authority != a_authority.key.to_bytes()
-                {
-                    return Err(PercolatorError::EngineUnauthorized.into());
-                }
-                // `timestamp` is legacy wire data — non-Hyperp consumed
-                // it as a staleness reference, Hyperp ignores it.
-                let _ = timestamp;
-                // Anti-retroactivity: capture funding rate before any config mutation (§5.5)
-                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
-                // Hard-timeout gate: once clock.slot - last_live_slot >=
-                // permissionless_resolve_stale_slots, the market is
-                // TERMINALLY dead. Reject before any mutation.
-                let push_clock = Clock::get().map_err(|_| ProgramError::UnsupportedSysvar)?;
-                if oracle::permissionless_stale_matured(&config, push_clock.slot) {
+                let clock = Clock::from_account_info(a_clock)?;
+
+                // Hard-timeout gate: don't mutate a matured market.
+                if oracle::permissionless_stale_matured(&config, clock.slot) {
                     return Err(PercolatorError::OracleStale.into());
                 }
-                // Flush index WITHOUT external staleness check
-                // (the hard-timeout gate above covers mark staleness).
-                let max_change_bps = zc::engine_ref(&data)?.params.max_price_move_bps_per_slot;
+
+                // Feature-disabled gate: max_bps == 0 means "bounded path
+                // turned off." Operator must opt in at init or via
+                // UpdateConfig (field is admin-set, not operator-set).
+                if config.insurance_withdraw_max_bps == 0 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                let deposits_only = insurance_withdraw_deposits_only(&config)?;
                 {
                     let engine = zc::engine_ref(&data)?;
-                    let mark = hyperp_target_price(&config);
-                    if mark > 0 {
-                        let anchor = if engine.last_oracle_price != 0 {
-                            engine.last_oracle_price
-                        } else if config.last_effective_price_e6 != 0 {
-                            config.last_effective_price_e6
-                        } else {
-                            mark
-                        };
-                        let dt = price_move_residual_dt(engine, push_clock.slot)?;
-                        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
-                        let new_index = if oi_any {
-                            oracle::clamp_toward_engine_dt(anchor, mark, max_change_bps, dt)
-                        } else {
-                            mark
-                        };
-                        config.last_effective_price_e6 = new_index;
-                        if new_index != anchor || new_index == mark {
-                            config.last_hyperp_index_slot = push_clock.slot;
-                        }
+                    reject_any_target_lag(&config, engine)?;
+                    let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+                    if oi_any && engine.last_market_slot != clock.slot {
+                        return Err(PercolatorError::CatchupRequired.into());
+                    }
+                    let stress_envelope_active = engine.stress_consumed_bps_e9_since_envelope != 0
+                        || engine.stress_envelope_remaining_indices != 0
+                        || engine.bankruptcy_hmax_lock_active;
+                    if !crate::policy::live_insurance_withdraw_market_healthy(
+                        engine.vault.get(),
+                        engine.c_tot.get(),
+                        engine.insurance_fund.balance.get(),
+                        stress_envelope_active,
+                    ) {
+                        return Err(PercolatorError::EngineInsufficientBalance.into());
                     }
-                    state::write_config(&mut data, &config);
-                    config = state::read_config(&data);
                 }
 
-                if price_e6 == 0 {
-                    return Err(PercolatorError::OracleInvalid.into());
+                // Cooldown: first call (last_slot == 0) bypasses. Subsequent
+                // calls require clock.slot - last ≥ cooldown_slots.
+                let last = config.last_insurance_withdraw_slot;
+                if last != 0
+                    && clock.slot.saturating_sub(last) < config.insurance_withdraw_cooldown_slots
+                {
+                    // ctx: last withdraw slot, now, cooldown slots
+                    return Err(fail(
+                        PercolatorError::InsuranceWithdrawCooldown,
+                        &[last, clock.slot, config.insurance_withdraw_cooldown_slots],
+                    ));
+                }
+
+                let (amount_units, dust) = crate::units::base_to_units(amount, config.unit_scale);
+                if dust != 0 || amount_units == 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+
+                // Compute per-call cap: bps × insurance / 10_000, floor
+                // lifted to MIN_WITHDRAW_FLOOR_UNITS, clamped to insurance.
+                let ins = zc::engine_ref(&data)?.insurance_fund.balance.get();
+                if ins == 0 {
+                    return Err(PercolatorError::EngineInsufficientBalance.into());
+                }
+                let bps_cap =
+                    ins.saturating_mul(config.insurance_withdraw_max_bps as u128) / 10_000;
+                let cap = core::cmp::max(bps_cap, MIN_WITHDRAW_FLOOR_UNITS);
+                let mut cap = core::cmp::min(cap, ins);
+                if deposits_only {
+                    cap = core::cmp::min(cap, config.insurance_withdraw_deposit_remaining as u128);
+                }
+                if (amount_units as u128) > cap {
+                    // ctx: requested units, cap
+                    return Err(fail(
+                        PercolatorError::InsuranceWithdrawCapExceeded,
+                        &[amount_units, clamp_u64(cap)],
+                    ));
                 }
 
-                let normalized_price =
-                    crate::policy::to_engine_price(price_e6, config.invert, config.unit_scale)
-                        .ok_or(PercolatorError::OracleInvalid)?;
-
-                if normalized_price > percolator::MAX_ORACLE_PRICE {
-                    return Err(PercolatorError::OracleInvalid.into());
-                }
+                // Vault + ATA checks (reuse the pattern from tag 20).
+                let mint = Pubkey::new_from_array(config.collateral_mint);
+                let auth = accounts::derive_vault_authority_with_bump(
+                    program_id,
+                    a_slab.key,
+                    config.vault_authority_bump,
+                )?;
+                verify_vault(
+                    a_vault,
+                    &auth,
+                    &mint,
+                    &Pubkey::new_from_array(config.vault_pubkey),
+                )?;
+                verify_token_account(a_operator_ata, a_operator.key, &mint)?;
+                accounts::expect_key(a_vault_pda, &auth)?;
 
-                // Hyperp stale-recovery policy (deliberate):
-                //   If mark liveness has been lost beyond the catchup
-                //   envelope while funding is active, the catchup_accrue
-                //   call below can return CatchupRequired. Such a market
-                //   is RESOLVE-ONLY (mark-staleness branch of
-                //   ResolvePermissionless). Operators who want revivable
-                //   Hyperp markets must push frequently enough to stay
-                //   within the catchup envelope.
+                // Commit state changes BEFORE the CPI. The CPI is SPL
+                // Token, which cannot re-enter this program, so ordering
+                // is safe; doing state-then-CPI means a CPI failure
+                // reverts the whole tx atomically.
                 {
                     let engine = zc::engine_mut(&mut data)?;
-                    reject_stuck_target_accrual(
-                        &config,
-                        engine,
-                        push_clock.slot,
-                        config.last_effective_price_e6,
-                    )?;
-                    reject_account_limited_market_progress(
-                        engine,
-                        push_clock.slot,
-                        config.last_effective_price_e6,
-                        funding_rate_e9,
-                    )?;
-                    catchup_accrue(
-                        engine,
-                        push_clock.slot,
-                        config.last_effective_price_e6,
-                        funding_rate_e9,
-                    )?;
                     engine
-                        .accrue_market_to(
-                            push_clock.slot,
-                            config.last_effective_price_e6,
-                            funding_rate_e9,
-                        )
+                        .withdraw_live_insurance_not_atomic(amount_units as u128, clock.slot)
                         .map_err(map_risk_error)?;
                 }
-
-                // Clamp against index (last_effective_price_e6). This
-                // bounds the mark-index gap to one cap-width regardless
-                // of how many same-slot pushes occur; the index itself
-                // only moves per-slot via clamp_toward_with_dt.
-                let clamp_base = config.last_effective_price_e6;
-                let clamped =
-                    oracle::clamp_oracle_price(clamp_base, normalized_price, max_change_bps);
-                config.hyperp_mark_e6 = clamped;
-                config.last_mark_push_slot = push_clock.slot as u128;
-                // Admin push feeds through EWMA like trades (full weight).
-                config.mark_ewma_e6 = crate::policy::ewma_update(
-                    config.mark_ewma_e6,
-                    clamped,
-                    config.mark_ewma_halflife_slots,
-                    config.mark_ewma_last_slot,
-                    push_clock.slot,
-                    config.mark_min_fee,
-                    config.mark_min_fee,
-                );
-                config.mark_ewma_last_slot = push_clock.slot;
+                config.last_insurance_withdraw_slot = clock.slot;
+                if deposits_only {
+                    config.insurance_withdraw_deposit_remaining = config
+                        .insurance_withdraw_deposit_remaining
+                        .checked_sub(amount_units)
+                        .ok_or(PercolatorError::InsuranceWithdrawCapExceeded)?;
+                } else {
+                    config.insurance_withdraw_deposit_remaining = config
+                        .insurance_withdraw_deposit_remaining
+                        .saturating_sub(amount_units);
+                }
                 state::write_config(&mut data, &config);
+                drop(data);
+
+                // PDA-signed SPL Token transfer.
+                let vault_tag: &[u8] = b"vault";
+                let slab_bytes = a_slab.key.to_bytes();
+                let seed1: &[u8] = vault_tag;
+                let seed2: &[u8] = &slab_bytes;
+                let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                let seed3: &[u8] = &bump_arr;
+                let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+
+                collateral::withdraw(
+                    a_token,
+                    a_vault,
+                    a_operator_ata,
+                    a_vault_pda,
+                    amount,
+                    &signer_seeds,
+                )?;
             }
 
-            Instruction::ResolveMarket { mode } => {
-                // Resolve market: snapshot resolution slot, set RESOLVED flag.
-                // Caller picks the §9.8 settlement arm explicitly via `mode`
-                // (0 = Ordinary, 1 = Degenerate). The wrapper no longer
-                // silently promotes a stale Ordinary call to Degenerate —
-                // clients that want dead-oracle settlement must ask for it.
-                accounts::expect_len_min(accounts, 4)?;
+            Instruction::AdminForceCloseAccount { user_idx } => {
+                // Admin force-close an abandoned account after market resolution.
+                // Settles PnL (with haircut for positive), forgives fee debt,
+                // then delegates to engine.close_account_not_atomic() for the rest.
+                accounts::expect_len(accounts, 7)?;
                 let a_admin = &accounts[0];
                 let a_slab = &accounts[1];
-                let a_clock = &accounts[2];
+                let a_vault = &accounts[2];
+                let a_owner_ata = &accounts[3];
+                let a_pda = &accounts[4];
+                let a_token = &accounts[5];
 
                 accounts::expect_signer(a_admin)?;
                 accounts::expect_writable(a_slab)?;
+                verify_token_program(a_token)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
 
-                let header = state::read_header(&data);
-                require_admin(header.admin, a_admin.key)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
 
-                // Can't re-resolve
-                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+                // Must be resolved
+                if !engine_is_resolved(zc::engine_ref(&data)?) {
                     return Err(ProgramError::InvalidAccountData);
                 }
+                // The backstop vault closes through BackstopWithdraw, which
+                // shares its payout among the depositors.
+                if backstop_vault_idx(&data) == Some(user_idx) {
+                    return Err(PercolatorError::EngineUnauthorized.into());
+                }
 
-                // Require admin oracle price to be set (hyperp_mark_e6 > 0)
-                let mut config = state::read_config(&data);
-                expect_len_with_oracles(accounts, 4, &config)?;
-                let oracle_count = oracle_account_count(&config)?;
-                let oracle_accounts = &accounts[3..3 + oracle_count];
-                // Per-slot price-move cap (init-immutable via RiskParams).
-                let max_change_bps = zc::engine_ref(&data)?.params.max_price_move_bps_per_slot;
-                // Anti-retroactivity: capture funding rate before any config mutation (§5.5)
-                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
+                let config = state::read_config(&data);
+                let mint = Pubkey::new_from_array(config.collateral_mint);
 
-                let clock_gate = Clock::from_account_info(a_clock)?;
+                let auth = accounts::derive_vault_authority_with_bump(
+                    program_id,
+                    a_slab.key,
+                    config.vault_authority_bump,
+                )?;
+                verify_vault(
+                    a_vault,
+                    &auth,
+                    &mint,
+                    &Pubkey::new_from_array(config.vault_pubkey),
+                )?;
+                accounts::expect_key(a_pda, &auth)?;
 
-                // Explicit Degenerate branch: settle at engine.last_oracle_price
-                // with rate = 0. For non-Hyperp Pyth Pull markets this is
-                // gated only by the hard stale/restart predicate below: a
-                // caller-selected stale/conf-wide PriceUpdateV2 account proves
-                // only that this account is bad, not that the feed has no
-                // fresh update. Hyperp has no external update account, so its
-                // admin emergency gate is based on stored mark liveness.
-                if mode == 1 {
-                    let stale_or_restarted =
-                        oracle::permissionless_stale_matured(&config, clock_gate.slot);
-                    if !stale_or_restarted {
-                        if oracle::is_hyperp_mode(&config) {
-                            let oracle_initialized = state::is_oracle_initialized(&data);
-                            let last_update = core::cmp::max(
-                                config.mark_ewma_last_slot,
-                                config.last_mark_push_slot as u64,
-                            );
-                            let max_stale_slots = config.max_staleness_secs.saturating_mul(3);
-                            let hyperp_stale = clock_gate.slot.saturating_sub(last_update)
-                                > max_stale_slots
-                                && oracle_initialized;
-                            if !hyperp_stale {
-                                return Err(PercolatorError::OracleInvalid.into());
-                            }
-                        } else {
-                            return Err(PercolatorError::OracleStale.into());
-                        }
-                    }
-                    let engine = zc::engine_mut(&mut data)?;
-                    let p_last = engine.last_oracle_price;
-                    if p_last == 0 {
-                        return Err(PercolatorError::OracleInvalid.into());
-                    }
-                    engine
-                        .resolve_market_not_atomic(
-                            percolator::ResolveMode::Degenerate,
-                            p_last,
-                            p_last,
-                            clock_gate.slot,
-                            0,
-                        )
-                        .map_err(map_risk_error)?;
-                    config.hyperp_mark_e6 = p_last;
-                    state::write_config(&mut data, &config);
-                    return Ok(());
+                let _clock = Clock::from_account_info(&accounts[6])?;
+                let engine = zc::engine_mut(&mut data)?;
+                let (price, _resolved_slot) = engine_resolved_context(engine);
+                if price == 0 {
+                    return Err(ProgramError::InvalidAccountData);
                 }
 
-                // Ordinary branch: require live oracle. If the stale gate has
-                // matured the caller must switch to Degenerate (mode = 1) —
-                // we don't quietly settle against a dead oracle.
-                if oracle::permissionless_stale_matured(&config, clock_gate.slot) {
-                    return Err(PercolatorError::OracleStale.into());
-                }
+                check_idx(engine, user_idx)?;
 
-                // Hyperp markets need their mark initialized to settle.
-                // Non-Hyperp markets settle at the fresh external oracle
-                // (read below) — no pre-push required.
-                if oracle::is_hyperp_mode(&config) && config.hyperp_mark_e6 == 0 {
-                    return Err(ProgramError::InvalidAccountData);
-                }
-                // Read fresh external oracle for two purposes:
-                // 1. Pass as live_oracle_price to the engine for
-                //    self-synchronizing final accrual and the
-                //    §9.8 deviation band check.
-                // 2. Non-Hyperp: also serves as the settlement price
-                //    (admin resolves at the current market price).
-                // Hyperp: admin's pushed mark IS the price source;
-                // live_oracle = index, settlement_price = EWMA/mark.
-                // If the external oracle is genuinely dead (stale or
-                // conf-too-wide), Ordinary rejects; callers must explicitly
-                // select mode = 1 for the gated Degenerate recovery arm.
-                // Other parse errors propagate (bad account, wrong feed).
-                let mut fresh_live_oracle: Option<u64> = None;
-                if !oracle::is_hyperp_mode(&config) {
-                    let clock_tmp = Clock::from_account_info(a_clock)?;
-                    let fresh = read_price_and_stamp(
-                        &mut config,
-                        oracle_accounts,
-                        clock_tmp.unix_timestamp,
-                        clock_tmp.slot,
-                        &mut data,
-                    )?;
-                    fresh_live_oracle = Some(fresh);
+                let owner_pubkey = Pubkey::new_from_array(engine.accounts[user_idx as usize].owner);
+
+                let amt_units = match engine
+                    .force_close_resolved_with_fee_not_atomic(
+                        user_idx,
+                        config.maintenance_fee_per_slot,
+                    )
+                    .map_err(map_risk_error)?
+                {
+                    percolator::ResolvedCloseResult::ProgressOnly => return Ok(()),
+                    percolator::ResolvedCloseResult::Closed(payout) => payout,
+                };
+
+                let amt_units_u64: u64 = amt_units
+                    .try_into()
+                    .map_err(|_| PercolatorError::EngineOverflow)?;
+
+                // Only verify owner ATA when there's a nonzero payout.
+                if amt_units_u64 > 0 {
+                    verify_token_account(a_owner_ata, &owner_pubkey, &mint)?;
                 }
 
-                let clock = Clock::from_account_info(a_clock)?;
-                let mut config = config;
+                // Remove from risk buffer (drop engine first)
+                {
+                    let mut buf = state::read_risk_buffer(&data);
+                    buf.remove(user_idx);
+                    state::write_risk_buffer(&mut data, &buf);
+                }
 
-                // Flush Hyperp index to resolution slot WITHOUT staleness check.
-                // Admin must be able to resolve even if mark is stale.
-                if oracle::is_hyperp_mode(&config) {
-                    let engine = zc::engine_ref(&data)?;
-                    let mark = hyperp_target_price(&config);
-                    if mark > 0 {
-                        let anchor = if engine.last_oracle_price != 0 {
-                            engine.last_oracle_price
-                        } else if config.last_effective_price_e6 != 0 {
-                            config.last_effective_price_e6
-                        } else {
-                            mark
-                        };
-                        let dt = price_move_residual_dt(engine, clock.slot)?;
-                        let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
-                        let new_index = if oi_any {
-                            oracle::clamp_toward_engine_dt(anchor, mark, max_change_bps, dt)
-                        } else {
-                            mark
-                        };
-                        config.last_effective_price_e6 = new_index;
-                        if new_index != anchor || new_index == mark {
-                            config.last_hyperp_index_slot = clock.slot;
-                        }
+                let base_to_pay =
+                    crate::units::units_to_base_checked(amt_units_u64, config.unit_scale)
+                        .ok_or(PercolatorError::EngineOverflow)?;
+
+                let seed1: &[u8] = b"vault";
+                let seed2: &[u8] = a_slab.key.as_ref();
+                let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                let seed3: &[u8] = &bump_arr;
+                let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+
+                collateral::withdraw(
+                    a_token,
+                    a_vault,
+                    a_owner_ata,
+                    a_pda,
+                    base_to_pay,
+                    &signer_seeds,
+                )?;
+            }
+
+            Instruction::DepositFeeCredits { user_idx, amount } => {
+                // Direct fee-debt repayment (§10.3.1). Owner only.
+                // SECURITY: Read fee debt BEFORE the SPL transfer to reject
+                // overpayment. Without this, excess tokens become stranded
+                // vault surplus with no withdrawal path for the user.
+                accounts::expect_len(accounts, 6)?;
+                let a_user = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_user_ata = &accounts[2];
+                let a_vault = &accounts[3];
+                let a_token = &accounts[4];
+                let a_clock = &accounts[5];
+
+                accounts::expect_signer(a_user)?;
+                accounts::expect_writable(a_slab)?;
+                verify_token_program(a_token)?;
+
+                // Phase 1: sync latent flat/current maintenance fees and read
+                // post-sync debt. Nonflat/stale accounts are not fee-synced on
+                // this no-oracle path because losses must be settled first by
+                // an oracle-backed touch.
+                let (unit_scale, debt_units) = {
+                    let mut data = state::slab_data_mut(a_slab)?;
+                    slab_guard(program_id, a_slab, &data)?;
+                    require_initialized(&data)?;
+                    if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+                    let cfg = state::read_config(&data);
+                    let mint = Pubkey::new_from_array(cfg.collateral_mint);
+                    let auth = accounts::derive_vault_authority_with_bump(
+                        program_id,
+                        a_slab.key,
+                        cfg.vault_authority_bump,
+                    )?;
+                    verify_vault(
+                        a_vault,
+                        &auth,
+                        &mint,
+                        &Pubkey::new_from_array(cfg.vault_pubkey),
+                    )?;
+                    verify_token_account(a_user_ata, a_user.key, &mint)?;
+                    let clock = Clock::from_account_info(a_clock)?;
+                    // Hard-timeout gate: no fee-credit deposits into a
+                    // terminally-stale market. Users exit via resolve.
+                    if oracle::permissionless_stale_matured(&cfg, clock.slot) {
+                        return Err(PercolatorError::OracleStale.into());
                     }
-                    state::write_config(&mut data, &config);
-                }
 
-                // Determine canonical settlement price.
-                //   Hyperp: mark EWMA (smoothed observable price), or
-                //     hyperp_mark_e6 if EWMA is uninitialized.
-                //   Non-Hyperp: the fresh external oracle reading
-                //     (if available). If external is dead, the
-                //     Degenerate arm below settles at
-                //     engine.last_oracle_price.
-                let settlement_price = if oracle::is_hyperp_mode(&config) {
-                    let mark = config.mark_ewma_e6;
-                    if mark > 0 {
-                        mark
-                    } else {
-                        config.hyperp_mark_e6
+                    let engine = zc::engine_mut(&mut data)?;
+                    check_idx(engine, user_idx)?;
+                    let owner = engine.accounts[user_idx as usize].owner;
+                    if !crate::policy::owner_ok(owner, a_user.key.to_bytes()) {
+                        return Err(PercolatorError::EngineUnauthorized.into());
                     }
-                } else {
-                    match fresh_live_oracle {
-                        Some(fresh) => fresh,
-                        None => {
-                            let engine_r = zc::engine_ref(&data)?;
-                            engine_r.last_oracle_price
-                        }
+                    check_no_oracle_live_envelope(engine, clock.slot)?;
+                    // No-oracle path: sync flat/current fees at an anchor
+                    // bounded by engine.last_market_slot.
+                    sync_account_fee_bounded_to_market(engine, &cfg, user_idx, clock.slot)?;
+                    let fc = engine.accounts[user_idx as usize].fee_credits.get();
+                    if fc > 0 || fc == i128::MIN {
+                        return Err(PercolatorError::EngineCorruptState.into());
                     }
+                    let debt = if fc < 0 { fc.unsigned_abs() } else { 0u128 };
+                    (cfg.unit_scale, debt)
                 };
+                // slab_data_mut released; OK to do SPL transfer next.
 
-                // Resolution uses two DISJOINT branches:
-                //
-                //   ORDINARY  — live-synchronizing inputs (fresh oracle or Hyperp index):
-                //       live_oracle_price = fresh external reading (or flushed index)
-                //       funding_rate_e9   = captured pre-oracle-mutation (§5.5)
-                //
-                //   DEGENERATE — oracle is confirmed dead (non-Hyperp only):
-                //       live_oracle_price = P_last = engine.last_oracle_price
-                //       funding_rate_e9   = 0   (no live signal over the dead interval)
-                //
-                // Conflating the two would apply a stale mark-vs-index rate over
-                // a dead interval, moving funds with no economic signal backing
-                // the transfer. Branch selection is explicit and logged via the
-                // distinct arms below.
-                let oracle_initialized = state::is_oracle_initialized(&data);
-                let engine = zc::engine_mut(&mut data)?;
-                let is_hyperp_local = oracle::is_hyperp_mode(&config);
+                // Phase 2: Reject zero, misaligned, or overpayment
+                let (units, dust) = crate::units::base_to_units(amount, unit_scale);
+                if units == 0 || dust != 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                if (units as u128) > debt_units {
+                    return Err(ProgramError::InvalidArgument);
+                }
 
-                // Detect stale Hyperp mark. If the Hyperp mark signal
-                // (max(mark_ewma_last_slot, last_mark_push_slot)) has
-                // been silent for longer than 3 × max_staleness_secs,
-                // the interval is signal-free. Ordinary rejects and the
-                // caller must explicitly choose mode = 1, which is separately
-                // gated above before Degenerate settlement at P_last/rate=0.
-                let hyperp_stale = if is_hyperp_local {
-                    let last_update = core::cmp::max(
-                        config.mark_ewma_last_slot,
-                        config.last_mark_push_slot as u64,
-                    );
-                    let max_stale_slots = config.max_staleness_secs.saturating_mul(3);
-                    clock.slot.saturating_sub(last_update) > max_stale_slots && oracle_initialized
-                } else {
-                    false
-                };
+                // Phase 3: SPL transfer (only after validation)
+                collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount)?;
 
-                // Mode = 0 (Ordinary) policy already stated above: require a
-                // live input. If none is available (oracle never initialized,
-                // Hyperp mark silent beyond 3× max_staleness, or external
-                // oracle missing on non-Hyperp) the caller must switch to
-                // mode = 1 (Degenerate); we no longer silently promote.
-                let (live_oracle, rate_for_final_accrual): (u64, i128) = if let Some(fresh) =
-                    fresh_live_oracle
-                {
-                    if config.oracle_target_price_e6 != 0 && fresh != config.oracle_target_price_e6
-                    {
-                        return Err(PercolatorError::CatchupRequired.into());
-                    }
-                    (fresh, funding_rate_e9)
-                } else if is_hyperp_local && !hyperp_stale {
-                    (config.last_effective_price_e6, funding_rate_e9)
-                } else {
-                    return Err(PercolatorError::OracleStale.into());
-                };
-                let _ = oracle_initialized;
-                // Engine v12.18.5+: resolve_market_not_atomic takes an explicit
-                // ResolveMode selector (Goal 51). This arm is always Ordinary;
-                // Degenerate is reached only via the mode == 1 branch below.
-                let resolve_mode = percolator::ResolveMode::Ordinary;
-                // Pre-chunk catch-up. Ordinary accrues through the final step
-                // and would otherwise hit Overflow when the gap exceeds
-                // max_dt. Catchup uses the same (price, rate) the final
-                // accrue will use, preserving anti-retroactivity (Finding 2).
-                reject_stuck_target_accrual(&config, engine, clock.slot, live_oracle)?;
-                catchup_accrue(engine, clock.slot, live_oracle, rate_for_final_accrual)?;
+                // Phase 4: book the repayment in the engine.
+                let mut data = state::slab_data_mut(a_slab)?;
+                let config = state::read_config(&data);
+                let clock = Clock::from_account_info(a_clock)?;
+                let (units2, _dust) = crate::units::base_to_units(amount, config.unit_scale);
+                let engine = zc::engine_mut(&mut data)?;
+                let _ = &config; // Phase 1 synced; no second sync needed.
                 engine
-                    .resolve_market_not_atomic(
-                        resolve_mode,
-                        settlement_price,
-                        live_oracle,
-                        clock.slot,
-                        rate_for_final_accrual,
-                    )
+                    .deposit_fee_credits(user_idx, units2 as u128, clock.slot)
                     .map_err(map_risk_error)?;
-
-                state::write_config(&mut data, &config);
             }
 
-            Instruction::WithdrawInsurance => {
-                // Withdraw insurance fund. Gated by insurance_authority
-                // (scoped), not general admin — operators who have
-                // burned admin can still withdraw here if they kept a
-                // separate insurance_authority, or lock withdrawal
-                // forever by burning insurance_authority.
-                accounts::expect_len(accounts, 6)?;
-                let a_admin = &accounts[0];
+            Instruction::ConvertReleasedPnl { user_idx, amount } => {
+                // Voluntary PnL conversion (§10.4.1). Owner only.
+                accounts::expect_len_min(accounts, 4)?;
+                let a_user = &accounts[0];
                 let a_slab = &accounts[1];
-                let a_admin_ata = &accounts[2];
-                let a_vault = &accounts[3];
-                let a_token = &accounts[4];
-                let a_vault_pda = &accounts[5];
+                let a_clock = &accounts[2];
 
-                accounts::expect_signer(a_admin)?;
+                accounts::expect_signer(a_user)?;
                 accounts::expect_writable(a_slab)?;
-                verify_token_program(a_token)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
-
-                let header = state::read_header(&data);
-                require_admin(header.insurance_authority, a_admin.key)?;
-
-                // Must be resolved
-                if !engine_is_resolved(zc::engine_ref(&data)?) {
+                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
 
-                let config = state::read_config(&data);
-
-                let mint = Pubkey::new_from_array(config.collateral_mint);
-
-                let auth = accounts::derive_vault_authority_with_bump(
-                    program_id,
-                    a_slab.key,
-                    config.vault_authority_bump,
-                )?;
-                verify_vault(
-                    a_vault,
-                    &auth,
-                    &mint,
-                    &Pubkey::new_from_array(config.vault_pubkey),
-                )?;
-                verify_token_account(a_admin_ata, a_admin.key, &mint)?;
-                accounts::expect_key(a_vault_pda, &auth)?;
-
-                let payout_units = {
-                    let engine = zc::engine_mut(&mut data)?;
-
-                    // Require all accounts to be fully closed (not just
-                    // effective_pos_q==0, which returns 0 for epoch-mismatched
-                    // stale positions). Any used account means unsettled state
-                    // may remain.
-                    if engine.num_used_accounts != 0 {
-                        return Err(ProgramError::InvalidAccountData);
-                    }
+                let mut config = state::read_config(&data);
+                expect_len_with_oracles(accounts, 4, &config)?;
+                let oracle_count = oracle_account_count(&config)?;
+                let oracle_accounts = &accounts[3..3 + oracle_count];
+                let clock = Clock::from_account_info(a_clock)?;
 
-                    engine
-                        .withdraw_resolved_insurance_not_atomic()
-                        .map_err(map_risk_error)?
+                let is_hyperp = oracle::is_hyperp_mode(&config);
+                // Anti-retroactivity: capture funding rate before oracle read (§5.5)
+                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
+                let price = if is_hyperp {
+                    let eng = zc::engine_ref(&data)?;
+                    let p_last = eng.last_oracle_price;
+                    let price_move_dt = price_move_residual_dt(eng, clock.slot)?;
+                    let cap_bps = eng.params.max_price_move_bps_per_slot;
+                    let oi_any = eng.oi_eff_long_q != 0 || eng.oi_eff_short_q != 0;
+                    oracle::get_engine_oracle_price_e6(
+                        p_last,
+                        price_move_dt,
+                        clock.slot,
+                        clock.unix_timestamp,
+                        &mut config,
+                        oracle_accounts,
+                        cap_bps,
+                        oi_any,
+                    )?
+                } else {
+                    read_price_and_stamp(
+                        &mut config,
+                        oracle_accounts,
+                        clock.unix_timestamp,
+                        clock.slot,
+                        &mut data,
+                    )?
                 };
-                if payout_units == 0 {
-                    return Ok(()); // Nothing to withdraw
-                }
-
-                // Reject if payout exceeds u64 — silent truncation would
-                // zero the engine balance but only pay out a capped amount.
-                let units_u64: u64 = payout_units
-                    .try_into()
-                    .map_err(|_| PercolatorError::EngineOverflow)?;
-                let base_amount = crate::units::units_to_base_checked(units_u64, config.unit_scale)
-                    .ok_or(PercolatorError::EngineOverflow)?;
-                drop(data);
+                state::write_config(&mut data, &config);
 
-                // Transfer from vault to admin
-                let seed1: &[u8] = b"vault";
-                let seed2: &[u8] = a_slab.key.as_ref();
-                let bump_arr: [u8; 1] = [config.vault_authority_bump];
-                let seed3: &[u8] = &bump_arr;
-                let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
-                let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+                let engine = zc::engine_mut(&mut data)?;
+                check_idx(engine, user_idx)?;
+                let owner = engine.accounts[user_idx as usize].owner;
+                if !crate::policy::owner_ok(owner, a_user.key.to_bytes()) {
+                    return Err(PercolatorError::EngineUnauthorized.into());
+                }
 
-                collateral::withdraw(
-                    a_token,
-                    a_vault,
-                    a_admin_ata,
-                    a_vault_pda,
-                    base_amount,
-                    &signer_seeds,
+                let (units, dust) = crate::units::base_to_units(amount, config.unit_scale);
+                if units == 0 || dust != 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                let admit_h_min = engine.params.h_min;
+                let admit_h_max = engine.params.h_max;
+                // Fully accrue market to clock.slot, then make the account
+                // authoritative and fee-current before conversion bounds.
+                ensure_market_accrued_to_now_for_account_limited_op(
+                    engine,
+                    &config,
+                    clock.slot,
+                    price,
+                    funding_rate_e9,
+                )?;
+                reject_any_target_lag(&config, engine)?;
+                settle_account_then_sync_fee_current(
+                    engine,
+                    &config,
+                    user_idx,
+                    clock.slot,
+                    price,
+                    funding_rate_e9,
+                    admit_h_min,
+                    admit_h_max,
+                    Some(engine.params.maintenance_margin_bps as u128),
                 )?;
+                engine
+                    .convert_released_pnl_not_atomic(
+                        user_idx,
+                        units as u128,
+                        price,
+                        clock.slot,
+                        funding_rate_e9,
+                        admit_h_min,
+                        admit_h_max,
+                        Some(engine.params.maintenance_margin_bps as u128),
+                    )
+                    .map_err(map_risk_error)?;
+                if !state::is_oracle_initialized(&data) {
+                    state::set_oracle_initialized(&mut data);
+                }
             }
 
-            Instruction::WithdrawInsuranceLimited { amount } => {
-                // BOUNDED live insurance withdrawal.
-                //
-                // Auth: `header.insurance_operator` (distinct from
-                // `insurance_authority` which gates the unbounded tag 20).
-                // This auth split is structural — an operator with only
-                // `insurance_operator` CANNOT bypass the bounds by
-                // calling tag 20.
+            Instruction::ResolvePermissionless => {
+                // STRICT HARD-TIMEOUT POLICY:
                 //
-                // Bounds:
-                //   per-call amount ≤ max(10, bps_cap), clamped to insurance
-                //     where bps_cap = insurance × max_bps / 10_000
-                //   cooldown: clock.slot - last_withdraw_slot ≥ cooldown_slots
+                //   clock.slot - last_live_slot >= permissionless_resolve_stale_slots
+                //     → market is stale; anyone resolves at engine.last_oracle_price.
                 //
-                // The 10-unit floor (anti-Zeno) lets the fund drain to zero
-                // even when max_bps × insurance < 10.
+                //   last_live_slot is:
+                //     non-Hyperp → config.last_good_oracle_slot
+                //                  (advances on successful external Pyth/Chainlink reads)
+                //     Hyperp     → max(mark_ewma_last_slot, last_mark_push_slot)
+                //                  (advances on full-weight trades and admin mark pushes)
                 //
-                // Live markets only. For resolved markets use tag 20
-                // (whose terminal-surplus sweep also folds residue into
-                // the payout — not replicated here).
-                const MIN_WITHDRAW_FLOOR_UNITS: u128 = 10;
-
-                accounts::expect_len(accounts, 7)?;
-                let a_operator = &accounts[0];
-                let a_slab = &accounts[1];
-                let a_operator_ata = &accounts[2];
-                let a_vault = &accounts[3];
-                let a_token = &accounts[4];
-                let a_vault_pda = &accounts[5];
-                let a_clock = &accounts[6];
+                // No challenge window, no oracle account submitted at resolve
+                // time. If no one has fed the market a fresh price for N
+                // slots, it's dead. Settlement is at engine.last_oracle_price
+                // (the last price the engine actually accrued against).
+                accounts::expect_len(accounts, 2)?;
+                let a_slab = &accounts[0];
+                let a_clock = &accounts[1];
 
-                accounts::expect_signer(a_operator)?;
                 accounts::expect_writable(a_slab)?;
-                verify_token_program(a_token)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
 
-                // Live markets only. Resolved markets go through tag 20.
                 if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
                     return Err(ProgramError::InvalidAccountData);
                 }
 
-                let header = state::read_header(&data);
-                require_admin(header.insurance_operator, a_operator.key)?;
-
                 let mut config = state::read_config(&data);
-                let clock = Clock::from_account_info(a_clock)?;
-
-                // Hard-timeout gate: don't mutate a matured market.
-                if oracle::permissionless_stale_matured(&config, clock.slot) {
-                    return Err(PercolatorError::OracleStale.into());
-                }
-
-                // Feature-disabled gate: max_bps == 0 means "bounded path
-                // turned off." Operator must opt in at init or via
-                // UpdateConfig (field is admin-set, not operator-set).
-                if config.insurance_withdraw_max_bps == 0 {
-                    return Err(PercolatorError::InvalidConfigParam.into());
-                }
-                let deposits_only = insurance_withdraw_deposits_only(&config)?;
-                {
-                    let engine = zc::engine_ref(&data)?;
-                    reject_any_target_lag(&config, engine)?;
-                    let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
-                    if oi_any && engine.last_market_slot != clock.slot {
-                        return Err(PercolatorError::CatchupRequired.into());
-                    }
-                    let stress_envelope_active = engine.stress_consumed_bps_e9_since_envelope != 0
-                        || engine.stress_envelope_remaining_indices != 0
-                        || engine.bankruptcy_hmax_lock_active;
-                    if !crate::policy::live_insurance_withdraw_market_healthy(
-                        engine.vault.get(),
-                        engine.c_tot.get(),
-                        engine.insurance_fund.balance.get(),
-                        stress_envelope_active,
-                    ) {
-                        return Err(PercolatorError::EngineInsufficientBalance.into());
-                    }
-                }
-
-                // Cooldown: first call (last_slot == 0) bypasses. Subsequent
-                // calls require clock.slot - last ≥ cooldown_slots.
-                let last = config.last_insurance_withdraw_slot;
-                if last != 0
-                    && clock.slot.saturating_sub(last) < config.insurance_withdraw_cooldown_slots
-                {
-                    return Err(PercolatorError::InsuranceWithdrawCooldown.into());
-                }
-
-                let (amount_units, dust) = crate::units::base_to_units(amount, config.unit_scale);
-                if dust != 0 || amount_units == 0 {
-                    return Err(ProgramError::InvalidArgument);
-                }
-
-                // Compute per-call cap: bps × insurance / 10_000, floor
-                // lifted to MIN_WITHDRAW_FLOOR_UNITS, clamped to insurance.
-                let ins = zc::engine_ref(&data)?.insurance_fund.balance.get();
-                if ins == 0 {
-                    return Err(PercolatorError::EngineInsufficientBalance.into());
-                }
-                let bps_cap =
-                    ins.saturating_mul(config.insurance_withdraw_max_bps as u128) / 10_000;
-                let cap = core::cmp::max(bps_cap, MIN_WITHDRAW_FLOOR_UNITS);
-                let mut cap = core::cmp::min(cap, ins);
-                if deposits_only {
-                    cap = core::cmp::min(cap, config.insurance_withdraw_deposit_remaining as u128);
-                }
-                if (amount_units as u128) > cap {
-                    return Err(PercolatorError::InsuranceWithdrawCapExceeded.into());
+
+                // A post-init cluster-restart (SIMD-0047 LastRestartSlot
+                // bump) freezes the market unconditionally — bypass the
+                // "feature disabled" gate so markets with no slot-based
+                // staleness window can still be resolved after a restart.
+                let restarted = oracle::cluster_restarted_since_init(&config);
+                if !restarted && config.permissionless_resolve_stale_slots == 0 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
                 }
 
-                // Vault + ATA checks (reuse the pattern from tag 20).
-                let mint = Pubkey::new_from_array(config.collateral_mint);
-                let auth = accounts::derive_vault_authority_with_bump(
-                    program_id,
-                    a_slab.key,
-                    config.vault_authority_bump,
-                )?;
-                verify_vault(
-                    a_vault,
-                    &auth,
-                    &mint,
-                    &Pubkey::new_from_array(config.vault_pubkey),
-                )?;
-                verify_token_account(a_operator_ata, a_operator.key, &mint)?;
-                accounts::expect_key(a_vault_pda, &auth)?;
+                let clock = Clock::from_account_info(a_clock)?;
 
-                // Commit state changes BEFORE the CPI. The CPI is SPL
-                // Token, which cannot re-enter this program, so ordering
-                // is safe; doing state-then-CPI means a CPI failure
-                // reverts the whole tx atomically.
-                {
-                    let engine = zc::engine_mut(&mut data)?;
-                    engine
-                        .withdraw_live_insurance_not_atomic(amount_units as u128, clock.slot)
-                        .map_err(map_risk_error)?;
-                }
-                config.last_insurance_withdraw_slot = clock.slot;
-                if deposits_only {
-                    config.insurance_withdraw_deposit_remaining = config
-                        .insurance_withdraw_deposit_remaining
-                        .checked_sub(amount_units)
-                        .ok_or(PercolatorError::InsuranceWithdrawCapExceeded)?;
-                } else {
-                    config.insurance_withdraw_deposit_remaining = config
-                        .insurance_withdraw_deposit_remaining
-                        .saturating_sub(amount_units);
+                if !oracle::permissionless_stale_matured(&config, clock.slot) {
+                    return Err(PercolatorError::OracleStale.into());
                 }
-                state::write_config(&mut data, &config);
-                drop(data);
 
-                // PDA-signed SPL Token transfer.
-                let vault_tag: &[u8] = b"vault";
-                let slab_bytes = a_slab.key.to_bytes();
-                let seed1: &[u8] = vault_tag;
-                let seed2: &[u8] = &slab_bytes;
-                let bump_arr: [u8; 1] = [config.vault_authority_bump];
-                let seed3: &[u8] = &bump_arr;
-                let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
-                let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+                // Degenerate resolve at engine.last_oracle_price. Both
+                // settlement_price and live_oracle_price use P_last (the
+                // engine's stored last-accrued price) — the engine's
+                // Degenerate arm requires equality between the two.
+                //
+                // This is the reason `permissionless_resolve_stale_slots`
+                // is NOT bounded by `MAX_ACCRUAL_DT_SLOTS`: this path does
+                // not call `accrue_market_to`, and passes rate=0. A market
+                // can therefore wait a product-scale stale horizon (capped
+                // at MAX_PERMISSIONLESS_RESOLVE_STALE_SLOTS) and still exit
+                // permissionlessly without violating the live-accrual
+                // envelope. Regression coverage:
+                //
+                //   test_resolve_permissionless_succeeds_after_outage_exceeding_max_accrual_dt
+                //
+                // Kani coverage is wrapper-policy only:
+                //
+                //   kani_permissionless_resolve_horizon_policy_independent_from_accrual_window
+                //
+                // Engine conservation/postconditions are enforced inside
+                // resolve_market_not_atomic and covered by engine tests; the
+                // wrapper proof deliberately does not model engine internals.
+                let engine = zc::engine_mut(&mut data)?;
+                let p_last = engine.last_oracle_price;
+                if p_last == 0 {
+                    return Err(PercolatorError::OracleInvalid.into());
+                }
+                engine
+                    .resolve_market_not_atomic(
+                        percolator::ResolveMode::Degenerate,
+                        p_last,
+                        p_last,
+                        clock.slot,
+                        0,
+                    )
+                    .map_err(map_risk_error)?;
 
-                collateral::withdraw(
-                    a_token,
-                    a_vault,
-                    a_operator_ata,
-                    a_vault_pda,
-                    amount,
-                    &signer_seeds,
-                )?;
+                config.hyperp_mark_e6 = p_last;
+                state::write_config(&mut data, &config);
             }
 
-            Instruction::AdminForceCloseAccount { user_idx } => {
-                // Admin force-close an abandoned account after market resolution.
-                // Settles PnL (with haircut for positive), forgives fee debt,
-                // then delegates to engine.close_account_not_atomic() for the rest.
-                accounts::expect_len(accounts, 7)?;
-                let a_admin = &accounts[0];
-                let a_slab = &accounts[1];
-                let a_vault = &accounts[2];
-                let a_owner_ata = &accounts[3];
-                let a_pda = &accounts[4];
-                let a_token = &accounts[5];
+            Instruction::ForceCloseResolved { user_idx } => {
+                // Permissionless force-close for resolved markets.
+                // Mirrors AdminForceCloseAccount but requires delay and no admin.
+                accounts::expect_len(accounts, 6)?;
+                let a_slab = &accounts[0];
+                let a_vault = &accounts[1];
+                let a_owner_ata = &accounts[2];
+                let a_pda = &accounts[3];
+                let a_token = &accounts[4];
+                let a_clock = &accounts[5];
 
-                accounts::expect_signer(a_admin)?;
                 accounts::expect_writable(a_slab)?;
                 verify_token_program(a_token)?;
 
@@ -9938,17 +17251,33 @@ pub mod processor {
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
 
-                let header = state::read_header(&data);
-                require_admin(header.admin, a_admin.key)?;
+                let resolved_slot = {
+                    let eng = zc::engine_ref(&data)?;
+                    if !engine_is_resolved(eng) {
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+                    engine_resolved_context(eng).1
+                };
+                // The backstop vault closes through BackstopWithdraw, which
+                // shares its payout among the depositors.
+                if backstop_vault_idx(&data) == Some(user_idx) {
+                    return Err(PercolatorError::EngineUnauthorized.into());
+                }
 
-                // Must be resolved
-                if !engine_is_resolved(zc::engine_ref(&data)?) {
+                let config = state::read_config(&data);
+                if config.force_close_delay_slots == 0 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                let clock = Clock::from_account_info(a_clock)?;
+                if !crate::policy::force_close_delay_elapsed(
+                    clock.slot,
+                    resolved_slot,
+                    config.force_close_delay_slots,
+                ) {
                     return Err(ProgramError::InvalidAccountData);
                 }
 
-                let config = state::read_config(&data);
                 let mint = Pubkey::new_from_array(config.collateral_mint);
-
                 let auth = accounts::derive_vault_authority_with_bump(
                     program_id,
                     a_slab.key,
@@ -9962,13 +17291,11 @@ pub mod processor {
                 )?;
                 accounts::expect_key(a_pda, &auth)?;
 
-                let _clock = Clock::from_account_info(&accounts[6])?;
                 let engine = zc::engine_mut(&mut data)?;
                 let (price, _resolved_slot) = engine_resolved_context(engine);
                 if price == 0 {
                     return Err(ProgramError::InvalidAccountData);
                 }
-
                 check_idx(engine, user_idx)?;
 
                 let owner_pubkey = Pubkey::new_from_array(engine.accounts[user_idx as usize].owner);
@@ -9984,16 +17311,16 @@ pub mod processor {
                     percolator::ResolvedCloseResult::Closed(payout) => payout,
                 };
 
-                let amt_units_u64: u64 = amt_units
-                    .try_into()
-                    .map_err(|_| PercolatorError::EngineOverflow)?;
-
                 // Only verify owner ATA when there's a nonzero payout.
-                if amt_units_u64 > 0 {
+                if amt_units > 0 {
                     verify_token_account(a_owner_ata, &owner_pubkey, &mint)?;
                 }
 
-                // Remove from risk buffer (drop engine first)
+                let amt_units_u64: u64 = amt_units
+                    .try_into()
+                    .map_err(|_| PercolatorError::EngineOverflow)?;
+
+                // Remove from risk buffer before withdraw
                 {
                     let mut buf = state::read_risk_buffer(&data);
                     buf.remove(user_idx);
@@ -10021,106 +17348,256 @@ pub mod processor {
                 )?;
             }
 
-            Instruction::DepositFeeCredits { user_idx, amount } => {
-                // Direct fee-debt repayment (§10.3.1). Owner only.
-                // SECURITY: Read fee debt BEFORE the SPL transfer to reject
-                // overpayment. Without this, excess tokens become stranded
-                // vault surplus with no withdrawal path for the user.
-                accounts::expect_len(accounts, 6)?;
-                let a_user = &accounts[0];
+            Instruction::UpdateAuthority { kind, new_pubkey } => {
+                handle_update_authority(program_id, accounts, kind, new_pubkey, instruction_data)?;
+            }
+
+            Instruction::WithdrawFees { amount } => {
+                // Admin withdrawal of collected new-account fees. The fees
+                // were credited to insurance at InitUser/InitLP (keeping
+                // engine.vault == SPL vault) and earmarked in the header
+                // accumulator; this path can only extract that earmark,
+                // never insurance contributed by top-ups or trading fees.
+                accounts::expect_len(accounts, 7)?;
+                let a_admin = &accounts[0];
                 let a_slab = &accounts[1];
-                let a_user_ata = &accounts[2];
+                let a_admin_ata = &accounts[2];
                 let a_vault = &accounts[3];
                 let a_token = &accounts[4];
-                let a_clock = &accounts[5];
+                let a_vault_pda = &accounts[5];
+                let a_clock = &accounts[6];
 
-                accounts::expect_signer(a_user)?;
+                accounts::expect_signer(a_admin)?;
                 accounts::expect_writable(a_slab)?;
                 verify_token_program(a_token)?;
 
-                // Phase 1: sync latent flat/current maintenance fees and read
-                // post-sync debt. Nonflat/stale accounts are not fee-synced on
-                // this no-oracle path because losses must be settled first by
-                // an oracle-backed touch.
-                let (unit_scale, debt_units) = {
-                    let mut data = state::slab_data_mut(a_slab)?;
-                    slab_guard(program_id, a_slab, &data)?;
-                    require_initialized(&data)?;
-                    if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
-                        return Err(ProgramError::InvalidAccountData);
-                    }
-                    let cfg = state::read_config(&data);
-                    let mint = Pubkey::new_from_array(cfg.collateral_mint);
-                    let auth = accounts::derive_vault_authority_with_bump(
-                        program_id,
-                        a_slab.key,
-                        cfg.vault_authority_bump,
-                    )?;
-                    verify_vault(
-                        a_vault,
-                        &auth,
-                        &mint,
-                        &Pubkey::new_from_array(cfg.vault_pubkey),
-                    )?;
-                    verify_token_account(a_user_ata, a_user.key, &mint)?;
-                    let clock = Clock::from_account_info(a_clock)?;
-                    // Hard-timeout gate: no fee-credit deposits into a
-                    // terminally-stale market. Users exit via resolve.
-                    if oracle::permissionless_stale_matured(&cfg, clock.slot) {
-                        return Err(PercolatorError::OracleStale.into());
-                    }
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
 
-                    let engine = zc::engine_mut(&mut data)?;
-                    check_idx(engine, user_idx)?;
-                    let owner = engine.accounts[user_idx as usize].owner;
-                    if !crate::policy::owner_ok(owner, a_user.key.to_bytes()) {
-                        return Err(PercolatorError::EngineUnauthorized.into());
+                // Live markets only. Resolved markets go through tag 20.
+                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                let config = state::read_config(&data);
+                let clock = Clock::from_account_info(a_clock)?;
+
+                if oracle::permissionless_stale_matured(&config, clock.slot) {
+                    return Err(PercolatorError::OracleStale.into());
+                }
+
+                let (amount_units, dust) = crate::units::base_to_units(amount, config.unit_scale);
+                if dust != 0 || amount_units == 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+
+                // Same live-market health gate as tag 23: fees are paid out
+                // of insurance, so they may not leave while insurance is
+                // backing an active stress envelope or a lagging market.
+                {
+                    let engine = zc::engine_ref(&data)?;
+                    reject_any_target_lag(&config, engine)?;
+                    let oi_any = engine.oi_eff_long_q != 0 || engine.oi_eff_short_q != 0;
+                    if oi_any && engine.last_market_slot != clock.slot {
+                        return Err(PercolatorError::CatchupRequired.into());
                     }
-                    check_no_oracle_live_envelope(engine, clock.slot)?;
-                    // No-oracle path: sync flat/current fees at an anchor
-                    // bounded by engine.last_market_slot.
-                    sync_account_fee_bounded_to_market(engine, &cfg, user_idx, clock.slot)?;
-                    let fc = engine.accounts[user_idx as usize].fee_credits.get();
-                    if fc > 0 || fc == i128::MIN {
-                        return Err(PercolatorError::EngineCorruptState.into());
+                    let stress_envelope_active = engine.stress_consumed_bps_e9_since_envelope != 0
+                        || engine.stress_envelope_remaining_indices != 0
+                        || engine.bankruptcy_hmax_lock_active;
+                    if !crate::policy::live_insurance_withdraw_market_healthy(
+                        engine.vault.get(),
+                        engine.c_tot.get(),
+                        engine.insurance_fund.balance.get(),
+                        stress_envelope_active,
+                    ) {
+                        return Err(PercolatorError::EngineInsufficientBalance.into());
                     }
-                    let debt = if fc < 0 { fc.unsigned_abs() } else { 0u128 };
-                    (cfg.unit_scale, debt)
-                };
-                // slab_data_mut released; OK to do SPL transfer next.
+                }
 
-                // Phase 2: Reject zero, misaligned, or overpayment
-                let (units, dust) = crate::units::base_to_units(amount, unit_scale);
-                if units == 0 || dust != 0 {
-                    return Err(ProgramError::InvalidArgument);
+                // Cap: the earmark, clamped to what insurance still holds
+                // (losses may already have consumed part of it).
+                let fees = state::read_account_fees(&data);
+                let ins = zc::engine_ref(&data)?.insurance_fund.balance.get();
+                let cap = core::cmp::min(fees as u128, ins);
+                if (amount_units as u128) > cap {
+                    // ctx: requested units, cap
+                    return Err(fail(
+                        PercolatorError::InsuranceWithdrawCapExceeded,
+                        &[amount_units, clamp_u64(cap)],
+                    ));
                 }
-                if (units as u128) > debt_units {
-                    return Err(ProgramError::InvalidArgument);
+
+                let mint = Pubkey::new_from_array(config.collateral_mint);
+                let auth = accounts::derive_vault_authority_with_bump(
+                    program_id,
+                    a_slab.key,
+                    config.vault_authority_bump,
+                )?;
+                verify_vault(
+                    a_vault,
+                    &auth,
+                    &mint,
+                    &Pubkey::new_from_array(config.vault_pubkey),
+                )?;
+                verify_token_account(a_admin_ata, a_admin.key, &mint)?;
+                accounts::expect_key(a_vault_pda, &auth)?;
+
+                // State first, then the SPL Token CPI (cannot re-enter).
+                {
+                    let engine = zc::engine_mut(&mut data)?;
+                    engine
+                        .withdraw_live_insurance_not_atomic(amount_units as u128, clock.slot)
+                        .map_err(map_risk_error)?;
                 }
+                state::write_account_fees(&mut data, fees - amount_units);
+                drop(data);
 
-                // Phase 3: SPL transfer (only after validation)
-                collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount)?;
+                let seed1: &[u8] = b"vault";
+                let seed2: &[u8] = a_slab.key.as_ref();
+                let bump_arr: [u8; 1] = [config.vault_authority_bump];
+                let seed3: &[u8] = &bump_arr;
+                let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
+                let signer_seeds: [&[&[u8]]; 1] = [&seeds];
+
+                collateral::withdraw(
+                    a_token,
+                    a_vault,
+                    a_admin_ata,
+                    a_vault_pda,
+                    amount,
+                    &signer_seeds,
+                )?;
+            }
+            Instruction::QueryHealth { user_idx } => {
+                accounts::expect_len(accounts, 1)?;
+                let a_slab = &accounts[0];
+
+                let data = a_slab.try_borrow_data()?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                let snapshot = health_snapshot(zc::engine_ref(&data)?, user_idx)?;
+                solana_program::program::set_return_data(&snapshot.encode());
+            }
+            Instruction::SetAccountLimits {
+                max_capital,
+                max_position_notional,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
 
-                // Phase 4: book the repayment in the engine.
                 let mut data = state::slab_data_mut(a_slab)?;
-                let config = state::read_config(&data);
-                let clock = Clock::from_account_info(a_clock)?;
-                let (units2, _dust) = crate::units::base_to_units(amount, config.unit_scale);
-                let engine = zc::engine_mut(&mut data)?;
-                let _ = &config; // Phase 1 synced; no second sync needed.
-                engine
-                    .deposit_fee_credits(user_idx, units2 as u128, clock.slot)
-                    .map_err(map_risk_error)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                state::write_account_limits(
+                    &mut data,
+                    &state::AccountLimits {
+                        max_capital,
+                        max_position_notional,
+                    },
+                );
             }
+            Instruction::SetAllowlistRoot { root } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
 
-            Instruction::ConvertReleasedPnl { user_idx, amount } => {
-                // Voluntary PnL conversion (§10.4.1). Owner only.
-                accounts::expect_len_min(accounts, 4)?;
-                let a_user = &accounts[0];
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                state::write_allowlist_root(&mut data, &root);
+            }
+            Instruction::SetFeeSplit {
+                insurance_bps,
+                treasury_bps,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
                 let a_slab = &accounts[1];
-                let a_clock = &accounts[2];
+                if insurance_bps as u32 + treasury_bps as u32 > 10_000 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                state::write_fee_split(
+                    &mut data,
+                    &state::FeeSplit {
+                        enabled: true,
+                        insurance_bps,
+                        treasury_bps,
+                    },
+                );
+            }
+            Instruction::ClaimProtocolFees { amount } => {
+                handle_claim_protocol_fees(program_id, accounts, amount)?;
+            }
+            Instruction::SetEpochLength { epoch_slots } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                let clock = Clock::get().map_err(|_| ProgramError::UnsupportedSysvar)?;
+                let cfg = state::read_epoch_config(&data);
+                state::write_epoch_config(
+                    &mut data,
+                    &state::EpochConfig {
+                        epoch_slots,
+                        epoch: cfg.epoch.saturating_add(1),
+                        start_slot: clock.slot,
+                    },
+                );
+            }
+            Instruction::EnterWithdrawOnly => {
+                accounts::expect_len(accounts, 2)?;
+                let a_slab = &accounts[0];
+                let a_clock = &accounts[1];
 
-                accounts::expect_signer(a_user)?;
                 accounts::expect_writable(a_slab)?;
 
                 let mut data = state::slab_data_mut(a_slab)?;
@@ -10130,299 +17607,1262 @@ pub mod processor {
                     return Err(ProgramError::InvalidAccountData);
                 }
 
-                let mut config = state::read_config(&data);
-                expect_len_with_oracles(accounts, 4, &config)?;
-                let oracle_count = oracle_account_count(&config)?;
-                let oracle_accounts = &accounts[3..3 + oracle_count];
                 let clock = Clock::from_account_info(a_clock)?;
+                let mut guard = state::read_crank_guard(&data);
+                if guard.withdraw_only {
+                    return Ok(());
+                }
+                if guard.max_crank_staleness_slots == 0 {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                // Same not-yet-stale code as ResolvePermissionless.
+                if !crate::policy::withdraw_only_eligible(
+                    guard.max_crank_staleness_slots,
+                    guard.last_crank_slot,
+                    clock.slot,
+                ) {
+                    // ctx: last crank slot, staleness setting
+                    return Err(fail(
+                        PercolatorError::OracleStale,
+                        &[guard.last_crank_slot, guard.max_crank_staleness_slots],
+                    ));
+                }
+                guard.withdraw_only = true;
+                state::write_crank_guard(&mut data, &guard);
+            }
+            Instruction::SetAdminMultisig { threshold, signers } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                if !crate::policy::admin_multisig_valid(threshold, &signers) {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
 
-                let is_hyperp = oracle::is_hyperp_mode(&config);
-                // Anti-retroactivity: capture funding rate before oracle read (§5.5)
-                let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
-                let price = if is_hyperp {
-                    let eng = zc::engine_ref(&data)?;
-                    let p_last = eng.last_oracle_price;
-                    let price_move_dt = price_move_residual_dt(eng, clock.slot)?;
-                    let cap_bps = eng.params.max_price_move_bps_per_slot;
-                    let oi_any = eng.oi_eff_long_q != 0 || eng.oi_eff_short_q != 0;
-                    oracle::get_engine_oracle_price_e6(
-                        p_last,
-                        price_move_dt,
-                        clock.slot,
-                        clock.unix_timestamp,
-                        &mut config,
-                        oracle_accounts,
-                        cap_bps,
-                        oi_any,
-                    )?
-                } else {
-                    read_price_and_stamp(
-                        &mut config,
-                        oracle_accounts,
-                        clock.unix_timestamp,
-                        clock.slot,
-                        &mut data,
-                    )?
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                let mut ms = state::AdminMultisig {
+                    threshold,
+                    len: signers.len() as u8,
+                    ..state::AdminMultisig::default()
                 };
-                state::write_config(&mut data, &config);
+                ms.signers[..signers.len()].copy_from_slice(&signers);
+                state::write_admin_multisig(&mut data, &ms);
+                // Bitmaps index the old key list.
+                for i in 0..crate::constants::MAX_PENDING_ADMIN_ACTIONS {
+                    state::write_pending_admin_action(
+                        &mut data,
+                        i,
+                        &state::PendingAdminAction::default(),
+                    );
+                }
+            }
+            Instruction::ApproveAdminAction { action_hash } => {
+                use accounts::{AccountSpec, Owner};
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("multisig member signer").signer(),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                ];
+                accounts::check_accounts(program_id, accounts, SPECS)?;
+                let a_signer = &accounts[0];
+                let a_slab = &accounts[1];
 
-                let engine = zc::engine_mut(&mut data)?;
-                check_idx(engine, user_idx)?;
-                let owner = engine.accounts[user_idx as usize].owner;
-                if !crate::policy::owner_ok(owner, a_user.key.to_bytes()) {
-                    return Err(PercolatorError::EngineUnauthorized.into());
+                if action_hash == [0u8; 32] {
+                    return Err(ProgramError::InvalidInstructionData);
                 }
 
-                let (units, dust) = crate::units::base_to_units(amount, config.unit_scale);
-                if units == 0 || dust != 0 {
-                    return Err(ProgramError::InvalidArgument);
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                if state::read_header(&data).admin == [0u8; 32] {
+                    return Err(PercolatorError::EngineUnauthorized.into());
                 }
-                let admit_h_min = engine.params.h_min;
-                let admit_h_max = engine.params.h_max;
-                // Fully accrue market to clock.slot, then make the account
-                // authoritative and fee-current before conversion bounds.
-                ensure_market_accrued_to_now_for_account_limited_op(
-                    engine,
-                    &config,
-                    clock.slot,
-                    price,
-                    funding_rate_e9,
-                )?;
-                reject_any_target_lag(&config, engine)?;
-                settle_account_then_sync_fee_current(
-                    engine,
-                    &config,
-                    user_idx,
-                    clock.slot,
-                    price,
-                    funding_rate_e9,
-                    admit_h_min,
-                    admit_h_max,
-                    Some(engine.params.maintenance_margin_bps as u128),
+                let ms = state::read_admin_multisig(&data);
+                let own = ms
+                    .signer_index(&a_signer.key.to_bytes())
+                    .map(|i| 1u8 << i)
+                    .ok_or(PercolatorError::EngineUnauthorized)?;
+
+                let now = Clock::get()
+                    .map_err(|_| ProgramError::UnsupportedSysvar)?
+                    .slot;
+                let live = |p: &state::PendingAdminAction| {
+                    p.action_hash != [0u8; 32]
+                        && now.saturating_sub(p.created_slot)
+                            <= crate::constants::ADMIN_ACTION_TTL_SLOTS
+                };
+                // Same live action: add the bit. Otherwise take a free or
+                // expired entry. Live entries are never evicted, so one
+                // member cannot wipe the others' proposals by flooding.
+                let mut target = None;
+                let mut reuse = None;
+                for i in 0..crate::constants::MAX_PENDING_ADMIN_ACTIONS {
+                    let p = state::read_pending_admin_action(&data, i);
+                    if !live(&p) {
+                        if reuse.is_none() {
+                            reuse = Some(i);
+                        }
+                    } else if p.action_hash == action_hash {
+                        target = Some((i, p));
+                        break;
+                    }
+                }
+                let (i, mut entry) = match target {
+                    Some(t) => t,
+                    None => (
+                        reuse.ok_or(PercolatorError::AdminActionsFull)?,
+                        state::PendingAdminAction {
+                            action_hash,
+                            approvals: 0,
+                            created_slot: now,
+                        },
+                    ),
+                };
+                entry.approvals |= own;
+                state::write_pending_admin_action(&mut data, i, &entry);
+            }
+            Instruction::SetDustLimits {
+                min_trade_abs,
+                min_position_abs,
+                sweep_lp_idx,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
                 )?;
-                engine
-                    .convert_released_pnl_not_atomic(
-                        user_idx,
-                        units as u128,
-                        price,
-                        clock.slot,
-                        funding_rate_e9,
-                        admit_h_min,
-                        admit_h_max,
-                        Some(engine.params.maintenance_margin_bps as u128),
-                    )
-                    .map_err(map_risk_error)?;
-                if !state::is_oracle_initialized(&data) {
-                    state::set_oracle_initialized(&mut data);
+
+                state::write_dust_limits(
+                    &mut data,
+                    &state::DustLimits {
+                        min_trade_abs,
+                        min_position_abs,
+                        sweep_lp_idx,
+                    },
+                );
+            }
+            Instruction::SetCircuitBreaker {
+                max_move_bps,
+                window_slots,
+                cooldown_slots,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                if max_move_bps != 0 && (window_slots == 0 || cooldown_slots == 0) {
+                    return Err(PercolatorError::InvalidConfigParam.into());
                 }
+                // Reconfiguring re-anchors at the next oracle read and ends
+                // any running cooling-off period.
+                state::write_circuit_breaker(
+                    &mut data,
+                    &state::CircuitBreaker {
+                        max_move_bps,
+                        window_slots,
+                        cooldown_slots,
+                        ..Default::default()
+                    },
+                );
             }
+            Instruction::SetTradeRateLimit {
+                min_slots_between_trades,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
 
-            Instruction::ResolvePermissionless => {
-                // STRICT HARD-TIMEOUT POLICY:
-                //
-                //   clock.slot - last_live_slot >= permissionless_resolve_stale_slots
-                //     → market is stale; anyone resolves at engine.last_oracle_price.
-                //
-                //   last_live_slot is:
-                //     non-Hyperp → config.last_good_oracle_slot
-                //                  (advances on successful external Pyth/Chainlink reads)
-                //     Hyperp     → max(mark_ewma_last_slot, last_mark_push_slot)
-                //                  (advances on full-weight trades and admin mark pushes)
-                //
-                // No challenge window, no oracle account submitted at resolve
-                // time. If no one has fed the market a fresh price for N
-                // slots, it's dead. Settlement is at engine.last_oracle_price
-                // (the last price the engine actually accrued against).
-                accounts::expect_len(accounts, 2)?;
-                let a_slab = &accounts[0];
-                let a_clock = &accounts[1];
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
 
-                accounts::expect_writable(a_slab)?;
+                if min_slots_between_trades > crate::constants::MAX_MIN_SLOTS_BETWEEN_TRADES {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                state::write_min_trade_interval(&mut data, min_slots_between_trades);
+            }
+            Instruction::SetLiquidatorWindow {
+                window_slots,
+                liquidators,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                if !crate::policy::liquidator_window_valid(window_slots, &liquidators) {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
 
-                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
-                    return Err(ProgramError::InvalidAccountData);
+                let mut w = state::LiquidatorWindow {
+                    window_slots,
+                    len: liquidators.len() as u8,
+                    ..state::LiquidatorWindow::default()
+                };
+                w.liquidators[..liquidators.len()].copy_from_slice(&liquidators);
+                state::write_liquidator_window(&mut data, &w);
+            }
+            Instruction::SetBackstop {
+                share_bps,
+                withdraw_delay_slots,
+            } => {
+                use crate::constants::{
+                    MAX_BACKSTOP_SHARE_BPS, MAX_BACKSTOP_WITHDRAW_DELAY_SLOTS,
+                    MIN_BACKSTOP_WITHDRAW_DELAY_SLOTS,
+                };
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                if share_bps > MAX_BACKSTOP_SHARE_BPS
+                    || !(MIN_BACKSTOP_WITHDRAW_DELAY_SLOTS..=MAX_BACKSTOP_WITHDRAW_DELAY_SLOTS)
+                        .contains(&withdraw_delay_slots)
+                {
+                    return Err(PercolatorError::InvalidConfigParam.into());
                 }
 
-                let mut config = state::read_config(&data);
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
 
-                // A post-init cluster-restart (SIMD-0047 LastRestartSlot
-                // bump) freezes the market unconditionally — bypass the
-                // "feature disabled" gate so markets with no slot-based
-                // staleness window can still be resolved after a restart.
-                let restarted = oracle::cluster_restarted_since_init(&config);
-                if !restarted && config.permissionless_resolve_stale_slots == 0 {
+                let mut backstop = state::read_backstop(&data);
+                backstop.share_bps = share_bps;
+                backstop.withdraw_delay_slots = withdraw_delay_slots;
+                state::write_backstop(&mut data, &backstop);
+            }
+            Instruction::SetStaleFallback {
+                grace_slots,
+                ramp_slots,
+                max_haircut_bps,
+            } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                if grace_slots != 0 {
+                    let config = state::read_config(&data);
+                    // Hyperp markets have no external feed; hybrid markets
+                    // already fall back to their EWMA mark.
+                    if oracle::is_hyperp_mode(&config)
+                        || oracle::is_hybrid_after_hours_mode(&config)
+                        || !crate::policy::stale_fallback_valid(
+                            grace_slots,
+                            max_haircut_bps,
+                            config.permissionless_resolve_stale_slots,
+                        )
+                    {
+                        return Err(PercolatorError::InvalidConfigParam.into());
+                    }
+                }
+                let f = if grace_slots == 0 {
+                    state::StaleFallback::default()
+                } else {
+                    state::StaleFallback {
+                        grace_slots,
+                        ramp_slots,
+                        max_haircut_bps,
+                    }
+                };
+                state::write_stale_fallback(&mut data, &f);
+            }
+            Instruction::SetMarketMetadata { metadata } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                if !crate::policy::market_metadata_ok(&metadata) {
                     return Err(PercolatorError::InvalidConfigParam.into());
                 }
 
-                let clock = Clock::from_account_info(a_clock)?;
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
 
-                if !oracle::permissionless_stale_matured(&config, clock.slot) {
-                    return Err(PercolatorError::OracleStale.into());
+                state::write_market_metadata(&mut data, &metadata);
+            }
+            Instruction::InitSlab { max_bytes } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                if max_bytes == 0 {
+                    return Err(ProgramError::InvalidInstructionData);
                 }
 
-                // Degenerate resolve at engine.last_oracle_price. Both
-                // settlement_price and live_oracle_price use P_last (the
-                // engine's stored last-accrued price) — the engine's
-                // Degenerate arm requires equality between the two.
-                //
-                // This is the reason `permissionless_resolve_stale_slots`
-                // is NOT bounded by `MAX_ACCRUAL_DT_SLOTS`: this path does
-                // not call `accrue_market_to`, and passes rate=0. A market
-                // can therefore wait a product-scale stale horizon (capped
-                // at MAX_PERMISSIONLESS_RESOLVE_STALE_SLOTS) and still exit
-                // permissionlessly without violating the live-accrual
-                // envelope. Regression coverage:
-                //
-                //   test_resolve_permissionless_succeeds_after_outage_exceeding_max_accrual_dt
-                //
-                // Kani coverage is wrapper-policy only:
-                //
-                //   kani_permissionless_resolve_horizon_policy_independent_from_accrual_window
-                //
-                // Engine conservation/postconditions are enforced inside
-                // resolve_market_not_atomic and covered by engine tests; the
-                // wrapper proof deliberately does not model engine internals.
-                let engine = zc::engine_mut(&mut data)?;
-                let p_last = engine.last_oracle_price;
-                if p_last == 0 {
-                    return Err(PercolatorError::OracleInvalid.into());
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_shape_guard(program_id, a_slab, &data)?;
+                require_slab_rent_exempt(a_slab, data.len())?;
+                let header = state::read_header(&data);
+                let start = if header.magic == MAGIC {
+                    return Err(PercolatorError::AlreadyInitialized.into());
+                } else if header.magic == crate::constants::INITIALIZING_MAGIC {
+                    require_admin(header.admin, a_admin.key)?;
+                    state::read_init_cursor(&data) as usize
+                } else {
+                    // First chunk: claim the slab before zeroing anything.
+                    let mut h = SlabHeader::zeroed();
+                    h.magic = crate::constants::INITIALIZING_MAGIC;
+                    h.admin = a_admin.key.to_bytes();
+                    state::write_header(&mut data, &h);
+                    crate::constants::HEADER_LEN
+                };
+                let end = start.saturating_add(max_bytes as usize).min(SLAB_LEN);
+                data[start..end].fill(0);
+                state::write_init_cursor(&mut data, end as u64);
+            }
+            Instruction::MigrateSlab => {
+                use accounts::{AccountSpec, Owner};
+                use solana_program::{
+                    entrypoint::MAX_PERMITTED_DATA_INCREASE, program::invoke, system_instruction,
+                };
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("rent payer signer").signer().writable(),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("system program").key(solana_program::system_program::ID),
+                ];
+                accounts::check_accounts(program_id, accounts, SPECS)?;
+                let a_payer = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_system = &accounts[2];
+
+                {
+                    let data = a_slab.try_borrow_data()?;
+                    if !slab_needs_migration(&data) {
+                        // Current slabs pass as a no-op, so racing
+                        // migrators do not fail; anything else rejects.
+                        slab_guard(program_id, a_slab, &data)?;
+                        return require_initialized(&data);
+                    }
                 }
-                engine
-                    .resolve_market_not_atomic(
-                        percolator::ResolveMode::Degenerate,
-                        p_last,
-                        p_last,
-                        clock.slot,
-                        0,
-                    )
-                    .map_err(map_risk_error)?;
 
-                config.hyperp_mark_e6 = p_last;
-                state::write_config(&mut data, &config);
+                let new_len = (a_slab.data_len() + MAX_PERMITTED_DATA_INCREASE).min(SLAB_LEN);
+                let rent = solana_program::sysvar::rent::Rent::get()?.minimum_balance(new_len);
+                let shortfall = rent.saturating_sub(a_slab.lamports());
+                if shortfall > 0 {
+                    invoke(
+                        &system_instruction::transfer(a_payer.key, a_slab.key, shortfall),
+                        &[a_payer.clone(), a_slab.clone(), a_system.clone()],
+                    )?;
+                }
+                a_slab.realloc(new_len, true)?;
+                if new_len == SLAB_LEN {
+                    let mut data = state::slab_data_mut(a_slab)?;
+                    seed_v1_tail(&mut data)?;
+                }
             }
-
-            Instruction::ForceCloseResolved { user_idx } => {
-                // Permissionless force-close for resolved markets.
-                // Mirrors AdminForceCloseAccount but requires delay and no admin.
-                accounts::expect_len(accounts, 6)?;
-                let a_slab = &accounts[0];
-                let a_vault = &accounts[1];
-                let a_owner_ata = &accounts[2];
-                let a_pda = &accounts[3];
+            Instruction::BackstopDeposit { amount } => {
+                // Same accounts as DepositCollateral. No oracle read: shares
+                // are priced at the vault's equity at the engine's last
+                // price, and the withdrawal delay keeps a depositor from
+                // cashing in a move the engine has not seen yet.
+                accounts::check_accounts(program_id, accounts, accounts::DEPOSIT)?;
+                let a_user = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_user_ata = &accounts[2];
+                let a_vault = &accounts[3];
                 let a_token = &accounts[4];
                 let a_clock = &accounts[5];
 
-                accounts::expect_writable(a_slab)?;
                 verify_token_program(a_token)?;
+                if amount == 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
 
                 let mut data = state::slab_data_mut(a_slab)?;
                 slab_guard(program_id, a_slab, &data)?;
                 require_initialized(&data)?;
+                reject_withdraw_only(&data)?;
+                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+                    return Err(ProgramError::InvalidAccountData);
+                }
 
-                let resolved_slot = {
-                    let eng = zc::engine_ref(&data)?;
-                    if !engine_is_resolved(eng) {
-                        return Err(ProgramError::InvalidAccountData);
+                let config = state::read_config(&data);
+                let mint = Pubkey::new_from_array(config.collateral_mint);
+                let auth = accounts::derive_vault_authority_with_bump(
+                    program_id,
+                    a_slab.key,
+                    config.vault_authority_bump,
+                )?;
+                verify_vault(
+                    a_vault,
+                    &auth,
+                    &mint,
+                    &Pubkey::new_from_array(config.vault_pubkey),
+                )?;
+                verify_token_account(a_user_ata, a_user.key, &mint)?;
+
+                let clock = Clock::from_account_info(a_clock)?;
+                if oracle::permissionless_stale_matured(&config, clock.slot) {
+                    return Err(PercolatorError::OracleStale.into());
+                }
+                check_no_oracle_live_envelope(zc::engine_ref(&data)?, clock.slot)?;
+
+                let (units, dust) = crate::units::base_to_units(amount, config.unit_scale);
+                if dust != 0 || units == 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                if config.tvl_insurance_cap_mult > 0 {
+                    let engine_r = zc::engine_ref(&data)?;
+                    let ins = engine_r.insurance_fund.balance.get();
+                    let c_tot_new = engine_r.c_tot.get().saturating_add(units as u128);
+                    let cap = ins.saturating_mul(config.tvl_insurance_cap_mult as u128);
+                    if c_tot_new > cap {
+                        // ctx: c_tot after deposit, cap
+                        return Err(fail(
+                            PercolatorError::DepositCapExceeded,
+                            &[clamp_u64(c_tot_new), clamp_u64(cap)],
+                        ));
+                    }
+                }
+
+                collateral::deposit(a_token, a_user_ata, a_vault, a_user, amount)?;
+
+                let mut backstop = state::read_backstop(&data);
+                let vault = backstop_vault_idx(&data);
+                let engine = zc::engine_mut(&mut data)?;
+                let shares = match vault {
+                    Some(idx) if idx_used_in_market(engine, idx as usize) => {
+                        sync_account_fee_bounded_to_market(engine, &config, idx, clock.slot)?;
+                        let equity = account_health(engine, idx, engine.last_oracle_price)?
+                            .equity()
+                            .max(0) as u128;
+                        // A vault whose shares back nothing cannot take
+                        // new money at par; it must be emptied first.
+                        let shares = crate::policy::lp_shares_for_deposit(
+                            units as u128,
+                            backstop.total_shares,
+                            equity,
+                        )
+                        .ok_or(PercolatorError::EngineUndercollateralized)?;
+                        engine
+                            .deposit_not_atomic(idx, units as u128, clock.slot)
+                            .map_err(map_risk_error)?;
+                        shares
+                    }
+                    _ => {
+                        // First deposit, or the old vault account was
+                        // reclaimed empty: start a fresh vault whose
+                        // shares are 1:1 with units.
+                        let idx = prepare_lazy_free_head(engine)?;
+                        engine
+                            .deposit_not_atomic(idx, units as u128, clock.slot)
+                            .map_err(map_risk_error)?;
+                        let (owner, _) =
+                            accounts::derive_backstop_authority(program_id, a_slab.key);
+                        set_wrapper_owner(engine, idx, owner.to_bytes())?;
+                        engine.accounts[idx as usize].kind = percolator::Account::KIND_LP;
+                        let equity = booked_equity(engine, idx);
+                        let gen = state::next_mat_counter(&mut data)
+                            .ok_or(PercolatorError::EngineOverflow)?;
+                        state::write_account_generation(&mut data, idx, gen);
+                        state::write_order_ids(
+                            &mut data,
+                            idx,
+                            &[0; crate::constants::ORDER_ID_RING],
+                        );
+                        state::write_last_trade_slot(&mut data, idx, 0);
+                        state::write_liq_flag_slot(&mut data, idx, 0);
+                        open_epoch_stats(&mut data, idx, equity);
+                        open_pnl_ledger(&mut data, idx);
+                        state::clear_backstop_depositors(&mut data);
+                        backstop.vault_idx = idx;
+                        backstop.generation = gen;
+                        backstop.total_shares = 0;
+                        backstop.closed_units = 0;
+                        units as u128
                     }
-                    engine_resolved_context(eng).1
                 };
+                if shares == 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                let owner = a_user.key.to_bytes();
+                let i = state::find_backstop_depositor(&data, &owner)
+                    .ok_or(PercolatorError::BackstopFull)?;
+                let mut d = state::read_backstop_depositor(&data, i);
+                d.owner = owner;
+                d.shares = d
+                    .shares
+                    .checked_add(shares)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                backstop.total_shares = backstop
+                    .total_shares
+                    .checked_add(shares)
+                    .ok_or(PercolatorError::EngineOverflow)?;
+                state::write_backstop_depositor(&mut data, i, &d);
+                state::write_backstop(&mut data, &backstop);
+            }
+            Instruction::BackstopRequestWithdraw { shares } => {
+                use accounts::{AccountSpec, Owner};
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("depositor signer").signer(),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+                ];
+                accounts::check_accounts(program_id, accounts, SPECS)?;
+                let a_user = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_clock = &accounts[2];
 
-                let config = state::read_config(&data);
-                if config.force_close_delay_slots == 0 {
-                    return Err(PercolatorError::InvalidConfigParam.into());
+                if shares == 0 {
+                    return Err(ProgramError::InvalidArgument);
                 }
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
                 let clock = Clock::from_account_info(a_clock)?;
-                if !crate::policy::force_close_delay_elapsed(
-                    clock.slot,
-                    resolved_slot,
-                    config.force_close_delay_slots,
-                ) {
-                    return Err(ProgramError::InvalidAccountData);
+
+                let owner = a_user.key.to_bytes();
+                let i = state::find_backstop_depositor(&data, &owner)
+                    .ok_or(PercolatorError::EngineAccountNotFound)?;
+                let mut d = state::read_backstop_depositor(&data, i);
+                if d.is_free() || d.shares < shares {
+                    // ctx: shares held, shares requested
+                    return Err(fail(
+                        PercolatorError::EngineInsufficientBalance,
+                        &[clamp_u64(d.shares), clamp_u64(shares)],
+                    ));
                 }
+                d.shares -= shares;
+                d.pending_shares += shares;
+                d.request_slot = clock.slot;
+                state::write_backstop_depositor(&mut data, i, &d);
+            }
+            Instruction::BackstopWithdraw => {
+                // Same layout as WithdrawCollateral, without the nonce. The
+                // oracle tail is only read while the market is live.
+                accounts::check_account_head(program_id, accounts, accounts::WITHDRAW_HEAD)?;
+                let a_user = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_vault = &accounts[2];
+                let a_user_ata = &accounts[3];
+                let a_vault_pda = &accounts[4];
+                let a_token = &accounts[5];
+                let a_clock = &accounts[6];
 
+                verify_token_program(a_token)?;
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                let mut config = state::read_config(&data);
                 let mint = Pubkey::new_from_array(config.collateral_mint);
-                let auth = accounts::derive_vault_authority_with_bump(
+                let derived_pda = accounts::derive_vault_authority_with_bump(
                     program_id,
                     a_slab.key,
                     config.vault_authority_bump,
                 )?;
+                accounts::expect_key(a_vault_pda, &derived_pda)?;
                 verify_vault(
                     a_vault,
-                    &auth,
+                    &derived_pda,
                     &mint,
                     &Pubkey::new_from_array(config.vault_pubkey),
                 )?;
-                accounts::expect_key(a_pda, &auth)?;
-
-                let engine = zc::engine_mut(&mut data)?;
-                let (price, _resolved_slot) = engine_resolved_context(engine);
-                if price == 0 {
-                    return Err(ProgramError::InvalidAccountData);
-                }
-                check_idx(engine, user_idx)?;
+                verify_token_account(a_user_ata, a_user.key, &mint)?;
 
-                let owner_pubkey = Pubkey::new_from_array(engine.accounts[user_idx as usize].owner);
+                let clock = Clock::from_account_info(a_clock)?;
+                let owner = a_user.key.to_bytes();
+                let i = state::find_backstop_depositor(&data, &owner)
+                    .ok_or(PercolatorError::EngineAccountNotFound)?;
+                let mut d = state::read_backstop_depositor(&data, i);
+                let mut backstop = state::read_backstop(&data);
+                let vault = backstop_vault_idx(&data);
+                let resolved = engine_is_resolved(zc::engine_ref(&data)?);
 
-                let amt_units = match engine
-                    .force_close_resolved_with_fee_not_atomic(
-                        user_idx,
-                        config.maintenance_fee_per_slot,
+                let (redeemed, units) = if resolved {
+                    // Resolution ends the vault's exposure, so the delay no
+                    // longer protects anyone: the first withdrawal closes
+                    // the vault account and every depositor takes a pro-rata
+                    // part of its payout.
+                    if d.is_free() {
+                        return Err(PercolatorError::BackstopWithdrawLocked.into());
+                    }
+                    if let Some(idx) = vault {
+                        let engine = zc::engine_mut(&mut data)?;
+                        let payout = match engine
+                            .force_close_resolved_with_fee_not_atomic(
+                                idx,
+                                config.maintenance_fee_per_slot,
+                            )
+                            .map_err(map_risk_error)?
+                        {
+                            percolator::ResolvedCloseResult::ProgressOnly => return Ok(()),
+                            percolator::ResolvedCloseResult::Closed(payout) => payout,
+                        };
+                        backstop.closed_units = payout
+                            .try_into()
+                            .map_err(|_| PercolatorError::EngineOverflow)?;
+                        backstop.generation = 0;
+                        let mut buf = state::read_risk_buffer(&data);
+                        buf.remove(idx);
+                        state::write_risk_buffer(&mut data, &buf);
+                    }
+                    let redeemed = d.shares.saturating_add(d.pending_shares);
+                    let units = crate::policy::lp_redeem_amount(
+                        redeemed,
+                        backstop.total_shares,
+                        backstop.closed_units as u128,
                     )
-                    .map_err(map_risk_error)?
-                {
-                    percolator::ResolvedCloseResult::ProgressOnly => return Ok(()),
-                    percolator::ResolvedCloseResult::Closed(payout) => payout,
+                    .ok_or(PercolatorError::EngineCorruptState)?;
+                    backstop.closed_units -= units as u64;
+                    d.shares = 0;
+                    (redeemed, units)
+                } else {
+                    let delay = backstop.effective_withdraw_delay();
+                    if d.is_free()
+                        || d.pending_shares == 0
+                        || !crate::policy::lp_redeem_unlocked(d.request_slot, clock.slot, delay)
+                    {
+                        // ctx: request slot, delay, now
+                        return Err(fail(
+                            PercolatorError::BackstopWithdrawLocked,
+                            &[d.request_slot, delay, clock.slot],
+                        ));
+                    }
+                    let redeemed = d.pending_shares;
+                    let units = match vault {
+                        Some(idx) => {
+                            expect_len_with_oracles(accounts, 8, &config)?;
+                            let oracle_count = oracle_account_count(&config)?;
+                            let oracle_accounts = &accounts[7..7 + oracle_count];
+                            let withdraw_only = state::read_crank_guard(&data).withdraw_only;
+                            let funding_rate_e9 = compute_current_funding_rate_e9(&config)?;
+                            let price = if withdraw_only {
+                                zc::engine_ref(&data)?.last_oracle_price
+                            } else {
+                                let px = if oracle::is_hyperp_mode(&config) {
+                                    let eng = zc::engine_ref(&data)?;
+                                    let p_last = eng.last_oracle_price;
+                                    let price_move_dt = price_move_residual_dt(eng, clock.slot)?;
+                                    let cap_bps = eng.params.max_price_move_bps_per_slot;
+                                    let oi_any = eng.oi_eff_long_q != 0 || eng.oi_eff_short_q != 0;
+                                    oracle::get_engine_oracle_price_e6(
+                                        p_last,
+                                        price_move_dt,
+                                        clock.slot,
+                                        clock.unix_timestamp,
+                                        &mut config,
+                                        oracle_accounts,
+                                        cap_bps,
+                                        oi_any,
+                                    )?
+                                } else {
+                                    read_price_and_stamp(
+                                        &mut config,
+                                        oracle_accounts,
+                                        clock.unix_timestamp,
+                                        clock.slot,
+                                        &mut data,
+                                    )?
+                                };
+                                state::write_config(&mut data, &config);
+                                px
+                            };
+                            let haircut_bps = state::read_stale_fallback(&data)
+                                .haircut_bps(clock.slot, config.last_good_oracle_slot);
+                            let engine = zc::engine_mut(&mut data)?;
+                            let withdraw_slot = if withdraw_only {
+                                core::cmp::max(engine.current_slot, engine.last_market_slot)
+                            } else {
+                                clock.slot
+                            };
+                            let admit_h_min = engine.params.h_min;
+                            let admit_h_max = engine.params.h_max;
+                            let admit_threshold =
+                                Some(engine.params.maintenance_margin_bps as u128);
+                            if !withdraw_only {
+                                ensure_market_accrued_to_now_for_account_limited_op(
+                                    engine,
+                                    &config,
+                                    clock.slot,
+                                    price,
+                                    funding_rate_e9,
+                                )?;
+                                reject_any_target_lag(&config, engine)?;
+                            }
+                            settle_account_then_sync_fee_current(
+                                engine,
+                                &config,
+                                idx,
+                                withdraw_slot,
+                                price,
+                                funding_rate_e9,
+                                admit_h_min,
+                                admit_h_max,
+                                admit_threshold,
+                            )?;
+                            let equity = booked_equity(engine, idx);
+                            let units = crate::policy::lp_redeem_amount(
+                                redeemed,
+                                backstop.total_shares,
+                                equity.max(0) as u128,
+                            )
+                            .ok_or(PercolatorError::EngineCorruptState)?;
+                            let pos = effective_pos_q_checked(engine, idx as usize)?;
+                            if haircut_bps != 0 && pos != 0 {
+                                // Stale fallback: the vault keeps the same
+                                // haircut as any open account.
+                                let keep = crate::policy::mul_div_ceil(
+                                    risk_notional_ceil(pos, price),
+                                    haircut_bps as u128,
+                                    10_000,
+                                )
+                                .ok_or(PercolatorError::EngineOverflow)?;
+                                let equity_after = equity.saturating_sub(units as i128);
+                                if equity_after < keep.min(i128::MAX as u128) as i128 {
+                                    // ctx: haircut bps, equity to keep
+                                    return Err(fail(
+                                        PercolatorError::OracleFallbackActive,
+                                        &[haircut_bps as u64, clamp_u64(keep)],
+                                    ));
+                                }
+                            }
+                            if units > 0 {
+                                engine
+                                    .withdraw_not_atomic(
+                                        idx,
+                                        units,
+                                        price,
+                                        withdraw_slot,
+                                        funding_rate_e9,
+                                        admit_h_min,
+                                        admit_h_max,
+                                        admit_threshold,
+                                    )
+                                    .map_err(map_risk_error)?;
+                            }
+                            refresh_liq_watch(&mut data, &[idx], price)?;
+                            if !state::is_oracle_initialized(&data) {
+                                state::set_oracle_initialized(&mut data);
+                            }
+                            units
+                        }
+                        // The vault account was reclaimed empty: its shares
+                        // back nothing and are burned.
+                        None => 0,
+                    };
+                    (redeemed, units)
                 };
-
-                // Only verify owner ATA when there's a nonzero payout.
-                if amt_units > 0 {
-                    verify_token_account(a_owner_ata, &owner_pubkey, &mint)?;
-                }
-
-                let amt_units_u64: u64 = amt_units
+                d.pending_shares = 0;
+                backstop.total_shares = backstop
+                    .total_shares
+                    .checked_sub(redeemed)
+                    .ok_or(PercolatorError::EngineCorruptState)?;
+                state::write_backstop_depositor(&mut data, i, &d);
+                state::write_backstop(&mut data, &backstop);
+
+                let units: u64 = units
                     .try_into()
                     .map_err(|_| PercolatorError::EngineOverflow)?;
-
-                // Remove from risk buffer before withdraw
-                {
-                    let mut buf = state::read_risk_buffer(&data);
-                    buf.remove(user_idx);
-                    state::write_risk_buffer(&mut data, &buf);
-                }
-
-                let base_to_pay =
-                    crate::units::units_to_base_checked(amt_units_u64, config.unit_scale)
-                        .ok_or(PercolatorError::EngineOverflow)?;
-
+                let base_to_pay = crate::units::units_to_base_checked(units, config.unit_scale)
+                    .ok_or(PercolatorError::EngineOverflow)?;
                 let seed1: &[u8] = b"vault";
                 let seed2: &[u8] = a_slab.key.as_ref();
                 let bump_arr: [u8; 1] = [config.vault_authority_bump];
                 let seed3: &[u8] = &bump_arr;
                 let seeds: [&[u8]; 3] = [seed1, seed2, seed3];
                 let signer_seeds: [&[&[u8]]; 1] = [&seeds];
-
                 collateral::withdraw(
                     a_token,
                     a_vault,
-                    a_owner_ata,
-                    a_pda,
+                    a_user_ata,
+                    a_vault_pda,
                     base_to_pay,
                     &signer_seeds,
                 )?;
             }
+            Instruction::AddHyperpPusher { pusher } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_authority = &accounts[0];
+                let a_slab = &accounts[1];
 
-            Instruction::UpdateAuthority { kind, new_pubkey } => {
-                handle_update_authority(program_id, accounts, kind, new_pubkey)?;
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                if zc::engine_ref(&data)?.market_mode == percolator::MarketMode::Resolved {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                let config = state::read_config(&data);
+                if !oracle::is_hyperp_mode(&config) {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                require_admin(config.hyperp_authority, a_authority.key)?;
+
+                let key = pusher.to_bytes();
+                if key == [0u8; 32]
+                    || state::find_hyperp_pusher(&data, &config.hyperp_authority, &key).is_some()
+                {
+                    return Err(PercolatorError::InvalidConfigParam.into());
+                }
+                let free = (1..crate::constants::MAX_HYPERP_PUSHERS)
+                    .find(|&i| state::read_hyperp_pusher(&data, i).is_free())
+                    .ok_or(PercolatorError::HyperpPushersFull)?;
+                state::write_hyperp_pusher(
+                    &mut data,
+                    free,
+                    &state::HyperpPusher {
+                        key,
+                        ..state::HyperpPusher::default()
+                    },
+                );
+            }
+
+            Instruction::RemoveHyperpPusher { pusher } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_authority = &accounts[0];
+                let a_slab = &accounts[1];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                let config = state::read_config(&data);
+                require_admin(config.hyperp_authority, a_authority.key)?;
+
+                // Entry 0 is the authority itself, which rotates through
+                // UpdateAuthority instead.
+                match state::find_hyperp_pusher(&data, &config.hyperp_authority, &pusher.to_bytes())
+                {
+                    Some(i) if i > 0 => {
+                        state::write_hyperp_pusher(&mut data, i, &state::HyperpPusher::default())
+                    }
+                    _ => return Err(PercolatorError::InvalidConfigParam.into()),
+                }
+            }
+
+            Instruction::DepositWithPermit {
+                user_idx,
+                amount,
+                nonce,
+                expiry,
+                allowlist_proof,
+            } => {
+                // DepositCollateral's accounts with the owner replaced by
+                // any relayer, plus the permit PDA and instructions sysvar.
+                use accounts::{AccountSpec, Owner};
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("relayer"),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("owner token account"),
+                    AccountSpec::new("vault token account"),
+                    AccountSpec::new("token program"),
+                    AccountSpec::new("clock sysvar").key(solana_program::sysvar::clock::ID),
+                    AccountSpec::new("permit PDA"),
+                    AccountSpec::new("instructions sysvar"),
+                ];
+                accounts::check_accounts(program_id, accounts, SPECS)?;
+                let a_slab = &accounts[1];
+                let a_user_ata = &accounts[2];
+                let a_vault = &accounts[3];
+                let a_token = &accounts[4];
+                let a_clock = &accounts[5];
+                let a_permit = &accounts[6];
+                let a_ix_sysvar = &accounts[7];
+
+                verify_token_program(a_token)?;
+                if amount == 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                let (permit_pda, permit_bump) =
+                    accounts::derive_permit_authority(program_id, a_slab.key);
+                accounts::expect_key(a_permit, &permit_pda)?;
+
+                let clock = Clock::from_account_info(a_clock)?;
+                if clock.unix_timestamp > expiry {
+                    // ctx: expiry, clock
+                    return Err(fail(
+                        PercolatorError::PermitExpired,
+                        &[expiry as u64, clock.unix_timestamp as u64],
+                    ));
+                }
+                let payload = crate::policy::permit_deposit_payload(
+                    &a_slab.key.to_bytes(),
+                    user_idx,
+                    amount,
+                    nonce,
+                    expiry,
+                );
+                let owner =
+                    Pubkey::new_from_array(verify_preceding_ed25519(a_ix_sysvar, &payload)?);
+
+                let seeds: &[&[u8]] = &[b"permit", a_slab.key.as_ref(), &[permit_bump]];
+                deposit_for_owner(
+                    program_id,
+                    a_slab,
+                    a_user_ata,
+                    a_vault,
+                    a_clock,
+                    &owner,
+                    user_idx,
+                    amount,
+                    Some(nonce),
+                    &allowlist_proof,
+                    // The permit PDA signs as the owner's SPL delegate.
+                    || {
+                        collateral::withdraw(
+                            a_token,
+                            a_user_ata,
+                            a_vault,
+                            a_permit,
+                            amount,
+                            &[seeds],
+                        )
+                    },
+                )?;
+            }
+
+            Instruction::RegisterMarket { index } => {
+                use accounts::{AccountSpec, Owner};
+                const SPECS: &[AccountSpec] = &[
+                    AccountSpec::new("admin signer").signer(),
+                    AccountSpec::new("writable program-owned slab")
+                        .writable()
+                        .owner(Owner::Program),
+                    AccountSpec::new("writable market pointer PDA").writable(),
+                    AccountSpec::new("system program").key(solana_program::system_program::ID),
+                ];
+                // Optional 5th account: the rent payer, when not the admin.
+                let ix_accounts = accounts;
+                let (accounts, sponsor) = match accounts {
+                    [head @ .., payer] if head.len() == SPECS.len() => (head, Some(payer)),
+                    _ => (accounts, None),
+                };
+                accounts::check_accounts(program_id, accounts, SPECS)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+                let a_pointer = &accounts[2];
+                let a_system = &accounts[3];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    ix_accounts,
+                    instruction_data,
+                )?;
+                let config = state::read_config(&data);
+                drop(data);
+
+                let (expected, bump) = accounts::derive_market(
+                    program_id,
+                    &config.collateral_mint,
+                    &config.index_feed_id,
+                    index,
+                );
+                accounts::expect_key(a_pointer, &expected)?;
+                if !a_pointer.data_is_empty() {
+                    return Err(PercolatorError::AlreadyInitialized.into());
+                }
+                create_pda_account(
+                    program_id,
+                    rent_payer(a_admin, sponsor)?,
+                    a_pointer,
+                    a_system,
+                    crate::constants::MARKET_POINTER_LEN,
+                    &[
+                        b"market",
+                        &config.collateral_mint,
+                        &config.index_feed_id,
+                        &index.to_le_bytes(),
+                        &[bump],
+                    ],
+                )?;
+                let pointer = state::MarketPointer {
+                    slab: a_slab.key.to_bytes(),
+                    bump,
+                };
+                a_pointer
+                    .try_borrow_mut_data()?
+                    .copy_from_slice(&pointer.encode());
+            }
+            Instruction::ProposeAdmin { new_admin } => {
+                accounts::check_accounts(program_id, accounts, accounts::ADMIN_SLAB)?;
+                let a_admin = &accounts[0];
+                let a_slab = &accounts[1];
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+                require_admin_action(
+                    &mut data,
+                    a_slab.key,
+                    a_admin.key,
+                    accounts,
+                    instruction_data,
+                )?;
+
+                state::write_pending_admin(&mut data, &new_admin.to_bytes());
+            }
+            Instruction::AcceptAdmin => {
+                accounts::expect_len(accounts, 2)?;
+                let a_new = &accounts[0];
+                let a_slab = &accounts[1];
+
+                accounts::expect_signer(a_new)?;
+                accounts::expect_writable(a_slab)?;
+
+                let mut data = state::slab_data_mut(a_slab)?;
+                slab_guard(program_id, a_slab, &data)?;
+                require_initialized(&data)?;
+
+                let pending = state::read_pending_admin(&data);
+                // A burned admin cannot be revived by an old nomination.
+                let mut header = state::read_header(&data);
+                if pending == [0u8; 32]
+                    || header.admin == [0u8; 32]
+                    || pending != a_new.key.to_bytes()
+                {
+                    return Err(PercolatorError::EngineUnauthorized.into());
+                }
+                // Same maturity gate as a non-burn UpdateAuthority.
+                let clock = Clock::get().map_err(|_| ProgramError::UnsupportedSysvar)?;
+                if oracle::permissionless_stale_matured(&state::read_config(&data), clock.slot) {
+                    return Err(PercolatorError::OracleStale.into());
+                }
+
+                header.admin = pending;
+                state::write_header(&mut data, &header);
+                state::write_pending_admin(&mut data, &[0u8; 32]);
+                state::write_admin_multisig(&mut data, &state::AdminMultisig::default());
+                for i in 0..crate::constants::MAX_PENDING_ADMIN_ACTIONS {
+                    state::write_pending_admin_action(
+                        &mut data,
+                        i,
+                        &state::PendingAdminAction::default(),
+                    );
+                }
             }
         }
         Ok(())
     }
 }
 
+// 9b. mod simulate
+/// Off-chain previews that run the processor's own engine path.
+///
+/// The caller supplies the fetched slab bytes and an aligned scratch buffer
+/// (at least `SLAB_LEN`, 16-byte aligned, e.g. backed by `[u128]`); the slab
+/// is copied in and the trade executes against the copy, so nothing here
+/// allocates or needs `std`. `price` is the effective oracle price the
+/// handler would read at `now_slot` (for Hyperp markets, after the
+/// per-slot clamp); fills, fees and post-trade state then match the
+/// on-chain TradeNoCpi exactly.
+pub mod simulate {
+    use crate::{
+        constants::{MAGIC, SLAB_LEN},
+        error::PercolatorError,
+        processor, state,
+    };
+    use solana_program::program_error::ProgramError;
+
+    /// One side's state after the simulated trade.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct AccountHealth {
+        pub position_q: i128,
+        pub capital: u128,
+        /// Stored PnL plus the K/F PnL and funding the next touch books.
+        pub pnl: i128,
+        /// `|position_q| * price / POS_SCALE`, rounded up as in the crank.
+        pub notional: u128,
+        pub maintenance_req: u128,
+        pub initial_req: u128,
+    }
+
+    impl AccountHealth {
+        /// `capital + pnl`.
+        pub fn equity(&self) -> i128 {
+            (self.capital.min(i128::MAX as u128) as i128).saturating_add(self.pnl)
+        }
+
+        pub fn above_maintenance(&self) -> bool {
+            self.equity() >= self.maintenance_req.min(i128::MAX as u128) as i128
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct TradePreview {
+        /// Filled size from the user's side, after any reduce-only clamp.
+        pub size: i128,
+        pub exec_price: u64,
+        pub oracle_price: u64,
+        pub fee_bps: u64,
+        /// Insurance delta across the fill, as the handler measures it.
+        pub fee_paid: u128,
+        pub user: AccountHealth,
+        pub lp: AccountHealth,
+    }
+
+    /// Hypothetical `TradeNoCpi` against a snapshot. Rejections surface as
+    /// the same `ProgramError` the program would return; signer checks
+    /// are out of scope.
+    #[allow(clippy::too_many_arguments)]
+    pub fn simulate_trade_nocpi(
+        slab: &[u8],
+        scratch: &mut [u8],
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        exec_price_e6: u64,
+        flags: u8,
+        now_slot: u64,
+        price: u64,
+    ) -> Result<TradePreview, ProgramError> {
+        if slab.len() != SLAB_LEN || scratch.len() < SLAB_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if state::read_header(slab).magic != MAGIC {
+            return Err(PercolatorError::NotInitialized.into());
+        }
+        let scratch = &mut scratch[..SLAB_LEN];
+        scratch.copy_from_slice(slab);
+        let config = state::read_config(scratch);
+        let guards = processor::TradeGuards::load(
+            scratch,
+            user_idx,
+            now_slot,
+            price,
+            config.last_good_oracle_slot,
+        );
+        let engine = crate::zc::engine_mut(scratch)?;
+        processor::preview_trade_nocpi(
+            engine,
+            &config,
+            lp_idx,
+            user_idx,
+            size,
+            exec_price_e6,
+            flags,
+            now_slot,
+            price,
+            &guards,
+        )
+    }
+
+    /// QueryHealth against a snapshot: the same `HealthSnapshot` the
+    /// instruction returns, so `encode()` matches its return data byte for
+    /// byte. `slab` must be 16-byte aligned (copied into a `[u128]`).
+    pub fn query_health(slab: &[u8], idx: u16) -> Result<crate::ix::HealthSnapshot, ProgramError> {
+        if slab.len() != SLAB_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if state::read_header(slab).magic != MAGIC {
+            return Err(PercolatorError::NotInitialized.into());
+        }
+        processor::health_snapshot(crate::zc::engine_ref(slab)?, idx)
+    }
+
+    /// `policy::liquidation_price_e6` for `idx`, from its QueryHealth
+    /// figures, the market's current funding rate and maintenance fee.
+    /// Same alignment requirement as [`query_health`].
+    pub fn liquidation_price(
+        slab: &[u8],
+        idx: u16,
+        horizon_slots: u64,
+    ) -> Result<Option<u64>, ProgramError> {
+        let h = query_health(slab, idx)?;
+        let params = &crate::zc::engine_ref(slab)?.params;
+        let config = state::read_config(slab);
+        let funding_rate_e9 = crate::policy::funding_rate_e9_from_mark_index(
+            config.mark_ewma_e6,
+            config.last_effective_price_e6,
+            config.funding_horizon_slots,
+            config.funding_k_bps,
+            config.funding_max_premium_bps,
+            config.funding_max_e9_per_slot,
+        )
+        .ok_or(PercolatorError::InvalidConfigParam)?;
+        Ok(crate::policy::liquidation_price_e6(
+            &crate::policy::LiqPriceInputs {
+                position_q: h.position_q,
+                equity: h.equity,
+                price_e6: h.price_e6,
+                maintenance_margin_bps: params.maintenance_margin_bps,
+                min_nonzero_mm_req: params.min_nonzero_mm_req,
+                funding_rate_e9,
+                maintenance_fee_per_slot: config.maintenance_fee_per_slot,
+                horizon_slots,
+            },
+        ))
+    }
+}
+
 // 10. mod entrypoint
 #[cfg(all(not(feature = "no-entrypoint"), not(feature = "anchor-v2")))]
 pub mod entrypoint {
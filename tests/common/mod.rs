@@ -15,38 +15,18 @@ pub use solana_sdk::{
 pub use spl_token::state::{Account as TokenAccount, AccountState};
 pub use std::path::PathBuf;
 
-// BPF-target SLAB_LEN values, cfg-gated by the wrapper's deployment-
-// size feature. u128 aligns to 8 on sbf (vs 16 on x86_64), so these
-// values cannot be derived from `percolator_prog::constants::SLAB_LEN`
-// at native compile time — they are BPF-target-specific and observed
-// empirically against the compiled BPF binary.
+// BPF-target slab length and engine offsets. u128 aligns to 8 on sbf
+// (vs 16 on x86_64), so these cannot be derived from native `size_of`;
+// they come from `state::offsets::sbf`, which the sbf build checks
+// against the compiled layout. Both are cfg-gated by the wrapper's
+// deployment-size feature.
 //
 // Run `cargo test --features small` for MAX_ACCOUNTS=256, `cargo test
 // --features medium` for MAX_ACCOUNTS=1024, or no flag for the
 // default MAX_ACCOUNTS=4096.
-#[cfg(all(feature = "small", not(feature = "medium")))]
-pub const SLAB_LEN: usize = 111520;
-#[cfg(all(feature = "small", not(feature = "medium")))]
-pub const MAX_ACCOUNTS: usize = 256;
-
-#[cfg(all(feature = "medium", not(feature = "small")))]
-pub const SLAB_LEN: usize = 440320;
-#[cfg(all(feature = "medium", not(feature = "small")))]
-pub const MAX_ACCOUNTS: usize = 1024;
-
-#[cfg(not(any(feature = "small", feature = "medium")))]
-pub const SLAB_LEN: usize = 1755520;
-#[cfg(not(any(feature = "small", feature = "medium")))]
-pub const MAX_ACCOUNTS: usize = 4096;
-
-// BPF-target offsets within RiskEngine — cfg-gated because the
-// bitmap and free_list arrays scale with MAX_ACCOUNTS. The BITMAP
-// offset itself is tier-independent (fields before it are size-
-// invariant); NUM_USED_OFFSET = BITMAP + bitmap_size; ACCOUNTS_OFFSET
-// = NUM_USED + 4 (num_used_accounts u16 + free_head u16) + 4 (align
-// padding to 8) + 2 × MAX_ACCOUNTS × 2 (next_free + prev_free u16
-// arrays). Values observed against the compiled BPF binary for each
-// tier.
+pub const SLAB_LEN: usize = sbf_offsets::SLAB_LEN;
+pub const MAX_ACCOUNTS: usize = percolator::MAX_ACCOUNTS;
+
 /// Per-slot price-move cap (standard bps, 100 = 1%) used by every
 /// default test fixture. Sized so the engine's §1.4 solvency envelope
 /// holds with maintenance_margin_bps=500, liquidation_fee_bps=50,
@@ -62,25 +42,12 @@ pub const DEFAULT_NEW_ACCOUNT_FEE: u64 = 1;
 pub const DEFAULT_INIT_PAYMENT: u64 = 100;
 pub const DEFAULT_INIT_CAPITAL: u64 = DEFAULT_INIT_PAYMENT - DEFAULT_NEW_ACCOUNT_FEE;
 
-// SBF-target RiskEngine offsets. These are not derived from native
-// `size_of` because host u128 alignment differs from SBF.
-pub const ENGINE_OFFSET: usize = 664;
-pub const ENGINE_BITMAP_OFFSET: usize = 1088;
-
-#[cfg(all(feature = "small", not(feature = "medium")))]
-pub const ENGINE_NUM_USED_OFFSET: usize = 1120;
-#[cfg(all(feature = "small", not(feature = "medium")))]
-pub const ENGINE_ACCOUNTS_OFFSET: usize = 2152;
-
-#[cfg(all(feature = "medium", not(feature = "small")))]
-pub const ENGINE_NUM_USED_OFFSET: usize = 1216;
-#[cfg(all(feature = "medium", not(feature = "small")))]
-pub const ENGINE_ACCOUNTS_OFFSET: usize = 5320;
-
-#[cfg(not(any(feature = "small", feature = "medium")))]
-pub const ENGINE_NUM_USED_OFFSET: usize = 1600;
-#[cfg(not(any(feature = "small", feature = "medium")))]
-pub const ENGINE_ACCOUNTS_OFFSET: usize = 17992;
+// SBF-target RiskEngine offsets, relative to ENGINE_OFFSET.
+pub use percolator_prog::state::offsets::sbf as sbf_offsets;
+pub const ENGINE_OFFSET: usize = sbf_offsets::ENGINE;
+pub const ENGINE_BITMAP_OFFSET: usize = sbf_offsets::USED_BITMAP - ENGINE_OFFSET;
+pub const ENGINE_NUM_USED_OFFSET: usize = sbf_offsets::NUM_USED_ACCOUNTS - ENGINE_OFFSET;
+pub const ENGINE_ACCOUNTS_OFFSET: usize = sbf_offsets::ACCOUNTS - ENGINE_OFFSET;
 
 // Pyth Receiver program ID
 pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
@@ -94,6 +61,35 @@ pub fn cu_ix() -> Instruction {
     solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(1_400_000)
 }
 
+/// Ed25519 precompile instruction verifying `signer`'s signature over
+/// `msg`, with key, signature and message inline (the only form the
+/// program accepts).
+pub fn ed25519_verify_ix(signer: &Keypair, msg: &[u8]) -> Instruction {
+    let pk_off: u16 = 16;
+    let sig_off: u16 = pk_off + 32;
+    let msg_off: u16 = sig_off + 64;
+    let mut data = vec![1u8, 0];
+    for v in [
+        sig_off,
+        u16::MAX,
+        pk_off,
+        u16::MAX,
+        msg_off,
+        msg.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.extend_from_slice(signer.pubkey().as_ref());
+    data.extend_from_slice(signer.sign_message(msg).as_ref());
+    data.extend_from_slice(msg);
+    Instruction {
+        program_id: solana_sdk::ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
 pub fn program_path() -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("target/deploy/percolator_prog.so");
@@ -201,6 +197,142 @@ pub fn make_pyth_data(
     data
 }
 
+/// Deterministic Pyth PriceUpdateV2 fixture.
+///
+/// Wraps `make_pyth_data` with named knobs for the scenarios oracle tests
+/// keep rebuilding by hand: stale publish times, wide confidence bands and
+/// non-default exponents. Inversion and unit scaling are applied by the
+/// market, so fixtures always carry the raw feed price; compare against
+/// `policy::to_engine_price` to get what an inverted market sees.
+#[derive(Clone, Copy, Debug)]
+pub struct MockPythPrice {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub expo: i32,
+    pub conf: u64,
+    pub publish_time: i64,
+    pub posted_slot: u64,
+}
+
+impl MockPythPrice {
+    /// `price_e6` at `publish_time` on `TEST_FEED_ID`, expo -6, conf 1.
+    pub fn new(price_e6: i64, publish_time: i64) -> Self {
+        Self {
+            feed_id: TEST_FEED_ID,
+            price: price_e6,
+            expo: -6,
+            conf: 1,
+            publish_time,
+            posted_slot: 0,
+        }
+    }
+
+    pub fn feed_id(mut self, feed_id: [u8; 32]) -> Self {
+        self.feed_id = feed_id;
+        self
+    }
+
+    /// Re-express the same price with a different exponent.
+    pub fn expo(mut self, expo: i32) -> Self {
+        let shift = expo + 6;
+        self.price = if shift >= 0 {
+            self.price / 10i64.pow(shift as u32)
+        } else {
+            self.price * 10i64.pow((-shift) as u32)
+        };
+        self.expo = expo;
+        self
+    }
+
+    pub fn conf(mut self, conf: u64) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Confidence band as a fraction of price, in bps (rounded up, so
+    /// `conf_bps(conf_filter_bps + 1)` is always rejected).
+    pub fn conf_bps(mut self, bps: u64) -> Self {
+        self.conf = (self.price.unsigned_abs() * bps).div_ceil(10_000);
+        self
+    }
+
+    /// Publish time one second past `max_staleness_secs` at `now`.
+    pub fn stale_at(mut self, now: i64, max_staleness_secs: u64) -> Self {
+        self.publish_time = now - max_staleness_secs as i64 - 1;
+        self
+    }
+
+    pub fn posted_slot(mut self, slot: u64) -> Self {
+        self.posted_slot = slot;
+        self
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = make_pyth_data(
+            &self.feed_id,
+            self.price,
+            self.expo,
+            self.conf,
+            self.publish_time,
+        );
+        data[125..133].copy_from_slice(&self.posted_slot.to_le_bytes());
+        data
+    }
+
+    pub fn account(&self) -> Account {
+        Account {
+            lamports: 1_000_000,
+            data: self.data(),
+            owner: PYTH_RECEIVER_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+}
+
+/// Deterministic Chainlink OCR2 aggregator fixture (the fields the wrapper
+/// reads: decimals, timestamp, answer).
+#[derive(Clone, Copy, Debug)]
+pub struct MockChainlinkRound {
+    pub answer: i128,
+    pub decimals: u8,
+    pub timestamp: u64,
+}
+
+impl MockChainlinkRound {
+    /// `price_e6` at `timestamp`, 6 decimals.
+    pub fn new(price_e6: i64, timestamp: u64) -> Self {
+        Self {
+            answer: price_e6 as i128,
+            decimals: 6,
+            timestamp,
+        }
+    }
+
+    pub fn stale_at(mut self, now: u64, max_staleness_secs: u64) -> Self {
+        self.timestamp = now - max_staleness_secs - 1;
+        self
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 232];
+        data[138] = self.decimals;
+        data[208..216].copy_from_slice(&self.timestamp.to_le_bytes());
+        data[216..232].copy_from_slice(&self.answer.to_le_bytes());
+        data
+    }
+
+    pub fn account(&self) -> Account {
+        Account {
+            lamports: 1_000_000,
+            data: self.data(),
+            owner: percolator_prog::oracle::CHAINLINK_OCR2_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+}
+
 /// Append default extended tail (82 bytes) to an InitMarket payload.
 ///
 /// v12.19.6: non-Hyperp markets MUST carry `permissionless_resolve_stale_slots > 0`
@@ -840,14 +972,14 @@ impl TestEnv {
         let pyth_index = Pubkey::new_unique();
         let pyth_col = Pubkey::new_unique();
         let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-        let vault = Pubkey::new_unique();
+        let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
         svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
         svm.set_account(
             slab,
             Account {
-                lamports: 1_000_000_000,
+                lamports: 100_000_000_000,
                 data: vec![0u8; SLAB_LEN],
                 owner: program_id,
                 executable: false,
@@ -1484,6 +1616,17 @@ impl TestEnv {
         self.set_slot_and_price_raw(effective_slot, price_e6);
     }
 
+    /// Install a mock Pyth fixture as both the index and collateral oracle.
+    /// Leaves the clock alone so callers control freshness explicitly.
+    pub fn set_oracle(&mut self, oracle: &MockPythPrice) {
+        self.svm
+            .set_account(self.pyth_index, oracle.account())
+            .unwrap();
+        self.svm
+            .set_account(self.pyth_col, oracle.account())
+            .unwrap();
+    }
+
     /// Like `set_slot_and_price` but takes the effective slot directly (no
     /// +100 offset). Internal helper.
     fn set_slot_and_price_raw(&mut self, effective_slot: u64, price_e6: i64) {
@@ -2683,6 +2826,17 @@ impl TestEnv {
         amount: u64,
     ) -> Result<(), String> {
         let ata = self.create_ata(&owner.pubkey(), 0);
+        self.try_withdraw_to(owner, user_idx, amount, ata)
+    }
+
+    /// Withdraw paying out to `dest`, any token account of the mint.
+    pub fn try_withdraw_to(
+        &mut self,
+        owner: &Keypair,
+        user_idx: u16,
+        amount: u64,
+        dest: Pubkey,
+    ) -> Result<(), String> {
         let (vault_pda, _) =
             Pubkey::find_program_address(&[b"vault", self.slab.as_ref()], &self.program_id);
 
@@ -2692,7 +2846,7 @@ impl TestEnv {
                 AccountMeta::new(owner.pubkey(), true),
                 AccountMeta::new(self.slab, false),
                 AccountMeta::new(self.vault, false),
-                AccountMeta::new(ata, false),
+                AccountMeta::new(dest, false),
                 AccountMeta::new_readonly(vault_pda, false),
                 AccountMeta::new_readonly(spl_token::ID, false),
                 AccountMeta::new_readonly(sysvar::clock::ID, false),
@@ -3429,6 +3583,18 @@ pub fn encode_trade_cpi_with_limit(
     data
 }
 
+pub fn encode_trade_cpi_with_flags(
+    lp_idx: u16,
+    user_idx: u16,
+    size: i128,
+    limit_price_e6: u64,
+    flags: u8,
+) -> Vec<u8> {
+    let mut data = encode_trade_cpi_with_limit(lp_idx, user_idx, size, limit_price_e6);
+    data.push(flags);
+    data
+}
+
 /// Test environment extended for TradeCpi tests
 pub struct TradeCpiTestEnv {
     pub svm: LiteSVM,
@@ -3464,14 +3630,14 @@ impl TradeCpiTestEnv {
         let pyth_index = Pubkey::new_unique();
         let pyth_col = Pubkey::new_unique();
         let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-        let vault = Pubkey::new_unique();
+        let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
         svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
         svm.set_account(
             slab,
             Account {
-                lamports: 1_000_000_000,
+                lamports: 100_000_000_000,
                 data: vec![0u8; SLAB_LEN],
                 owner: program_id,
                 executable: false,
@@ -3609,6 +3775,28 @@ impl TradeCpiTestEnv {
         &mut self,
         owner: &Keypair,
         matcher_prog: &Pubkey,
+    ) -> (u16, Pubkey) {
+        let params = percolator_prog::matcher_abi::VammParams {
+            trading_fee_bps: 5,
+            base_spread_bps: 10,
+            max_total_bps: 200,
+            impact_k_bps: 0,
+            liquidity_notional_e6: 0,
+            max_fill_abs: 1_000_000_000_000,
+            max_inventory_abs: 0,
+        };
+        self.init_lp_with_vamm(owner, matcher_prog, MatcherMode::Passive, &params)
+    }
+
+    /// Initialize LP with a matcher context set up by InitVamm with the
+    /// given mode and parameters.
+    /// Returns (lp_idx, matcher_context_pubkey)
+    pub fn init_lp_with_vamm(
+        &mut self,
+        owner: &Keypair,
+        matcher_prog: &Pubkey,
+        mode: MatcherMode,
+        params: &percolator_prog::matcher_abi::VammParams,
     ) -> (u16, Pubkey) {
         let idx = self.account_count;
         self.svm.airdrop(&owner.pubkey(), 1_000_000_000).unwrap();
@@ -3641,16 +3829,7 @@ impl TradeCpiTestEnv {
                 AccountMeta::new_readonly(lp_pda, false), // LP PDA (stored for signature verification)
                 AccountMeta::new(ctx, false),             // Context account
             ],
-            data: encode_init_vamm(
-                MatcherMode::Passive,
-                5,
-                10,
-                200,
-                0,
-                0,
-                1_000_000_000_000, // max fill
-                0,
-            ),
+            data: percolator_prog::matcher_abi::encode_init_vamm(mode as u8, params).to_vec(),
         };
 
         let tx = Transaction::new_signed_with_payer(
@@ -3889,6 +4068,48 @@ impl TradeCpiTestEnv {
         limit_price_e6: u64,
         matcher_prog: &Pubkey,
         matcher_ctx: &Pubkey,
+    ) -> Result<(), String> {
+        self.send_trade_cpi_data(
+            user,
+            lp_owner,
+            lp_idx,
+            encode_trade_cpi_with_limit(lp_idx, user_idx, size, limit_price_e6),
+            matcher_prog,
+            matcher_ctx,
+        )
+    }
+
+    /// Execute TradeCpi with an explicit trailing trade-flags byte.
+    pub fn try_trade_cpi_with_flags(
+        &mut self,
+        user: &Keypair,
+        lp_owner: &Pubkey,
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        limit_price_e6: u64,
+        flags: u8,
+        matcher_prog: &Pubkey,
+        matcher_ctx: &Pubkey,
+    ) -> Result<(), String> {
+        self.send_trade_cpi_data(
+            user,
+            lp_owner,
+            lp_idx,
+            encode_trade_cpi_with_flags(lp_idx, user_idx, size, limit_price_e6, flags),
+            matcher_prog,
+            matcher_ctx,
+        )
+    }
+
+    fn send_trade_cpi_data(
+        &mut self,
+        user: &Keypair,
+        lp_owner: &Pubkey,
+        lp_idx: u16,
+        data: Vec<u8>,
+        matcher_prog: &Pubkey,
+        matcher_ctx: &Pubkey,
     ) -> Result<(), String> {
         let lp_bytes = lp_idx.to_le_bytes();
         let (lp_pda, _) =
@@ -3906,7 +4127,7 @@ impl TradeCpiTestEnv {
                 AccountMeta::new(*matcher_ctx, false),
                 AccountMeta::new_readonly(lp_pda, false),
             ],
-            data: encode_trade_cpi_with_limit(lp_idx, user_idx, size, limit_price_e6),
+            data,
         };
 
         let tx = Transaction::new_signed_with_payer(
@@ -7167,6 +7388,11 @@ pub struct IntegrationFuzzer {
     pub all_indices: Vec<u16>, // Every index ever allocated (for invariant checks)
     pub step: usize,
     pub seed: u64,
+    /// SPL vault balance once `setup` completes.
+    pub vault_at_setup: u64,
+    /// Net token flow into the vault since setup: deposits, top-ups and
+    /// account-creation payments minus withdrawals and close payouts.
+    pub net_flow: i128,
 }
 
 impl IntegrationFuzzer {
@@ -7183,6 +7409,8 @@ impl IntegrationFuzzer {
             all_indices: Vec::new(),
             step: 0,
             seed,
+            vault_at_setup: 0,
+            net_flow: 0,
         }
     }
 
@@ -7210,6 +7438,7 @@ impl IntegrationFuzzer {
             .try_top_up_insurance(&self.admin, 2_000_000_000)
             .unwrap();
         self.env.crank();
+        self.vault_at_setup = self.env.vault_balance();
     }
 
     /// Check all global invariants. Panics with detailed diagnostics on failure.
@@ -7270,6 +7499,20 @@ impl IntegrationFuzzer {
             "[seed={} step={} {}] P4 PNL_POS_TOT MISMATCH: tracked={} computed={}",
             self.seed, self.step, context, pnl_pos_tot, sum_pnl_pos
         );
+
+        // P7: Flow ledger — the vault holds exactly what came in minus what
+        // went out. Trades, cranks and price moves never move tokens.
+        assert_eq!(
+            vault as i128,
+            self.vault_at_setup as i128 + self.net_flow,
+            "[seed={} step={} {}] P7 FLOW LEDGER MISMATCH: vault={} setup={} net_flow={}",
+            self.seed,
+            self.step,
+            context,
+            vault,
+            self.vault_at_setup,
+            self.net_flow
+        );
     }
 
     pub fn random_action(&self, rng: &mut FuzzRng) -> FuzzAction {
@@ -7333,16 +7576,24 @@ impl IntegrationFuzzer {
         let vault_before = self.env.vault_balance();
         self.step += 1;
 
-        let (result, desc): (Result<(), String>, String) = match action {
+        let (result, desc, flow): (Result<(), String>, String, i128) = match action {
             FuzzAction::Deposit { user_idx, amount } => {
                 let (ref user, idx) = self.users[user_idx];
                 let r = self.env.try_deposit(user, idx, amount);
-                (r, format!("deposit(user={}, amt={})", idx, amount))
+                (
+                    r,
+                    format!("deposit(user={}, amt={})", idx, amount),
+                    amount as i128,
+                )
             }
             FuzzAction::Withdraw { user_idx, amount } => {
                 let (ref user, idx) = self.users[user_idx];
                 let r = self.env.try_withdraw(user, idx, amount);
-                (r, format!("withdraw(user={}, amt={})", idx, amount))
+                (
+                    r,
+                    format!("withdraw(user={}, amt={})", idx, amount),
+                    -(amount as i128),
+                )
             }
             FuzzAction::Trade {
                 user_idx,
@@ -7355,11 +7606,12 @@ impl IntegrationFuzzer {
                 (
                     r,
                     format!("trade(user={}, lp={}, size={})", u_idx, l_idx, size),
+                    0,
                 )
             }
             FuzzAction::Crank => {
                 let r = self.env.try_crank();
-                (r, "crank".to_string())
+                (r, "crank".to_string(), 0)
             }
             FuzzAction::AdvanceSlotAndPrice { dt, price_e6 } => {
                 self.current_slot += dt;
@@ -7371,32 +7623,47 @@ impl IntegrationFuzzer {
                         "set_slot_price(slot={}, price={})",
                         self.current_slot, price_e6
                     ),
+                    0,
                 )
             }
             FuzzAction::TopUpInsurance { amount } => {
                 let r = self.env.try_top_up_insurance(&self.admin, amount);
-                (r, format!("topup_insurance({})", amount))
+                (r, format!("topup_insurance({})", amount), amount as i128)
             }
             FuzzAction::InitUser => {
+                const NEW_USER_DEPOSIT: u64 = 1_000_000_000;
                 let new_user = Keypair::new();
-                match self.env.try_init_user(&new_user) {
+                // Both inflows are known amounts: the account-creation
+                // payment, then the follow-up deposit if it lands.
+                match self
+                    .env
+                    .try_init_user_with_fee(&new_user, DEFAULT_INIT_PAYMENT)
+                {
                     Ok(idx) => {
                         // Immediately deposit to prevent GC (use try_deposit since it may fail)
-                        match self.env.try_deposit(&new_user, idx, 1_000_000_000) {
+                        match self.env.try_deposit(&new_user, idx, NEW_USER_DEPOSIT) {
                             Ok(()) => {
                                 self.users.push((new_user, idx));
                                 self.all_indices.push(idx);
-                                (Ok(()), format!("init_user(idx={})+deposit", idx))
+                                (
+                                    Ok(()),
+                                    format!("init_user(idx={})+deposit", idx),
+                                    (DEFAULT_INIT_PAYMENT + NEW_USER_DEPOSIT) as i128,
+                                )
                             }
                             Err(_) => {
                                 // Init succeeded but deposit failed - account may get GC'd
                                 // Still track it for invariant checks
                                 self.all_indices.push(idx);
-                                (Ok(()), format!("init_user(idx={}) deposit_failed", idx))
+                                (
+                                    Ok(()),
+                                    format!("init_user(idx={}) deposit_failed", idx),
+                                    DEFAULT_INIT_PAYMENT as i128,
+                                )
                             }
                         }
                     }
-                    Err(e) => (Err(e), "init_user(failed)".to_string()),
+                    Err(e) => (Err(e), "init_user(failed)".to_string(), 0),
                 }
             }
             FuzzAction::CloseAccount { user_idx } => {
@@ -7406,6 +7673,7 @@ impl IntegrationFuzzer {
                 if cap > 0 {
                     let vault_before_withdraw = self.env.vault_balance();
                     if self.env.try_withdraw(user, idx, cap as u64).is_ok() {
+                        self.net_flow -= cap as i128;
                         self.check_invariants(&format!("pre_close_withdraw(idx={})", idx));
                     } else {
                         let vault_after_withdraw = self.env.vault_balance();
@@ -7419,8 +7687,12 @@ impl IntegrationFuzzer {
                 // Now try close on the (hopefully) empty account
                 // Re-capture vault for P6 check
                 let vault_now = self.env.vault_balance();
+                // A flat account closes by paying out its capital; fees were
+                // synced by the withdrawal in this same slot.
+                let expected_payout = self.env.read_account_capital(idx);
                 let r = self.env.try_close_account(user, idx);
                 if r.is_ok() {
+                    self.net_flow -= expected_payout as i128;
                     self.check_invariants(&format!("close_account(idx={})", idx));
                     return; // Already checked, skip outer check
                 }
@@ -7437,6 +7709,7 @@ impl IntegrationFuzzer {
 
         match result {
             Ok(()) => {
+                self.net_flow += flow;
                 self.check_invariants(&desc);
             }
             Err(_) => {
@@ -7660,15 +7933,13 @@ pub fn encode_init_market_with_maintenance_fee(
 impl TestEnv {
     /// Read the risk buffer from the slab.
     /// Read risk buffer from BPF slab layout.
-    /// Buffer is at (SLAB_LEN - GEN_TABLE_LEN - RISK_BUF_LEN) in BPF.
-    /// We use the BPF-specific offset: the risk buffer sits right before the
-    /// generation table, and the gen table is at the end of the slab.
+    /// We use the BPF-specific offset: the risk buffer sits right after the
+    /// engine, before the generation table.
     pub fn read_risk_buffer(&self) -> percolator_prog::risk_buffer::RiskBuffer {
         use bytemuck::Zeroable;
         let d = self.svm.get_account(&self.slab).unwrap().data;
         let buf_size = core::mem::size_of::<percolator_prog::risk_buffer::RiskBuffer>();
-        let gen_table_size = MAX_ACCOUNTS * 8;
-        let buf_off = SLAB_LEN - gen_table_size - buf_size;
+        let buf_off = sbf_offsets::RISK_BUF;
         let mut buf = percolator_prog::risk_buffer::RiskBuffer::zeroed();
         bytemuck::bytes_of_mut(&mut buf).copy_from_slice(&d[buf_off..buf_off + buf_size]);
         buf
@@ -7715,4 +7986,55 @@ impl TestEnv {
             .map(|_| ())
             .map_err(|e| format!("{:?}", e))
     }
+
+    /// InitSlab (tag 54): zero the next `max_bytes` of the slab.
+    pub fn try_init_slab(&mut self, signer: &Keypair, max_bytes: u32) -> Result<(), String> {
+        let mut data = vec![54u8];
+        data.extend_from_slice(&max_bytes.to_le_bytes());
+        let ix = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(self.slab, false),
+            ],
+            data,
+        };
+        // Chunks repeat byte-for-byte; a fresh blockhash keeps them distinct.
+        self.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            self.svm.latest_blockhash(),
+        );
+        self.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    /// MigrateSlab (tag 55): grow an older slab one step, rent paid by
+    /// `payer`.
+    pub fn try_migrate_slab(&mut self, payer: &Keypair) -> Result<(), String> {
+        let ix = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(self.slab, false),
+                AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            ],
+            data: vec![55u8],
+        };
+        self.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&payer.pubkey()),
+            &[payer],
+            self.svm.latest_blockhash(),
+        );
+        self.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e))
+    }
 }
@@ -31,12 +31,7 @@ use std::path::PathBuf;
 // feature (which compiled the engine with MAX_ACCOUNTS=64 for native unit
 // tests) has been removed; integration tests go through the BPF binary.
 // BPF-target SLAB_LEN, cfg-gated by deployment-size feature.
-#[cfg(all(feature = "small", not(feature = "medium")))]
-const SLAB_LEN: usize = 111520;
-#[cfg(all(feature = "medium", not(feature = "small")))]
-const SLAB_LEN: usize = 440320;
-#[cfg(not(any(feature = "small", feature = "medium")))]
-const SLAB_LEN: usize = 1755520;
+const SLAB_LEN: usize = percolator_prog::state::offsets::sbf::SLAB_LEN;
 #[cfg(all(feature = "small", not(feature = "medium")))]
 const MAX_ACCOUNTS: usize = 256;
 #[cfg(all(feature = "medium", not(feature = "small")))]
@@ -271,14 +266,14 @@ impl TestEnv {
         let pyth_index = Pubkey::new_unique();
         let pyth_col = Pubkey::new_unique();
         let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-        let vault = Pubkey::new_unique();
+        let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
         svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
         svm.set_account(
             slab,
             Account {
-                lamports: 1_000_000_000,
+                lamports: 100_000_000_000,
                 data: vec![0u8; SLAB_LEN],
                 owner: program_id,
                 executable: false,
@@ -2329,6 +2324,19 @@ fn benchmark_worst_case_scenarios() {
     println!("• ADL/liquidation processing adds CU overhead per affected account");
 }
 
+/// Measured cost must fit the ceiling clients budget from.
+fn assert_within_published_cu(tag: u8, cu: u64) {
+    let published = percolator_prog::constants::CU_ESTIMATES
+        .iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, cu)| *cu)
+        .unwrap_or(percolator_prog::constants::CU_ESTIMATE_DEFAULT);
+    assert!(
+        cu <= published as u64,
+        "tag {tag} used {cu} CU, above its published estimate {published}; raise CU_ESTIMATES"
+    );
+}
+
 /// Per-instruction CU benchmark covering all instruction types.
 /// Measures CU consumed for each instruction under typical conditions.
 #[test]
@@ -2386,6 +2394,7 @@ fn benchmark_all_instructions() {
         };
         let cu = measure(&mut env.svm, ix, &[&user, &lp]).unwrap();
         println!("TradeNoCpi:            {:>8} CU", cu);
+        assert_within_published_cu(6, cu);
     }
 
     // --- DepositCollateral (Tag 3) ---
@@ -2405,6 +2414,7 @@ fn benchmark_all_instructions() {
         };
         let cu = measure(&mut env.svm, ix, &[&user]).unwrap();
         println!("DepositCollateral:     {:>8} CU", cu);
+        assert_within_published_cu(3, cu);
     }
 
     // --- WithdrawCollateral (Tag 4) ---
@@ -2428,6 +2438,7 @@ fn benchmark_all_instructions() {
         };
         let cu = measure(&mut env.svm, ix, &[&user]).unwrap();
         println!("WithdrawCollateral:    {:>8} CU", cu);
+        assert_within_published_cu(4, cu);
     }
 
     // --- KeeperCrank (Tag 5) ---
@@ -2435,6 +2446,7 @@ fn benchmark_all_instructions() {
         env.set_price(100_000_000, 400);
         let cu = env.crank();
         println!("KeeperCrank:           {:>8} CU", cu);
+        assert_within_published_cu(5, cu);
     }
 
     // --- TopUpInsurance (Tag 9) ---
@@ -2454,6 +2466,7 @@ fn benchmark_all_instructions() {
         };
         let cu = measure(&mut env.svm, ix, &[&admin]).unwrap();
         println!("TopUpInsurance:        {:>8} CU", cu);
+        assert_within_published_cu(9, cu);
     }
 
     // Tag 11 (SetRiskThreshold) benchmark removed: instruction deleted.
@@ -2479,6 +2492,7 @@ fn benchmark_all_instructions() {
         };
         let cu = measure(&mut env.svm, ix, &[&admin]).unwrap();
         println!("UpdateConfig:          {:>8} CU", cu);
+        assert_within_published_cu(14, cu);
     }
 
     // SetMaintenanceFee (Tag 15) — removed per spec §8.2. Decoder rejects.
@@ -2672,3 +2686,160 @@ fn benchmark_all_instructions() {
 
     println!("\n=== END PER-INSTRUCTION CU BENCHMARK ===");
 }
+
+/// Slab populations the scaling benchmark measures, clamped to the
+/// build's capacity (one slot is the LP).
+const SCALING_USER_COUNTS: [usize; 3] = [100, 1000, 4096];
+
+/// Regression threshold for instructions that touch a fixed number of
+/// accounts: CU on the fullest slab may be at most this multiple of CU on
+/// the emptiest. A breach means a per-instruction path started scanning
+/// the slab.
+const SCALING_MAX_CU_GROWTH: u64 = 2;
+
+fn measure_cu(svm: &mut LiteSVM, ix: Instruction, signers: &[&Keypair]) -> u64 {
+    let payer = signers[0];
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&payer.pubkey()),
+        signers,
+        svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx)
+        .unwrap_or_else(|e| panic!("benchmark instruction failed: {:?}", e))
+        .compute_units_consumed
+}
+
+/// CU for TradeNoCpi, DepositCollateral, WithdrawCollateral and
+/// KeeperCrank on one market holding `users` funded accounts, half long
+/// and half short against a single LP.
+fn measure_at_population(users: usize) -> [(&'static str, u8, u64); 4] {
+    let mut env = TestEnv::new();
+    env.init_market();
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 1_000_000_000_000);
+    env.set_price(100_000_000, 200);
+    env.crank();
+
+    let accounts = create_users(&mut env, users, 1_000_000_000);
+    for (i, user) in accounts.iter().enumerate() {
+        let size = if i % 2 == 0 { 100_000 } else { -100_000 };
+        env.trade(user, &lp, lp_idx, (i + 1) as u16, size);
+    }
+    env.set_price(100_000_000, 300);
+    env.crank();
+
+    let user = &accounts[0];
+    let user_idx = 1u16;
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+
+    let trade = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new(lp.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_trade(lp_idx, user_idx, 50_000),
+    };
+    let trade_cu = measure_cu(&mut env.svm, trade, &[user, &lp]);
+
+    let ata = env.create_ata(&user.pubkey(), 1_000_000);
+    let deposit = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ],
+        data: encode_deposit(user_idx, 1_000_000),
+    };
+    let deposit_cu = measure_cu(&mut env.svm, deposit, &[user]);
+
+    env.set_price(100_000_000, 400);
+    env.crank();
+    let ata = env.create_ata(&user.pubkey(), 0);
+    let withdraw = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_withdraw(user_idx, 100_000),
+    };
+    let withdraw_cu = measure_cu(&mut env.svm, withdraw, &[user]);
+
+    env.set_price(100_000_000, 500);
+    let crank_cu = env.crank();
+
+    [
+        ("TradeNoCpi", 6, trade_cu),
+        ("DepositCollateral", 3, deposit_cu),
+        ("WithdrawCollateral", 4, withdraw_cu),
+        ("KeeperCrank", 5, crank_cu),
+    ]
+}
+
+/// Per-instruction CU across slab populations. Every measurement must fit
+/// its published estimate, and the fixed-account instructions must not
+/// grow with the number of users (`SCALING_MAX_CU_GROWTH`).
+#[test]
+fn benchmark_instructions_across_account_counts() {
+    let mut counts: Vec<usize> = SCALING_USER_COUNTS
+        .iter()
+        .map(|&n| n.min(MAX_ACCOUNTS - 1))
+        .collect();
+    counts.dedup();
+
+    println!("\n=== CU BY SLAB POPULATION (MAX_ACCOUNTS={MAX_ACCOUNTS}) ===\n");
+    let rows: Vec<(usize, [(&'static str, u8, u64); 4])> = counts
+        .iter()
+        .map(|&n| {
+            println!("  populating {n} users...");
+            (n, measure_at_population(n))
+        })
+        .collect();
+
+    print!("{:<20}", "instruction");
+    for (n, _) in &rows {
+        print!("{:>12}", format!("{n} users"));
+    }
+    println!();
+    for col in 0..4 {
+        print!("{:<20}", rows[0].1[col].0);
+        for (_, row) in &rows {
+            print!("{:>12}", row[col].2);
+        }
+        println!();
+    }
+
+    for (_, row) in &rows {
+        for &(_, tag, cu) in row {
+            assert_within_published_cu(tag, cu);
+        }
+    }
+    let (first, last) = (&rows[0], &rows[rows.len() - 1]);
+    for col in 0..3 {
+        let (name, _, base) = first.1[col];
+        let (_, _, full) = last.1[col];
+        assert!(
+            full <= base * SCALING_MAX_CU_GROWTH,
+            "{name}: {full} CU at {} users vs {base} CU at {} users",
+            last.0,
+            first.0
+        );
+    }
+}
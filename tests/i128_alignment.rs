@@ -25,12 +25,7 @@ use std::path::PathBuf;
 
 // SLAB_LEN for production BPF (MAX_ACCOUNTS=4096)
 // BPF-target SLAB_LEN, cfg-gated by deployment-size feature.
-#[cfg(all(feature = "small", not(feature = "medium")))]
-const SLAB_LEN: usize = 111520;
-#[cfg(all(feature = "medium", not(feature = "small")))]
-const SLAB_LEN: usize = 440320;
-#[cfg(not(any(feature = "small", feature = "medium")))]
-const SLAB_LEN: usize = 1755520;
+const SLAB_LEN: usize = percolator_prog::state::offsets::sbf::SLAB_LEN;
 const TEST_MAX_STALENESS_SECS: u64 = percolator_prog::constants::MAX_ORACLE_STALENESS_SECS;
 #[cfg(all(feature = "small", not(feature = "medium")))]
 const MAX_ACCOUNTS: usize = 256;
@@ -496,14 +491,14 @@ fn test_bpf_i128_alignment() {
     let pyth_index = Pubkey::new_unique();
     let pyth_col = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
     svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
     svm.set_account(
         slab,
         SolanaAccount {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; SLAB_LEN],
             owner: program_id,
             executable: false,
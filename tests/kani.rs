@@ -11,6 +11,9 @@
 //! - PDA key mismatch rejection
 //! - Nonce monotonicity (unchanged on failure, +1 on success)
 //! - CPI uses exec_size (not requested size)
+//! - Wrapper fixed-point rounding direction and fee/payout bounds
+//! - Margin, fee and funding helpers never overflow within configured caps
+//! - Health and liquidation distance are monotone in collateral
 //!
 //! Only wrapper-level authorization and binding logic is proven. The
 //! issue-65 scan proof treats `provably_nonnegative` and `phase1_reachable`
@@ -30,7 +33,7 @@ use percolator_prog::constants::{
     MAX_UNIT_SCALE, MIN_FUNDING_LIFETIME_SLOTS, ORACLE_LEG_FLAG_DIVIDE_LEG2,
     ORACLE_LEG_FLAG_DIVIDE_LEG3,
 };
-use percolator_prog::ix::Instruction;
+use percolator_prog::ix::{free_collateral, Instruction};
 use percolator_prog::matcher_abi::{
     validate_matcher_return, MatcherReturn, FLAG_PARTIAL_OK, FLAG_REJECTED, FLAG_VALID,
 };
@@ -56,9 +59,14 @@ use percolator_prog::policy::{
     // Fee-weighted EWMA
     ewma_effective_alpha_bps,
     ewma_update,
+    exec_price_within_margin,
+    fee_split,
     // Account validation helpers
     fee_sync_anchor_within_accrued_boundary,
     force_close_delay_elapsed,
+    funding_bps_bound,
+    funding_index_advance,
+    funding_owed,
     funding_rate_e9_from_mark_index,
     // New: InitMarket scale validation
     init_market_scale_ok,
@@ -66,11 +74,21 @@ use percolator_prog::policy::{
     invert_price_e6,
     len_at_least,
     len_ok,
+    liq_distance_e6,
+    liq_projected_equity,
+    liq_watch_clear_ok,
+    liq_watch_covers,
     live_insurance_withdraw_market_healthy,
     live_insurance_withdraw_residual_ok,
+    // Fixed-point helpers and the fee/payout math built on them
+    lp_redeem_amount,
+    lp_shares_for_deposit,
     market_idx_within_capacity,
+    matcher_ctx_bound,
     matcher_identity_ok,
     matcher_shape_ok,
+    mul_div_ceil,
+    mul_div_floor,
     no_oracle_fee_sync_anchor,
     nonce_on_failure,
     nonce_on_success,
@@ -79,27 +97,34 @@ use percolator_prog::policy::{
     partial_crank_config_fields_to_write,
     pda_key_matches,
     permissionless_resolve_horizon_ok,
+    portfolio_margin,
     recurring_fee_pre_touch_safe_shape,
     // New: Oracle unit scale math
     scale_price_e6,
     signer_ok,
     slab_shape_ok,
+    slippage_bps_ok,
     target_lag_after_read,
     target_lag_pending,
     trade_cpi_allowed_after_oracle_read,
     user_value_op_allowed_after_accrual,
     writable_ok,
     CrankCatchupTarget,
+    LiqPriceInputs,
+    MarginOffset,
     MatcherAccountsShape,
     // ABI validation from real inputs
     MatcherReturnFields,
     PartialCrankConfigFields,
+    PortfolioLeg,
     SimpleDecision,
     SlabShape,
     TradeCpiDecision,
     TradeNoCpiDecision,
     INVERSION_CONSTANT,
+    MAX_MARGIN_OFFSET_BPS,
 };
+use percolator_prog::simulate::AccountHealth;
 
 // Kani-specific bounds to avoid SAT explosion on division/modulo.
 // MAX_UNIT_SCALE (1 billion) is too large for bit-precise SAT solving.
@@ -775,6 +800,20 @@ fn kani_matcher_identity_match_accepted() {
     );
 }
 
+/// Prove: a matcher context is accepted exactly when it records this LP's
+/// PDA, and an unbound (zeroed) context is never accepted
+#[kani::proof]
+fn kani_matcher_ctx_bound_characterized() {
+    let ctx_lp_pda: [u8; 32] = kani::any();
+    let expected: [u8; 32] = kani::any();
+
+    assert_eq!(
+        matcher_ctx_bound(ctx_lp_pda, expected),
+        ctx_lp_pda == expected && ctx_lp_pda != [0u8; 32],
+        "context binding must equal a nonzero PDA match"
+    );
+}
+
 // =============================================================================
 // E. MATCHER ACCOUNT SHAPE VALIDATION (5 proofs)
 // NOTE: These use concrete structs (UNIT TEST classification). Individually
@@ -4701,3 +4740,319 @@ fn kani_permissionless_resolve_horizon_policy_independent_from_accrual_window()
         "cap+1 is rejected"
     );
 }
+
+// =============================================================================
+// AE. FIXED-POINT ROUNDING AND FEE/PAYOUT BOUNDS (5 proofs)
+// =============================================================================
+// Operand widths are narrowed (u32/u16) to keep u128 division tractable;
+// the helpers are width-generic so the rounding argument carries over.
+
+/// floor <= exact <= ceil <= floor + 1, and the two agree iff exact.
+#[kani::proof]
+fn kani_mul_div_floor_ceil_bracket() {
+    let a: u32 = kani::any();
+    let b: u16 = kani::any();
+    let d: u16 = kani::any();
+    kani::assume(d > 0);
+    let (a, b, d) = (a as u128, b as u128, d as u128);
+
+    let f = mul_div_floor(a, b, d).expect("u32*u16 cannot overflow u128");
+    let c = mul_div_ceil(a, b, d).expect("u32*u16 cannot overflow u128");
+    assert!(f * d <= a * b, "floor never overshoots");
+    assert!(c * d >= a * b, "ceil never undershoots");
+    assert!(c - f <= 1);
+    assert_eq!(c == f, (a * b) % d == 0);
+}
+
+/// Zero divisor is rejected, never a panic.
+#[kani::proof]
+fn kani_mul_div_zero_divisor_rejected() {
+    let a: u128 = kani::any();
+    let b: u128 = kani::any();
+    assert!(mul_div_floor(a, b, 0).is_none());
+    assert!(mul_div_ceil(a, b, 0).is_none());
+}
+
+/// Backstop share math never pays out more than the vault holds and never
+/// mints shares worth more than the deposit.
+#[kani::proof]
+fn kani_lp_share_math_never_creates_value() {
+    let total_shares: u16 = kani::any();
+    let equity: u16 = kani::any();
+    let shares: u16 = kani::any();
+    let deposit: u16 = kani::any();
+    kani::assume(total_shares > 0 && equity > 0);
+    kani::assume(shares <= total_shares);
+    let (ts, eq) = (total_shares as u128, equity as u128);
+
+    let out = lp_redeem_amount(shares as u128, ts, eq).unwrap();
+    assert!(out <= eq, "redemption bounded by vault equity");
+
+    let minted = lp_shares_for_deposit(deposit as u128, ts, eq).unwrap();
+    // minted / (ts + minted) of (eq + deposit) <= deposit
+    assert!(minted * (eq + deposit as u128) <= (deposit as u128) * (ts + minted));
+}
+
+/// A fill that passes a slippage bound still passes any looser bound.
+#[kani::proof]
+fn kani_slippage_bound_monotone_in_bps() {
+    let exec: u32 = kani::any();
+    let oracle: u32 = kani::any();
+    let long: bool = kani::any();
+    let bps: u16 = kani::any();
+    let looser: u16 = kani::any();
+    kani::assume(bps <= looser && looser <= 10_000);
+    let size_q: i128 = if long { 1 } else { -1 };
+
+    if slippage_bps_ok(exec as u64, oracle as u64, size_q, bps as u64) {
+        assert!(slippage_bps_ok(
+            exec as u64,
+            oracle as u64,
+            size_q,
+            looser as u64
+        ));
+    }
+}
+
+/// Nothing hides outside the liquidation watch set: an account cleared at
+/// `p_r` stays at or above maintenance at every price the index still
+/// covers, in the linear model the distance is built on (equity moves by
+/// `q * dp / POS_SCALE`, the requirement is `mm_bps` of notional). Funding
+/// drift is zero here; `liq_watch_covers` charges it as extra price move.
+#[kani::proof]
+fn kani_liq_watch_cleared_account_safe_inside_band() {
+    use percolator_prog::constants::LIQ_WATCH_BAND_BPS;
+    let equity: u32 = kani::any();
+    let abs_q: u16 = kani::any();
+    let mm_bps: u16 = kani::any();
+    let p_r: u16 = kani::any();
+    let anchor: u16 = kani::any();
+    let p: u16 = kani::any();
+    let long: bool = kani::any();
+    kani::assume(abs_q > 0 && mm_bps <= 10_000);
+    kani::assume(p_r > 0 && anchor > 0 && p > 0);
+    let s = percolator::POS_SCALE;
+
+    let mm_req = mul_div_ceil(abs_q as u128 * p_r as u128, mm_bps as u128, s * 10_000).unwrap();
+    let d = liq_distance_e6(equity as i128, mm_req, abs_q as u128, mm_bps as u64);
+    kani::assume(liq_watch_clear_ok(
+        d,
+        p_r as u64,
+        anchor as u64,
+        LIQ_WATCH_BAND_BPS,
+    ));
+    kani::assume(liq_watch_covers(
+        anchor as u64,
+        p as u64,
+        0,
+        LIQ_WATCH_BAND_BPS,
+    ));
+
+    // equity(p) * POS_SCALE * 10_000 >= mm_bps * |q| * p, exactly.
+    let dp = p as i128 - p_r as i128;
+    let pnl_scaled = if long {
+        abs_q as i128 * dp
+    } else {
+        -(abs_q as i128) * dp
+    };
+    let lhs = (equity as i128 * s as i128 + pnl_scaled) * 10_000;
+    let rhs = mm_bps as i128 * abs_q as i128 * p as i128;
+    assert!(lhs >= rhs);
+}
+
+// =============================================================================
+// AF. MARGIN/FEE/FUNDING OVERFLOW AND HEALTH MONOTONICITY (9 proofs)
+// =============================================================================
+// Inputs range over each helper's full type domain, bounded only by the
+// configured caps the handlers enforce before calling them. Helpers that
+// go through POS_SCALE division keep the narrowed widths used in AE.
+
+/// The post-CPI price bound cannot overflow for any price or margin, a
+/// fill at the oracle price always passes, and a wider margin never
+/// rejects a fill a tighter one accepted.
+#[kani::proof]
+fn kani_exec_price_margin_bound_total_and_monotone() {
+    let exec: u64 = kani::any();
+    let oracle: u64 = kani::any();
+    let bps: u64 = kani::any();
+    let wider: u64 = kani::any();
+    kani::assume(bps <= wider);
+
+    assert!(exec_price_within_margin(oracle, oracle, bps));
+    if exec_price_within_margin(exec, oracle, bps) {
+        assert!(exec_price_within_margin(exec, oracle, wider));
+    }
+}
+
+/// Two-leg portfolio margin never panics on any notional; with margin
+/// rates up to 100% and notionals up to `u128::MAX / 20_000` it always
+/// returns a figure, and correlation offsets only ever lower it.
+#[kani::proof]
+fn kani_portfolio_margin_no_overflow_within_caps() {
+    let legs = [
+        PortfolioLeg {
+            notional: kani::any(),
+            margin_bps: kani::any(),
+        },
+        PortfolioLeg {
+            notional: kani::any(),
+            margin_bps: kani::any(),
+        },
+    ];
+    let offset = MarginOffset {
+        a: kani::any(),
+        b: kani::any(),
+        offset_bps: kani::any(),
+    };
+    kani::assume(legs[0].margin_bps <= 10_000 && legs[1].margin_bps <= 10_000);
+
+    let with_offset = portfolio_margin(&legs, &[offset]);
+    let cap = u128::MAX / 20_000;
+    if legs.iter().all(|l| l.notional.unsigned_abs() <= cap) {
+        let naive = portfolio_margin(&legs, &[]).expect("capped legs cannot overflow");
+        let valid = offset.a != offset.b
+            && offset.a < 2
+            && offset.b < 2
+            && offset.offset_bps <= MAX_MARGIN_OFFSET_BPS;
+        assert_eq!(with_offset.is_some(), valid);
+        if let Some(m) = with_offset {
+            assert!(m <= naive, "offsets never add margin");
+        }
+    }
+}
+
+/// The fee split never underflows for any fee and any split SetFeeSplit
+/// accepts, and the three shares always sum back to the fee.
+#[kani::proof]
+fn kani_fee_split_no_overflow_and_conserves() {
+    let fee: u128 = kani::any();
+    let insurance_bps: u16 = kani::any();
+    let treasury_bps: u16 = kani::any();
+    kani::assume(insurance_bps as u32 + treasury_bps as u32 <= 10_000);
+
+    let (insurance, treasury, lp) = fee_split(fee, insurance_bps, treasury_bps);
+    assert_eq!(insurance + treasury + lp, fee);
+}
+
+/// The worst-case funding bound exists for every rate up to
+/// `MAX_ABS_FUNDING_E9_PER_SLOT` and any slot count, and within one
+/// accrual it never exceeds the bound at the cap.
+#[kani::proof]
+fn kani_funding_bps_bound_no_overflow_within_cap() {
+    let max_e9: u64 = kani::any();
+    let slots: u64 = kani::any();
+    kani::assume(max_e9 <= MAX_ABS_FUNDING_E9_PER_SLOT);
+
+    let bound = funding_bps_bound(max_e9 as i64, slots).expect("capped rate cannot overflow");
+    if slots <= MAX_ACCRUAL_DT_SLOTS {
+        let worst =
+            funding_bps_bound(MAX_ABS_FUNDING_E9_PER_SLOT as i64, MAX_ACCRUAL_DT_SLOTS).unwrap();
+        assert!(bound <= worst);
+    }
+}
+
+/// One accrual at any price up to `MAX_ORACLE_PRICE`, any rate within
+/// `MAX_ABS_FUNDING_E9_PER_SLOT` and any `dt <= MAX_ACCRUAL_DT_SLOTS`
+/// advances the index exactly: the saturating arithmetic never engages.
+#[kani::proof]
+fn kani_funding_index_advance_exact_within_caps() {
+    let index: i128 = kani::any();
+    let price: u64 = kani::any();
+    let rate: i128 = kani::any();
+    let dt: u64 = kani::any();
+    kani::assume(price <= percolator::MAX_ORACLE_PRICE);
+    kani::assume(rate.unsigned_abs() <= MAX_ABS_FUNDING_E9_PER_SLOT as u128);
+    kani::assume(dt <= MAX_ACCRUAL_DT_SLOTS);
+
+    let step = price as i128 * rate * dt as i128;
+    kani::assume(index.checked_add(step).is_some());
+    assert_eq!(funding_index_advance(index, price, rate, dt), index + step);
+}
+
+/// Equal and opposite positions over the same index move never create
+/// funding: the payer's ceil and the receiver's floor differ by at most
+/// one unit, in the protocol's favour.
+#[kani::proof]
+fn kani_funding_owed_pair_never_mints() {
+    let q: u32 = kani::any();
+    let from: i64 = kani::any();
+    let to: i64 = kani::any();
+    let (q, from, to) = (q as i128, from as i128, to as i128);
+
+    let net = funding_owed(q, from, to) + funding_owed(-q, from, to);
+    assert!((0..=1).contains(&net));
+}
+
+/// Health is monotone in collateral: adding capital never lowers equity
+/// or free collateral, and never takes an account below maintenance.
+#[kani::proof]
+fn kani_health_monotone_in_capital() {
+    let h = AccountHealth {
+        position_q: kani::any(),
+        capital: kani::any(),
+        pnl: kani::any(),
+        notional: kani::any(),
+        maintenance_req: kani::any(),
+        initial_req: kani::any(),
+    };
+    let extra: u128 = kani::any();
+    kani::assume(h.capital.checked_add(extra).is_some());
+    let richer = AccountHealth {
+        capital: h.capital + extra,
+        ..h
+    };
+
+    assert!(richer.equity() >= h.equity());
+    if h.above_maintenance() {
+        assert!(richer.above_maintenance());
+    }
+    assert!(
+        free_collateral(richer.equity(), h.initial_req)
+            >= free_collateral(h.equity(), h.initial_req)
+    );
+}
+
+/// More equity never shrinks the liquidation distance.
+#[kani::proof]
+fn kani_liq_distance_monotone_in_equity() {
+    let equity: u32 = kani::any();
+    let extra: u32 = kani::any();
+    let mm_req: u32 = kani::any();
+    let abs_q: u16 = kani::any();
+    let mm_bps: u16 = kani::any();
+    kani::assume(mm_bps <= 10_000);
+    let (mm_req, abs_q, mm_bps) = (mm_req as u128, abs_q as u128, mm_bps as u64);
+
+    let d = liq_distance_e6(equity as i128, mm_req, abs_q, mm_bps);
+    let d_richer = liq_distance_e6(equity as i128 + extra as i128, mm_req, abs_q, mm_bps);
+    assert!(d_richer >= d);
+}
+
+/// Projected equity moves one-for-one with collateral: fees and funding
+/// over the horizon do not depend on the account's equity.
+#[kani::proof]
+fn kani_liq_projected_equity_shifts_with_collateral() {
+    let position_q: i16 = kani::any();
+    let equity: i32 = kani::any();
+    let price_e6: u16 = kani::any();
+    let funding_rate_e9: i16 = kani::any();
+    let fee: u16 = kani::any();
+    let horizon_slots: u8 = kani::any();
+    let extra: u32 = kani::any();
+    let i = LiqPriceInputs {
+        position_q: position_q as i128,
+        equity: equity as i128,
+        price_e6: price_e6 as u64,
+        funding_rate_e9: funding_rate_e9 as i128,
+        maintenance_fee_per_slot: fee as u128,
+        horizon_slots: horizon_slots as u64,
+        ..LiqPriceInputs::default()
+    };
+    let richer = LiqPriceInputs {
+        equity: i.equity + extra as i128,
+        ..i
+    };
+
+    let base = liq_projected_equity(&i).expect("narrow inputs cannot overflow");
+    assert_eq!(liq_projected_equity(&richer), Some(base + extra as i128));
+}
@@ -383,13 +383,13 @@ fn test_init_market_rejects_vault_with_delegate() {
     let slab = Pubkey::new_unique();
     let mint = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
     let attacker = Pubkey::new_unique();
 
     svm.set_account(
         slab,
         Account {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; 1156736],
             owner: program_id,
             executable: false,
@@ -478,13 +478,13 @@ fn test_init_market_rejects_vault_with_close_authority() {
     let slab = Pubkey::new_unique();
     let mint = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
     let attacker = Pubkey::new_unique();
 
     svm.set_account(
         slab,
         Account {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; 1156736],
             owner: program_id,
             executable: false,
@@ -717,6 +717,202 @@ fn test_init_market_rejects_nonempty_vault() {
     );
 }
 
+/// With the trailing [vault_authority, system, token, ata] accounts,
+/// InitMarket creates the vault ATA itself; no vault keypair is involved.
+#[test]
+fn test_init_market_creates_the_vault_ata() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.svm.set_account(env.vault, Account::default()).unwrap();
+
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+    assert_eq!(
+        percolator_prog::accounts::derive_vault(&env.program_id, &env.slab, &env.mint),
+        env.vault
+    );
+    let admin = &env.payer;
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(env.mint, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(
+                percolator_prog::accounts::ASSOCIATED_TOKEN_PROGRAM_ID,
+                false,
+            ),
+        ],
+        data: encode_init_market_full_v2(&admin.pubkey(), &env.mint, &TEST_FEED_ID, 0, 0, 0),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&admin.pubkey()),
+        &[admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("InitMarket should create the vault ATA");
+
+    let vault = env.svm.get_account(&env.vault).unwrap();
+    assert_eq!(vault.owner, spl_token::ID);
+    let tok = TokenAccount::unpack(&vault.data).unwrap();
+    assert_eq!(tok.mint, env.mint);
+    assert_eq!(tok.owner, vault_pda);
+    assert_eq!(tok.amount, 0);
+    assert_eq!(tok.state, AccountState::Initialized);
+}
+
+/// Two more trailing accounts, [matcher_program, matcher_ctx (signer)],
+/// also create the matcher context: one transaction for a pre-created slab.
+#[test]
+fn test_init_market_creates_the_matcher_context() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.svm.set_account(env.vault, Account::default()).unwrap();
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+    // Any executable program will do as the context's owner here.
+    let matcher_program = spl_token::ID;
+    let ctx = Keypair::new();
+
+    let admin = &env.payer;
+    let mut accounts = vec![
+        AccountMeta::new(admin.pubkey(), true),
+        AccountMeta::new(env.slab, false),
+        AccountMeta::new_readonly(env.mint, false),
+        AccountMeta::new(env.vault, false),
+        AccountMeta::new_readonly(sysvar::clock::ID, false),
+        AccountMeta::new_readonly(env.pyth_index, false),
+        AccountMeta::new_readonly(vault_pda, false),
+        AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(
+            percolator_prog::accounts::ASSOCIATED_TOKEN_PROGRAM_ID,
+            false,
+        ),
+        AccountMeta::new_readonly(matcher_program, false),
+    ];
+    let data = encode_init_market_full_v2(&admin.pubkey(), &env.mint, &TEST_FEED_ID, 0, 0, 0);
+
+    // The context must sign for its own creation.
+    let mut unsigned = accounts.clone();
+    unsigned.push(AccountMeta::new(ctx.pubkey(), false));
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            cu_ix(),
+            Instruction {
+                program_id: env.program_id,
+                accounts: unsigned,
+                data: data.clone(),
+            },
+        ],
+        Some(&admin.pubkey()),
+        &[admin],
+        env.svm.latest_blockhash(),
+    );
+    assert!(env.svm.send_transaction(tx).is_err());
+
+    accounts.push(AccountMeta::new(ctx.pubkey(), true));
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            cu_ix(),
+            Instruction {
+                program_id: env.program_id,
+                accounts,
+                data,
+            },
+        ],
+        Some(&admin.pubkey()),
+        &[admin, &ctx],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("InitMarket should create the vault and matcher context");
+
+    let created = env.svm.get_account(&ctx.pubkey()).unwrap();
+    assert_eq!(created.owner, matcher_program);
+    assert_eq!(
+        created.data.len(),
+        percolator_prog::constants::MATCHER_CONTEXT_LEN
+    );
+    assert!(created.data.iter().all(|&b| b == 0));
+    assert_eq!(env.svm.get_account(&env.vault).unwrap().owner, spl_token::ID);
+}
+
+/// A trailing writable signer after the create tail pays the rent; the
+/// admin only signs, read-only, and needs no lamports.
+#[test]
+fn test_init_market_rent_payer_need_not_be_admin() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.svm.set_account(env.vault, Account::default()).unwrap();
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+    let admin = Keypair::new();
+    let sponsor = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let ix = |sponsor_signs: bool| Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(env.mint, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(
+                percolator_prog::accounts::ASSOCIATED_TOKEN_PROGRAM_ID,
+                false,
+            ),
+            AccountMeta::new(Pubkey::new_unique(), sponsor_signs),
+        ],
+        data: encode_init_market_full_v2(&admin.pubkey(), &env.mint, &TEST_FEED_ID, 0, 0, 0),
+    };
+
+    let (unsigned, mut sponsored) = (ix(false), ix(true));
+    sponsored.accounts.last_mut().unwrap().pubkey = sponsor.pubkey();
+
+    // A rent payer that does not sign is refused.
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), unsigned],
+        Some(&sponsor.pubkey()),
+        &[&sponsor, &admin],
+        env.svm.latest_blockhash(),
+    );
+    assert!(env.svm.send_transaction(tx).is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), sponsored],
+        Some(&sponsor.pubkey()),
+        &[&sponsor, &admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("a sponsor pays the vault rent");
+    assert_eq!(
+        env.svm.get_account(&env.vault).unwrap().owner,
+        spl_token::ID
+    );
+    assert_eq!(
+        env.svm
+            .get_account(&admin.pubkey())
+            .map_or(0, |a| a.lamports),
+        0
+    );
+}
+
 // ============================================================================
 // UpdateConfig (tag 14) additional coverage
 // ============================================================================
@@ -953,3 +1149,235 @@ fn test_update_authority_insurance_survives_admin_burn() {
     env.try_update_authority(&ins_authority, AUTHORITY_INSURANCE, Some(&new_ins))
         .expect("insurance_authority survives admin burn");
 }
+
+/// DAO-owned market: the admin is a governance PDA. A PDA signing through
+/// `invoke_signed` reaches the callee as a plain signer (`is_signer`, no
+/// signature), so with signature verification off LiteSVM hands the
+/// program exactly what a governance program's CPI would.
+#[test]
+fn test_pda_admin_via_propose_accept() {
+    use solana_sdk::{message::Message, signature::Signature};
+
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let governance_program = Pubkey::new_unique();
+    let (dao, _) =
+        Pubkey::find_program_address(&[b"governance", env.slab.as_ref()], &governance_program);
+    assert!(!dao.is_on_curve());
+
+    let svm = std::mem::replace(&mut env.svm, LiteSVM::new());
+    env.svm = svm.with_sigverify(false);
+    let fee_payer = admin.pubkey();
+    let send = |env: &mut TestEnv, signer: Pubkey, data: Vec<u8>| {
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(signer, true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let message = Message::new_with_blockhash(
+            &[cu_ix(), ix],
+            Some(&fee_payer),
+            &env.svm.latest_blockhash(),
+        );
+        let tx = Transaction {
+            signatures: vec![Signature::default(); message.header.num_required_signatures as usize],
+            message,
+        };
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+    let propose = |key: &Pubkey| {
+        let mut data = vec![43u8];
+        data.extend_from_slice(key.as_ref());
+        data
+    };
+    let mut limits = vec![35u8];
+    limits.extend_from_slice(&1_000_000u128.to_le_bytes());
+    limits.extend_from_slice(&0u128.to_le_bytes());
+
+    let err = send(&mut env, dao, vec![44u8]).unwrap_err();
+    assert!(err.contains("Custom(15)"), "nothing nominated: {err}");
+    assert!(
+        send(&mut env, dao, propose(&dao)).is_err(),
+        "nominee is not admin yet"
+    );
+
+    send(&mut env, admin.pubkey(), propose(&dao)).expect("admin nominates the DAO");
+    let err = send(&mut env, Pubkey::new_unique(), vec![44u8]).unwrap_err();
+    assert!(
+        err.contains("Custom(15)"),
+        "only the nominee accepts: {err}"
+    );
+    send(&mut env, dao, vec![44u8]).expect("DAO PDA accepts via CPI");
+
+    assert!(send(&mut env, admin.pubkey(), limits.clone()).is_err());
+    send(&mut env, dao, limits).expect("DAO PDA passes admin checks");
+
+    // The DAO can hand the market on the same way.
+    let next = Keypair::new();
+    send(&mut env, dao, propose(&next.pubkey())).expect("DAO nominates");
+    send(&mut env, next.pubkey(), vec![44u8]).expect("nominee accepts");
+    assert!(
+        send(&mut env, dao, vec![44u8]).is_err(),
+        "nomination consumed"
+    );
+}
+
+/// Chunked InitSlab: the slab reads as uninitialized until InitMarket
+/// finalizes it, only the claiming signer may continue, and InitMarket
+/// refuses a slab whose zeroing has not reached the end.
+#[test]
+fn test_init_slab_chunks_gate_init_market() {
+    program_path();
+    let mut env = TestEnv::new();
+    let mut slab = env.svm.get_account(&env.slab).unwrap();
+    slab.data.fill(0xAB);
+    env.svm.set_account(env.slab, slab).unwrap();
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let other = Keypair::new();
+    env.svm.airdrop(&other.pubkey(), 1_000_000_000).unwrap();
+    let chunk = (SLAB_LEN / 3) as u32;
+
+    env.try_init_slab(&admin, chunk).expect("first chunk claims the slab");
+    assert!(
+        env.try_init_slab(&other, chunk).is_err(),
+        "only the claiming signer may continue"
+    );
+    assert!(env.try_crank().is_err(), "initializing slab must not crank");
+    let err = env
+        .try_init_market_raw(encode_init_market_with_cap(
+            &admin.pubkey(),
+            &env.mint,
+            &TEST_FEED_ID,
+            0,
+            80,
+        ))
+        .unwrap_err();
+    assert!(err.contains("Custom(49)"), "expected SlabInitIncomplete: {err}");
+
+    while env.svm.get_account(&env.slab).unwrap().data[SLAB_LEN - 1] != 0 {
+        env.try_init_slab(&admin, chunk).unwrap();
+    }
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    assert!(data[percolator_prog::constants::HEADER_LEN..]
+        .iter()
+        .all(|&b| b == 0));
+    env.init_market_with_invert(0);
+    assert!(env.try_init_slab(&admin, chunk).is_err());
+    env.try_crank().expect("finalized market cranks");
+}
+
+/// MigrateSlab: a version-0 market, which ends at the generation table,
+/// rejects everything until permissionless steps have grown it to
+/// `SLAB_LEN`. Its bytes are kept, the new regions are seeded, the slab
+/// stays rent exempt and the market trades on.
+#[test]
+fn test_migrate_slab_grows_version_0_market() {
+    use percolator_prog::constants::{
+        FEE_AUTHORITY_OFF, POSITION_BITMAP_OFF, SLAB_VERSION, TAIL_OFF,
+    };
+    use percolator_prog::state::offsets;
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_cap(0, 80);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+
+    // The same market as version 0 left it: no tail, header version 0.
+    let v0_len = sbf_offsets::TAIL;
+    let mut slab = env.svm.get_account(&env.slab).unwrap();
+    slab.data.truncate(v0_len);
+    slab.data[offsets::VERSION..offsets::VERSION + 4].copy_from_slice(&0u32.to_le_bytes());
+    slab.lamports = env.svm.minimum_balance_for_rent_exemption(v0_len);
+    let v0 = slab.data.clone();
+    env.svm.set_account(env.slab, slab).unwrap();
+
+    let err = env.try_crank().unwrap_err();
+    assert!(
+        err.contains("Custom(55)"),
+        "expected SlabMigrationRequired: {err}"
+    );
+
+    let payer = Keypair::new();
+    env.svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+    let mut steps = 0;
+    while env.svm.get_account(&env.slab).unwrap().data.len() < SLAB_LEN {
+        if steps > 0 {
+            assert!(env.try_crank().is_err(), "partly grown slab must not crank");
+        }
+        env.try_migrate_slab(&payer).expect("migration step");
+        steps += 1;
+    }
+    assert_eq!(steps, (SLAB_LEN - v0_len).div_ceil(10_240));
+
+    let slab = env.svm.get_account(&env.slab).unwrap();
+    assert!(slab.lamports >= env.svm.minimum_balance_for_rent_exemption(SLAB_LEN));
+    let version = u32::from_le_bytes(
+        slab.data[offsets::VERSION..offsets::VERSION + 4]
+            .try_into()
+            .unwrap(),
+    );
+    assert_eq!(version, SLAB_VERSION);
+    assert_eq!(slab.data[..offsets::VERSION], v0[..offsets::VERSION]);
+    assert_eq!(
+        slab.data[offsets::VERSION + 4..v0_len],
+        v0[offsets::VERSION + 4..]
+    );
+    let fee_authority = v0_len + (FEE_AUTHORITY_OFF - TAIL_OFF);
+    assert_eq!(
+        slab.data[fee_authority..fee_authority + 32],
+        slab.data[offsets::ADMIN..offsets::ADMIN + 32]
+    );
+    let bitmap = v0_len + (POSITION_BITMAP_OFF - TAIL_OFF);
+    for idx in [lp_idx, user_idx] {
+        let i = idx as usize;
+        assert_ne!(slab.data[bitmap + i / 8] & (1 << (i % 8)), 0, "slot {idx}");
+    }
+
+    env.try_migrate_slab(&payer).expect("a current slab is left alone");
+    env.crank();
+    env.trade(&user, &lp, lp_idx, user_idx, -1_000_000);
+    assert_eq!(env.read_account_position(user_idx), 0);
+}
+
+/// InitMarket names each slab problem: size (`InvalidSlabLen`), owner
+/// (`IllegalOwner`), rent (`SlabNotRentExempt`) and stale bytes
+/// (`SlabNotZeroed`), before anything else can trip over it.
+#[test]
+fn test_init_market_slab_validation_errors() {
+    program_path();
+    let data = |env: &TestEnv| {
+        encode_init_market_with_cap(&env.payer.pubkey(), &env.mint, &TEST_FEED_ID, 0, 80)
+    };
+    let with_slab = |f: &dyn Fn(&mut Account)| {
+        let mut env = TestEnv::new();
+        let mut slab = env.svm.get_account(&env.slab).unwrap();
+        f(&mut slab);
+        env.svm.set_account(env.slab, slab).unwrap();
+        let d = data(&env);
+        env.try_init_market_raw(d).unwrap_err()
+    };
+
+    let err = with_slab(&|a| a.data.truncate(SLAB_LEN - 8));
+    assert!(err.contains("Custom(4)"), "expected InvalidSlabLen: {err}");
+    let err = with_slab(&|a| a.owner = Pubkey::new_unique());
+    assert!(err.contains("IllegalOwner"), "expected IllegalOwner: {err}");
+    let err = with_slab(&|a| a.lamports = 1_000_000);
+    assert!(err.contains("Custom(50)"), "expected SlabNotRentExempt: {err}");
+    let err = with_slab(&|a| a.data[SLAB_LEN / 2] = 1);
+    assert!(err.contains("Custom(51)"), "expected SlabNotZeroed: {err}");
+}
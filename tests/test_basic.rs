@@ -80,12 +80,79 @@ fn read_engine_sweep_generation(env: &TestEnv) -> u64 {
 fn clear_risk_buffer_for_test(env: &mut TestEnv) {
     let mut slab = env.svm.get_account(&env.slab).unwrap();
     let buf_size = core::mem::size_of::<percolator_prog::risk_buffer::RiskBuffer>();
-    let gen_table_size = MAX_ACCOUNTS * 8;
-    let buf_off = SLAB_LEN - gen_table_size - buf_size;
+    let buf_off = sbf_offsets::RISK_BUF;
     slab.data[buf_off..buf_off + buf_size].fill(0);
     env.svm.set_account(env.slab, slab).unwrap();
 }
 
+/// sbf offset of a slab-tail region from its host offset: everything
+/// after the engine has the same layout on both targets.
+fn tail_off(host_off: usize) -> usize {
+    SLAB_LEN - (percolator_prog::constants::SLAB_LEN - host_off)
+}
+
+/// Unclaimed treasury share of trading fees, read from the slab tail.
+fn read_protocol_fees(env: &TestEnv) -> u128 {
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let off = tail_off(percolator_prog::constants::FEE_SPLIT_OFF) + 16;
+    u128::from_le_bytes(data[off..off + 16].try_into().unwrap())
+}
+
+/// Market history samples, oldest first, read from the slab tail.
+fn read_history(env: &TestEnv) -> Vec<percolator_prog::state::HistorySample> {
+    use percolator_prog::constants::{HISTORY_LEN, HISTORY_OFF};
+    use percolator_prog::state::{history_sample, read_history_cursor};
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let off = tail_off(HISTORY_OFF);
+    let region = &data[off..off + HISTORY_LEN];
+    let (_, count) = read_history_cursor(region);
+    (0..count).map(|i| history_sample(region, i)).collect()
+}
+
+/// LP performance counters for `idx`, if tracked, read from the slab tail.
+fn read_lp_stats(env: &TestEnv, idx: u16) -> Option<percolator_prog::state::LpStats> {
+    use percolator_prog::constants::{
+        GEN_TABLE_OFF, LP_STATS_CAP, LP_STATS_ENTRY_LEN, LP_STATS_OFF,
+    };
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let g = tail_off(GEN_TABLE_OFF) + idx as usize * 8;
+    let gen = u64::from_le_bytes(data[g..g + 8].try_into().unwrap());
+    (0..LP_STATS_CAP).find_map(|slot| {
+        let off = tail_off(LP_STATS_OFF) + slot * LP_STATS_ENTRY_LEN;
+        let u64_at = |o: usize| u64::from_le_bytes(data[off + o..off + o + 8].try_into().unwrap());
+        let u128_at =
+            |o: usize| u128::from_le_bytes(data[off + o..off + o + 16].try_into().unwrap());
+        let s = percolator_prog::state::LpStats {
+            generation: u64_at(0),
+            idx: u16::from_le_bytes([data[off + 8], data[off + 9]]),
+            volume: u128_at(16),
+            fees_earned: u128_at(32),
+            spread_pnl: u128_at(48) as i128,
+            fills: u64_at(64),
+            last_fill_slot: u64_at(72),
+            utilization_bps: u64_at(80),
+        };
+        (gen != 0 && s.generation == gen && s.idx == idx).then_some(s)
+    })
+}
+
+/// Epoch counters for `idx`, read from the slab tail.
+fn read_epoch_stats(env: &TestEnv, idx: u16) -> percolator_prog::state::EpochStats {
+    use percolator_prog::constants::{EPOCH_STATS_ENTRY_LEN, EPOCH_STATS_TABLE_OFF};
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let off = tail_off(EPOCH_STATS_TABLE_OFF) + idx as usize * EPOCH_STATS_ENTRY_LEN;
+    let u64_at = |o: usize| u64::from_le_bytes(data[off + o..off + o + 8].try_into().unwrap());
+    percolator_prog::state::EpochStats {
+        epoch: u64_at(0),
+        start_equity: u64_at(8) as i64,
+        net_flow: u64_at(16) as i64,
+        volume: u64_at(24),
+        last_epoch: u64_at(32),
+        last_pnl: u64_at(40) as i64,
+        last_volume: u64_at(48),
+    }
+}
+
 fn write_account_fee_credits(env: &mut TestEnv, idx: u16, value: i128) {
     const ACCOUNT_SIZE: usize = 416;
     const FEE_CREDITS_OFFSET: usize = 280;
@@ -1581,6 +1648,19 @@ fn test_matcher_vamm_mode_with_impact() {
         "vAMM exec_price mismatch: expected {} got {}",
         expected_price, exec_price
     );
+    assert_eq!(
+        percolator_prog::policy::vamm_exec_price_e6(
+            oracle_price,
+            req_size,
+            5,
+            10,
+            200,
+            50,
+            10_000_000_000
+        ),
+        Some(expected_price),
+        "the matcher prices on the reference curve"
+    );
 
     println!("VAMM MODE VERIFIED: Correct pricing with 20 bps (10 spread + 5 fee + 5 impact)");
 }
@@ -6637,6 +6717,65 @@ fn test_per_account_maintenance_fee_not_back_charged_to_new_user() {
     );
 }
 
+/// Maintenance fee sweeps move value from account capital into insurance and
+/// never mint it. Uses an odd per-slot fee and odd slot deltas so any
+/// rounding in the per-account cursor math would show up as drift between
+/// (c_tot + insurance) and the engine vault.
+#[test]
+fn test_maintenance_fee_sweep_conserves_value() {
+    program_path();
+    let mut env = TestEnv::new();
+    let data = encode_init_market_with_maint_fee_bounded(
+        &env.payer.pubkey(),
+        &env.mint,
+        &TEST_FEED_ID,
+        1_000_000_000,
+        7, // maintenance_fee_per_slot — odd to exercise rounding
+        0,
+    );
+    env.try_init_market_raw(data).expect("init_market");
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    env.top_up_insurance(&admin, 1_000_000_001);
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 50_000_000_003);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_001);
+
+    let total_before = env.read_c_tot() + env.read_insurance_balance();
+    let vault_before = env.read_engine_vault();
+    assert_eq!(
+        total_before, vault_before,
+        "baseline: capital + insurance == vault"
+    );
+
+    for slot in [3u64, 16, 29, 47, 71] {
+        env.set_slot(slot);
+        env.crank();
+        let c_tot = env.read_c_tot();
+        let ins = env.read_insurance_balance();
+        assert_eq!(
+            env.read_engine_vault(),
+            vault_before,
+            "maintenance fees are internal transfers; vault must not move"
+        );
+        assert_eq!(
+            c_tot + ins,
+            total_before,
+            "fee sweep at slot {slot} created or destroyed value \
+             (c_tot={c_tot}, insurance={ins})"
+        );
+    }
+
+    assert!(
+        env.read_c_tot() < total_before - 1_000_000_001,
+        "fees must actually have been charged"
+    );
+}
+
 /// Disproof of the "fee sync erases market accrual" audit claim.
 ///
 /// Hypothesis under test: when recurring fee sync self-advances
@@ -7176,6 +7315,66 @@ fn test_keeper_crank_candidate_cranks_do_not_run_bitmap_fee_sweep() {
     );
 }
 
+/// Maintenance fees accrue lazily: one empty crank realizes at most
+/// FEE_SWEEP_BUDGET accounts no matter how many are live, and an account
+/// the sweep has not reached yet pays its whole elapsed interval the next
+/// time it is touched, so nothing is lost by leaving it behind.
+#[test]
+fn test_fee_sweep_is_bounded_and_untouched_accounts_settle_on_touch() {
+    program_path();
+    let mut env = TestEnv::new();
+    let data = encode_init_market_with_maint_fee_bounded(
+        &env.payer.pubkey(),
+        &env.mint,
+        &TEST_FEED_ID,
+        1_000_000_000,
+        1_000,
+        0,
+    );
+    env.try_init_market_raw(data).expect("init_market");
+    let budget = percolator_prog::constants::FEE_SWEEP_BUDGET;
+
+    env.set_slot(10);
+    let mut owners = Vec::new();
+    let mut caps = Vec::new();
+    for _ in 0..budget + 12 {
+        let owner = Keypair::new();
+        let idx = env.init_user(&owner);
+        assert_eq!(idx as usize, owners.len());
+        env.deposit(&owner, idx, 1_000_000_000);
+        caps.push(env.read_account_capital(idx));
+        owners.push(owner);
+    }
+
+    env.set_slot(20);
+    env.crank();
+    let charged = (0..owners.len())
+        .filter(|&i| env.read_account_capital(i as u16) < caps[i])
+        .count();
+    assert!(
+        charged > 0 && charged <= budget,
+        "one crank must realize between 1 and {budget} fee syncs, got {charged}"
+    );
+
+    let last = (owners.len() - 1) as u16;
+    assert_eq!(
+        env.read_account_capital(last),
+        caps[last as usize],
+        "the sweep must stop before the last account"
+    );
+
+    // Touching the skipped account settles its full [10, 40] interval.
+    env.set_slot(40);
+    let amount = 1_000_000u64;
+    env.try_withdraw(&owners[last as usize], last, amount)
+        .expect("withdraw");
+    assert_eq!(
+        caps[last as usize] - env.read_account_capital(last),
+        amount as u128 + 1_000 * 30,
+        "untouched account must pay exactly 30 slots of fees on its next touch"
+    );
+}
+
 #[test]
 fn test_keeper_crank_noop_candidates_do_not_suppress_phase2() {
     program_path();
@@ -7801,3 +8000,2586 @@ fn test_init_hyperp_with_perm_resolve_accepts_nonzero_mark_min_fee() {
     env.try_init_market_raw(payload)
         .expect("Hyperp+perm_resolve+nonzero mark_min_fee must succeed");
 }
+
+/// Canonical end-to-end regression: InitMarket -> InitLP/InitUser -> Deposit
+/// -> TradeNoCpi -> KeeperCrank -> Withdraw -> crank-candidate liquidation,
+/// against the compiled SBF binary with a mock Pyth account. Every stage
+/// re-checks that the engine vault tracks the SPL vault.
+#[test]
+fn test_canonical_lifecycle_regression() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_cap(0, 80);
+
+    let check_vault = |env: &TestEnv, stage: &str| {
+        assert_eq!(
+            env.read_engine_vault() as u64,
+            env.vault_balance(),
+            "{stage}: engine vault must match SPL vault"
+        );
+        assert!(
+            env.read_c_tot() + env.read_insurance_balance() <= env.read_engine_vault(),
+            "{stage}: capital + insurance exceeds vault"
+        );
+    };
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+
+    let trader = Keypair::new();
+    let trader_idx = env.init_user(&trader);
+    env.deposit(&trader, trader_idx, 10_000_000_000);
+
+    let thin = Keypair::new();
+    let thin_idx = env.init_user(&thin);
+    env.deposit(&thin, thin_idx, 1_500_000_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    env.try_top_up_insurance(&admin, 1_000_000_000).unwrap();
+    check_vault(&env, "deposit");
+
+    env.set_slot(50);
+    env.crank();
+
+    env.trade(&trader, &lp, lp_idx, trader_idx, 10_000_000);
+    env.trade(&thin, &lp, lp_idx, thin_idx, 100_000_000);
+    assert_eq!(env.read_account_position(trader_idx), 10_000_000);
+    assert_eq!(env.read_account_position(thin_idx), 100_000_000);
+    check_vault(&env, "trade");
+
+    env.set_slot(100);
+    env.crank();
+    check_vault(&env, "crank");
+
+    let vault_before = env.vault_balance();
+    env.try_withdraw(&trader, trader_idx, 1_000_000_000)
+        .expect("margin-healthy trader withdraws part of its capital");
+    assert_eq!(env.vault_balance(), vault_before - 1_000_000_000);
+    check_vault(&env, "withdraw");
+
+    env.set_slot_and_price(2000, 90_000_000);
+    env.try_liquidate(thin_idx)
+        .expect("crank with FullClose candidate succeeds");
+    assert_eq!(
+        env.read_account_position(thin_idx),
+        0,
+        "thin-margin long is liquidated after the price drop"
+    );
+    assert_ne!(
+        env.read_account_position(trader_idx),
+        0,
+        "well-capitalized trader keeps its position"
+    );
+    check_vault(&env, "liquidate");
+}
+
+/// TradeNoCpi publishes the fill through return data so CPI callers and
+/// `simulateTransaction` clients need not scrape logs.
+#[test]
+fn test_trade_nocpi_sets_return_data() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_fee_weighted(0, 10_000, 10, 100);
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let ins_before = env.read_insurance_balance();
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new(lp.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_trade(lp_idx, user_idx, -2_000_000),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&user.pubkey()),
+        &[&user, &lp],
+        env.svm.latest_blockhash(),
+    );
+    let meta = env.svm.send_transaction(tx).expect("trade");
+    assert_eq!(meta.return_data.program_id, env.program_id);
+    let r = percolator_prog::ix::TradeResult::decode(&meta.return_data.data)
+        .expect("56-byte trade result");
+
+    assert_eq!(r.exec_size, -2_000_000);
+    assert_eq!(r.oracle_price_e6, env.read_last_effective_price());
+    assert_eq!(r.exec_price_e6, r.oracle_price_e6);
+    assert_eq!(r.fee_bps, 10);
+    // Trade fee only: recurring fees synced before the fill are excluded.
+    assert!(r.fee_paid > 0);
+    assert!(r.fee_paid <= env.read_insurance_balance() - ins_before);
+}
+
+/// `simulate_trade_nocpi` on a slab snapshot predicts exactly what the
+/// TradeNoCpi that follows does: fill, fee and both sides' position,
+/// capital and PnL, for an opening fill and a closing one at a new price.
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    ignore = "native debug engine scans unused zero-memory accounts; run with --release"
+)]
+fn test_simulate_trade_matches_sbf_trade_nocpi() {
+    use percolator_prog::simulate::simulate_trade_nocpi;
+
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_fee_weighted(0, 10_000, 10, 100);
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let mut scratch = vec![0u128; percolator_prog::constants::SLAB_LEN.div_ceil(16)];
+    let mut preview_then_trade = |env: &mut TestEnv, size: i128| {
+        let snapshot = env.svm.get_account(&env.slab).unwrap().data;
+        let slot = env.svm.get_sysvar::<Clock>().slot;
+        let preview = simulate_trade_nocpi(
+            &snapshot,
+            bytemuck::cast_slice_mut::<u128, u8>(&mut scratch),
+            lp_idx,
+            user_idx,
+            size,
+            0,
+            0,
+            slot,
+            env.read_last_effective_price(),
+        )
+        .expect("admissible trade previews");
+
+        env.svm.expire_blockhash();
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(lp.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(env.pyth_index, false),
+            ],
+            data: encode_trade(lp_idx, user_idx, size),
+        };
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&user.pubkey()),
+            &[&user, &lp],
+            env.svm.latest_blockhash(),
+        );
+        let meta = env.svm.send_transaction(tx).expect("trade");
+        let r = percolator_prog::ix::TradeResult::decode(&meta.return_data.data).unwrap();
+        assert_eq!(
+            (r.exec_size, r.exec_price_e6, r.oracle_price_e6),
+            (preview.size, preview.exec_price, preview.oracle_price)
+        );
+        assert_eq!((r.fee_bps, r.fee_paid), (preview.fee_bps, preview.fee_paid));
+        assert!(r.fee_paid > 0);
+        for (idx, side) in [(user_idx, preview.user), (lp_idx, preview.lp)] {
+            assert_eq!(env.read_account_position(idx), side.position_q);
+            assert_eq!(env.read_account_capital(idx), side.capital);
+            assert_eq!(env.read_account_pnl(idx), side.pnl);
+        }
+    };
+
+    preview_then_trade(&mut env, 2_000_000);
+    env.set_slot_and_price(200, 140_000_000);
+    preview_then_trade(&mut env, -2_000_000);
+    assert_eq!(env.read_account_position(user_idx), 0);
+}
+
+/// A retried TradeNoCpi with the same client_order_id is a no-op; a new id
+/// or no id trades normally.
+#[test]
+fn test_trade_nocpi_duplicate_client_order_id_is_noop() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let send = |env: &mut TestEnv, coid: u64| {
+        let mut data = encode_trade_with_exec_price(lp_idx, user_idx, 1_000_000, 0);
+        data.push(0); // flags
+        data.extend_from_slice(&coid.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(lp.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(env.pyth_index, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&user.pubkey()),
+            &[&user, &lp],
+            env.svm.latest_blockhash(),
+        );
+        env.svm.send_transaction(tx).expect("trade tx");
+    };
+
+    send(&mut env, 42);
+    assert_eq!(env.read_account_position(user_idx), 1_000_000);
+    let slab_after_first = env.svm.get_account(&env.slab).unwrap().data;
+
+    send(&mut env, 42);
+    assert_eq!(env.read_account_position(user_idx), 1_000_000);
+    assert_eq!(
+        env.svm.get_account(&env.slab).unwrap().data,
+        slab_after_first,
+        "duplicate order id must not touch the slab"
+    );
+
+    send(&mut env, 43);
+    assert_eq!(env.read_account_position(user_idx), 2_000_000);
+    send(&mut env, 0);
+    send(&mut env, 0);
+    assert_eq!(env.read_account_position(user_idx), 4_000_000);
+}
+
+#[test]
+fn test_withdraw_nonce_rejects_replay() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let send = |env: &mut TestEnv, nonce: u64| {
+        let ata = env.create_ata(&user.pubkey(), 0);
+        let (vault_pda, _) =
+            Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+        let mut data = encode_withdraw(user_idx, 1_000);
+        data.extend_from_slice(&nonce.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new(env.vault, false),
+                AccountMeta::new(ata, false),
+                AccountMeta::new_readonly(vault_pda, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(env.pyth_index, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&user.pubkey()),
+            &[&user],
+            env.svm.latest_blockhash(),
+        );
+        env.svm.send_transaction(tx).map(|_| ())
+    };
+
+    send(&mut env, 0).expect("nonce 0 must be accepted");
+    assert!(
+        send(&mut env, 0).is_err(),
+        "replayed nonce must be rejected"
+    );
+    assert!(send(&mut env, 5).is_err(), "future nonce must be rejected");
+    send(&mut env, 1).expect("next nonce must be accepted");
+    // Omitting the nonce leaves it unchecked and unchanged.
+    env.try_withdraw(&user, user_idx, 1_000)
+        .expect("withdraw without nonce");
+    send(&mut env, 2).expect("nonce not consumed by unchecked withdraw");
+}
+
+/// DepositWithPermit: the owner approves the permit PDA once, then a
+/// relayer lands signed deposits without any owner transaction signature.
+#[test]
+fn test_deposit_with_permit_consumes_the_nonce() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    let ata = env.create_ata(&user.pubkey(), 1_000_000_000);
+    let relayer = Keypair::new();
+    env.svm.airdrop(&relayer.pubkey(), 1_000_000_000).unwrap();
+    let (permit, _) =
+        Pubkey::find_program_address(&[b"permit", env.slab.as_ref()], &env.program_id);
+
+    let approve = spl_token::instruction::approve(
+        &spl_token::ID,
+        &ata,
+        &permit,
+        &user.pubkey(),
+        &[],
+        1_000_000_000,
+    )
+    .unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[approve],
+        Some(&user.pubkey()),
+        &[&user],
+        env.svm.latest_blockhash(),
+    );
+    env.svm.send_transaction(tx).expect("approve permit PDA");
+
+    let now = env.svm.get_sysvar::<Clock>().unix_timestamp;
+    let send = |env: &mut TestEnv, signer: &Keypair, amount: u64, nonce: u64, expiry: i64| {
+        let mut msg = env.slab.to_bytes().to_vec();
+        msg.extend_from_slice(&user_idx.to_le_bytes());
+        msg.extend_from_slice(&amount.to_le_bytes());
+        msg.extend_from_slice(&nonce.to_le_bytes());
+        msg.extend_from_slice(&expiry.to_le_bytes());
+        let mut data = vec![61u8];
+        data.extend_from_slice(&msg[32..]);
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(relayer.pubkey(), false),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new(ata, false),
+                AccountMeta::new(env.vault, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(permit, false),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ed25519_verify_ix(signer, &msg), ix],
+            Some(&relayer.pubkey()),
+            &[&relayer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+
+    let vault = env.vault_balance();
+    send(&mut env, &user, 400_000_000, 0, now + 60).expect("permit deposit");
+    assert_eq!(env.vault_balance(), vault + 400_000_000);
+    let balance = TokenAccount::unpack(&env.svm.get_account(&ata).unwrap().data)
+        .unwrap()
+        .amount;
+    assert_eq!(balance, 600_000_000);
+
+    let err = send(&mut env, &user, 400_000_000, 0, now + 60).unwrap_err();
+    assert!(err.contains("Custom(39)"), "replayed permit: {err}");
+    let err = send(&mut env, &user, 1_000_000, 1, now - 1).unwrap_err();
+    assert!(err.contains("Custom(59)"), "expired permit: {err}");
+    let err = send(&mut env, &relayer, 1_000_000, 1, now + 60).unwrap_err();
+    assert!(
+        err.contains("Custom(15)"),
+        "not the owner's signature: {err}"
+    );
+    // Past the approved allowance the token program refuses the pull.
+    assert!(send(&mut env, &user, 700_000_000, 1, now + 60).is_err());
+    send(&mut env, &user, 600_000_000, 1, now + 60).expect("next nonce");
+    assert_eq!(env.vault_balance(), vault + 1_000_000_000);
+}
+
+/// QueryHealth is read-only and reports the account's margin summary via
+/// return data.
+#[test]
+fn test_query_health_returns_snapshot() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let query = |env: &mut TestEnv| {
+        let mut data = vec![34u8];
+        data.extend_from_slice(&user_idx.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![AccountMeta::new_readonly(env.slab, false)],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&user.pubkey()),
+            &[&user],
+            env.svm.latest_blockhash(),
+        );
+        let before = env.svm.get_account(&env.slab).unwrap().data;
+        let meta = env.svm.send_transaction(tx).expect("query health");
+        assert_eq!(env.svm.get_account(&env.slab).unwrap().data, before);
+        percolator_prog::ix::HealthSnapshot::decode(&meta.return_data.data)
+            .expect("96-byte health snapshot")
+    };
+
+    let flat = query(&mut env);
+    let capital = env.read_account_capital(user_idx) as i128;
+    assert_eq!(flat.position_q, 0);
+    assert_eq!(flat.equity, capital);
+    assert_eq!(flat.maintenance_req, 0);
+    assert_eq!(flat.free_collateral, capital as u128);
+
+    env.trade(&user, &lp, lp_idx, user_idx, 10_000_000);
+    let open = query(&mut env);
+    assert_eq!(open.position_q, 10_000_000);
+    assert!(open.maintenance_req > 0);
+    assert!(open.initial_req >= open.maintenance_req);
+    assert!(open.free_collateral < flat.free_collateral);
+    assert_eq!(open.price_e6, env.read_last_effective_price());
+}
+
+/// Guarded-launch limits: user capital and position notional are capped,
+/// reductions stay open, LPs are exempt.
+#[test]
+fn test_account_limits_cap_deposits_and_positions() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let set_limits = |env: &mut TestEnv, signer: &Keypair, capital: u128, notional: u128| {
+        let mut data = vec![35u8];
+        data.extend_from_slice(&capital.to_le_bytes());
+        data.extend_from_slice(&notional.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm.send_transaction(tx).map(|_| ())
+    };
+
+    let price = env.read_last_effective_price() as u128;
+    let notional = |pos: u128| (pos * price).div_ceil(percolator::POS_SCALE);
+    let cap = env.read_account_capital(user_idx) + 500_000_000;
+    assert!(set_limits(&mut env, &user, cap, 0).is_err(), "admin only");
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    set_limits(&mut env, &admin, cap, notional(2_000_000)).expect("set limits");
+
+    // Capital cap on user deposits; LPs are exempt.
+    env.deposit(&user, user_idx, 500_000_000);
+    let err = env.try_deposit(&user, user_idx, 1_000_000).unwrap_err();
+    assert!(err.contains("Custom(30)"), "DepositCapExceeded: {err}");
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+
+    // Position cap applies to growth only.
+    env.trade(&user, &lp, lp_idx, user_idx, 2_000_000);
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, 1_000_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(40)"), "PositionLimitExceeded: {err}");
+    set_limits(&mut env, &admin, 0, 1).expect("lower limit");
+    env.trade(&user, &lp, lp_idx, user_idx, -500_000);
+    assert_eq!(env.read_account_position(user_idx), 1_500_000);
+}
+
+/// Permissioned market: with an allowlist root set, InitUser and user
+/// deposits need a merkle proof for the signer; LPs are exempt.
+#[test]
+fn test_allowlist_gates_init_user_and_deposit() {
+    use solana_sdk::hash::hashv;
+
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    let member = Keypair::new();
+    let outsider = Keypair::new();
+
+    let leaf = |k: &Keypair| percolator_prog::policy::allowlist_leaf(&k.pubkey().to_bytes());
+    let (a, b) = (leaf(&user), leaf(&member));
+    let root = if a <= b {
+        hashv(&[&a, &b]).to_bytes()
+    } else {
+        hashv(&[&b, &a]).to_bytes()
+    };
+
+    let send = |env: &mut TestEnv, signer: &Keypair, accounts: Vec<AccountMeta>, data: Vec<u8>| {
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts,
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{e:?}"))
+    };
+    let set_root = |env: &mut TestEnv, signer: &Keypair, root: [u8; 32]| {
+        let mut data = vec![36u8];
+        data.extend_from_slice(&root);
+        let accounts = vec![
+            AccountMeta::new(signer.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ];
+        send(env, signer, accounts, data)
+    };
+    let with_proof = |mut data: Vec<u8>, proof: &[[u8; 32]]| {
+        data.push(proof.len() as u8);
+        for p in proof {
+            data.extend_from_slice(p);
+        }
+        data
+    };
+    let token_accounts = |env: &mut TestEnv, owner: &Keypair, amount: u64| {
+        env.svm.airdrop(&owner.pubkey(), 1_000_000_000).unwrap();
+        let ata = env.create_ata(&owner.pubkey(), amount);
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ]
+    };
+
+    assert!(set_root(&mut env, &user, root).is_err(), "admin only");
+    set_root(&mut env, &admin, root).expect("set root");
+
+    // Existing user must prove membership to deposit.
+    let accs = token_accounts(&mut env, &user, 1_000);
+    let err = send(&mut env, &user, accs, encode_deposit(user_idx, 1_000)).unwrap_err();
+    assert!(err.contains("Custom(41)"), "NotAllowlisted: {err}");
+    let accs = token_accounts(&mut env, &user, 1_000);
+    send(
+        &mut env,
+        &user,
+        accs,
+        with_proof(encode_deposit(user_idx, 1_000), &[b]),
+    )
+    .expect("deposit with proof");
+    // LPs are exempt.
+    env.deposit(&lp, lp_idx, 1_000);
+
+    // Onboarding: members with a proof only.
+    let accs = token_accounts(&mut env, &outsider, DEFAULT_INIT_PAYMENT);
+    let data = with_proof(encode_init_user(DEFAULT_INIT_PAYMENT), &[b]);
+    assert!(send(&mut env, &outsider, accs, data).is_err());
+    let accs = token_accounts(&mut env, &member, DEFAULT_INIT_PAYMENT);
+    send(
+        &mut env,
+        &member,
+        accs,
+        with_proof(encode_init_user(DEFAULT_INIT_PAYMENT), &[a]),
+    )
+    .expect("member init with proof");
+
+    // Zero root reopens the market.
+    set_root(&mut env, &admin, [0; 32]).expect("clear root");
+    env.deposit(&user, user_idx, 1_000);
+}
+
+/// With a fee split set, each fill's fee is divided between insurance,
+/// the protocol-fee accumulator and the LP's capital, and the three
+/// shares add back up to the fee.
+#[test]
+fn test_fee_split_routes_trade_fee() {
+    program_path();
+
+    // Same market and fill twice: without and with the split.
+    let run = |split: Option<(u16, u16)>| {
+        let mut env = TestEnv::new();
+        env.init_market_fee_weighted(0, 10_000, 10, 100);
+        let lp = Keypair::new();
+        let lp_idx = env.init_lp(&lp);
+        env.deposit(&lp, lp_idx, 10_000_000_000);
+        let user = Keypair::new();
+        let user_idx = env.init_user(&user);
+        env.deposit(&user, user_idx, 1_000_000_000);
+
+        if let Some((ib, tb)) = split {
+            let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+            let mut data = vec![37u8];
+            data.extend_from_slice(&ib.to_le_bytes());
+            data.extend_from_slice(&tb.to_le_bytes());
+            let ix = Instruction {
+                program_id: env.program_id,
+                accounts: vec![
+                    AccountMeta::new(admin.pubkey(), true),
+                    AccountMeta::new(env.slab, false),
+                ],
+                data,
+            };
+            let tx = Transaction::new_signed_with_payer(
+                &[cu_ix(), ix],
+                Some(&admin.pubkey()),
+                &[&admin],
+                env.svm.latest_blockhash(),
+            );
+            env.svm.send_transaction(tx).expect("set fee split");
+        }
+
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(lp.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(env.pyth_index, false),
+            ],
+            data: encode_trade(lp_idx, user_idx, -2_000_000),
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&user.pubkey()),
+            &[&user, &lp],
+            env.svm.latest_blockhash(),
+        );
+        let meta = env.svm.send_transaction(tx).expect("trade");
+        let r = percolator_prog::ix::TradeResult::decode(&meta.return_data.data).unwrap();
+        (
+            r.fee_paid,
+            env.read_insurance_balance(),
+            env.read_account_capital(lp_idx),
+            read_protocol_fees(&env),
+        )
+    };
+
+    let (fee, ins_base, lp_base, pf_base) = run(None);
+    assert!(fee > 0);
+    assert_eq!(pf_base, 0);
+
+    let (fee_split, ins, lp_cap, pf) = run(Some((2_000, 3_000)));
+    assert_eq!(fee_split, fee, "return data reports the whole fee");
+    let (_, treasury, lp_share) = percolator_prog::policy::fee_split(fee, 2_000, 3_000);
+    assert!(lp_share > 0);
+    assert_eq!(pf, treasury);
+    assert_eq!(lp_cap, lp_base + lp_share);
+    assert_eq!(
+        ins,
+        ins_base - lp_share,
+        "treasury share stays in insurance"
+    );
+}
+
+/// ClaimProtocolFees: only the fee authority may sweep, the sweep is capped
+/// by the accumulator, and vault and engine accounting move together.
+#[test]
+fn test_claim_protocol_fees_sweeps_to_treasury() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_fee_weighted(0, 10_000, 10, 100);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let send = |env: &mut TestEnv, signer: &Keypair, data: Vec<u8>, metas: Vec<AccountMeta>| {
+        let mut accounts = vec![
+            AccountMeta::new(signer.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ];
+        accounts.extend(metas);
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts,
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm.send_transaction(tx).map(|_| ())
+    };
+
+    // All of the fee goes to the treasury.
+    let mut data = vec![37u8];
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&10_000u16.to_le_bytes());
+    send(&mut env, &admin, data, vec![]).expect("set fee split");
+    env.trade(&user, &lp, lp_idx, user_idx, -2_000_000);
+    let accrued = read_protocol_fees(&env);
+    assert!(accrued > 1);
+
+    // Hand the fee role to a separate key (both sides sign).
+    let fee_auth = Keypair::new();
+    env.svm.airdrop(&fee_auth.pubkey(), 1_000_000_000).unwrap();
+    let mut data = vec![32u8, 5u8];
+    data.extend_from_slice(fee_auth.pubkey().as_ref());
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(fee_auth.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ],
+        data,
+    };
+    env.svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&admin.pubkey()),
+        &[&admin, &fee_auth],
+        env.svm.latest_blockhash(),
+    );
+    env.svm.send_transaction(tx).expect("rotate fee authority");
+
+    let (treasury, _) =
+        Pubkey::find_program_address(&[b"treasury", env.slab.as_ref()], &env.program_id);
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+    let treasury_ata = env.create_ata(&treasury, 0);
+    let dest = env.create_ata(&fee_auth.pubkey(), 0);
+    let vault = env.vault;
+    let claim_metas = |dest: Option<Pubkey>| {
+        let mut metas = vec![
+            AccountMeta::new(treasury_ata, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(treasury, false),
+        ];
+        metas.extend(dest.map(|d| AccountMeta::new(d, false)));
+        metas
+    };
+    let claim = |amount: u64| {
+        let mut data = vec![38u8];
+        data.extend_from_slice(&amount.to_le_bytes());
+        data
+    };
+    let token_balance = |env: &TestEnv, key: &Pubkey| {
+        TokenAccount::unpack(&env.svm.get_account(key).unwrap().data)
+            .unwrap()
+            .amount
+    };
+
+    let half = (accrued / 2) as u64;
+    assert!(
+        send(&mut env, &admin, claim(half), claim_metas(None)).is_err(),
+        "admin is not the fee authority"
+    );
+    assert!(
+        send(
+            &mut env,
+            &fee_auth,
+            claim(accrued as u64 + 1),
+            claim_metas(None)
+        )
+        .is_err(),
+        "claim is capped by the accumulator"
+    );
+
+    let vault_before = env.vault_balance();
+    let ins_before = env.read_insurance_balance();
+    send(&mut env, &fee_auth, claim(half), claim_metas(None)).expect("sweep");
+    assert_eq!(token_balance(&env, &treasury_ata), half);
+    assert_eq!(env.vault_balance(), vault_before - half);
+    assert_eq!(env.read_insurance_balance(), ins_before - half as u128);
+    assert_eq!(read_protocol_fees(&env), accrued - half as u128);
+
+    // Sweep the rest and forward the whole treasury balance.
+    let rest = (accrued - half as u128) as u64;
+    send(&mut env, &fee_auth, claim(rest), claim_metas(Some(dest))).expect("claim");
+    assert_eq!(token_balance(&env, &treasury_ata), 0);
+    assert_eq!(token_balance(&env, &dest), accrued as u64);
+    assert_eq!(read_protocol_fees(&env), 0);
+}
+
+/// Epoch counters: fills add volume for both sides, deposits count as
+/// flows rather than PnL, and the first touch after a crank-rolled
+/// boundary closes the previous epoch's totals.
+#[test]
+fn test_epoch_counters_roll_over_on_crank() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let mut data = vec![39u8];
+    data.extend_from_slice(&50u64.to_le_bytes());
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ],
+        data,
+    };
+    env.svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&admin.pubkey()),
+        &[&admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm.send_transaction(tx).expect("set epoch length");
+
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+    let first = read_epoch_stats(&env, user_idx);
+    assert_eq!(first.epoch, 1, "SetEpochLength opens a new epoch");
+    assert!(first.volume > 0);
+    assert_eq!(read_epoch_stats(&env, lp_idx).volume, first.volume);
+
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 200);
+    env.crank();
+    env.deposit(&user, user_idx, 5_000_000);
+    let rolled = read_epoch_stats(&env, user_idx);
+    assert!(rolled.epoch > first.epoch);
+    assert_eq!(rolled.last_epoch, first.epoch);
+    assert_eq!(rolled.last_volume, first.volume);
+    assert!(rolled.last_pnl <= 0, "flat price: fees only");
+    assert_eq!(rolled.volume, 0);
+    assert_eq!(rolled.net_flow, 5_000_000);
+
+    env.trade(&user, &lp, lp_idx, user_idx, -1_000_000);
+    let after = read_epoch_stats(&env, user_idx);
+    assert_eq!(after.epoch, rolled.epoch);
+    assert_eq!(after.volume, first.volume);
+    assert_eq!(after.last_volume, first.volume);
+}
+
+#[test]
+fn test_enter_withdraw_only_after_crank_staleness() {
+    program_path();
+    let mut env = TestEnv::new();
+    // max_crank_staleness_slots = 79: eligible after 790 uncranked slots.
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+
+    let enter = |env: &mut TestEnv| {
+        let caller = Keypair::new();
+        env.svm.airdrop(&caller.pubkey(), 1_000_000_000).unwrap();
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+            ],
+            data: vec![40u8],
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&caller.pubkey()),
+            &[&caller],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+
+    assert!(enter(&mut env).is_err(), "fresh crank: not eligible");
+
+    // Keepers go dark: the clock moves past K * staleness with no crank.
+    let last = env.read_last_market_slot();
+    env.set_slot_and_price_raw_no_walk(last + 800, 138_000_000);
+    enter(&mut env).expect("stale market enters withdraw-only");
+    enter(&mut env).expect("idempotent once set");
+
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, 1_000_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(42)"), "trade blocked: {err}");
+    let err = env.try_deposit(&user, user_idx, 1_000_000).unwrap_err();
+    assert!(err.contains("Custom(42)"), "deposit blocked: {err}");
+    let last_slot = env.read_last_market_slot();
+    env.try_withdraw(&user, user_idx, 10_000_000)
+        .expect("withdrawals stay open at the last accrued price");
+    assert_eq!(
+        env.read_last_market_slot(),
+        last_slot,
+        "withdraw-only does not accrue the market"
+    );
+}
+
+#[test]
+fn test_admin_multisig_collects_approvals() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+
+    let send = |env: &mut TestEnv, signer: &Keypair, data: Vec<u8>| {
+        env.svm.airdrop(&signer.pubkey(), 1_000_000_000).unwrap();
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let members = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let mut set = vec![41u8, 2, 3];
+    for m in &members {
+        set.extend_from_slice(m.pubkey().as_ref());
+    }
+    send(&mut env, &admin, set).expect("single admin installs 2-of-3");
+
+    let mut limits = vec![35u8];
+    limits.extend_from_slice(&1_000_000_000u128.to_le_bytes());
+    limits.extend_from_slice(&0u128.to_le_bytes());
+
+    let err = send(&mut env, &admin, limits.clone()).unwrap_err();
+    assert!(
+        err.contains("Custom(15)"),
+        "header admin alone is out: {err}"
+    );
+    let err = send(&mut env, &members[0], limits.clone()).unwrap_err();
+    assert!(err.contains("Custom(43)"), "one of two approvals: {err}");
+
+    // The approval names the executor: it is account 0 of the action.
+    let approve_for = |env: &TestEnv, executor: &Keypair, data: &[u8]| {
+        let keys = [executor.pubkey().to_bytes(), env.slab.to_bytes()];
+        let hash = percolator_prog::policy::admin_action_hash(&env.slab.to_bytes(), &keys, data);
+        let mut approve = vec![42u8];
+        approve.extend_from_slice(&hash);
+        approve
+    };
+    let approve = approve_for(&env, &members[0], &limits);
+    let err = send(&mut env, &admin, approve.clone()).unwrap_err();
+    assert!(
+        err.contains("Custom(15)"),
+        "old admin is not a member: {err}"
+    );
+    send(&mut env, &members[1], approve.clone()).expect("member approves");
+    send(&mut env, &members[0], limits.clone()).expect("second member executes");
+
+    // Approvals are consumed on execution.
+    let err = send(&mut env, &members[2], limits.clone()).unwrap_err();
+    assert!(
+        err.contains("Custom(43)"),
+        "replay needs fresh approvals: {err}"
+    );
+
+    // An approval for different bytes does not carry over.
+    let approve = approve_for(&env, &members[2], &limits);
+    send(&mut env, &members[1], approve).expect("approve again");
+    let mut other = limits.clone();
+    other[1] ^= 1;
+    assert!(send(&mut env, &members[2], other).is_err());
+    // Nor does it carry over to a different executor.
+    let err = send(&mut env, &members[0], limits.clone()).unwrap_err();
+    assert!(
+        err.contains("Custom(43)"),
+        "approval is bound to its executor: {err}"
+    );
+    send(&mut env, &members[2], limits.clone()).expect("matching bytes execute");
+
+    // One member flooding approvals cannot evict another's live proposal:
+    // once the table is full, new actions are rejected instead.
+    let approve = approve_for(&env, &members[2], &limits);
+    send(&mut env, &members[1], approve).expect("member proposes");
+    for junk in 1..=3u8 {
+        let mut flood = vec![42u8];
+        flood.extend_from_slice(&[junk; 32]);
+        send(&mut env, &members[0], flood).expect("free entry");
+    }
+    let mut flood = vec![42u8];
+    flood.extend_from_slice(&[4u8; 32]);
+    let err = send(&mut env, &members[0], flood).unwrap_err();
+    assert!(err.contains("Custom(61)"), "table full: {err}");
+    send(&mut env, &members[2], limits).expect("the proposal survived the flood");
+}
+
+#[test]
+fn test_dust_limits_reject_small_fills_and_crank_sweeps_dust() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+    let dusty = Keypair::new();
+    let dusty_idx = env.init_user(&dusty);
+    env.deposit(&dusty, dusty_idx, 1_000_000_000);
+    // Opened before the floors exist, so it is dust once they do.
+    env.trade(&dusty, &lp, lp_idx, dusty_idx, 50_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let mut data = vec![46u8];
+    data.extend_from_slice(&100_000u128.to_le_bytes());
+    data.extend_from_slice(&500_000u128.to_le_bytes());
+    data.extend_from_slice(&lp_idx.to_le_bytes());
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ],
+        data,
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&admin.pubkey()),
+        &[&admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("admin sets dust limits");
+
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, 50_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(44)"), "fill below min trade: {err}");
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, 200_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(44)"), "leaves dust position: {err}");
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, -700_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(44)"), "reduce into dust: {err}");
+
+    // The crank closes a touch-only dust candidate against the sweep LP;
+    // healthy candidates are left alone.
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 10);
+    let caller = Keypair::new();
+    env.svm.airdrop(&caller.pubkey(), 1_000_000_000).unwrap();
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(caller.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_crank_with_touch_candidates(&[user_idx, dusty_idx]),
+    };
+    env.svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&caller.pubkey()),
+        &[&caller],
+        env.svm.latest_blockhash(),
+    );
+    env.svm.send_transaction(tx).expect("crank sweeps dust");
+    assert_eq!(env.read_account_position(dusty_idx), 0);
+    assert_eq!(env.read_account_position(user_idx), 1_000_000);
+    assert_eq!(read_pnl_ledger(&env, dusty_idx).basis.position_q, 0);
+    assert_eq!(
+        read_pnl_ledger(&env, lp_idx).basis.position_q,
+        env.read_account_position(lp_idx)
+    );
+
+    // Flattening is always allowed, however small the fill.
+    env.trade(&user, &lp, lp_idx, user_idx, -1_000_000);
+    assert_eq!(env.read_account_position(user_idx), 0);
+}
+
+/// A sweep LP too thin to take a dust position over leaves it in place:
+/// the crank still succeeds.
+#[test]
+fn test_dust_sweep_skips_candidate_the_sweep_lp_cannot_absorb() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let thin = Keypair::new();
+    let thin_idx = env.init_lp(&thin);
+    env.deposit(&thin, thin_idx, 1_000);
+    let dusty = Keypair::new();
+    let dusty_idx = env.init_user(&dusty);
+    env.deposit(&dusty, dusty_idx, 1_000_000_000);
+    env.trade(&dusty, &lp, lp_idx, dusty_idx, 50_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let mut data = vec![46u8];
+    data.extend_from_slice(&100_000u128.to_le_bytes());
+    data.extend_from_slice(&500_000u128.to_le_bytes());
+    data.extend_from_slice(&thin_idx.to_le_bytes());
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ],
+        data,
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&admin.pubkey()),
+        &[&admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("admin sets dust limits");
+
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 10);
+    let caller = Keypair::new();
+    env.svm.airdrop(&caller.pubkey(), 1_000_000_000).unwrap();
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(caller.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_crank_with_touch_candidates(&[dusty_idx]),
+    };
+    env.svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&caller.pubkey()),
+        &[&caller],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("crank succeeds past an unsweepable candidate");
+    assert_eq!(env.read_account_position(dusty_idx), 50_000);
+    assert_eq!(env.read_account_position(thin_idx), 0);
+}
+
+#[test]
+fn test_circuit_breaker_pauses_opening_after_price_jump() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let set = |env: &mut TestEnv, bps: u64, window: u64, cooldown: u64| {
+        let mut data = vec![47u8];
+        data.extend_from_slice(&bps.to_le_bytes());
+        data.extend_from_slice(&window.to_le_bytes());
+        data.extend_from_slice(&cooldown.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(admin.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&admin.pubkey()),
+            &[&admin],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+    let err = set(&mut env, 100, 0, 200).unwrap_err();
+    assert!(err.contains("Custom(26)"), "window required: {err}");
+    // 1% within 1_000 slots trips a 200-slot cooling-off period.
+    set(&mut env, 100, 1_000, 200).expect("admin sets breaker");
+
+    // The first read anchors at the current price.
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot_and_price(slot + 10, 141_000_000);
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, 500_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(45)"), "opening paused: {err}");
+    env.trade(&user, &lp, lp_idx, user_idx, -500_000);
+    assert_eq!(env.read_account_position(user_idx), 500_000);
+
+    // Price holds; once the cooling-off period ends, opening resumes.
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 300);
+    env.trade(&user, &lp, lp_idx, user_idx, 500_000);
+    assert_eq!(env.read_account_position(user_idx), 1_000_000);
+}
+
+#[test]
+fn test_trade_rate_limit_spaces_user_fills() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let set = |env: &mut TestEnv, min_slots: u64| {
+        let mut data = vec![48u8];
+        data.extend_from_slice(&min_slots.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(admin.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&admin.pubkey()),
+            &[&admin],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+    let err = set(&mut env, 151).unwrap_err();
+    assert!(err.contains("Custom(26)"), "capped: {err}");
+    set(&mut env, 20).expect("admin sets rate limit");
+
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 19);
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, -500_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(46)"), "too soon: {err}");
+    assert_eq!(env.read_account_position(user_idx), 1_000_000);
+
+    env.set_slot(slot + 20);
+    env.trade(&user, &lp, lp_idx, user_idx, -500_000);
+    assert_eq!(env.read_account_position(user_idx), 500_000);
+
+    // Off again: back-to-back fills pass.
+    set(&mut env, 0).expect("admin clears rate limit");
+    env.trade(&user, &lp, lp_idx, user_idx, -500_000);
+    assert_eq!(env.read_account_position(user_idx), 0);
+}
+
+#[test]
+fn test_liquidator_window_reserves_fresh_liquidations() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_cap(0, 80);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let alice = Keypair::new();
+    let alice_idx = env.init_user(&alice);
+    env.deposit(&alice, alice_idx, 1_500_000_000);
+    let bob = Keypair::new();
+    let bob_idx = env.init_user(&bob);
+    env.deposit(&bob, bob_idx, 1_500_000_000);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    env.try_top_up_insurance(&admin, 1_000_000_000).unwrap();
+    env.set_slot(50);
+    env.crank();
+
+    let liquidator = Keypair::new();
+    env.svm
+        .airdrop(&liquidator.pubkey(), 1_000_000_000)
+        .unwrap();
+    let set = |env: &mut TestEnv, window: u64, keys: &[Pubkey]| {
+        let mut data = vec![49u8];
+        data.extend_from_slice(&window.to_le_bytes());
+        data.push(keys.len() as u8);
+        for k in keys {
+            data.extend_from_slice(k.as_ref());
+        }
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(admin.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&admin.pubkey()),
+            &[&admin],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+    let err = set(&mut env, 100, &[]).unwrap_err();
+    assert!(
+        err.contains("Custom(26)"),
+        "window needs a liquidator: {err}"
+    );
+    set(&mut env, 150, &[liquidator.pubkey()]).expect("admin sets window");
+
+    // Both users sit near max leverage; liquidation starts below ~$129.5.
+    env.trade(&alice, &lp, lp_idx, alice_idx, 100_000_000);
+    env.trade(&bob, &lp, lp_idx, bob_idx, 100_000_000);
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot_and_price(slot, 127_000_000);
+
+    // Inside the window other keepers only touch.
+    env.try_liquidate(alice_idx).expect("crank still succeeds");
+    env.try_liquidate(bob_idx).expect("crank still succeeds");
+    assert_ne!(env.read_account_position(alice_idx), 0);
+    assert_ne!(env.read_account_position(bob_idx), 0);
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(liquidator.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_crank_with_candidates(&[alice_idx]),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&liquidator.pubkey()),
+        &[&liquidator],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("whitelisted liquidator acts at once");
+    assert_eq!(env.read_account_position(alice_idx), 0);
+
+    // Once the window lapses, any keeper may liquidate.
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 150);
+    env.try_liquidate(bob_idx).expect("open liquidation");
+    assert_eq!(env.read_account_position(bob_idx), 0);
+}
+
+#[test]
+fn test_backstop_vault_shares_fee_share_and_withdraw_delay() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_cap(0, 80);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 5_000_000_000);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    env.top_up_insurance(&admin, 1_000_000_000);
+    env.set_slot(50);
+    env.crank();
+
+    fn send(
+        env: &mut TestEnv,
+        signer: &Keypair,
+        accounts: Vec<AccountMeta>,
+        data: Vec<u8>,
+    ) -> Result<(), String> {
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts,
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    }
+    let set = |env: &mut TestEnv, share_bps: u16, delay: u64| {
+        let mut data = vec![50u8];
+        data.extend_from_slice(&share_bps.to_le_bytes());
+        data.extend_from_slice(&delay.to_le_bytes());
+        let metas = vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ];
+        send(env, &admin, metas, data)
+    };
+    let deposit = |env: &mut TestEnv, who: &Keypair, amount: u64| {
+        env.svm.airdrop(&who.pubkey(), 1_000_000_000).unwrap();
+        let ata = env.create_ata(&who.pubkey(), amount);
+        let mut data = vec![56u8];
+        data.extend_from_slice(&amount.to_le_bytes());
+        let metas = vec![
+            AccountMeta::new(who.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ];
+        send(env, who, metas, data)
+    };
+    let request = |env: &mut TestEnv, who: &Keypair, shares: u128| {
+        let mut data = vec![57u8];
+        data.extend_from_slice(&shares.to_le_bytes());
+        let metas = vec![
+            AccountMeta::new(who.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ];
+        send(env, who, metas, data)
+    };
+    let withdraw = |env: &mut TestEnv, who: &Keypair, dest: Pubkey| {
+        let (vault_pda, _) =
+            Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+        let metas = vec![
+            AccountMeta::new(who.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new(dest, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ];
+        send(env, who, metas, vec![58u8])
+    };
+    let backstop_u128 = |env: &TestEnv, at: usize| {
+        let data = env.svm.get_account(&env.slab).unwrap().data;
+        let off = tail_off(percolator_prog::constants::BACKSTOP_OFF) + at;
+        u128::from_le_bytes(data[off..off + 16].try_into().unwrap())
+    };
+    let balance = |env: &TestEnv, ata: &Pubkey| {
+        TokenAccount::unpack(&env.svm.get_account(ata).unwrap().data)
+            .unwrap()
+            .amount
+    };
+
+    let err = set(&mut env, 5_001, 150).unwrap_err();
+    assert!(err.contains("Custom(26)"), "share above cap: {err}");
+    let err = set(&mut env, 5_000, 149).unwrap_err();
+    assert!(err.contains("Custom(26)"), "delay below the floor: {err}");
+    let err = set(&mut env, 5_000, 216_001).unwrap_err();
+    assert!(err.contains("Custom(26)"), "delay above the cap: {err}");
+    set(&mut env, 5_000, 150).expect("admin configures the backstop");
+
+    // The first deposit creates the vault's LP account, owned by the
+    // backstop PDA, at one share per unit.
+    let alice = Keypair::new();
+    let vault_idx = env.account_count;
+    deposit(&mut env, &alice, 10_000_000_000).expect("first backstop deposit");
+    assert_eq!(env.read_account_capital(vault_idx), 10_000_000_000);
+    assert_eq!(backstop_u128(&env, 32), 10_000_000_000, "total shares");
+    let (backstop_pda, _) =
+        Pubkey::find_program_address(&[b"backstop", env.slab.as_ref()], &env.program_id);
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let owner_off = sbf_offsets::ACCOUNTS
+        + vault_idx as usize * sbf_offsets::ACCOUNT_SIZE
+        + sbf_offsets::OWNER_IN_ACCOUNT;
+    assert_eq!(&data[owner_off..owner_off + 32], backstop_pda.as_ref());
+
+    // A liquidation pays the vault its share of the fee.
+    env.trade(&user, &lp, lp_idx, user_idx, 100_000_000);
+    env.set_slot_and_price(2000, 90_000_000);
+    env.try_liquidate(user_idx).expect("liquidation succeeds");
+    assert_eq!(env.read_account_position(user_idx), 0);
+    let paid = backstop_u128(&env, 16);
+    assert!(paid > 0, "the vault shares the liquidation fee");
+    assert!(env.read_account_capital(vault_idx) > 10_000_000_000);
+
+    // A later depositor buys in at the higher share price.
+    let bob = Keypair::new();
+    deposit(&mut env, &bob, 1_000_000_000).expect("second backstop deposit");
+    let bob_shares = backstop_u128(&env, 32) - 10_000_000_000;
+    assert!(
+        bob_shares < 1_000_000_000,
+        "shares are priced at vault equity"
+    );
+
+    // Withdrawals wait out the delay from the request.
+    let dest = env.create_ata(&alice.pubkey(), 0);
+    let err = withdraw(&mut env, &alice, dest).unwrap_err();
+    assert!(err.contains("Custom(57)"), "nothing requested: {err}");
+    let err = request(&mut env, &alice, 10_000_000_001).unwrap_err();
+    assert!(err.contains("Custom(13)"), "more than held: {err}");
+    request(&mut env, &alice, 10_000_000_000).expect("request withdrawal");
+    let err = withdraw(&mut env, &alice, dest).unwrap_err();
+    assert!(err.contains("Custom(57)"), "inside the delay: {err}");
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 150);
+    withdraw(&mut env, &alice, dest).expect("withdraw after the delay");
+    assert!(
+        balance(&env, &dest) > 10_000_000_000,
+        "alice takes her deposit and the fee share"
+    );
+    assert_eq!(backstop_u128(&env, 32), bob_shares, "alice's shares burned");
+    let err = withdraw(&mut env, &alice, dest).unwrap_err();
+    assert!(err.contains("Custom(57)"), "nothing left pending: {err}");
+}
+
+#[test]
+fn test_close_slab_waits_for_backstop_depositors_after_resolution() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+
+    fn send(
+        env: &mut TestEnv,
+        signer: &Keypair,
+        accounts: Vec<AccountMeta>,
+        data: Vec<u8>,
+    ) -> Result<(), String> {
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts,
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    }
+    let deposit = |env: &mut TestEnv, who: &Keypair, amount: u64| {
+        env.svm.airdrop(&who.pubkey(), 1_000_000_000).unwrap();
+        let ata = env.create_ata(&who.pubkey(), amount);
+        let mut data = vec![56u8];
+        data.extend_from_slice(&amount.to_le_bytes());
+        let metas = vec![
+            AccountMeta::new(who.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ];
+        send(env, who, metas, data)
+    };
+    let withdraw = |env: &mut TestEnv, who: &Keypair, dest: Pubkey| {
+        let (vault_pda, _) =
+            Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+        let metas = vec![
+            AccountMeta::new(who.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new(dest, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ];
+        send(env, who, metas, vec![58u8])
+    };
+    // (closed_units, total_shares)
+    let backstop = |env: &TestEnv| {
+        let data = env.svm.get_account(&env.slab).unwrap().data;
+        let off = tail_off(percolator_prog::constants::BACKSTOP_OFF);
+        (
+            u64::from_le_bytes(data[off + 56..off + 64].try_into().unwrap()),
+            u128::from_le_bytes(data[off + 32..off + 48].try_into().unwrap()),
+        )
+    };
+    let balance = |env: &TestEnv, ata: &Pubkey| {
+        TokenAccount::unpack(&env.svm.get_account(ata).unwrap().data)
+            .unwrap()
+            .amount
+    };
+
+    let alice = Keypair::new();
+    let bob = Keypair::new();
+    deposit(&mut env, &alice, 1_000_000_000).expect("alice funds the backstop");
+    deposit(&mut env, &bob, 2_000_000_000).expect("bob funds the backstop");
+
+    env.try_resolve_market(&admin, 0).expect("resolve");
+    env.set_slot(100);
+    env.crank();
+    let err = env.try_close_slab().unwrap_err();
+    assert!(
+        err.contains("Custom(60)"),
+        "vault account still open: {err}"
+    );
+
+    // Alice's withdrawal closes the vault account; Bob's part of its
+    // payout is now only in the SPL vault.
+    let alice_dest = env.create_ata(&alice.pubkey(), 0);
+    withdraw(&mut env, &alice, alice_dest).expect("alice redeems");
+    assert!(balance(&env, &alice_dest) > 0);
+    let (closed_units, total_shares) = backstop(&env);
+    assert!(closed_units > 0 && total_shares > 0);
+    let vault_before = env.vault_balance();
+    let err = env.try_close_slab().unwrap_err();
+    assert!(err.contains("Custom(60)"), "bob has not redeemed: {err}");
+    assert_eq!(env.vault_balance(), vault_before, "nothing drained");
+
+    let bob_dest = env.create_ata(&bob.pubkey(), 0);
+    withdraw(&mut env, &bob, bob_dest).expect("bob redeems");
+    assert!(balance(&env, &bob_dest) > balance(&env, &alice_dest));
+    assert_eq!(backstop(&env), (0, 0));
+
+    if env.read_insurance_balance() > 0 {
+        env.try_withdraw_insurance(&admin)
+            .expect("withdraw insurance");
+    }
+    env.try_close_slab()
+        .expect("CloseSlab once every depositor is paid");
+}
+
+#[test]
+fn test_stale_fallback_holds_price_and_haircuts_withdrawals() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_cap(0, 1_000);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+    env.trade(&user, &lp, lp_idx, user_idx, 20_000_000);
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let set = |env: &mut TestEnv, grace: u64, ramp: u64, max_bps: u16| {
+        let mut data = vec![51u8];
+        data.extend_from_slice(&grace.to_le_bytes());
+        data.extend_from_slice(&ramp.to_le_bytes());
+        data.extend_from_slice(&max_bps.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(admin.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&admin.pubkey()),
+            &[&admin],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    };
+    let err = set(&mut env, 1_000, 400, 2_000).unwrap_err();
+    assert!(
+        err.contains("Custom(26)"),
+        "grace must end before permissionless resolve: {err}"
+    );
+    set(&mut env, 100, 400, 2_000).expect("admin sets stale fallback");
+
+    // Last fresh publish, then the feed goes dark. Keepers keep cranking
+    // inside the accrual envelope; past max_staleness the crank holds the
+    // last price instead of failing.
+    env.set_slot_and_price(200, 138_000_000);
+    let s0 = env.svm.get_sysvar::<Clock>().slot;
+    for t in (s0 + 10..=s0 + 700).step_by(10) {
+        env.svm.set_sysvar(&Clock {
+            slot: t,
+            unix_timestamp: t as i64,
+            ..Clock::default()
+        });
+        env.try_crank_once().expect("crank survives a dead feed");
+    }
+    assert_eq!(env.read_last_market_slot(), s0 + 700);
+
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, 1_000_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(47)"), "no new risk: {err}");
+    // Nor a close: the held price is not a market the LP can be hit at.
+    let err = env
+        .try_trade(&user, &lp, lp_idx, user_idx, -10_000_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(47)"), "no voluntary close: {err}");
+    assert_eq!(env.read_account_position(user_idx), 20_000_000);
+
+    // 20M q at $138 with the haircut fully ramped (20%) keeps 552M.
+    let capital = env.read_account_capital(user_idx) as u64;
+    let err = env
+        .try_withdraw(&user, user_idx, capital - 400_000_000)
+        .unwrap_err();
+    assert!(err.contains("Custom(47)"), "haircut withheld: {err}");
+    env.try_withdraw(&user, user_idx, capital - 600_000_000)
+        .expect("withdrawal above the haircut goes through");
+}
+
+#[test]
+fn test_client_tag_is_echoed_in_program_data_logs() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+
+    let send = |env: &mut TestEnv, tag: Option<&[u8; 16]>| {
+        let ata = env.create_ata(&user.pubkey(), 1_000_000);
+        let mut data = encode_deposit(user_idx, 1_000_000);
+        if let Some(tag) = tag {
+            data.push(0); // empty allowlist proof
+            data.extend_from_slice(tag);
+        }
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new(ata, false),
+                AccountMeta::new(env.vault, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&user.pubkey()),
+            &[&user],
+            env.svm.latest_blockhash(),
+        );
+        env.svm.send_transaction(tx).expect("deposit").logs
+    };
+
+    let logs = send(&mut env, None);
+    assert!(
+        !logs.iter().any(|l| l.starts_with("Program data: ")),
+        "untagged deposits log nothing extra: {logs:?}"
+    );
+    // base64("cta...") = "Y3Rh..."
+    let logs = send(&mut env, Some(b"acme-frontend-01"));
+    assert!(
+        logs.iter().any(|l| l.starts_with("Program data: Y3Rh")),
+        "tag echoed as a ClientTagEvent: {logs:?}"
+    );
+}
+
+#[test]
+fn test_keeper_crank_logs_a_crank_summary() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+
+    env.set_slot(200);
+    let caller = Keypair::new();
+    env.svm.airdrop(&caller.pubkey(), 1_000_000_000).unwrap();
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(caller.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_crank_permissionless(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&caller.pubkey()),
+        &[&caller],
+        env.svm.latest_blockhash(),
+    );
+    let logs = env.svm.send_transaction(tx).expect("crank").logs;
+    // base64("crn...") = "Y3Ju..."
+    let summaries = logs
+        .iter()
+        .filter(|l| l.starts_with("Program data: Y3Ju"))
+        .count();
+    assert_eq!(summaries, 1, "one CrankSummaryEvent per crank: {logs:?}");
+}
+
+#[test]
+fn test_market_metadata_set_at_init_and_by_admin() {
+    use percolator_prog::state::MarketMetadata;
+    program_path();
+    let mut env = TestEnv::new();
+
+    let label = |s: &[u8], n: usize| {
+        let mut out = vec![0u8; n];
+        out[..s.len()].copy_from_slice(s);
+        out
+    };
+    let meta = MarketMetadata {
+        symbol: label(b"SOL-PERP", 16).try_into().unwrap(),
+        oracle_description: label(b"Pyth SOL/USD", 32).try_into().unwrap(),
+        uri_hash: [7u8; 32],
+        base_decimals: 9,
+    };
+    let mut init = encode_init_market_with_cap(
+        &env.payer.pubkey(),
+        &env.mint,
+        &TEST_FEED_ID,
+        0,
+        80,
+    );
+    init.extend_from_slice(&meta.to_wire());
+    env.try_init_market_raw(init).expect("init with metadata");
+
+    let read = |env: &TestEnv| {
+        let data = env.svm.get_account(&env.slab).unwrap().data;
+        let off = tail_off(percolator_prog::constants::MARKET_METADATA_OFF);
+        MarketMetadata::from_wire(&data[off..off + 81])
+    };
+    let stored = read(&env);
+    assert_eq!(stored, meta);
+    assert_eq!(stored.symbol_str(), "SOL-PERP");
+    assert_eq!(stored.oracle_description_str(), "Pyth SOL/USD");
+
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let set = |env: &mut TestEnv, signer: &Keypair, m: &MarketMetadata| {
+        let mut data = vec![52u8];
+        data.extend_from_slice(&m.to_wire());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{e:?}"))
+    };
+
+    let renamed = MarketMetadata {
+        symbol: label(b"SOL-USD-PERP", 16).try_into().unwrap(),
+        ..meta
+    };
+    set(&mut env, &admin, &renamed).expect("admin relabels");
+    assert_eq!(read(&env).symbol_str(), "SOL-USD-PERP");
+
+    let stranger = Keypair::new();
+    env.svm.airdrop(&stranger.pubkey(), 1_000_000_000).unwrap();
+    assert!(set(&mut env, &stranger, &meta).is_err());
+
+    let mut bad = meta;
+    bad.symbol[9] = b'X'; // text after the padding starts
+    let err = set(&mut env, &admin, &bad).unwrap_err();
+    assert!(err.contains("Custom(26)"), "malformed label: {err}");
+    bad = meta;
+    bad.base_decimals = 19;
+    let err = set(&mut env, &admin, &bad).unwrap_err();
+    assert!(err.contains("Custom(26)"), "decimals capped: {err}");
+    assert_eq!(read(&env), renamed);
+}
+
+#[test]
+fn test_register_market_creates_a_seed_derived_pointer() {
+    use percolator_prog::{accounts::derive_market, state::MarketPointer};
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+
+    let mint = env.mint.to_bytes();
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let register = |env: &mut TestEnv, signer: &Keypair, pointer: Pubkey, index: u16| {
+        let mut data = vec![53u8];
+        data.extend_from_slice(&index.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+                AccountMeta::new(pointer, false),
+                AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{e:?}"))
+    };
+
+    let (pointer, bump) = derive_market(&env.program_id, &mint, &TEST_FEED_ID, 0);
+    let (other, _) = derive_market(&env.program_id, &mint, &TEST_FEED_ID, 1);
+    assert!(
+        register(&mut env, &admin, other, 0).is_err(),
+        "pointer must match the seeds"
+    );
+    let stranger = Keypair::new();
+    env.svm.airdrop(&stranger.pubkey(), 1_000_000_000).unwrap();
+    assert!(register(&mut env, &stranger, pointer, 0).is_err());
+
+    // Lamports parked at the address beforehand do not block creation.
+    env.svm.airdrop(&pointer, 1).unwrap();
+    register(&mut env, &admin, pointer, 0).expect("admin registers");
+    let acct = env.svm.get_account(&pointer).unwrap();
+    assert_eq!(acct.owner, env.program_id);
+    assert_eq!(
+        MarketPointer::decode(&acct.data),
+        Some(MarketPointer {
+            slab: env.slab.to_bytes(),
+            bump,
+        })
+    );
+    assert!(
+        register(&mut env, &admin, pointer, 0).is_err(),
+        "a pointer is registered once"
+    );
+
+    // A sponsor pays the next pointer's rent; the admin signs read-only.
+    let sponsor = Keypair::new();
+    env.svm.airdrop(&sponsor.pubkey(), 1_000_000_000).unwrap();
+    let (second, _) = derive_market(&env.program_id, &mint, &TEST_FEED_ID, 1);
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(second, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            AccountMeta::new(sponsor.pubkey(), true),
+        ],
+        data: vec![53, 1, 0],
+    };
+    let admin_lamports = env.svm.get_account(&admin.pubkey()).unwrap().lamports;
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&sponsor.pubkey()),
+        &[&sponsor, &admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .expect("sponsored registration");
+    assert_eq!(env.svm.get_account(&second).unwrap().owner, env.program_id);
+    assert_eq!(
+        env.svm.get_account(&admin.pubkey()).unwrap().lamports,
+        admin_lamports
+    );
+}
+
+/// Deposits and withdrawals authorize on the owner's signature alone: a
+/// relayer pays the fees and the owner signs read-only.
+#[test]
+fn test_relayer_pays_fees_for_a_read_only_owner() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    let relayer = Keypair::new();
+    env.svm.airdrop(&relayer.pubkey(), 1_000_000_000).unwrap();
+    let lamports = env.svm.get_account(&user.pubkey()).unwrap().lamports;
+    let ata = env.create_ata(&user.pubkey(), 1_000_000_000);
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+
+    let deposit = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(user.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ],
+        data: encode_deposit(user_idx, 1_000_000_000),
+    };
+    let withdraw = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(user.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(env.pyth_index, false),
+        ],
+        data: encode_withdraw(user_idx, 400_000_000),
+    };
+    for ix in [deposit, withdraw] {
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&relayer.pubkey()),
+            &[&relayer, &user],
+            env.svm.latest_blockhash(),
+        );
+        env.svm.send_transaction(tx).expect("relayed instruction");
+    }
+    assert_eq!(
+        env.svm.get_account(&user.pubkey()).unwrap().lamports,
+        lamports
+    );
+    let balance = TokenAccount::unpack(&env.svm.get_account(&ata).unwrap().data)
+        .unwrap()
+        .amount;
+    assert_eq!(balance, 400_000_000);
+}
+
+#[test]
+fn test_withdraw_to_any_token_account_of_the_mint() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 2_000_000_000);
+
+    // A treasury account the user does not own.
+    let treasury = Pubkey::new_unique();
+    let dest = env.create_ata(&treasury, 0);
+    env.try_withdraw_to(&user, user_idx, 500_000_000, dest)
+        .expect("owner-signed withdrawal to a third-party account");
+    let balance = TokenAccount::unpack(&env.svm.get_account(&dest).unwrap().data)
+        .unwrap()
+        .amount;
+    assert_eq!(balance, 500_000_000);
+
+    let vault = env.vault;
+    let before = env.vault_balance();
+    let err = env
+        .try_withdraw_to(&user, user_idx, 100_000_000, vault)
+        .unwrap_err();
+    assert!(
+        err.contains("Custom(24)"),
+        "vault is not a destination: {err}"
+    );
+    assert_eq!(env.vault_balance(), before);
+
+    // The signer still has to own the engine account.
+    let other = Keypair::new();
+    env.svm.airdrop(&other.pubkey(), 1_000_000_000).unwrap();
+    assert!(env
+        .try_withdraw_to(&other, user_idx, 100_000_000, dest)
+        .is_err());
+}
+
+#[test]
+fn test_crank_records_market_history() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+
+    let start = read_history(&env).len();
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot(slot + 400);
+    env.crank();
+    let history = read_history(&env);
+    assert!(history.len() > start, "crank appends a sample");
+    let newest = *history.last().unwrap();
+    assert_eq!(newest.oi_long_q, 1_000_000);
+    assert_eq!(newest.oi_short_q, 1_000_000);
+    assert_eq!(newest.price_e6, 138_000_000);
+    assert!(newest.mark_e6 > 0);
+    assert!(history.windows(2).all(
+        |w| w[1].slot >= w[0].slot + percolator_prog::constants::HISTORY_SAMPLE_INTERVAL_SLOTS
+    ));
+
+    // A second crank inside the sampling interval does not add one.
+    let len = history.len();
+    env.set_slot(newest.slot - 100 + 10);
+    env.crank();
+    assert_eq!(read_history(&env).len(), len);
+}
+
+#[test]
+fn test_lp_stats_track_fills_fees_and_utilization() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_fee_weighted(0, 10_000, 10, 100);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+    assert!(
+        read_lp_stats(&env, lp_idx).is_none(),
+        "tracked from first fill"
+    );
+
+    // Whole fee to the LP.
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let mut data = vec![37u8];
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+        ],
+        data,
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&admin.pubkey()),
+        &[&admin],
+        env.svm.latest_blockhash(),
+    );
+    env.svm.send_transaction(tx).expect("set fee split");
+
+    env.trade(&user, &lp, lp_idx, user_idx, -2_000_000);
+    let first = read_lp_stats(&env, lp_idx).expect("LP tracked");
+    assert_eq!(first.fills, 1);
+    assert!(first.volume > 0);
+    assert!(first.fees_earned > 0, "LP fee share recorded");
+    assert_eq!(first.spread_pnl, 0, "filled at the oracle price");
+    assert!(first.utilization_bps > 0);
+    assert!(
+        read_lp_stats(&env, user_idx).is_none(),
+        "users are not tracked"
+    );
+
+    env.trade(&user, &lp, lp_idx, user_idx, 2_000_000);
+    let second = read_lp_stats(&env, lp_idx).unwrap();
+    assert_eq!(second.fills, 2);
+    assert_eq!(second.volume, 2 * first.volume);
+    assert!(second.fees_earned > first.fees_earned);
+    assert_eq!(second.utilization_bps, 0, "LP is flat again");
+}
+
+/// PnL ledger entry for `idx`, read from the slab tail.
+fn read_pnl_ledger(env: &TestEnv, idx: u16) -> percolator_prog::state::PnlLedger {
+    use percolator_prog::constants::{PNL_LEDGER_ENTRY_LEN, PNL_LEDGER_OFF};
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let off = tail_off(PNL_LEDGER_OFF) + 32 + idx as usize * PNL_LEDGER_ENTRY_LEN;
+    percolator_prog::state::PnlLedger::from_bytes(&data[off..off + PNL_LEDGER_ENTRY_LEN])
+}
+
+/// The PnL ledger follows both sides of every fill: entry at the fill
+/// price, realized PnL on the closed part of a flip, and each side's
+/// half of the trading fee.
+#[test]
+fn test_pnl_ledger_tracks_entry_realized_and_fees() {
+    use percolator_prog::policy::settle_cost_basis;
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_fee_weighted(0, 10_000, 10, 100);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 10_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 1_000_000_000);
+    assert_eq!(read_pnl_ledger(&env, user_idx).basis.position_q, 0);
+
+    env.trade(&user, &lp, lp_idx, user_idx, 2_000_000);
+    let (user_open, lp_open) = (
+        read_pnl_ledger(&env, user_idx),
+        read_pnl_ledger(&env, lp_idx),
+    );
+    assert_eq!(
+        user_open.basis.position_q,
+        env.read_account_position(user_idx)
+    );
+    assert_eq!(lp_open.basis.position_q, env.read_account_position(lp_idx));
+    assert!(user_open.basis.entry_price_e6 > 0);
+    assert_eq!(lp_open.basis.entry_price_e6, user_open.basis.entry_price_e6);
+    assert_eq!((user_open.realized_pnl, lp_open.realized_pnl), (0, 0));
+    assert!(user_open.fees_paid > 0);
+    assert!(
+        user_open.fees_paid - lp_open.fees_paid <= 1,
+        "fee split per side"
+    );
+
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    env.set_slot_and_price(slot + 50, 140_000_000);
+    env.trade(&user, &lp, lp_idx, user_idx, -3_000_000);
+    let (user_flip, lp_flip) = (
+        read_pnl_ledger(&env, user_idx),
+        read_pnl_ledger(&env, lp_idx),
+    );
+    assert_eq!(
+        user_flip.basis.position_q,
+        env.read_account_position(user_idx)
+    );
+    assert!(user_flip.basis.position_q < 0, "flipped short");
+    let exec = user_flip.basis.entry_price_e6;
+    assert_ne!(
+        exec, user_open.basis.entry_price_e6,
+        "reopened at the new price"
+    );
+    let (_, user_realized) = settle_cost_basis(&user_open.basis, -3_000_000, exec).unwrap();
+    let (_, lp_realized) = settle_cost_basis(&lp_open.basis, 3_000_000, exec).unwrap();
+    assert_eq!(user_flip.realized_pnl as i128, user_realized);
+    assert_eq!(lp_flip.realized_pnl as i128, lp_realized);
+    assert!(user_flip.fees_paid > user_open.fees_paid);
+    assert!(lp_flip.fees_paid > lp_open.fees_paid);
+}
+
+/// A crank liquidation closes the position in the ledger too, booking the
+/// loss at the crank price without charging a trading fee.
+#[test]
+fn test_pnl_ledger_books_crank_liquidation() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_cap(0, 80);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 5_000_000_000);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    env.top_up_insurance(&admin, 1_000_000_000);
+    env.set_slot(50);
+    env.crank();
+
+    env.trade(&user, &lp, lp_idx, user_idx, 100_000_000);
+    let user_open = read_pnl_ledger(&env, user_idx);
+    assert_eq!(user_open.basis.position_q, 100_000_000);
+
+    env.set_slot_and_price(2000, 90_000_000);
+    env.try_liquidate(user_idx).expect("liquidation succeeds");
+    assert_eq!(env.read_account_position(user_idx), 0);
+    let user_closed = read_pnl_ledger(&env, user_idx);
+    assert_eq!(user_closed.basis.position_q, 0);
+    assert!(user_closed.realized_pnl < 0, "the long closed at a loss");
+    assert_eq!(user_closed.fees_paid, user_open.fees_paid);
+}
+
+/// Whether the open-position bitmap in the slab tail has `idx` set.
+fn has_position_bit(env: &TestEnv, idx: u16) -> bool {
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let off = tail_off(percolator_prog::constants::POSITION_BITMAP_OFF) + idx as usize / 8;
+    data[off] & (1 << (idx % 8)) != 0
+}
+
+#[test]
+fn test_position_bitmap_tracks_open_accounts() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+    let idle = Keypair::new();
+    let idle_idx = env.init_user(&idle);
+    env.deposit(&idle, idle_idx, 10_000_000_000);
+
+    assert!(!has_position_bit(&env, user_idx) && !has_position_bit(&env, lp_idx));
+
+    env.trade(&user, &lp, lp_idx, user_idx, 1_000_000);
+    assert!(has_position_bit(&env, user_idx), "user opened");
+    assert!(has_position_bit(&env, lp_idx), "LP took the other side");
+    assert!(!has_position_bit(&env, idle_idx), "no fill, no bit");
+
+    env.crank();
+    assert!(
+        has_position_bit(&env, user_idx),
+        "crank keeps open accounts"
+    );
+
+    env.trade(&user, &lp, lp_idx, user_idx, -1_000_000);
+    assert!(
+        !has_position_bit(&env, user_idx),
+        "flattening fill clears it"
+    );
+    assert!(!has_position_bit(&env, lp_idx));
+}
+
+/// Liquidation watch index header and membership, read from the slab tail.
+fn read_liq_watch(env: &TestEnv) -> (percolator_prog::state::LiqWatch, Vec<u8>) {
+    use percolator_prog::constants::{LIQ_WATCH_LEN, LIQ_WATCH_OFF};
+    let data = env.svm.get_account(&env.slab).unwrap().data;
+    let off = tail_off(LIQ_WATCH_OFF);
+    let u64_at = |o: usize| u64::from_le_bytes(data[off + o..off + o + 8].try_into().unwrap());
+    let header = percolator_prog::state::LiqWatch {
+        anchor_price_e6: u64_at(0),
+        anchor_slot: u64_at(8),
+        cursor: u64_at(16),
+    };
+    (header, data[off + 24..off + LIQ_WATCH_LEN].to_vec())
+}
+
+fn is_watched(bits: &[u8], idx: u16) -> bool {
+    bits[idx as usize / 8] & (1 << (idx % 8)) != 0
+}
+
+#[test]
+fn test_liq_watch_index_keeps_leveraged_accounts_and_reanchors() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 1_000_000_000_000);
+
+    let price = 138_000_000u128;
+    let risky = Keypair::new();
+    let risky_idx = env.init_user(&risky);
+    env.deposit(&risky, risky_idx, 1_000_000_000);
+    let safe = Keypair::new();
+    let safe_idx = env.init_user(&safe);
+    env.deposit(&safe, safe_idx, 10_000_000_000);
+
+    // ~8x: about 7% from maintenance, inside the 10% band.
+    let risky_size = (8 * 1_000_000_000 * percolator::POS_SCALE / price) as i128;
+    env.trade(&risky, &lp, lp_idx, risky_idx, risky_size);
+    // ~0.1x: nowhere near maintenance.
+    env.trade(&safe, &lp, lp_idx, safe_idx, 1_000_000);
+    env.crank();
+
+    let (w, bits) = read_liq_watch(&env);
+    assert_eq!(w.anchor_price_e6, price as u64);
+    assert!(is_watched(&bits, risky_idx), "leveraged account is watched");
+    assert!(!is_watched(&bits, safe_idx), "far account is dropped");
+
+    // Push the anchor far from the market: the next crank re-anchors,
+    // re-watches every open account, then drops the far ones again.
+    let mut slab = env.svm.get_account(&env.slab).unwrap();
+    let off = tail_off(percolator_prog::constants::LIQ_WATCH_OFF);
+    slab.data[off..off + 8].copy_from_slice(&1u64.to_le_bytes());
+    env.svm.set_account(env.slab, slab).unwrap();
+    env.set_slot(30);
+    env.crank();
+
+    let (w, bits) = read_liq_watch(&env);
+    assert_eq!(w.anchor_price_e6, price as u64);
+    assert_eq!(w.anchor_slot, 30);
+    assert!(is_watched(&bits, risky_idx));
+    assert!(!is_watched(&bits, safe_idx));
+}
+
+/// An account the crank's round-robin has not reached carries its loss in
+/// the side's K index, not in its stored PnL. Re-anchoring must still keep
+/// it watched, and QueryHealth must report the loss.
+#[test]
+fn test_liq_watch_counts_unsettled_k_pnl_across_a_reanchor() {
+    const ACCOUNT_SIZE: usize = 416;
+    const BASIS: usize = 56;
+    const A_BASIS: usize = 72;
+    const K_SNAP: usize = 88;
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    let lp = Keypair::new();
+    let lp_idx = env.init_lp(&lp);
+    env.deposit(&lp, lp_idx, 1_000_000_000_000);
+
+    let price = 138_000_000u128;
+    let short = Keypair::new();
+    let short_idx = env.init_user(&short);
+    env.deposit(&short, short_idx, 1_000_000_000);
+    // ~3x short: about 27% from maintenance, well outside the band.
+    let size = (3 * 1_000_000_000 * percolator::POS_SCALE / price) as i128;
+    env.trade(&short, &lp, lp_idx, short_idx, -size);
+    // More used accounts than two round-robin windows, so a crank right
+    // after the one that touched `short` cannot reach it again.
+    for _ in 0..70 {
+        let filler = Keypair::new();
+        let idx = env.init_user(&filler);
+        env.deposit(&filler, idx, 100_000_000);
+    }
+    env.crank();
+    let (_, bits) = read_liq_watch(&env);
+    assert!(!is_watched(&bits, short_idx), "far short starts cleared");
+
+    let acc_off = sbf_offsets::ACCOUNTS + short_idx as usize * ACCOUNT_SIZE;
+    let read_i128 = |env: &TestEnv, off: usize| {
+        let d = env.svm.get_account(&env.slab).unwrap().data;
+        i128::from_le_bytes(d[off..off + 16].try_into().unwrap())
+    };
+    let write_i128 = |env: &mut TestEnv, off: usize, v: i128| {
+        let mut slab = env.svm.get_account(&env.slab).unwrap();
+        slab.data[off..off + 16].copy_from_slice(&v.to_le_bytes());
+        env.svm.set_account(env.slab, slab).unwrap();
+    };
+    let mut slot = env.svm.get_sysvar::<Clock>().slot;
+    let mut crank_next_slot = |env: &mut TestEnv| {
+        slot += 1;
+        env.set_slot_and_price_raw_no_walk(slot, price as i64);
+        env.crank();
+    };
+
+    // Probe with a one-unit K offset until a crank's touch resets the
+    // snapshot; the next crank's window is then past `short`.
+    let mut touched = false;
+    for _ in 0..8 {
+        let probe = read_i128(&env, acc_off + K_SNAP) + 1;
+        write_i128(&mut env, acc_off + K_SNAP, probe);
+        crank_next_slot(&mut env);
+        if read_i128(&env, acc_off + K_SNAP) != probe {
+            touched = true;
+            break;
+        }
+    }
+    assert!(touched, "round-robin reaches every account");
+
+    // A price move the engine has booked into K but not into `short`:
+    // a loss of 6e8 leaves it ~8% from maintenance.
+    let loss = 600_000_000u128;
+    let basis = read_i128(&env, acc_off + BASIS).unsigned_abs();
+    let a_basis = read_i128(&env, acc_off + A_BASIS) as u128;
+    let dk = (loss * a_basis * percolator::POS_SCALE).div_ceil(basis) as i128;
+    let snap = read_i128(&env, acc_off + K_SNAP) + dk;
+    write_i128(&mut env, acc_off + K_SNAP, snap);
+    let pnl_before = env.read_account_pnl(short_idx);
+
+    let query = |env: &mut TestEnv| {
+        let mut data = vec![34u8];
+        data.extend_from_slice(&short_idx.to_le_bytes());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![AccountMeta::new_readonly(env.slab, false)],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&short.pubkey()),
+            &[&short],
+            env.svm.latest_blockhash(),
+        );
+        let meta = env.svm.send_transaction(tx).expect("query health");
+        percolator_prog::ix::HealthSnapshot::decode(&meta.return_data.data).unwrap()
+    };
+    let capital = env.read_account_capital(short_idx) as i128;
+    let h = query(&mut env);
+    assert!(
+        h.equity <= capital + pnl_before - loss as i128,
+        "pending K loss is in equity: {} vs {}",
+        h.equity,
+        capital + pnl_before
+    );
+
+    // Force a re-anchor: every open account is watched again and the scan
+    // from index 0 re-checks `short` without settling it.
+    let mut slab = env.svm.get_account(&env.slab).unwrap();
+    let off = tail_off(percolator_prog::constants::LIQ_WATCH_OFF);
+    slab.data[off..off + 8].copy_from_slice(&1u64.to_le_bytes());
+    env.svm.set_account(env.slab, slab).unwrap();
+    crank_next_slot(&mut env);
+
+    assert_eq!(
+        read_i128(&env, acc_off + K_SNAP),
+        snap,
+        "the crank did not touch the short"
+    );
+    assert_eq!(env.read_account_pnl(short_idx), pnl_before);
+    let (w, bits) = read_liq_watch(&env);
+    assert_eq!(w.anchor_price_e6, price as u64);
+    assert!(
+        is_watched(&bits, short_idx),
+        "unsettled loss keeps the short in the watch set"
+    );
+}
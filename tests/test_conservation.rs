@@ -2040,7 +2040,8 @@ fn test_attack_opposing_users_pnl_conservation() {
     );
 }
 
-/// PROPERTY TEST: State machine fuzzer verifies 6 invariants across random operation sequences.
+/// PROPERTY TEST: State machine fuzzer verifies 7 invariants across random operation sequences,
+/// including the P7 flow ledger (vault == setup balance + inflows - outflows).
 ///
 /// Subsumes the following classes of individual tests:
 ///   - All conservation tests (~30)
@@ -2558,3 +2559,104 @@ fn test_adl_conservation_after_liquidation() {
         );
     }
 }
+
+/// Maintenance margin and liquidation fee used by `init_market_with_cap`.
+const PROP_MM_BPS: u128 = 500;
+const PROP_LIQ_FEE_BPS: u128 = 50;
+const PROP_LIQ_FEE_CAP: u128 = 1_000_000_000_000;
+
+proptest::proptest! {
+    #![proptest_config(proptest::test_runner::Config::with_cases(24))]
+
+    /// PROPERTY TEST: KeeperCrank liquidation across randomized capital,
+    /// position size/direction and post-trade oracle targets.
+    ///
+    /// After the FullClose candidate is processed:
+    ///   - a liquidated account is flat; an account left open was not
+    ///     liquidated and still meets maintenance margin at the effective price
+    ///   - insurance grows by at most the liquidation fee on the closed
+    ///     notional (bps at the worst price seen, capped by liquidation_fee_cap)
+    ///   - no tokens move: engine vault == SPL vault, unchanged by the crank,
+    ///     and c_tot + insurance <= vault
+    #[test]
+    fn test_property_liquidation_postconditions(
+        capital in 800_000_000u64..5_000_000_000,
+        size in 10_000_000i128..100_000_000,
+        long in proptest::bool::ANY,
+        target_price in 40_000_000i64..240_000_000,
+    ) {
+        program_path();
+        let mut env = TestEnv::new();
+        env.init_market_with_cap(0, 80);
+
+        let lp = Keypair::new();
+        let lp_idx = env.init_lp(&lp);
+        env.deposit(&lp, lp_idx, 100_000_000_000);
+
+        let user = Keypair::new();
+        let user_idx = env.init_user(&user);
+        env.deposit(&user, user_idx, capital);
+
+        let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+        env.try_top_up_insurance(&admin, 1_000_000_000).unwrap();
+
+        env.set_slot(50);
+        env.crank();
+        let entry_price = env.read_last_effective_price() as u128;
+
+        let size = if long { size } else { -size };
+        if env.try_trade(&user, &lp, lp_idx, user_idx, size).is_err() {
+            // Initial margin rejected this combination; nothing to liquidate.
+            return Ok(());
+        }
+        let pos_before = env.read_account_position(user_idx);
+        proptest::prop_assert_ne!(pos_before, 0);
+
+        env.set_slot_and_price(2000, target_price);
+
+        let vault_before = env.read_engine_vault();
+        let insurance_before = env.read_insurance_balance();
+        let result = env.try_liquidate(user_idx);
+        proptest::prop_assert!(
+            result.is_ok(),
+            "crank with candidate failed: {:?}",
+            result
+        );
+
+        let pos_after = env.read_account_position(user_idx);
+        if pos_after != 0 {
+            proptest::prop_assert_eq!(pos_after, pos_before, "partial close under FullClose");
+            let price = env.read_last_effective_price() as u128;
+            let notional = pos_after.unsigned_abs() * price / 1_000_000;
+            let equity = env.read_account_capital(user_idx) as i128
+                + env.read_account_pnl(user_idx);
+            proptest::prop_assert!(
+                equity.max(0) as u128 * 10_000 >= notional * PROP_MM_BPS,
+                "open account below maintenance: equity={} notional={}",
+                equity,
+                notional
+            );
+        }
+
+        // The effective price walks from the entry price toward the target,
+        // so the notional closed is never valued above the larger of the two.
+        let worst_price = (target_price as u128).max(entry_price);
+        let max_notional = pos_before.unsigned_abs() * worst_price / 1_000_000;
+        let max_fee = (max_notional * PROP_LIQ_FEE_BPS)
+            .div_ceil(10_000)
+            .min(PROP_LIQ_FEE_CAP);
+        let insurance_after = env.read_insurance_balance();
+        proptest::prop_assert!(
+            insurance_after <= insurance_before + max_fee,
+            "insurance grew past the liquidation fee bound: before={} after={} max_fee={}",
+            insurance_before,
+            insurance_after,
+            max_fee
+        );
+
+        let vault_after = env.read_engine_vault();
+        proptest::prop_assert_eq!(vault_after, vault_before, "liquidation moved tokens");
+        proptest::prop_assert_eq!(vault_after as u64, env.vault_balance());
+        proptest::prop_assert!(env.read_c_tot() + insurance_after <= vault_after);
+    }
+}
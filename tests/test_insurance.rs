@@ -1863,6 +1863,67 @@ fn test_withdraw_limited_deposit_only_tracks_topups_and_withdrawals() {
     assert_eq!(read_withdraw_deposit_remaining_raw(&env), 0);
 }
 
+fn donate_insurance(env: &mut TestEnv, payer: &Keypair, amount: u64) -> Result<(), String> {
+    let ata = env.create_ata(&payer.pubkey(), amount);
+    let mut data = vec![45u8];
+    data.extend_from_slice(&amount.to_le_bytes());
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+        ],
+        data,
+    };
+    env.svm.expire_blockhash();
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&payer.pubkey()),
+        &[payer],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// 10bb. DonateInsurance credits insurance and the vault like a top-up but
+///       never grows the deposit-only withdrawal budget.
+#[test]
+fn test_donate_insurance_is_not_withdrawable_principal() {
+    program_path();
+    let mut env = TestEnv::new();
+    setup_bounded_withdrawal(&mut env, 1_000, 10_000, 1);
+    set_withdraw_deposits_only_raw(&mut env, 1);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    let vault_before = env.vault_balance();
+
+    let donor = Keypair::new();
+    env.svm.airdrop(&donor.pubkey(), 10_000_000_000).unwrap();
+    donate_insurance(&mut env, &donor, 5_000).expect("anyone may donate");
+    assert_eq!(env.read_insurance_balance(), 6_000);
+    assert_eq!(env.vault_balance(), vault_before + 5_000);
+    assert_eq!(
+        read_withdraw_deposit_remaining_raw(&env),
+        1_000,
+        "donations must not become withdrawable principal"
+    );
+    assert!(donate_insurance(&mut env, &donor, 0).is_err());
+
+    send_withdraw_limited(&mut env, &admin, 1_000).expect("top-up principal stays withdrawable");
+    env.set_slot(10);
+    assert!(
+        send_withdraw_limited(&mut env, &admin, 1).is_err(),
+        "donated insurance is out of reach of deposit-only withdrawals"
+    );
+    assert_eq!(env.read_insurance_balance(), 5_000);
+}
+
 /// 10ba. Failed or out-of-order withdrawals must not consume the deposited-
 ///       principal budget. Later TopUpInsurance calls should still add to the
 ///       exact remaining principal.
@@ -2228,3 +2289,109 @@ fn test_deposit_cap_bypassed_via_init_user() {
         "InitUser with fee_payment that breaches the cap must be rejected"
     );
 }
+
+// ============================================================================
+// WithdrawFees (tag 33) — new-account fee accumulator
+// ============================================================================
+
+fn encode_withdraw_fees(amount: u64) -> Vec<u8> {
+    let mut data = vec![33u8]; // Tag 33
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
+
+fn send_withdraw_fees(env: &mut TestEnv, signer: &Keypair, amount: u64) -> Result<(), String> {
+    let signer_ata = env.create_ata(&signer.pubkey(), 0);
+    let (vault_pda, _) =
+        Pubkey::find_program_address(&[b"vault", env.slab.as_ref()], &env.program_id);
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(signer.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(signer_ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(vault_pda, false),
+            AccountMeta::new_readonly(solana_sdk::sysvar::clock::ID, false),
+        ],
+        data: encode_withdraw_fees(amount),
+    };
+    let _ = env.svm.airdrop(&signer.pubkey(), 1_000_000_000);
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&signer.pubkey()),
+        &[signer],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+}
+
+fn read_account_fees_raw(env: &TestEnv) -> u64 {
+    let slab = env.svm.get_account(&env.slab).unwrap();
+    percolator_prog::state::read_account_fees(&slab.data)
+}
+
+/// New-account fees are earmarked in the header accumulator and only that
+/// earmark can leave via WithdrawFees: insurance from top-ups stays put,
+/// c_tot is never touched, and engine.vault keeps matching the SPL vault.
+#[test]
+fn test_withdraw_fees_capped_by_collected_new_account_fees() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_full(0, 0, 1_000);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+
+    let insurance_payer = Keypair::new();
+    env.svm
+        .airdrop(&insurance_payer.pubkey(), 10_000_000_000)
+        .unwrap();
+    env.top_up_insurance(&insurance_payer, 50_000);
+
+    let u1 = Keypair::new();
+    env.init_user_with_fee(&u1, 10_000);
+    let u2 = Keypair::new();
+    env.init_user_with_fee(&u2, 10_000);
+    assert_eq!(read_account_fees_raw(&env), 2_000);
+
+    let c_tot_before = env.read_c_tot();
+    let ins_before = env.read_insurance_balance();
+    let vault_before = env.vault_balance();
+
+    let err = send_withdraw_fees(&mut env, &admin, 2_001)
+        .expect_err("withdrawal above collected fees must be rejected");
+    assert!(
+        err.contains("Custom(32)"),
+        "expected InsuranceWithdrawCapExceeded, got {err}"
+    );
+
+    send_withdraw_fees(&mut env, &admin, 2_000).expect("admin withdraws collected fees");
+
+    assert_eq!(read_account_fees_raw(&env), 0);
+    assert_eq!(env.read_insurance_balance(), ins_before - 2_000);
+    assert_eq!(env.read_c_tot(), c_tot_before, "fees never touch c_tot");
+    assert_eq!(env.vault_balance(), vault_before - 2_000);
+    assert_eq!(env.read_engine_vault(), env.vault_balance() as u128);
+
+    assert!(
+        send_withdraw_fees(&mut env, &admin, 1).is_err(),
+        "top-up insurance is not withdrawable as fees"
+    );
+}
+
+#[test]
+fn test_withdraw_fees_rejects_non_admin() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_full(0, 0, 1_000);
+
+    let user = Keypair::new();
+    env.init_user_with_fee(&user, 10_000);
+
+    let attacker = Keypair::new();
+    assert!(send_withdraw_fees(&mut env, &attacker, 1_000).is_err());
+    assert_eq!(read_account_fees_raw(&env), 1_000);
+}
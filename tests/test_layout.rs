@@ -0,0 +1,283 @@
+//! Slab layout stability.
+//!
+//! Live markets are plain byte arrays: any change to field order, width or
+//! padding in `SlabHeader`, `MarketConfig` or `RiskBuffer` reinterprets
+//! existing slabs. These tests pin sizes and offsets at compile time and
+//! compare a fully populated image, with a distinct value in every field,
+//! against the committed bytes in `tests/golden/`.
+//!
+//! The header, config and risk buffer contain no implicit padding, so the
+//! same golden bytes hold for sbf and x86_64. The engine region differs
+//! between targets (`u128` alignment) and is pinned by the BPF suite's
+//! hardcoded offsets in `tests/common/mod.rs`.
+//!
+//! An intentional layout change must bump the slab version and regenerate:
+//!   UPDATE_GOLDEN=1 cargo test --test test_layout
+
+use core::mem::{offset_of, size_of};
+use percolator_prog::{
+    constants::{
+        CONFIG_LEN, ENGINE_ALIGN, ENGINE_OFF, HEADER_LEN, RISK_BUF_CAP, RISK_BUF_LEN, RISK_BUF_OFF,
+    },
+    risk_buffer::{RiskBuffer, RiskEntry},
+    state::{self, MarketConfig, SlabHeader},
+};
+use std::path::PathBuf;
+
+const _: () = assert!(HEADER_LEN == 136);
+const _: () = assert!(CONFIG_LEN == 528);
+const _: () = assert!(RISK_BUF_LEN == 160);
+const _: () = assert!(size_of::<RiskEntry>() == 32);
+const _: () = assert!(RISK_BUF_CAP == 4);
+const _: () = assert!(offset_of!(SlabHeader, _reserved) == 48);
+const _: () = assert!(offset_of!(SlabHeader, insurance_operator) == 104);
+const _: () = assert!(offset_of!(MarketConfig, index_feed_id) == 64);
+const _: () = assert!(offset_of!(MarketConfig, max_staleness_secs) == 176);
+const _: () = assert!(offset_of!(MarketConfig, hyperp_authority) == 224);
+const _: () = assert!(offset_of!(MarketConfig, last_effective_price_e6) == 272);
+const _: () = assert!(offset_of!(MarketConfig, last_mark_push_slot) == 368);
+const _: () = assert!(offset_of!(MarketConfig, mark_ewma_e6) == 400);
+const _: () = assert!(offset_of!(MarketConfig, maintenance_fee_per_slot) == 448);
+const _: () = assert!(offset_of!(MarketConfig, new_account_fee) == 512);
+const _: () = assert!(offset_of!(RiskBuffer, min_notional) == 16);
+const _: () = assert!(offset_of!(RiskBuffer, entries) == 32);
+
+fn seq<const N: usize>(start: u8) -> [u8; N] {
+    core::array::from_fn(|i| start.wrapping_add(i as u8))
+}
+
+fn populated_header() -> SlabHeader {
+    SlabHeader {
+        magic: 0xe0d0c0b0a090807,
+        version: 0x11100f0e,
+        bump: 0x15,
+        _padding: seq(28),
+        admin: seq(35),
+        _reserved: seq(42),
+        insurance_authority: seq(49),
+        insurance_operator: seq(56),
+    }
+}
+
+fn populated_config() -> MarketConfig {
+    MarketConfig {
+        collateral_mint: seq(77),
+        vault_pubkey: seq(84),
+        index_feed_id: seq(91),
+        oracle_leg2_feed_id: seq(98),
+        oracle_leg3_feed_id: seq(105),
+        oracle_leg_count: 0x70,
+        oracle_leg_flags: 0x77,
+        _oracle_leg_padding: seq(126),
+        max_staleness_secs: 0x8c8b8a8988878685,
+        conf_filter_bps: 0x8d8c,
+        vault_authority_bump: 0x93,
+        invert: 0x9a,
+        unit_scale: 0xa4a3a2a1,
+        funding_horizon_slots: 0xafaeadacabaaa9a8,
+        funding_k_bps: 0xb6b5b4b3b2b1b0af,
+        funding_max_premium_bps: -0x424344454647484a,
+        funding_max_e9_per_slot: -0x3b3c3d3e3f404143,
+        hyperp_authority: seq(196),
+        hyperp_mark_e6: 0xd2d1d0cfcecdcccb,
+        last_oracle_publish_time: -0x262728292a2b2c2e,
+        last_effective_price_e6: 0xe0dfdedddcdbdad9,
+        insurance_withdraw_max_bps: 0xe1e0,
+        tvl_insurance_cap_mult: 0xe8e7,
+        insurance_withdraw_deposits_only: 0xee,
+        _iw_padding: seq(245),
+        insurance_withdraw_cooldown_slots: 0x3020100fffefdfc,
+        oracle_target_price_e6: 0xa09080706050403,
+        oracle_target_publish_time: 0x11100f0e0d0c0b0a,
+        oracle_leg_prices_e6: [0x1817161514131211, 0x201f1e1d1c1b1a19, 0x2827262524232221],
+        oracle_leg_publish_times: [0x1f1e1d1c1b1a1918, 0x2726252423222120, 0x2f2e2d2c2b2a2928],
+        last_hyperp_index_slot: 0x262524232221201f,
+        last_mark_push_slot: 0x3534333231302f2e2d2c2b2a29282726,
+        last_insurance_withdraw_slot: 0x34333231302f2e2d,
+        insurance_withdraw_deposit_remaining: 0x3b3a393837363534,
+        mark_ewma_e6: 0x4241403f3e3d3c3b,
+        mark_ewma_last_slot: 0x4948474645444342,
+        mark_ewma_halflife_slots: 0x504f4e4d4c4b4a49,
+        init_restart_slot: 0x5756555453525150,
+        permissionless_resolve_stale_slots: 0x5e5d5c5b5a595857,
+        last_good_oracle_slot: 0x6564636261605f5e,
+        maintenance_fee_per_slot: 0x74737271706f6e6d6c6b6a6968676665,
+        fee_sweep_cursor_word: 0x737271706f6e6d6c,
+        fee_sweep_cursor_bit: 0x7a79787776757473,
+        mark_min_fee: 0x81807f7e7d7c7b7a,
+        trade_fee_base_bps: 0x8887868584838281,
+        trade_fee_mode: 0x8f8e8d8c8b8a8988,
+        force_close_delay_slots: 0x969594939291908f,
+        new_account_fee: 0xa5a4a3a2a1a09f9e9d9c9b9a99989796,
+    }
+}
+
+fn populated_risk_buffer() -> RiskBuffer {
+    let mut buf = RiskBuffer {
+        scan_cursor: 0x0102,
+        count: 3,
+        _pad: [0; 13],
+        min_notional: 0x11223344556677889900aabbccddeeff,
+        entries: [RiskEntry {
+            idx: 0,
+            _pad: [0; 14],
+            notional: 0,
+        }; RISK_BUF_CAP],
+    };
+    for (j, e) in buf.entries.iter_mut().enumerate() {
+        e.idx = 10 + j as u16;
+        e.notional = (j as u128 + 1) * 1_000_000_007;
+    }
+    buf
+}
+
+fn to_hex_lines(bytes: &[u8]) -> String {
+    bytes
+        .chunks(32)
+        .map(|c| c.iter().map(|b| format!("{b:02x}")).collect::<String>() + "\n")
+        .collect()
+}
+
+fn check_golden(name: &str, bytes: &[u8]) {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/golden");
+    path.push(name);
+    let actual = to_hex_lines(bytes);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected =
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    if actual != expected {
+        let first = actual
+            .lines()
+            .zip(expected.lines())
+            .position(|(a, b)| a != b)
+            .unwrap_or(actual.lines().count().min(expected.lines().count()));
+        panic!(
+            "{name}: layout drift at bytes {}..{} ({} lines vs {} golden)",
+            first * 32,
+            first * 32 + 32,
+            actual.lines().count(),
+            expected.lines().count()
+        );
+    }
+}
+
+#[test]
+fn test_slab_header_and_config_match_golden() {
+    let mut data = vec![0u8; HEADER_LEN + CONFIG_LEN];
+    state::write_header(&mut data, &populated_header());
+    state::write_config(&mut data, &populated_config());
+    check_golden("slab_header_config.hex", &data);
+
+    // Round trip through the readers the processor uses.
+    assert_eq!(
+        bytemuck::bytes_of(&state::read_header(&data)),
+        &data[..HEADER_LEN]
+    );
+    assert_eq!(
+        bytemuck::bytes_of(&state::read_config(&data)),
+        &data[HEADER_LEN..]
+    );
+}
+
+#[test]
+fn test_risk_buffer_matches_golden() {
+    check_golden(
+        "risk_buffer.hex",
+        bytemuck::bytes_of(&populated_risk_buffer()),
+    );
+}
+
+#[test]
+fn test_region_offsets_are_contiguous() {
+    assert_eq!(ENGINE_OFF % ENGINE_ALIGN, 0);
+    assert!(ENGINE_OFF >= HEADER_LEN + CONFIG_LEN);
+    assert!(ENGINE_OFF - (HEADER_LEN + CONFIG_LEN) < ENGINE_ALIGN);
+    assert_eq!(RISK_BUF_OFF % 8, 0);
+}
+
+#[test]
+fn test_slab_versions_only_append() {
+    use percolator_prog::constants::{
+        ENGINE_LEN, GEN_TABLE_LEN, GEN_TABLE_OFF, SLAB_LEN, SLAB_LEN_BY_VERSION, SLAB_VERSION,
+        TAIL_LEN, TAIL_OFF,
+    };
+    // Version 0: engine, risk buffer, generation table. Later versions
+    // keep that prefix and add regions after it.
+    assert_eq!(SLAB_VERSION, 1);
+    assert_eq!(RISK_BUF_OFF, ENGINE_OFF + ENGINE_LEN);
+    assert_eq!(GEN_TABLE_OFF, RISK_BUF_OFF + RISK_BUF_LEN);
+    assert_eq!(TAIL_OFF, GEN_TABLE_OFF + GEN_TABLE_LEN);
+    assert_eq!(SLAB_LEN_BY_VERSION, [TAIL_OFF, SLAB_LEN]);
+    assert_eq!(SLAB_LEN, TAIL_OFF + TAIL_LEN);
+}
+
+#[test]
+fn test_sbf_offsets_are_consistent() {
+    use percolator::MAX_ACCOUNTS;
+    use percolator_prog::constants::{
+        ACCOUNT_LIMITS_LEN, ADMIN_MULTISIG_LEN, ALLOWLIST_ROOT_LEN, BACKSTOP_LEN,
+        CIRCUIT_BREAKER_LEN, CRANK_GUARD_LEN, DUST_LIMITS_LEN, EPOCH_CONFIG_LEN,
+        EPOCH_STATS_TABLE_LEN, FEE_AUTHORITY_LEN, FEE_SPLIT_LEN, HISTORY_LEN, LIQ_WATCH_LEN,
+        LIQ_WINDOW_LEN, LP_STATS_LEN, MARKET_METADATA_LEN, ORDER_ID_TABLE_LEN,
+        PENDING_ADMIN_ACTIONS_LEN, PENDING_ADMIN_LEN, PNL_LEDGER_LEN, POSITION_BITMAP_LEN,
+        STALE_FALLBACK_LEN, TRADE_RATE_LEN, USER_NONCE_TABLE_LEN,
+    };
+    use percolator_prog::state::offsets::{self, sbf};
+
+    assert_eq!(offsets::CONFIG, HEADER_LEN);
+    assert_eq!(sbf::ENGINE % 8, 0);
+    assert!(sbf::ENGINE >= HEADER_LEN + CONFIG_LEN && sbf::ENGINE <= ENGINE_OFF);
+    assert_eq!(sbf::NUM_USED_ACCOUNTS, sbf::USED_BITMAP + MAX_ACCOUNTS / 8);
+    // num_used + free_head (u16 each), pad to 8, then next/prev free lists.
+    assert_eq!(
+        sbf::ACCOUNTS,
+        sbf::NUM_USED_ACCOUNTS + 8 + 2 * MAX_ACCOUNTS * 2
+    );
+    assert_eq!(sbf::ACCOUNTS % 8, 0);
+    // Accounts, risk buffer and generation table end a version-0 slab.
+    assert_eq!(
+        sbf::RISK_BUF,
+        sbf::ACCOUNTS + MAX_ACCOUNTS * sbf::ACCOUNT_SIZE
+    );
+    assert_eq!(sbf::GEN_TABLE, sbf::RISK_BUF + RISK_BUF_LEN);
+    assert_eq!(sbf::TAIL, sbf::GEN_TABLE + MAX_ACCOUNTS * 8);
+    assert_eq!(sbf::SLAB_LEN_V0, sbf::TAIL);
+    // Then order-id and nonce tables, account limits, allowlist root, fee
+    // split, fee authority, epoch clock and stats, crank guard, admin key
+    // set, pending approvals and nominee, dust limits, circuit breaker,
+    // market history, LP stats, trade rate limit, liquidator window,
+    // backstop, stale fallback, open-position bitmap, liquidation watch
+    // index, PnL ledger and market metadata; same tail as host.
+    assert_eq!(
+        sbf::SLAB_LEN,
+        sbf::TAIL
+            + ORDER_ID_TABLE_LEN
+            + USER_NONCE_TABLE_LEN
+            + ACCOUNT_LIMITS_LEN
+            + ALLOWLIST_ROOT_LEN
+            + FEE_SPLIT_LEN
+            + FEE_AUTHORITY_LEN
+            + EPOCH_CONFIG_LEN
+            + EPOCH_STATS_TABLE_LEN
+            + CRANK_GUARD_LEN
+            + ADMIN_MULTISIG_LEN
+            + PENDING_ADMIN_ACTIONS_LEN
+            + PENDING_ADMIN_LEN
+            + DUST_LIMITS_LEN
+            + CIRCUIT_BREAKER_LEN
+            + HISTORY_LEN
+            + LP_STATS_LEN
+            + TRADE_RATE_LEN
+            + LIQ_WINDOW_LEN
+            + BACKSTOP_LEN
+            + STALE_FALLBACK_LEN
+            + POSITION_BITMAP_LEN
+            + LIQ_WATCH_LEN
+            + PNL_LEDGER_LEN
+            + MARKET_METADATA_LEN
+    );
+}
@@ -32,14 +32,14 @@ fn test_hyperp_rejects_zero_initial_mark_price() {
     let slab = Pubkey::new_unique();
     let mint = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
     svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
     svm.set_account(
         slab,
         Account {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; SLAB_LEN],
             owner: program_id,
             executable: false,
@@ -186,14 +186,14 @@ fn test_hyperp_init_market_with_valid_price() {
     let slab = Pubkey::new_unique();
     let mint = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
     svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
     svm.set_account(
         slab,
         Account {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; SLAB_LEN],
             owner: program_id,
             executable: false,
@@ -350,14 +350,14 @@ fn test_hyperp_init_market_with_inverted_price() {
     let slab = Pubkey::new_unique();
     let mint = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
 
     svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
 
     svm.set_account(
         slab,
         Account {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; SLAB_LEN],
             owner: program_id,
             executable: false,
@@ -622,7 +622,7 @@ fn test_hyperp_index_smoothing_multiple_cranks_same_slot() {
     let slab = Pubkey::new_unique();
     let mint = Pubkey::new_unique();
     let (vault_pda, _) = Pubkey::find_program_address(&[b"vault", slab.as_ref()], &program_id);
-    let vault = Pubkey::new_unique();
+    let vault = percolator_prog::accounts::associated_token_address(&vault_pda, &mint);
     let dummy_oracle = Pubkey::new_unique();
 
     svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
@@ -630,7 +630,7 @@ fn test_hyperp_index_smoothing_multiple_cranks_same_slot() {
     svm.set_account(
         slab,
         Account {
-            lamports: 1_000_000_000,
+            lamports: 100_000_000_000,
             data: vec![0u8; SLAB_LEN],
             owner: program_id,
             executable: false,
@@ -1394,3 +1394,165 @@ fn test_oracle_replay_does_not_advance_liveness_cursor() {
         "duplicate-publish_time read must not advance last_good_oracle_slot",
     );
 }
+
+/// Step the clock forward by `dt` seconds/slots without touching the oracle.
+fn advance_clock(env: &mut TestEnv, dt: i64) -> i64 {
+    let clock = env.svm.get_sysvar::<Clock>();
+    let now = clock.unix_timestamp + dt;
+    env.svm.set_sysvar(&Clock {
+        slot: clock.slot + dt as u64,
+        unix_timestamp: now,
+        ..clock
+    });
+    now
+}
+
+/// Mock-oracle fixtures: a confidence band wider than conf_filter_bps
+/// (500 in the default market) fails the crank with OracleConfTooWide; the
+/// same observation inside the band is accepted.
+#[test]
+fn test_mock_oracle_wide_confidence_rejected() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    env.set_slot_and_price(100, 138_000_000);
+    env.crank();
+
+    let now = advance_clock(&mut env, 1);
+    env.set_oracle(&MockPythPrice::new(138_000_000, now).conf_bps(501));
+    let err = env
+        .try_crank()
+        .expect_err("wide confidence must be rejected");
+    assert!(
+        err.contains("Custom(7)"),
+        "expected OracleConfTooWide, got {err}"
+    );
+
+    env.set_oracle(&MockPythPrice::new(138_000_000, now).conf_bps(100));
+    env.try_crank()
+        .expect("confidence inside the filter is accepted");
+}
+
+/// Mock-oracle fixtures: a publish time one second past max_staleness_secs
+/// fails the crank with OracleStale even though it is newer than the last
+/// accepted observation; a fresh publish time at the same clock is accepted.
+#[test]
+fn test_mock_oracle_stale_publish_time_rejected() {
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_with_invert(0);
+    env.set_slot_and_price(100, 138_000_000);
+    env.crank();
+
+    let clock = env.svm.get_sysvar::<Clock>();
+    let now = clock.unix_timestamp + TEST_MAX_STALENESS_SECS as i64 + 10;
+    env.svm.set_sysvar(&Clock {
+        slot: clock.slot + 1,
+        unix_timestamp: now,
+        ..clock
+    });
+
+    let stale = MockPythPrice::new(138_000_000, 0).stale_at(now, TEST_MAX_STALENESS_SECS);
+    assert!(stale.publish_time > clock.unix_timestamp);
+    env.set_oracle(&stale);
+    let err = env
+        .try_crank()
+        .expect_err("stale observation must be rejected");
+    assert!(err.contains("Custom(6)"), "expected OracleStale, got {err}");
+
+    env.set_oracle(&MockPythPrice::new(138_000_000, now));
+    env.try_crank().expect("fresh observation is accepted");
+}
+
+/// The Hyperp mark authority adds and removes extra pushers; each key's
+/// pushes are tracked separately and a removed key is out at once.
+#[test]
+fn test_hyperp_pusher_set_add_push_remove() {
+    use percolator_prog::constants::{HYPERP_PUSHERS_OFF, HYPERP_PUSHER_LEN};
+    use percolator_prog::state::HyperpPusher;
+
+    program_path();
+    let mut env = TestEnv::new();
+    env.init_market_hyperp(1_000_000);
+    let admin = Keypair::from_bytes(&env.payer.to_bytes()).unwrap();
+    env.try_set_oracle_authority(&admin, &admin.pubkey())
+        .unwrap();
+
+    fn send(env: &mut TestEnv, signer: &Keypair, tag: u8, pusher: &Pubkey) -> Result<(), String> {
+        let mut data = vec![tag];
+        data.extend_from_slice(pusher.as_ref());
+        let ix = Instruction {
+            program_id: env.program_id,
+            accounts: vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(env.slab, false),
+            ],
+            data,
+        };
+        env.svm.expire_blockhash();
+        let tx = Transaction::new_signed_with_payer(
+            &[cu_ix(), ix],
+            Some(&signer.pubkey()),
+            &[signer],
+            env.svm.latest_blockhash(),
+        );
+        env.svm
+            .send_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e.err))
+    }
+    let entry = |env: &TestEnv, i: usize| {
+        let data = env.svm.get_account(&env.slab).unwrap().data;
+        let off = SLAB_LEN - (percolator_prog::constants::SLAB_LEN - HYPERP_PUSHERS_OFF)
+            + i * HYPERP_PUSHER_LEN;
+        HyperpPusher::from_bytes(&data[off..off + HYPERP_PUSHER_LEN])
+    };
+
+    let pusher = Keypair::new();
+    env.svm.airdrop(&pusher.pubkey(), 1_000_000_000).unwrap();
+    let err = env
+        .try_push_oracle_price(&pusher, 1_000_000, 0)
+        .unwrap_err();
+    assert!(err.contains("Custom(15)"), "not a pusher yet: {err}");
+    let err = send(&mut env, &pusher, 59, &pusher.pubkey()).unwrap_err();
+    assert!(
+        err.contains("Custom(15)"),
+        "only the mark authority adds: {err}"
+    );
+    send(&mut env, &admin, 59, &pusher.pubkey()).expect("authority adds a pusher");
+    let err = send(&mut env, &admin, 59, &pusher.pubkey()).unwrap_err();
+    assert!(err.contains("Custom(26)"), "already a pusher: {err}");
+
+    env.try_push_oracle_price(&pusher, 1_000_000, 0)
+        .expect("extra pusher pushes");
+    env.try_push_oracle_price(&admin, 1_000_000, 0)
+        .expect("authority still pushes");
+    env.try_push_oracle_price(&admin, 1_000_000, 0)
+        .expect("authority pushes again");
+    let slot = env.svm.get_sysvar::<Clock>().slot;
+    let (by_admin, by_pusher) = (entry(&env, 0), entry(&env, 1));
+    assert_eq!(by_admin.key, admin.pubkey().to_bytes());
+    assert_eq!((by_admin.pushes, by_admin.last_push_slot), (2, slot));
+    assert_eq!(by_pusher.key, pusher.pubkey().to_bytes());
+    assert_eq!((by_pusher.pushes, by_pusher.last_push_slot), (1, slot));
+
+    // Three extra keys at most.
+    send(&mut env, &admin, 59, &Pubkey::new_unique()).expect("second extra key");
+    send(&mut env, &admin, 59, &Pubkey::new_unique()).expect("third extra key");
+    let err = send(&mut env, &admin, 59, &Pubkey::new_unique()).unwrap_err();
+    assert!(err.contains("Custom(58)"), "set is full: {err}");
+
+    send(&mut env, &admin, 60, &pusher.pubkey()).expect("authority removes the pusher");
+    assert!(entry(&env, 1).is_free());
+    let err = env
+        .try_push_oracle_price(&pusher, 1_000_000, 0)
+        .unwrap_err();
+    assert!(err.contains("Custom(15)"), "removed key is out: {err}");
+    let err = send(&mut env, &admin, 60, &pusher.pubkey()).unwrap_err();
+    assert!(err.contains("Custom(26)"), "not a pusher: {err}");
+    let err = send(&mut env, &admin, 60, &admin.pubkey()).unwrap_err();
+    assert!(
+        err.contains("Custom(26)"),
+        "the authority rotates instead: {err}"
+    );
+}
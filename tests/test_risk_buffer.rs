@@ -8,8 +8,7 @@ use solana_sdk::signature::{Keypair, Signer};
 fn write_risk_buffer_for_test(env: &mut TestEnv, buf: &percolator_prog::risk_buffer::RiskBuffer) {
     let mut slab = env.svm.get_account(&env.slab).unwrap();
     let buf_size = core::mem::size_of::<percolator_prog::risk_buffer::RiskBuffer>();
-    let gen_table_size = MAX_ACCOUNTS * 8;
-    let buf_off = SLAB_LEN - gen_table_size - buf_size;
+    let buf_off = sbf_offsets::RISK_BUF;
     slab.data[buf_off..buf_off + buf_size].copy_from_slice(bytemuck::bytes_of(buf));
     env.svm.set_account(env.slab, slab).unwrap();
 }
@@ -60,6 +59,17 @@ fn move_used_account_slot_for_test(env: &mut TestEnv, from: u16, to: u16) {
     slab.data[src_word_off..src_word_off + 8].copy_from_slice(&src_word_bits.to_le_bytes());
     slab.data[dst_word_off..dst_word_off + 8].copy_from_slice(&dst_word_bits.to_le_bytes());
 
+    // The open-position bitmap, at the same distance from the end as on host.
+    let pos_off = SLAB_LEN
+        - (percolator_prog::constants::SLAB_LEN
+            - percolator_prog::constants::POSITION_BITMAP_OFF);
+    let src_byte = pos_off + from as usize / 8;
+    let open = slab.data[src_byte] & (1 << (from % 8)) != 0;
+    slab.data[src_byte] &= !(1 << (from % 8));
+    if open {
+        slab.data[pos_off + to as usize / 8] |= 1 << (to % 8);
+    }
+
     env.svm.set_account(env.slab, slab).unwrap();
     assert!(!env.is_slot_used(from), "source slot should now be free");
     assert!(env.is_slot_used(to), "target slot should now be used");
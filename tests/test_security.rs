@@ -12587,8 +12587,9 @@ fn test_attack_oracle_timestamp_zero_then_crank() {
 
     let config_after_pushes = read_market_config(&env);
     assert_eq!(
-        config_after_pushes.last_oracle_publish_time, 0,
-        "PushHyperpMark must not treat its ignored timestamp argument as an external oracle publish time"
+        config_after_pushes.last_oracle_publish_time,
+        env.svm.get_sysvar::<Clock>().unix_timestamp,
+        "PushHyperpMark advances the replay cursor from the clock, not its ignored timestamp argument"
     );
     assert_eq!(
         config_after_pushes.oracle_target_publish_time, 0,
@@ -12636,8 +12637,9 @@ fn test_attack_oracle_timestamp_i64_max_no_overflow() {
 
     let config_after_max = read_market_config(&env);
     assert_eq!(
-        config_after_max.last_oracle_publish_time, 0,
-        "Hyperp PushHyperpMark must ignore i64::MAX as an external oracle publish time"
+        config_after_max.last_oracle_publish_time,
+        env.svm.get_sysvar::<Clock>().unix_timestamp,
+        "Hyperp PushHyperpMark must ignore i64::MAX and take the replay cursor from the clock"
     );
     assert_eq!(
         config_after_max.oracle_target_publish_time, 0,
@@ -5584,6 +5584,101 @@ fn test_tradecpi_slippage_old_wire_format_backward_compat() {
     );
 }
 
+/// Max-slippage form: with TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS the limit field
+/// is a bps bound versus the oracle. Zero tolerance rejects any vAMM spread
+/// with SlippageExceeded (Custom(35)); a generous bound fills.
+#[test]
+fn test_tradecpi_max_slippage_bps() {
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+    let lp = Keypair::new();
+    let (lp_idx, matcher_ctx) = env.init_lp_with_matcher(&lp, &matcher_prog);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+    let flag = percolator_prog::constants::TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS;
+
+    for size in [1_000_000i128, -1_000_000i128] {
+        let result = env.try_trade_cpi_with_flags(
+            &user,
+            &lp.pubkey(),
+            lp_idx,
+            user_idx,
+            size,
+            0,
+            flag,
+            &matcher_prog,
+            &matcher_ctx,
+        );
+        match result {
+            Err(e) => assert!(e.contains("Custom(35)"), "expected SlippageExceeded: {e}"),
+            Ok(()) => panic!("zero slippage tolerance must reject a spread fill"),
+        }
+    }
+
+    let result = env.try_trade_cpi_with_flags(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        1_000_000i128,
+        500,
+        flag,
+        &matcher_prog,
+        &matcher_ctx,
+    );
+    assert!(result.is_ok(), "5% slippage bound should fill: {:?}", result);
+}
+
+/// Fill-or-kill: a matcher capped below the request partially fills; with
+/// TRADE_FLAG_FILL_OR_KILL that is FillOrKillNotFilled (Custom(36)) and no
+/// position changes. Without the flag the same request fills partially (IOC).
+#[test]
+fn test_tradecpi_fill_or_kill_rejects_partial_fill() {
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+    let lp = Keypair::new();
+    let (lp_idx, matcher_ctx) =
+        init_lp_with_matcher_fill_cap(&mut env, &lp, &matcher_prog, 500_000);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+
+    let err = env
+        .try_trade_cpi_with_flags(
+            &user,
+            &lp.pubkey(),
+            lp_idx,
+            user_idx,
+            1_000_000i128,
+            0,
+            percolator_prog::constants::TRADE_FLAG_FILL_OR_KILL,
+            &matcher_prog,
+            &matcher_ctx,
+        )
+        .expect_err("partial fill must fail under fill-or-kill");
+    assert!(err.contains("Custom(36)"), "expected FillOrKillNotFilled: {err}");
+    assert_eq!(env.read_account_position(user_idx), 0);
+
+    env.try_trade_cpi_with_flags(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        1_000_000i128,
+        0,
+        0,
+        &matcher_prog,
+        &matcher_ctx,
+    )
+    .expect("immediate-or-cancel accepts the partial fill");
+    assert_eq!(env.read_account_position(user_idx), 500_000);
+}
+
 // ── Inverted market slippage protection tests ──────────────────────────
 
 /// Helper: initialize an inverted (invert=1) market on a TradeCpiTestEnv.
@@ -6431,8 +6526,7 @@ fn test_tradecpi_buffer_notional_uses_oracle_price() {
         use bytemuck::Zeroable;
         let d = env.svm.get_account(&env.slab).unwrap().data;
         let buf_size = core::mem::size_of::<percolator_prog::risk_buffer::RiskBuffer>();
-        let gen_table_size = common::MAX_ACCOUNTS * 8;
-        let buf_off = SLAB_LEN - gen_table_size - buf_size;
+        let buf_off = common::sbf_offsets::RISK_BUF;
         let mut buf = percolator_prog::risk_buffer::RiskBuffer::zeroed();
         bytemuck::bytes_of_mut(&mut buf).copy_from_slice(&d[buf_off..buf_off + buf_size]);
         buf
@@ -9445,3 +9539,501 @@ fn test_tradecpi_empty_tail_is_canonical() {
     )
     .expect("TradeCpi with empty tail must succeed (canonical 8-account form)");
 }
+
+/// Economic state a fill can touch, read back from the slab.
+fn fill_state_snapshot(env: &TradeCpiTestEnv, lp_idx: u16, user_idx: u16) -> Vec<u128> {
+    let slab = env.svm.get_account(&env.slab).unwrap();
+    let cfg = percolator_prog::state::read_config(&slab.data);
+    vec![
+        env.read_account_position(lp_idx) as u128,
+        env.read_account_position(user_idx) as u128,
+        env.read_account_capital(lp_idx),
+        env.read_account_capital(user_idx),
+        env.read_account_pnl(lp_idx) as u128,
+        env.read_account_pnl(user_idx) as u128,
+        env.read_insurance_balance(),
+        env.read_c_tot(),
+        env.read_pnl_pos_tot(),
+        env.read_vault(),
+        cfg.mark_ewma_e6 as u128,
+        cfg.mark_ewma_last_slot as u128,
+        cfg.last_effective_price_e6 as u128,
+    ]
+}
+
+/// TradeCpi and TradeNoCpi settle through one shared fill core. The same
+/// fill (size and price) on two identically prepared markets must leave
+/// identical engine and mark state whichever path delivered it.
+#[test]
+fn test_tradecpi_and_nocpi_identical_fill_identical_state() {
+    let size: i128 = 5_000_000;
+    let mut cpi = TradeCpiTestEnv::new();
+    let mut nocpi = TradeCpiTestEnv::new();
+    let mut parties = Vec::new();
+    for env in [&mut cpi, &mut nocpi] {
+        env.init_market();
+        let matcher_prog = env.matcher_program_id;
+        let lp = Keypair::new();
+        let (lp_idx, matcher_ctx) = env.init_lp_with_matcher(&lp, &matcher_prog);
+        env.deposit(&lp, lp_idx, 50_000_000_000);
+        let user = Keypair::new();
+        let user_idx = env.init_user(&user);
+        env.deposit(&user, user_idx, 5_000_000_000);
+        env.set_slot(10);
+        parties.push((lp, lp_idx, matcher_ctx, user, user_idx));
+    }
+
+    // Path A: the matcher quotes the fill.
+    let (lp, lp_idx, matcher_ctx, user, user_idx) = &parties[0];
+    let (lp_pda, _) = Pubkey::find_program_address(
+        &[b"lp", cpi.slab.as_ref(), &lp_idx.to_le_bytes()],
+        &cpi.program_id,
+    );
+    let ix = Instruction {
+        program_id: cpi.program_id,
+        accounts: vec![
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new(lp.pubkey(), false),
+            AccountMeta::new(cpi.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(cpi.pyth_index, false),
+            AccountMeta::new_readonly(cpi.matcher_program_id, false),
+            AccountMeta::new(*matcher_ctx, false),
+            AccountMeta::new_readonly(lp_pda, false),
+        ],
+        data: encode_trade_cpi(*lp_idx, *user_idx, size),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&user.pubkey()),
+        &[user],
+        cpi.svm.latest_blockhash(),
+    );
+    let meta = cpi.svm.send_transaction(tx).expect("TradeCpi");
+    let fill = percolator_prog::ix::TradeResult::decode(&meta.return_data.data)
+        .expect("TradeCpi trade result");
+    assert_eq!(fill.exec_size, size);
+    assert_ne!(
+        fill.exec_price_e6, fill.oracle_price_e6,
+        "matcher spread must move the fill off the oracle"
+    );
+
+    // Path B: both signers agree the same fill bilaterally.
+    let (lp, lp_idx, _, user, user_idx) = &parties[1];
+    let ix = Instruction {
+        program_id: nocpi.program_id,
+        accounts: vec![
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new(lp.pubkey(), true),
+            AccountMeta::new(nocpi.slab, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(nocpi.pyth_index, false),
+        ],
+        data: encode_trade_with_exec_price(*lp_idx, *user_idx, size, fill.exec_price_e6),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&user.pubkey()),
+        &[user, lp],
+        nocpi.svm.latest_blockhash(),
+    );
+    let meta = nocpi.svm.send_transaction(tx).expect("TradeNoCpi");
+    let fill_nocpi = percolator_prog::ix::TradeResult::decode(&meta.return_data.data)
+        .expect("TradeNoCpi trade result");
+
+    assert_eq!(fill_nocpi, fill, "both paths must report the same fill");
+    assert_eq!(
+        fill_state_snapshot(&cpi, parties[0].1, parties[0].4),
+        fill_state_snapshot(&nocpi, parties[1].1, parties[1].4),
+        "identical fills must produce identical state transitions"
+    );
+}
+
+/// Create a matcher context and run the matcher's InitVamm with `lp_pda`
+/// as the LP it is bound to.
+fn init_matcher_ctx_bound_to(
+    env: &mut TradeCpiTestEnv,
+    payer: &Keypair,
+    lp_pda: &Pubkey,
+) -> Pubkey {
+    let matcher_prog = env.matcher_program_id;
+    let ctx = Pubkey::new_unique();
+    env.svm
+        .set_account(
+            ctx,
+            Account {
+                lamports: 10_000_000,
+                data: vec![0; MATCHER_CONTEXT_LEN],
+                owner: matcher_prog,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    let ix = Instruction {
+        program_id: matcher_prog,
+        accounts: vec![
+            AccountMeta::new_readonly(*lp_pda, false),
+            AccountMeta::new(ctx, false),
+        ],
+        data: encode_init_vamm(MatcherMode::Passive, 5, 10, 200, 0, 0, 1_000_000_000_000, 0),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&payer.pubkey()),
+        &[payer],
+        env.svm.latest_blockhash(),
+    );
+    env.svm.send_transaction(tx).expect("matcher init failed");
+    ctx
+}
+
+fn lp_pda_for(env: &TradeCpiTestEnv, lp_idx: u16) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"lp", env.slab.as_ref(), &lp_idx.to_le_bytes()],
+        &env.program_id,
+    )
+    .0
+}
+
+/// Send InitLP with the matcher context appended as the 7th account.
+fn try_init_lp_with_ctx_account(
+    env: &mut TradeCpiTestEnv,
+    owner: &Keypair,
+    matcher_prog: &Pubkey,
+    ctx: &Pubkey,
+) -> Result<u16, String> {
+    let idx = env.account_count;
+    let ata = env.create_ata(&owner.pubkey(), DEFAULT_INIT_PAYMENT);
+    let ix = Instruction {
+        program_id: env.program_id,
+        accounts: vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(env.slab, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(env.vault, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::clock::ID, false),
+            AccountMeta::new_readonly(*ctx, false),
+        ],
+        data: encode_init_lp(matcher_prog, ctx, DEFAULT_INIT_PAYMENT),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cu_ix(), ix],
+        Some(&owner.pubkey()),
+        &[owner],
+        env.svm.latest_blockhash(),
+    );
+    env.svm
+        .send_transaction(tx)
+        .map_err(|e| format!("{:?}", e))?;
+    env.account_count += 1;
+    Ok(idx)
+}
+
+/// ATTACK: an LP registers a genuine matcher context that the matcher
+/// bound to a different LP slot. The context passes the ownership and
+/// identity checks, but TradeCpi must reject it on the binding.
+#[test]
+fn test_attack_tradecpi_ctx_bound_to_other_lp_rejected() {
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+
+    let lp = Keypair::new();
+    env.svm.airdrop(&lp.pubkey(), 1_000_000_000).unwrap();
+    let other_slot_pda = lp_pda_for(&env, env.account_count + 1);
+    let foreign_ctx = init_matcher_ctx_bound_to(&mut env, &lp, &other_slot_pda);
+    let lp_idx = env.init_lp_with_raw_matcher(&lp, &matcher_prog, &foreign_ctx);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+    let user_cap_before = env.read_account_capital(user_idx);
+    let lp_cap_before = env.read_account_capital(lp_idx);
+
+    let result = env.try_trade_cpi(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        1_000_000,
+        &matcher_prog,
+        &foreign_ctx,
+    );
+    assert!(
+        result.is_err(),
+        "ATTACK: context bound to another LP slot must be rejected"
+    );
+    assert_eq!(env.read_account_position(user_idx), 0);
+    assert_eq!(env.read_account_position(lp_idx), 0);
+    assert_eq!(env.read_account_capital(user_idx), user_cap_before);
+    assert_eq!(env.read_account_capital(lp_idx), lp_cap_before);
+}
+
+/// InitLP with the optional context account checks ownership and binding
+/// up front: a context bound elsewhere or owned by another program is
+/// rejected, a correctly bound one is accepted and trades.
+#[test]
+fn test_init_lp_with_ctx_account_validates_binding() {
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+
+    let lp = Keypair::new();
+    env.svm.airdrop(&lp.pubkey(), 1_000_000_000).unwrap();
+    let used_before = env.read_num_used_accounts();
+
+    let other_slot_pda = lp_pda_for(&env, env.account_count + 1);
+    let foreign_ctx = init_matcher_ctx_bound_to(&mut env, &lp, &other_slot_pda);
+    assert!(
+        try_init_lp_with_ctx_account(&mut env, &lp, &matcher_prog, &foreign_ctx).is_err(),
+        "context bound to another LP slot must be rejected at InitLP"
+    );
+
+    let own_pda = lp_pda_for(&env, env.account_count);
+    let ctx = init_matcher_ctx_bound_to(&mut env, &lp, &own_pda);
+    let wrong_prog = Pubkey::new_unique();
+    assert!(
+        try_init_lp_with_ctx_account(&mut env, &lp, &wrong_prog, &ctx).is_err(),
+        "context not owned by the registered matcher program must be rejected"
+    );
+    assert_eq!(env.read_num_used_accounts(), used_before);
+
+    let lp_idx = try_init_lp_with_ctx_account(&mut env, &lp, &matcher_prog, &ctx)
+        .expect("correctly bound context must be accepted");
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+    env.try_trade_cpi(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        1_000_000,
+        &matcher_prog,
+        &ctx,
+    )
+    .expect("TradeCpi through a bound context must succeed");
+    assert_eq!(env.read_account_position(user_idx), 1_000_000);
+}
+
+/// Write a capability header into a matcher context, as an upgraded
+/// matcher would.
+fn set_matcher_caps(env: &mut TradeCpiTestEnv, ctx: &Pubkey, flags: u32, max_fill_abs: u128) {
+    use percolator_prog::matcher_abi::{CAPS_VERSION, CTX_CAPS_OFFSET};
+    let mut acc = env.svm.get_account(ctx).unwrap();
+    acc.data[CTX_CAPS_OFFSET..CTX_CAPS_OFFSET + 4].copy_from_slice(&CAPS_VERSION.to_le_bytes());
+    acc.data[CTX_CAPS_OFFSET + 4..CTX_CAPS_OFFSET + 8].copy_from_slice(&flags.to_le_bytes());
+    acc.data[CTX_CAPS_OFFSET + 8..CTX_CAPS_OFFSET + 24]
+        .copy_from_slice(&max_fill_abs.to_le_bytes());
+    env.svm.set_account(*ctx, acc).unwrap();
+}
+
+/// The wrapper reads the matcher's capability header before the CPI:
+/// an oversize request to a matcher without partial fills fails up front,
+/// and a fill larger than the declared maximum fails after the CPI.
+#[test]
+fn test_tradecpi_matcher_caps_gate_request_and_fill() {
+    use percolator_prog::matcher_abi::CAP_PARTIAL_FILL;
+
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+    let lp = Keypair::new();
+    let (lp_idx, matcher_ctx) = env.init_lp_with_matcher(&lp, &matcher_prog);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+
+    // Full fills only, at most 500_000 per call.
+    set_matcher_caps(&mut env, &matcher_ctx, 0, 500_000);
+    let err = env
+        .try_trade_cpi(
+            &user,
+            &lp.pubkey(),
+            lp_idx,
+            user_idx,
+            1_000_000,
+            &matcher_prog,
+            &matcher_ctx,
+        )
+        .expect_err("oversize request to a full-fill matcher must fail");
+    assert!(
+        err.contains("Custom(48)"),
+        "expected MatcherCapabilityExceeded: {err}"
+    );
+    assert_eq!(env.read_account_position(user_idx), 0);
+    env.try_trade_cpi(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        400_000,
+        &matcher_prog,
+        &matcher_ctx,
+    )
+    .expect("request within the declared maximum must fill");
+    assert_eq!(env.read_account_position(user_idx), 400_000);
+
+    // The header promises partial fills up to 300_000, but this matcher
+    // fills the whole request: the wrapper holds it to the header.
+    set_matcher_caps(&mut env, &matcher_ctx, CAP_PARTIAL_FILL, 300_000);
+    let err = env
+        .try_trade_cpi(
+            &user,
+            &lp.pubkey(),
+            lp_idx,
+            user_idx,
+            1_000_000,
+            &matcher_prog,
+            &matcher_ctx,
+        )
+        .expect_err("fill above the declared maximum must fail");
+    assert!(
+        err.contains("Custom(48)"),
+        "expected MatcherCapabilityExceeded: {err}"
+    );
+    assert_eq!(env.read_account_position(user_idx), 400_000);
+}
+
+/// The off-chain quote read from the matcher context's bytes equals the
+/// fill the matcher actually gives through TradeCpi.
+#[test]
+fn test_tradecpi_ctx_quote_matches_matcher_fill() {
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+    let lp = Keypair::new();
+    let (lp_idx, matcher_ctx) = env.init_lp_with_matcher(&lp, &matcher_prog);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+
+    let size = 1_000_000i128;
+    let price = read_market_config(&env).last_effective_price_e6;
+    let ctx_data = env.svm.get_account(&matcher_ctx).unwrap().data;
+    let vamm = percolator_prog::matcher_abi::read_vamm_ctx(&ctx_data)
+        .expect("initialized matcher context must parse");
+    let q = percolator_prog::policy::vamm_quote(
+        &vamm.effective_params(),
+        price,
+        size,
+        vamm.inventory,
+    )
+    .expect("quote");
+
+    env.try_trade_cpi(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        size,
+        &matcher_prog,
+        &matcher_ctx,
+    )
+    .expect("TradeCpi");
+    let ret = read_matcher_return(&env.svm.get_account(&matcher_ctx).unwrap().data);
+    assert_eq!(read_market_config(&env).last_effective_price_e6, price);
+    assert_eq!((ret.2, ret.3), (q.exec_price_e6, q.fill_size));
+}
+
+/// An LP whose matcher runs the impact curve fills at the reference
+/// `vamm_exec_price_e6` price: fee + spread + impact over its depth.
+#[test]
+fn test_tradecpi_impact_mode_fills_on_the_reference_curve() {
+    use percolator_prog::matcher_abi::VammParams;
+    use percolator_prog::policy::{vamm_exec_price_e6, vamm_impact_bps};
+
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+    let params = VammParams {
+        trading_fee_bps: 5,
+        base_spread_bps: 10,
+        max_total_bps: 200,
+        impact_k_bps: 100,
+        liquidity_notional_e6: 10_000_000_000,
+        max_fill_abs: 1_000_000_000_000,
+        max_inventory_abs: 0,
+    };
+    let lp = Keypair::new();
+    let (lp_idx, matcher_ctx) =
+        env.init_lp_with_vamm(&lp, &matcher_prog, MatcherMode::Vamm, &params);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+
+    let size = 10 * percolator::POS_SCALE as i128;
+    let price = read_market_config(&env).last_effective_price_e6;
+    let notional = size as u128 * price as u128 / percolator::POS_SCALE;
+    let impact = vamm_impact_bps(notional, 100, 10_000_000_000).unwrap();
+    assert!(impact > 0, "this fill is large enough to move the price");
+    let expected = vamm_exec_price_e6(price, size, 5, 10, 200, 100, 10_000_000_000).unwrap();
+    let passive = vamm_exec_price_e6(price, size, 5, 10, 200, 0, 0).unwrap();
+    assert!(expected > passive);
+
+    env.try_trade_cpi(
+        &user,
+        &lp.pubkey(),
+        lp_idx,
+        user_idx,
+        size,
+        &matcher_prog,
+        &matcher_ctx,
+    )
+    .expect("TradeCpi");
+    let ret = read_matcher_return(&env.svm.get_account(&matcher_ctx).unwrap().data);
+    assert_eq!((ret.2, ret.3), (expected, size));
+    assert_eq!(env.read_account_position(user_idx), size);
+}
+
+/// A matcher quoting further from the oracle than the market's 10%
+/// initial margin is refused with MatcherFillOutOfBounds, in both
+/// directions, and no position changes.
+#[test]
+fn test_tradecpi_rejects_matcher_fill_beyond_initial_margin() {
+    use percolator_prog::matcher_abi::VammParams;
+
+    let mut env = TradeCpiTestEnv::new();
+    env.init_market();
+    let matcher_prog = env.matcher_program_id;
+    let params = VammParams {
+        trading_fee_bps: 5,
+        base_spread_bps: 2_000,
+        max_total_bps: 2_500,
+        impact_k_bps: 0,
+        liquidity_notional_e6: 0,
+        max_fill_abs: 1_000_000_000_000,
+        max_inventory_abs: 0,
+    };
+    let lp = Keypair::new();
+    let (lp_idx, matcher_ctx) =
+        env.init_lp_with_vamm(&lp, &matcher_prog, MatcherMode::Passive, &params);
+    env.deposit(&lp, lp_idx, 100_000_000_000);
+    let user = Keypair::new();
+    let user_idx = env.init_user(&user);
+    env.deposit(&user, user_idx, 10_000_000_000);
+
+    for size in [1_000_000i128, -1_000_000] {
+        let err = env
+            .try_trade_cpi(
+                &user,
+                &lp.pubkey(),
+                lp_idx,
+                user_idx,
+                size,
+                &matcher_prog,
+                &matcher_ctx,
+            )
+            .expect_err("a 20% spread is outside the 10% margin band");
+        assert!(err.contains("Custom(54)"), "size {}: {}", size, err);
+    }
+    assert_eq!(env.read_account_position(user_idx), 0);
+    assert_eq!(env.read_account_position(lp_idx), 0);
+}
@@ -182,7 +182,7 @@ fn setup_market() -> MarketFixture {
         .writable(),
         mint: TestAccount::new(mint_key, spl_token::ID, 0, make_mint_account()),
         vault: TestAccount::new(
-            Pubkey::new_unique(),
+            percolator_prog::accounts::associated_token_address(&vault_pda, &mint_key),
             spl_token::ID,
             0,
             make_token_account(mint_key, vault_pda, 0),
@@ -415,11 +415,17 @@ fn test_trade_nocpi_optional_exec_price_tail_decodes() {
             user_idx,
             size,
             exec_price_e6,
+            flags,
+            client_order_id,
+            client_tag,
         } => {
             assert_eq!(lp_idx, 2);
             assert_eq!(user_idx, 3);
             assert_eq!(size, -123);
             assert_eq!(exec_price_e6, 0);
+            assert_eq!(flags, 0);
+            assert_eq!(client_order_id, 0);
+            assert_eq!(client_tag, [0; 16]);
         }
         other => panic!("unexpected decode: {other:?}"),
     }
@@ -432,21 +438,1300 @@ fn test_trade_nocpi_optional_exec_price_tail_decodes() {
             user_idx,
             size,
             exec_price_e6,
+            flags,
+            client_order_id,
+            client_tag,
         } => {
             assert_eq!(lp_idx, 4);
             assert_eq!(user_idx, 5);
             assert_eq!(size, 456);
             assert_eq!(exec_price_e6, 123_456_789);
+            assert_eq!(flags, 0);
+            assert_eq!(client_order_id, 0);
+            assert_eq!(client_tag, [0; 16]);
         }
         other => panic!("unexpected decode: {other:?}"),
     }
 
-    let mut trailing = encode_trade_with_exec_price(4, 5, 456, 123_456_789);
-    trailing.push(1);
-    assert!(
-        Instruction::decode(&trailing).is_err(),
-        "TradeNoCpi must reject malformed trailing bytes after optional exec price"
+    let mut trailing = encode_trade_with_exec_price(4, 5, 456, 123_456_789);
+    trailing.push(0x80);
+    assert!(
+        Instruction::decode(&trailing).is_err(),
+        "TradeNoCpi must reject unknown trade-flag bits after optional exec price"
+    );
+    let mut trailing = encode_trade_with_exec_price(4, 5, 456, 123_456_789);
+    trailing.extend_from_slice(&[1, 0]);
+    assert!(
+        Instruction::decode(&trailing).is_err(),
+        "TradeNoCpi must reject malformed trailing bytes after the flags byte"
+    );
+}
+
+#[test]
+fn test_trade_reduce_only_flag_tail_decodes() {
+    let mut nocpi = encode_trade_with_exec_price(4, 5, 456, 0);
+    nocpi.push(percolator_prog::constants::TRADE_FLAG_REDUCE_ONLY);
+    match Instruction::decode(&nocpi).expect("reduce-only TradeNoCpi must decode") {
+        Instruction::TradeNoCpi { flags, .. } => {
+            assert_eq!(flags, percolator_prog::constants::TRADE_FLAG_REDUCE_ONLY)
+        }
+        other => panic!("unexpected decode: {other:?}"),
+    }
+
+    let mut legacy = encode_trade_cpi(4, 5, 456);
+    encode_u64(0, &mut legacy); // limit_price_e6
+    match Instruction::decode(&legacy).expect("legacy TradeCpi must decode") {
+        Instruction::TradeCpi { flags, .. } => assert_eq!(flags, 0),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    let mut cpi = legacy.clone();
+    cpi.push(percolator_prog::constants::TRADE_FLAG_REDUCE_ONLY);
+    match Instruction::decode(&cpi).expect("reduce-only TradeCpi must decode") {
+        Instruction::TradeCpi { flags, .. } => {
+            assert_eq!(flags, percolator_prog::constants::TRADE_FLAG_REDUCE_ONLY)
+        }
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    let mut bad = legacy;
+    bad.push(0x02);
+    assert!(Instruction::decode(&bad).is_err());
+}
+
+#[test]
+fn test_trade_client_order_id_tail_decodes() {
+    let mut nocpi = encode_trade_with_exec_price(4, 5, 456, 0);
+    nocpi.push(0);
+    encode_u64(0xfeed, &mut nocpi);
+    match Instruction::decode(&nocpi).expect("TradeNoCpi with order id must decode") {
+        Instruction::TradeNoCpi {
+            client_order_id, ..
+        } => assert_eq!(client_order_id, 0xfeed),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+
+    let mut cpi = encode_trade_cpi(4, 5, 456);
+    encode_u64(0, &mut cpi); // limit_price_e6
+    cpi.push(0);
+    encode_u64(7, &mut cpi);
+    match Instruction::decode(&cpi).expect("TradeCpi with order id must decode") {
+        Instruction::TradeCpi {
+            client_order_id, ..
+        } => assert_eq!(client_order_id, 7),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+
+    // A truncated id is malformed, not "absent".
+    cpi.pop();
+    assert!(Instruction::decode(&cpi).is_err());
+}
+
+#[test]
+fn test_client_tag_tail_decodes_and_event_round_trips() {
+    use percolator_prog::ix::{ClientTagEvent, CLIENT_TAG_EVENT_LEN};
+    let tag = *b"acme-frontend-01";
+
+    let mut nocpi = encode_trade_with_exec_price(4, 5, 456, 0);
+    nocpi.push(0);
+    encode_u64(9, &mut nocpi);
+    nocpi.extend_from_slice(&tag);
+    match Instruction::decode(&nocpi).expect("tagged TradeNoCpi must decode") {
+        Instruction::TradeNoCpi {
+            client_order_id,
+            client_tag,
+            ..
+        } => assert_eq!((client_order_id, client_tag), (9, tag)),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+
+    let mut cpi = encode_trade_cpi(4, 5, 456);
+    encode_u64(0, &mut cpi); // limit_price_e6
+    cpi.push(0);
+    encode_u64(0, &mut cpi);
+    cpi.extend_from_slice(&tag);
+    match Instruction::decode(&cpi).expect("tagged TradeCpi must decode") {
+        Instruction::TradeCpi { client_tag, .. } => assert_eq!(client_tag, tag),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    cpi.pop();
+    assert!(
+        Instruction::decode(&cpi).is_err(),
+        "a truncated tag is malformed"
+    );
+
+    // Deposit: empty proof (count 0), then the tag.
+    let mut deposit = vec![3u8];
+    deposit.extend_from_slice(&7u16.to_le_bytes());
+    encode_u64(1_000, &mut deposit);
+    deposit.push(0);
+    deposit.extend_from_slice(&tag);
+    match Instruction::decode(&deposit).expect("tagged deposit must decode") {
+        Instruction::DepositCollateral {
+            allowlist_proof,
+            client_tag,
+            ..
+        } => {
+            assert!(allowlist_proof.is_empty());
+            assert_eq!(client_tag, tag);
+        }
+        other => panic!("unexpected decode: {other:?}"),
+    }
+
+    let event = ClientTagEvent {
+        ix_tag: 6,
+        user_idx: 5,
+        client_tag: tag,
+    };
+    let bytes = event.encode();
+    assert_eq!(bytes.len(), CLIENT_TAG_EVENT_LEN);
+    assert_eq!(&bytes[0..4], b"ctag");
+    assert_eq!(ClientTagEvent::decode(&bytes), Some(event));
+    assert_eq!(ClientTagEvent::decode(&bytes[..22]), None);
+    let mut other = bytes;
+    other[0] = b'x';
+    assert_eq!(ClientTagEvent::decode(&other), None);
+}
+
+#[test]
+fn test_crank_summary_event_roundtrip() {
+    use percolator_prog::ix::{
+        CrankSummaryEvent, CRANK_SUMMARY_CRANKED, CRANK_SUMMARY_EVENT_LEN,
+        CRANK_SUMMARY_PARTIAL_CATCHUP,
+    };
+
+    let event = CrankSummaryEvent {
+        slot: 1_000,
+        crank_slot: 990,
+        funding_rate_e9: -12_345,
+        fees_accrued: 7_000_000,
+        candidates: 3,
+        scanned: 64,
+        liquidations: 1,
+        next_cursor: 65,
+        flags: CRANK_SUMMARY_CRANKED | CRANK_SUMMARY_PARTIAL_CATCHUP,
+    };
+    let bytes = event.encode();
+    assert_eq!(bytes.len(), CRANK_SUMMARY_EVENT_LEN);
+    assert_eq!(&bytes[0..4], b"crnk");
+    assert_eq!(i128::from_le_bytes(bytes[20..36].try_into().unwrap()), -12_345);
+    assert_eq!(bytes[60], 0b11);
+    assert_eq!(CrankSummaryEvent::decode(&bytes), Some(event));
+    assert_eq!(CrankSummaryEvent::decode(&bytes[..60]), None);
+    let mut other = bytes;
+    other[0] = b'c' + 1;
+    assert_eq!(CrankSummaryEvent::decode(&other), None);
+}
+
+#[test]
+fn test_market_metadata_labels_and_decode() {
+    use percolator_prog::constants::MARKET_METADATA_WIRE_LEN;
+    use percolator_prog::state::MarketMetadata;
+
+    assert!(policy::metadata_label_ok(&[0u8; 16]));
+    assert!(policy::metadata_label_ok(b"BTC-PERP\0\0\0\0\0\0\0\0"));
+    assert!(policy::metadata_label_ok(b"0123456789ABCDEF"));
+    assert!(!policy::metadata_label_ok(b"BTC\0PERP\0\0\0\0\0\0\0\0"));
+    assert!(!policy::metadata_label_ok(b"BTC\nPERP\0\0\0\0\0\0\0\0"));
+    assert!(!policy::metadata_label_ok(&[0xC3, 0xA9, 0, 0]));
+
+    let mut m = MarketMetadata {
+        symbol: *b"BTC-PERP\0\0\0\0\0\0\0\0",
+        oracle_description: [0u8; 32],
+        uri_hash: [9u8; 32],
+        base_decimals: 8,
+    };
+    assert!(policy::market_metadata_ok(&m));
+    assert_eq!(m.symbol_str(), "BTC-PERP");
+    assert_eq!(m.oracle_description_str(), "");
+    m.base_decimals = 19;
+    assert!(!policy::market_metadata_ok(&m));
+    m.base_decimals = 8;
+
+    let wire = m.to_wire();
+    assert_eq!(wire.len(), MARKET_METADATA_WIRE_LEN);
+    assert_eq!(MarketMetadata::from_wire(&wire), m);
+
+    let mut data = vec![52u8];
+    data.extend_from_slice(&wire);
+    match Instruction::decode(&data).expect("metadata must decode") {
+        Instruction::SetMarketMetadata { metadata } => assert_eq!(metadata, m),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn test_market_pointer_layout_and_register_decode() {
+    use percolator_prog::constants::MARKET_POINTER_LEN;
+    use percolator_prog::state::MarketPointer;
+
+    let p = MarketPointer {
+        slab: [4u8; 32],
+        bump: 254,
+    };
+    let bytes = p.encode();
+    assert_eq!(bytes.len(), MARKET_POINTER_LEN);
+    assert_eq!(&bytes[0..8], b"PERCMKT1");
+    assert_eq!(MarketPointer::decode(&bytes), Some(p));
+    assert_eq!(MarketPointer::decode(&bytes[..47]), None);
+    assert_eq!(MarketPointer::decode(&[0u8; MARKET_POINTER_LEN]), None);
+
+    match Instruction::decode(&[53, 7, 0]).expect("register must decode") {
+        Instruction::RegisterMarket { index } => assert_eq!(index, 7),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(Instruction::decode(&[53, 7]).is_err());
+    assert!(Instruction::decode(&[53, 7, 0, 0]).is_err());
+}
+
+#[test]
+fn test_init_slab_decode_and_initializing_magic() {
+    use percolator_prog::constants::{INITIALIZING_MAGIC, MAGIC};
+
+    assert_eq!(&INITIALIZING_MAGIC.to_be_bytes(), b"PERCINIT");
+    assert_ne!(INITIALIZING_MAGIC, MAGIC);
+    match Instruction::decode(&[54, 0, 0, 4, 0]).expect("init slab must decode") {
+        Instruction::InitSlab { max_bytes } => assert_eq!(max_bytes, 256 * 1024),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(Instruction::decode(&[54, 0, 0, 4]).is_err());
+    assert!(Instruction::decode(&[54, 0, 0, 4, 0, 0]).is_err());
+}
+
+#[test]
+fn test_tag_namespace_and_exact_payload_lengths() {
+    use percolator_prog::ix::{tag_class, TagClass, LAST_ASSIGNED_TAG, RETIRED_TAGS};
+
+    // Variants without optional tails: (tag, payload length).
+    let fixed: [(u8, usize); 38] = [
+        (2, 72),
+        (9, 8),
+        (13, 0),
+        (14, 34),
+        (17, 16),
+        (19, 1),
+        (20, 0),
+        (21, 2),
+        (23, 8),
+        (27, 10),
+        (28, 10),
+        (29, 0),
+        (30, 2),
+        (32, 33),
+        (33, 8),
+        (34, 2),
+        (35, 32),
+        (36, 32),
+        (37, 4),
+        (38, 8),
+        (39, 8),
+        (40, 0),
+        (42, 32),
+        (43, 32),
+        (44, 0),
+        (45, 8),
+        (46, 34),
+        (47, 24),
+        (48, 8),
+        (50, 10),
+        (51, 18),
+        (54, 4),
+        (55, 0),
+        (56, 8),
+        (57, 16),
+        (58, 0),
+        (59, 32),
+        (60, 32),
+    ];
+    for (tag, len) in fixed {
+        assert_eq!(tag_class(tag), TagClass::Assigned);
+        let mut data = vec![0u8; 1 + len];
+        data[0] = tag;
+        assert!(Instruction::decode(&data).is_ok(), "tag {tag} exact length");
+        data.push(0);
+        assert!(Instruction::decode(&data).is_err(), "tag {tag} trailing byte");
+        if len > 0 {
+            data.truncate(len);
+            assert!(Instruction::decode(&data).is_err(), "tag {tag} short payload");
+        }
+    }
+
+    // Nothing outside the assigned set decodes, whatever follows the tag.
+    let mut assigned = 0;
+    for tag in 0..=255u8 {
+        let class = tag_class(tag);
+        if class == TagClass::Assigned {
+            assigned += 1;
+            continue;
+        }
+        for len in 0..=96 {
+            let mut data = vec![0u8; 1 + len];
+            data[0] = tag;
+            assert!(Instruction::decode(&data).is_err(), "{class:?} tag {tag}");
+        }
+    }
+    assert_eq!(assigned, LAST_ASSIGNED_TAG as usize + 1 - RETIRED_TAGS.len());
+    assert_eq!(tag_class(LAST_ASSIGNED_TAG + 1), TagClass::Unassigned);
+    assert_eq!(tag_class(128), TagClass::Reserved);
+    assert_eq!(tag_class(255), TagClass::Reserved);
+}
+
+#[cfg(feature = "anchor-compat")]
+#[test]
+fn test_anchor_discriminators_map_to_core_tags() {
+    use percolator_prog::ix::{anchor_compat, tag_class, TagClass};
+
+    for (disc, tag, name) in anchor_compat::DISCRIMINATORS {
+        let hash = solana_program::hash::hash(format!("global:{name}").as_bytes());
+        assert_eq!(disc.to_le_bytes(), hash.to_bytes()[..8], "{name}");
+        assert_eq!(tag_class(tag), TagClass::Assigned, "{name}");
+    }
+
+    // Same payload, either prefix.
+    let native = encode_deposit(3, 1_000);
+    assert_eq!(anchor_compat::DISCRIMINATORS[3].2, "deposit_collateral");
+    let mut anchored = anchor_compat::DISCRIMINATORS[3].0.to_le_bytes().to_vec();
+    anchored.extend_from_slice(&native[1..]);
+    match anchor_compat::decode(&anchored).expect("anchor deposit decodes") {
+        Instruction::DepositCollateral {
+            user_idx, amount, ..
+        } => assert_eq!((user_idx, amount), (3, 1_000)),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(anchor_compat::decode(&native).is_ok());
+    anchored.pop();
+    assert!(anchor_compat::decode(&anchored).is_err());
+}
+
+#[test]
+fn test_withdraw_and_close_nonce_tail_decodes() {
+    let mut data = encode_withdraw(3, 500);
+    match Instruction::decode(&data).expect("withdraw without nonce must decode") {
+        Instruction::WithdrawCollateral { nonce, .. } => assert_eq!(nonce, None),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    encode_u64(9, &mut data);
+    match Instruction::decode(&data).expect("withdraw with nonce must decode") {
+        Instruction::WithdrawCollateral {
+            user_idx,
+            amount,
+            nonce,
+        } => assert_eq!((user_idx, amount, nonce), (3, 500, Some(9))),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    data.pop();
+    assert!(Instruction::decode(&data).is_err());
+
+    let mut close = vec![8u8];
+    encode_u16(3, &mut close);
+    encode_u64(0, &mut close);
+    match Instruction::decode(&close).expect("close with nonce must decode") {
+        Instruction::CloseAccount { user_idx, nonce } => {
+            assert_eq!((user_idx, nonce), (3, Some(0)))
+        }
+        other => panic!("unexpected decode: {other:?}"),
+    }
+}
+
+#[test]
+fn test_order_id_ring() {
+    use percolator_prog::constants::ORDER_ID_RING;
+
+    let mut ring = [0u64; ORDER_ID_RING];
+    assert!(!policy::order_id_seen(&ring, 0));
+    policy::order_id_push(&mut ring, 0);
+    assert_eq!(ring, [0; ORDER_ID_RING]);
+
+    for id in 1..=ORDER_ID_RING as u64 {
+        policy::order_id_push(&mut ring, id);
+    }
+    assert!((1..=ORDER_ID_RING as u64).all(|id| policy::order_id_seen(&ring, id)));
+    assert_eq!(ring[0], ORDER_ID_RING as u64);
+
+    // The oldest id falls out of the window.
+    policy::order_id_push(&mut ring, 100);
+    assert!(!policy::order_id_seen(&ring, 1));
+    assert!(policy::order_id_seen(&ring, 100));
+    assert!(policy::order_id_seen(&ring, 2));
+}
+
+#[test]
+fn test_account_limit_policy() {
+    assert!(policy::capital_limit_ok(u128::MAX, 0));
+    assert!(policy::capital_limit_ok(100, 100));
+    assert!(!policy::capital_limit_ok(101, 100));
+
+    // Disabled.
+    assert!(policy::position_limit_ok(0, 1_000, u128::MAX, 0));
+    // Growing within and beyond the limit.
+    assert!(policy::position_limit_ok(0, 1_000, 100, 100));
+    assert!(!policy::position_limit_ok(0, 1_000, 101, 100));
+    assert!(!policy::position_limit_ok(-500, -1_000, 101, 100));
+    // Reductions and non-growing flips pass even above the limit.
+    assert!(policy::position_limit_ok(1_000, 500, 1_000, 100));
+    assert!(policy::position_limit_ok(1_000, -1_000, 1_000, 100));
+    assert!(!policy::position_limit_ok(1_000, -1_001, 1_000, 100));
+
+    let mut data = vec![35u8];
+    encode_u128(5, &mut data);
+    encode_u128(6, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::SetAccountLimits {
+            max_capital: 5,
+            max_position_notional: 6
+        })
+    ));
+    data.pop();
+    assert!(Instruction::decode(&data).is_err());
+}
+
+#[test]
+fn test_fee_split_sums_to_fee() {
+    let bps = [0u16, 1, 2_000, 3_333, 5_000, 9_999, 10_000];
+    let fees = [0u128, 1, 7, 9_999, 10_000, 10_001, 123_456_789, u128::MAX];
+    for &ib in &bps {
+        for &tb in &bps {
+            for &fee in &fees {
+                let (ins, treasury, lp) = policy::fee_split(fee, ib, tb);
+                if ib as u32 + tb as u32 > 10_000 {
+                    assert_eq!((ins, treasury, lp), (fee, 0, 0));
+                    continue;
+                }
+                assert_eq!(ins + treasury + lp, fee, "fee={fee} ib={ib} tb={tb}");
+                // Each routed share is the floor of its exact bps slice.
+                let exact = |b: u16| fee / 10_000 * b as u128 + fee % 10_000 * b as u128 / 10_000;
+                assert_eq!(treasury, exact(tb));
+                assert_eq!(lp, exact(10_000 - ib - tb));
+            }
+        }
+    }
+    assert_eq!(policy::fee_split(1_000, 2_000, 3_000), (200, 300, 500));
+
+    let mut data = vec![37u8];
+    encode_u16(2_000, &mut data);
+    encode_u16(3_000, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::SetFeeSplit {
+            insurance_bps: 2_000,
+            treasury_bps: 3_000
+        })
+    ));
+
+    let mut data = vec![38u8];
+    encode_u64(1_234, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::ClaimProtocolFees { amount: 1_234 })
+    ));
+    data.push(0);
+    assert!(Instruction::decode(&data).is_err());
+}
+
+#[test]
+fn test_epoch_clock_and_pnl() {
+    // Disabled clock never rolls.
+    assert_eq!(policy::epoch_advance(3, 100, 0, u64::MAX), (3, 100));
+    // Before the boundary.
+    assert_eq!(policy::epoch_advance(3, 100, 50, 149), (3, 100));
+    // Exactly on and past it; skipped epochs stay on the grid.
+    assert_eq!(policy::epoch_advance(3, 100, 50, 150), (4, 150));
+    assert_eq!(policy::epoch_advance(3, 100, 50, 275), (6, 250));
+
+    // Deposits are not PnL; withdrawals are not losses.
+    assert_eq!(policy::epoch_pnl(1_500, 1_000, 400), 100);
+    assert_eq!(policy::epoch_pnl(200, 1_000, -700), -100);
+    assert_eq!(policy::epoch_pnl(i128::MAX, 0, 0), i64::MAX);
+    assert_eq!(policy::epoch_pnl(i128::MIN, 0, 0), i64::MIN);
+
+    let mut data = vec![39u8];
+    encode_u64(9_000, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::SetEpochLength { epoch_slots: 9_000 })
+    ));
+}
+
+#[test]
+fn test_withdraw_only_eligibility() {
+    use percolator_prog::constants::WITHDRAW_ONLY_STALENESS_MULTIPLIER as K;
+    // Disabled guard is never eligible.
+    assert!(!policy::withdraw_only_eligible(0, 0, u64::MAX));
+    assert!(!policy::withdraw_only_eligible(
+        100,
+        1_000,
+        1_000 + 100 * K - 1
+    ));
+    assert!(policy::withdraw_only_eligible(100, 1_000, 1_000 + 100 * K));
+    // Clock behind the last crank is not stale.
+    assert!(!policy::withdraw_only_eligible(100, 1_000, 0));
+    // Huge staleness saturates instead of wrapping.
+    assert!(!policy::withdraw_only_eligible(u64::MAX, 0, u64::MAX - 1));
+
+    assert!(matches!(
+        Instruction::decode(&[40u8]),
+        Ok(Instruction::EnterWithdrawOnly)
+    ));
+    assert!(Instruction::decode(&[40u8, 0]).is_err());
+}
+
+#[test]
+fn test_dust_limits_policy() {
+    // Floors off.
+    assert!(policy::dust_fill_ok(1, 1, 0, 0));
+    // Small fill or small remainder is rejected.
+    assert!(!policy::dust_fill_ok(99, 5_000, 100, 1_000));
+    assert!(!policy::dust_fill_ok(-500, 500, 100, 1_000));
+    assert!(policy::dust_fill_ok(-500, -1_500, 100, 1_000));
+    // Flattening always passes, whatever the size.
+    assert!(policy::dust_fill_ok(-7, 0, 100, 1_000));
+
+    assert!(policy::is_dust_position(-999, 1_000));
+    assert!(!policy::is_dust_position(1_000, 1_000));
+    assert!(!policy::is_dust_position(0, 1_000));
+    assert!(!policy::is_dust_position(5, 0));
+
+    let mut data = vec![46u8];
+    data.extend_from_slice(&100u128.to_le_bytes());
+    data.extend_from_slice(&1_000u128.to_le_bytes());
+    data.extend_from_slice(&3u16.to_le_bytes());
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::SetDustLimits {
+            min_trade_abs: 100,
+            min_position_abs: 1_000,
+            sweep_lp_idx: 3,
+        })
+    ));
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn test_circuit_breaker_policy() {
+    assert!(!policy::circuit_breaker_tripped(
+        100_000_000,
+        105_000_000,
+        500
+    ));
+    assert!(policy::circuit_breaker_tripped(
+        100_000_000,
+        105_000_001,
+        500
+    ));
+    assert!(policy::circuit_breaker_tripped(
+        100_000_000,
+        94_999_999,
+        500
+    ));
+    // No anchor yet, or disabled.
+    assert!(!policy::circuit_breaker_tripped(0, 1, 500));
+    assert!(!policy::circuit_breaker_tripped(1, u64::MAX, 0));
+    assert!(policy::circuit_breaker_tripped(u64::MAX, 1, 1));
+
+    let mut data = vec![47u8];
+    encode_u64(500, &mut data);
+    encode_u64(150, &mut data);
+    encode_u64(300, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::SetCircuitBreaker {
+            max_move_bps: 500,
+            window_slots: 150,
+            cooldown_slots: 300,
+        })
+    ));
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn test_funding_clamps_bound_a_dislocation() {
+    use percolator_prog::constants::{MAX_ABS_FUNDING_E9_PER_SLOT, MAX_ACCRUAL_DT_SLOTS};
+    // A 50% oracle dislocation is cut to the 5% premium cap, then to the
+    // per-slot rate cap.
+    let uncapped =
+        policy::funding_rate_e9_from_mark_index(150_000_000, 100_000_000, 1, 100, 500, i64::MAX);
+    assert_eq!(uncapped, Some(50_000_000));
+    let capped =
+        policy::funding_rate_e9_from_mark_index(150_000_000, 100_000_000, 1, 100, 500, 1_000);
+    assert_eq!(capped, Some(1_000));
+    let short_side =
+        policy::funding_rate_e9_from_mark_index(50_000_000, 100_000_000, 1, 100, 500, 1_000);
+    assert_eq!(short_side, Some(-1_000));
+
+    // One accrual at the engine ceiling moves at most 1 bps of notional.
+    assert_eq!(
+        policy::funding_bps_bound(MAX_ABS_FUNDING_E9_PER_SLOT as i64, MAX_ACCRUAL_DT_SLOTS),
+        Some(1)
+    );
+    assert_eq!(policy::funding_bps_bound(1_000, 9_000), Some(90));
+    assert_eq!(policy::funding_bps_bound(1, 1), Some(1));
+    assert_eq!(policy::funding_bps_bound(0, u64::MAX), Some(0));
+    assert_eq!(policy::funding_bps_bound(-1, 1), None);
+}
+
+#[test]
+fn test_liq_watch_policy() {
+    // 10 units long at $100 (notional 1_000), equity 200, maintenance 50
+    // at 5%: (150 / 10) * 10_000 / 10_500 = $14.285714.
+    let q = 10 * percolator::POS_SCALE;
+    let d = policy::liq_distance_e6(200_000_000, 50_000_000, q, 500);
+    assert_eq!(d, 14_285_714);
+    assert_eq!(policy::liq_distance_e6(50_000_000, 50_000_000, q, 500), 0);
+    assert_eq!(policy::liq_distance_e6(-1, 0, q, 500), 0);
+    assert_eq!(policy::liq_distance_e6(1, 0, 0, 500), u64::MAX);
+
+    // 10% band on a $100 anchor = $10, plus the refresh price's offset.
+    assert!(policy::liq_watch_clear_ok(
+        d,
+        100_000_000,
+        100_000_000,
+        1_000
+    ));
+    assert!(!policy::liq_watch_clear_ok(
+        d,
+        95_000_000,
+        100_000_000,
+        1_000
+    ));
+    assert!(!policy::liq_watch_clear_ok(u64::MAX, 100_000_000, 0, 1_000));
+
+    assert!(policy::liq_watch_covers(100_000_000, 105_000_000, 0, 1_000));
+    assert!(!policy::liq_watch_covers(
+        100_000_000,
+        110_000_000,
+        0,
+        1_000
+    ));
+    // 500 bps of funding on the higher price eats the rest of the band.
+    assert!(!policy::liq_watch_covers(
+        100_000_000,
+        105_000_000,
+        500,
+        1_000
+    ));
+    assert!(!policy::liq_watch_covers(0, 100_000_000, 0, 1_000));
+}
+
+#[test]
+fn test_liq_watch_state_round_trip() {
+    use percolator_prog::constants::SLAB_LEN;
+    let mut data = vec![0u8; SLAB_LEN];
+    let w = state::LiqWatch {
+        anchor_price_e6: 138_000_000,
+        anchor_slot: 42,
+        cursor: 7,
+    };
+    state::write_liq_watch(&mut data, &w);
+    assert_eq!(state::read_liq_watch(&data), w);
+    state::set_watched(&mut data, 65, true);
+    assert!(state::is_watched(&data, 65));
+    assert!(!state::is_watched(&data, 64));
+    state::set_watched(&mut data, 65, false);
+    assert!(!state::is_watched(&data, 65));
+    assert_eq!(
+        state::read_liq_watch(&data),
+        w,
+        "bits never touch the header"
+    );
+}
+
+#[test]
+fn test_trade_rate_policy() {
+    // Off, or no previous fill.
+    assert!(policy::trade_rate_ok(100, 100, 0));
+    assert!(policy::trade_rate_ok(0, 5, 10));
+    assert!(!policy::trade_rate_ok(100, 109, 10));
+    assert!(policy::trade_rate_ok(100, 110, 10));
+    assert!(!policy::trade_rate_ok(u64::MAX - 1, u64::MAX - 1, 10));
+
+    let mut data = vec![48u8];
+    encode_u64(20, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::SetTradeRateLimit {
+            min_slots_between_trades: 20,
+        })
+    ));
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn test_liquidator_window_policy() {
+    use percolator_prog::constants::{MAX_LIQUIDATION_WINDOW_SLOTS, MAX_LIQUIDATORS};
+    let a = [1u8; 32];
+    let b = [2u8; 32];
+    assert!(policy::liquidator_window_valid(0, &[]));
+    assert!(policy::liquidator_window_valid(50, &[a, b]));
+    // Keys may be kept while the window is off.
+    assert!(policy::liquidator_window_valid(0, &[a]));
+    assert!(!policy::liquidator_window_valid(50, &[]));
+    assert!(!policy::liquidator_window_valid(50, &[a, a]));
+    assert!(!policy::liquidator_window_valid(50, &[[0u8; 32]]));
+    assert!(!policy::liquidator_window_valid(
+        MAX_LIQUIDATION_WINDOW_SLOTS + 1,
+        &[a]
+    ));
+    let too_many: Vec<[u8; 32]> = (0..=MAX_LIQUIDATORS as u8).map(|i| [i + 1; 32]).collect();
+    assert!(!policy::liquidator_window_valid(50, &too_many));
+
+    // Off: open to all. Unflagged: window not started.
+    assert!(policy::liquidation_open(0, 100, 0));
+    assert!(!policy::liquidation_open(0, 100, 50));
+    assert!(!policy::liquidation_open(100, 149, 50));
+    assert!(policy::liquidation_open(100, 150, 50));
+    assert!(!policy::liquidation_open(u64::MAX, u64::MAX - 1, 50));
+
+    let mut data = vec![49u8];
+    encode_u64(50, &mut data);
+    data.push(2);
+    data.extend_from_slice(&a);
+    data.extend_from_slice(&b);
+    match Instruction::decode(&data).expect("liquidator window must decode") {
+        Instruction::SetLiquidatorWindow {
+            window_slots,
+            liquidators,
+        } => assert_eq!((window_slots, liquidators), (50, vec![a, b])),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+    let mut data = vec![49u8];
+    encode_u64(50, &mut data);
+    data.push(MAX_LIQUIDATORS as u8 + 1);
+    assert!(Instruction::decode(&data).is_err());
+}
+
+#[test]
+fn test_backstop_decode_and_state() {
+    use percolator_prog::constants::{
+        BACKSTOP_LEN, BACKSTOP_OFF, MAX_BACKSTOP_DEPOSITORS, MIN_BACKSTOP_WITHDRAW_DELAY_SLOTS,
+    };
+    use percolator_prog::state::{
+        clear_backstop_depositors, find_backstop_depositor, read_backstop, read_backstop_depositor,
+        write_backstop, write_backstop_depositor, Backstop, BackstopDepositor,
+    };
+
+    let mut data = vec![50u8];
+    data.extend_from_slice(&2_500u16.to_le_bytes());
+    data.extend_from_slice(&9_000u64.to_le_bytes());
+    match Instruction::decode(&data).expect("backstop must decode") {
+        Instruction::SetBackstop {
+            share_bps,
+            withdraw_delay_slots,
+        } => assert_eq!((share_bps, withdraw_delay_slots), (2_500, 9_000)),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+    let mut data = vec![56u8];
+    encode_u64(1_000, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::BackstopDeposit { amount: 1_000 })
+    ));
+    let mut data = vec![57u8];
+    data.extend_from_slice(&7u128.to_le_bytes());
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::BackstopRequestWithdraw { shares: 7 })
+    ));
+    assert!(matches!(
+        Instruction::decode(&[58]),
+        Ok(Instruction::BackstopWithdraw)
+    ));
+
+    let mut slab = vec![0u8; BACKSTOP_OFF + BACKSTOP_LEN];
+    assert!(!read_backstop(&slab).active());
+    assert_eq!(
+        read_backstop(&slab).effective_withdraw_delay(),
+        MIN_BACKSTOP_WITHDRAW_DELAY_SLOTS,
+        "the floor holds before the admin sets a delay"
+    );
+    let b = Backstop {
+        vault_idx: 3,
+        share_bps: 2_500,
+        generation: 7,
+        rewards_paid: u128::MAX - 1,
+        total_shares: 1_000,
+        withdraw_delay_slots: 9_000,
+        closed_units: 42,
+    };
+    write_backstop(&mut slab, &b);
+    assert_eq!(read_backstop(&slab), b);
+    assert!(b.active());
+    assert!(!Backstop { share_bps: 0, ..b }.active());
+    assert!(!Backstop { generation: 0, ..b }.active());
+
+    // Depositors: an owner finds its own entry, newcomers the first free
+    // one, and a full table turns newcomers away.
+    let alice = [1u8; 32];
+    let bob = [2u8; 32];
+    assert_eq!(find_backstop_depositor(&slab, &alice), Some(0));
+    let d = BackstopDepositor {
+        owner: alice,
+        shares: 10,
+        pending_shares: 5,
+        request_slot: 99,
+    };
+    write_backstop_depositor(&mut slab, 0, &d);
+    assert_eq!(read_backstop_depositor(&slab, 0), d);
+    assert_eq!(find_backstop_depositor(&slab, &alice), Some(0));
+    assert_eq!(find_backstop_depositor(&slab, &bob), Some(1));
+    for i in 1..MAX_BACKSTOP_DEPOSITORS {
+        let other = BackstopDepositor {
+            owner: [i as u8 + 10; 32],
+            pending_shares: 1,
+            ..BackstopDepositor::default()
+        };
+        write_backstop_depositor(&mut slab, i, &other);
+    }
+    assert_eq!(find_backstop_depositor(&slab, &bob), None);
+    assert_eq!(find_backstop_depositor(&slab, &alice), Some(0));
+    clear_backstop_depositors(&mut slab);
+    assert_eq!(find_backstop_depositor(&slab, &bob), Some(0));
+    assert_eq!(read_backstop(&slab), b, "clearing keeps the vault header");
+}
+
+#[test]
+fn test_hyperp_pusher_decode_and_state() {
+    use percolator_prog::constants::{HYPERP_PUSHERS_LEN, HYPERP_PUSHERS_OFF};
+    use percolator_prog::state::{
+        find_hyperp_pusher, read_hyperp_pusher, record_hyperp_push, write_hyperp_pusher,
+        HyperpPusher,
+    };
+
+    let key = [7u8; 32];
+    for (tag, add) in [(59u8, true), (60u8, false)] {
+        let mut data = vec![tag];
+        data.extend_from_slice(&key);
+        match Instruction::decode(&data).expect("pusher ix must decode") {
+            Instruction::AddHyperpPusher { pusher } if add => {
+                assert_eq!(pusher.to_bytes(), key)
+            }
+            Instruction::RemoveHyperpPusher { pusher } if !add => {
+                assert_eq!(pusher.to_bytes(), key)
+            }
+            other => panic!("unexpected decode: {other:?}"),
+        }
+        assert!(Instruction::decode(&data[..32]).is_err());
+    }
+
+    let mut slab = vec![0u8; HYPERP_PUSHERS_OFF + HYPERP_PUSHERS_LEN];
+    let authority = [1u8; 32];
+    assert_eq!(find_hyperp_pusher(&slab, &authority, &authority), Some(0));
+    assert_eq!(find_hyperp_pusher(&slab, &authority, &key), None);
+    write_hyperp_pusher(
+        &mut slab,
+        2,
+        &HyperpPusher {
+            key,
+            ..HyperpPusher::default()
+        },
+    );
+    assert_eq!(find_hyperp_pusher(&slab, &authority, &key), Some(2));
+    assert_eq!(
+        find_hyperp_pusher(&slab, &[0u8; 32], &key),
+        None,
+        "a burned authority disables every pusher"
+    );
+
+    record_hyperp_push(&mut slab, 2, key, 10);
+    record_hyperp_push(&mut slab, 2, key, 12);
+    assert_eq!(
+        read_hyperp_pusher(&slab, 2),
+        HyperpPusher {
+            key,
+            last_push_slot: 12,
+            pushes: 2,
+        }
+    );
+    record_hyperp_push(&mut slab, 0, authority, 20);
+    record_hyperp_push(&mut slab, 0, [9u8; 32], 21);
+    assert_eq!(
+        read_hyperp_pusher(&slab, 0),
+        HyperpPusher {
+            key: [9u8; 32],
+            last_push_slot: 21,
+            pushes: 1,
+        },
+        "a rotated authority starts a fresh count"
+    );
+}
+
+#[test]
+fn test_stale_fallback_haircut_ramp() {
+    use percolator_prog::constants::MAX_STALE_HAIRCUT_BPS;
+    use percolator_prog::state::StaleFallback;
+
+    assert!(policy::stale_fallback_valid(100, 2_000, 1_000));
+    assert!(policy::stale_fallback_valid(100, MAX_STALE_HAIRCUT_BPS, 0));
+    assert!(!policy::stale_fallback_valid(0, 2_000, 1_000));
+    assert!(!policy::stale_fallback_valid(1_000, 2_000, 1_000));
+    assert!(!policy::stale_fallback_valid(
+        100,
+        MAX_STALE_HAIRCUT_BPS + 1,
+        1_000
+    ));
+
+    // Nothing through the grace window, then linear up to the cap.
+    assert_eq!(policy::stale_haircut_bps(100, 100, 400, 2_000), 0);
+    assert_eq!(policy::stale_haircut_bps(300, 100, 400, 2_000), 1_000);
+    assert_eq!(policy::stale_haircut_bps(500, 100, 400, 2_000), 2_000);
+    assert_eq!(policy::stale_haircut_bps(u64::MAX, 100, 400, 2_000), 2_000);
+    assert_eq!(policy::stale_haircut_bps(101, 100, 0, 2_000), 2_000);
+
+    let f = StaleFallback {
+        grace_slots: 100,
+        ramp_slots: 400,
+        max_haircut_bps: 2_000,
+    };
+    assert!(!f.engaged(1_100, 1_000));
+    assert!(f.engaged(1_101, 1_000));
+    assert_eq!(f.haircut_bps(1_300, 1_000), 1_000);
+    assert!(!StaleFallback::default().engaged(u64::MAX, 0));
+    assert_eq!(StaleFallback::default().haircut_bps(u64::MAX, 0), 0);
+
+    let mut data = vec![51u8];
+    data.extend_from_slice(&100u64.to_le_bytes());
+    data.extend_from_slice(&400u64.to_le_bytes());
+    data.extend_from_slice(&2_000u16.to_le_bytes());
+    match Instruction::decode(&data).expect("stale fallback must decode") {
+        Instruction::SetStaleFallback {
+            grace_slots,
+            ramp_slots,
+            max_haircut_bps,
+        } => assert_eq!(
+            (grace_slots, ramp_slots, max_haircut_bps),
+            (100, 400, 2_000)
+        ),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    assert!(Instruction::decode(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn test_history_ring_wraps_oldest_first() {
+    use percolator_prog::constants::{HISTORY_CAP, HISTORY_LEN, HISTORY_OFF};
+    use percolator_prog::state::{
+        history_region, history_sample, push_history_sample, read_history_cursor, HistorySample,
+    };
+    assert_eq!(policy::history_ring_index(0, 0, 4, 0), 0);
+    assert_eq!(policy::history_ring_index(3, 3, 4, 0), 0);
+    assert_eq!(policy::history_ring_index(1, 4, 4, 0), 1);
+    assert_eq!(policy::history_ring_index(1, 4, 4, 3), 0);
+
+    let mut data = vec![0u8; HISTORY_OFF + HISTORY_LEN];
+    for slot in 1..=(HISTORY_CAP as u64 + 3) {
+        push_history_sample(
+            &mut data,
+            &HistorySample {
+                slot,
+                funding_rate_e9: -(slot as i64),
+                oi_short_q: u128::MAX,
+                ..Default::default()
+            },
+        );
+    }
+    let region = history_region(&data);
+    assert_eq!(read_history_cursor(region), (3, HISTORY_CAP));
+    let oldest = history_sample(region, 0);
+    assert_eq!(oldest.slot, 4);
+    assert_eq!(oldest.funding_rate_e9, -4);
+    assert_eq!(oldest.oi_short_q, u128::MAX);
+    assert_eq!(
+        history_sample(region, HISTORY_CAP - 1).slot,
+        HISTORY_CAP as u64 + 3
+    );
+}
+
+#[test]
+fn test_lp_metrics_policy() {
+    assert_eq!(policy::lp_utilization_bps(0, 0), 0);
+    assert_eq!(policy::lp_utilization_bps(5_000, 10_000), 5_000);
+    assert_eq!(policy::lp_utilization_bps(30_000, 10_000), 30_000);
+    assert_eq!(policy::lp_utilization_bps(1, -5), u64::MAX);
+    assert_eq!(policy::lp_utilization_bps(u128::MAX, 1), u64::MAX);
+
+    // User buys 1.0 at 101 with oracle 100: LP sold 1 above.
+    assert_eq!(
+        policy::lp_spread_pnl(1_000_000, 101_000_000, 100_000_000),
+        1_000_000
+    );
+    // User sells below oracle: LP bought cheap.
+    assert_eq!(
+        policy::lp_spread_pnl(-1_000_000, 99_000_000, 100_000_000),
+        1_000_000
+    );
+    // LP gave up edge.
+    assert_eq!(
+        policy::lp_spread_pnl(1_000_000, 99_000_000, 100_000_000),
+        -1_000_000
+    );
+    assert_eq!(
+        policy::lp_spread_pnl(-2_000_000, 100_000_000, 100_000_000),
+        0
+    );
+}
+
+#[test]
+fn test_admin_multisig_policy() {
+    let keys = [[1u8; 32], [2u8; 32], [3u8; 32]];
+    assert!(policy::admin_multisig_valid(2, &keys));
+    assert!(policy::admin_multisig_valid(3, &keys));
+    assert!(policy::admin_multisig_valid(0, &[]), "clears the set");
+    assert!(!policy::admin_multisig_valid(0, &keys));
+    assert!(!policy::admin_multisig_valid(4, &keys));
+    assert!(!policy::admin_multisig_valid(1, &[[1u8; 32], [1u8; 32]]));
+    assert!(!policy::admin_multisig_valid(1, &[[0u8; 32]]));
+    assert!(!policy::admin_multisig_valid(1, &[[7u8; 32]; 6]));
+
+    assert!(policy::admin_threshold_met(0b101, 2));
+    assert!(!policy::admin_threshold_met(0b100, 2));
+    assert!(!policy::admin_threshold_met(0b111, 0));
+
+    // Bound to the slab, the ordered account keys and the exact bytes.
+    let metas = [[1u8; 32], [9u8; 32]];
+    let h = policy::admin_action_hash(&[9u8; 32], &metas, &[34, 1]);
+    assert_ne!(h, policy::admin_action_hash(&[8u8; 32], &metas, &[34, 1]));
+    assert_ne!(h, policy::admin_action_hash(&[9u8; 32], &metas, &[34, 2]));
+    let swapped = [[9u8; 32], [1u8; 32]];
+    assert_ne!(h, policy::admin_action_hash(&[9u8; 32], &swapped, &[34, 1]));
+    let other_dest = [[2u8; 32], [9u8; 32]];
+    assert_ne!(h, policy::admin_action_hash(&[9u8; 32], &other_dest, &[34, 1]));
+    // A key cannot migrate into the data and still match.
+    let mut shifted = vec![34u8];
+    shifted.extend_from_slice(&[9u8; 32]);
+    assert_ne!(
+        policy::admin_action_hash(&[9u8; 32], &metas, &[34]),
+        policy::admin_action_hash(&[9u8; 32], &metas[..1], &shifted)
+    );
+
+    let mut data = vec![41u8, 2, 3];
+    for k in &keys {
+        data.extend_from_slice(k);
+    }
+    match Instruction::decode(&data) {
+        Ok(Instruction::SetAdminMultisig { threshold, signers }) => {
+            assert_eq!(threshold, 2);
+            assert_eq!(signers, keys.to_vec());
+        }
+        _ => panic!("decode SetAdminMultisig"),
+    }
+    let mut too_many = vec![41u8, 1, 6];
+    too_many.extend_from_slice(&[7u8; 32 * 6]);
+    assert!(Instruction::decode(&too_many).is_err());
+
+    let mut data = vec![42u8];
+    data.extend_from_slice(&h);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::ApproveAdminAction { action_hash }) if action_hash == h
+    ));
+
+    let mut data = vec![43u8];
+    data.extend_from_slice(&[5u8; 32]);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::ProposeAdmin { new_admin }) if new_admin.to_bytes() == [5u8; 32]
+    ));
+    assert!(matches!(
+        Instruction::decode(&[44u8]),
+        Ok(Instruction::AcceptAdmin)
+    ));
+}
+
+#[test]
+fn test_allowlist_merkle_proofs() {
+    use solana_sdk::hash::hashv;
+
+    let pair = |a: [u8; 32], b: [u8; 32]| {
+        let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+        hashv(&[&lo, &hi]).to_bytes()
+    };
+    let owners: Vec<[u8; 32]> = (1..=3u8).map(|i| [i; 32]).collect();
+    let leaves: Vec<[u8; 32]> = owners.iter().map(policy::allowlist_leaf).collect();
+    // Three leaves: ((l0, l1), l2).
+    let n01 = pair(leaves[0], leaves[1]);
+    let root = pair(n01, leaves[2]);
+
+    assert!(policy::allowlist_ok(
+        &root,
+        &owners[0],
+        &[leaves[1], leaves[2]]
+    ));
+    assert!(policy::allowlist_ok(
+        &root,
+        &owners[1],
+        &[leaves[0], leaves[2]]
+    ));
+    assert!(policy::allowlist_ok(&root, &owners[2], &[n01]));
+    assert!(!policy::allowlist_ok(&root, &owners[2], &[]));
+    assert!(!policy::allowlist_ok(&root, &[9; 32], &[n01]));
+    assert!(!policy::allowlist_ok(
+        &root,
+        &owners[0],
+        &[leaves[2], leaves[1], leaves[1]]
+    ));
+    // Single-owner list: the root is the leaf itself.
+    assert!(policy::allowlist_ok(&leaves[0], &owners[0], &[]));
+    // Zero root = open market.
+    assert!(policy::allowlist_ok(&[0; 32], &[9; 32], &[]));
+
+    let mut data = encode_init_user(100);
+    data.push(2);
+    encode_bytes32(&leaves[1], &mut data);
+    encode_bytes32(&leaves[2], &mut data);
+    match Instruction::decode(&data).expect("InitUser with proof must decode") {
+        Instruction::InitUser {
+            fee_payment,
+            allowlist_proof,
+        } => {
+            assert_eq!(fee_payment, 100);
+            assert_eq!(allowlist_proof, vec![leaves[1], leaves[2]]);
+        }
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    data.pop();
+    assert!(Instruction::decode(&data).is_err());
+
+    let mut deep = encode_init_user(100);
+    deep.push(percolator_prog::constants::MAX_ALLOWLIST_PROOF_LEN as u8 + 1);
+    for _ in 0..=percolator_prog::constants::MAX_ALLOWLIST_PROOF_LEN {
+        encode_bytes32(&[0; 32], &mut deep);
+    }
+    assert!(Instruction::decode(&deep).is_err());
+}
+
+#[test]
+fn test_reduce_only_clamp() {
+    // Partial reduce passes through unchanged.
+    assert_eq!(policy::reduce_only_clamp(100, -40), Some(-40));
+    assert_eq!(policy::reduce_only_clamp(-100, 40), Some(40));
+    // Crossing through zero is clamped to a full close.
+    assert_eq!(policy::reduce_only_clamp(100, -250), Some(-100));
+    assert_eq!(policy::reduce_only_clamp(-100, 250), Some(100));
+    // Growing, opening from flat, or zero size cannot reduce.
+    assert_eq!(policy::reduce_only_clamp(100, 1), None);
+    assert_eq!(policy::reduce_only_clamp(-100, -1), None);
+    assert_eq!(policy::reduce_only_clamp(0, 5), None);
+    assert_eq!(policy::reduce_only_clamp(100, 0), None);
+}
+
+#[test]
+fn test_split_fill_at_the_crossover() {
+    use policy::{split_fill, FillSplit};
+    let split = |close_q, open_q| FillSplit { close_q, open_q };
+    assert_eq!(split_fill(100, -40), split(-40, 0));
+    assert_eq!(split_fill(100, -100), split(-100, 0));
+    // Crossing zero closes everything and opens the remainder.
+    assert_eq!(split_fill(100, -250), split(-100, -150));
+    assert_eq!(split_fill(-100, 250), split(100, 150));
+    assert_eq!(split_fill(100, 30), split(0, 30));
+    assert_eq!(split_fill(0, -5), split(0, -5));
+    assert_eq!(split_fill(i128::MIN, 5), split(5, 0));
+    assert_eq!(split_fill(i128::MAX, i128::MIN), split(-i128::MAX, -1));
+}
+
+#[test]
+fn test_cost_basis_realized_and_unrealized_pnl() {
+    use policy::{settle_cost_basis, unrealized_pnl, CostBasis};
+    let u = percolator::POS_SCALE as i128;
+    let e6 = |p: u64| p * 1_000_000;
+    let basis = |q: i128, p: u64| CostBasis {
+        position_q: q * u,
+        entry_price_e6: p,
+    };
+    let long = basis(10, e6(100));
+
+    // Partial close realizes on the closed part at the old entry.
+    let (after, realized) = settle_cost_basis(&long, -4 * u, e6(90)).unwrap();
+    assert_eq!((after, realized), (basis(6, e6(100)), -40_000_000));
+
+    // Flip: close 10 at +10, reopen the other 15 short at the fill price.
+    let mark = e6(105);
+    let (after, realized) = settle_cost_basis(&long, -25 * u, e6(110)).unwrap();
+    assert_eq!((after, realized), (basis(-15, e6(110)), 100_000_000));
+    assert_eq!(unrealized_pnl(&long, mark), 50_000_000);
+    assert_eq!(unrealized_pnl(&after, mark), 75_000_000);
+    // Realized plus the change in unrealized is the fill's PnL against the
+    // mark: -25 * (105 - 110).
+    assert_eq!(
+        realized + unrealized_pnl(&after, mark) - unrealized_pnl(&long, mark),
+        125_000_000
+    );
+
+    // Growing averages the entry, against the holder when it is inexact.
+    let (after, realized) = settle_cost_basis(&long, 5 * u, e6(106)).unwrap();
+    assert_eq!((after, realized), (basis(15, e6(102)), 0));
+    let (after, _) = settle_cost_basis(&basis(1, e6(100)), 2 * u, e6(101)).unwrap();
+    assert_eq!(after.entry_price_e6, 100_666_667);
+    let (after, _) = settle_cost_basis(&basis(-1, e6(100)), -2 * u, e6(101)).unwrap();
+    assert_eq!(after.entry_price_e6, 100_666_666);
+
+    // A full close leaves a flat basis.
+    let (after, realized) = settle_cost_basis(&long, -10 * u, e6(120)).unwrap();
+    assert_eq!((after, realized), (CostBasis::default(), 200_000_000));
+    assert_eq!(settle_cost_basis(&long, 0, 0), Some((long, 0)));
+    assert_eq!(settle_cost_basis(&long, u, 0), None);
+
+    // Round trip through two flips: everything realized once flat equals
+    // the fills' cash flow, -sum(size * price).
+    let mut b = CostBasis::default();
+    let mut total = 0;
+    for (q, p) in [(10, 100), (10, 110), (-30, 120), (4, 100), (6, 130)] {
+        let (next, realized) = settle_cost_basis(&b, q * u, e6(p)).unwrap();
+        b = next;
+        total += realized;
+    }
+    assert_eq!(b, CostBasis::default());
+    assert_eq!(total, 320_000_000);
+}
+
+#[test]
+fn test_pnl_ledger_reconcile_and_funding() {
+    use policy::{funding_index_advance, funding_owed, reconcile_cost_basis, CostBasis};
+    let u = percolator::POS_SCALE as i128;
+    let e6 = |p: u64| p * 1_000_000;
+    let basis = |q: i128, p: u64| CostBasis {
+        position_q: q * u,
+        entry_price_e6: p,
+    };
+    let long = basis(10, e6(100));
+
+    // Changes made outside fills: a reduction keeps the entry, a flip or
+    // an increase is priced at the given price, flat clears it.
+    assert_eq!(reconcile_cost_basis(&long, 10 * u, e6(90)), long);
+    assert_eq!(
+        reconcile_cost_basis(&long, 4 * u, e6(90)),
+        basis(4, e6(100))
+    );
+    assert_eq!(
+        reconcile_cost_basis(&long, -3 * u, e6(90)),
+        basis(-3, e6(90))
     );
+    assert_eq!(
+        reconcile_cost_basis(&long, 15 * u, e6(106)),
+        basis(15, e6(102))
+    );
+    assert_eq!(reconcile_cost_basis(&long, 0, e6(90)), CostBasis::default());
+    assert_eq!(
+        reconcile_cost_basis(&CostBasis::default(), 2 * u, e6(95)),
+        basis(2, e6(95))
+    );
+
+    // 1e-6 per slot on a price of 100 for 50 slots.
+    let index = funding_index_advance(0, e6(100), 1_000, 50);
+    assert_eq!(index, 5_000_000_000_000);
+    assert_eq!(funding_index_advance(index, e6(100), -1_000, 50), 0);
+    assert_eq!(funding_index_advance(i128::MAX - 1, 1, 1, 10), i128::MAX);
+    // 10 contracts pay 10 * 100 * 1e-6 * 50; the short side receives it.
+    assert_eq!(funding_owed(10 * u, 0, index), 50_000);
+    assert_eq!(funding_owed(-10 * u, 0, index), -50_000);
+    assert_eq!(funding_owed(-10 * u, index, 0), 50_000);
+    assert_eq!(funding_owed(0, 0, index), 0);
+    // Payments round up, receipts down.
+    assert_eq!(funding_owed(1, 0, 1), 1);
+    assert_eq!(funding_owed(-1, 0, 1), 0);
+    assert_eq!(funding_owed(-1, 1, 0), 1);
+    assert_eq!(funding_owed(1, 1, 0), 0);
 }
 
 fn encode_update_admin(new_admin: &Pubkey) -> Vec<u8> {
@@ -579,6 +1864,41 @@ fn test_matcher_zero_fill_with_oracle_price_is_accepted() {
     );
 }
 
+#[test]
+fn test_matcher_caps_header() {
+    use percolator_prog::matcher_abi::{
+        read_matcher_caps, MatcherCaps, CAP_IMPACT_PRICING, CAP_PARTIAL_FILL, CTX_CAPS_OFFSET,
+    };
+
+    let mut ctx = vec![0u8; percolator_prog::constants::MATCHER_CONTEXT_LEN];
+    // No header: treated as a pre-capability matcher, unrestricted.
+    assert_eq!(read_matcher_caps(&ctx).unwrap(), MatcherCaps::LEGACY);
+    assert!(read_matcher_caps(&ctx[..CTX_CAPS_OFFSET + 23]).is_err());
+
+    // A newer header version still reads as its v1 prefix.
+    ctx[CTX_CAPS_OFFSET..CTX_CAPS_OFFSET + 4].copy_from_slice(&2u32.to_le_bytes());
+    ctx[CTX_CAPS_OFFSET + 4..CTX_CAPS_OFFSET + 8]
+        .copy_from_slice(&(CAP_IMPACT_PRICING | 0x100).to_le_bytes());
+    ctx[CTX_CAPS_OFFSET + 8..CTX_CAPS_OFFSET + 24].copy_from_slice(&500u128.to_le_bytes());
+    let caps = read_matcher_caps(&ctx).unwrap();
+    assert_eq!(caps.version, 2);
+    assert_eq!(caps.max_fill_abs, 500);
+    assert_eq!(caps.flags & CAP_PARTIAL_FILL, 0);
+
+    // Pre-CPI: oversize requests need partial fills and no fill-or-kill.
+    assert!(policy::matcher_request_ok(0, 0, u128::MAX, true));
+    assert!(policy::matcher_request_ok(0, 500, 500, true));
+    assert!(!policy::matcher_request_ok(0, 500, 501, false));
+    assert!(policy::matcher_request_ok(CAP_PARTIAL_FILL, 500, 501, false));
+    assert!(!policy::matcher_request_ok(CAP_PARTIAL_FILL, 500, 501, true));
+
+    // Post-CPI: the fill keeps the declared limits.
+    assert!(policy::matcher_fill_within_caps(CAP_PARTIAL_FILL, 500, 500, 900));
+    assert!(!policy::matcher_fill_within_caps(CAP_PARTIAL_FILL, 500, 501, 900));
+    assert!(!policy::matcher_fill_within_caps(0, 0, 400, 900));
+    assert!(policy::matcher_fill_within_caps(0, 0, 900, 900));
+}
+
 #[test]
 fn test_external_oracle_flat_market_uses_raw_target() {
     let mut config = state::MarketConfig::zeroed();
@@ -619,6 +1939,99 @@ fn test_external_oracle_with_open_interest_respects_zero_dt_clamp() {
     assert_eq!(config.oracle_target_price_e6, 120_000_000);
 }
 
+#[test]
+fn test_external_oracle_reject_jumps_flag() {
+    let reject = percolator_prog::constants::ORACLE_FLAG_REJECT_JUMPS;
+
+    // 1 bps/slot over 10 slots allows 0.1%: 100.1 passes, 100.2 does not.
+    let mut config = state::MarketConfig::zeroed();
+    config.oracle_leg_flags = reject;
+    let (price, _) = oracle::clamp_external_price(
+        &mut config,
+        Ok((100_100_000, 1, true)),
+        100_000_000,
+        1,
+        10,
+        true,
+    )
+    .unwrap();
+    assert_eq!(price, 100_100_000);
+
+    let mut config = state::MarketConfig::zeroed();
+    config.oracle_leg_flags = reject;
+    let err = oracle::clamp_external_price(
+        &mut config,
+        Ok((100_200_000, 1, true)),
+        100_000_000,
+        1,
+        10,
+        true,
+    )
+    .unwrap_err();
+    assert_eq!(err, PercolatorError::OracleInvalid.into());
+    assert_eq!(
+        config.oracle_target_price_e6, 0,
+        "rejected print is not adopted"
+    );
+
+    // Without the flag the same print is clamped, not rejected.
+    let mut config = state::MarketConfig::zeroed();
+    let (price, _) = oracle::clamp_external_price(
+        &mut config,
+        Ok((100_200_000, 1, true)),
+        100_000_000,
+        1,
+        10,
+        true,
+    )
+    .unwrap();
+    assert_eq!(price, 100_100_000);
+
+    // Flag is accepted on external markets of any leg count, never on Hyperp.
+    let leg1 = [0x01u8; 32];
+    let zero = [0u8; 32];
+    assert!(oracle::oracle_leg_config_ok(
+        false, 1, reject, &leg1, &zero, &zero
+    ));
+    assert!(!oracle::oracle_leg_config_ok(
+        true, 1, reject, &zero, &zero, &zero
+    ));
+}
+
+#[test]
+fn test_oracle_jump_exceeds_cap_bounds() {
+    assert!(!policy::oracle_jump_exceeds_cap(100, 101, 100, 1));
+    assert!(policy::oracle_jump_exceeds_cap(100, 102, 100, 1));
+    // dt = 0 is held to one slot, not zero.
+    assert!(!policy::oracle_jump_exceeds_cap(100, 99, 100, 0));
+    // No anchor or disabled cap never rejects.
+    assert!(!policy::oracle_jump_exceeds_cap(0, 1_000_000, 1, 1));
+    assert!(!policy::oracle_jump_exceeds_cap(100, 1_000_000, 0, 1));
+}
+
+/// Pinned examples for the single engine-price normalization step
+/// (invert, then unit-scale) shared by every price ingress.
+#[test]
+fn test_to_engine_price_inversion_examples() {
+    // SOL/USD $150 inverted → USD/SOL 0.006666 (floored).
+    assert_eq!(policy::to_engine_price(150_000_000, 1, 0), Some(6_666));
+    assert_eq!(policy::to_engine_price(2_000_000, 1, 0), Some(500_000));
+    // $1 is the inversion fixed point.
+    assert_eq!(policy::to_engine_price(1_000_000, 1, 0), Some(1_000_000));
+    assert_eq!(policy::invert_price_e6(4_000_000, 1), Some(250_000));
+    assert_eq!(policy::invert_price_e6(250_000, 1), Some(4_000_000));
+    // Inversion happens before scaling.
+    assert_eq!(policy::to_engine_price(150_000, 1, 1_000), Some(6_666));
+    assert_eq!(
+        policy::to_engine_price(150_000_000, 0, 1_000),
+        Some(150_000)
+    );
+    // A result that floors to zero is rejected, never passed on as 0.
+    assert_eq!(policy::to_engine_price(150_000_000, 1, 10_000), None);
+    assert_eq!(policy::to_engine_price(2_000_000_000_000, 1, 0), None);
+    assert_eq!(policy::to_engine_price(0, 1, 0), None);
+}
+
 #[test]
 fn test_three_leg_external_oracle_composes_toto_sol_cross() {
     let toto_jpy_feed = [0x11u8; 32];
@@ -999,6 +2412,42 @@ fn test_init_market_dynamic_fee_tail_decodes_after_extended_tail() {
     }
 }
 
+#[test]
+fn test_init_market_metadata_tail_follows_other_tails() {
+    use percolator_prog::state::MarketMetadata;
+    let f = setup_market();
+    let meta = MarketMetadata {
+        symbol: *b"ETH-PERP\0\0\0\0\0\0\0\0",
+        oracle_description: [b'x'; 32],
+        uri_hash: [3u8; 32],
+        base_decimals: 18,
+    };
+
+    let mut data = encode_init_market(&f, 50);
+    match Instruction::decode(&data).expect("no metadata tail") {
+        Instruction::InitMarket(args) => assert_eq!(args.metadata, MarketMetadata::default()),
+        other => panic!("unexpected instruction: {other:?}"),
+    }
+    data.extend_from_slice(&meta.to_wire());
+    match Instruction::decode(&data).expect("metadata tail alone") {
+        Instruction::InitMarket(args) => assert_eq!(args.metadata, meta),
+        other => panic!("unexpected instruction: {other:?}"),
+    }
+
+    let mut data = encode_init_market(&f, 50);
+    data.extend_from_slice(&7u64.to_le_bytes());
+    data.extend_from_slice(&meta.to_wire());
+    match Instruction::decode(&data).expect("dynamic fee + metadata tails") {
+        Instruction::InitMarket(args) => {
+            assert_eq!(args.trade_fee_base_bps, 7);
+            assert_eq!(args.metadata, meta);
+        }
+        other => panic!("unexpected instruction: {other:?}"),
+    }
+    data.pop();
+    assert!(Instruction::decode(&data).is_err(), "truncated metadata");
+}
+
 #[test]
 fn test_init_market_three_leg_oracle_tail_can_enable_hybrid_dynamic_fee() {
     let f = setup_market();
@@ -1371,16 +2820,312 @@ fn test_init_market() {
 
     let header = state::read_header(&f.slab.data);
     assert_eq!(header.magic, MAGIC);
-    assert_eq!(header.version, 0);
+    assert_eq!(header.version, percolator_prog::constants::SLAB_VERSION);
 
     let engine = zc::engine_ref(&f.slab.data).unwrap();
     assert_eq!(engine.params.max_accounts, MAX_ACCOUNTS as u64);
 }
 
 #[test]
-fn test_vault_validation() {
+fn test_split_mut_regions_are_disjoint_views_of_the_slab() {
+    use percolator_prog::constants::{ENGINE_OFF, RISK_BUF_LEN, RISK_BUF_OFF, SLAB_LEN};
+    let mut f = setup_market();
+    let data = encode_init_market(&f, 50);
+    {
+        let accounts = vec![
+            f.admin.to_info(),
+            f.slab.to_info(),
+            f.mint.to_info(),
+            f.vault.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(&f.program_id, &accounts, &data).unwrap();
+    }
+    let staleness = state::read_config(&f.slab.data).max_staleness_secs;
+
+    {
+        let mut regions = zc::split_mut(&mut f.slab.data).unwrap();
+        assert_eq!(regions.head.len(), ENGINE_OFF);
+        assert_eq!(regions.tail.len(), SLAB_LEN - RISK_BUF_OFF);
+        assert_eq!(
+            state::read_config(regions.head).max_staleness_secs,
+            staleness
+        );
+        assert_eq!(regions.engine.params.max_accounts, MAX_ACCOUNTS as u64);
+
+        // Engine and tail are written through the same split borrow.
+        let mut buf = state::risk_buffer_from_bytes(regions.region(RISK_BUF_OFF, RISK_BUF_LEN));
+        buf.upsert(3, 1_000);
+        regions.engine.current_slot = 77;
+        regions
+            .region_mut(RISK_BUF_OFF, RISK_BUF_LEN)
+            .copy_from_slice(bytemuck::bytes_of(&buf));
+    }
+    let buf = state::read_risk_buffer(&f.slab.data);
+    assert_eq!(buf.count, 1);
+    assert_eq!(buf.entries[0].idx, 3);
+    assert_eq!(zc::engine_ref(&f.slab.data).unwrap().current_slot, 77);
+
+    // The same shape check as engine_ref.
+    assert!(zc::split_mut(&mut f.slab.data[..RISK_BUF_OFF - 1]).is_err());
+}
+
+#[test]
+fn test_older_slab_version_requires_migration() {
+    use percolator_prog::constants::TAIL_OFF;
+    let mut f = setup_market();
+    let data = encode_init_market(&f, 50);
+    {
+        let accounts = vec![
+            f.admin.to_info(),
+            f.slab.to_info(),
+            f.mint.to_info(),
+            f.vault.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(&f.program_id, &accounts, &data).unwrap();
+    }
+
+    // The same market as a version-0 slab, ending at the generation table.
+    f.slab.data.truncate(TAIL_OFF);
+    let mut header = state::read_header(&f.slab.data);
+    header.version = 0;
+    state::write_header(&mut f.slab.data, &header);
+
+    let mut caller = TestAccount::new(
+        Pubkey::new_unique(),
+        solana_program::system_program::id(),
+        0,
+        vec![],
+    )
+    .signer();
+    for len in [TAIL_OFF, TAIL_OFF + 10_240] {
+        f.slab.data.resize(len, 0);
+        let accs = vec![
+            caller.to_info(),
+            f.slab.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        assert_eq!(
+            process_instruction(&f.program_id, &accs, &encode_crank_permissionless(0)),
+            Err(PercolatorError::SlabMigrationRequired.into()),
+            "slab of {len} bytes"
+        );
+    }
+}
+
+#[test]
+fn test_position_bits_share_the_engine_used_word_layout() {
+    use percolator_prog::constants::{POSITION_BITMAP_LEN, POSITION_BITMAP_OFF, SLAB_LEN};
+    let mut data = vec![0u8; SLAB_LEN];
+    for idx in [0u16, 5, 63, 64, (MAX_ACCOUNTS - 1) as u16] {
+        state::set_position_bit(&mut data, idx, true);
+    }
+    let region = &data[POSITION_BITMAP_OFF..POSITION_BITMAP_OFF + POSITION_BITMAP_LEN];
+    assert_eq!(
+        state::position_word(region, 0),
+        1 | 1 << 5 | 1 << 63,
+        "bit i of word i / 64, as in engine.used"
+    );
+    assert_eq!(state::position_word(region, 1), 1);
+    assert_eq!(state::position_word(region, MAX_ACCOUNTS / 64 - 1), 1 << 63);
+
+    state::set_position_bit(&mut data, 5, false);
+    assert!(!state::has_position_bit(&data, 5));
+    assert!(state::has_position_bit(&data, 63));
+    // Out-of-range indices are ignored, never written.
+    state::set_position_bit(&mut data, MAX_ACCOUNTS as u16, true);
+    assert!(!state::has_position_bit(&data, MAX_ACCOUNTS as u16));
+}
+
+#[test]
+fn test_vault_validation() {
+    let mut f = setup_market();
+    f.vault.owner = solana_program::system_program::id();
+    let init_data = encode_init_market(&f, 50);
+    let init_accounts = vec![
+        f.admin.to_info(),
+        f.slab.to_info(),
+        f.mint.to_info(),
+        f.vault.to_info(),
+        f.clock.to_info(),
+        f.pyth_index.to_info(),
+    ];
+    let res = process_instruction(&f.program_id, &init_accounts, &init_data);
+    assert_eq!(res, Err(PercolatorError::InvalidVaultAta.into()));
+}
+
+#[test]
+fn test_check_accounts_against_admin_slab_spec() {
+    use percolator_prog::accounts::{check_accounts, AccountSpec, Owner, ADMIN_SLAB};
+
+    let mut f = setup_market();
+    let program_id = f.program_id;
+    let infos = [f.admin.to_info(), f.slab.to_info()];
+    assert_eq!(check_accounts(&program_id, &infos, ADMIN_SLAB), Ok(()));
+    assert_eq!(
+        check_accounts(&program_id, &infos[..1], ADMIN_SLAB),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    // The slab is not owned by some other program.
+    assert_eq!(
+        check_accounts(&Pubkey::new_unique(), &infos, ADMIN_SLAB),
+        Err(ProgramError::IllegalOwner)
+    );
+    let wrong_key = [AccountSpec::new("system program").key(Pubkey::new_unique())];
+    assert_eq!(
+        check_accounts(&program_id, &infos[..1], &wrong_key),
+        Err(ProgramError::InvalidArgument)
+    );
+    let token_owned = [AccountSpec::new("token account").owner(Owner::Token)];
+    assert_eq!(check_accounts(&program_id, &[f.mint.to_info()], &token_owned), Ok(()));
+
+    let mut f = setup_market();
+    f.admin.is_signer = false;
+    let infos = [f.admin.to_info(), f.slab.to_info()];
+    assert_eq!(
+        check_accounts(&program_id, &infos, ADMIN_SLAB),
+        Err(PercolatorError::ExpectedSigner.into())
+    );
+}
+
+#[test]
+fn test_cpi_in_progress_flag_blocks_reentry() {
+    let mut f = setup_market();
+    let init_data = encode_init_market(&f, 50);
+    {
+        let accs = vec![
+            f.admin.to_info(),
+            f.slab.to_info(),
+            f.mint.to_info(),
+            f.vault.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(&f.program_id, &accs, &init_data).unwrap();
+    }
+    let mut user = TestAccount::new(
+        Pubkey::new_unique(),
+        solana_program::system_program::id(),
+        0,
+        vec![],
+    )
+    .signer();
+    let mut user_ata = TestAccount::new(
+        Pubkey::new_unique(),
+        spl_token::ID,
+        0,
+        make_token_account(f.mint.key, user.key, 2000),
+    )
+    .writable();
+    {
+        let accounts = vec![
+            user.to_info(),
+            f.slab.to_info(),
+            user_ata.to_info(),
+            f.vault.to_info(),
+            f.token_prog.to_info(),
+            f.clock.to_info(),
+        ];
+        process_instruction(&f.program_id, &accounts, &encode_init_user(100)).unwrap();
+    }
+    let user_idx = find_idx_by_owner(&f.slab.data, user.key).unwrap();
+
+    // What the slab looks like to a matcher calling back mid-TradeCpi.
+    percolator_prog::state::set_cpi_in_progress(&mut f.slab.data);
+    {
+        let accounts = vec![
+            user.to_info(),
+            f.slab.to_info(),
+            user_ata.to_info(),
+            f.vault.to_info(),
+            f.token_prog.to_info(),
+            f.clock.to_info(),
+        ];
+        let res = process_instruction(&f.program_id, &accounts, &encode_deposit(user_idx, 1000));
+        assert_eq!(res, Err(PercolatorError::CpiReentrancy.into()));
+    }
+    {
+        // SetEpochLength (tag 39), an admin instruction.
+        let mut data = vec![39u8];
+        encode_u64(1000, &mut data);
+        let accs = vec![f.admin.to_info(), f.slab.to_info()];
+        let res = process_instruction(&f.program_id, &accs, &data);
+        assert_eq!(res, Err(PercolatorError::CpiReentrancy.into()));
+    }
+
+    percolator_prog::state::clear_cpi_in_progress(&mut f.slab.data);
+    {
+        let accounts = vec![
+            user.to_info(),
+            f.slab.to_info(),
+            user_ata.to_info(),
+            f.vault.to_info(),
+            f.token_prog.to_info(),
+            f.clock.to_info(),
+        ];
+        process_instruction(&f.program_id, &accounts, &encode_deposit(user_idx, 1000)).unwrap();
+    }
+}
+
+#[test]
+fn test_check_account_head_checks_only_the_fixed_prefix() {
+    use percolator_prog::accounts::{check_account_head, check_accounts, DEPOSIT};
+
+    let mut f = setup_market();
+    let program_id = f.program_id;
+    let infos = [
+        f.admin.to_info(),
+        f.slab.to_info(),
+        f.vault.to_info(),
+        f.vault.to_info(),
+        f.token_prog.to_info(),
+        f.clock.to_info(),
+        f.pyth_index.to_info(),
+    ];
+    assert_eq!(check_accounts(&program_id, &infos[..6], DEPOSIT), Ok(()));
+    // A tail is the handler's to bound: the exact-count check refuses it,
+    // the head check ignores it.
+    assert_eq!(
+        check_accounts(&program_id, &infos, DEPOSIT),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    assert_eq!(check_account_head(&program_id, &infos, DEPOSIT), Ok(()));
+    assert_eq!(
+        check_account_head(&program_id, &infos[..5], DEPOSIT),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    // The clock sits at a fixed address.
+    let infos = [
+        f.admin.to_info(),
+        f.slab.to_info(),
+        f.vault.to_info(),
+        f.vault.to_info(),
+        f.token_prog.to_info(),
+        f.pyth_index.to_info(),
+    ];
+    assert_eq!(
+        check_account_head(&program_id, &infos, DEPOSIT),
+        Err(ProgramError::InvalidArgument)
+    );
+}
+
+#[test]
+fn test_init_market_requires_the_vault_ata() {
+    use percolator_prog::accounts;
+
+    let f = setup_market();
+    assert_eq!(
+        accounts::derive_vault(&f.program_id, &f.slab.key, &f.mint.key),
+        f.vault.key
+    );
+
+    // A correctly configured token account at any other address is refused.
     let mut f = setup_market();
-    f.vault.owner = solana_program::system_program::id();
+    f.vault.key = Pubkey::new_unique();
     let init_data = encode_init_market(&f, 50);
     let init_accounts = vec![
         f.admin.to_info(),
@@ -1394,6 +3139,132 @@ fn test_vault_validation() {
     assert_eq!(res, Err(PercolatorError::InvalidVaultAta.into()));
 }
 
+#[test]
+fn test_trade_result_round_trip() {
+    use percolator_prog::ix::{TradeResult, TRADE_RESULT_LEN};
+
+    let r = TradeResult {
+        exec_size: -123_456_789_012,
+        exec_price_e6: 138_000_000,
+        oracle_price_e6: 137_500_000,
+        fee_bps: 10,
+        fee_paid: u64::MAX as u128 + 1,
+    };
+    let bytes = r.encode();
+    assert_eq!(bytes.len(), TRADE_RESULT_LEN);
+    assert_eq!(&bytes[16..24], &138_000_000u64.to_le_bytes());
+    assert_eq!(TradeResult::decode(&bytes), Some(r));
+    assert_eq!(TradeResult::decode(&bytes[..55]), None);
+}
+
+#[test]
+fn test_pending_kf_pnl_reference_values() {
+    let fd = percolator::FUNDING_DEN as i128;
+    // 2 units with a_basis = A: one K step of A * $1 books $2.
+    assert_eq!(
+        policy::pending_kf_pnl(2_000_000, 1_000_000, 1_000_000_000_000, 0),
+        2_000_000
+    );
+    assert_eq!(
+        policy::pending_kf_pnl(2_000_000, 1_000_000, -1_000_000_000_000, 0),
+        -2_000_000
+    );
+    // The same move carried by F alone books the same amount.
+    assert_eq!(
+        policy::pending_kf_pnl(2_000_000, 1_000_000, 0, 1_000_000_000_000 * fd),
+        2_000_000
+    );
+    // Dust rounds against the holder.
+    assert_eq!(policy::pending_kf_pnl(2_000_000, 1_000_000, 0, 1), 0);
+    assert_eq!(policy::pending_kf_pnl(2_000_000, 1_000_000, 0, -1), -1);
+    assert_eq!(policy::pending_kf_pnl(0, 1_000_000, 1 << 40, 0), 0);
+    assert_eq!(policy::pending_kf_pnl(2_000_000, 0, 1 << 40, 0), 0);
+}
+
+#[test]
+fn test_health_snapshot_round_trip() {
+    use percolator_prog::ix::{free_collateral, HealthSnapshot, HEALTH_SNAPSHOT_LEN};
+
+    let h = HealthSnapshot {
+        equity: -5_000,
+        maintenance_req: 7_000,
+        initial_req: 14_000,
+        free_collateral: free_collateral(-5_000, 14_000),
+        position_q: -42_000_000,
+        price_e6: 138_000_000,
+        last_market_slot: 99,
+    };
+    let bytes = h.encode();
+    assert_eq!(bytes.len(), HEALTH_SNAPSHOT_LEN);
+    assert_eq!(HealthSnapshot::decode(&bytes), Some(h));
+    assert_eq!(HealthSnapshot::decode(&bytes[..95]), None);
+
+    assert_eq!(free_collateral(-5_000, 14_000), 0);
+    assert_eq!(free_collateral(10_000, 14_000), 0);
+    assert_eq!(free_collateral(20_000, 14_000), 6_000);
+    assert_eq!(free_collateral(i128::MAX, 0), i128::MAX as u128);
+
+    let mut data = vec![34u8];
+    encode_u16(7, &mut data);
+    assert!(matches!(
+        Instruction::decode(&data),
+        Ok(Instruction::QueryHealth { user_idx: 7 })
+    ));
+    data.push(0);
+    assert!(Instruction::decode(&data).is_err());
+}
+
+#[test]
+fn test_error_codes_and_context_logging() {
+    use percolator_prog::error::{clamp_u64, fail};
+
+    // Codes are positional and part of the ABI; new variants append.
+    assert_eq!(PercolatorError::InvalidMagic as u32, 0);
+    assert_eq!(PercolatorError::EngineOverflow as u32, 18);
+    assert_eq!(PercolatorError::DepositCapExceeded as u32, 30);
+    assert_eq!(PercolatorError::FillOrKillNotFilled as u32, 36);
+    assert_eq!(PercolatorError::SlabFull as u32, 37);
+    assert_eq!(PercolatorError::MatcherContextMismatch as u32, 38);
+    assert_eq!(PercolatorError::NonceMismatch as u32, 39);
+    assert_eq!(PercolatorError::PositionLimitExceeded as u32, 40);
+    assert_eq!(PercolatorError::NotAllowlisted as u32, 41);
+    assert_eq!(PercolatorError::WithdrawOnly as u32, 42);
+    assert_eq!(PercolatorError::AdminApprovalsPending as u32, 43);
+    assert_eq!(PercolatorError::BelowMinSize as u32, 44);
+    assert_eq!(PercolatorError::CircuitBreakerTripped as u32, 45);
+    assert_eq!(PercolatorError::TradeRateLimited as u32, 46);
+    assert_eq!(PercolatorError::OracleFallbackActive as u32, 47);
+    assert_eq!(PercolatorError::MatcherCapabilityExceeded as u32, 48);
+    assert_eq!(PercolatorError::SlabInitIncomplete as u32, 49);
+    assert_eq!(PercolatorError::SlabNotRentExempt as u32, 50);
+    assert_eq!(PercolatorError::SlabNotZeroed as u32, 51);
+    assert_eq!(PercolatorError::CpiReentrancy as u32, 52);
+    assert_eq!(PercolatorError::MatcherModifiedSlab as u32, 53);
+    assert_eq!(PercolatorError::MatcherFillOutOfBounds as u32, 54);
+    assert_eq!(PercolatorError::SlabMigrationRequired as u32, 55);
+    assert_eq!(PercolatorError::BackstopFull as u32, 56);
+    assert_eq!(PercolatorError::BackstopWithdrawLocked as u32, 57);
+    assert_eq!(PercolatorError::HyperpPushersFull as u32, 58);
+    assert_eq!(PercolatorError::PermitExpired as u32, 59);
+    assert_eq!(PercolatorError::BackstopNotEmpty as u32, 60);
+    assert_eq!(PercolatorError::AdminActionsFull as u32, 61);
+
+    assert_eq!(PercolatorError::OracleStale.name(), "OracleStale");
+    assert_eq!(PercolatorError::SlabFull.name(), "SlabFull");
+
+    // Logging must not change the code callers match on.
+    assert_eq!(
+        fail(PercolatorError::OracleStale, &[1, 2, 3, 4]),
+        PercolatorError::OracleStale.into()
+    );
+    assert_eq!(
+        fail(PercolatorError::InvalidVaultAta, &[]),
+        PercolatorError::InvalidVaultAta.into()
+    );
+    assert_eq!(clamp_u64(u128::MAX), u64::MAX);
+    assert_eq!(clamp_u64(7), 7);
+}
+
 #[test]
 #[ignore = "native debug engine scans unused zero-memory accounts; SBF integration covers zero-copy materialization"]
 fn test_trade() {
@@ -1791,6 +3662,16 @@ fn test_unit_scale_conversion() {
     // With scale=100
     assert_eq!(base_to_units(201, 100), (2, 1)); // 2 units, 1 dust
     assert_eq!(units_to_base_checked(2, 100), Some(200));
+
+    // Client-side helpers
+    use percolator_prog::units::{align_base_down, unscale_price_e6};
+    assert_eq!(align_base_down(5500, 1000), 5000);
+    assert_eq!(align_base_down(999, 1000), 0);
+    assert_eq!(align_base_down(12345, 0), 12345);
+    assert_eq!(base_to_units(align_base_down(u64::MAX, 7), 7).1, 0);
+    assert_eq!(unscale_price_e6(150_000, 1000), Some(150_000_000));
+    assert_eq!(unscale_price_e6(150_000, 0), Some(150_000));
+    assert_eq!(unscale_price_e6(u64::MAX, 2), None);
 }
 
 #[test]
@@ -1872,3 +3753,691 @@ fn test_nonce_overflow_does_not_reopen_request_id_space() {
         "u64::MAX-1 should advance to u64::MAX"
     );
 }
+
+// ============================================================================
+// vAMM impact curve reference values
+// ============================================================================
+
+#[test]
+fn test_vamm_impact_bps_reference_values() {
+    // 1_000 notional against 10_000 depth at k = 100 bps -> 10 bps.
+    assert_eq!(
+        policy::vamm_impact_bps(1_000_000_000, 100, 10_000_000_000),
+        Some(10)
+    );
+    // Linear: doubling the fill doubles the impact.
+    assert_eq!(
+        policy::vamm_impact_bps(2_000_000_000, 100, 10_000_000_000),
+        Some(20)
+    );
+    // Rounds up: the LP keeps the residue.
+    assert_eq!(policy::vamm_impact_bps(1, 1, 3), Some(1));
+    // Zero depth or zero k disables impact.
+    assert_eq!(policy::vamm_impact_bps(1_000_000_000, 100, 0), Some(0));
+    assert_eq!(policy::vamm_impact_bps(1_000_000_000, 0, 1), Some(0));
+    assert_eq!(policy::vamm_impact_bps(u128::MAX, u32::MAX, 1), None);
+}
+
+#[test]
+fn test_vamm_exec_price_reference_values() {
+    let oracle = 100_000_000u64; // 100.0
+    let size = 10 * percolator::POS_SCALE as i128; // notional 1_000
+    let depth = 10_000_000_000u128;
+
+    // fee 5 + spread 10 + impact 10 = 25 bps either side of oracle.
+    assert_eq!(
+        policy::vamm_exec_price_e6(oracle, size, 5, 10, 200, 100, depth),
+        Some(100_250_000)
+    );
+    assert_eq!(
+        policy::vamm_exec_price_e6(oracle, -size, 5, 10, 200, 100, depth),
+        Some(99_750_000)
+    );
+    // max_total_bps caps the combined spread.
+    assert_eq!(
+        policy::vamm_exec_price_e6(oracle, size, 5, 10, 20, 100, depth),
+        Some(100_200_000)
+    );
+    // Impact disabled: flat fee + spread.
+    assert_eq!(
+        policy::vamm_exec_price_e6(oracle, size, 5, 10, 200, 100, 0),
+        Some(100_150_000)
+    );
+    // Larger fills pay strictly more.
+    let small = policy::vamm_exec_price_e6(oracle, size, 5, 10, 10_000, 100, depth).unwrap();
+    let large = policy::vamm_exec_price_e6(oracle, 10 * size, 5, 10, 10_000, 100, depth).unwrap();
+    assert!(large > small);
+    // A sell whose spread eats the whole price is unquotable.
+    assert_eq!(
+        policy::vamm_exec_price_e6(oracle, -size, 0, 10_000, 10_000, 0, 0),
+        None
+    );
+    assert_eq!(
+        policy::vamm_exec_price_e6(0, size, 5, 10, 200, 100, depth),
+        None
+    );
+    assert_eq!(
+        policy::vamm_exec_price_e6(oracle, 0, 5, 10, 200, 100, depth),
+        None
+    );
+}
+
+// ============================================================================
+// Backstop vault share math
+// ============================================================================
+
+#[test]
+fn test_lp_share_math_rounds_in_vault_favor() {
+    // First depositor mints 1:1.
+    assert_eq!(policy::lp_shares_for_deposit(1_000, 0, 0), Some(1_000));
+    // Equity grew 10% -> fewer shares per unit deposited (floor).
+    assert_eq!(
+        policy::lp_shares_for_deposit(1_000, 1_000, 1_100),
+        Some(909)
+    );
+    // Existing shares against zero equity cannot be re-diluted.
+    assert_eq!(policy::lp_shares_for_deposit(1_000, 1_000, 0), None);
+
+    assert_eq!(policy::lp_redeem_amount(909, 1_909, 2_100), Some(999));
+    assert_eq!(policy::lp_redeem_amount(2, 1, 10), None);
+    assert_eq!(policy::lp_redeem_amount(0, 0, 0), Some(0));
+
+    // Round trip never returns more than was deposited.
+    let (shares_total, equity) = (1_000u128, 1_337u128);
+    let minted = policy::lp_shares_for_deposit(777, shares_total, equity).unwrap();
+    let back = policy::lp_redeem_amount(minted, shares_total + minted, equity + 777).unwrap();
+    assert!(back <= 777);
+}
+
+#[test]
+fn test_lp_redeem_delay() {
+    assert!(!policy::lp_redeem_unlocked(100, 149, 50));
+    assert!(policy::lp_redeem_unlocked(100, 150, 50));
+    assert!(!policy::lp_redeem_unlocked(u64::MAX, u64::MAX, 1));
+}
+
+#[test]
+fn test_portfolio_margin_offsets() {
+    use policy::{portfolio_margin, MarginOffset, PortfolioLeg, MAX_PORTFOLIO_LEGS};
+    let leg = |notional: i128, margin_bps: u64| PortfolioLeg {
+        notional,
+        margin_bps,
+    };
+    let off = |a: usize, b: usize, offset_bps: u64| MarginOffset { a, b, offset_bps };
+    // SOL long vs mSOL short, 5% margin each: naive 10_000.
+    let hedge = [leg(100_000, 500), leg(-100_000, 500)];
+    assert_eq!(portfolio_margin(&hedge, &[]), Some(10_000));
+    assert_eq!(portfolio_margin(&hedge, &[off(0, 1, 8_000)]), Some(2_000));
+    // Same side earns nothing.
+    let same = [leg(100_000, 500), leg(100_000, 500)];
+    assert_eq!(portfolio_margin(&same, &[off(0, 1, 8_000)]), Some(10_000));
+    // Hedged notional is consumed once: leg 0 is fully offset by leg 1,
+    // so the pair (0, 2) has nothing left.
+    let three = [leg(100_000, 500), leg(-100_000, 500), leg(-50_000, 1_000)];
+    assert_eq!(
+        portfolio_margin(&three, &[off(0, 1, 8_000), off(0, 2, 8_000)]),
+        Some(2_000 + 5_000)
+    );
+    // Partial hedge: only the smaller side is offset.
+    let partial = [leg(100_000, 500), leg(-40_000, 500)];
+    assert_eq!(
+        portfolio_margin(&partial, &[off(0, 1, 5_000)]),
+        Some(7_000 - 2_000)
+    );
+    // Rounding: naive ceils, credit floors.
+    assert_eq!(portfolio_margin(&[leg(1, 1)], &[]), Some(1));
+    // Malformed matrices fail closed.
+    assert_eq!(portfolio_margin(&hedge, &[off(0, 0, 1)]), None);
+    assert_eq!(portfolio_margin(&hedge, &[off(0, 2, 1)]), None);
+    assert_eq!(portfolio_margin(&hedge, &[off(0, 1, 9_001)]), None);
+    let many = [leg(1, 1); MAX_PORTFOLIO_LEGS + 1];
+    assert_eq!(portfolio_margin(&many, &[]), None);
+}
+
+#[test]
+fn test_slippage_bps_bounds() {
+    let oracle = 100_000_000u64;
+    // Long: pay at most oracle * 1.01.
+    assert!(policy::slippage_bps_ok(101_000_000, oracle, 1, 100));
+    assert!(!policy::slippage_bps_ok(101_000_001, oracle, 1, 100));
+    // Short: receive at least oracle * 0.99.
+    assert!(policy::slippage_bps_ok(99_000_000, oracle, -1, 100));
+    assert!(!policy::slippage_bps_ok(98_999_999, oracle, -1, 100));
+    // Zero tolerance accepts only oracle-or-better.
+    assert!(policy::slippage_bps_ok(oracle, oracle, 1, 0));
+    assert!(policy::slippage_bps_ok(oracle - 1, oracle, 1, 0));
+    assert!(!policy::slippage_bps_ok(oracle + 1, oracle, 1, 0));
+    // Malformed bound fails closed.
+    assert!(!policy::slippage_bps_ok(oracle, oracle, 1, 10_001));
+}
+
+#[test]
+fn test_exec_price_within_margin() {
+    let oracle = 100_000_000u64;
+    // 10% initial margin: either side of the oracle up to the margin.
+    assert!(policy::exec_price_within_margin(110_000_000, oracle, 1_000));
+    assert!(policy::exec_price_within_margin(90_000_000, oracle, 1_000));
+    assert!(!policy::exec_price_within_margin(110_000_001, oracle, 1_000));
+    assert!(!policy::exec_price_within_margin(89_999_999, oracle, 1_000));
+    // Extreme matcher prices cannot overflow the check.
+    assert!(!policy::exec_price_within_margin(u64::MAX, oracle, 10_000));
+    assert!(policy::exec_price_within_margin(1, u64::MAX, 10_000));
+}
+
+#[test]
+fn test_liquidation_price_e6() {
+    let unit = percolator::POS_SCALE as i128;
+    let long = policy::LiqPriceInputs {
+        position_q: 2 * unit,
+        equity: 50_000_000,
+        price_e6: 100_000_000,
+        maintenance_margin_bps: 1_000,
+        ..Default::default()
+    };
+    // equity 2P - 150 against 0.2P: below maintenance under 83.33, with the
+    // requirement's floor deciding the last unit.
+    assert_eq!(policy::liquidation_price_e6(&long), Some(83_333_332));
+    let short = policy::LiqPriceInputs {
+        position_q: -2 * unit,
+        ..long
+    };
+    assert_eq!(policy::liquidation_price_e6(&short), Some(113_636_365));
+
+    // Positive funding: the long pays 20_000 over 100 slots and its
+    // liquidation price rises; the short receives it and its price rises too.
+    let funded = |i: policy::LiqPriceInputs| policy::LiqPriceInputs {
+        funding_rate_e9: 1_000,
+        horizon_slots: 100,
+        ..i
+    };
+    assert_eq!(policy::liq_projected_equity(&funded(long)), Some(49_980_000));
+    assert_eq!(policy::liq_projected_equity(&funded(short)), Some(50_020_000));
+    assert_eq!(policy::liquidation_price_e6(&funded(long)), Some(83_344_443));
+    assert_eq!(policy::liquidation_price_e6(&funded(short)), Some(113_645_455));
+    let fees = policy::LiqPriceInputs {
+        maintenance_fee_per_slot: 10,
+        horizon_slots: 100,
+        ..long
+    };
+    assert_eq!(policy::liquidation_price_e6(&fees), Some(83_333_888));
+
+    // Already past it: the answer is at or above the current price.
+    let thin = policy::LiqPriceInputs {
+        equity: 10_000_000,
+        ..long
+    };
+    assert_eq!(policy::liquidation_price_e6(&thin), Some(105_555_555));
+    // A long backed beyond its notional survives a zero price unless the
+    // requirement floor catches it.
+    let rich = policy::LiqPriceInputs {
+        equity: 250_000_000,
+        ..long
+    };
+    assert_eq!(policy::liquidation_price_e6(&rich), None);
+    let floored = policy::LiqPriceInputs {
+        min_nonzero_mm_req: 60_000_000,
+        ..rich
+    };
+    assert_eq!(policy::liquidation_price_e6(&floored), Some(4_999_999));
+
+    let flat = policy::LiqPriceInputs {
+        position_q: 0,
+        ..long
+    };
+    assert_eq!(policy::liquidation_price_e6(&flat), None);
+    let full_margin = policy::LiqPriceInputs {
+        maintenance_margin_bps: 10_000,
+        ..long
+    };
+    assert_eq!(policy::liquidation_price_e6(&full_margin), None);
+}
+
+#[test]
+fn test_trade_slippage_flag_decode_rules() {
+    let slip = percolator_prog::constants::TRADE_FLAG_LIMIT_IS_SLIPPAGE_BPS;
+    let mut cpi = encode_trade_cpi(1, 2, 10);
+    encode_u64(10_000, &mut cpi);
+    cpi.push(slip);
+    assert!(Instruction::decode(&cpi).is_ok());
+
+    let mut too_wide = encode_trade_cpi(1, 2, 10);
+    encode_u64(10_001, &mut too_wide);
+    too_wide.push(slip);
+    assert!(Instruction::decode(&too_wide).is_err());
+
+    let mut nocpi = encode_trade_with_exec_price(1, 2, 10, 0);
+    nocpi.push(slip);
+    assert!(
+        Instruction::decode(&nocpi).is_err(),
+        "slippage-bps form is TradeCpi-only"
+    );
+}
+
+#[test]
+fn test_trade_fill_or_kill_flag_is_tradecpi_only() {
+    let fok = percolator_prog::constants::TRADE_FLAG_FILL_OR_KILL;
+    let mut cpi = encode_trade_cpi(1, 2, 10);
+    encode_u64(0, &mut cpi);
+    cpi.push(fok);
+    match Instruction::decode(&cpi).expect("fill-or-kill TradeCpi must decode") {
+        Instruction::TradeCpi { flags, .. } => assert_eq!(flags, fok),
+        other => panic!("unexpected decode: {other:?}"),
+    }
+    let mut nocpi = encode_trade_with_exec_price(1, 2, 10, 0);
+    nocpi.push(fok);
+    assert!(Instruction::decode(&nocpi).is_err());
+}
+
+/// Build ed25519 precompile data the way `new_ed25519_instruction` lays it
+/// out: header, one offsets record, pubkey, signature, message.
+fn ed25519_ix_data(pk: &[u8; 32], msg: &[u8], ix_index: u16) -> Vec<u8> {
+    let pk_off: u16 = 16;
+    let sig_off: u16 = pk_off + 32;
+    let msg_off: u16 = sig_off + 64;
+    let mut d = vec![1u8, 0];
+    for v in [
+        sig_off,
+        ix_index,
+        pk_off,
+        ix_index,
+        msg_off,
+        msg.len() as u16,
+        ix_index,
+    ] {
+        d.extend_from_slice(&v.to_le_bytes());
+    }
+    d.extend_from_slice(pk);
+    d.extend_from_slice(&[0u8; 64]);
+    d.extend_from_slice(msg);
+    d
+}
+
+#[test]
+fn test_signed_hyperp_mark_payload_roundtrip() {
+    let slab = [7u8; 32];
+    let authority = [9u8; 32];
+    let msg = policy::hyperp_mark_payload(&slab, 1_234_567, 42);
+    assert_eq!(&msg[..32], &slab);
+    assert_eq!(&msg[32..40], &1_234_567u64.to_le_bytes());
+    assert_eq!(&msg[40..48], &42i64.to_le_bytes());
+
+    let data = ed25519_ix_data(&authority, &msg, u16::MAX);
+    let (pk, got) = policy::ed25519_single_sig_payload(&data).unwrap();
+    assert_eq!(pk, authority);
+    assert_eq!(got, msg.as_slice());
+}
+
+#[test]
+fn test_permit_deposit_payload_layout() {
+    let msg = policy::permit_deposit_payload(&[7u8; 32], 3, 1_000, 9, -1);
+    assert_eq!(&msg[..32], &[7u8; 32]);
+    assert_eq!(&msg[32..34], &3u16.to_le_bytes());
+    assert_eq!(&msg[34..42], &1_000u64.to_le_bytes());
+    assert_eq!(&msg[42..50], &9u64.to_le_bytes());
+    assert_eq!(&msg[50..58], &(-1i64).to_le_bytes());
+
+    let mut data = vec![61u8];
+    data.extend_from_slice(&msg[32..]);
+    data.push(1);
+    data.extend_from_slice(&[5u8; 32]);
+    match Instruction::decode(&data).expect("permit deposit must decode") {
+        Instruction::DepositWithPermit {
+            user_idx,
+            amount,
+            nonce,
+            expiry,
+            allowlist_proof,
+        } => {
+            assert_eq!((user_idx, amount, nonce, expiry), (3, 1_000, 9, -1));
+            assert_eq!(allowlist_proof, vec![[5u8; 32]]);
+        }
+        other => panic!("unexpected decode: {other:?}"),
+    }
+}
+
+#[test]
+fn test_signed_hyperp_mark_freshness_window() {
+    assert!(policy::signed_mark_fresh(1_000, 1_000, 30));
+    assert!(policy::signed_mark_fresh(970, 1_000, 30));
+    assert!(!policy::signed_mark_fresh(969, 1_000, 30));
+    // Not ahead of the clock.
+    assert!(!policy::signed_mark_fresh(1_001, 1_000, 30));
+    assert!(!policy::signed_mark_fresh(i64::MAX, 1_000, 30));
+    assert!(!policy::signed_mark_fresh(i64::MIN, 1_000, u64::MAX >> 1));
+}
+
+#[test]
+fn test_signed_hyperp_mark_rejects_foreign_or_malformed_ed25519_data() {
+    let msg = policy::hyperp_mark_payload(&[7u8; 32], 1, 1);
+    // Key/message pulled from another instruction must not be trusted.
+    let foreign = ed25519_ix_data(&[9u8; 32], &msg, 0);
+    assert!(policy::ed25519_single_sig_payload(&foreign).is_none());
+    // Multiple signatures are rejected.
+    let mut multi = ed25519_ix_data(&[9u8; 32], &msg, u16::MAX);
+    multi[0] = 2;
+    assert!(policy::ed25519_single_sig_payload(&multi).is_none());
+    // Truncated message.
+    let mut short = ed25519_ix_data(&[9u8; 32], &msg, u16::MAX);
+    short.truncate(short.len() - 1);
+    assert!(policy::ed25519_single_sig_payload(&short).is_none());
+    assert!(policy::ed25519_single_sig_payload(&[]).is_none());
+}
+
+#[test]
+fn test_mul_div_rounding_and_overflow() {
+    assert_eq!(policy::mul_div_floor(7, 3, 2), Some(10));
+    assert_eq!(policy::mul_div_ceil(7, 3, 2), Some(11));
+    assert_eq!(policy::mul_div_ceil(6, 3, 2), Some(9));
+    assert_eq!(policy::mul_div_floor(0, u128::MAX, 1), Some(0));
+    assert_eq!(policy::mul_div_floor(1, 1, 0), None);
+    assert_eq!(policy::mul_div_ceil(1, 1, 0), None);
+    assert_eq!(policy::mul_div_floor(u128::MAX, 2, 2), None);
+    assert_eq!(policy::mul_div_ceil(u128::MAX, 1, 1), Some(u128::MAX));
+    // ceil - floor is at most 1 and only when the division is inexact.
+    for (a, b, d) in [(10u128, 10u128, 3u128), (9, 1, 3), (1, 1, 10_000)] {
+        let f = policy::mul_div_floor(a, b, d).unwrap();
+        let c = policy::mul_div_ceil(a, b, d).unwrap();
+        assert_eq!(c - f, u128::from((a * b) % d != 0));
+    }
+}
+
+fn simulate_scratch() -> Vec<u128> {
+    vec![0u128; percolator_prog::constants::SLAB_LEN.div_ceil(16)]
+}
+
+#[test]
+fn test_simulate_trade_rejections_match_processor() {
+    use percolator_prog::simulate::simulate_trade_nocpi;
+
+    let mut f = setup_market();
+    let mut scratch = simulate_scratch();
+    let scratch = bytemuck::cast_slice_mut::<u128, u8>(&mut scratch);
+
+    // Uninitialized and wrong-length snapshots.
+    assert_eq!(
+        simulate_trade_nocpi(&f.slab.data, scratch, 0, 1, 100, 0, 0, 100, 100_000_000),
+        Err(PercolatorError::NotInitialized.into())
+    );
+    assert_eq!(
+        simulate_trade_nocpi(
+            &f.slab.data[1..],
+            scratch,
+            0,
+            1,
+            100,
+            0,
+            0,
+            100,
+            100_000_000
+        ),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    let init_data = encode_init_market(&f, 50);
+    {
+        let accs = vec![
+            f.admin.to_info(),
+            f.slab.to_info(),
+            f.mint.to_info(),
+            f.vault.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(&f.program_id, &accs, &init_data).unwrap();
+    }
+    let snapshot = f.slab.data.clone();
+
+    assert_eq!(
+        simulate_trade_nocpi(&snapshot, scratch, 0, 1, 0, 0, 0, 100, 100_000_000),
+        Err(ProgramError::InvalidInstructionData)
+    );
+
+    // Neither index is in use: the preview fails exactly where the
+    // processor does.
+    let sim = simulate_trade_nocpi(&snapshot, scratch, 0, 1, 100, 0, 0, 100, 100_000_000);
+    let mut user = TestAccount::new(Pubkey::new_unique(), Pubkey::default(), 0, vec![]).signer();
+    let mut lp = TestAccount::new(Pubkey::new_unique(), Pubkey::default(), 0, vec![]).signer();
+    let live = {
+        let accounts = vec![
+            user.to_info(),
+            lp.to_info(),
+            f.slab.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(&f.program_id, &accounts, &encode_trade(0, 1, 100))
+    };
+    assert_eq!(sim, Err(PercolatorError::EngineAccountNotFound.into()));
+    assert_eq!(sim.map(|_| ()), live);
+    assert_eq!(
+        snapshot, f.slab.data,
+        "simulation must not touch the snapshot"
+    );
+}
+
+#[test]
+#[ignore = "native debug engine scans unused zero-memory accounts; SBF integration covers zero-copy materialization"]
+fn test_simulate_trade_matches_trade_nocpi() {
+    use percolator_prog::simulate::simulate_trade_nocpi;
+
+    let mut f = setup_market();
+    let init_data = encode_init_market(&f, 50);
+    {
+        let accs = vec![
+            f.admin.to_info(),
+            f.slab.to_info(),
+            f.mint.to_info(),
+            f.vault.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(&f.program_id, &accs, &init_data).unwrap();
+    }
+    let open = |f: &mut MarketFixture, lp: bool| -> (TestAccount, u16) {
+        let mut owner = TestAccount::new(
+            Pubkey::new_unique(),
+            solana_program::system_program::id(),
+            0,
+            vec![],
+        )
+        .signer();
+        let mut ata = TestAccount::new(
+            Pubkey::new_unique(),
+            spl_token::ID,
+            0,
+            make_token_account(f.mint.key, owner.key, 2000),
+        )
+        .writable();
+        let data = if lp {
+            encode_init_lp(Pubkey::new_unique(), Pubkey::new_unique(), 100)
+        } else {
+            encode_init_user(100)
+        };
+        {
+            let accounts = vec![
+                owner.to_info(),
+                f.slab.to_info(),
+                ata.to_info(),
+                f.vault.to_info(),
+                f.token_prog.to_info(),
+                f.clock.to_info(),
+            ];
+            process_instruction(&f.program_id, &accounts, &data).unwrap();
+        }
+        let idx = find_idx_by_owner(&f.slab.data, owner.key).unwrap();
+        {
+            let accounts = vec![
+                owner.to_info(),
+                f.slab.to_info(),
+                ata.to_info(),
+                f.vault.to_info(),
+                f.token_prog.to_info(),
+                f.clock.to_info(),
+            ];
+            process_instruction(&f.program_id, &accounts, &encode_deposit(idx, 1000)).unwrap();
+        }
+        (owner, idx)
+    };
+    let (mut user, user_idx) = open(&mut f, false);
+    let (mut lp, lp_idx) = open(&mut f, true);
+
+    let mut scratch = simulate_scratch();
+    let scratch = bytemuck::cast_slice_mut::<u128, u8>(&mut scratch);
+    let preview = simulate_trade_nocpi(
+        &f.slab.data,
+        scratch,
+        lp_idx,
+        user_idx,
+        100,
+        0,
+        0,
+        100,
+        100_000_000,
+    )
+    .expect("admissible trade previews");
+    let ins_before = zc::engine_ref(&f.slab.data)
+        .unwrap()
+        .insurance_fund
+        .balance
+        .get();
+    {
+        let accounts = vec![
+            user.to_info(),
+            lp.to_info(),
+            f.slab.to_info(),
+            f.clock.to_info(),
+            f.pyth_index.to_info(),
+        ];
+        process_instruction(
+            &f.program_id,
+            &accounts,
+            &encode_trade(lp_idx, user_idx, 100),
+        )
+        .unwrap();
+    }
+    let engine = zc::engine_ref(&f.slab.data).unwrap();
+    for (idx, side) in [(user_idx, preview.user), (lp_idx, preview.lp)] {
+        let acc = &engine.accounts[idx as usize];
+        assert_eq!(acc.position_basis_q, side.position_q);
+        assert_eq!(acc.capital.get(), side.capital);
+        assert_eq!(acc.pnl, side.pnl);
+    }
+    assert_eq!(preview.size, 100);
+    assert_eq!(preview.exec_price, 100_000_000);
+    assert_eq!(
+        preview.fee_paid,
+        engine.insurance_fund.balance.get() - ins_before
+    );
+}
+
+#[test]
+fn test_vamm_params_bounds_and_update_encoding() {
+    use percolator_prog::matcher_abi::{
+        encode_init_vamm, encode_update_vamm_params, VammParams, VammParamsUpdatedEvent,
+        MATCHER_INIT_VAMM_TAG, MATCHER_UPDATE_VAMM_TAG, VAMM_MODE_IMPACT,
+    };
+
+    let p = VammParams {
+        trading_fee_bps: 5,
+        base_spread_bps: 10,
+        max_total_bps: 200,
+        impact_k_bps: 100,
+        liquidity_notional_e6: 10_000_000_000,
+        max_fill_abs: 1_000_000,
+        max_inventory_abs: 5_000_000,
+    };
+    assert!(policy::vamm_params_ok(&p));
+    // Fee + spread above the cap.
+    assert!(!policy::vamm_params_ok(&VammParams {
+        max_total_bps: 14,
+        ..p
+    }));
+    // Cap above the ceiling.
+    assert!(!policy::vamm_params_ok(&VammParams {
+        max_total_bps: percolator_prog::constants::MAX_VAMM_TOTAL_BPS + 1,
+        ..p
+    }));
+    // Impact without depth.
+    assert!(!policy::vamm_params_ok(&VammParams {
+        liquidity_notional_e6: 0,
+        ..p
+    }));
+    // Inventory cap below one fill; zero caps are unbounded.
+    assert!(!policy::vamm_params_ok(&VammParams {
+        max_inventory_abs: 999_999,
+        ..p
+    }));
+    assert!(policy::vamm_params_ok(&VammParams {
+        max_inventory_abs: 0,
+        ..p
+    }));
+
+    let data = encode_update_vamm_params(&p);
+    assert_eq!(data[0], MATCHER_UPDATE_VAMM_TAG);
+    assert_eq!(VammParams::decode(&data[1..]), Some(p));
+    let data = encode_init_vamm(VAMM_MODE_IMPACT, &p);
+    assert_eq!(data[..2], [MATCHER_INIT_VAMM_TAG, VAMM_MODE_IMPACT]);
+    assert_eq!(VammParams::decode(&data[2..]), Some(p));
+
+    let ev = VammParamsUpdatedEvent {
+        old: p,
+        new: VammParams {
+            base_spread_bps: 20,
+            ..p
+        },
+    };
+    assert_eq!(VammParamsUpdatedEvent::decode(&ev.encode()), Some(ev));
+    assert_eq!(VammParamsUpdatedEvent::decode(&ev.encode()[1..]), None);
+}
+
+#[test]
+fn test_vamm_quote_reference_values() {
+    use percolator_prog::matcher_abi::{encode_quote, VammParams, VammQuote, MATCHER_QUOTE_TAG};
+
+    let oracle = 100_000_000u64;
+    let unit = percolator::POS_SCALE as i128; // notional 100 at this price
+    let p = VammParams {
+        trading_fee_bps: 5,
+        base_spread_bps: 10,
+        max_total_bps: 200,
+        impact_k_bps: 100,
+        liquidity_notional_e6: 10_000_000_000,
+        max_fill_abs: 0,
+        max_inventory_abs: 0,
+    };
+
+    // Unbounded: the quote is the curve price for the whole request.
+    let q = policy::vamm_quote(&p, oracle, 10 * unit, 0).unwrap();
+    assert_eq!(q.fill_size, 10 * unit);
+    assert_eq!(q.exec_price_e6, 100_250_000);
+    assert_eq!(q.total_bps, 25);
+
+    // Max fill clamps the size, and impact follows the clamped fill.
+    let capped = VammParams {
+        max_fill_abs: 5 * unit as u128,
+        ..p
+    };
+    let q = policy::vamm_quote(&capped, oracle, -10 * unit, 0).unwrap();
+    assert_eq!(q.fill_size, -5 * unit);
+    assert_eq!(q.total_bps, 20);
+    assert_eq!(q.exec_price_e6, 99_800_000);
+
+    // Inventory cap: an LP already 8 units short can sell 2 more to a buyer.
+    let inv = VammParams {
+        max_inventory_abs: 10 * unit as u128,
+        ..p
+    };
+    let q = policy::vamm_quote(&inv, oracle, 10 * unit, -8 * unit).unwrap();
+    assert_eq!(q.fill_size, 2 * unit);
+    // At the cap nothing is fillable in that direction, but the other
+    // direction has the full request.
+    let q = policy::vamm_quote(&inv, oracle, unit, -10 * unit).unwrap();
+    assert_eq!(q, VammQuote::default());
+    let q = policy::vamm_quote(&inv, oracle, -unit, -10 * unit).unwrap();
+    assert_eq!(q.fill_size, -unit);
+
+    assert_eq!(VammQuote::decode(&q.encode()), Some(q));
+    let data = encode_quote(oracle, -unit);
+    assert_eq!(data[0], MATCHER_QUOTE_TAG);
+    assert_eq!(i128::from_le_bytes(data[9..25].try_into().unwrap()), -unit);
+}
@@ -0,0 +1,303 @@
+//! JS bindings over the program's risk and quote math.
+//!
+//! Everything here calls the program crate directly, so a browser gets the
+//! chain's numbers rather than a TypeScript port of them:
+//!
+//! - `Snapshot.health`: `simulate::query_health`, the QueryHealth
+//!   instruction's figures; `Health.bytes()` equals its return data.
+//! - `Snapshot.liquidationDistance`: `policy::liq_distance_e6` at the
+//!   snapshot's price.
+//! - `Snapshot.liquidationPrice`: `simulate::liquidation_price`.
+//! - `Snapshot.simulateTrade`: `simulate::simulate_trade_nocpi`.
+//! - `quote`: `policy::vamm_quote` from matcher context bytes.
+//! - `fundingRateE9`: `policy::funding_rate_e9_from_mark_index`.
+//!
+//! ```js
+//! import init, { Snapshot, quote } from "percolator-wasm";
+//! await init();
+//! const snap = new Snapshot(slabAccount.data);
+//! const h = snap.health(userIdx);
+//! const q = quote(matcherCtx.data, oraclePriceE6, 10n * POS_SCALE);
+//! const p = snap.simulateTrade(lpIdx, userIdx, q.fill_size, q.exec_price_e6,
+//!                              oraclePriceE6, slot, 0);
+//! ```
+//!
+//! u64 / i128 / u128 cross as `BigInt`. Failures throw a string: the
+//! program error (`Custom(n)` is `PercolatorError` code `n`) or a short
+//! reason.
+
+use percolator_prog::{constants::SLAB_LEN, matcher_abi, policy, simulate, state, zc};
+use wasm_bindgen::prelude::*;
+
+fn program_err(e: impl core::fmt::Debug) -> String {
+    format!("{e:?}")
+}
+
+/// QueryHealth's `HealthSnapshot`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Health {
+    pub equity: i128,
+    pub maintenance_req: u128,
+    pub initial_req: u128,
+    pub free_collateral: u128,
+    pub position_q: i128,
+    pub price_e6: u64,
+    pub last_market_slot: u64,
+}
+
+#[wasm_bindgen]
+impl Health {
+    /// The 96-byte QueryHealth return data for these figures.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.snapshot().encode().to_vec()
+    }
+}
+
+impl Health {
+    fn snapshot(&self) -> percolator_prog::ix::HealthSnapshot {
+        percolator_prog::ix::HealthSnapshot {
+            equity: self.equity,
+            maintenance_req: self.maintenance_req,
+            initial_req: self.initial_req,
+            free_collateral: self.free_collateral,
+            position_q: self.position_q,
+            price_e6: self.price_e6,
+            last_market_slot: self.last_market_slot,
+        }
+    }
+}
+
+impl From<percolator_prog::ix::HealthSnapshot> for Health {
+    fn from(h: percolator_prog::ix::HealthSnapshot) -> Self {
+        Self {
+            equity: h.equity,
+            maintenance_req: h.maintenance_req,
+            initial_req: h.initial_req,
+            free_collateral: h.free_collateral,
+            position_q: h.position_q,
+            price_e6: h.price_e6,
+            last_market_slot: h.last_market_slot,
+        }
+    }
+}
+
+/// `simulate::AccountHealth`, one side after a simulated trade.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountHealth {
+    pub position_q: i128,
+    pub capital: u128,
+    pub pnl: i128,
+    pub notional: u128,
+    pub maintenance_req: u128,
+    pub initial_req: u128,
+    pub equity: i128,
+    pub above_maintenance: bool,
+}
+
+impl From<simulate::AccountHealth> for AccountHealth {
+    fn from(a: simulate::AccountHealth) -> Self {
+        Self {
+            position_q: a.position_q,
+            capital: a.capital,
+            pnl: a.pnl,
+            notional: a.notional,
+            maintenance_req: a.maintenance_req,
+            initial_req: a.initial_req,
+            equity: a.equity(),
+            above_maintenance: a.above_maintenance(),
+        }
+    }
+}
+
+/// `simulate::TradePreview`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TradePreview {
+    pub size: i128,
+    pub exec_price: u64,
+    pub oracle_price: u64,
+    pub fee_bps: u64,
+    pub fee_paid: u128,
+    pub user: AccountHealth,
+    pub lp: AccountHealth,
+}
+
+/// A priced fill against one LP's vAMM matcher.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quote {
+    /// 0 when nothing is fillable in the requested direction.
+    pub exec_price_e6: u64,
+    pub fill_size: i128,
+    pub total_bps: u32,
+}
+
+/// A slab account's data, copied once into an aligned buffer so the
+/// program's zero-copy readers run on it unchanged.
+#[wasm_bindgen]
+pub struct Snapshot {
+    words: Vec<u128>,
+}
+
+impl Snapshot {
+    fn bytes(&self) -> &[u8] {
+        &bytemuck::cast_slice::<u128, u8>(&self.words)[..SLAB_LEN]
+    }
+}
+
+#[wasm_bindgen]
+impl Snapshot {
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: &[u8]) -> Result<Snapshot, String> {
+        if data.len() != SLAB_LEN {
+            return Err(format!(
+                "slab is {} bytes, this build expects {SLAB_LEN}",
+                data.len()
+            ));
+        }
+        let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+        bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN].copy_from_slice(data);
+        let snap = Snapshot { words };
+        zc::engine_ref(snap.bytes()).map_err(program_err)?;
+        Ok(snap)
+    }
+
+    /// QueryHealth for `idx` at the engine's last oracle price (the
+    /// settlement price once resolved).
+    pub fn health(&self, idx: u16) -> Result<Health, String> {
+        simulate::query_health(self.bytes(), idx)
+            .map(Health::from)
+            .map_err(program_err)
+    }
+
+    /// Adverse oracle move (e6) before `idx` reaches maintenance, valued as
+    /// in [`Snapshot::health`]; `u64::MAX` when flat, 0 at or below
+    /// maintenance.
+    #[wasm_bindgen(js_name = liquidationDistance)]
+    pub fn liquidation_distance(&self, idx: u16) -> Result<u64, String> {
+        let h = simulate::query_health(self.bytes(), idx).map_err(program_err)?;
+        let mm_bps = zc::engine_ref(self.bytes())
+            .map_err(program_err)?
+            .params
+            .maintenance_margin_bps;
+        Ok(policy::liq_distance_e6(
+            h.equity,
+            h.maintenance_req,
+            h.position_q.unsigned_abs(),
+            mm_bps,
+        ))
+    }
+
+    /// Oracle price at which `idx` becomes liquidatable after
+    /// `horizon_slots` of the current funding rate and maintenance fee
+    /// (`simulate::liquidation_price`); `undefined` when none does.
+    #[wasm_bindgen(js_name = liquidationPrice)]
+    pub fn liquidation_price(&self, idx: u16, horizon_slots: u64) -> Result<Option<u64>, String> {
+        simulate::liquidation_price(self.bytes(), idx, horizon_slots).map_err(program_err)
+    }
+
+    /// TradeNoCpi against a copy of the snapshot. `price` is the effective
+    /// oracle price the handler would read at `now_slot`.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen(js_name = simulateTrade)]
+    pub fn simulate_trade(
+        &self,
+        lp_idx: u16,
+        user_idx: u16,
+        size: i128,
+        exec_price_e6: u64,
+        price: u64,
+        now_slot: u64,
+        flags: u8,
+    ) -> Result<TradePreview, String> {
+        let mut scratch = vec![0u128; SLAB_LEN.div_ceil(16)];
+        let p = simulate::simulate_trade_nocpi(
+            self.bytes(),
+            bytemuck::cast_slice_mut(&mut scratch),
+            lp_idx,
+            user_idx,
+            size,
+            exec_price_e6,
+            flags,
+            now_slot,
+            price,
+        )
+        .map_err(program_err)?;
+        Ok(TradePreview {
+            size: p.size,
+            exec_price: p.exec_price,
+            oracle_price: p.oracle_price,
+            fee_bps: p.fee_bps,
+            fee_paid: p.fee_paid,
+            user: p.user.into(),
+            lp: p.lp.into(),
+        })
+    }
+
+    /// The per-slot funding rate (e9) the next crank would apply.
+    #[wasm_bindgen(js_name = fundingRateE9)]
+    pub fn funding_rate_e9(&self) -> Result<i128, String> {
+        let c = state::read_config(self.bytes());
+        funding_rate_e9(
+            c.mark_ewma_e6,
+            c.last_effective_price_e6,
+            c.funding_horizon_slots,
+            c.funding_k_bps,
+            c.funding_max_premium_bps,
+            c.funding_max_e9_per_slot,
+        )
+    }
+}
+
+/// Fill for `size` (taker side) against a vAMM matcher context.
+#[wasm_bindgen]
+pub fn quote(ctx: &[u8], oracle_price_e6: u64, size: i128) -> Result<Quote, String> {
+    let vamm = matcher_abi::read_vamm_ctx(ctx).ok_or("not an initialized vAMM matcher context")?;
+    let q = policy::vamm_quote(
+        &vamm.effective_params(),
+        oracle_price_e6,
+        size,
+        vamm.inventory,
+    )
+    .ok_or_else(|| format!("size {size} at {oracle_price_e6} is not priceable"))?;
+    Ok(Quote {
+        exec_price_e6: q.exec_price_e6,
+        fill_size: q.fill_size,
+        total_bps: q.total_bps,
+    })
+}
+
+/// Per-slot funding rate (e9) for a mark / index pair.
+#[wasm_bindgen(js_name = fundingRateE9)]
+pub fn funding_rate_e9(
+    mark_ewma_e6: u64,
+    index_e6: u64,
+    funding_horizon_slots: u64,
+    funding_k_bps: u64,
+    funding_max_premium_bps: i64,
+    funding_max_e9_per_slot: i64,
+) -> Result<i128, String> {
+    policy::funding_rate_e9_from_mark_index(
+        mark_ewma_e6,
+        index_e6,
+        funding_horizon_slots,
+        funding_k_bps,
+        funding_max_premium_bps,
+        funding_max_e9_per_slot,
+    )
+    .ok_or_else(|| "negative funding cap".to_string())
+}
+
+/// `percolator::POS_SCALE`: position units per whole contract.
+#[wasm_bindgen(js_name = posScale)]
+pub fn pos_scale() -> u128 {
+    percolator::POS_SCALE
+}
+
+/// Slab length this build accepts.
+#[wasm_bindgen(js_name = slabLen)]
+pub fn slab_len() -> usize {
+    SLAB_LEN
+}
@@ -0,0 +1,117 @@
+//! The JS-facing functions called natively, against the program's own
+//! readers.
+
+use percolator::{Account, POS_SCALE, U128};
+use percolator_prog::{
+    constants::{MAGIC, SLAB_LEN},
+    policy, simulate, state, zc,
+};
+use percolator_wasm::{funding_rate_e9, quote, Snapshot};
+
+const PRICE: u64 = 100_000_000;
+
+/// An initialized slab with a 2-contract long at idx 3 (capital 50_000_000,
+/// 10% maintenance) and a flat LP at idx 1.
+fn slab() -> Vec<u8> {
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let data = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    let mut header = state::read_header(data);
+    header.magic = MAGIC;
+    state::write_header(data, &header);
+    {
+        let engine = zc::engine_mut(data).unwrap();
+        engine.params.max_accounts = 8;
+        engine.params.maintenance_margin_bps = 1_000;
+        engine.params.initial_margin_bps = 2_000;
+        engine.last_oracle_price = PRICE;
+        engine.last_market_slot = 42;
+        engine.adl_mult_long = 1;
+        for (idx, kind) in [(1usize, Account::KIND_LP), (3, Account::KIND_USER)] {
+            engine.used[0] |= 1 << idx;
+            engine.accounts[idx].kind = kind;
+        }
+        let user = &mut engine.accounts[3];
+        user.capital = U128::new(50_000_000);
+        user.position_basis_q = 2 * POS_SCALE as i128;
+        user.adl_a_basis = 1;
+    }
+    data.to_vec()
+}
+
+#[test]
+fn health_matches_query_health_byte_for_byte() {
+    let data = slab();
+    let snap = Snapshot::new(&data).unwrap();
+
+    let mut words = vec![0u128; SLAB_LEN.div_ceil(16)];
+    let aligned = &mut bytemuck::cast_slice_mut::<u128, u8>(&mut words)[..SLAB_LEN];
+    aligned.copy_from_slice(&data);
+    let expected = simulate::query_health(aligned, 3).unwrap();
+
+    let h = snap.health(3).unwrap();
+    assert_eq!(h.bytes(), expected.encode());
+    // 2 contracts at 100: notional 200, 10% maintenance, 20% initial (e6).
+    assert_eq!(
+        (h.position_q, h.equity, h.maintenance_req, h.initial_req),
+        (2 * POS_SCALE as i128, 50_000_000, 20_000_000, 40_000_000)
+    );
+    assert_eq!((h.price_e6, h.last_market_slot), (PRICE, 42));
+
+    assert_eq!(
+        snap.liquidation_distance(3).unwrap(),
+        policy::liq_distance_e6(50_000_000, 20_000_000, 2 * POS_SCALE, 1_000)
+    );
+    assert_eq!(snap.liquidation_distance(1).unwrap(), u64::MAX);
+    assert_eq!(snap.liquidation_price(3, 0).unwrap(), Some(83_333_332));
+    assert_eq!(
+        snap.liquidation_price(3, 0).unwrap(),
+        simulate::liquidation_price(aligned, 3, 0).unwrap()
+    );
+    assert_eq!(snap.liquidation_price(1, 0).unwrap(), None);
+    assert!(snap.health(2).is_err());
+    assert!(snap.health(9).is_err());
+}
+
+#[test]
+fn snapshots_reject_foreign_lengths_and_uninitialized_trades() {
+    assert!(Snapshot::new(&[0u8; 64]).is_err());
+    let snap = Snapshot::new(&vec![0u8; SLAB_LEN]).unwrap();
+    assert!(snap.health(0).is_err());
+    assert!(snap
+        .simulate_trade(1, 3, POS_SCALE as i128, PRICE, PRICE, 42, 0)
+        .is_err());
+}
+
+#[test]
+fn quote_and_funding_match_the_policy() {
+    use percolator_prog::matcher_abi::{self, VAMM_MAGIC, VAMM_MODE_IMPACT};
+
+    let mut ctx = vec![0u8; 320];
+    ctx[64..72].copy_from_slice(&VAMM_MAGIC.to_le_bytes());
+    ctx[76] = VAMM_MODE_IMPACT;
+    ctx[112..116].copy_from_slice(&5u32.to_le_bytes()); // fee
+    ctx[116..120].copy_from_slice(&10u32.to_le_bytes()); // spread
+    ctx[120..124].copy_from_slice(&200u32.to_le_bytes()); // max total
+    ctx[124..128].copy_from_slice(&100u32.to_le_bytes()); // impact k
+    ctx[128..144].copy_from_slice(&10_000_000_000u128.to_le_bytes());
+    let size = 10 * POS_SCALE as i128;
+    let vamm = matcher_abi::read_vamm_ctx(&ctx).unwrap();
+    let expected =
+        policy::vamm_quote(&vamm.effective_params(), PRICE, size, vamm.inventory).unwrap();
+    let q = quote(&ctx, PRICE, size).unwrap();
+    assert_eq!(
+        (q.exec_price_e6, q.fill_size, q.total_bps),
+        (
+            expected.exec_price_e6,
+            expected.fill_size,
+            expected.total_bps
+        )
+    );
+    assert!(quote(&[0u8; 320], PRICE, size).is_err());
+
+    assert_eq!(
+        funding_rate_e9(101_000_000, PRICE, 500, 100, 500, 1_000).unwrap(),
+        policy::funding_rate_e9_from_mark_index(101_000_000, PRICE, 500, 100, 500, 1_000).unwrap()
+    );
+    assert!(funding_rate_e9(PRICE, PRICE, 500, 100, -1, 1_000).is_err());
+}